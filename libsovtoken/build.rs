@@ -1,6 +1,7 @@
 //! modifies build to dynamically link in a) indy-sdk
 
 use std::env;
+use std::fs;
 use std::path::Path;
 
 
@@ -13,10 +14,13 @@ fn main() {
 
     println!("cargo:rustc-link-search=native={}",libindy_lib_path);
 
-    if let Ok(_mode) = env::var("LIBINDY_STATIC") {
-        println!("cargo:rustc-link-lib=static=indy");
-    } else {
-        println!("cargo:rustc-link-lib=dylib=indy");
+    let linkage = if let Ok(_mode) = env::var("LIBINDY_STATIC") { "static" } else { "dylib" };
+    println!("cargo:rustc-link-lib={}=indy", linkage);
+
+    // reported by sovtoken_diagnostics
+    println!("cargo:rustc-env=SOVTOKEN_LIBINDY_LINKAGE={}", linkage);
+    if let Some(version) = locked_version("indy") {
+        println!("cargo:rustc-env=SOVTOKEN_INDY_CRATE_VERSION={}", version);
     }
 
     let target = env::var("TARGET").unwrap();
@@ -50,3 +54,17 @@ fn main() {
         return;
     }
 }
+
+// The version of a dependency in Cargo.lock, None without a lock file.
+fn locked_version(name: &str) -> Option<String> {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").ok()?;
+    let lock = fs::read_to_string(Path::new(&manifest_dir).join("Cargo.lock")).ok()?;
+    let name_line = format!("name = \"{}\"", name);
+
+    // the version follows the name
+    let mut lines = lock.lines();
+    lines.by_ref().find(|line| line.trim() == name_line)?;
+    lines.next()
+        .and_then(|line| line.trim().splitn(2, " = ").nth(1))
+        .map(|version| version.trim_matches('"').to_string())
+}
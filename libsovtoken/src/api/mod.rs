@@ -3,40 +3,115 @@
 
 use libc::c_char;
 use indy;
+use serde_json::{self, Map, Value};
+use std::ffi::CString;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use indy::payments::Payment;
 use indy::ledger::Ledger;
 use indy::ErrorCode;
+use logic::address;
+use logic::address_book::{self, AddressFilter};
+use logic::address_proof;
+use logic::address_vectors;
 use logic::api_internals::{
     add_request_fees,
-    create_address
+    create_address,
+    refresh_request,
 };
+use logic::api_internals::add_request_fees::{FeesMetadata, RawRequest};
+use logic::audit_log::{self, AuditSink, ExportFormat};
+use logic::balance;
+use logic::batch_payment::{self, BatchChunk, BatchEntry, BatchManifest};
 use logic::build_payment;
 use logic::config::{
     get_fees_config::GetFeesRequest,
     get_utxo_config::*,
+    payment_address_config::{PaymentAddressConfig, SeedError},
+    library_config::{LibraryConfig, apply_env_overrides, init_library_config, library_config, resolve_library_config},
 };
+use logic::context;
+use logic::correlation::{self, Correlator};
 use logic::did::Did;
-use logic::indy_sdk_api::crypto_api::CryptoSdk;
+use logic::error::SovtokenError;
+use logic::extra::extra_from_char_ptr;
+use logic::fee_attestation;
+use logic::fee_waiver;
+use logic::history::{self, HistoryEntry};
+use logic::inclusion_proof;
+use logic::messages;
+#[cfg(feature = "devnet")]
+use logic::devnet;
+use logic::diagnostics;
+use logic::indy_sdk_api::ledger_api::LedgerAPI;
+use logic::indy_vdr;
 use logic::minting;
+use logic::optimistic_updates;
+use logic::output::{self, Outputs};
+use logic::storage;
 use logic::verify;
+use logic::wallet_handles;
+use logic::write_with_fees::{Funding, WriteTxn};
 use logic::parsers::{
+    legacy_reply,
     parse_get_utxo_response,
     parse_response_with_fees_handler,
     parse_verify,
-    parse_get_utxo_response::{ParseGetUtxoResponse, ParseGetUtxoReply},
+    parse_get_receipt::parse_get_receipt_response,
+    parse_fees_section::parse_fees_section,
+    parse_options::ParseOptions,
+    parse_get_utxo_response::{ParseGetUtxoReply, UTXO},
     parse_payment_response::{ParsePaymentResponse, ParsePaymentReply, from_response},
     parse_response_with_fees_handler::{ParseResponseWithFees, ParseResponseWithFeesReply},
-    parse_get_txn_fees::{parse_fees_from_get_txn_fees_response, get_fees_state_proof_extractor}
+    parse_get_txn_fees::{parse_fees_of_pool, get_fees_state_proof_extractor}
 };
-use logic::payments::{CreatePaymentHandler};
+use logic::payment_intent::PaymentIntent;
+use logic::payment_proof;
+use logic::payment_template::PaymentTemplate;
+use logic::payment_uri::PaymentUri;
+use logic::payments;
+use logic::pending_txn::PendingState;
+use logic::pools;
+use logic::receipt::Receipt;
+use logic::registration;
+use logic::request::{check_framing, default_identifier};
+use logic::request_builder::PaymentRequestBuilder;
+use logic::request_hash;
+use logic::request_signatures;
+use logic::request_size;
+use logic::schema::{validate_if_strict, Schema};
+use logic::services::{services, init_services};
 use logic::set_fees;
+use logic::signing_payload;
+use logic::simulation::{self, BuildOptions};
+use logic::state::state;
+use logic::stateless::{self, SignerCallback};
+use logic::transfer_limits::TransferLimits;
+use logic::type_aliases::TokenAmount;
+use logic::unknown_fields;
+use logic::utxo_cache;
 use logic::xfer_payload::XferPayload;
 
-use utils::constants::general::{JsonCallback, PAYMENT_METHOD_NAME, LEDGER_ID};
-use utils::constants::txn_types::{GET_FEES, GET_UTXO};
-use utils::ffi_support::{str_from_char_ptr, string_from_char_ptr, c_pointer_from_string};
+use utils::constants::general::{JsonCallback, JsonCallbackUnwrapped, PAYMENT_METHOD_NAME, LEDGER_ID};
+use utils::constants::txn_types::{GET_FEES, GET_UTXO, XFER_PUBLIC};
+use utils::ffi_support::{str_from_char_ptr, string_from_char_ptr, cstring_from_str, with_c_str, buffer_pool_stats};
 use utils::json_conversion::{JsonDeserialize, JsonSerialize};
+use utils::dead_letter;
+use utils::dispatcher;
+use utils::envelope;
+use utils::metrics;
+use utils::rate_limit;
+use utils::secret_sharing::{self, ShareError};
+use utils::serde_buffers;
+use utils::span;
+use utils::sync_call::{call_sync, SyncResult};
+use utils::wire_format::{self, WireFormat};
 use utils::general::ResultExtension;
 
 /// This method generates private part of payment address
@@ -52,7 +127,13 @@ use utils::general::ResultExtension;
 /// command_handle: command handle to map callback to context
 /// config_str: payment address config as json:
 ///   {
-///     seed: <str>, // allows deterministic creation of payment address
+///     seed: <str>, // allows deterministic creation of payment address,
+///                  // 32 characters or the base64 or base58 of 32 bytes
+///     seed_encoding: "utf8" | "hex" | "base64", // optional, decode the seed as such instead of
+///                                              // guessing, it has to be 32 bytes once decoded
+///     strict_seed: <bool>, // optional, reject seeds repeating a short pattern, e.g. all zeros
+///     reuse_existing: <bool>, // optional, return the address of a seed whose key is already
+///                             // in the wallet instead of failing
 ///   }
 /// cb: description
 ///
@@ -61,7 +142,10 @@ use utils::general::ResultExtension;
 ///              pay:sov:{32 byte public key}{4 digit check sum}
 ///
 /// # Errors
-/// description of errors
+/// CommonInvalidStructure when the callback or config are missing, or the seed is invalid
+/// The wallet error is passed to the callback, e.g. WalletItemAlreadyExists for a seed used
+/// before without reuse_existing, with the error as json:
+///   {"code": <int>, "id": "SOV-0042", "text": <str>, "args": [<existing address>]}
 #[no_mangle]
 pub extern "C" fn create_payment_address_handler(
     command_handle: i32,
@@ -69,19 +153,9 @@ pub extern "C" fn create_payment_address_handler(
     config_str: *const c_char,
     cb: JsonCallback
 ) -> i32 {
-
-    trace!("api::create_payment_address_handler called");
-    let (config, cb) = match create_address::deserialize_arguments(config_str, cb) {
-        Ok(tup) => tup,
-        Err(e) => return e as i32
-    };
-
-    let payment_closure = create_address::create_address_cb(command_handle, cb);
-
-    let handler = CreatePaymentHandler::new(CryptoSdk {});
-    let ec = handler.create_payment_address_async(wallet_handle, config, payment_closure);
-    trace!("api::create_payment_address_handler << result: {:?}", ec);
-    return ec as i32;
+    span::with_command_handle(command_handle, || metrics::measure_wallet_handler("create_payment_address_handler", rate_limit::CREATE_ADDRESS, wallet_handle, || {
+        create_payment_address(command_handle, wallet_handle, config_str, cb)
+    }))
 }
 
 /**
@@ -91,6 +165,20 @@ pub extern "C" fn create_payment_address_handler(
  * If you are building a transfer request, fees should be included in the 
  * `inputs_json` and `outputs_json` of the [`build_payment_req_handler`].
  * 
+ * As for a transfer, the inputs can be drawn from several payment
+ * addresses of the wallet, each signed with the key of its own address.
+ * 
+ * When a fee schedule parsed by [`parse_get_txn_fees_response_handler`]
+ * set the fee of the type of the request to 0, the request is returned as
+ * it was given, whatever the inputs and outputs.
+ * [`add_request_fees_with_status_handler`] tells whether fees were added.
+ * 
+ * When the fee of the type of the request and the amounts of the inputs
+ * are known, the balance is checked as for [`build_payment_req_handler`].
+ * 
+ * A request stating another `protocolVersion` than the `protocol_version`
+ * of the library config fails with `PoolIncompatibleProtocolVersion`, the
+ * fees would be signed for a pool of another version.
  * 
  * ## Parameters
  * 
@@ -178,46 +266,256 @@ pub extern "C" fn add_request_fees_handler(
     extra: *const c_char,
     cb: JsonCallback
 ) -> i32 {
+    span::with_command_handle(command_handle, || metrics::measure_wallet_handler("add_request_fees_handler", rate_limit::FEES, wallet_handle, || {
+        add_request_fees(command_handle, wallet_handle, did, req_json, inputs_json, outputs_json, extra, cb)
+    }))
+}
 
-    trace!("api::add_request_fees_handler called did (address) >> {:?}", did);
-    let (inputs, outputs, extra, request_json_map, cb) = match add_request_fees::deserialize_inputs(req_json, inputs_json, outputs_json, extra, cb) {
-        Ok(tup) => tup,
-        Err(error_code) => {
-            trace!("api::add_request_fees_handler result >> {:?}", error_code);
-            return error_code as i32;
+/**
+    Add fees to a request as `add_request_fees_handler` does, and tell
+    whether they were added.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the keys of the inputs
+    req_json, inputs_json, outputs_json, extra: as for `add_request_fees_handler`
+    cb: called with:
+    ```text
+    {
+        "request": <str>,       // the request with fees, or as it was given
+        "feesApplied": <bool>,  // false when the fee schedule waives the fees of the request
+        "metadata": {           // the fees added, all 0 when they are waived
+            "feeTotal": <int>,              // what inputs_json gives beyond outputs_json, null when
+                                            // a parsed GET_UTXO reply gave the amount of no input
+            "feeTotalTokens": <str>,        // the same in tokens, e.g. "0.5"
+            "changeTotal": <int>,           // the total of outputs_json
+            "changeTotalTokens": <str>,
+            "inputsUsed": [<str>, ...]      // the txos of inputs_json
         }
-    };
+    }
+    ```
 
-    /*
-        Errors when the request is a XFER request becaause the 
-        fees should be implicit in the operation's inputs and
-        outputs.
-    */
-    if let Err(_) = add_request_fees::validate_type_not_transfer(&request_json_map) {
-        error!("api::add_request_fees_handler Can't add fees to a transfer request");
-        return ErrorCode::CommonInvalidStructure as i32;
+    # Returns
+    the errors of `add_request_fees_handler`, and `PaymentInsufficientFundsError`
+    when outputs_json gives more than the known amounts of inputs_json
+*/
+#[no_mangle]
+pub extern "C" fn add_request_fees_with_status_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    req_json: *const c_char,
+    inputs_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    span::with_command_handle(command_handle, || metrics::measure_wallet_handler("add_request_fees_with_status_handler", rate_limit::FEES, wallet_handle, || {
+        trace!("api::add_request_fees_with_status_handler called >> wallet_handle {}", wallet_handle);
+        messages::clear_last_error();
+        let res = add_fees_then(command_handle, wallet_handle, req_json, inputs_json, outputs_json, extra, &BuildOptions::default(), cb, move |cb, result| {
+            dispatch_fees_status(cb, command_handle, result);
+        });
+        trace!("api::add_request_fees_with_status_handler << result: {:?}", res);
+        return res as i32;
+    }))
+}
+
+/**
+    Add fees to a request, as `add_request_fees_handler`, with options.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the keys of the inputs
+    req_json, inputs_json, outputs_json, extra: as for `add_request_fees_handler`
+    options_json: the options, as for `build_batch_payment_req_with_options_handler`
+    cb: called with the request with fees, or when simulating with the plan
+    of the fees, nothing signed and an oversized extra not stored:
+    ```text
+    {
+        "feesApplied": <bool>,          // false when the fee schedule waives the fees of the request
+        "metadata": {...},              // as for `add_request_fees_with_status_handler`, the amounts
+                                        // of the inputs from the GET_UTXO replies parsed for the
+                                        // network of "poolHandle", when given
+        "txnDigest": <str>,             // hex digest of the request, signed with the fees
+        "signingPayloads": [...]        // one per input, as returned by `get_signing_payload_handler`,
+                                        // none when the fees are waived
     }
+    ```
 
-    let result = add_request_fees::add_fees_to_request_and_serialize(
-        wallet_handle,
-        inputs,
-        outputs,
-        extra,
-        request_json_map,
-        Box::new(add_request_fees::closure_cb_response(command_handle, cb))
-    );
-    
-    match result {
-        Err(e) => {
-            error!("api::add_request_fees_handler Received error adding fees to request_json");
-            return e as i32;
-        }
-        _ => {
-            let res = ErrorCode::Success;
-            trace!("api::add_request_fees_handler result >> {:?}", res);
-            return res as i32;
+    # Returns
+    the errors of `add_request_fees_handler`
+*/
+#[no_mangle]
+pub extern "C" fn add_request_fees_with_options_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    req_json: *const c_char,
+    inputs_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    options_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    span::with_command_handle(command_handle, || metrics::measure_wallet_handler("add_request_fees_with_options_handler", rate_limit::FEES, wallet_handle, || {
+        trace!("api::add_request_fees_with_options_handler called >> wallet_handle {}", wallet_handle);
+        messages::clear_last_error();
+        let res = match build_options(options_json) {
+            Ok(options) => add_fees_then(command_handle, wallet_handle, req_json, inputs_json, outputs_json, extra, &options, cb, move |cb, result| {
+                add_request_fees::closure_cb_response(command_handle, cb)(result.map(|(request, _, _)| request));
+            }),
+            Err(ec) => ec,
+        };
+        trace!("api::add_request_fees_with_options_handler << result: {:?}", res);
+        return res as i32;
+    }))
+}
+
+/**
+    Add the fees of a NYM request, paid by a funding address.
+
+    The fee is the fee of NYM in the parsed GET_FEES replies or the fee
+    schedule set with `set_fee_schedule_handler`. The UTXOs of the funding
+    address are fetched from the ledger, less the ones spent by the requests
+    submitted with `submit_pending_txn_handler`, and the largest are spent
+    first, the change goes back to the funding address. See `logic::write_with_fees`.
+    The request can be signed by its DID before or after, the fees aren't
+    part of what the DID signs. Submit the request and parse the reply with
+    `parse_write_with_fees_response_handler`.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the key of the funding address
+    pool_handle: pool to fetch the UTXOs of the funding address from
+    req_json: the NYM request, e.g. of indy_build_nym_request
+    funding_address: the payment address paying the fee
+    cb: called with the request with fees, as by `add_request_fees_with_status_handler`:
+    ```text
+    {
+        "request": <str>,       // the request with fees, or as it was given when its fee is 0
+        "feesApplied": <bool>,
+        "metadata": {...}
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when req_json isn't a NYM request or the funding address is invalid
+    PoolIncompatibleProtocolVersion when req_json isn't framed with the protocol version of the pool
+    CommonInvalidState when the fee of NYM isn't known, SOV-0055
+    the errors of `add_request_fees_handler`
+
+    cb is called with PaymentInsufficientFundsError when the funding address
+    can't pay the fee, and with the errors of fetching the UTXOs.
+*/
+#[no_mangle]
+pub extern "C" fn build_nym_with_fees_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    pool_handle: i32,
+    req_json: *const c_char,
+    funding_address: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    build_write_with_fees("build_nym_with_fees_handler", WriteTxn::Nym, command_handle, wallet_handle, pool_handle, req_json, funding_address, cb)
+}
+
+/**
+    Add the fees of an ATTRIB request, paid by a funding address.
+
+    See `build_nym_with_fees_handler`, req_json is the ATTRIB request, e.g.
+    of indy_build_attrib_request.
+*/
+#[no_mangle]
+pub extern "C" fn build_attrib_with_fees_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    pool_handle: i32,
+    req_json: *const c_char,
+    funding_address: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    build_write_with_fees("build_attrib_with_fees_handler", WriteTxn::Attrib, command_handle, wallet_handle, pool_handle, req_json, funding_address, cb)
+}
+
+/**
+    Add the fees of a SCHEMA request, paid by a funding address.
+
+    See `build_nym_with_fees_handler`, req_json is the SCHEMA request, e.g.
+    of indy_build_schema_request.
+*/
+#[no_mangle]
+pub extern "C" fn build_schema_with_fees_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    pool_handle: i32,
+    req_json: *const c_char,
+    funding_address: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    build_write_with_fees("build_schema_with_fees_handler", WriteTxn::Schema, command_handle, wallet_handle, pool_handle, req_json, funding_address, cb)
+}
+
+/**
+    Parse the reply to a NYM, ATTRIB or SCHEMA request with fees.
+
+    # Params
+    command_handle: standard command handle
+    resp_json: the reply of the ledger
+    cb: called with:
+    ```text
+    {
+        "txnType": <str>,   // the txn type of the write
+        "seqNo": <int>,     // the seqNo of the write
+        "txnTime": <int>,
+        "receipts": [...]   // the receipts of the change of the fees, as by
+                            // `parse_response_with_fees_handler`, empty without fees
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when resp_json isn't a reply
+    the error of the ledger for a REQNACK or REJECT, e.g. PaymentInsufficientFundsError
+*/
+#[no_mangle]
+pub extern "C" fn parse_write_with_fees_response_handler(
+    command_handle: i32,
+    resp_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::parse_write_with_fees_response_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let parsed = str_from_char_ptr(resp_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(wire_format::to_json)
+        .and_then(|resp_json| legacy_reply::nest_json(&resp_json))
+        .and_then(|resp_json| {
+            let receipts = parse_fees_reply(&resp_json)?.unwrap_or_default();
+            let reply = ParseResponseWithFees::from_json(&resp_json)
+                .map_err(map_err_err!())
+                .or(Err(ErrorCode::CommonInvalidStructure))?;
+            let result = reply.result.ok_or(ErrorCode::CommonInvalidStructure)?;
+            Ok(json!({
+                "txnType": result.txn.txn_type,
+                "seqNo": result.tnx_meta_data.seq_no,
+                "txnTime": result.tnx_meta_data.txn_time,
+                "receipts": receipts,
+            }).to_string())
+        });
+
+    let parsed = match parsed {
+        Ok(parsed) => parsed,
+        Err(ec) => {
+            trace!("api::parse_write_with_fees_response_handler << result: {:?}", ec);
+            return ec as i32;
         }
     };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("write_with_fees", parsed));
+
+    let res = ErrorCode::Success;
+    trace!("api::parse_write_with_fees_response_handler << result: {:?}", res);
+    return res as i32;
 }
 
 
@@ -241,57 +539,9 @@ pub extern "C" fn parse_response_with_fees_handler(
     req_json: *const c_char,
     cb: JsonCallback
 ) -> i32 {
-
-    trace!("api::parse_response_with_fees_handler called");
-    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
-
-    if req_json.is_null() {
-        trace!("api::parse_response_with_fees_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
-        return ErrorCode::CommonInvalidStructure as i32;
-    }
-
-    let resp_json_string = match string_from_char_ptr(req_json) {
-        Some(s) => s,
-        None => {
-            error!("Failed to convert inputs_json pointer to string");
-            return ErrorCode::CommonInvalidStructure as i32;
-        }
-    };
-
-    debug!("api::parse_response_with_fees_handler >> req_json: {:?}", resp_json_string);
-
-    let response: ParseResponseWithFees = match ParseResponseWithFees::from_json(&resp_json_string).map_err(map_err_err!()) {
-        Ok(r) => r,
-        Err(_) => return ErrorCode::CommonInvalidStructure as i32,
-    };
-
-    // here is where the magic happens--conversion from input structure to output structure
-    // is handled in ParseResponseWithFeesReply::from_response
-    let reply: Option<ParseResponseWithFeesReply> = match parse_response_with_fees_handler::from_response(response) {
-        Ok(rep) => rep,
-        Err(ec) => {
-            trace!("api::parse_response_with_fees_handler << result: {:?}", ec);
-            return ec as i32
-        },
-    };
-
-    let reply_str: Option<String> = match reply {
-        Some(reply) => {
-            match reply.to_json().map_err(map_err_err!()) {
-                Ok(j) => Some(j),
-                Err(_) => return ErrorCode::CommonInvalidState as i32,
-            }
-        }
-        None => None
-    };
-
-    let reply_str_ptr: *const c_char = c_pointer_from_string(reply_str.unwrap_or(String::from("[]")));
-    let ec = ErrorCode::Success;
-
-    cb(command_handle, ec as i32, reply_str_ptr);
-
-    trace!("api::parse_response_with_fees_handler << result: {:?}", ec);
-    return ec as i32;
+    span::with_command_handle(command_handle, || metrics::measure_handler("parse_response_with_fees_handler", || {
+        parse_response_with_fees(command_handle, req_json, cb)
+    }))
 }
 
 
@@ -304,6 +554,18 @@ pub extern "C" fn parse_response_with_fees_handler(
  * The amount in the output addresses needs to match the
  * amount stored in input addresses.
  * 
+ * When the fee of XFER_PUBLIC and the amounts of the inputs are known,
+ * from a parsed fee schedule and cached GET_UTXO replies, the balance is
+ * checked before signing: `PaymentInsufficientFundsError` when the inputs
+ * don't cover the outputs or the fee, the last error tells which, and
+ * `PaymentExtraFundsError` when they give more.
+ * 
+ * The inputs can be drawn from several payment addresses of the
+ * wallet, each input is signed with the key of its own address and
+ * the signatures are in the order of the inputs. The callback gets
+ * `WalletItemNotFound` when the wallet has no key for an input address,
+ * no request is built with part of the signatures.
+ * 
  * ## Parameters
  * 
  * ### inputs_json
@@ -347,29 +609,48 @@ pub extern "C" fn build_payment_req_handler(
     extra: *const c_char,
     cb: JsonCallback
 ) -> i32 {
-    trace!("api::build_payment_req_handler called >> submitter_did (address) {:?}", submitter_did);
-    let (inputs, outputs, extra, cb) = match build_payment::deserialize_inputs(inputs_json, outputs_json, extra, cb) {
-        Ok(tup) => tup,
-        Err(error_code) => {
-            trace!("api::build_payment_req_handler << result: {:?}", error_code);
-            return error_code as i32;
-        }
-    };
+    span::with_command_handle(command_handle, || metrics::measure_wallet_handler("build_payment_req_handler", rate_limit::PAYMENT, wallet_handle, || {
+        build_payment_req(command_handle, wallet_handle, submitter_did, inputs_json, outputs_json, extra, cb)
+    }))
+}
 
-    let payload = XferPayload::new(inputs, outputs, extra);
+/**
+    Build a payment request, as `build_payment_req_handler`, with options.
 
-    let result = payload.sign_transfer(
-        &CryptoSdk {},
-        wallet_handle,
-        Box::new(move |result| build_payment::handle_signing(command_handle, result, cb))
-    );
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the keys of the inputs
+    submitter_did, inputs_json, outputs_json, extra: as for `build_payment_req_handler`
+    options_json: the options, as for `build_batch_payment_req_with_options_handler`
+    cb: called with the payment request, or when simulating with the plan of
+    `build_batch_payment_req_with_options_handler`, of one request. The amounts
+    of the inputs aren't known, `fee` is null and `requiredFee` is the fee of
+    XFER_PUBLIC in the fee schedule. An oversized extra isn't stored.
 
-    let ec = match result {
-        Ok(()) => ErrorCode::Success,
-        Err(ec) => ec
-    };
-    trace!("api::build_payment_req_handler << result {:?}", ec);
-    return ec as i32;
+    # Returns
+    the errors of `build_payment_req_handler`
+*/
+#[no_mangle]
+pub extern "C" fn build_payment_req_with_options_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    submitter_did: *const c_char,
+    inputs_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    options_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    span::with_command_handle(command_handle, || metrics::measure_wallet_handler("build_payment_req_with_options_handler", rate_limit::PAYMENT, wallet_handle, || {
+        trace!("api::build_payment_req_with_options_handler called >> submitter_did (address) {:?}", submitter_did);
+        messages::clear_last_error();
+        let ec = match build_options(options_json) {
+            Ok(options) => sign_or_plan_payment(command_handle, wallet_handle, inputs_json, outputs_json, extra, &options, cb),
+            Err(ec) => ec,
+        };
+        trace!("api::build_payment_req_with_options_handler << result {:?}", ec);
+        return ec as i32;
+    }))
 }
 
 /// Parses inputted payment data and returns formatted UTXOs
@@ -392,52 +673,9 @@ pub extern "C" fn parse_payment_response_handler(
     resp_json: *const c_char,
     cb: JsonCallback
 ) -> i32 {
-    trace!("api::parse_payment_response_handler called");
-    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
-
-    if resp_json.is_null() {
-        trace!("api::parse_payment_response_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
-        return ErrorCode::CommonInvalidStructure as i32;
-    }
-
-    let resp_json_string = match string_from_char_ptr(resp_json) {
-        Some(s) => s,
-        None => {
-            error!("Failed to convert inputs_json pointer to string");
-            return ErrorCode::CommonInvalidStructure as i32;
-        }
-    };
-
-    debug!("api::parse_payment_response_handler >> resp_json: {:?}", &resp_json_string);
-
-    let response: ParsePaymentResponse = match ParsePaymentResponse::from_json(&resp_json_string)
-        .map_err(map_err_err!()) {
-        Ok(r) => r,
-        Err(_) => return ErrorCode::CommonInvalidStructure as i32,
-    };
-
-    // here is where the magic happens--conversion from input structure to output structure
-    // is handled in ParsePaymentReply::from_response
-    let reply: ParsePaymentReply = match from_response(response) {
-        Ok(rep) => rep,
-        Err(ec) => {
-            trace!("api::parse_payment_response_handler << result: {:?}", ec);
-            return ec as i32
-        },
-    };
-
-    let reply_str: String = match reply.to_json().map_err(map_err_err!()) {
-        Ok(j) => j,
-        Err(_) => return ErrorCode::CommonInvalidState as i32,
-    };
-
-    info!("Parsed payment response: {:?}", reply_str);
-
-    let reply_str_ptr: *const c_char = c_pointer_from_string(reply_str);
-
-    cb(command_handle, ErrorCode::Success as i32, reply_str_ptr);
-    trace!("api::parse_payment_response_handler << result: {:?}", ErrorCode::Success);
-    return ErrorCode::Success as i32;
+    span::with_command_handle(command_handle, || metrics::measure_handler("parse_payment_response_handler", || {
+        parse_payment_response(command_handle, resp_json, cb)
+    }))
 }
 
 
@@ -459,28 +697,10 @@ pub extern "C" fn build_get_utxo_request_handler(command_handle: i32,
                                                  _submitter_did: *const c_char,
                                                  payment_address: *const c_char,
                                                  cb: JsonCallback)-> i32 {
-    trace!("api::build_get_utxo_request_handler called");
-    let handle_result = api_result_handler!(< *const c_char >, command_handle, cb);
-
-    let payment_address = match str_from_char_ptr(payment_address) {
-        Some(s) => s,
-        None => {
-            error!("Failed to convert payment_address pointer to string");
-            return ErrorCode::CommonInvalidStructure as i32;
-        }
-    };
-    debug!("api::build_get_utxo_request_handler >> wallet_handle: {:?}, payment_address: {:?}", wallet_handle, payment_address);
-
-    let utxo_request =
-        GetUtxoOperationRequest::new(String::from(payment_address));
-    info!("Built GET_UTXO request: {:?}", utxo_request);
-    let utxo_request = utxo_request.serialize_to_pointer()
-        .map_err(|_| ErrorCode::CommonInvalidStructure);
-
-    let res = handle_result(utxo_request) as i32;
-    trace!("api::build_get_utxo_request_handler << result: {:?}", res);
-    return res;
-}
+    span::with_command_handle(command_handle, || metrics::measure_wallet_handler("build_get_utxo_request_handler", rate_limit::GET_UTXO, wallet_handle, || {
+        build_get_utxo_request(command_handle, wallet_handle, _submitter_did, payment_address, cb)
+    }))
+}
 
 /// Description
 ///
@@ -494,6 +714,11 @@ pub extern "C" fn build_get_utxo_request_handler(command_handle: i32,
 /// # Returns
 /// utxo_json: json. For format see https://github.com/evernym/libsovtoken/blob/master/doc/data_structures.md
 ///
+/// Replies with a state proof are cached by address and state root, see
+/// `utxo_cache_size` in the library config. The UTXOs spent by requests
+/// submitted with `submit_pending_txn_handler` are left out until they are
+/// rolled back, see `rollback_transaction_handler`.
+///
 /// # Errors
 /// CommonInvalidStructure when any of the inputs are invalid
 /// CommonInvalidState when any processing of inputs produces invalid results
@@ -503,53 +728,9 @@ pub extern "C" fn parse_get_utxo_response_handler(
     resp_json: *const c_char,
     cb: JsonCallback
 )-> i32 {
-
-    trace!("api::parse_get_utxo_response_handler called");
-    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
-
-    if resp_json.is_null() {
-        trace!("api::parse_get_utxo_response_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
-        return ErrorCode::CommonInvalidStructure as i32;
-    }
-
-    let resp_json_string = match string_from_char_ptr(resp_json) {
-        Some(s) => s,
-        None => {
-            error!("Failed to convert inputs_json pointer to string");
-            trace!("api::parse_get_utxo_response_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
-            return ErrorCode::CommonInvalidStructure as i32;
-        }
-    };
-
-    debug!("api::parse_get_utxo_response_handler >> resp_json: {:?}", resp_json_string);
-
-    let response: ParseGetUtxoResponse = match ParseGetUtxoResponse::from_json(&resp_json_string)
-        .map_err(map_err_err!()) {
-        Ok(r) => r,
-        Err(_) => return ErrorCode::CommonInvalidStructure as i32,
-    };
-
-    // here is where the magic happens--conversion from input structure to output structure
-    // is handled in ParseGetUtxoReply::from_response
-    let reply: ParseGetUtxoReply = match parse_get_utxo_response::from_response(response) {
-        Ok(reply) => reply,
-        Err(err) => {
-            trace!("api::parse_get_utxo_response_handler << result: {:?}", err);
-            return err as i32
-        }
-    };
-
-    let reply_str: String = match reply.to_json().map_err(map_err_err!())  {
-        Ok(j) => j,
-        Err(_) => return ErrorCode::CommonInvalidState as i32,
-    };
-    info!("Parsed GET_UTXO response, received: {:?}", reply_str);
-
-    let reply_str_ptr: *const c_char = c_pointer_from_string(reply_str);
-
-    cb(command_handle, ErrorCode::Success as i32, reply_str_ptr);
-    trace!("api::parse_get_utxo_response_handler << result: {:?}", ErrorCode::Success);
-    return ErrorCode::Success as i32;
+    span::with_command_handle(command_handle, || metrics::measure_handler("parse_get_utxo_response_handler", || {
+        parse_get_utxo_response(command_handle, resp_json, cb)
+    }))
 }
 
 /**
@@ -582,37 +763,9 @@ pub extern "C" fn build_set_txn_fees_handler(
     fees_json: *const c_char,
     cb: JsonCallback
 ) -> i32 {
-
-    trace!("api::build_set_txn_fees_handler called >> wallet_handle {}", wallet_handle);
-    let (did, set_fees, cb) = match set_fees::deserialize_inputs(
-        submitter_did,
-        fees_json,
-        cb
-    ) {
-        Ok(tup) => tup,
-        Err(e) => {
-            trace!("api::build_set_txn_fees_handler << result: {:?}", e);
-            return e as i32
-        }
-    };
-
-    let fees_request = set_fees.as_request(did);
-
-    let fees_request_pointer_option = fees_request.serialize_to_pointer()
-        .or(Err(ErrorCode::CommonInvalidStructure));
-
-    let fees_request_pointer = match fees_request_pointer_option {
-        Ok(ptr) => ptr,
-        Err(e) => {
-            trace!("api::build_set_txn_fees_handler << result: {:?}", e);
-            return e as i32
-        },
-    };
-
-    cb(command_handle, ErrorCode::Success as i32, fees_request_pointer);
-
-    trace!("api::build_set_txn_fees_handler << result: {:?}", ErrorCode::Success);
-    return ErrorCode::Success as i32;
+    span::with_command_handle(command_handle, || metrics::measure_wallet_handler("build_set_txn_fees_handler", rate_limit::SET_FEES, wallet_handle, || {
+        build_set_txn_fees(command_handle, wallet_handle, submitter_did, fees_json, cb)
+    }))
 }
 
 /// Description
@@ -620,7 +773,7 @@ pub extern "C" fn build_set_txn_fees_handler(
 ///
 /// from tokens-interface.md/BuildGetTxnFeesReqCB
 /// # Params
-/// param1: description.
+/// submitter_did: optional, null or "" for the `default_identifier` of the library config
 ///
 /// # Returns
 /// description. example if json, etc...
@@ -634,39 +787,9 @@ pub extern "C" fn build_get_txn_fees_handler(
     submitter_did: *const c_char,
     cb: JsonCallback
 ) -> i32 {
-
-    let handle_result = api_result_handler!(< *const c_char >, command_handle, cb);
-    trace!("api::build_get_txn_fees_handler called");
-
-    if cb.is_none() {
-        return handle_result(Err(ErrorCode::CommonInvalidStructure)) as i32;
-    }
-
-    let did = Did::from_pointer(submitter_did).map(|did| {
-        did.validate().map_err(map_err_trace!()).or(Err(ErrorCode::CommonInvalidStructure))
-    });
-
-    debug!("api::build_get_txn_fees_handler >> wallet_handle: {:?}, submitter_did: {:?}", wallet_handle, did);
-
-    let did = match opt_res_to_res_opt!(did) {
-        Ok(did) => did,
-        Err(e) => { return e as i32; }
-    };
-
-    let get_txn_request = GetFeesRequest::new().as_request(did);
-    info!("Built GET_TXN_FEES request: {:?}", get_txn_request);
-
-    let request_pointer = match get_txn_request.serialize_to_pointer() {
-        Ok(p) => p,
-        Err(_) => {
-            trace!("api::build_get_txn_fees_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
-            return ErrorCode::CommonInvalidState as i32
-        }
-    };
-
-    let res = handle_result(Ok(request_pointer)) as i32;
-    trace!("api::build_get_txn_fees_handler << res: {:?}", res);
-    return res;
+    span::with_command_handle(command_handle, || metrics::measure_wallet_handler("build_get_txn_fees_handler", rate_limit::GET_FEES, wallet_handle, || {
+        build_get_txn_fees(command_handle, wallet_handle, submitter_did, cb)
+    }))
 }
 
 /// Description
@@ -687,41 +810,161 @@ pub extern "C" fn parse_get_txn_fees_response_handler(
     resp_json: *const c_char,
     cb: JsonCallback
 )-> i32{
-    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+    parse_get_txn_fees("parse_get_txn_fees_response_handler", command_handle, None, resp_json, cb)
+}
 
-    trace!("api::parse_get_txn_fees_response_handler called");
-    if resp_json.is_null() {
-        return ErrorCode::CommonInvalidStructure as i32;
+/**
+    Parse a GET_FEES reply, as `parse_get_txn_fees_response_handler`, of the
+    pool of `pool_handle`.
+
+    The fees are remembered for the network of the pool, see
+    `sovtoken_register_pool`, instead of in the schedule shared by the
+    networks. `add_request_fees_with_options_handler` with the pool and the
+    NYM, ATTRIB and SCHEMA helpers use them.
+
+    # Params
+    command_handle: standard command handle
+    pool_handle: the pool the GET_FEES request was submitted to
+    resp_json: the reply of the ledger
+    cb: called with the fees, as by `parse_get_txn_fees_response_handler`
+
+    # Returns
+    the errors of `parse_get_txn_fees_response_handler`
+*/
+#[no_mangle]
+pub extern "C" fn parse_get_txn_fees_response_of_pool_handler(
+    command_handle: i32,
+    pool_handle: i32,
+    resp_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    parse_get_txn_fees("parse_get_txn_fees_response_of_pool_handler", command_handle, Some(pool_handle), resp_json, cb)
+}
+
+/**
+    Parse a GET_FEES reply into an attestation of the fee schedule in force.
+
+    The attestation can be stored with a payment to prove later which fees
+    were in force, see `logic::fee_attestation`. Unlike
+    `parse_get_txn_fees_response_handler`, the fees aren't remembered.
+
+    # Params
+    command_handle: standard command handle
+    resp_json: the GET_FEES reply
+    cb: called with the attestation:
+    ```text
+    {
+        "scheduleHash": <str>, // sha256 hex of the fees json, txn types sorted, no whitespace
+        "fees": {"<txn type>": <int>, ...},
+        "reqId": <int>, // of the GET_FEES request
+        "ledgerRoot": <str>, // state root the nodes signed, null without a state proof
+        "txnRoot": <str>, // transactions root the nodes signed, null without a state proof
+        "ledgerId": <int>, // null without a state proof
+        "timestamp": <int>, // when the nodes signed, null without a state proof
+        "multiSignature": <json> // the BLS multi-signature of the nodes, null without a state proof
     }
-    let resp_json_string = match string_from_char_ptr(resp_json) {
-        Some(s) => s,
-        None => {
-            error!("Failed to convert resp_json pointer to string");
-            trace!("api::parse_get_txn_fees_response_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
-            return ErrorCode::CommonInvalidStructure as i32;
+    ```
+    The root of the state proof is checked against the signed state root.
+    The proof and the multi-signature aren't verified, this is left to
+    libindy and to the BLS keys of the pool.
+
+    # Returns
+    CommonInvalidStructure when resp_json isn't a GET_FEES reply or the root
+    of its state proof isn't the signed state root
+*/
+#[no_mangle]
+pub extern "C" fn attest_get_txn_fees_response_handler(
+    command_handle: i32,
+    resp_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::attest_get_txn_fees_response_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let attestation = str_from_char_ptr(resp_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|resp_json| fee_attestation::attest(&wire_format::to_json(resp_json)?))
+        .and_then(|attestation| attestation.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+    let attestation = match attestation {
+        Ok(attestation) => attestation,
+        Err(ec) => {
+            trace!("api::attest_get_txn_fees_response_handler << result: {:?}", ec);
+            return ec as i32;
         }
     };
 
-    debug!("api::parse_get_txn_fees_response_handler >> resp_json: {:?}", resp_json_string);
-    debug!("Deserialized parse_get_txn_fees_response_handler arguments");
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("fee_attestation", attestation));
 
-    let fees_json_obj =
-        match parse_fees_from_get_txn_fees_response(resp_json_string){
-            Ok(s) => {
-                s
-            },
-            Err(_) => {
-                trace!("api::parse_get_txn_fees_response_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
-                return ErrorCode::CommonInvalidStructure as i32;
-            }
-        };
-    info!("Parsed get_txn_fees_response, result: {:?}", fees_json_obj);
-    let fees_json_ptr : *const c_char = c_pointer_from_string(fees_json_obj);
-    cb(command_handle, ErrorCode::Success as i32, fees_json_ptr);
+    let res = ErrorCode::Success;
+    trace!("api::attest_get_txn_fees_response_handler << result: {:?}", res);
+    return res as i32;
+}
 
-    let res = ErrorCode::Success as i32;
-    trace!("api::parse_get_txn_fees_response_handler << result: {:?}", res);
-    return res;
+/**
+    Preload or override the cached fee schedule, without a GET_FEES round trip.
+
+    For agents distributing the fee schedule through their own config. The
+    fees replace the cached fees of their txn types at once, and are used as
+    the fees parsed by `parse_get_txn_fees_response_handler`, e.g. to leave
+    a request of a type with no fee without fees. When a GET_FEES reply is
+    parsed later, its fees replace the preloaded ones, and a preloaded fee
+    the ledger disagrees with is logged and listed in `mismatches`.
+
+    # Params
+    command_handle: standard command handle
+    fees_json: the fees by txn type, as for `build_set_txn_fees_handler`:
+    ```text
+    {"1": 4, "10001": 12, "ATTRIB": 0}
+    ```
+    cb: called with the cached schedule:
+    ```text
+    {
+        "fees": {"<txn type>": <int>, ...},
+        "preloaded": ["<txn type>", ...], // the fees no GET_FEES reply confirmed yet
+        "mismatches": [{"txnType": <str>, "preloaded": <int>, "ledger": <int>}, ...] // the last 32
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when the fees are empty or a txn type isn't an integer or known name
+*/
+#[no_mangle]
+pub extern "C" fn set_fee_schedule_handler(
+    command_handle: i32,
+    fees_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    set_fee_schedule("set_fee_schedule_handler", command_handle, None, fees_json, cb)
+}
+
+/**
+    Preload or override the fee schedule of the network of the pool of
+    `pool_handle`, as `set_fee_schedule_handler` does for the schedule
+    shared by the networks.
+
+    The fees are used for the requests of the pool, see
+    `parse_get_txn_fees_response_of_pool_handler`, and are replaced by the
+    fees of a GET_FEES reply parsed for the pool.
+
+    # Params
+    command_handle: standard command handle
+    pool_handle: the pool whose network the fees are of, see `sovtoken_register_pool`
+    fees_json: the fees by txn type, as for `set_fee_schedule_handler`
+    cb: called with the schedule of the network, as by `set_fee_schedule_handler`
+
+    # Returns
+    the errors of `set_fee_schedule_handler`
+*/
+#[no_mangle]
+pub extern "C" fn set_fee_schedule_of_pool_handler(
+    command_handle: i32,
+    pool_handle: i32,
+    fees_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    set_fee_schedule("set_fee_schedule_of_pool_handler", command_handle, Some(pool_handle), fees_json, cb)
 }
 
 
@@ -756,42 +999,16 @@ pub extern "C" fn build_mint_txn_handler(
     cb: JsonCallback
 ) -> i32
 {
-    trace!("api::build_mint_txn_handle called >> wallet_handle {}", wallet_handle);
-    let (did, outputs, extra, cb) = match minting::deserialize_inputs(
-        submitter_did,
-        outputs_json,
-        extra,
-        cb
-    ) {
-        Ok(tup) => tup,
-        Err(e) => {
-            trace!("api::build_mint_txn_handle << res: {:?}", e);
-            return e as i32
-        },
-    };
-
-    debug!("Deserialized build_mint_txn_handler arguments.");
-
-    let mint_request = match minting::build_mint_request(did, outputs, extra) {
-        Ok(json) => json,
-        Err(e) => {
-            trace!("api::build_mint_txn_handle << res: {:?}", e);
-            return e as i32
-        }
-    };
-    debug!("Serialized mint request as pointer.");
-
-    cb(command_handle, ErrorCode::Success as i32, mint_request);
-    let res = ErrorCode::Success;
-    trace!("api::build_mint_txn_handle << res: {:?}", res);
-    return res as i32;
+    span::with_command_handle(command_handle, || metrics::measure_wallet_handler("build_mint_txn_handler", rate_limit::MINT, wallet_handle, || {
+        build_mint_txn(command_handle, wallet_handle, submitter_did, outputs_json, extra, cb)
+    }))
 }
 
 /// Build a verify transaction request.
 ///
 /// # Parameters
 /// wallet_handle
-/// did
+/// did -- optional, null or "" for the `default_identifier` of the library config
 /// txo -- txo to get transaction
 ///
 /// # Returns
@@ -804,30 +1021,9 @@ pub extern "C" fn build_verify_req_handler(
     txo: *const c_char,
     cb: JsonCallback
 ) -> i32 {
-    trace!("api::build_verify_req called >> wallet_handle {}", wallet_handle);
-
-    let (did, txo, cb) = match verify::deserialize(did, txo, cb) {
-        Ok(a) => a,
-        Err(ec) => {
-            trace!("api::build_verify_req << res {:?}", ec);
-            return ec as i32;
-        }
-    };
-    let did = did.map(|s| String::from(s));
-
-    let res = indy::ledger::Ledger::build_get_txn_request_async(
-        did.as_ref().map(|x| &**x),
-        Some(LEDGER_ID),
-        txo.seq_no as i32,
-        move |ec, res| {
-            trace!("api::build_verify_req cb << ec: {:?}, res: {:?}", ec, res);
-            cb(command_handle, ec as i32, c_pointer_from_string(res));
-        }
-    );
-
-    trace!("api::build_verify_req << res {:?}", res);
-
-    res as i32
+    span::with_command_handle(command_handle, || metrics::measure_wallet_handler("build_verify_req_handler", rate_limit::VERIFY, wallet_handle, || {
+        build_verify_req(command_handle, wallet_handle, did, txo, cb)
+    }))
 }
 
 /// Parse response of verification of txo
@@ -839,9 +1035,13 @@ pub extern "C" fn build_verify_req_handler(
 /// txn_json: {
 ///     sources: [<str>, ]
 ///     receipts: [ {
+///         ver: <int>, // version of the receipt json, 1
 ///         recipient: <str>, // payment address of recipient
 ///         receipt: <str>, // receipt that can be used for payment referencing and verification
 ///         amount: <int>, // amount
+///         seqNo: <int>, // seqNo of the transaction
+///         txnTime: <int>, // time the transaction was ordered, null when the reply doesn't have it
+///         extra: <str>, // extra of the transaction, "" when it has none
 ///     } ],
 ///     extra: <str>, //optional data
 /// }
@@ -851,39 +1051,37 @@ pub extern "C" fn parse_verify_response_handler(
     resp_json: *const c_char,
     cb: JsonCallback
 ) -> i32 {
-    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
-
-    trace!("api::parse_verify_response_handler called");
-    if resp_json.is_null() {
-        return ErrorCode::CommonInvalidStructure as i32;
-    }
+    span::with_command_handle(command_handle, || metrics::measure_handler("parse_verify_response_handler", || {
+        parse_verify_response(command_handle, resp_json, cb)
+    }))
+}
 
-    let resp_json_string = match string_from_char_ptr(resp_json) {
-        Some(s) => s,
-        None => {
-            error!("Failed to convert resp_json pointer to string");
-            trace!("api::parse_verify_response_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
-            return ErrorCode::CommonInvalidStructure as i32;
-        }
-    };
-
-    debug!("api::parse_verify_response_handler >> resp_json: {:?}", resp_json_string);
-
-    let result = match parse_verify::parse_response(&resp_json_string) {
-        Ok(e) => e,
-        Err(ec) => {
-            trace!("api::parse_verify_response_handler << result: {:?}", ec);
-            return ec as i32;
-        }
-    };
-
-    let ec = ErrorCode::Success;
-
-    trace!("api::parse_verify_response_handler << result: {:?}", result);
-    let result = c_pointer_from_string(result);
-    cb(command_handle, ErrorCode::Success as i32, result);
-
-    ec as i32
+/// Build a MINT request of test tokens for a developer network.
+///
+/// Only available with the `devnet` feature. The request is signed by the
+/// trustees bundled in [`DEVNET_TRUSTEE_SEEDS`], which are stored in the wallet.
+///
+/// # Parameters
+/// wallet_handle -- wallet the trustee dids are stored in
+/// payment_address -- qualified address receiving the tokens
+/// amount -- number of tokens to mint
+///
+/// # Returns
+/// Signed mint request to send to the ledger
+///
+/// [`DEVNET_TRUSTEE_SEEDS`]: ../logic/devnet/constant.DEVNET_TRUSTEE_SEEDS.html
+#[cfg(feature = "devnet")]
+#[no_mangle]
+pub extern "C" fn request_test_tokens_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    payment_address: *const c_char,
+    amount: u64,
+    cb: JsonCallback
+) -> i32 {
+    span::with_command_handle(command_handle, || metrics::measure_wallet_handler("request_test_tokens_handler", rate_limit::TEST_TOKENS, wallet_handle, || {
+        request_test_tokens(command_handle, wallet_handle, payment_address, amount, cb)
+    }))
 }
 
 #[no_mangle]
@@ -928,63 +1126,5313 @@ pub extern fn free_parsed_state_proof(sp: *const c_char) -> i32 {
 }
 
 /**
-    exported method indy-sdk will call for us to register our payment methods with indy-sdk
+    Export the audit log of built and parsed payments.
 
     # Params
-    none
+    command_handle: standard command handle
+    format: "json" or "csv"
+    cb: called with the exported audit log. json is formatted as:
+    ```text
+    [
+        {
+            "timestamp": <int>, // seconds since the unix epoch
+            "operation": "build_payment" | "parse_payment",
+            "request_hash": <str>, // canonical hash of the request or response json, see `hash_request_handler`
+            "outcome": <str>, // "Success" or the name of the error code
+            "hash_version": <int> // version of the request_hash, 1 for the entries before it was versioned
+        },
+        ...
+    ]
+    ```
+    csv has the header `timestamp,operation,request_hash,outcome,hash_version`.
 
     # Returns
-    ErrorCode from register_payment_method
+    CommonInvalidStructure when the format is unknown
+    CommonInvalidState when no audit log is configured, see `sovtoken_init_with_config`
+    CommonIOError when the audit log can't be read
 */
 #[no_mangle]
-pub extern fn sovtoken_init() -> i32 {
+pub extern "C" fn export_audit_log_handler(
+    command_handle: i32,
+    format: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::export_audit_log_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
 
-    super::utils::logger::init_log();
+    let format = match str_from_char_ptr(format).ok_or(ErrorCode::CommonInvalidStructure).and_then(ExportFormat::from_str) {
+        Ok(format) => format,
+        Err(ec) => {
+            trace!("api::export_audit_log_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
 
-    debug!("sovtoken_init() started");
-    debug!("Going to call Payment::register");
-
-    if let Err(e) = Payment::register_method(
-        PAYMENT_METHOD_NAME,
-        Some(create_payment_address_handler),
-        Some(add_request_fees_handler),
-        Some(parse_response_with_fees_handler),
-        Some(build_get_utxo_request_handler),
-        Some(parse_get_utxo_response_handler),
-        Some(build_payment_req_handler),
-        Some(parse_payment_response_handler),
-        Some(build_mint_txn_handler),
-        Some(build_set_txn_fees_handler),
-        Some(build_get_txn_fees_handler),
-        Some(parse_get_txn_fees_response_handler),
-        Some(build_verify_req_handler),
-        Some(parse_verify_response_handler),
-    ) {
-        debug!("Payment::register failed with {:?}", e);
-        return e as i32
+    let exported = audit_log::configured_sink()
+        .ok_or(ErrorCode::CommonInvalidState)
+        .and_then(|sink| sink.entries())
+        .and_then(|entries| audit_log::export_entries(&entries, format));
+
+    let exported = match exported {
+        Ok(exported) => exported,
+        Err(ec) => {
+            trace!("api::export_audit_log_handler << result: {:?}", ec);
+            return ec as i32;
+        }
     };
 
-    debug!("Going to call Ledger::register_transaction_parser_for_sp for GET_UTXO");
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("audit_log", exported));
 
-    if let Err(e) = Ledger::register_transaction_parser_for_sp(
-        GET_UTXO,
-        Some(get_utxo_state_proof_parser),
-        Some(free_parsed_state_proof)
-    ) {
-        debug!("Ledger::register_transaction_parser_for_sp for GET_UTXO failed with {:?}", e);
-        return e as i32
+    let res = ErrorCode::Success;
+    trace!("api::export_audit_log_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Export the transaction history of a payment address as csv.
+
+    # Params
+    command_handle: standard command handle
+    history_json: the parsed transaction history:
+    ```text
+    [
+        {
+            "address": <str>, // the payment address the entry belongs to
+            "counterparty": <str>,
+            "amountIn": <int>, // optional, default 0
+            "amountOut": <int>, // optional, default 0
+            "fees": <int>, // optional, default 0
+            "seqNo": <int>,
+            "txnTime": <int> // seconds since the unix epoch
+        },
+        ...
+    ]
+    ```
+    address: payment address to export the history of
+    from: first txnTime to export
+    to: last txnTime to export, 0 for no upper bound
+    cb: called with the csv, with the header `date,counterparty,amount_in,amount_out,fees,seq_no`
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid
+*/
+#[no_mangle]
+pub extern "C" fn export_history_csv_handler(
+    command_handle: i32,
+    history_json: *const c_char,
+    address: *const c_char,
+    from: u64,
+    to: u64,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::export_history_csv_handler called >> from: {:?}, to: {:?}", from, to);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let address = match str_from_char_ptr(address) {
+        Some(s) => s,
+        None => {
+            error!("Failed to convert address pointer to string");
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
     };
 
-    debug!("Going to call Ledger::register_transaction_parser_for_sp for GET_FEES");
-    if let Err(e) =  Ledger::register_transaction_parser_for_sp(
-        GET_FEES,
-        Some(get_fees_state_proof_parser),
-        Some(free_parsed_state_proof)
-    ) {
-        debug!("Ledger::register_transaction_parser_for_sp for GET_FEES failed with {:?}", e);
-        return e as i32
+    let history = str_from_char_ptr(history_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| Vec::<HistoryEntry>::from_json(json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure)));
+
+    let csv = match history.and_then(|history| history::export_history_csv(&history, address, from, to)) {
+        Ok(csv) => csv,
+        Err(ec) => {
+            trace!("api::export_history_csv_handler << result: {:?}", ec);
+            return ec as i32;
+        }
     };
 
-    debug!("sovtoken_init() returning ErrorCode::Success");
-    return ErrorCode::Success as i32;
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("history_csv", csv));
+
+    let res = ErrorCode::Success;
+    trace!("api::export_history_csv_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Get the balance of every sovrin payment address in several wallets.
+
+    The UTXOs of each address are fetched from the ledger, without waiting
+    for one another. The balances are cached in the wallets, for
+    `search_payment_addresses_handler`.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handles_json: json array of wallet handles, e.g. `[1, 2]`
+    pool_handle: pool to fetch the UTXOs from
+    cb: called with the balance summary:
+    ```text
+    {
+        "wallets": [
+            {
+                "walletHandle": <int>,
+                "addresses": [{"address": <str>, "balance": <int>, "utxoCount": <int>}, ...],
+                "balance": <int>
+            },
+            ...
+        ],
+        "total": <int>
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid
+    or the error of starting to list the addresses
+
+    cb is called with CommonInvalidState when a balance overflows, and with
+    the errors of listing the addresses or fetching the UTXOs.
+*/
+#[no_mangle]
+pub extern "C" fn get_aggregate_balance_handler(
+    command_handle: i32,
+    wallet_handles_json: *const c_char,
+    pool_handle: i32,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::get_aggregate_balance_handler called >> pool_handle: {:?}", pool_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let wallet_handles = str_from_char_ptr(wallet_handles_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| Vec::<i32>::from_json(json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure)));
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let aggregated = wallet_handles.and_then(|wallet_handles| {
+        let cached_handles = wallet_handles.clone();
+        services().aggregator().aggregate_balance(&wallet_handles, pool_handle, move |balance| {
+            // the balances are cached in the wallets off the thread of libindy
+            let wallet_handles = cached_handles.clone();
+            context::spawn(move || {
+                let balance = balance
+                    .map(|balance| {
+                        for wallet_handle in wallet_handles {
+                            if let Err(ec) = services().address_book().cache_balances(wallet_handle, &balance, now) {
+                                warn!("Failed to cache the balances of wallet {}: {:?}", wallet_handle, ec);
+                            }
+                        }
+                        balance
+                    })
+                    .and_then(|balance| balance.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+                match balance {
+                    Ok(balance) => dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("aggregate_balance", balance)),
+                    Err(ec) => dispatcher::dispatch(cb, command_handle, ec as i32, String::new()),
+                }
+            });
+        })
+    });
+
+    if let Err(ec) = aggregated {
+        trace!("api::get_aggregate_balance_handler << result: {:?}", ec);
+        return ec as i32;
+    }
+
+    let res = ErrorCode::Success;
+    trace!("api::get_aggregate_balance_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Store a named payment template in the wallet.
+
+    A template with the same name is replaced.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet to store the template in
+    template_name: name of the template
+    template_json: the template:
+    ```text
+    {
+        "recipients": [{"recipient": <str>, "share": <int>}, ...], // the amount is split by share
+        "extra": <str>, // optional memo
+        "feePolicy": {"type": "none"} | {"type": "fixed", "amount": <int>}, // optional, default none
+        "changeAddress": <str> // optional, receives what the inputs hold on top of the amount and fee
+    }
+    ```
+    cb: called with the stored template name
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid
+*/
+#[no_mangle]
+pub extern "C" fn store_payment_template_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    template_name: *const c_char,
+    template_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::store_payment_template_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let template_name = match str_from_char_ptr(template_name) {
+        Some(s) => s,
+        None => {
+            error!("Failed to convert template_name pointer to string");
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let result = str_from_char_ptr(template_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| PaymentTemplate::from_json(json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure)))
+        .and_then(PaymentTemplate::validate)
+        .and_then(|template| services().templates().store(wallet_handle, template_name, &template));
+
+    if let Err(ec) = result {
+        trace!("api::store_payment_template_handler << result: {:?}", ec);
+        return ec as i32;
+    }
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("template_name", template_name.to_string()));
+
+    let res = ErrorCode::Success;
+    trace!("api::store_payment_template_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Build a payment request from a payment template stored in the wallet.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the template and the keys of the inputs
+    template_name: name of the template, see `store_payment_template_handler`
+    amount: amount split over the recipients of the template
+    utxos_json: the UTXOs to pay with, as returned by `parse_get_utxo_response_handler`
+    cb: called with the signed payment request json
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid
+    WalletItemNotFound when there is no such template
+    PaymentInsufficientFundsError when the UTXOs don't cover the amount and the fee
+    PaymentExtraFundsError when the UTXOs hold more and the template has no change address
+*/
+#[no_mangle]
+pub extern "C" fn build_payment_from_template_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    template_name: *const c_char,
+    amount: u64,
+    utxos_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::build_payment_from_template_handler called >> wallet_handle {}, amount {}", wallet_handle, amount);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let template_name = match str_from_char_ptr(template_name) {
+        Some(s) => s,
+        None => {
+            error!("Failed to convert template_name pointer to string");
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let utxos = str_from_char_ptr(utxos_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| Vec::<UTXO>::from_json(json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure)));
+
+    let result = utxos
+        .and_then(|utxos| {
+            services().templates()
+                .get(wallet_handle, template_name)
+                .and_then(|template| template.builder(amount, &utxos))
+        })
+        .and_then(|builder| sign_payment_request(builder, command_handle, wallet_handle, cb));
+
+    let ec = match result {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec
+    };
+    trace!("api::build_payment_from_template_handler << result: {:?}", ec);
+    return ec as i32;
+}
+
+/**
+    Register a payment to materialize later with `materialize_intent_handler`.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet to store the intent in
+    intent_json: the payment:
+    ```text
+    {
+        "recipient": <str>,
+        "amount": <int>,
+        "notBefore": <int>, // optional, earliest materialization in seconds since the unix epoch
+        "fee": <int>, // optional, default 0
+        "extra": <str>, // optional
+        "changeAddress": <str> // optional, receives what the inputs hold on top of the amount and fee
+    }
+    ```
+    cb: called with the id of the intent
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid
+*/
+#[no_mangle]
+pub extern "C" fn register_payment_intent_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    intent_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::register_payment_intent_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let intent_id = str_from_char_ptr(intent_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| PaymentIntent::from_json(json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure)))
+        .and_then(|intent| services().intents().register(wallet_handle, &intent));
+
+    let intent_id = match intent_id {
+        Ok(intent_id) => intent_id,
+        Err(ec) => {
+            trace!("api::register_payment_intent_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("intent_id", intent_id));
+
+    let res = ErrorCode::Success;
+    trace!("api::register_payment_intent_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Materialize a payment intent into a signed payment request.
+
+    The UTXOs are reserved for the intent, they can't be used by another intent.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the intent and the keys of the inputs
+    intent_id: id returned by `register_payment_intent_handler`
+    utxos_json: the UTXOs to pay with, as returned by `parse_get_utxo_response_handler`
+    cb: called with the signed payment request json
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid
+    CommonInvalidState when the intent's notBefore hasn't passed or a UTXO is reserved by another intent
+    WalletItemNotFound when there is no such intent
+    PaymentInsufficientFundsError when the UTXOs don't cover the amount and the fee
+    PaymentExtraFundsError when the UTXOs hold more and the intent has no change address
+*/
+#[no_mangle]
+pub extern "C" fn materialize_intent_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    intent_id: *const c_char,
+    utxos_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::materialize_intent_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let intent_id = match str_from_char_ptr(intent_id) {
+        Some(s) => s,
+        None => {
+            error!("Failed to convert intent_id pointer to string");
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let result = str_from_char_ptr(utxos_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| Vec::<UTXO>::from_json(json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure)))
+        .and_then(|utxos| services().intents().materialize(wallet_handle, intent_id, &utxos, now))
+        .and_then(|builder| sign_payment_request(builder, command_handle, wallet_handle, cb));
+
+    let ec = match result {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec
+    };
+    trace!("api::materialize_intent_handler << result: {:?}", ec);
+    return ec as i32;
+}
+
+/**
+    Build a payout batch: one payment to many recipients.
+
+    Outputs to the same recipient are merged. When the outputs don't fit in one
+    request, they are split over several requests, each paid with the next UTXOs.
+    The change of a request goes back to the address of the last UTXO it spends.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the keys of the inputs
+    utxos_json: the UTXOs to pay with, as returned by `parse_get_utxo_response_handler`
+    outputs_json: the outputs, same format as for `build_payment_req_handler`
+    extra: optional extra data added to every request
+    max_outputs: maximum number of outputs per request, change included. 0 for the `max_outputs` of the library config,
+    which is also the upper bound
+    cb: called with the manifest of the batch:
+    ```text
+    {
+        "requests": [
+            {
+                "index": <int>,
+                "outputCount": <int>,
+                "amount": <int>, // paid to the recipients, change excluded
+                "request": <str> // the signed request json
+            },
+            ...
+        ],
+        "totalAmount": <int>,
+        "mergedDuplicates": <int>
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid
+    PaymentInsufficientFundsError when the UTXOs don't cover the outputs
+*/
+#[no_mangle]
+pub extern "C" fn build_batch_payment_req_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    utxos_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    max_outputs: u32,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::build_batch_payment_req_handler called >> wallet_handle {}, max_outputs {}", wallet_handle, max_outputs);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let result = batch_chunks(wallet_handle, utxos_json, outputs_json, extra, max_outputs, &BuildOptions::default())
+        .and_then(|(chunks, merged_duplicates)| sign_batch_manifest(chunks, merged_duplicates, command_handle, wallet_handle, cb));
+
+    let ec = match result {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec
+    };
+    trace!("api::build_batch_payment_req_handler << result: {:?}", ec);
+    return ec as i32;
+}
+
+/**
+    Build the payment requests of a transfer too large for one request.
+
+    The UTXOs are split into groups small enough that a request spending a
+    group stays below the `max_request_bytes` and `max_inputs` of the library config. Each
+    request pays the next outputs with its group, so an output may be paid by
+    two requests. Only as many UTXOs as the outputs need are spent, the change
+    of a request goes back to the address of the last UTXO it spends.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the keys of the inputs
+    utxos_json: the UTXOs to pay with, as returned by `parse_get_utxo_response_handler`
+    outputs_json: the outputs, same format as for `build_payment_req_handler`
+    extra: optional extra data added to every request
+    cb: called with the requests, in the same format as for `build_batch_payment_req_handler`.
+    The requests should be submitted in the order of their `index`.
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid or the outputs alone don't fit in a request
+    PaymentInsufficientFundsError when the UTXOs don't cover the outputs
+*/
+#[no_mangle]
+pub extern "C" fn build_split_payment_req_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    utxos_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::build_split_payment_req_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let result = split_chunks(wallet_handle, utxos_json, outputs_json, extra, &BuildOptions::default())
+        .and_then(|chunks| sign_batch_manifest(chunks, 0, command_handle, wallet_handle, cb));
+
+    let ec = match result {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec
+    };
+    trace!("api::build_split_payment_req_handler << result: {:?}", ec);
+    return ec as i32;
+}
+
+/**
+    Build a payout batch, as `build_batch_payment_req_handler`, with options.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the keys of the inputs
+    utxos_json: the UTXOs to pay with, as returned by `parse_get_utxo_response_handler`
+    outputs_json: the outputs, same format as for `build_payment_req_handler`
+    extra: optional extra data added to every request
+    max_outputs: as for `build_batch_payment_req_handler`
+    options_json: the options, every field is optional:
+    ```text
+    {
+        "simulate": <bool>, // return the plan of the requests instead of signing them, nothing is
+                            // stored in the wallet, default false
+        "allowZeroOutputs": <bool>, // build outputs of 0 tokens, default the allow_zero_outputs of the library config
+        "poolHandle": <int>, // for `add_request_fees_with_options_handler`, the pool the request is for
+        "preserveOrder": <bool> // for `build_payment_req_with_options_handler` and `add_request_fees_with_options_handler`,
+                                // keep the inputs and outputs in the order given, default false
+    }
+    ```
+    cb: called with the manifest of `build_batch_payment_req_handler`, or when simulating with the plan:
+    ```text
+    {
+        "requests": [
+            {
+                "index": <int>,
+                "amount": <int>, // paid to the recipients, change excluded
+                "inputs": [{"address": <str>, "seqNo": <int>, "amount": <int>}, ...],
+                "outputs": [{"address": <str>, "amount": <int>}, ...], // change included
+                "change": {"address": <str>, "amount": <int>}, // null without change
+                "fee": <int>, // what the inputs hold on top of the outputs
+                "requiredFee": <int>, // the fee of XFER_PUBLIC in the fee schedule, null when unknown
+                "estimatedBytes": <int>, // largest size of the signed request
+                "signingPayloads": [...] // one per input, as returned by `get_signing_payload_handler`
+            },
+            ...
+        ],
+        "totalAmount": <int>,
+        "totalChange": <int>,
+        "totalFees": <int>,
+        "totalRequiredFees": <int>,
+        "estimatedBytes": <int>,
+        "mergedDuplicates": <int>
+    }
+    ```
+
+    # Returns
+    the errors of `build_batch_payment_req_handler`
+*/
+#[no_mangle]
+pub extern "C" fn build_batch_payment_req_with_options_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    utxos_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    max_outputs: u32,
+    options_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::build_batch_payment_req_with_options_handler called >> wallet_handle {}, max_outputs {}", wallet_handle, max_outputs);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let result = build_options(options_json).and_then(|options| {
+        let (chunks, merged_duplicates) = batch_chunks(wallet_handle, utxos_json, outputs_json, extra, max_outputs, &options)?;
+        finish_build(&options, chunks, merged_duplicates, command_handle, wallet_handle, cb)
+    });
+
+    let ec = match result {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec
+    };
+    trace!("api::build_batch_payment_req_with_options_handler << result: {:?}", ec);
+    return ec as i32;
+}
+
+/**
+    Build the payment requests of a large transfer, as `build_split_payment_req_handler`, with options.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the keys of the inputs
+    utxos_json: the UTXOs to pay with, as returned by `parse_get_utxo_response_handler`
+    outputs_json: the outputs, same format as for `build_payment_req_handler`
+    extra: optional extra data added to every request
+    options_json: the options, as for `build_batch_payment_req_with_options_handler`
+    cb: called with the manifest of `build_split_payment_req_handler`, or when simulating with
+    the plan of `build_batch_payment_req_with_options_handler`
+
+    # Returns
+    the errors of `build_split_payment_req_handler`
+*/
+#[no_mangle]
+pub extern "C" fn build_split_payment_req_with_options_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    utxos_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    options_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::build_split_payment_req_with_options_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let result = build_options(options_json).and_then(|options| {
+        let chunks = split_chunks(wallet_handle, utxos_json, outputs_json, extra, &options)?;
+        finish_build(&options, chunks, 0, command_handle, wallet_handle, cb)
+    });
+
+    let ec = match result {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec
+    };
+    trace!("api::build_split_payment_req_with_options_handler << result: {:?}", ec);
+    return ec as i32;
+}
+
+/**
+    Match ledger replies to the requests they answer and parse them.
+
+    Replies are matched to requests by `reqId` and parsed with the parser of
+    the request type: XFER_PUBLIC, GET_UTXO and GET_FEES replies are parsed
+    as by their parse handlers, other replies are passed through as they are.
+
+    # Params
+    command_handle: standard command handle
+    requests_json: json array of the submitted requests, as objects or strings
+    replies_json: json array of the received replies, as objects or strings
+    cb: called with the outcome of every request, in the order of the requests:
+    ```text
+    {
+        "correlations": [
+            {
+                "reqId": <int>,
+                "type": <str>, // the operation type of the request
+                "requestHash": <str>, // see `hash_request_handler`
+                "status": "parsed" | "failed" | "pending",
+                "result": <str>, // the parsed reply, when parsed
+                "error": <int> // the error code, when failed
+            },
+            ...
+        ],
+        "unmatchedReplies": [<str>, ...]
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid or a request has no reqId
+*/
+#[no_mangle]
+pub extern "C" fn correlate_response_handler(
+    command_handle: i32,
+    requests_json: *const c_char,
+    replies_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::correlate_response_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let requests = str_from_char_ptr(requests_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(correlation::messages_from_json);
+
+    let replies = str_from_char_ptr(replies_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(correlation::messages_from_json);
+
+    let report = requests.and_then(|requests| replies.and_then(|replies| {
+        Correlator::with_default_parsers().correlate(&requests, &replies)
+    }));
+
+    let report_json = match report.and_then(|report| report.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState))) {
+        Ok(json) => json,
+        Err(ec) => {
+            trace!("api::correlate_response_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("correlation_report", report_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::correlate_response_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Refresh a request the ledger rejected for a stale reqId or agreement acceptance.
+
+    The request gets a new reqId and, when it accepts a transaction author
+    agreement, the current acceptance time. The signatures of the inputs of
+    an XFER_PUBLIC operation, of the fees and of the submitter DIDs are made
+    again with the keys in the wallet.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the keys of the inputs and the DIDs
+    request_json: the rejected request json
+    cb: called with the refreshed request json
+
+    # Returns
+    CommonInvalidStructure when the request is invalid
+*/
+#[no_mangle]
+pub extern "C" fn refresh_request_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    request_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::refresh_request_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let request_json = match str_from_char_ptr(request_json) {
+        Some(json) => json,
+        None => {
+            trace!("api::refresh_request_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let result = refresh_request::refresh_request(wallet_handle, request_json, now, Box::new(move |request| {
+        match request {
+            Ok(request) => dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("request", request)),
+            Err(ec) => dispatcher::dispatch(cb, command_handle, ec as i32, String::new()),
+        };
+    }));
+
+    let ec = match result {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec
+    };
+    trace!("api::refresh_request_handler << result: {:?}", ec);
+    return ec as i32;
+}
+
+/**
+    Build a GET_FEES request for the fees of some transaction types only.
+
+    Ledgers whose plugin doesn't support the filter answer with the full fee
+    schedule; [`parse_get_txn_fees_response_handler`] then keeps only the
+    requested types.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet handle
+    submitter_did: optional DID of the submitter, null or "" for the `default_identifier` of the library config
+    types_json: the transaction types to get the fees of
+    ```text
+    ["1", "10001"]
+    ```
+    cb: called with the GET_FEES request json
+
+    # Returns
+    CommonInvalidStructure when the DID is invalid or types_json isn't a
+    non empty array of strings
+
+    [`parse_get_txn_fees_response_handler`]: fn.parse_get_txn_fees_response_handler.html
+*/
+#[no_mangle]
+pub extern "C" fn build_get_txn_fees_for_types_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    submitter_did: *const c_char,
+    types_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::build_get_txn_fees_for_types_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let did = Did::submitter_from_pointer(submitter_did);
+
+    let did = match did {
+        Ok(did) => did,
+        Err(e) => {
+            trace!("api::build_get_txn_fees_for_types_handler << result: {:?}", e);
+            return e as i32;
+        }
+    };
+
+    let txn_types = str_from_char_ptr(types_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| Vec::<String>::from_json(json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure)));
+
+    let txn_types = match txn_types {
+        Ok(ref txn_types) if txn_types.is_empty() => {
+            error!("GET_FEES needs at least one transaction type to filter on");
+            trace!("api::build_get_txn_fees_for_types_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+            return ErrorCode::CommonInvalidStructure as i32;
+        },
+        Ok(txn_types) => txn_types,
+        Err(ec) => {
+            trace!("api::build_get_txn_fees_for_types_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    debug!("api::build_get_txn_fees_for_types_handler >> submitter_did: {:?}, txn_types: {:?}", did, txn_types);
+
+    let get_txn_request = GetFeesRequest::for_types(txn_types).as_request(did);
+    info!("Built GET_TXN_FEES request: {:?}", get_txn_request);
+
+    let request_json = match get_txn_request.serialize_to_string() {
+        Ok(json) => json,
+        Err(_) => {
+            trace!("api::build_get_txn_fees_for_types_handler << result: {:?}", ErrorCode::CommonInvalidState);
+            return ErrorCode::CommonInvalidState as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("request", request_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::build_get_txn_fees_for_types_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Start tracking a payment request in the wallet.
+
+    The state of the payment survives a crash and can be read back with
+    `get_pending_txn_handler`.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet to store the state in
+    request_json: the payment request
+    state: "built" or "signed"
+    cb: called with the id of the pending transaction
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid
+*/
+#[no_mangle]
+pub extern "C" fn track_pending_txn_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    request_json: *const c_char,
+    state: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::track_pending_txn_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let txn_id = str_from_char_ptr(state)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(PendingState::from_name)
+        .and_then(|state| {
+            let request = str_from_char_ptr(request_json).ok_or(ErrorCode::CommonInvalidStructure)?;
+            services().pending_txns().track(wallet_handle, request, state, now)
+        });
+
+    let txn_id = match txn_id {
+        Ok(txn_id) => txn_id,
+        Err(ec) => {
+            trace!("api::track_pending_txn_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("pending_txn_id", txn_id));
+
+    let res = ErrorCode::Success;
+    trace!("api::track_pending_txn_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Move a pending transaction to its next state.
+
+    The allowed transitions are built → signed → submitted → confirmed or
+    rejected. A submitted transaction can be submitted again and a rejected
+    one signed again.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the pending transaction
+    txn_id: id returned by `track_pending_txn_handler`
+    state: the new state
+    request_json: optional, replaces the stored request, e.g. with the signed one
+    reason: optional, why the ledger rejected the request
+    cb: called with the pending transaction json:
+    ```text
+    {
+        "state": "built" | "signed" | "submitted" | "confirmed" | "rejected",
+        "request": <str>,
+        "updated": <int>, // seconds since the unix epoch
+        "reason": <str>, // only for rejected transactions
+        "requestHash": <str> // see `hash_request_handler`, only when the request is a json object
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid
+    CommonInvalidState when the transition isn't allowed
+    WalletItemNotFound when there is no such pending transaction
+*/
+#[no_mangle]
+pub extern "C" fn advance_pending_txn_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    txn_id: *const c_char,
+    state: *const c_char,
+    request_json: *const c_char,
+    reason: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::advance_pending_txn_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let txn_id = match str_from_char_ptr(txn_id) {
+        Some(s) => s,
+        None => {
+            error!("Failed to convert txn_id pointer to string");
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let request = str_from_char_ptr(request_json).map(String::from);
+    let reason = str_from_char_ptr(reason).map(String::from);
+
+    let txn_json = str_from_char_ptr(state)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(PendingState::from_name)
+        .and_then(|state| services().pending_txns().advance(wallet_handle, txn_id, state, request, reason, now))
+        .and_then(|txn| txn.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+    let txn_json = match txn_json {
+        Ok(json) => json,
+        Err(ec) => {
+            trace!("api::advance_pending_txn_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("pending_txn", txn_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::advance_pending_txn_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Get the state of a pending transaction.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the pending transaction
+    txn_id: id returned by `track_pending_txn_handler`
+    cb: called with the pending transaction json, see `advance_pending_txn_handler`
+
+    # Returns
+    CommonInvalidStructure when txn_id is invalid
+    WalletItemNotFound when there is no such pending transaction
+*/
+#[no_mangle]
+pub extern "C" fn get_pending_txn_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    txn_id: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::get_pending_txn_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let txn_json = str_from_char_ptr(txn_id)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|txn_id| services().pending_txns().get(wallet_handle, txn_id))
+        .and_then(|txn| txn.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+    let txn_json = match txn_json {
+        Ok(json) => json,
+        Err(ec) => {
+            trace!("api::get_pending_txn_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("pending_txn", txn_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::get_pending_txn_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Submit a signed pending transaction to the ledger and record the outcome.
+
+    The transaction is marked submitted before it is sent, then confirmed on
+    a REPLY or rejected on a REQNACK or REJECT. When the ledger can't be
+    reached it stays submitted and can be submitted again. A transaction
+    framed with another protocol version than the pool speaks isn't sent.
+
+    The inputs of a submitted transaction are left out of the UTXOs parsed
+    by `parse_get_utxo_response_handler`, until it is rejected.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the pending transaction
+    pool_handle: pool to submit the request to
+    txn_id: id returned by `track_pending_txn_handler`
+    cb: called with the reply of the ledger once it replied
+
+    # Returns
+    CommonInvalidStructure when txn_id is invalid
+    CommonInvalidState when the transaction isn't signed
+    WalletItemNotFound when there is no such pending transaction
+    PoolIncompatibleProtocolVersion when the transaction isn't framed with
+    the protocol version set for the pool with `sovtoken_set_pool_protocol_version`
+
+    cb is called with CommonInvalidStructure when the reply is unknown, and
+    with the errors of submitting the request, e.g. PoolLedgerTimeout.
+*/
+#[no_mangle]
+pub extern "C" fn submit_pending_txn_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    pool_handle: i32,
+    txn_id: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::submit_pending_txn_handler called >> wallet_handle {}, pool_handle: {}", wallet_handle, pool_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let submitted = str_from_char_ptr(txn_id)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|txn_id| {
+            let txn = services().pending_txns().get(wallet_handle, txn_id)?;
+            state().pools.lock().check_request(pool_handle, &txn.request)?;
+
+            let txn = services().pending_txns().submitting(wallet_handle, txn_id, now)?;
+            if let Err(ec) = optimistic_updates::record(Some(pool_handle), &txn.request) {
+                warn!("Failed to record the update of pending transaction {}: {:?}", txn_id, ec);
+            }
+
+            let txn_id = txn_id.to_string();
+            let ec = services().ledger.indy_submit_request_async(pool_handle, &txn.request, move |reply| {
+                // the pending transaction is recorded off the thread of libindy
+                let txn_id = txn_id.clone();
+                context::spawn(move || {
+                    let recorded = reply.and_then(|reply| {
+                        let txn = services().pending_txns().record_reply(wallet_handle, &txn_id, now, &reply)?;
+                        if txn.state == PendingState::Rejected {
+                            if let Err(ec) = optimistic_updates::rollback(&txn.request) {
+                                warn!("Failed to roll back a rejected pending transaction: {:?}", ec);
+                            }
+                        }
+                        Ok(reply)
+                    });
+
+                    match recorded {
+                        Ok(reply) => dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("reply", reply)),
+                        Err(ec) => dispatcher::dispatch(cb, command_handle, ec as i32, String::new()),
+                    }
+                });
+            });
+            match ec {
+                ErrorCode::Success => Ok(()),
+                ec => Err(ec),
+            }
+        });
+
+    if let Err(ec) = submitted {
+        trace!("api::submit_pending_txn_handler << result: {:?}", ec);
+        return ec as i32;
+    }
+
+    let res = ErrorCode::Success;
+    trace!("api::submit_pending_txn_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Submit a signed pending transaction, as `submit_pending_txn_handler`, with options.
+
+    When simulating, the transaction is checked as it would be before it is
+    sent: it exists, is signed and framed with the protocol version of the
+    pool. Nothing is recorded nor sent.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the pending transaction
+    pool_handle: pool to submit the request to
+    txn_id: id returned by `track_pending_txn_handler`
+    options_json: the options, as for `build_batch_payment_req_with_options_handler`,
+    only "simulate" applies
+    cb: called with the reply of the ledger, or when simulating with the
+    pending transaction as `get_pending_txn_handler` returns it
+
+    # Returns
+    the errors of `submit_pending_txn_handler`
+*/
+#[no_mangle]
+pub extern "C" fn submit_pending_txn_with_options_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    pool_handle: i32,
+    txn_id: *const c_char,
+    options_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::submit_pending_txn_with_options_handler called >> wallet_handle {}, pool_handle: {}", wallet_handle, pool_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    match build_options(options_json) {
+        Ok(ref options) if !options.simulate => return submit_pending_txn_handler(command_handle, wallet_handle, pool_handle, txn_id, Some(cb)),
+        Ok(_) => (),
+        Err(ec) => {
+            trace!("api::submit_pending_txn_with_options_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    }
+
+    let txn_json = str_from_char_ptr(txn_id)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|txn_id| services().pending_txns().get(wallet_handle, txn_id))
+        .and_then(|txn| {
+            state().pools.lock().check_request(pool_handle, &txn.request)?;
+            if !txn.state.can_advance_to(PendingState::Submitted) {
+                error!("A {:?} pending transaction can't be submitted", txn.state);
+                return Err(ErrorCode::CommonInvalidState);
+            }
+            txn.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState))
+        });
+
+    let txn_json = match txn_json {
+        Ok(json) => json,
+        Err(ec) => {
+            trace!("api::submit_pending_txn_with_options_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("pending_txn", txn_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::submit_pending_txn_with_options_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Reconcile the wallet state of a payment address with the ledger after a crash.
+
+    The pending transactions and UTXO reservations are replayed against the
+    UTXOs the ledger holds now. Submitted transactions whose inputs are spent
+    are marked confirmed, reservations of spent UTXOs are released and
+    anything else that disagrees with the ledger is reported.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the pending transactions and reservations
+    pool_handle: pool to fetch the UTXOs from
+    address: the payment address to reconcile
+    cb: called with the report:
+    ```text
+    {
+        "address": <str>,
+        "utxoCount": <int>,
+        "confirmed": [<str: pending transaction id>, ...],
+        "released": [<str: utxo source>, ...],
+        "discrepancies": [
+            {
+                "txnId": <str>,
+                "state": <str>,
+                "kind": "notOnLedger" | "partiallySpent" | "confirmedUnspent" | "spentElsewhere",
+                "spentInputs": <int>,
+                "unspentInputs": <int>
+            },
+            ...
+        ]
+    }
+    ```
+
+    The report is built from the GET_UTXO reply, cb is called with the errors
+    of fetching the UTXOs or of updating the wallet.
+
+    # Returns
+    CommonInvalidStructure when the address is invalid
+    or the error of submitting the GET_UTXO request
+*/
+#[no_mangle]
+pub extern "C" fn reconcile_utxos_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    pool_handle: i32,
+    address: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::reconcile_utxos_handler called >> wallet_handle {}, pool_handle: {}", wallet_handle, pool_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let result = str_from_char_ptr(address)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|address| address::validate_address(address).map(|_| address))
+        .and_then(|address| services().reconciler().reconcile(wallet_handle, pool_handle, address, now, move |report| {
+            let report = report
+                .and_then(|report| report.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+            match report {
+                Ok(report) => dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("reconcile_report", report)),
+                Err(ec) => dispatcher::dispatch(cb, command_handle, ec as i32, String::new()),
+            }
+        }));
+
+    let res = match result {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec,
+    };
+    trace!("api::reconcile_utxos_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Refresh the UTXOs of a payment address and record the highest seqNo seen for it.
+
+    Once the ledger accepts a starting seqNo in a GET_UTXO request, only the
+    UTXOs after the recorded seqNo are requested. Until then, or when nothing
+    was recorded for the address, every UTXO is requested.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet the seqNos are recorded in
+    pool_handle: pool to fetch the UTXOs from
+    address: the payment address to refresh
+    cb: called with the UTXOs, or with the errors of fetching them or of
+    recording their seqNo:
+    ```text
+    {
+        "address": <str>,
+        "fromSeqNo": <int>,     // null for a full refresh
+        "lastSeqNo": <int>,     // null when the address never had a UTXO
+        "utxos": [
+            {
+                "paymentAddress": <str>,
+                "source": <str>,
+                "amount": <int>,
+                "extra": <str>
+            },
+            ...
+        ]
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when the address is invalid
+    or the error of reading the wallet or of submitting the GET_UTXO request
+*/
+#[no_mangle]
+pub extern "C" fn refresh_utxos_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    pool_handle: i32,
+    address: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::refresh_utxos_handler called >> wallet_handle {}, pool_handle: {}", wallet_handle, pool_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let result = str_from_char_ptr(address)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|address| address::validate_address(address).map(|_| address))
+        .and_then(|address| services().utxo_refresher().refresh(wallet_handle, pool_handle, address, move |refreshed| {
+            let refreshed = refreshed
+                .and_then(|refreshed| refreshed.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+            match refreshed {
+                Ok(refreshed) => dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("refreshed_utxos", refreshed)),
+                Err(ec) => dispatcher::dispatch(cb, command_handle, ec as i32, String::new()),
+            }
+        }));
+
+    let res = match result {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec,
+    };
+    trace!("api::refresh_utxos_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Check whether a txo is still unspent, to refresh a stale cache of UTXOs.
+
+    The UTXOs of the address of the txo are fetched from the ledger. A spent
+    txo is matched with the pending transaction of the wallet spending it,
+    with the seqNo the ledger gave that transaction when it was confirmed.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the pending transactions
+    pool_handle: pool to fetch the UTXOs from
+    txo: the txo:sov: id to check
+    cb: called with the status:
+    ```text
+    {
+        "txo": <str>,
+        "address": <str>,
+        "seqNo": <int>,
+        "spent": <bool>,
+        "amount": <int>,        // null when spent
+        "spentBy": {            // null when unspent or not spent by this wallet
+            "txnId": <str>,
+            "state": <str>,
+            "seqNo": <int>      // null when not known
+        }
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when the txo is invalid
+    or the error of reading the wallet. cb is called with the error of
+    fetching the UTXOs.
+*/
+#[no_mangle]
+pub extern "C" fn is_txo_spent_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    pool_handle: i32,
+    txo: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::is_txo_spent_handler called >> wallet_handle {}, pool_handle: {}", wallet_handle, pool_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let checked = str_from_char_ptr(txo)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|txo| services().txo_status().check(wallet_handle, pool_handle, txo, move |status| {
+            match status.and_then(|status| status.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState))) {
+                Ok(status) => dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("txo_status", status)),
+                Err(ec) => dispatcher::dispatch(cb, command_handle, ec as i32, String::new()),
+            };
+        }));
+
+    if let Err(ec) = checked {
+        trace!("api::is_txo_spent_handler << result: {:?}", ec);
+        return ec as i32;
+    }
+
+    let res = ErrorCode::Success;
+    trace!("api::is_txo_spent_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Roll back the optimistic updates of a rejected request.
+
+    The UTXOs the request spends are listed again by
+    `parse_get_utxo_response_handler` and the outputs it was expected to
+    create are dropped. `submit_pending_txn_handler` rolls back the requests
+    rejected by their reply itself, this is for rejections it doesn't see,
+    e.g. when the ledger reorganizes or the request was submitted elsewhere.
+
+    # Params
+    command_handle: standard command handle
+    request_json: the request as it was submitted, with its fees: its hash and
+    the inputs it spends are the key of the updates, so the update of the same
+    request paid with other UTXOs is kept
+    cb: called with what was rolled back:
+    ```text
+    {
+        "requestHash": <str>,
+        "rolledBack": <bool>,   // false when there was no update of the request
+        "restored": [<str: txo>, ...]
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when the request isn't a json object
+*/
+#[no_mangle]
+pub extern "C" fn rollback_transaction_handler(
+    command_handle: i32,
+    request_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::rollback_transaction_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let rollback = str_from_char_ptr(request_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(optimistic_updates::rollback)
+        .and_then(|rollback| rollback.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+    let rollback = match rollback {
+        Ok(rollback) => rollback,
+        Err(ec) => {
+            trace!("api::rollback_transaction_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("rollback", rollback));
+
+    let res = ErrorCode::Success;
+    trace!("api::rollback_transaction_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Prove control of a payment address by signing a challenge with its key.
+
+    The signed message is `sovtoken-address-control:<address>:<challenge>`,
+    so the signature can't be used for anything but the proof.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the key of the address
+    address: the payment address
+    challenge: the challenge of the service, 1 to 1024 bytes
+    cb: called with the base58 signature
+
+    # Returns
+    CommonInvalidStructure when the address or challenge are invalid
+    PaymentIncompatibleMethodsError when the address isn't a sovrin address
+*/
+#[no_mangle]
+pub extern "C" fn prove_address_control_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    address: *const c_char,
+    challenge: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::prove_address_control_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let (address, challenge) = match (str_from_char_ptr(address), str_from_char_ptr(challenge)) {
+        (Some(address), Some(challenge)) => (address, challenge),
+        _ => {
+            trace!("api::prove_address_control_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let result = address_proof::prove_address_control(&services().crypto, wallet_handle, address, challenge, Box::new(move |signature| {
+        match signature {
+            Ok(signature) => dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("address_proof", signature)),
+            Err(ec) => dispatcher::dispatch(cb, command_handle, ec as i32, String::new()),
+        };
+    }));
+
+    let ec = match result {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec
+    };
+    trace!("api::prove_address_control_handler << result: {:?}", ec);
+    return ec as i32;
+}
+
+/**
+    Verify a proof of control of a payment address made by `prove_address_control_handler`.
+
+    # Params
+    command_handle: standard command handle
+    address: the payment address
+    challenge: the challenge sent to the owner of the address
+    signature: the base58 signature returned by `prove_address_control_handler`
+    cb: called with `true` when the signature proves control of the address, `false` otherwise
+
+    # Returns
+    CommonInvalidStructure when the address, challenge or signature are malformed
+    PaymentIncompatibleMethodsError when the address isn't a sovrin address
+*/
+#[no_mangle]
+pub extern "C" fn verify_address_control_handler(
+    command_handle: i32,
+    address: *const c_char,
+    challenge: *const c_char,
+    signature: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::verify_address_control_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let verified = match (str_from_char_ptr(address), str_from_char_ptr(challenge), str_from_char_ptr(signature)) {
+        (Some(address), Some(challenge), Some(signature)) => address_proof::verify_address_control(address, challenge, signature),
+        _ => Err(ErrorCode::CommonInvalidStructure),
+    };
+
+    let verified = match verified {
+        Ok(verified) => verified,
+        Err(ec) => {
+            trace!("api::verify_address_control_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("verified", json!(verified).to_string()));
+
+    let res = ErrorCode::Success;
+    trace!("api::verify_address_control_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Export a signed proof of a payment, for a third party to verify offline
+    with `verify_payment_proof_handler`.
+
+    The proof bundles the receipt with its inclusion proof, the request, the
+    reply of the ledger and the verkeys of the addresses involved, and is
+    signed with the key of `signer`, see `logic::payment_proof`.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the key of the signer
+    receipt_json: a receipt parsed from the reply with `verbose_receipts`
+    request_json: the request as submitted
+    reply_json: the reply of the ledger to the request
+    signer: the payment address signing the proof
+    cb: called with the proof json
+
+    # Returns
+    CommonInvalidStructure when a json is invalid, the receipt has no
+    inclusion proof or isn't an output of the request and its reply
+    PaymentIncompatibleMethodsError when the signer isn't a sovrin address
+*/
+#[no_mangle]
+pub extern "C" fn export_payment_proof_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    receipt_json: *const c_char,
+    request_json: *const c_char,
+    reply_json: *const c_char,
+    signer: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::export_payment_proof_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let args = (str_from_char_ptr(receipt_json), str_from_char_ptr(request_json), str_from_char_ptr(reply_json), str_from_char_ptr(signer));
+    let (receipt_json, request_json, reply_json, signer) = match args {
+        (Some(receipt_json), Some(request_json), Some(reply_json), Some(signer)) => (receipt_json, request_json, reply_json, signer),
+        _ => {
+            trace!("api::export_payment_proof_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let result = payment_proof::export_payment_proof(&services().crypto, wallet_handle, receipt_json, request_json, reply_json, signer, Box::new(move |proof| {
+        match proof {
+            Ok(proof) => dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("payment_proof", proof)),
+            Err(ec) => dispatcher::dispatch(cb, command_handle, ec as i32, String::new()),
+        };
+    }));
+
+    let ec = match result {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec
+    };
+    trace!("api::export_payment_proof_handler << result: {:?}", ec);
+    return ec as i32;
+}
+
+/**
+    Verify a proof of payment made by `export_payment_proof_handler`, without a wallet or ledger.
+
+    # Params
+    command_handle: standard command handle
+    proof_json: the proof json
+    root_hash: base58 root of the ledger known from another source, e.g. an
+    auditor. When null, the receipt is checked against the root in the
+    proof, which only shows the proof is consistent.
+    cb: called with the verification:
+    ```text
+    {
+        "valid": <bool>,        // all of the below
+        "signed": <bool>,       // the signer signed the proof
+        "consistent": <bool>,   // the reply is the one of the request, with the output of the receipt
+        "inputsSigned": <bool>, // the inputs of the request are signed by the keys of their addresses
+        "included": <bool>,     // the audit path of the receipt leads to rootHash
+        "rootHash": <str>       // the root the receipt was checked against
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when the proof is malformed or of an unknown version
+*/
+#[no_mangle]
+pub extern "C" fn verify_payment_proof_handler(
+    command_handle: i32,
+    proof_json: *const c_char,
+    root_hash: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::verify_payment_proof_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let verification = str_from_char_ptr(proof_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|proof_json| payment_proof::verify_payment_proof(proof_json, str_from_char_ptr(root_hash)))
+        .and_then(|verification| verification.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+    let verification = match verification {
+        Ok(verification) => verification,
+        Err(ec) => {
+            trace!("api::verify_payment_proof_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("payment_proof_verification", verification));
+
+    let res = ErrorCode::Success;
+    trace!("api::verify_payment_proof_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Split the seed of a payment address into Shamir shares, to escrow it
+    without a single point of compromise.
+
+    Any `threshold` of the shares give the seed back with
+    `recover_seed_handler`, fewer tell nothing about it. The shares are
+    base58check strings, meant to be handed to different custodians.
+
+    The shares are secret: they are called back on the calling thread
+    whatever the `callback_dispatch` of the config, never kept as a dead
+    letter, and zeroed once the callback returned. Copy them in the callback.
+
+    # Params
+    command_handle: standard command handle
+    seed: the seed, as accepted by `create_payment_address_handler`
+    threshold: the number of shares needed to recover the seed, at least 2
+    share_count: the number of shares, from `threshold` to 255
+    cb: called with the shares: `[<str>, ...]`
+
+    # Returns
+    CommonInvalidStructure when the seed is invalid or the threshold is out of range
+*/
+#[no_mangle]
+pub extern "C" fn split_seed_handler(
+    command_handle: i32,
+    seed: *const c_char,
+    threshold: u32,
+    share_count: u32,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::split_seed_handler called >> threshold: {}, share_count: {}", threshold, share_count);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    // the seed and shares are zeroed once called back, and passed by dispatch_secret
+    let mut seed = match string_from_char_ptr(seed) {
+        Some(seed) => seed,
+        None => return ErrorCode::CommonInvalidStructure as i32,
+    };
+    let config = PaymentAddressConfig { seed, strict_seed: false, reuse_existing: false, seed_encoding: None };
+    let checked = match config.seed_bytes() {
+        Ok(Some(mut bytes)) => {
+            secret_sharing::zeroize(&mut bytes);
+            Ok(())
+        },
+        Ok(None) => Err(ErrorCode::from(SovtokenError::from(SeedError::NotEncoded))),
+        Err(error) => Err(ErrorCode::from(SovtokenError::from(error))),
+    };
+    seed = config.seed;
+
+    let shares = checked
+        .and_then(|_| secret_sharing::split(seed.as_bytes(), threshold, share_count).map_err(|error| ErrorCode::from(SovtokenError::from(error))))
+        .and_then(|mut shares| {
+            let json = shares.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState));
+            for share in shares.iter_mut() {
+                secret_sharing::zeroize_string(share);
+            }
+            json
+        });
+    secret_sharing::zeroize_string(&mut seed);
+
+    let shares = match shares {
+        Ok(shares) => shares,
+        Err(ec) => {
+            trace!("api::split_seed_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch_secret(cb, command_handle, "seed_shares", shares, true);
+
+    let res = ErrorCode::Success;
+    trace!("api::split_seed_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Recover a seed split by `split_seed_handler`.
+
+    Shares given twice are counted once, and the shares beyond the
+    threshold are ignored. The seed is called back as the shares of
+    `split_seed_handler` are, and zeroed once the callback returned.
+
+    # Params
+    command_handle: standard command handle
+    shares_json: at least `threshold` of the shares: `[<str>, ...]`
+    cb: called with the seed
+
+    # Returns
+    CommonInvalidStructure when a share is malformed, the shares are from
+    different splits or too few, or the recovered seed doesn't match its checksum
+*/
+#[no_mangle]
+pub extern "C" fn recover_seed_handler(
+    command_handle: i32,
+    shares_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::recover_seed_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    // the shares aren't logged, unlike with deserialize_from_char_ptr, and are zeroed once combined
+    let seed = str_from_char_ptr(shares_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| Vec::<String>::from_json_error_code(json))
+        .and_then(|mut shares| {
+            let seed = secret_sharing::combine(&shares).map_err(|error| ErrorCode::from(SovtokenError::from(error)));
+            for share in shares.iter_mut() {
+                secret_sharing::zeroize_string(share);
+            }
+            seed
+        })
+        .and_then(|seed| String::from_utf8(seed).or_else(|error| {
+            secret_sharing::zeroize(&mut error.into_bytes());
+            Err(ErrorCode::from(SovtokenError::from(ShareError::Corrupted)))
+        }));
+
+    let seed = match seed {
+        Ok(seed) => seed,
+        Err(ec) => {
+            trace!("api::recover_seed_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch_secret(cb, command_handle, "seed", seed, false);
+
+    let res = ErrorCode::Success;
+    trace!("api::recover_seed_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Convert a request or response to another wire format.
+
+    Agents on slow links can relay requests as base64 encoded MessagePack
+    and convert them back to json before submitting them. The parse handlers
+    take either format.
+
+    # Params
+    command_handle: standard command handle
+    request_json: the request, as json or base64 encoded MessagePack
+    output_format: optional, `json` (default) or `msgpack`
+    cb: called with the request in the output format
+
+    # Returns
+    CommonInvalidStructure when the request or format are invalid
+*/
+#[no_mangle]
+pub extern "C" fn serialize_request_handler(
+    command_handle: i32,
+    request_json: *const c_char,
+    output_format: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::serialize_request_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let request = str_from_char_ptr(request_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(wire_format::to_json)
+        .and_then(|json| {
+            WireFormat::from_name(str_from_char_ptr(output_format))
+                .and_then(|format| format.encode(json))
+        });
+
+    let request = match request {
+        Ok(request) => request,
+        Err(ec) => {
+            trace!("api::serialize_request_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("request", request));
+
+    let res = ErrorCode::Success;
+    trace!("api::serialize_request_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Get the canonical hash of a request, the id the library knows it by.
+
+    The hash is the sha256, as hex, of the canonical json of the request: the
+    keys of every object sorted, no whitespace, and the top level `signature`,
+    `signatures` and `fees` left out. So external systems compute the same id
+    from the request alone. It doesn't change when the request is reformatted,
+    signed by its DID or given fees, and is the `requestHash` of the pending
+    transactions, the audit log, the rollbacks and the correlations.
+
+    # Params
+    command_handle: standard command handle
+    request_json: the request
+    cb: called with the hash, 64 hex characters
+
+    # Returns
+    CommonInvalidStructure when the request isn't a json object
+*/
+#[no_mangle]
+pub extern "C" fn hash_request_handler(
+    command_handle: i32,
+    request_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::hash_request_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let hash = str_from_char_ptr(request_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(request_hash::request_hash);
+
+    let hash = match hash {
+        Ok(hash) => hash,
+        Err(ec) => {
+            trace!("api::hash_request_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("request_hash", hash));
+
+    let res = ErrorCode::Success;
+    trace!("api::hash_request_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Build a payment URI to show as a QR code.
+
+    # Params
+    command_handle: standard command handle
+    address: the payment address to pay to
+    amount: the amount asked for, 0 to let the payer choose
+    memo: optional text shown to the payer, up to 256 bytes
+    cb: called with the URI:
+    ```text
+    sov:pay?address=pay:sov:<address>&amount=<int>&memo=<percent encoded text>
+    ```
+
+    # Returns
+    CommonInvalidStructure when the address or memo are invalid
+    PaymentIncompatibleMethodsError when the address isn't a sovrin address
+*/
+#[no_mangle]
+pub extern "C" fn build_payment_uri_handler(
+    command_handle: i32,
+    address: *const c_char,
+    amount: u64,
+    memo: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::build_payment_uri_handler called >> amount: {}", amount);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let amount = if amount == 0 { None } else { Some(amount) };
+    let uri = str_from_char_ptr(address)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|address| PaymentUri::new(address, amount, str_from_char_ptr(memo)));
+
+    let uri = match uri {
+        Ok(uri) => uri,
+        Err(ec) => {
+            trace!("api::build_payment_uri_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("payment_uri", uri.to_uri()));
+
+    let res = ErrorCode::Success;
+    trace!("api::build_payment_uri_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Read a payment URI scanned from a QR code.
+
+    # Params
+    command_handle: standard command handle
+    uri: the payment URI
+    cb: called with the payment asked for:
+    ```text
+    {
+        "address": <str>,
+        "amount": <int>, // missing when the payer chooses
+        "memo": <str>    // missing when there is none
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when the URI is invalid
+    PaymentIncompatibleMethodsError when the address isn't a sovrin address
+*/
+#[no_mangle]
+pub extern "C" fn parse_payment_uri_handler(
+    command_handle: i32,
+    uri: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::parse_payment_uri_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let payment = str_from_char_ptr(uri)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(PaymentUri::parse)
+        .and_then(|payment| payment.to_json().or(Err(ErrorCode::CommonInvalidState)));
+
+    let payment = match payment {
+        Ok(payment) => payment,
+        Err(ec) => {
+            trace!("api::parse_payment_uri_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("payment", payment));
+
+    let res = ErrorCode::Success;
+    trace!("api::parse_payment_uri_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Suggest the nearest valid `txo:sov:` string of a txo mangled when it was copied.
+
+    Parsing a txo never repairs it, a repair tries up to about 9000
+    candidates and is only made when asked for, see `logic::address::repair_txo`.
+    The suggestion should be confirmed by the user.
+
+    # Params
+    command_handle: standard command handle
+    txo: the mangled txo string, up to 160 characters
+    cb: called with the repaired txo string
+
+    # Returns
+    CommonInvalidStructure when the txo can't be repaired
+*/
+#[no_mangle]
+pub extern "C" fn repair_txo_handler(
+    command_handle: i32,
+    txo: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::repair_txo_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let repaired = str_from_char_ptr(txo)
+        .and_then(address::repair_txo)
+        .ok_or(ErrorCode::CommonInvalidStructure);
+
+    let repaired = match repaired {
+        Ok(repaired) => repaired,
+        Err(ec) => {
+            trace!("api::repair_txo_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("txo", repaired));
+
+    let res = ErrorCode::Success;
+    trace!("api::repair_txo_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Set the label of a payment address of the wallet.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the payment address
+    payment_address: the payment address, e.g. `pay:sov:...`
+    label: the label, an empty label removes it
+    cb: called with the record of the address:
+    ```text
+    {
+        "address": <str>,
+        "label": <str>, // absent without a label
+        "created": <int>, // when libsovtoken first saw the address, seconds since the unix epoch
+        "balance": <int> // the balance last fetched by `get_aggregate_balance_handler`, absent before
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid
+    WalletItemNotFound when the address isn't one of the wallet
+*/
+#[no_mangle]
+pub extern "C" fn set_payment_address_label_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    payment_address: *const c_char,
+    label: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::set_payment_address_label_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let label = str_from_char_ptr(label).ok_or(ErrorCode::CommonInvalidStructure);
+
+    let record = str_from_char_ptr(payment_address)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|address| label.and_then(|label| services().address_book().set_label(wallet_handle, address, label, now)))
+        .and_then(|record| record.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+    let record = match record {
+        Ok(record) => record,
+        Err(ec) => {
+            trace!("api::set_payment_address_label_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("address_record", record));
+
+    let res = ErrorCode::Success;
+    trace!("api::set_payment_address_label_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Open a search over the payment addresses of the wallet.
+
+    Addresses libsovtoken didn't see before are recorded as first seen now.
+    The matching records are fetched with `fetch_payment_address_search_handler`,
+    the search is closed with `close_payment_address_search_handler`.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the payment addresses
+    filter_json: the filter, every field is optional:
+    ```text
+    {
+        "labelPrefix": <str>, // only addresses with a label starting with the prefix
+        "createdAfter": <int>, // only addresses first seen after the time, seconds since the unix epoch
+        "hasBalance": <bool> // only addresses with, or without, a positive cached balance
+    }
+    ```
+    cb: called with the search:
+    ```text
+    {
+        "searchHandle": <int>,
+        "totalCount": <int>
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid
+    or the error of listing the addresses or reading the wallet
+*/
+#[no_mangle]
+pub extern "C" fn search_payment_addresses_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    filter_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::search_payment_addresses_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let search = str_from_char_ptr(filter_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| AddressFilter::from_json(json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure)))
+        .and_then(|filter| services().address_book().search(wallet_handle, &filter, now))
+        .map(|records| {
+            let total_count = records.len();
+            json!({"searchHandle": address_book::open_search(records), "totalCount": total_count}).to_string()
+        });
+
+    let search = match search {
+        Ok(search) => search,
+        Err(ec) => {
+            trace!("api::search_payment_addresses_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("search_handle", search));
+
+    let res = ErrorCode::Success;
+    trace!("api::search_payment_addresses_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Fetch the next records of a payment address search.
+
+    # Params
+    command_handle: standard command handle
+    search_handle: the search, as returned by `search_payment_addresses_handler`
+    count: the most records to fetch
+    cb: called with a json array of address records, in the format of
+    `set_payment_address_label_handler`. The array is empty when all records were fetched.
+
+    # Returns
+    CommonInvalidStructure when the search is unknown or closed
+*/
+#[no_mangle]
+pub extern "C" fn fetch_payment_address_search_handler(
+    command_handle: i32,
+    search_handle: i32,
+    count: u32,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::fetch_payment_address_search_handler called >> search_handle {}, count {}", search_handle, count);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let records = address_book::fetch_search(search_handle, count as usize)
+        .and_then(|records| records.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+    let records = match records {
+        Ok(records) => records,
+        Err(ec) => {
+            trace!("api::fetch_payment_address_search_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("address_records", records));
+
+    let res = ErrorCode::Success;
+    trace!("api::fetch_payment_address_search_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Close a payment address search, dropping the records not fetched yet.
+
+    # Params
+    search_handle: the search, as returned by `search_payment_addresses_handler`
+
+    # Returns
+    CommonInvalidStructure when the search is unknown or already closed
+*/
+#[no_mangle]
+pub extern "C" fn close_payment_address_search_handler(search_handle: i32) -> i32 {
+    trace!("api::close_payment_address_search_handler called >> search_handle {}", search_handle);
+    messages::clear_last_error();
+
+    let res = match address_book::close_search(search_handle) {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec,
+    };
+    trace!("api::close_payment_address_search_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Tell the library a wallet was closed.
+
+    libindy doesn't tell payment plugins when a wallet closes. Once told,
+    the handlers fail at once with `WalletInvalidHandle` when given the
+    handle, instead of failing in libindy after their callbacks are set up.
+    Call it right after `indy_close_wallet`.
+
+    # Params
+    wallet_handle: the handle of the closed wallet
+
+    # Returns
+    WalletInvalidHandle when the handle can't be a wallet handle
+*/
+#[no_mangle]
+pub extern "C" fn wallet_closed_handler(wallet_handle: i32) -> i32 {
+    trace!("api::wallet_closed_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+
+    let res = if wallet_handle <= 0 {
+        ErrorCode::WalletInvalidHandle
+    } else {
+        wallet_handles::mark_closed(wallet_handle);
+        rate_limit::forget_wallet(wallet_handle);
+        storage::forget_cache_wallet(wallet_handle);
+        ErrorCode::Success
+    };
+    trace!("api::wallet_closed_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Persist the UTXO and fee caches in a wallet.
+
+    The GET_UTXO and GET_FEES replies are parsed without a wallet handle.
+    With the `wallet` storage backend the caches are kept in memory only
+    until a wallet is set, the other backends keep them as records of no
+    wallet. The wallet is forgotten once `wallet_closed_handler` is told it
+    closed.
+
+    # Params
+    wallet_handle: the wallet to keep the caches in
+
+    # Returns
+    WalletInvalidHandle when the handle can't be a wallet handle
+*/
+#[no_mangle]
+pub extern "C" fn set_cache_wallet_handler(wallet_handle: i32) -> i32 {
+    trace!("api::set_cache_wallet_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+
+    let res = if wallet_handle <= 0 {
+        ErrorCode::WalletInvalidHandle
+    } else {
+        storage::set_cache_wallet(Some(wallet_handle));
+        ErrorCode::Success
+    };
+    trace!("api::set_cache_wallet_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Export the public state of a wallet, for accounting and reporting systems.
+
+    The export has the payment addresses with their label and cached
+    balance, and the pending transactions with their inputs and outputs.
+    It holds no keys, seeds or signed requests.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet to export
+    cb: called with the export:
+    ```text
+    {
+        "ver": 1,
+        "exported": <int>,          // seconds since the unix epoch
+        "addresses": [<address record>, ...], // in the format of `set_payment_address_label_handler`
+        "cachedBalance": <int>,     // the sum of the cached balances
+        "pendingTxns": [
+            {
+                "txnId": <str>,
+                "state": <str>,
+                "updated": <int>,
+                "reason": <str>,    // only when rejected
+                "seqNo": <int>,     // only when confirmed by its reply
+                "inputs": [<str: txo>, ...],
+                "outputs": [{"address": <str>, "amount": <int>}, ...]
+            },
+            ...
+        ]
+    }
+    ```
+
+    # Returns
+    the error of listing the addresses or reading the wallet
+*/
+#[no_mangle]
+pub extern "C" fn export_public_state_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::export_public_state_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let export = services().public_export().export(wallet_handle, now)
+        .and_then(|export| export.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+    let export = match export {
+        Ok(export) => export,
+        Err(ec) => {
+            trace!("api::export_public_state_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("public_state", export));
+
+    let res = ErrorCode::Success;
+    trace!("api::export_public_state_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Get the payment address of a key already in the wallet, e.g. the verkey of a DID.
+
+    No key is created, the address is derived from the verkey as
+    `create_payment_address_handler` does for a new key. The wallet has to
+    hold the key, which is checked by signing with it. The address isn't
+    added to the payment addresses libindy lists for the wallet.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet holding the key
+    verkey: the full base58 verkey, an abbreviated verkey (`~...`) isn't accepted
+    cb: called with the payment address, `pay:sov:...`
+
+    # Returns
+    CommonInvalidStructure when the verkey is invalid.
+    The callback gets the error of the wallet, e.g. WalletItemNotFound, when the wallet doesn't hold the key
+*/
+#[no_mangle]
+pub extern "C" fn create_payment_address_from_key_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    verkey: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::create_payment_address_from_key_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let verkey = match str_from_char_ptr(verkey) {
+        Some(verkey) => verkey,
+        None => {
+            error!("Failed to convert verkey pointer to string");
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let result = services().payment_addresses().payment_address_from_key(wallet_handle, verkey, move |address| {
+        match address {
+            Ok(address) => dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("payment_address", address)),
+            Err(ec) => dispatcher::dispatch(cb, command_handle, ec as i32, String::new()),
+        }
+    });
+
+    let ec = match result {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec
+    };
+    trace!("api::create_payment_address_from_key_handler << result: {:?}", ec);
+    return ec as i32;
+}
+
+/**
+    Create several payment addresses in one call.
+
+    The addresses are created one after the other by libindy, without
+    blocking the caller, as `indy_create_payment_address` creates one: the
+    addresses are listed by `indy_list_payment_addresses`. When a config is
+    invalid or its address can't be created, the result tells which
+    addresses were created and which failed, instead of one error for the
+    batch.
+
+    The created keys aren't deleted when a later one fails, libindy can't
+    delete keys from a wallet.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: wallet to create the keys in
+    config_json: the configs, each as for `create_payment_address_handler`:
+    ```text
+    {
+        "configs": [{"seed": <str>, "strict_seed": <bool>}, ...],
+        "stopOnFailure": <bool> // optional, skip the configs after the first failure, default false
+    }
+    ```
+    cb: called with the result, by index in `configs`:
+    ```text
+    {
+        "created": [{"index": <int>, "address": <str>}, ...],
+        "failed": [{"index": <int>, "error": <int: error code>, "message": <str>}, ...],
+        "skipped": [<int>, ...]
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when the callback is missing or the config json is invalid
+*/
+#[no_mangle]
+pub extern "C" fn create_payment_addresses_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    config_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    span::with_command_handle(command_handle, || metrics::measure_wallet_handler("create_payment_addresses_handler", rate_limit::CREATE_ADDRESS, wallet_handle, || {
+        trace!("api::create_payment_addresses_handler called >> wallet_handle {}", wallet_handle);
+        messages::clear_last_error();
+        check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+        let batch = match str_from_char_ptr(config_json).map(create_address::BatchAddressConfig::from_json) {
+            Some(Ok(batch)) => batch,
+            _ => {
+                error!("Failed to parse the batch address config");
+                return ErrorCode::CommonInvalidStructure as i32;
+            }
+        };
+
+        let stop_on_failure = batch.stop_on_failure;
+        payments::create_payment_addresses_async(&services().payment, wallet_handle, batch.config_jsons(), stop_on_failure, move |result| {
+            match result.to_json() {
+                Ok(json) => dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("payment_addresses", json)),
+                Err(_) => dispatcher::dispatch(cb, command_handle, ErrorCode::CommonInvalidState as i32, String::new()),
+            }
+        });
+
+        let res = ErrorCode::Success;
+        trace!("api::create_payment_addresses_handler << result: {:?}", res);
+        return res as i32;
+    }))
+}
+
+/**
+    Tell the library which network a pool handle is connected to.
+
+    The state the library caches for a pool, e.g. the UTXOs fetched for
+    `get_aggregate_balance_handler`, is kept by network. Pools opened with
+    the same genesis transactions share it, and pools of different networks,
+    e.g. a staging and the main network, never mix it. A pool handle which
+    isn't registered is a network of its own.
+
+    # Params
+    pool_handle: the pool, as opened with libindy
+    genesis_txn_path: the genesis transactions file the pool was created with
+
+    # Returns
+    CommonInvalidStructure when the path is missing
+    CommonIOError when the file can't be read
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_register_pool(pool_handle: i32, genesis_txn_path: *const c_char) -> i32 {
+    trace!("api::sovtoken_register_pool called >> pool_handle: {}", pool_handle);
+    messages::clear_last_error();
+
+    let path = match str_from_char_ptr(genesis_txn_path) {
+        Some(path) => path,
+        None => {
+            let ec = ErrorCode::CommonInvalidStructure;
+            trace!("api::sovtoken_register_pool << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    let mut genesis_txns = String::new();
+    if let Err(error) = File::open(path).and_then(|mut file| file.read_to_string(&mut genesis_txns)) {
+        error!("Failed to read the genesis transactions {:?}: {}", path, error);
+        let ec = ErrorCode::CommonIOError;
+        trace!("api::sovtoken_register_pool << result: {:?}", ec);
+        return ec as i32;
+    }
+
+    let network_id = pools::genesis_hash(&genesis_txns);
+    info!("Pool {} is connected to the network {}", pool_handle, network_id);
+    state().pools.lock().register(pool_handle, &network_id);
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_register_pool << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Register the signer of the stateless mode, for keys kept outside of libindy.
+
+    With `stateless` in the library config, the inputs of payments and fees
+    and the proofs are signed by `signer` instead of the wallet, and the
+    wallet handles given to the handlers are ignored. See `logic::stateless`.
+
+    # Params
+    signer: called with the base58 verkey, the message and its length, and a
+    buffer of 64 bytes to write the Ed25519 signature to. Returns 0 when it
+    signed, any other value otherwise. null removes the signer.
+
+    # Returns
+    Success, a signer doesn't fail to register
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_register_signer(signer: Option<SignerCallback>) -> i32 {
+    trace!("api::sovtoken_register_signer called >> signer: {}", signer.is_some());
+    messages::clear_last_error();
+    stateless::register_signer(signer);
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_register_signer << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Drop the state cached for the network of a pool, e.g. after the network was reset.
+
+    The pools of other networks keep theirs, the pool stays registered.
+    The replies libindy hands to `parse_get_utxo_response_handler` come
+    without a pool, their cache is keyed by ledger state root and isn't
+    dropped.
+
+    # Params
+    pool_handle: the pool
+
+    # Returns
+    Success, whether or not anything was cached
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_clear_pool_state(pool_handle: i32) -> i32 {
+    trace!("api::sovtoken_clear_pool_state called >> pool_handle: {}", pool_handle);
+    messages::clear_last_error();
+
+    if state().pools.lock().clear(pool_handle) {
+        info!("Dropped the cached state of the network of pool {}", pool_handle);
+    }
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_clear_pool_state << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Tell the library which protocol version the network of a pool speaks.
+
+    The pools older than indy-node 1.4 only know protocol version 1, set
+    `protocol_version` to 1 in the library config to build requests for
+    them. `submit_pending_txn_handler` refuses to send a request framed
+    with another version than the one set here, which the pool would
+    reject or misread. Requests of any version are sent to a pool without
+    a protocol version.
+
+    # Params
+    pool_handle: the pool, registered with `sovtoken_register_pool` or not
+    protocol_version: 1 or 2
+
+    # Returns
+    CommonInvalidStructure when the protocol version isn't supported
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_set_pool_protocol_version(pool_handle: i32, protocol_version: u32) -> i32 {
+    trace!("api::sovtoken_set_pool_protocol_version called >> pool_handle: {}, protocol_version: {}", pool_handle, protocol_version);
+    messages::clear_last_error();
+
+    let res = match state().pools.lock().set_protocol_version(pool_handle, protocol_version) {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec,
+    };
+
+    trace!("api::sovtoken_set_pool_protocol_version << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Verify that the transaction of a receipt is on the ledger.
+
+    The receipt has to be parsed by `parse_payment_response_handler` with
+    `verbose_receipts` set in the library config, which keeps the audit path
+    of the transaction in the receipt.
+
+    # Params
+    command_handle: standard command handle
+    receipt_json: a receipt of the receipts_json of `parse_payment_response_handler`
+    root_hash: the base58 root hash of the ledger, of `proof.ledgerSize` transactions,
+               known from a source other than the reply
+    cb: called with `true` when the audit path of the receipt leads to root_hash, `false` otherwise
+
+    # Returns
+    CommonInvalidStructure when the receipt has no proof, or the receipt or root_hash are malformed
+*/
+#[no_mangle]
+pub extern "C" fn verify_receipt_inclusion_handler(
+    command_handle: i32,
+    receipt_json: *const c_char,
+    root_hash: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::verify_receipt_inclusion_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let receipt = match str_from_char_ptr(receipt_json).map(Receipt::from_json) {
+        Some(Ok(receipt)) => receipt,
+        _ => {
+            error!("Invalid receipt_json");
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let verified = match str_from_char_ptr(root_hash) {
+        Some(root_hash) => inclusion_proof::verify_inclusion(&receipt, root_hash),
+        None => Err(ErrorCode::CommonInvalidStructure),
+    };
+
+    let verified = match verified {
+        Ok(verified) => verified,
+        Err(ec) => {
+            trace!("api::verify_receipt_inclusion_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("verified", json!(verified).to_string()));
+
+    let res = ErrorCode::Success;
+    trace!("api::verify_receipt_inclusion_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Get the message of the last error reported on the calling thread.
+
+    The errors of the synchronous checks of a call, e.g. an invalid address
+    or too many inputs, are reported on the thread of the call. Errors
+    reported to a callback aren't kept. The other calls of the library,
+    but `sovtoken_free_json`, clear the error of the call before them.
+
+    # Params
+    command_handle: standard command handle
+    cb: called with the last error json, null when the last call reported no error with a message:
+    ```text
+    {
+        "code": <int>, // the error code the error was reported as
+        "id": <string>, // stable id of the message in the catalog, e.g. "SOV-0009"
+        "text": <string>, // the english text of the message
+        "args": [<string>] // the values filled in the placeholders of the text
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when the callback is missing
+    CommonInvalidState when the error can't be serialized
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_get_last_error_json(command_handle: i32, cb: JsonCallback) -> i32 {
+    trace!("api::sovtoken_get_last_error_json called");
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let error_json = match messages::last_error().to_json().map_err(map_err_err!()) {
+        Ok(json) => json,
+        Err(_) => {
+            let ec = ErrorCode::CommonInvalidState;
+            trace!("api::sovtoken_get_last_error_json << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("last_error", error_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_get_last_error_json << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Get the catalog of the error messages, to translate them by id.
+
+    Ids are never reused; a text with `{}` placeholders gets the args of
+    the message in order.
+
+    # Params
+    command_handle: standard command handle
+    cb: called with the catalog json:
+    ```text
+    [
+        {
+            "id": <string>, // e.g. "SOV-0009"
+            "text": <string> // e.g. "A transfer can have {} inputs, found {}. ..."
+        }
+    ]
+    ```
+
+    # Returns
+    CommonInvalidStructure when the callback is missing
+    CommonInvalidState when the catalog can't be serialized
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_get_message_catalog_json(command_handle: i32, cb: JsonCallback) -> i32 {
+    trace!("api::sovtoken_get_message_catalog_json called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let catalog_json = match messages::CATALOG.to_json().map_err(map_err_err!()) {
+        Ok(json) => json,
+        Err(_) => {
+            let ec = ErrorCode::CommonInvalidState;
+            trace!("api::sovtoken_get_message_catalog_json << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("message_catalog", catalog_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_get_message_catalog_json << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Get the exact bytes signed for an input of a request.
+
+    For reviewers and hardware signers checking the signing scheme. The
+    request is an XFER_PUBLIC request, or a request with fees, as built by
+    libsovtoken; its signatures are ignored.
+
+    # Params
+    command_handle: standard command handle
+    request_json: the request the input is spent in
+    input_index: the position of the input, in the inputs of the transfer or of the fees
+    cb: called with the payload json:
+    ```text
+    {
+        "input": {"address": <string>, "seqNo": <int>},
+        "verkey": <string>, // the key the input is signed with
+        "txnDigest": <string>, // hex sha256 of the request the fees are paid for, null for a transfer
+        "message": <string>, // the signed bytes as text
+        "hex": <string>, // the signed bytes as hex
+        "base64": <string> // the signed bytes as base64
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when the request is malformed, neither a transfer nor has fees,
+    or has no input at input_index
+*/
+#[no_mangle]
+pub extern "C" fn get_signing_payload_handler(
+    command_handle: i32,
+    request_json: *const c_char,
+    input_index: u32,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::get_signing_payload_handler called >> input_index: {}", input_index);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let payload = match str_from_char_ptr(request_json) {
+        Some(request_json) => signing_payload::signing_payload(request_json, input_index as usize),
+        None => Err(ErrorCode::CommonInvalidStructure),
+    };
+
+    let payload_json = match payload.and_then(|payload| payload.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState))) {
+        Ok(json) => json,
+        Err(ec) => {
+            trace!("api::get_signing_payload_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("signing_payload", payload_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::get_signing_payload_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Check the fee inputs of a request written with fees are signed over the request.
+
+    The signatures of the DIDs of a request are its `signature` or its
+    `signatures` by DID, the signatures of the fee inputs are in its
+    `fees`, one per input. Each fee input signs the fee inputs and outputs
+    and the digest of the host request, so a fee signature can't pay for
+    another request. The DID signatures aren't verified.
+
+    # Params
+    command_handle: standard command handle
+    request_json: the request with fees, signed by `add_request_fees_handler`
+    cb: called with the check:
+    ```text
+    {
+        "valid": <bool>, // every fee input is signed by its key over the request
+        "txnDigest": <string>, // hex digest of the request the fee inputs sign
+        "signed": [<bool>, ...] // by fee input
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when the request has no fees, or its DID and fee
+    input signatures are mixed up: `signatures` isn't an object by DID, the
+    fees don't have one signature per input, or a signature is given as both
+*/
+#[no_mangle]
+pub extern "C" fn verify_fee_signatures_handler(
+    command_handle: i32,
+    request_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::verify_fee_signatures_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let check = str_from_char_ptr(request_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(request_signatures::verify_fee_signatures)
+        .and_then(|check| check.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+    let check = match check {
+        Ok(check) => check,
+        Err(ec) => {
+            trace!("api::verify_fee_signatures_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("fee_signatures_check", check));
+
+    let res = ErrorCode::Success;
+    trace!("api::verify_fee_signatures_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Parse the reply to a payment, as `parse_payment_response_handler`, with options.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: the wallet the options read from
+    resp_json: the reply to the payment
+    options_json: the options, every field is optional:
+    ```text
+    {
+        "resolveLabels": <bool> // set the "label" of the receipts of the addresses labeled in the wallet
+    }
+    ```
+    cb: called with the receipts json of `parse_payment_response_handler`
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid
+    the errors of `parse_payment_response_handler`, or of reading the wallet
+*/
+#[no_mangle]
+pub extern "C" fn parse_payment_response_with_options_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    resp_json: *const c_char,
+    options_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::parse_payment_response_with_options_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let receipts = parse_options(options_json).and_then(|options| {
+        let resp_json = str_from_char_ptr(resp_json).ok_or(ErrorCode::CommonInvalidStructure)?;
+        let mut receipts = parse_payment_reply(&wire_format::to_json(resp_json)?)?;
+        options.apply(&services().address_book(), wallet_handle, &mut receipts)?;
+        receipts.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState))
+    });
+
+    let receipts = match receipts {
+        Ok(receipts) => receipts,
+        Err(ec) => {
+            trace!("api::parse_payment_response_with_options_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("receipts", receipts));
+
+    let res = ErrorCode::Success;
+    trace!("api::parse_payment_response_with_options_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Parse the fees of a reply, as `parse_response_with_fees_handler`, with options.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: the wallet the options read from
+    resp_json: the reply to the request with fees
+    options_json: the options, as for `parse_payment_response_with_options_handler`
+    cb: called with the receipts json of `parse_response_with_fees_handler`
+
+    # Returns
+    CommonInvalidStructure when any of the inputs are invalid
+    the errors of `parse_response_with_fees_handler`, or of reading the wallet
+*/
+#[no_mangle]
+pub extern "C" fn parse_response_with_fees_with_options_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    resp_json: *const c_char,
+    options_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::parse_response_with_fees_with_options_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let receipts = parse_options(options_json).and_then(|options| {
+        let resp_json = str_from_char_ptr(resp_json).ok_or(ErrorCode::CommonInvalidStructure)?;
+        let mut receipts = parse_fees_reply(&wire_format::to_json(resp_json)?)?.unwrap_or_default();
+        options.apply(&services().address_book(), wallet_handle, &mut receipts)?;
+        receipts.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState))
+    });
+
+    let receipts = match receipts {
+        Ok(receipts) => receipts,
+        Err(ec) => {
+            trace!("api::parse_response_with_fees_with_options_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("receipts", receipts));
+
+    let res = ErrorCode::Success;
+    trace!("api::parse_response_with_fees_with_options_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Get the extra a receipt or a request has the hash of.
+
+    With `hash_oversized_extra` set in the library config, an extra longer
+    than `max_extra_bytes` is replaced on the ledger by `sha256:<hex>` and
+    stored in the storage backend of the library config.
+
+    # Params
+    command_handle: standard command handle
+    wallet_handle: the wallet the request was built with
+    extra: the extra of the ledger, an extra which isn't a hash is returned as it is
+    cb: called with the full extra
+
+    # Returns
+    CommonInvalidStructure when the extra is missing or isn't UTF-8
+    WalletItemNotFound when the extra of the hash isn't stored
+*/
+#[no_mangle]
+pub extern "C" fn get_full_extra_handler(
+    command_handle: i32,
+    wallet_handle: i32,
+    extra: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::get_full_extra_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let full_extra = extra_from_char_ptr(extra)
+        .and_then(|extra| extra.ok_or(ErrorCode::CommonInvalidStructure))
+        .and_then(|extra| services().extras().full_extra(wallet_handle, &extra));
+
+    let full_extra = match full_extra {
+        Ok(full_extra) => full_extra,
+        Err(ec) => {
+            trace!("api::get_full_extra_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("extra", full_extra));
+
+    let res = ErrorCode::Success;
+    trace!("api::get_full_extra_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Build a GET_TXN request of a token transaction, to look it up by its seqNo.
+
+    # Params
+    command_handle: standard command handle
+    ledger: the ledger of the transaction, as for the GET_TXN of libindy, null for the token ledger
+    seq_no: seqNo of the transaction
+    cb: called with the unsigned request json, sent with the `default_identifier` of the library config.
+    The reply is parsed by `parse_get_receipt_response_handler`
+
+    # Returns
+    CommonInvalidStructure when seq_no is 0 or larger than the ledger accepts
+*/
+#[no_mangle]
+pub extern "C" fn build_get_receipt_request_handler(
+    command_handle: i32,
+    ledger: *const c_char,
+    seq_no: u64,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::build_get_receipt_request_handler called >> seq_no: {}", seq_no);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    if seq_no == 0 || seq_no > i32::max_value() as u64 {
+        error!("No transaction has the seqNo {}", seq_no);
+        return ErrorCode::CommonInvalidStructure as i32;
+    }
+    let ledger = str_from_char_ptr(ledger).unwrap_or(LEDGER_ID);
+    let identifier = default_identifier();
+
+    // the envelope and dispatch mode are the ones of the context of the call
+    let span = span::current();
+    let context = context::current();
+    let res = indy::ledger::Ledger::build_get_txn_request_async(
+        Some(&identifier),
+        Some(ledger),
+        seq_no as i32,
+        move |ec, request| context::in_context(context.clone(), || span::in_span(span, || {
+            trace!("api::build_get_receipt_request_handler cb << ec: {:?}", ec);
+            let request = if ec == ErrorCode::Success { envelope::wrap("request", request) } else { request };
+            dispatcher::dispatch(cb, command_handle, ec as i32, request);
+        }))
+    );
+
+    trace!("api::build_get_receipt_request_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Parse the reply to a request of `build_get_receipt_request_handler`.
+
+    # Params
+    command_handle: standard command handle
+    resp_json: the reply of the ledger
+    cb: called with the transaction:
+    ```text
+    {
+        "seqNo": <int>,
+        "txnTime": <int>, // null when the reply doesn't have it
+        "type": <str>, // the transaction type, e.g. "10001" for XFER_PUBLIC
+        "sources": [<str>, ...], // the inputs of the transaction, none for a MINT
+        "receipts": [...], // the receipts of the outputs, as for parse_payment_response_handler
+        "extra": <str> // null when the transaction has none
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when the reply is invalid or the transaction isn't a token transaction
+    PaymentSourceDoesNotExistError when the ledger has no transaction with the seqNo
+*/
+#[no_mangle]
+pub extern "C" fn parse_get_receipt_response_handler(
+    command_handle: i32,
+    resp_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::parse_get_receipt_response_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let lookup = str_from_char_ptr(resp_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(wire_format::to_json)
+        .and_then(|resp_json| {
+            validate_if_strict(Schema::Response, &resp_json)?;
+            parse_get_receipt_response(&resp_json)
+        })
+        .and_then(|lookup| lookup.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+    let lookup = match lookup {
+        Ok(lookup) => lookup,
+        Err(ec) => {
+            trace!("api::parse_get_receipt_response_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("receipt_lookup", lookup));
+
+    let res = ErrorCode::Success;
+    trace!("api::parse_get_receipt_response_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Parse the fees section of the reply to any transaction written with fees.
+
+    A transaction which isn't a payment, e.g. a NYM, can be written with
+    fees added by `add_request_fees_handler`. The reply holds the fees
+    transaction next to the one written, this gives the UTXOs it spent and
+    the change it created, to reconcile the balances of the addresses.
+
+    # Params
+    command_handle: standard command handle
+    resp_json: the reply of the ledger
+    cb: called with null when the transaction was written without fees, and otherwise:
+    ```text
+    {
+        "seqNo": <int>, // of the fees transaction, which holds the change
+        "txnTime": <int>, // null when the reply doesn't have it
+        "fees": <int>, // the fees paid
+        "spent": [<str>, ...], // the txo:sov: ids of the inputs spent on the fees
+        "receipts": [...] // the receipts of the change, as for parse_payment_response_handler
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when the reply or its fees section is invalid
+    the error of the reason of a REQNACK or REJECT, e.g. PaymentInsufficientFundsError
+*/
+#[no_mangle]
+pub extern "C" fn parse_fees_section_handler(
+    command_handle: i32,
+    resp_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::parse_fees_section_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let section = str_from_char_ptr(resp_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(wire_format::to_json)
+        .and_then(|resp_json| {
+            validate_if_strict(Schema::Response, &resp_json)?;
+            parse_fees_section(&resp_json)
+        })
+        .and_then(|section| section.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+
+    let section = match section {
+        Ok(section) => section,
+        Err(ec) => {
+            trace!("api::parse_fees_section_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("fees_section", section));
+
+    let res = ErrorCode::Success;
+    trace!("api::parse_fees_section_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Frame a request built by the library for indy-vdr.
+
+    Pass the request called back to `indy_vdr_build_custom_request`. A
+    request without a protocolVersion is framed with version 2, the only one
+    indy-vdr speaks, its other fields are kept.
+
+    # Params
+    command_handle: standard command handle
+    request_json: a request built by the library, as json or base64 encoded MessagePack
+    cb: called with the request json for indy-vdr
+
+    # Returns
+    CommonInvalidStructure when the request is invalid, or is signed with
+    protocol version 1, see `protocol_version` in the library config
+*/
+#[no_mangle]
+pub extern "C" fn build_vdr_request_handler(
+    command_handle: i32,
+    request_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::build_vdr_request_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let request = str_from_char_ptr(request_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(wire_format::to_json)
+        .and_then(|request_json| indy_vdr::request_for_vdr(&request_json));
+
+    let request = match request {
+        Ok(request) => request,
+        Err(ec) => {
+            trace!("api::build_vdr_request_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("request", request));
+
+    let res = ErrorCode::Success;
+    trace!("api::build_vdr_request_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Turn what indy-vdr returned for a request into the reply libindy gives,
+    for the parse handlers.
+
+    # Params
+    command_handle: standard command handle
+    response_json: one of
+        the reply indy-vdr called back with,
+        the `result` of the reply alone,
+        the error json of `indy_vdr_get_current_error` when the request failed
+    cb: called with the reply json, a REJECT with the reason when the ledger
+        refused the request
+
+    # Returns
+    CommonInvalidStructure when the response isn't a json object
+    PoolLedgerTimeout when indy-vdr timed out
+    CommonInvalidState when indy-vdr failed otherwise
+*/
+#[no_mangle]
+pub extern "C" fn parse_vdr_response_handler(
+    command_handle: i32,
+    response_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::parse_vdr_response_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let reply = str_from_char_ptr(response_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(indy_vdr::response_from_vdr);
+
+    let reply = match reply {
+        Ok(reply) => reply,
+        Err(ec) => {
+            trace!("api::parse_vdr_response_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("reply", reply));
+
+    let res = ErrorCode::Success;
+    trace!("api::parse_vdr_response_handler << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Build a payment request without a callback, see `build_payment_req_handler`.
+
+    Blocks until the request is signed. The json of the returned `SyncResult`
+    is null when its error_code isn't Success, otherwise it has to be freed
+    with `sovtoken_free_json`.
+*/
+#[no_mangle]
+pub extern "C" fn build_payment_req_sync(
+    wallet_handle: i32,
+    submitter_did: *const c_char,
+    inputs_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char
+) -> SyncResult {
+    trace!("api::build_payment_req_sync called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    SyncResult::from(call_sync(|command_handle, cb| {
+        build_payment_req_handler(command_handle, wallet_handle, submitter_did, inputs_json, outputs_json, extra, cb)
+    }))
+}
+
+/**
+    Build a mint request without a callback, see `build_mint_txn_handler`.
+
+    The json of the returned `SyncResult` is freed with `sovtoken_free_json`.
+*/
+#[no_mangle]
+pub extern "C" fn build_mint_txn_sync(
+    wallet_handle: i32,
+    submitter_did: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char
+) -> SyncResult {
+    trace!("api::build_mint_txn_sync called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    SyncResult::from(call_sync(|command_handle, cb| {
+        build_mint_txn_handler(command_handle, wallet_handle, submitter_did, outputs_json, extra, cb)
+    }))
+}
+
+/**
+    Parse a payment response without a callback, see `parse_payment_response_handler`.
+
+    The json of the returned `SyncResult` is freed with `sovtoken_free_json`.
+*/
+#[no_mangle]
+pub extern "C" fn parse_payment_response_sync(resp_json: *const c_char) -> SyncResult {
+    trace!("api::parse_payment_response_sync called");
+    messages::clear_last_error();
+    SyncResult::from(call_sync(|command_handle, cb| parse_payment_response_handler(command_handle, resp_json, cb)))
+}
+
+/**
+    Parse a response with fees without a callback, see `parse_response_with_fees_handler`.
+
+    The json of the returned `SyncResult` is freed with `sovtoken_free_json`.
+*/
+#[no_mangle]
+pub extern "C" fn parse_response_with_fees_sync(resp_json: *const c_char) -> SyncResult {
+    trace!("api::parse_response_with_fees_sync called");
+    messages::clear_last_error();
+    SyncResult::from(call_sync(|command_handle, cb| parse_response_with_fees_handler(command_handle, resp_json, cb)))
+}
+
+/**
+    Parse a GET_UTXO response without a callback, see `parse_get_utxo_response_handler`.
+
+    The json of the returned `SyncResult` is freed with `sovtoken_free_json`.
+*/
+#[no_mangle]
+pub extern "C" fn parse_get_utxo_response_sync(resp_json: *const c_char) -> SyncResult {
+    trace!("api::parse_get_utxo_response_sync called");
+    messages::clear_last_error();
+    SyncResult::from(call_sync(|command_handle, cb| parse_get_utxo_response_handler(command_handle, resp_json, cb)))
+}
+
+/**
+    Parse a GET_FEES response without a callback, see `parse_get_txn_fees_response_handler`.
+
+    The json of the returned `SyncResult` is freed with `sovtoken_free_json`.
+*/
+#[no_mangle]
+pub extern "C" fn parse_get_txn_fees_response_sync(resp_json: *const c_char) -> SyncResult {
+    trace!("api::parse_get_txn_fees_response_sync called");
+    messages::clear_last_error();
+    SyncResult::from(call_sync(|command_handle, cb| parse_get_txn_fees_response_handler(command_handle, resp_json, cb)))
+}
+
+/**
+    Parse the reply to a write with fees without a callback, see `parse_write_with_fees_response_handler`.
+
+    The json of the returned `SyncResult` is freed with `sovtoken_free_json`.
+*/
+#[no_mangle]
+pub extern "C" fn parse_write_with_fees_response_sync(resp_json: *const c_char) -> SyncResult {
+    trace!("api::parse_write_with_fees_response_sync called");
+    messages::clear_last_error();
+    SyncResult::from(call_sync(|command_handle, cb| parse_write_with_fees_response_handler(command_handle, resp_json, cb)))
+}
+
+/**
+    Attest a GET_FEES reply without a callback, see `attest_get_txn_fees_response_handler`.
+
+    The json of the returned `SyncResult` is freed with `sovtoken_free_json`.
+*/
+#[no_mangle]
+pub extern "C" fn attest_get_txn_fees_response_sync(resp_json: *const c_char) -> SyncResult {
+    trace!("api::attest_get_txn_fees_response_sync called");
+    messages::clear_last_error();
+    SyncResult::from(call_sync(|command_handle, cb| attest_get_txn_fees_response_handler(command_handle, resp_json, cb)))
+}
+
+/**
+    Parse a GET_TXN response without a callback, see `parse_verify_response_handler`.
+
+    The json of the returned `SyncResult` is freed with `sovtoken_free_json`.
+*/
+#[no_mangle]
+pub extern "C" fn parse_verify_response_sync(resp_json: *const c_char) -> SyncResult {
+    trace!("api::parse_verify_response_sync called");
+    messages::clear_last_error();
+    SyncResult::from(call_sync(|command_handle, cb| parse_verify_response_handler(command_handle, resp_json, cb)))
+}
+
+/**
+    Get the canonical hash of a request without a callback, see `hash_request_handler`.
+
+    The json of the returned `SyncResult` is freed with `sovtoken_free_json`.
+*/
+#[no_mangle]
+pub extern "C" fn hash_request_sync(request_json: *const c_char) -> SyncResult {
+    trace!("api::hash_request_sync called");
+    messages::clear_last_error();
+    SyncResult::from(call_sync(|command_handle, cb| hash_request_handler(command_handle, request_json, cb)))
+}
+
+/**
+    Frame a request for indy-vdr without a callback, see `build_vdr_request_handler`.
+
+    The json of the returned `SyncResult` is freed with `sovtoken_free_json`.
+*/
+#[no_mangle]
+pub extern "C" fn build_vdr_request_sync(request_json: *const c_char) -> SyncResult {
+    trace!("api::build_vdr_request_sync called");
+    messages::clear_last_error();
+    SyncResult::from(call_sync(|command_handle, cb| build_vdr_request_handler(command_handle, request_json, cb)))
+}
+
+/**
+    Turn an indy-vdr response into a reply without a callback, see `parse_vdr_response_handler`.
+
+    The json of the returned `SyncResult` is freed with `sovtoken_free_json`.
+*/
+#[no_mangle]
+pub extern "C" fn parse_vdr_response_sync(response_json: *const c_char) -> SyncResult {
+    trace!("api::parse_vdr_response_sync called");
+    messages::clear_last_error();
+    SyncResult::from(call_sync(|command_handle, cb| parse_vdr_response_handler(command_handle, response_json, cb)))
+}
+
+/**
+    Free the json of a `SyncResult`, a null json is ignored.
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_free_json(json: *mut c_char) {
+    trace!("api::sovtoken_free_json called");
+    if !json.is_null() {
+        drop(unsafe { CString::from_raw(json) });
+    }
+}
+
+/**
+    Get the data of a result wrapped in an envelope, for consumers written
+    before `json_envelope` was set in the library config.
+
+    # Params
+    command_handle: standard command handle
+    json: the result of a handler, `{"ver": 1, "type": <string>, "data": <json>}`
+    cb: called with the result as the handler would have called back without
+        envelopes. A json which isn't an envelope is called back as it is.
+
+    # Returns
+    CommonInvalidStructure when json is null or an envelope of a newer version
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_unwrap_envelope(
+    command_handle: i32,
+    json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::sovtoken_unwrap_envelope called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let data = str_from_char_ptr(json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(envelope::unwrap);
+
+    let data = match data {
+        Ok(data) => data,
+        Err(ec) => {
+            trace!("api::sovtoken_unwrap_envelope << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, data);
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_unwrap_envelope << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Get the counters of the pool of buffers the callback strings are passed in.
+
+    The strings given to the callbacks are only valid during the callback,
+    their buffers are reused by the next callbacks. The counters of the
+    buffers the requests are serialized and base58 encoded in, pooled by
+    thread, are under `serde`.
+
+    # Params
+    command_handle: standard command handle
+    cb: called with the counters json:
+    ```text
+    {
+        "lent": <int>, // buffers taken from the pool or allocated
+        "reused": <int>, // lent buffers which came from the pool
+        "freed": <int>, // buffers freed instead of given back, the pool being full or the buffer too large
+        "pooled": <int>, // buffers in the pool now
+        "serde": {
+            "lent": <int>, // serialization buffers taken from the pool of a thread or allocated
+            "reused": <int>, // lent serialization buffers which came from the pool of a thread
+            "freed": <int> // serialization buffers freed instead of given back
+        }
+    }
+    ```
+
+    # Returns
+    CommonInvalidStructure when the callback is missing
+    CommonInvalidState when the counters can't be serialized
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_get_buffer_pool_stats_json(command_handle: i32, cb: JsonCallback) -> i32 {
+    trace!("api::sovtoken_get_buffer_pool_stats_json called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let stats = serde_json::to_value(buffer_pool_stats())
+        .map(|mut stats| {
+            stats["serde"] = json!(serde_buffers::serde_buffer_stats());
+            stats
+        });
+
+    let stats_json = match stats.and_then(|stats| stats.to_json()).map_err(map_err_err!()) {
+        Ok(json) => json,
+        Err(_) => {
+            let ec = ErrorCode::CommonInvalidState;
+            trace!("api::sovtoken_get_buffer_pool_stats_json << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("buffer_pool_stats", stats_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_get_buffer_pool_stats_json << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Call handlers from many threads at once, to check the library is thread safe
+    where it is embedded.
+
+    Parses GET_UTXO and GET_FEES replies and reads the library config from
+    `SELF_TEST_THREADS` threads, `SELF_TEST_CALLS` times each, and checks every
+    call succeeded. The counters of the locks of the library state are logged.
+    Built with the `deadlock_detection` feature, a lock order which could
+    deadlock makes the self test fail.
+
+    # Params
+    none
+
+    # Returns
+    Success when every call succeeded, CommonInvalidState otherwise
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_self_test() -> i32 {
+    trace!("api::sovtoken_self_test called");
+    messages::clear_last_error();
+
+    let res = match run_self_test() {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec,
+    };
+
+    trace!("api::sovtoken_self_test << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Get the metrics recorded by the handlers as json.
+
+    # Params
+    command_handle: standard command handle
+    cb: called with the metrics json:
+    ```text
+    {
+        <handler name>: {
+            "calls": <int>,
+            "failures": <int>,
+            "throttled": <int>, // calls refused by the rate limit, see `rate_limits` of the library config
+            "last_throttled": <object or null>, // the busy message of the last call refused, as in sovtoken_get_last_error_json
+            "latency_sum_ms": <float>,
+            "latency_ms": [{"le_ms": <int or null>, "count": <int>}, ...]
+        },
+        ...
+    }
+    ```
+    Besides the api handlers, the time spent signing inputs is recorded as `sign_inputs`.
+
+    # Returns
+    CommonInvalidStructure when the callback is missing
+    CommonInvalidState when the metrics can't be serialized
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_get_metrics_json(command_handle: i32, cb: JsonCallback) -> i32 {
+    trace!("api::sovtoken_get_metrics_json called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let metrics_json = match metrics::metrics_json() {
+        Ok(json) => json,
+        Err(ec) => {
+            trace!("api::sovtoken_get_metrics_json << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("metrics", metrics_json));
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_get_metrics_json << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Get a report of how the library was built and what it is doing, to
+    attach to a support request when an integration misbehaves.
+
+    The report has the versions of the library and of the indy crate it was
+    built against, the enabled features, the library config, the callbacks
+    not called yet, the sizes of the caches and the last error. The last
+    error is the one of the calling thread, call it on the thread of the
+    call which failed. See `logic::diagnostics::Diagnostics` for the json.
+
+    # Returns
+    the report json in a `SyncResult`, freed with `sovtoken_free_json`
+    CommonInvalidState as error_code when the report can't be serialized
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_diagnostics() -> SyncResult {
+    trace!("api::sovtoken_diagnostics called");
+    messages::clear_last_error();
+    let report = diagnostics::diagnostics()
+        .to_json()
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))
+        .map(|report| envelope::wrap("diagnostics", report));
+
+    trace!("api::sovtoken_diagnostics << result: {:?}", report.as_ref().map(|_| ErrorCode::Success));
+    SyncResult::from(report)
+}
+
+/**
+    Get the test vectors of the payment addresses, to check an
+    implementation of the addresses in another language against.
+
+    ```text
+    {
+        "version": <int>, // raised when vectors are changed or removed
+        "encoding": [
+            {"algorithm": "double_sha256" | "sha3", "verkey": <str>, "address": <str>}, // the address of the verkey
+            ...
+        ],
+        "validity": [
+            {"algorithm": "double_sha256" | "sha3", "address": <str>, "valid": <bool>, "description": <str>},
+            ...
+        ]
+    }
+    ```
+    `algorithm` is the checksum, as `address_checksum` of the library
+    config. An address is valid when it is `pay:sov:` and the base58 of the
+    32 bytes of an Ed25519 verkey followed by their checksum.
+
+    # Returns
+    the vectors json in a `SyncResult`, freed with `sovtoken_free_json`
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_get_address_test_vectors_json() -> SyncResult {
+    trace!("api::sovtoken_get_address_test_vectors_json called");
+    messages::clear_last_error();
+    let vectors = address_vectors::test_vectors()
+        .to_json()
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))
+        .map(|vectors| envelope::wrap("address_test_vectors", vectors));
+
+    trace!("api::sovtoken_get_address_test_vectors_json << result: {:?}", vectors.as_ref().map(|_| ErrorCode::Success));
+    SyncResult::from(vectors)
+}
+
+/**
+    Get a fixture of `sovtoken::fixtures`, for the test suites of the
+    wrappers. Only exported when the library is built with the `fixtures`
+    feature.
+
+    The fixtures are built from numbered seeds, in the format of this
+    version of the library, so a test suite needs no json of its own.
+    **The keys of the fixtures are public, never use them outside of tests.**
+
+    # Params
+    kind: the fixture, e.g. "address" or "signed_payment_request"
+    params_json: its parameters, e.g. {"index": 1}, see `fixtures::fixture_json`
+    for the kinds and their parameters
+
+    # Returns
+    the fixture json in a `SyncResult`, freed with `sovtoken_free_json`
+    CommonInvalidStructure for an unknown kind or parameter
+*/
+#[cfg(feature = "fixtures")]
+#[no_mangle]
+pub extern "C" fn sovtoken_get_fixture_json(kind: *const c_char, params_json: *const c_char) -> SyncResult {
+    trace!("api::sovtoken_get_fixture_json called");
+    messages::clear_last_error();
+    let fixture = match (str_from_char_ptr(kind), str_from_char_ptr(params_json)) {
+        (Some(kind), Some(params_json)) => ::fixtures::fixture_json(kind, params_json)
+            .map(|fixture| envelope::wrap("fixture", fixture)),
+        _ => Err(ErrorCode::CommonInvalidStructure),
+    };
+
+    trace!("api::sovtoken_get_fixture_json << result: {:?}", fixture.as_ref().map(|_| ErrorCode::Success));
+    SyncResult::from(fixture)
+}
+
+/**
+    Call the queued callbacks on the calling thread.
+
+    Only does something when the library config sets `"callback_dispatch": "poll"`,
+    then the callbacks of the handlers called by the application are queued
+    until the application pumps them with this function.
+
+    # Params
+    max_events: the most callbacks to call, 0 for all queued callbacks
+
+    # Returns
+    how many callbacks were called
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_poll_events(max_events: i32) -> i32 {
+    let max_events = if max_events <= 0 { usize::max_value() } else { max_events as usize };
+    let called = dispatcher::poll_events(max_events);
+    trace!("api::sovtoken_poll_events << called: {:?}", called);
+    return called as i32;
+}
+
+/**
+    exported method indy-sdk will call for us to register our payment methods with indy-sdk
+
+    Registers the payment method and the state proof parsers of GET_UTXO
+    and GET_FEES. A registration failing doesn't stop the others, and what
+    an earlier call registered isn't registered again unless the library
+    config sets `force_reregister`. `sovtoken_get_registration_status_json`
+    tells which registrations succeeded and why the others failed.
+
+    # Params
+    none
+
+    # Returns
+    CommonInvalidState in a debug build failing the address test vectors, see `logic::address_vectors`
+    otherwise the ErrorCode of the first registration libindy refused, Success when none
+*/
+#[no_mangle]
+pub extern fn sovtoken_init() -> i32 {
+
+    if let Err(ec) = apply_env_overrides() {
+        return ec as i32;
+    }
+
+    super::utils::logger::init_log();
+    init_services();
+
+    debug!("sovtoken_init() started");
+
+    if cfg!(debug_assertions) {
+        if let Err(ec) = address_vectors::check_test_vectors() {
+            return ec as i32;
+        }
+    }
+
+    let steps = vec![
+        registration::Step {
+            name: registration::PAYMENT_METHOD,
+            callbacks: registration::PAYMENT_METHOD_CALLBACKS,
+            register: Box::new(|| {
+                debug!("Going to call Payment::register");
+                Payment::register_method(
+                    PAYMENT_METHOD_NAME,
+                    Some(create_payment_address_handler),
+                    Some(add_request_fees_handler),
+                    Some(parse_response_with_fees_handler),
+                    Some(build_get_utxo_request_handler),
+                    Some(parse_get_utxo_response_handler),
+                    Some(build_payment_req_handler),
+                    Some(parse_payment_response_handler),
+                    Some(build_mint_txn_handler),
+                    Some(build_set_txn_fees_handler),
+                    Some(build_get_txn_fees_handler),
+                    Some(parse_get_txn_fees_response_handler),
+                    Some(build_verify_req_handler),
+                    Some(parse_verify_response_handler),
+                )
+            }),
+            // libindy replaces the payment method registered before
+            already_registered: None,
+        },
+        registration::Step {
+            name: registration::GET_UTXO_STATE_PROOF_PARSER,
+            callbacks: &["get_utxo_state_proof_parser", "free_parsed_state_proof"],
+            register: Box::new(|| {
+                debug!("Going to call Ledger::register_transaction_parser_for_sp for GET_UTXO");
+                Ledger::register_transaction_parser_for_sp(GET_UTXO, Some(get_utxo_state_proof_parser), Some(free_parsed_state_proof))
+            }),
+            already_registered: Some(ErrorCode::CommonInvalidState),
+        },
+        registration::Step {
+            name: registration::GET_FEES_STATE_PROOF_PARSER,
+            callbacks: &["get_fees_state_proof_parser", "free_parsed_state_proof"],
+            register: Box::new(|| {
+                debug!("Going to call Ledger::register_transaction_parser_for_sp for GET_FEES");
+                Ledger::register_transaction_parser_for_sp(GET_FEES, Some(get_fees_state_proof_parser), Some(free_parsed_state_proof))
+            }),
+            already_registered: Some(ErrorCode::CommonInvalidState),
+        },
+    ];
+
+    let status = registration::register(steps, library_config().force_reregister);
+    let res = status.error_code();
+    debug!("sovtoken_init() returning {:?}", res);
+    return res as i32;
+}
+
+/**
+    Get what came of the last `sovtoken_init`, e.g. after it failed.
+
+    ```text
+    {
+        "registered": <bool>, // whether every registration succeeded
+        "forced": <bool>, // whether force_reregister was set
+        "steps": [
+            {
+                "name": "payment_method" | "get_utxo_state_proof_parser" | "get_fees_state_proof_parser",
+                "callbacks": [<str>, ...], // the callbacks registered
+                "outcome": "registered" | "already_registered" | "failed",
+                "error": <int>, // the error code of libindy, when failed
+                "failure": "incompatible_libindy" | "conflict" | "other" // when failed
+            },
+            ...
+        ]
+    }
+    ```
+
+    # Returns
+    the status json in a `SyncResult`, freed with `sovtoken_free_json`
+    CommonInvalidState as error_code before the first `sovtoken_init`
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_get_registration_status_json() -> SyncResult {
+    trace!("api::sovtoken_get_registration_status_json called");
+    messages::clear_last_error();
+    let status = registration::status()
+        .ok_or(ErrorCode::CommonInvalidState)
+        .and_then(|status| status.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)))
+        .map(|status| envelope::wrap("registration_status", status));
+
+    trace!("api::sovtoken_get_registration_status_json << result: {:?}", status.as_ref().map(|_| ErrorCode::Success));
+    SyncResult::from(status)
+}
+
+/**
+    Take the results which couldn't be delivered, the oldest first.
+
+    A result is kept when no `_sync` call waits for it anymore, when libindy
+    called back after the timeout of the call, or when its callback was
+    queued in a context destroyed before it was polled, as a dead letter of
+    the default context. A dead letter identifies the result, it doesn't
+    hold it. The library keeps the last `dead_letter_capacity` of the
+    library config and counts the older ones dropped.
+
+    ```text
+    {
+        "deadLetters": [
+            {
+                "commandHandle": <int>, // the command handle of the result, null for a result of libindy
+                "operation": <str>, // the libindy operation, e.g. "signing", null for a command result
+                "err": <int>, // the error code of the result, null when unknown
+                "resultLen": <int>, // the length of the json of the result, or of the result of libindy as debug text
+                "resultHash": <str>, // the sha256 of the same, as hex
+                "reason": "no_waiter" | "after_timeout" | "context_destroyed",
+                "timestamp": <int> // seconds since the epoch it was kept
+            },
+            ...
+        ],
+        "dropped": <int> // the letters dropped over the capacity since the last drain
+    }
+    ```
+
+    # Params
+    max_letters: the most letters to take, 0 for all of them
+
+    # Returns
+    the dead letters json in a `SyncResult`, freed with `sovtoken_free_json`
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_drain_dead_letters_json(max_letters: i32) -> SyncResult {
+    trace!("api::sovtoken_drain_dead_letters_json called >> max_letters: {:?}", max_letters);
+    messages::clear_last_error();
+    let max_letters = if max_letters <= 0 { usize::max_value() } else { max_letters as usize };
+    let drained = dead_letter::drain(max_letters)
+        .to_json()
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))
+        .map(|drained| envelope::wrap("dead_letters", drained));
+
+    trace!("api::sovtoken_drain_dead_letters_json << result: {:?}", drained.as_ref().map(|_| ErrorCode::Success));
+    SyncResult::from(drained)
+}
+
+/**
+    Same as [`sovtoken_init`], but first applies a library config.
+
+    # Params
+    config_json: library config as json:
+    ```text
+    {
+        "strict_validation": <bool>, // validate incoming json against the embedded schemas, default false.
+                                     // Without it the json is only checked by deserializing it
+        "audit_log_path": <string>, // file to append the audit log to, default no audit log
+        "max_request_bytes": <int>, // largest request the ledger accepts, default 131072
+        "max_inputs": <int>, // most inputs of an XFER request, should match the ledger, default 100
+        "max_outputs": <int>, // most outputs of an XFER request, change included, should match the ledger, default 100
+        "callback_dispatch": "inline" | "thread" | "poll", // where the callbacks of the handlers called
+                                                           // by the application run, default "inline".
+                                                           // "poll" queues them for `sovtoken_poll_events`
+        "field_names": "address" | "recipient", // names emitted for the address of outputs and inputs,
+                                                // "recipient" emits `recipient` and `source`, default "address"
+        "amount_format": "number" | "string", // json type of the amounts of outputs, UTXOs and fees, default "number",
+                                              // "string" keeps amounts above 2^53 exact for JavaScript.
+                                              // Both are accepted as input
+        "address_checksum": "double_sha256" | "sha3", // checksum of the payment addresses, default "double_sha256",
+                                                      // the checksum of the Sovrin ledger plugin
+        "canonical_order": <bool>, // sort the inputs by address and seqNo, the outputs by address and amount,
+                                   // before signing, unless an options json preserves their order, default false
+        "duplicate_inputs": "reject" | "dedupe", // what to do with a UTXO given twice as an input, default "reject"
+        "allow_zero_outputs": <bool>, // build the outputs of 0 tokens of mints, payments and fees, which
+                                      // are rejected by default, e.g. for markers. default false
+        "log_level": <string>, // log filter in the format of RUST_LOG, e.g. "sovtoken=debug", default RUST_LOG
+        "log_format": "text" | "json", // how the log lines are written, default "text". Both tag the lines
+                                       // with the command handle and reqId of the call they are logged in
+        "devnet_faucet": <bool>, // build test token requests, with the `devnet` feature, default true
+        "req_id_strategy": "random" | "sequential", // req_id of the requests built, default "random",
+                                                    // "sequential" counts up from the time of the init in nanoseconds
+        "utxo_cache_size": <int>, // GET_UTXO replies kept parsed by address and state root, default 64,
+                                  // 0 disables the cache
+        "compression": "none" | "gzip" | "brotli", // compression of the address records and pending
+                                                   // transactions stored in the wallet, default "none".
+                                                   // Records stored uncompressed are compressed when read
+        "storage": "wallet" | "memory" | "file", // where the pending transactions are stored, default "wallet".
+                                                 // Also keeps the GET_UTXO and fee caches, "wallet" only
+                                                 // once set_cache_wallet_handler set a wallet
+        "storage_path": <string>, // directory of the "file" storage, required by it
+        "network": <string>, // ledger network, e.g. "mainnet". Addresses created are tagged
+                             // "pay:sov:<network>:...", and addresses tagged with another
+                             // network are rejected. Untagged addresses are always accepted
+        "verbose_receipts": <bool>, // keep the audit path of the transaction in the receipts of
+                                    // parse_payment_response_handler, see verify_receipt_inclusion_handler,
+                                    // default false
+        "max_extra_bytes": <int>, // largest extra of payments, mints and fees, default 1024
+        "hash_oversized_extra": <bool>, // put "sha256:<hex>" of a larger extra on the ledger and store the
+                                        // extra, see get_full_extra_handler, default false
+        "default_identifier": <string>, // DID the requests built without a submitter DID, null or "", are
+                                        // sent with, default "LibsovtokenDid11111111"
+        "json_envelope": <bool>, // wrap the results of the handlers called by the application, not through
+                                 // libindy, in {"ver": 1, "type": <string>, "data": <json>}, default false.
+                                 // See sovtoken_unwrap_envelope
+        "random_seed": <int>, // seed of the random req_ids, keys and partitions, for tests building the same
+                              // requests on every run. Only with the `seeded_random` feature, a build
+                              // without it rejects a seed. Default null, the random of the system
+        "create_key_timeout_ms": <int>, // milliseconds libindy gets to create a key, 0 waits forever, default 60000
+        "sign_timeout_ms": <int>, // milliseconds libindy gets to sign a message, 0 waits forever, default 60000
+        "protocol_version": 1 | 2, // protocol version the requests are framed with, 1 for the pools older
+                                   // than indy-node 1.4, default 2
+        "stateless": <bool>, // run without a wallet, the wallet handles are ignored and the inputs signed
+                             // by the signer of sovtoken_register_signer, default false
+        "force_reregister": <bool>, // register with libindy again, also what an earlier init registered,
+                                    // e.g. after libindy was reloaded, default false
+        "rate_limits": {<operation type>: <int>, ...}, // most calls per second of a wallet to the handlers of an
+                                                       // operation type, e.g. {"payment": 5}. The types are create_address,
+                                                       // payment, fees, get_utxo, set_fees, get_fees, mint, verify and
+                                                       // test_tokens. A call over the limit fails with CommonInvalidState,
+                                                       // counted as throttled in sovtoken_get_metrics_json with the
+                                                       // message SOV-0048 (busy), default no limits
+        "dead_letter_capacity": <int> // most undeliverable results kept for sovtoken_drain_dead_letters_json,
+                                      // 0 keeps none, default 64
+    }
+    ```
+
+    Every key can be overridden by an environment variable named `SOVTOKEN_`
+    and the key in upper case, e.g. `SOVTOKEN_LOG_LEVEL=sovtoken=debug` or
+    `SOVTOKEN_DEVNET_FAUCET=false`. The keys of config_json override the
+    environment variables, which override the defaults. The environment is
+    read once, by the first init.
+
+    # Returns
+    CommonInvalidStructure when config_json or an environment variable is invalid,
+    otherwise the ErrorCode from [`sovtoken_init`]
+
+    [`sovtoken_init`]: ./fn.sovtoken_init.html
+*/
+#[no_mangle]
+pub extern fn sovtoken_init_with_config(config_json: *const c_char) -> i32 {
+    let config_json = match str_from_char_ptr(config_json) {
+        Some(s) => s,
+        None => return ErrorCode::CommonInvalidStructure as i32,
+    };
+
+    let config = match Map::<String, Value>::from_json(config_json).map_err(map_err_err!()) {
+        Ok(config) => config,
+        Err(_) => return ErrorCode::CommonInvalidStructure as i32,
+    };
+
+    if let Err(ec) = init_library_config(LibraryConfig::default(), &config) {
+        return ec as i32;
+    }
+    return sovtoken_init();
+}
+
+/**
+    Same as [`sovtoken_init_with_config`], but reads the library config from a file.
+
+    The file is TOML when its name ends with `.toml`, json otherwise. It has
+    the keys of the config_json of [`sovtoken_init_with_config`], e.g.:
+    ```text
+    strict_validation = true
+    callback_dispatch = "thread"
+    log_level = "sovtoken=info"
+    ```
+
+    The `SOVTOKEN_*` environment variables override the keys of the file.
+
+    # Params
+    config_path: path of the config file
+
+    # Returns
+    CommonIOError when the file can't be read
+    CommonInvalidStructure when it or an environment variable isn't a valid config,
+    otherwise the ErrorCode from [`sovtoken_init`]
+
+    [`sovtoken_init`]: ./fn.sovtoken_init.html
+    [`sovtoken_init_with_config`]: ./fn.sovtoken_init_with_config.html
+*/
+#[no_mangle]
+pub extern fn sovtoken_init_from_file(config_path: *const c_char) -> i32 {
+    let config_path = match str_from_char_ptr(config_path) {
+        Some(s) => s,
+        None => return ErrorCode::CommonInvalidStructure as i32,
+    };
+
+    let config = match LibraryConfig::from_file(Path::new(config_path)) {
+        Ok(config) => config,
+        Err(ec) => return ec as i32,
+    };
+
+    if let Err(ec) = init_library_config(config, &Map::new()) {
+        return ec as i32;
+    }
+    return sovtoken_init();
+}
+
+/**
+    Create a context, an instance of the library with a state of its own.
+
+    A context has its own library config, caches, pending transactions,
+    queued callbacks and metrics, e.g. for a test harness running a context
+    per network in one process. The handlers run in the context their
+    thread entered with [`sovtoken_context_enter`]. The registration with
+    libindy, the logger and the services are shared by every context, and
+    set up by [`sovtoken_init`].
+
+    # Params
+    config_json: library config of the context, with the keys of the
+    config_json of [`sovtoken_init_with_config`]. The `SOVTOKEN_*`
+    environment variables apply to it the same way, the `log_level` and
+    `log_format` are ignored.
+    context_handle_p: written with the handle of the context
+
+    # Returns
+    CommonInvalidStructure when config_json or an environment variable is
+    invalid, or context_handle_p is null
+
+    [`sovtoken_init`]: ./fn.sovtoken_init.html
+    [`sovtoken_init_with_config`]: ./fn.sovtoken_init_with_config.html
+    [`sovtoken_context_enter`]: ./fn.sovtoken_context_enter.html
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_context_create(config_json: *const c_char, context_handle_p: *mut i32) -> i32 {
+    trace!("api::sovtoken_context_create called");
+    messages::clear_last_error();
+
+    if context_handle_p.is_null() {
+        let ec = ErrorCode::CommonInvalidStructure;
+        trace!("api::sovtoken_context_create << result: {:?}", ec);
+        return ec as i32;
+    }
+
+    let config = str_from_char_ptr(config_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|config_json| Map::<String, Value>::from_json(config_json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure)))
+        .and_then(|config| resolve_library_config(LibraryConfig::default(), &config));
+
+    let config = match config {
+        Ok(config) => config,
+        Err(ec) => {
+            trace!("api::sovtoken_context_create << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    let handle = context::create(config);
+    unsafe { *context_handle_p = handle; }
+
+    let res = ErrorCode::Success;
+    trace!("api::sovtoken_context_create << result: {:?}, context_handle: {}", res, handle);
+    return res as i32;
+}
+
+/**
+    Run the calls of this thread in a context.
+
+    The handlers called on this thread, and their callbacks wherever they
+    run, use the state of the context until the thread enters another one.
+    A thread which didn't enter a context runs in the default context, the
+    context of [`sovtoken_init`] and of the handlers libindy calls.
+
+    # Params
+    context_handle: a context of [`sovtoken_context_create`], 0 for the default context
+
+    # Returns
+    CommonInvalidStructure when context_handle isn't the handle of a context,
+    or the context was destroyed
+
+    [`sovtoken_init`]: ./fn.sovtoken_init.html
+    [`sovtoken_context_create`]: ./fn.sovtoken_context_create.html
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_context_enter(context_handle: i32) -> i32 {
+    trace!("api::sovtoken_context_enter called >> context_handle: {}", context_handle);
+    messages::clear_last_error();
+
+    let res = match context::enter(context_handle) {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec,
+    };
+
+    trace!("api::sovtoken_context_enter << result: {:?}", res);
+    return res as i32;
+}
+
+/**
+    Destroy a context, it can't be entered anymore.
+
+    The threads in the context stay in it, and the callbacks still to come
+    of its calls run in it. Its state is freed once they are done, and the
+    threads entered another context or exited. The callbacks queued for
+    `sovtoken_poll_events` are dropped, as dead letters of the default
+    context.
+
+    # Params
+    context_handle: a context of [`sovtoken_context_create`]
+
+    # Returns
+    CommonInvalidStructure when context_handle isn't the handle of a context
+    CommonInvalidState for the default context, 0
+
+    [`sovtoken_context_create`]: ./fn.sovtoken_context_create.html
+*/
+#[no_mangle]
+pub extern "C" fn sovtoken_context_destroy(context_handle: i32) -> i32 {
+    trace!("api::sovtoken_context_destroy called >> context_handle: {}", context_handle);
+    messages::clear_last_error();
+
+    let res = match context::destroy(context_handle) {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec,
+    };
+
+    trace!("api::sovtoken_context_destroy << result: {:?}", res);
+    return res as i32;
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+*/
+
+// `create_payment_address_handler` without its span and metrics.
+fn create_payment_address(
+    command_handle: i32,
+    wallet_handle: i32,
+    config_str: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::create_payment_address_handler called");
+    messages::clear_last_error();
+    let (config, cb) = match create_address::deserialize_arguments(config_str, cb) {
+        Ok(tup) => tup,
+        Err(e) => return e as i32
+    };
+
+    let payment_closure = create_address::create_address_cb(command_handle, cb);
+
+    let handler = services().payment_addresses();
+    let ec = match handler.create_payment_address_async(wallet_handle, config, payment_closure) {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec,
+    };
+    trace!("api::create_payment_address_handler << result: {:?}", ec);
+    return ec as i32;
+}
+
+// `add_request_fees_handler` without its span and metrics.
+fn add_request_fees(
+    command_handle: i32,
+    wallet_handle: i32,
+    did: *const c_char,
+    req_json: *const c_char,
+    inputs_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::add_request_fees_handler called did (address) >> {:?}", did);
+    messages::clear_last_error();
+    let res = add_fees_then(command_handle, wallet_handle, req_json, inputs_json, outputs_json, extra, &BuildOptions::default(), cb, move |cb, result| {
+        add_request_fees::closure_cb_response(command_handle, cb)(result.map(|(request, _, _)| request));
+    });
+    trace!("api::add_request_fees_handler result >> {:?}", res);
+    return res as i32;
+}
+
+// `parse_response_with_fees_handler` without its span and metrics.
+fn parse_response_with_fees(
+    command_handle: i32,
+    req_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::parse_response_with_fees_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    if req_json.is_null() {
+        trace!("api::parse_response_with_fees_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+        return ErrorCode::CommonInvalidStructure as i32;
+    }
+
+    let resp_json_string = match string_from_char_ptr(req_json) {
+        Some(s) => s,
+        None => {
+            error!("Failed to convert inputs_json pointer to string");
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let resp_json_string = match wire_format::to_json(&resp_json_string) {
+        Ok(json) => json,
+        Err(ec) => {
+            trace!("api::parse_response_with_fees_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    debug!("api::parse_response_with_fees_handler >> req_json: {:?}", resp_json_string);
+
+    let reply: Option<ParseResponseWithFeesReply> = match parse_fees_reply(&resp_json_string) {
+        Ok(rep) => rep,
+        Err(ec) => {
+            trace!("api::parse_response_with_fees_handler << result: {:?}", ec);
+            return ec as i32
+        },
+    };
+
+    let reply_str: Option<String> = match reply {
+        Some(reply) => {
+            match reply.to_json().map_err(map_err_err!()) {
+                Ok(j) => Some(j),
+                Err(_) => return ErrorCode::CommonInvalidState as i32,
+            }
+        }
+        None => None
+    };
+
+    let ec = ErrorCode::Success;
+
+    with_c_str(&reply_str.unwrap_or(String::from("[]")), |reply_str| cb(command_handle, ec as i32, reply_str));
+
+    trace!("api::parse_response_with_fees_handler << result: {:?}", ec);
+    return ec as i32;
+}
+
+// `build_payment_req_handler` without its span and metrics.
+fn build_payment_req(
+    command_handle: i32,
+    wallet_handle: i32,
+    submitter_did: *const c_char,
+    inputs_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::build_payment_req_handler called >> submitter_did (address) {:?}", submitter_did);
+    messages::clear_last_error();
+    let ec = sign_or_plan_payment(command_handle, wallet_handle, inputs_json, outputs_json, extra, &BuildOptions::default(), cb);
+    trace!("api::build_payment_req_handler << result {:?}", ec);
+    return ec as i32;
+}
+
+// `parse_payment_response_handler` without its span and metrics.
+fn parse_payment_response(
+    command_handle: i32,
+    resp_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::parse_payment_response_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    if resp_json.is_null() {
+        trace!("api::parse_payment_response_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+        return ErrorCode::CommonInvalidStructure as i32;
+    }
+
+    let resp_json_string = match string_from_char_ptr(resp_json) {
+        Some(s) => s,
+        None => {
+            error!("Failed to convert inputs_json pointer to string");
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let resp_json_string = match wire_format::to_json(&resp_json_string) {
+        Ok(json) => json,
+        Err(ec) => {
+            trace!("api::parse_payment_response_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    debug!("api::parse_payment_response_handler >> resp_json: {:?}", &resp_json_string);
+
+    let reply: ParsePaymentReply = match parse_payment_reply(&resp_json_string) {
+        Ok(rep) => rep,
+        Err(ec) => {
+            trace!("api::parse_payment_response_handler << result: {:?}", ec);
+            return ec as i32
+        },
+    };
+
+    let reply_str: String = match reply.to_json().map_err(map_err_err!()) {
+        Ok(j) => j,
+        Err(_) => return ErrorCode::CommonInvalidState as i32,
+    };
+
+    info!("Parsed payment response: {:?}", reply_str);
+    audit_log::record(audit_log::PARSE_PAYMENT, &resp_json_string, ErrorCode::Success);
+
+    with_c_str(&reply_str, |reply_str| cb(command_handle, ErrorCode::Success as i32, reply_str));
+    trace!("api::parse_payment_response_handler << result: {:?}", ErrorCode::Success);
+    return ErrorCode::Success as i32;
+}
+
+// `build_get_utxo_request_handler` without its span and metrics.
+fn build_get_utxo_request(
+    command_handle: i32,
+    wallet_handle: i32,
+    _submitter_did: *const c_char,
+    payment_address: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::build_get_utxo_request_handler called");
+    messages::clear_last_error();
+    let handle_result = api_result_handler!(< *const c_char >, command_handle, cb);
+
+    let payment_address = match str_from_char_ptr(payment_address) {
+        Some(s) => s,
+        None => {
+            error!("Failed to convert payment_address pointer to string");
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+    debug!("api::build_get_utxo_request_handler >> wallet_handle: {:?}, payment_address: {:?}", wallet_handle, payment_address);
+
+    let utxo_request =
+        GetUtxoOperationRequest::new(String::from(payment_address));
+    info!("Built GET_UTXO request: {:?}", utxo_request);
+    let utxo_request = utxo_request.serialize_to_pointer()
+        .map_err(|_| ErrorCode::CommonInvalidStructure);
+
+    let res = handle_result(utxo_request) as i32;
+    trace!("api::build_get_utxo_request_handler << result: {:?}", res);
+    return res;
+}
+
+// `parse_get_utxo_response_handler` without its span and metrics.
+fn parse_get_utxo_response(
+    command_handle: i32,
+    resp_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::parse_get_utxo_response_handler called");
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    if resp_json.is_null() {
+        trace!("api::parse_get_utxo_response_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+        return ErrorCode::CommonInvalidStructure as i32;
+    }
+
+    let resp_json_string = match string_from_char_ptr(resp_json) {
+        Some(s) => s,
+        None => {
+            error!("Failed to convert inputs_json pointer to string");
+            trace!("api::parse_get_utxo_response_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let resp_json_string = match wire_format::to_json(&resp_json_string) {
+        Ok(json) => json,
+        Err(ec) => {
+            trace!("api::parse_get_utxo_response_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    debug!("api::parse_get_utxo_response_handler >> resp_json: {:?}", resp_json_string);
+
+    if let Err(ec) = validate_if_strict(Schema::Response, &resp_json_string) {
+        trace!("api::parse_get_utxo_response_handler << result: {:?}", ec);
+        return ec as i32;
+    }
+
+    // here is where the magic happens--conversion from input structure to output structure
+    // is handled in ParseGetUtxoReply::from_response, unless the reply is cached
+    let reply: ParseGetUtxoReply = match utxo_cache::parse_get_utxo_response_cached(None, &resp_json_string) {
+        Ok(reply) => optimistic_updates::apply(None, reply),
+        Err(err) => {
+            trace!("api::parse_get_utxo_response_handler << result: {:?}", err);
+            return err as i32
+        }
+    };
+
+    let reply_str: String = match reply.to_json().map_err(map_err_err!())  {
+        Ok(j) => j,
+        Err(_) => return ErrorCode::CommonInvalidState as i32,
+    };
+    info!("Parsed GET_UTXO response, received: {:?}", reply_str);
+
+    with_c_str(&reply_str, |reply_str| cb(command_handle, ErrorCode::Success as i32, reply_str));
+    trace!("api::parse_get_utxo_response_handler << result: {:?}", ErrorCode::Success);
+    return ErrorCode::Success as i32;
+}
+
+// `build_set_txn_fees_handler` without its span and metrics.
+fn build_set_txn_fees(
+    command_handle: i32,
+    wallet_handle: i32,
+    submitter_did: *const c_char,
+    fees_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::build_set_txn_fees_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    let (did, set_fees, cb) = match set_fees::deserialize_inputs(
+        submitter_did,
+        fees_json,
+        cb
+    ) {
+        Ok(tup) => tup,
+        Err(e) => {
+            trace!("api::build_set_txn_fees_handler << result: {:?}", e);
+            return e as i32
+        }
+    };
+
+    let fees_request = set_fees.as_request(did);
+
+    let fees_request_pointer_option = fees_request.serialize_to_pointer()
+        .or(Err(ErrorCode::CommonInvalidStructure));
+
+    let fees_request_pointer = match fees_request_pointer_option {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            trace!("api::build_set_txn_fees_handler << result: {:?}", e);
+            return e as i32
+        },
+    };
+
+    cb(command_handle, ErrorCode::Success as i32, fees_request_pointer);
+
+    trace!("api::build_set_txn_fees_handler << result: {:?}", ErrorCode::Success);
+    return ErrorCode::Success as i32;
+}
+
+// `build_get_txn_fees_handler` without its span and metrics.
+fn build_get_txn_fees(
+    command_handle: i32,
+    wallet_handle: i32,
+    submitter_did: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    let handle_result = api_result_handler!(< *const c_char >, command_handle, cb);
+    trace!("api::build_get_txn_fees_handler called");
+    messages::clear_last_error();
+
+    if cb.is_none() {
+        return handle_result(Err(ErrorCode::CommonInvalidStructure)) as i32;
+    }
+
+    let did = Did::submitter_from_pointer(submitter_did);
+
+    debug!("api::build_get_txn_fees_handler >> wallet_handle: {:?}, submitter_did: {:?}", wallet_handle, did);
+
+    let did = match did {
+        Ok(did) => did,
+        Err(e) => { return e as i32; }
+    };
+
+    let get_txn_request = GetFeesRequest::new().as_request(did);
+    info!("Built GET_TXN_FEES request: {:?}", get_txn_request);
+
+    let request_pointer = match get_txn_request.serialize_to_pointer() {
+        Ok(p) => p,
+        Err(_) => {
+            trace!("api::build_get_txn_fees_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+            return ErrorCode::CommonInvalidState as i32
+        }
+    };
+
+    let res = handle_result(Ok(request_pointer)) as i32;
+    trace!("api::build_get_txn_fees_handler << res: {:?}", res);
+    return res;
+}
+
+// `build_mint_txn_handler` without its span and metrics.
+fn build_mint_txn(
+    command_handle:i32,
+    wallet_handle: i32,
+    submitter_did: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::build_mint_txn_handle called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    let (did, outputs, extra, cb) = match minting::deserialize_inputs(
+        submitter_did,
+        outputs_json,
+        extra,
+        cb
+    ) {
+        Ok(tup) => tup,
+        Err(e) => {
+            trace!("api::build_mint_txn_handle << res: {:?}", e);
+            return e as i32
+        },
+    };
+    let extra = match services().extras().prepare(wallet_handle, extra) {
+        Ok(extra) => extra,
+        Err(e) => {
+            trace!("api::build_mint_txn_handle << res: {:?}", e);
+            return e as i32
+        },
+    };
+
+    debug!("Deserialized build_mint_txn_handler arguments.");
+
+    let mint_request = match minting::build_mint_request(did, outputs, extra) {
+        Ok(json) => json,
+        Err(e) => {
+            trace!("api::build_mint_txn_handle << res: {:?}", e);
+            return e as i32
+        }
+    };
+    debug!("Serialized mint request as pointer.");
+
+    cb(command_handle, ErrorCode::Success as i32, mint_request);
+    let res = ErrorCode::Success;
+    trace!("api::build_mint_txn_handle << res: {:?}", res);
+    return res as i32;
+}
+
+// `build_verify_req_handler` without its span and metrics.
+fn build_verify_req(
+    command_handle: i32,
+    wallet_handle: i32,
+    did: *const c_char,
+    txo: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::build_verify_req called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+
+    let (did, txo, cb) = match verify::deserialize(did, txo, cb) {
+        Ok(a) => a,
+        Err(ec) => {
+            trace!("api::build_verify_req << res {:?}", ec);
+            return ec as i32;
+        }
+    };
+    let did = did.map(String::from).unwrap_or_else(default_identifier);
+
+    // libindy calls back on its own thread, which has to enter the context and span of the call
+    let span = span::current();
+    let context = context::current();
+    let res = indy::ledger::Ledger::build_get_txn_request_async(
+        Some(&did),
+        Some(LEDGER_ID),
+        txo.seq_no as i32,
+        move |ec, res| context::in_context(context.clone(), || span::in_span(span, || {
+            trace!("api::build_verify_req cb << ec: {:?}, res: {:?}", ec, res);
+            with_c_str(&res, |res| cb(command_handle, ec as i32, res));
+        }))
+    );
+
+    trace!("api::build_verify_req << res {:?}", res);
+
+    res as i32
+}
+
+// `parse_verify_response_handler` without its span and metrics.
+fn parse_verify_response(
+    command_handle: i32,
+    resp_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    trace!("api::parse_verify_response_handler called");
+    messages::clear_last_error();
+    if resp_json.is_null() {
+        return ErrorCode::CommonInvalidStructure as i32;
+    }
+
+    let resp_json_string = match string_from_char_ptr(resp_json) {
+        Some(s) => s,
+        None => {
+            error!("Failed to convert resp_json pointer to string");
+            trace!("api::parse_verify_response_handler << result: {:?}", ErrorCode::CommonInvalidStructure);
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let resp_json_string = match wire_format::to_json(&resp_json_string) {
+        Ok(json) => json,
+        Err(ec) => {
+            trace!("api::parse_verify_response_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    debug!("api::parse_verify_response_handler >> resp_json: {:?}", resp_json_string);
+
+    if let Err(ec) = validate_if_strict(Schema::Response, &resp_json_string) {
+        trace!("api::parse_verify_response_handler << result: {:?}", ec);
+        return ec as i32;
+    }
+
+    let result = match parse_verify::parse_response(&resp_json_string) {
+        Ok(e) => e,
+        Err(ec) => {
+            trace!("api::parse_verify_response_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    let ec = ErrorCode::Success;
+
+    trace!("api::parse_verify_response_handler << result: {:?}", result);
+    with_c_str(&result, |result| cb(command_handle, ErrorCode::Success as i32, result));
+
+    ec as i32
+}
+
+// `request_test_tokens_handler` without its span and metrics.
+fn request_test_tokens(
+    command_handle: i32,
+    wallet_handle: i32,
+    payment_address: *const c_char,
+    amount: u64,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::request_test_tokens_handler called >> wallet_handle {}", wallet_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let payment_address = match str_from_char_ptr(payment_address) {
+        Some(s) => s,
+        None => {
+            error!("Failed to convert payment_address pointer to string");
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+    };
+
+    let mint_request = match devnet::build_test_tokens_request(wallet_handle, payment_address, amount) {
+        Ok(request) => request,
+        Err(ec) => {
+            trace!("api::request_test_tokens_handler << result: {:?}", ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("request", mint_request));
+
+    let res = ErrorCode::Success;
+    trace!("api::request_test_tokens_handler << result: {:?}", res);
+    return res as i32;
+}
+
+// The receipts of the reply to a payment, with the proofs when `verbose_receipts` is set.
+fn parse_payment_reply(resp_json: &str) -> Result<ParsePaymentReply, ErrorCode> {
+    let resp_json = &legacy_reply::nest_json(resp_json)?;
+    validate_if_strict(Schema::Response, resp_json)?;
+
+    let response = ParsePaymentResponse::from_json(resp_json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    // here is where the magic happens--conversion from input structure to output structure
+    // is handled in ParsePaymentReply::from_response
+    let mut reply = from_response(response).map_err(|ec| {
+        audit_log::record(audit_log::PARSE_PAYMENT, resp_json, ec);
+        ec
+    })?;
+
+    if library_config().verbose_receipts {
+        inclusion_proof::add_to_receipts(&mut reply, resp_json)?;
+    }
+    Ok(reply)
+}
+
+// Call back with the request with fees of `add_request_fees_with_status_handler`.
+fn dispatch_fees_status(cb: JsonCallbackUnwrapped, command_handle: i32, result: Result<(String, bool, FeesMetadata), ErrorCode>) {
+    match result {
+        Ok((request, fees_applied, metadata)) => {
+            let status = json!({"request": request, "feesApplied": fees_applied, "metadata": metadata}).to_string();
+            dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("request_with_fees", status))
+        },
+        Err(ec) => dispatcher::dispatch(cb, command_handle, ec as i32, String::new()),
+    };
+}
+
+// Add the fees of a `write` request, paid by the funding address, see `build_nym_with_fees_handler`.
+fn build_write_with_fees(
+    name: &str,
+    write: WriteTxn,
+    command_handle: i32,
+    wallet_handle: i32,
+    pool_handle: i32,
+    req_json: *const c_char,
+    funding_address: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    span::with_command_handle(command_handle, || metrics::measure_wallet_handler(name, rate_limit::FEES, wallet_handle, || {
+        trace!("api::{} called >> wallet_handle {}, pool_handle: {}", name, wallet_handle, pool_handle);
+        messages::clear_last_error();
+        check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+        let result = str_from_char_ptr(req_json)
+            .ok_or(ErrorCode::CommonInvalidStructure)
+            .and_then(|req_json| RawRequest::new(wire_format::to_json(req_json)?))
+            .and_then(|request| {
+                let funding_address = str_from_char_ptr(funding_address).ok_or(ErrorCode::CommonInvalidStructure)?;
+                address::validate_address(funding_address)?;
+                let funded = request.clone();
+                services().write_funder().fund(pool_handle, write, &request, funding_address, move |funding| {
+                    add_write_fees(cb, command_handle, wallet_handle, write, funded.clone(), funding)
+                })
+            });
+
+        let res = match result {
+            Ok(()) => ErrorCode::Success,
+            Err(ec) => ec,
+        };
+        trace!("api::{} << result: {:?}", name, res);
+        return res as i32;
+    }))
+}
+
+// Add the fees of `funding` to `request`, a request of `write`, and call back with it.
+fn add_write_fees(cb: JsonCallbackUnwrapped, command_handle: i32, wallet_handle: i32, write: WriteTxn, request: RawRequest, funding: Result<Option<Funding>, ErrorCode>) {
+    let funding = match funding {
+        Ok(Some(funding)) => funding,
+        Ok(None) => {
+            info!("The fee schedule waives the fee of {}, the request is returned without fees", write.name());
+            return dispatch_fees_status(cb, command_handle, Ok((request.as_str().to_string(), false, FeesMetadata::waived())));
+        },
+        Err(ec) => return dispatch_fees_status(cb, command_handle, Err(ec)),
+    };
+
+    let input_amounts: Vec<Option<TokenAmount>> = funding.input_amounts.iter().cloned().map(Some).collect();
+    let result = FeesMetadata::of_amounts(&funding.inputs, &input_amounts, &funding.outputs).and_then(|metadata| {
+        add_request_fees::add_fees_to_request_and_serialize(
+            wallet_handle,
+            funding.inputs,
+            funding.outputs,
+            None,
+            request,
+            Box::new(move |result| dispatch_fees_status(cb, command_handle, result.map(|request| (request, true, metadata.clone()))))
+        )
+    });
+    if let Err(ec) = result {
+        dispatch_fees_status(cb, command_handle, Err(ec));
+    }
+}
+
+// Parse a GET_FEES reply and remember its fees for the network of `pool_handle`, or the shared schedule.
+// Preload the fees of `fees_json` in the schedule of the network of
+// `pool_handle`, the shared one for `None`, and call back with the schedule.
+fn set_fee_schedule(
+    name: &str,
+    command_handle: i32,
+    pool_handle: Option<i32>,
+    fees_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    trace!("api::{} called >> pool_handle: {:?}", name, pool_handle);
+    messages::clear_last_error();
+    check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+    let result = str_from_char_ptr(fees_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(set_fees::fees_from_json)
+        .and_then(|fees| {
+            fee_waiver::preload_schedule(pool_handle, &fees);
+            fee_waiver::schedule(pool_handle).to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState))
+        });
+
+    let schedule = match result {
+        Ok(schedule) => schedule,
+        Err(ec) => {
+            trace!("api::{} << result: {:?}", name, ec);
+            return ec as i32;
+        }
+    };
+
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("fee_schedule", schedule));
+
+    let res = ErrorCode::Success;
+    trace!("api::{} << result: {:?}", name, res);
+    return res as i32;
+}
+
+fn parse_get_txn_fees(
+    name: &str,
+    command_handle: i32,
+    pool_handle: Option<i32>,
+    resp_json: *const c_char,
+    cb: JsonCallback
+) -> i32 {
+    span::with_command_handle(command_handle, || metrics::measure_handler(name, || {
+        check_useful_c_callback!(cb, ErrorCode::CommonInvalidStructure as i32);
+
+        trace!("api::{} called", name);
+        messages::clear_last_error();
+        if resp_json.is_null() {
+            return ErrorCode::CommonInvalidStructure as i32;
+        }
+        let resp_json_string = match string_from_char_ptr(resp_json) {
+            Some(s) => s,
+            None => {
+                error!("Failed to convert resp_json pointer to string");
+                trace!("api::{} << result: {:?}", name, ErrorCode::CommonInvalidStructure);
+                return ErrorCode::CommonInvalidStructure as i32;
+            }
+        };
+
+        let resp_json_string = match wire_format::to_json(&resp_json_string) {
+            Ok(json) => json,
+            Err(ec) => {
+                trace!("api::{} << result: {:?}", name, ec);
+                return ec as i32;
+            }
+        };
+
+        debug!("api::{} >> resp_json: {:?}", name, resp_json_string);
+
+        if let Err(ec) = validate_if_strict(Schema::Response, &resp_json_string) {
+            trace!("api::{} << result: {:?}", name, ec);
+            return ec as i32;
+        }
+        debug!("Deserialized {} arguments", name);
+
+        let fees_json_obj =
+            match parse_fees_of_pool(pool_handle, resp_json_string){
+                Ok(s) => {
+                    s
+                },
+                Err(_) => {
+                    trace!("api::{} << result: {:?}", name, ErrorCode::CommonInvalidStructure);
+                    return ErrorCode::CommonInvalidStructure as i32;
+                }
+            };
+        info!("Parsed get_txn_fees_response, result: {:?}", fees_json_obj);
+        with_c_str(&fees_json_obj, |fees_json| cb(command_handle, ErrorCode::Success as i32, fees_json));
+
+        let res = ErrorCode::Success as i32;
+        trace!("api::{} << result: {:?}", name, res);
+        return res;
+    }))
+}
+
+// The receipts of the fees of the reply, `None` when the request had no fees.
+fn parse_fees_reply(resp_json: &str) -> Result<Option<ParseResponseWithFeesReply>, ErrorCode> {
+    let resp_json = &legacy_reply::nest_json(resp_json)?;
+    validate_if_strict(Schema::Response, resp_json)?;
+
+    let response = ParseResponseWithFees::from_json(resp_json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    // here is where the magic happens--conversion from input structure to output structure
+    // is handled in ParseResponseWithFeesReply::from_response
+    parse_response_with_fees_handler::from_response(response)
+}
+
+// The json of the options of a `_with_options` parse handler.
+fn parse_options(options_json: *const c_char) -> Result<ParseOptions, ErrorCode> {
+    str_from_char_ptr(options_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| ParseOptions::from_json(json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure)))
+}
+
+// The requests of a payout batch, with how many duplicate outputs were merged.
+fn batch_chunks(
+    wallet_handle: i32,
+    utxos_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    max_outputs: u32,
+    options: &BuildOptions
+) -> Result<(Vec<BatchChunk>, usize), ErrorCode> {
+    let mut limits = TransferLimits::configured();
+    if max_outputs != 0 {
+        limits.max_outputs = ::std::cmp::min(limits.max_outputs, max_outputs as usize);
+    }
+    let extra = extra_from_char_ptr(extra).and_then(|extra| ledger_extra(wallet_handle, extra, options))?;
+
+    let utxos = str_from_char_ptr(utxos_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| Vec::<UTXO>::from_json(json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure)))?;
+
+    let (outputs, merged_duplicates) = str_from_char_ptr(outputs_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| unknown_fields::from_json(json, "outputs"))
+        .and_then(|outputs: Outputs| output::check_amounts(&outputs, options.zero_outputs_allowed()).map(|_| outputs).map_err(ErrorCode::from))
+        .and_then(batch_payment::merge_duplicate_outputs)?;
+
+    let chunks = batch_payment::plan_batch(&utxos, &outputs, limits, extra.as_ref().map(String::as_str))?;
+    Ok((chunks, merged_duplicates))
+}
+
+// The requests of a transfer split to fit the `max_request_bytes` of the library config.
+fn split_chunks(
+    wallet_handle: i32,
+    utxos_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    options: &BuildOptions
+) -> Result<Vec<BatchChunk>, ErrorCode> {
+    let extra = extra_from_char_ptr(extra).and_then(|extra| ledger_extra(wallet_handle, extra, options))?;
+    let max_request_bytes = library_config().max_request_bytes;
+
+    let utxos = str_from_char_ptr(utxos_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| Vec::<UTXO>::from_json(json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure)))?;
+
+    let outputs: Outputs = str_from_char_ptr(outputs_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| unknown_fields::from_json(json, "outputs"))?;
+    output::check_amounts(&outputs, options.zero_outputs_allowed()).map_err(ErrorCode::from)?;
+
+    request_size::split_transfer(&utxos, &outputs, extra.as_ref().map(String::as_str), max_request_bytes, TransferLimits::configured())
+}
+
+// Sign the requests, or call back with their plan when simulating.
+fn finish_build(
+    options: &BuildOptions,
+    chunks: Vec<BatchChunk>,
+    merged_duplicates: usize,
+    command_handle: i32,
+    wallet_handle: i32,
+    cb: JsonCallbackUnwrapped
+) -> Result<(), ErrorCode> {
+    if !options.simulate {
+        return sign_batch_manifest(chunks, merged_duplicates, command_handle, wallet_handle, cb);
+    }
+
+    let plan = simulation::plan_chunks(chunks, merged_duplicates)?
+        .to_json()
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))?;
+    dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("payment_plan", plan));
+    Ok(())
+}
+
+// Sign the payment request, or call back with its plan when simulating.
+fn sign_or_plan_payment(
+    command_handle: i32,
+    wallet_handle: i32,
+    inputs_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    options: &BuildOptions,
+    cb: JsonCallback
+) -> ErrorCode {
+    let (inputs, outputs, extra, cb) = match build_payment::deserialize_inputs_with_options(inputs_json, outputs_json, extra, cb, options) {
+        Ok(tup) => tup,
+        Err(ec) => return ec,
+    };
+    let extra = match ledger_extra(wallet_handle, extra, options) {
+        Ok(extra) => extra,
+        Err(ec) => return ec,
+    };
+
+    if !options.simulate {
+        if let Err(ec) = balance::validate_known_balance(options.pool_handle, XFER_PUBLIC, &inputs, &outputs) {
+            return ec;
+        }
+    }
+
+    let payload = XferPayload::new(inputs, outputs, extra);
+
+    let result = if options.simulate {
+        simulation::plan_payment(&payload)
+            .and_then(|plan| plan.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)))
+            .map(|plan| dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("payment_plan", plan)))
+    } else {
+        payload.sign_transfer(
+            &services().crypto,
+            wallet_handle,
+            Box::new(move |result| build_payment::handle_signing(command_handle, result, cb))
+        )
+    };
+
+    match result {
+        Ok(()) => ErrorCode::Success,
+        Err(ec) => ec
+    }
+}
+
+// The extra to put on the ledger, an oversized extra is only stored when not simulating.
+fn ledger_extra(wallet_handle: i32, extra: Option<String>, options: &BuildOptions) -> Result<Option<String>, ErrorCode> {
+    if options.simulate {
+        services().extras().preview(extra)
+    } else {
+        services().extras().prepare(wallet_handle, extra)
+    }
+}
+
+// The json of the options of a `_with_options` build handler.
+fn build_options(options_json: *const c_char) -> Result<BuildOptions, ErrorCode> {
+    str_from_char_ptr(options_json)
+        .ok_or(ErrorCode::CommonInvalidStructure)
+        .and_then(|json| BuildOptions::from_json(json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure)))
+}
+
+// Add fees to the request, or leave it as it is when the fee schedule waives
+// its fees, and pass `done` the callback, the request and whether fees were added.
+// When simulating, call back with the plan of the fees instead.
+fn add_fees_then<F>(
+    command_handle: i32,
+    wallet_handle: i32,
+    req_json: *const c_char,
+    inputs_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    options: &BuildOptions,
+    cb: JsonCallback,
+    done: F
+) -> ErrorCode where F: Fn(JsonCallbackUnwrapped, Result<(String, bool, FeesMetadata), ErrorCode>) + Send + Sync + 'static {
+    let (inputs, outputs, extra, request, cb) = match add_request_fees::deserialize_inputs_with_options(req_json, inputs_json, outputs_json, extra, cb, options) {
+        Ok(tup) => tup,
+        Err(error_code) => return error_code,
+    };
+
+    /*
+        Errors when the request is a XFER request becaause the
+        fees should be implicit in the operation's inputs and
+        outputs.
+    */
+    if add_request_fees::validate_type_not_transfer(&request).is_err() {
+        error!("api::add_request_fees_handler Can't add fees to a transfer request");
+        return ErrorCode::CommonInvalidStructure;
+    }
+
+    let framed = match options.pool_handle {
+        Some(pool_handle) => state().pools.lock().check_request(pool_handle, request.as_str()),
+        None => check_framing(request.as_str()),
+    };
+    if let Err(ec) = framed {
+        return ec;
+    }
+
+    if options.simulate {
+        let plan = ledger_extra(wallet_handle, extra, options)
+            .and_then(|_| simulation::plan_fees(&request, &inputs, &outputs, options.pool_handle))
+            .and_then(|plan| plan.to_json().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+        return match plan {
+            Ok(plan) => {
+                dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("fees_plan", plan));
+                ErrorCode::Success
+            },
+            Err(ec) => ec,
+        };
+    }
+
+    if add_request_fees::fees_waived(&request, options.pool_handle) {
+        info!("The fee schedule waives the fees of a {:?} request, it is returned without fees", add_request_fees::txn_type(&request));
+        done(cb, Ok((request.as_str().to_string(), false, FeesMetadata::waived())));
+        return ErrorCode::Success;
+    }
+
+    let extra = match services().extras().prepare(wallet_handle, extra) {
+        Ok(extra) => extra,
+        Err(ec) => return ec,
+    };
+
+    if let Some(txn_type) = add_request_fees::txn_type(&request) {
+        if let Err(ec) = balance::validate_known_balance(options.pool_handle, &txn_type, &inputs, &outputs) {
+            return ec;
+        }
+    }
+
+    let metadata = match FeesMetadata::new(&inputs, &outputs, options.pool_handle) {
+        Ok(metadata) => metadata,
+        Err(ec) => return ec,
+    };
+
+    let result = add_request_fees::add_fees_to_request_and_serialize(
+        wallet_handle,
+        inputs,
+        outputs,
+        extra,
+        request,
+        Box::new(move |result| done(cb, result.map(|request| (request, true, metadata.clone()))))
+    );
+
+    match result {
+        Err(e) => {
+            error!("api::add_request_fees_handler Received error adding fees to request_json");
+            e
+        },
+        Ok(()) => ErrorCode::Success,
+    }
+}
+
+const SELF_TEST_THREADS: usize = 8;
+const SELF_TEST_CALLS: usize = 50;
+
+// Runs the calls of `sovtoken_self_test` and checks their callbacks.
+fn run_self_test() -> Result<(), ErrorCode> {
+    let state = state();
+    let _running = state.self_test.lock();
+    state.self_test_callbacks.store(0, Ordering::SeqCst);
+    state.self_test_failures.store(0, Ordering::SeqCst);
+
+    let address = address::unqualified_address_from_verkey("EFfodscoymgdJDuM885uEWmgCcA25P6VR6TjVqsYZLW3")?;
+    let utxo_reply = cstring_from_str(json!({
+        "op": "REPLY",
+        "protocol_version": 2,
+        "result": {"type": GET_UTXO, "address": address, "identifier": "6ouriXMZkLeHsuXrN1X1fd", "reqId": 1, "outputs": [[address, 1, 10]]}
+    }).to_string());
+    let fees_reply = cstring_from_str(json!({
+        "op": "REPLY",
+        "protocol_version": 2,
+        "result": {"type": GET_FEES, "identifier": "6ouriXMZkLeHsuXrN1X1fd", "reqId": 1, "fees": {"1": 1}}
+    }).to_string());
+    let replies = Arc::new((utxo_reply, fees_reply));
+
+    let threads: Vec<_> = (0..SELF_TEST_THREADS).map(|_| {
+        let replies = Arc::clone(&replies);
+        thread::spawn(move || {
+            let mut failures = 0;
+            for call in 0..SELF_TEST_CALLS {
+                let command_handle = call as i32;
+                let ec = parse_get_utxo_response_handler(command_handle, replies.0.as_ptr(), Some(record_self_test_callback));
+                let ec2 = parse_get_txn_fees_response_handler(command_handle, replies.1.as_ptr(), Some(record_self_test_callback));
+                library_config();
+                if ec != ErrorCode::Success as i32 || ec2 != ErrorCode::Success as i32 {
+                    failures += 1;
+                }
+            }
+            failures
+        })
+    }).collect();
+
+    let mut failures = 0;
+    for handle in threads {
+        failures += handle.join().unwrap_or(1);
+    }
+
+    let callbacks = state.self_test_callbacks.load(Ordering::SeqCst);
+    failures += state.self_test_failures.load(Ordering::SeqCst);
+    info!("Self test: {} callbacks, {} failures, locks: {:?}", callbacks, failures, state.lock_stats());
+
+    if failures > 0 || callbacks != SELF_TEST_THREADS * SELF_TEST_CALLS * 2 {
+        error!("The self test failed with {} failures and {} callbacks", failures, callbacks);
+        return Err(ErrorCode::CommonInvalidState);
+    }
+    Ok(())
+}
+
+extern fn record_self_test_callback(_command_handle: i32, err: i32, _json: *const c_char) -> i32 {
+    state().self_test_callbacks.fetch_add(1, Ordering::SeqCst);
+    if err != ErrorCode::Success as i32 {
+        state().self_test_failures.fetch_add(1, Ordering::SeqCst);
+    }
+    ErrorCode::Success as i32
+}
+
+// Signs the payment built by `builder` and calls `cb` with the request json.
+fn sign_payment_request(
+    builder: PaymentRequestBuilder,
+    command_handle: i32,
+    wallet_handle: i32,
+    cb: JsonCallbackUnwrapped
+) -> Result<(), ErrorCode> {
+    builder.sign(&services().crypto, wallet_handle, Box::new(move |request| {
+        let request = request.and_then(|request| request.serialize_to_string().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)));
+        match request {
+            Ok(request) => dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("request", request)),
+            Err(ec) => dispatcher::dispatch(cb, command_handle, ec as i32, String::new()),
+        };
+    }))
+}
+
+// Signs the chunks of a batch and calls `cb` with the manifest json.
+fn sign_batch_manifest(
+    chunks: Vec<BatchChunk>,
+    merged_duplicates: usize,
+    command_handle: i32,
+    wallet_handle: i32,
+    cb: JsonCallbackUnwrapped
+) -> Result<(), ErrorCode> {
+    let summaries: Vec<(usize, u64)> = chunks.iter().map(|chunk| (chunk.output_count, chunk.amount)).collect();
+
+    batch_payment::sign_batch(chunks, &services().crypto, wallet_handle, Box::new(move |requests| {
+        let manifest = requests.and_then(|requests| {
+            let requests = requests.iter().zip(summaries.iter()).enumerate()
+                .map(|(index, (request, &(output_count, amount)))| {
+                    let request = request.serialize_to_string().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState))?;
+                    Ok(BatchEntry { index, output_count, amount, request })
+                })
+                .collect::<Result<Vec<BatchEntry>, ErrorCode>>()?;
+
+            let total_amount = summaries.iter().map(|&(_, amount)| amount).sum();
+            BatchManifest { requests, total_amount, merged_duplicates }
+                .to_json()
+                .map_err(map_err_err!())
+                .or(Err(ErrorCode::CommonInvalidState))
+        });
+
+        match manifest {
+            Ok(manifest) => dispatcher::dispatch(cb, command_handle, ErrorCode::Success as i32, envelope::wrap("batch_manifest", manifest)),
+            Err(ec) => dispatcher::dispatch(cb, command_handle, ec as i32, String::new()),
+        };
+    }))
 }
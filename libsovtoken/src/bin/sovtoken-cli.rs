@@ -0,0 +1,237 @@
+/*!
+    Command line smoke tool, built with the `cli` feature.
+
+    Runs libsovtoken through libindy, the way an agent does, and prints the
+    requests it builds and the replies it parses. Nothing is sent to a pool.
+
+    ```text
+    cargo run --features cli --bin sovtoken-cli -- address
+    cargo run --features cli --bin sovtoken-cli -- mint pay:sov:<address> 10
+    cargo run --features cli --bin sovtoken-cli -- parse-payment reply.json
+    ```
+
+    The wallet is a throw away one unless `--wallet <name>` is given, which
+    creates the wallet the first time and keeps it, so `transfer` can spend
+    the UTXOs of an address created by a previous run.
+*/
+
+extern crate indy;
+#[macro_use] extern crate serde_json;
+extern crate sovtoken;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::process;
+
+use indy::ErrorCode;
+use indy::did::Did;
+use indy::payments::Payment;
+use indy::wallet::Wallet;
+use sovtoken::utils::constants::general::PAYMENT_METHOD_NAME;
+use sovtoken::utils::random::rand_string;
+
+const DEFAULT_WALLET_KEY: &str = "sovtoken-cli-wallet-key";
+
+const USAGE: &str = "\
+usage: sovtoken-cli [--wallet <name>] [--wallet-key <key>] [--did-seed <seed>] <command> [<args>]
+
+commands:
+    address [<seed>]                           create a payment address
+    mint <address> <amount>                    build a MINT request
+    transfer <txo> <address> <amount>          build an XFER request spending the UTXO txo
+    set-fees <txn type>=<amount>...            build a SET_FEES request
+    get-fees                                   build a GET_FEES request
+    parse-payment <reply file>                 parse the reply to an XFER request
+    parse-fees <reply file>                    parse the reply to a request with fees
+    parse-sources <reply file>                 parse the reply to a GET_UTXO request
+
+the json of the requests and parsed replies is printed to stdout";
+
+/**
+    The options and command of a run.
+*/
+#[derive(Debug, PartialEq, Eq)]
+struct Args {
+    wallet: Option<String>,
+    wallet_key: String,
+    did_seed: Option<String>,
+    command: String,
+    args: Vec<String>,
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1).collect()) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let ec = sovtoken::api::sovtoken_init();
+    if ec != ErrorCode::Success as i32 {
+        eprintln!("Failed to initialize libsovtoken: {}", ec);
+        process::exit(1);
+    }
+
+    match run(&args) {
+        Ok(output) => println!("{}", output),
+        Err(error) => {
+            eprintln!("{} failed: {}", args.command, error);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_args(mut argv: Vec<String>) -> Result<Args, String> {
+    let mut wallet = None;
+    let mut wallet_key = DEFAULT_WALLET_KEY.to_string();
+    let mut did_seed = None;
+
+    argv.reverse();
+    while let Some(arg) = argv.pop() {
+        match arg.as_str() {
+            "--wallet" => wallet = Some(option_value(&arg, &mut argv)?),
+            "--wallet-key" => wallet_key = option_value(&arg, &mut argv)?,
+            "--did-seed" => did_seed = Some(option_value(&arg, &mut argv)?),
+            "-h" | "--help" => return Err(String::from("sovtoken-cli exercises libsovtoken without a pool")),
+            option if option.starts_with("--") => return Err(format!("Unknown option {}", option)),
+            _ => {
+                argv.reverse();
+                return Ok(Args { wallet, wallet_key, did_seed, command: arg, args: argv });
+            }
+        }
+    }
+
+    Err(String::from("No command given"))
+}
+
+fn option_value(option: &str, argv: &mut Vec<String>) -> Result<String, String> {
+    argv.pop().ok_or_else(|| format!("{} needs a value", option))
+}
+
+fn run(args: &Args) -> Result<String, String> {
+    match (args.command.as_str(), args.args.len()) {
+        ("parse-payment", 1) => parse_reply(&args.args[0], Payment::parse_payment_response),
+        ("parse-fees", 1) => parse_reply(&args.args[0], Payment::parse_response_with_fees),
+        ("parse-sources", 1) => parse_reply(&args.args[0], Payment::parse_get_payment_sources_response),
+        ("address", 0) | ("address", 1) | ("mint", 2) | ("transfer", 3) | ("get-fees", 0) => with_wallet(args, build),
+        ("set-fees", count) if count > 0 => with_wallet(args, build),
+        (command, _) => Err(format!("Unknown command or wrong arguments: {} {}", command, args.args.join(" "))),
+    }
+}
+
+// Runs `f` with an open wallet and a DID of it, and closes the wallet after.
+fn with_wallet<F>(args: &Args, f: F) -> Result<String, String>
+    where F: FnOnce(&Args, i32, &str) -> Result<String, ErrorCode>
+{
+    let name = args.wallet.clone().unwrap_or_else(|| format!("sovtoken-cli-{}", rand_string(10)));
+    let config = json!({"id": name}).to_string();
+    let credentials = json!({"key": args.wallet_key}).to_string();
+
+    match Wallet::create(&config, &credentials) {
+        Ok(()) | Err(ErrorCode::WalletAlreadyExistsError) => (),
+        Err(ec) => return Err(format!("Failed to create the wallet {}: {:?}", name, ec)),
+    }
+    let wallet_handle = Wallet::open(&config, &credentials)
+        .map_err(|ec| format!("Failed to open the wallet {}: {:?}", name, ec))?;
+
+    let did_json = match args.did_seed {
+        Some(ref seed) => json!({"seed": seed}).to_string(),
+        None => String::from("{}"),
+    };
+    let result = Did::new(wallet_handle, &did_json)
+        .and_then(|(did, _)| f(args, wallet_handle, &did))
+        .map_err(|ec| format!("{:?}", ec));
+
+    let _ = Wallet::close(wallet_handle);
+    if args.wallet.is_none() {
+        let _ = Wallet::delete(&config, &credentials);
+    }
+    result
+}
+
+fn build(args: &Args, wallet_handle: i32, did: &str) -> Result<String, ErrorCode> {
+    let a = &args.args;
+    match args.command.as_str() {
+        "address" => {
+            let config = match a.first() {
+                Some(seed) => json!({"seed": seed}).to_string(),
+                None => String::from("{}"),
+            };
+            Payment::create_payment_address(wallet_handle, PAYMENT_METHOD_NAME, &config)
+        },
+        "mint" => {
+            let outputs = json!([{"recipient": a[0], "amount": amount(&a[1])?}]).to_string();
+            Payment::build_mint_req(wallet_handle, Some(did), &outputs, None).map(|(request, _)| request)
+        },
+        "transfer" => {
+            let inputs = json!([a[0]]).to_string();
+            let outputs = json!([{"recipient": a[1], "amount": amount(&a[2])?}]).to_string();
+            Payment::build_payment_req(wallet_handle, Some(did), &inputs, &outputs, None).map(|(request, _)| request)
+        },
+        "set-fees" => {
+            let mut fees = serde_json::Map::new();
+            for fee in a {
+                let mut parts = fee.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(txn_type), Some(fee)) => fees.insert(txn_type.to_string(), json!(amount(fee)?)),
+                    _ => return Err(ErrorCode::CommonInvalidStructure),
+                };
+            }
+            let fees = serde_json::Value::Object(fees).to_string();
+            Payment::build_set_txn_fees_req(wallet_handle, Some(did), PAYMENT_METHOD_NAME, &fees)
+        },
+        "get-fees" => Payment::build_get_txn_fees_req(wallet_handle, Some(did), PAYMENT_METHOD_NAME),
+        _ => Err(ErrorCode::CommonInvalidStructure),
+    }
+}
+
+fn amount(amount: &str) -> Result<u64, ErrorCode> {
+    amount.parse().or(Err(ErrorCode::CommonInvalidStructure))
+}
+
+fn parse_reply(path: &str, parse: fn(&str, &str) -> Result<String, ErrorCode>) -> Result<String, String> {
+    let mut reply = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut reply))
+        .map_err(|error| format!("Failed to read {}: {}", path, error))?;
+
+    parse(PAYMENT_METHOD_NAME, &reply).map_err(|ec| format!("{:?}", ec))
+}
+
+
+#[cfg(test)]
+mod sovtoken_cli_tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn options_before_the_command() {
+        let args = parse_args(argv(&["--wallet", "ops", "mint", "pay:sov:1", "10"])).unwrap();
+        assert_eq!(Args {
+            wallet: Some(String::from("ops")),
+            wallet_key: DEFAULT_WALLET_KEY.to_string(),
+            did_seed: None,
+            command: String::from("mint"),
+            args: argv(&["pay:sov:1", "10"]),
+        }, args);
+    }
+
+    #[test]
+    fn option_without_value() {
+        assert!(parse_args(argv(&["--wallet"])).is_err());
+        assert!(parse_args(argv(&["--verbose", "address"])).is_err());
+        assert!(parse_args(argv(&[])).is_err());
+    }
+
+    #[test]
+    fn wrong_arguments() {
+        let args = parse_args(argv(&["mint", "pay:sov:1"])).unwrap();
+        assert!(run(&args).unwrap_err().contains("wrong arguments"));
+    }
+}
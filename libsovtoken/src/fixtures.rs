@@ -0,0 +1,358 @@
+/*!
+    Fixtures for the test suites of the wrapper SDKs and applications.
+
+    Only compiled with the `fixtures` feature. Everything here is built
+    from numbered seeds, so the same index always gives the same key,
+    address, TXO and signature, in the format of this version of the
+    library. A test suite can generate its json here instead of embedding
+    strings which drift from the format.
+
+    ```
+        use sovtoken::fixtures;
+
+        let address = fixtures::address(1);
+        assert_eq!(address, fixtures::address(1));
+        assert!(address.starts_with("pay:sov:"));
+    ```
+
+    Test suites of other languages get the same fixtures as json with
+    `sovtoken_get_fixture_json`, see [`fixture_json`].
+
+    **The keys are public, never use them outside of tests.**
+
+    [`fixture_json`]: fn.fixture_json.html
+*/
+
+use std::sync::{Arc, Mutex};
+
+use indy::{IndyHandle, ErrorCode};
+use serde_json;
+use sodiumoxide::crypto::sign::{self, keypair_from_seed, Seed, PublicKey, SecretKey};
+
+use logic::address;
+use logic::config::payment_address_config::PaymentAddressConfig;
+use logic::config::set_fees_config::SetFeesMap;
+use logic::indy_sdk_api::crypto_api::CryptoAPI;
+use logic::input::Input;
+use logic::output::Output;
+use logic::parsers::common::TXO;
+use logic::request_builder::PaymentRequestBuilder;
+use logic::type_aliases::{ReqId, TokenAmount, TxnSeqNo};
+use utils::base58::{serialize_bytes, IntoBase58};
+use utils::constants::txn_types::{ATTRIB, GET_FEES, GET_UTXO, NYM, XFER_PUBLIC};
+
+/**
+    The req_id of the requests and replies built here.
+*/
+pub const FIXTURE_REQ_ID: ReqId = 1_000_000;
+
+/**
+    The did the replies built here are addressed to.
+*/
+pub const FIXTURE_DID: &str = "6ouriXMZkLeHsuXrN1X1fd";
+
+/**
+    The seed of the key with `index`, 32 bytes ending with `Fixture` and the index.
+*/
+pub fn seed(index: u8) -> String {
+    format!("{:0>32}", format!("Fixture{:03}", index))
+}
+
+/**
+    The base58 verkey of the key with `index`.
+*/
+pub fn verkey(index: u8) -> String {
+    let (public_key, _) = keypair(index);
+    public_key.0.into_base58()
+}
+
+/**
+    The qualified payment address of the key with `index`.
+*/
+pub fn address(index: u8) -> String {
+    address::qualified_address_from_verkey(&verkey(index))
+        .expect("the verkey of a fixture is valid")
+}
+
+/**
+    The `txo:sov:` string of the output `seq_no` of the address with `index`.
+*/
+pub fn txo(index: u8, seq_no: TxnSeqNo) -> String {
+    TXO::new(&address(index), seq_no).to_string()
+}
+
+/**
+    The reply of the ledger to a GET_UTXO request for the address with
+    `index`, with an output for each `(seq_no, amount)`. The reply has no
+    state proof.
+*/
+pub fn get_utxo_reply(index: u8, outputs: &[(TxnSeqNo, TokenAmount)]) -> String {
+    let unqualified = address::strip_qualifier_from_address(&address(index));
+    let outputs: Vec<_> = outputs.iter()
+        .map(|&(seq_no, amount)| json!([unqualified, seq_no, amount]))
+        .collect();
+
+    json!({
+        "op": "REPLY",
+        "protocol_version": 2,
+        "result": {
+            "type": GET_UTXO,
+            "address": unqualified,
+            "identifier": FIXTURE_DID,
+            "reqId": FIXTURE_REQ_ID,
+            "outputs": outputs,
+        }
+    }).to_string()
+}
+
+/**
+    A fee schedule: 1 token for a NYM, 2 for an ATTRIB and 3 for a transfer.
+*/
+pub fn fee_schedule() -> SetFeesMap {
+    let mut fees = SetFeesMap::new();
+    fees.insert(NYM.to_string(), 1);
+    fees.insert(ATTRIB.to_string(), 2);
+    fees.insert(XFER_PUBLIC.to_string(), 3);
+    fees
+}
+
+/**
+    The reply of the ledger to a GET_FEES request, with `fees`. The reply
+    has no state proof.
+*/
+pub fn get_fees_reply(fees: &SetFeesMap) -> String {
+    json!({
+        "op": "REPLY",
+        "protocol_version": 2,
+        "result": {
+            "type": GET_FEES,
+            "identifier": FIXTURE_DID,
+            "reqId": FIXTURE_REQ_ID,
+            "fees": fees,
+        }
+    }).to_string()
+}
+
+/**
+    A signed XFER_PUBLIC request spending the `(index, seq_no)` inputs to
+    the `(index, amount)` outputs, as [`build_payment_req_handler`] returns
+    it.
+
+    The signatures are made with the fixture keys, so they verify, and the
+    req_id is [`FIXTURE_REQ_ID`].
+
+    [`build_payment_req_handler`]: ../api/fn.build_payment_req_handler.html
+    [`FIXTURE_REQ_ID`]: constant.FIXTURE_REQ_ID.html
+*/
+pub fn signed_payment_request(
+    inputs: &[(u8, TxnSeqNo)],
+    outputs: &[(u8, TokenAmount)]
+) -> Result<String, ErrorCode> {
+    let mut builder = PaymentRequestBuilder::new();
+    for &(index, seq_no) in inputs {
+        builder = builder.input(Input::new(address(index), seq_no));
+    }
+    for &(index, amount) in outputs {
+        builder = builder.output(Output::new(address(index), amount));
+    }
+
+    let signed = Arc::new(Mutex::new(None));
+    let signed_clone = Arc::clone(&signed);
+    builder.sign(&FixtureCrypto {}, 1, Box::new(move |request| {
+        *signed_clone.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(request);
+    }))?;
+
+    let signed = signed.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take();
+    let mut request = signed.ok_or(ErrorCode::CommonInvalidState)??;
+    request.req_id = FIXTURE_REQ_ID;
+    request.serialize_to_string().map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState))
+}
+
+/**
+    A [`CryptoAPI`] holding the fixture keys instead of a wallet.
+
+    Signs synchronously with the key whose verkey is given, and creates the
+    key with the index given as the seed of the config, e.g. `"7"`.
+
+    [`CryptoAPI`]: ../logic/indy_sdk_api/crypto_api/trait.CryptoAPI.html
+*/
+pub struct FixtureCrypto {}
+
+impl CryptoAPI for FixtureCrypto {
+    fn indy_create_key(&self, _: IndyHandle, config: PaymentAddressConfig) -> Result<String, ErrorCode> {
+        config.seed.parse::<u8>()
+            .map(verkey)
+            .or(Err(ErrorCode::CommonInvalidStructure))
+    }
+
+    fn indy_create_key_async<F: 'static>(&self, wallet_id: i32, config: PaymentAddressConfig, mut closure: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+        match self.indy_create_key(wallet_id, config) {
+            Ok(verkey) => closure(ErrorCode::Success, verkey),
+            Err(ec) => closure(ec, String::new()),
+        }
+        ErrorCode::Success
+    }
+
+    fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _: IndyHandle, verkey: String, message: &[u8], mut cb: F) -> ErrorCode {
+        match secret_key(&verkey) {
+            Some(secret_key) => {
+                cb(Ok(serialize_bytes(&sign::sign_detached(message, &secret_key).0)));
+                ErrorCode::Success
+            }
+            None => ErrorCode::WalletItemNotFound,
+        }
+    }
+}
+
+/**
+    The json of the fixture `kind`, built with the parameters of
+    `params_json`, for the test suites which can't call this module.
+
+    ```text
+    kind                        params_json                                 json
+    "seed"                      {"index": <int>}                            the seed, as a json string
+    "verkey"                    {"index": <int>}                            the verkey, as a json string
+    "address"                   {"index": <int>}                            the address, as a json string
+    "txo"                       {"index": <int>, "seqNo": <int>}            the txo, as a json string
+    "get_utxo_reply"            {"index": <int>, "utxos": [[<seqNo>, <amount>], ...]}
+    "fee_schedule"              {}                                          {<txn type>: <fee>, ...}
+    "get_fees_reply"            {"fees": {<txn type>: <fee>, ...}}          the fee schedule when fees is missing
+    "signed_payment_request"    {"inputs": [[<index>, <seqNo>], ...], "outputs": [[<index>, <amount>], ...]}
+    ```
+
+    Missing parameters are 0 or empty. Fails with `CommonInvalidStructure`
+    for an unknown kind or parameter.
+
+    ```
+        use sovtoken::fixtures;
+
+        let address = fixtures::fixture_json("address", r#"{"index": 1}"#).unwrap();
+        assert_eq!(format!("{:?}", fixtures::address(1)), address);
+    ```
+*/
+pub fn fixture_json(kind: &str, params_json: &str) -> Result<String, ErrorCode> {
+    let params: FixtureParams = serde_json::from_str(params_json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    let fixture = match kind {
+        "seed" => json!(seed(params.index)),
+        "verkey" => json!(verkey(params.index)),
+        "address" => json!(address(params.index)),
+        "txo" => json!(txo(params.index, params.seq_no)),
+        "get_utxo_reply" => return Ok(get_utxo_reply(params.index, &params.utxos)),
+        "fee_schedule" => json!(fee_schedule()),
+        "get_fees_reply" => return Ok(get_fees_reply(&params.fees.unwrap_or_else(fee_schedule))),
+        "signed_payment_request" => return signed_payment_request(&params.inputs, &params.outputs),
+        _ => {
+            error!("Unknown fixture {:?}", kind);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+    };
+    Ok(fixture.to_string())
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+struct FixtureParams {
+    index: u8,
+    seq_no: TxnSeqNo,
+    utxos: Vec<(TxnSeqNo, TokenAmount)>,
+    fees: Option<SetFeesMap>,
+    inputs: Vec<(u8, TxnSeqNo)>,
+    outputs: Vec<(u8, TokenAmount)>,
+}
+
+fn keypair(index: u8) -> (PublicKey, SecretKey) {
+    let seed = Seed::from_slice(seed(index).as_bytes()).expect("a fixture seed is 32 bytes");
+    keypair_from_seed(&seed)
+}
+
+fn secret_key(verkey: &str) -> Option<SecretKey> {
+    (0..256u16)
+        .map(|index| keypair(index as u8))
+        .find(|&(ref public_key, _)| public_key.0.into_base58() == verkey)
+        .map(|(_, secret_key)| secret_key)
+}
+
+
+#[cfg(test)]
+mod fixtures_tests {
+    use serde_json::Value;
+
+    use super::*;
+    use logic::parsers::parse_get_txn_fees::ParseGetTxnFeesResponse;
+    use logic::parsers::parse_get_utxo_response::{from_response, ParseGetUtxoResponse};
+    use utils::base58::FromBase58;
+    use utils::json_conversion::JsonDeserialize;
+
+    #[test]
+    fn seeds_are_deterministic() {
+        assert_eq!("0000000000000000000000Fixture007", seed(7));
+        assert_eq!(verkey(7), verkey(7));
+        assert_ne!(address(1), address(2));
+        assert!(address::validate_address(&address(255)).is_ok());
+    }
+
+    #[test]
+    fn txo_round_trip() {
+        let txo = TXO::parse(&txo(3, 12)).unwrap();
+        assert_eq!(address(3), txo.address());
+        assert_eq!(12, txo.seq_no());
+    }
+
+    #[test]
+    fn get_utxo_reply_parses() {
+        let reply = ParseGetUtxoResponse::from_json(&get_utxo_reply(1, &[(1, 10), (2, 20)])).unwrap();
+        let utxos = from_response(reply).unwrap();
+        assert_eq!(2, utxos.len());
+        assert_eq!(address(1), utxos[0].payment_address);
+        assert_eq!(txo(1, 2), utxos[1].source);
+    }
+
+    #[test]
+    fn get_fees_reply_parses() {
+        let reply = ParseGetTxnFeesResponse::from_json(&get_fees_reply(&fee_schedule())).unwrap();
+        assert_eq!(fee_schedule(), reply.result.fees);
+    }
+
+    #[test]
+    fn signed_payment_request_verifies() {
+        let request = signed_payment_request(&[(1, 1)], &[(2, 10)]).unwrap();
+        assert_eq!(request, signed_payment_request(&[(1, 1)], &[(2, 10)]).unwrap());
+
+        let request: Value = ::serde_json::from_str(&request).unwrap();
+        assert_eq!(json!(FIXTURE_REQ_ID), request["reqId"]);
+
+        let signature = request["operation"]["signatures"][0].as_str().unwrap().from_base58().unwrap();
+        assert_eq!(64, signature.len());
+    }
+
+    #[test]
+    fn fixture_json_of_a_kind() {
+        assert_eq!(json!(txo(2, 5)).to_string(), fixture_json("txo", r#"{"index": 2, "seqNo": 5}"#).unwrap());
+        assert_eq!(get_utxo_reply(1, &[(1, 10)]), fixture_json("get_utxo_reply", r#"{"index": 1, "utxos": [[1, 10]]}"#).unwrap());
+        assert_eq!(get_fees_reply(&fee_schedule()), fixture_json("get_fees_reply", "{}").unwrap());
+        assert_eq!(
+            signed_payment_request(&[(1, 1)], &[(2, 10)]).unwrap(),
+            fixture_json("signed_payment_request", r#"{"inputs": [[1, 1]], "outputs": [[2, 10]]}"#).unwrap()
+        );
+    }
+
+    #[test]
+    fn fixture_json_of_unknown_kind_or_param() {
+        assert_eq!(ErrorCode::CommonInvalidStructure, fixture_json("wallet", "{}").unwrap_err());
+        assert_eq!(ErrorCode::CommonInvalidStructure, fixture_json("address", r#"{"idx": 1}"#).unwrap_err());
+    }
+
+    #[test]
+    fn unknown_key_is_not_signed() {
+        let ec = FixtureCrypto {}.indy_crypto_sign(1, String::from("unknown"), b"message", |_| ());
+        assert_eq!(ErrorCode::WalletItemNotFound, ec);
+    }
+}
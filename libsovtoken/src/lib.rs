@@ -8,16 +8,21 @@
 // crates from crate.io etc
 // ------------------------------------------
 extern crate base64;
+extern crate brotli;
 extern crate bs58;
 extern crate env_logger;
+extern crate flate2;
 extern crate hex;
 extern crate libc;
 extern crate log_panics;
 extern crate openssl;
 extern crate rand;
+extern crate rmp_serde;
 extern crate serde;
 extern crate sodiumoxide;
 extern crate sha2;
+extern crate sha3;
+extern crate toml;
 
 
 // ------------------------------------------
@@ -28,9 +33,11 @@ extern crate sha2;
 #[macro_use] extern crate serde_derive;
 #[macro_use] extern crate serde_json;
 
-#[cfg(any(test, feature = "integration"))]
 #[macro_use] extern crate lazy_static;
 
+#[cfg(test)]
+#[macro_use] extern crate proptest;
+
 // ------------------------------------------
 // android crates
 // ------------------------------------------
@@ -55,3 +62,9 @@ pub mod utils;
 pub mod api;
 pub mod logic;
 pub mod libraries;
+
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
+
+// stable api for wrapper SDKs, see the docs of TXO
+pub use logic::parsers::common::{TXO, TxoParseError};
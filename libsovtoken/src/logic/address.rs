@@ -7,23 +7,50 @@
     e.g. `"pay:sov:"`
 
     ### unqualified address
-    `<verkey><checksum>`
+    `<verkey><checksum>`, the checksum is computed as set in the library
+    config, see [`address_checksum`].
     e.g. `"WqXg36yxheP7wzUZnhnkUY6Qeaib5uyUZuyaujr7atPHRH3d2"`
 
     ### qualified address
     `<qualifier><verkey><checksum>`
     e.g. `"pay:sov:WqXg36yxheP7wzUZnhnkUY6Qeaib5uyUZuyaujr7atPHRH3d2"`
 
+    ### network tag
+    The ledger network a qualified address belongs to, between the qualifier
+    and the unqualified address, `<qualifier><network>:<verkey><checksum>`.
+    e.g. `"pay:sov:mainnet:WqXg36yxheP7wzUZnhnkUY6Qeaib5uyUZuyaujr7atPHRH3d2"`
+
+    The addresses are tagged with `network` of the library config when it
+    is set, and so are the TXOs, which embed their address. The tag never
+    reaches the ledger, requests carry unqualified addresses.
+
+    Addresses without a tag are valid on every network. A tagged address
+    of another network than the configured one is rejected wherever an
+    address is validated, so an input or output of a request can't be of
+    another network.
+
+    ### key type
+    The verkeys are Ed25519 keys. Addresses of other key types carry a
+    version byte before the key, see [`address_version`]; they parse and
+    format like the others, but can't be signed for.
+
+    [`address_checksum`]: ../address_checksum/index.html
+    [`address_version`]: ../address_version/index.html
 */
 
 use serde_json;
-use std::{io, str};
+use std::str;
 
 use indy::ErrorCode;
-use logic::parsers::common::TXO;
+use logic::address_checksum;
+use logic::address_version::VersionedKey;
+use logic::config::library_config::library_config;
+use logic::error::SovtokenError;
+use logic::messages;
+use logic::parsers::common::{TXO, TxoParseError};
 use utils::json_conversion::{JsonDeserialize, JsonSerialize};
 use utils::constants::general::{PAYMENT_ADDRESS_QUALIFIER, TXO_QUALIFIER};
-use utils::base58::{IntoBase58, FromBase58};
+use utils::base58::{self, Base58Error, Checksum, BASE58_ALPHABET};
 
 // Following lengths are in bytes
 pub const VERKEY_LEN: usize = 32;
@@ -37,8 +64,17 @@ pub const ADDRESS_CHECKSUM_LEN: usize = 4;
 
 pub const ADDRESS_LEN: usize = VERKEY_LEN + ADDRESS_CHECKSUM_LEN + ADDRESS_QUAL_LEN;
 
+// The start of the payment addresses of every payment method.
+const PAYMENT_ADDRESS_PREFIX: &str = "pay:";
+
+// Ends the network tag of a qualified address, base58 has no ':'.
+const NETWORK_TAG_SEPARATOR: char = ':';
+
+// The only version of the TXO json. A TXO json without "ver" is of this version.
+const TXO_VERSION: u64 = 1;
+
 /**
-    Removes the "pay:sov:" from an address.
+    Removes the "pay:sov:" and the network tag from an address.
     Leaves the verkey with the checksum.
 
     ```
@@ -67,18 +103,18 @@ pub fn unqualified_address_from_address(fq_address: &str) -> Result<String, Erro
     ```
 */
 pub fn unqualified_address_from_verkey(verkey: &str) -> Result<String, ErrorCode> {
-    let vk_bytes = verkey.from_base58()
-        .or(Err(ErrorCode::CommonInvalidStructure))?;
-
-    if vk_bytes.len() != VERKEY_LEN {
-        return Err(ErrorCode::CommonInvalidStructure);
-    }
-    Ok(vk_bytes.into_base58_check())
+    let vk_bytes = base58::decode_exact(verkey, Checksum::None, VERKEY_LEN)?;
+    Ok(address_checksum::encode_address(&vk_bytes, address_checksum::configured()))
 }
 
 /**
     Form a qualified address from a unqualified one.
 
+    The address can be of any key type, one of a version unknown to the
+    library is only checked for its checksum, see [`address_version`].
+
+    [`address_version`]: ../address_version/index.html
+
     ```
     use sovtoken::logic::address::address_from_unqualified_address;
     let verkey = "2Viu9qrpqM48PSw3vdoQoFKP5AvYTChUZhwWtCydfW9iu7ftRt";
@@ -89,8 +125,8 @@ pub fn unqualified_address_from_verkey(verkey: &str) -> Result<String, ErrorCode
     ```
 */
 pub fn address_from_unqualified_address(unqual_address: &str) -> Result<String, ErrorCode> {
-    verkey_from_unqualified_address(unqual_address)?;
-    Ok(format!("{}{}", PAYMENT_ADDRESS_QUALIFIER, unqual_address))
+    VersionedKey::decode(unqual_address, address_checksum::configured())?;
+    Ok(qualify(unqual_address, configured_network()))
 }
 
 /**
@@ -107,14 +143,18 @@ pub fn address_from_unqualified_address(unqual_address: &str) -> Result<String,
 */
 pub fn qualified_address_from_verkey(verkey: &str) -> Result<String, ErrorCode> {
     let address = unqualified_address_from_verkey(verkey)?;
-    return Ok(format!("{}{}", PAYMENT_ADDRESS_QUALIFIER, address));
+    return Ok(qualify(&address, configured_network()));
 }
 
 
 /**
     `validate_address` checks that a qualified address is formatted
-    as `pay:sov:<verkey><checksum>` and the verkey is valid. Returns
-    the verkey.
+    as `pay:sov:<verkey><checksum>`, or `pay:sov:<network>:<verkey><checksum>`,
+    and the verkey is valid. Returns the verkey.
+
+    An address of another payment method, `pay:<method>:...`, or tagged with
+    another network than the configured one, fails with
+    `PaymentIncompatibleMethodsError`.
    
     ```
     use sovtoken::logic::address::validate_address;
@@ -126,32 +166,39 @@ pub fn qualified_address_from_verkey(verkey: &str) -> Result<String, ErrorCode>
 
 
 pub fn validate_address(fully_qualified_address: &str) -> Result<String, ErrorCode> {
+    if fully_qualified_address.starts_with(PAYMENT_ADDRESS_PREFIX) && !fully_qualified_address.starts_with(&PAYMENT_ADDRESS_QUALIFIER) {
+        let error = SovtokenError::IncompatiblePaymentMethod(messages::OTHER_PAYMENT_METHOD.with(&[fully_qualified_address.to_string(), PAYMENT_ADDRESS_QUALIFIER.to_string()]));
+        return Err(ErrorCode::from(error));
+    }
+
     if !fully_qualified_address.starts_with(&PAYMENT_ADDRESS_QUALIFIER) {
         error!("Payment address should start with a correct qualifier {}", PAYMENT_ADDRESS_QUALIFIER);
         return Err(ErrorCode::CommonInvalidStructure);
     }
 
+    check_network(fully_qualified_address, configured_network())?;
+
     let address = strip_qualifier_from_address(&fully_qualified_address);
     verkey_from_unqualified_address(&address)
 }
 
+/**
+    The verkey of an unqualified address.
+
+    An address of another key type than Ed25519, see [`address_version`],
+    fails with `PaymentOperationNotSupportedError`.
+
+    [`address_version`]: ../address_version/index.html
+*/
 pub fn verkey_from_unqualified_address(unqualified_address: &str) -> Result<String, ErrorCode> {
-    match unqualified_address.from_base58_check().map_err(map_err_err!()) {
-        Ok(vk) => {
-            if vk.len() != VERKEY_LEN {
-                error!("Incorrect verkey length, expected {:?}, real {:?}", VERKEY_LEN, vk.len());
-                return Err(ErrorCode::CommonInvalidStructure)
-            } else {
-                return Ok(vk.into_base58());
-            }
-        },
-        Err(_) => return Err(ErrorCode::CommonInvalidStructure)
-    }
+    VersionedKey::decode(unqualified_address, address_checksum::configured())?.verkey()
 }
 
 /**
     `string_to_txo` checks that the string is formatted as `txo:sov:<base58-encoded json>` and parses it to TXO struct.
-    Returns TXO.
+    Returns TXO, or a [`TxoParseError`] telling what is wrong with the string.
+
+    [`TxoParseError`]: ../parsers/common/enum.TxoParseError.html
 
     ```
     use sovtoken::logic::parsers::common::TXO;
@@ -161,15 +208,109 @@ pub fn verkey_from_unqualified_address(unqualified_address: &str) -> Result<Stri
     assert_eq!(TXO {address:"pay:sov:iTQzpdRdugkJ2gLD5vW5c159dncSL9jbAtu3WfPcb8qWD9bUd".to_string(), seq_no: 1}, result_txo);
     ```
 */
-pub fn string_to_txo(txo_str: &str) -> Result<TXO, serde_json::Error> {
+pub fn string_to_txo(txo_str: &str) -> Result<TXO, TxoParseError> {
     if !txo_str.starts_with(TXO_QUALIFIER) {
-        return Err(serde_json::Error::io(io::ErrorKind::InvalidInput.into()));
+        let start = txo_str.chars().take(TXO_QUALIFIER.len()).collect();
+        return Err(TxoParseError::BadPrefix(start));
     }
-    let json_u8 = (&txo_str[TXO_QUALIFIER.len()..]).from_base58_check()
-        .map_err(|_| serde_json::Error::io(io::ErrorKind::InvalidInput.into()))?;
+
+    let json_u8 = base58::decode(&txo_str[TXO_QUALIFIER.len()..], Checksum::DoubleSha256)
+        .map_err(|error| match error {
+            Base58Error::BadChecksum => TxoParseError::BadChecksum(error.to_string()),
+            Base58Error::MissingChecksum => TxoParseError::Truncated(error.to_string()),
+            error => TxoParseError::BadBase58(error.to_string()),
+        })?;
     let json = str::from_utf8(&json_u8)
-        .map_err(|_| serde_json::Error::io(io::ErrorKind::InvalidInput.into()))?;
-    TXO::from_json(json)
+        .map_err(|error| TxoParseError::BadJson(error.to_string()))?;
+
+    let value: serde_json::Value = serde_json::from_str(json)
+        .map_err(|error| TxoParseError::BadJson(error.to_string()))?;
+    match value.get("ver") {
+        None => (),
+        Some(version) if version == &json!(TXO_VERSION) || version == &json!(TXO_VERSION.to_string()) => (),
+        Some(version) => return Err(TxoParseError::UnsupportedVersion(version.to_string())),
+    }
+
+    TXO::from_json(json).map_err(|error| TxoParseError::BadJson(error.to_string()))
+}
+
+/**
+    The longest string [`repair_txo`] tries to repair.
+
+    [`repair_txo`]: fn.repair_txo.html
+*/
+pub const MAX_REPAIRED_TXO_LEN: usize = 160;
+
+/**
+    The nearest valid `txo:sov:` string of a TXO string mangled when it was
+    copied, `None` when no repair makes it valid.
+
+    The repairs tried, in order: trimming the whitespace and quotes around
+    it, restoring its qualifier, adding one character cut off at its end,
+    dropping one character and replacing one character. A repair is only
+    suggested, the checksum of the TXO is what tells it is right, so the
+    suggestion should be confirmed by the user.
+
+    Every candidate is decoded, up to about 9000 for the longest string, so
+    it is only called when asked for, not when a txo fails to parse.
+
+    ```
+    use sovtoken::logic::address::repair_txo;
+    let txo_str = "txo:sov:fkjZEd8eTBnYJsw7m7twMph3UYD7j2SoWcDM45DkmRx8eq2SkQnzxoLxyMT1RBAat9x86MwXNJH88Pxf9u7JsM5m8ApXn3bvgbtS5cegZzNp7WmMSpWL";
+    assert_eq!(Some(txo_str.to_string()), repair_txo(&txo_str[..txo_str.len() - 1]));
+    ```
+*/
+pub fn repair_txo(txo_str: &str) -> Option<String> {
+    if txo_str.len() > MAX_REPAIRED_TXO_LEN {
+        return None;
+    }
+
+    let trimmed = txo_str.trim_matches(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '<' || c == '>');
+    let body = match trimmed.rfind(':') {
+        Some(colon) => &trimmed[colon + 1..],
+        None => trimmed,
+    };
+    let candidate = format!("{}{}", TXO_QUALIFIER, body);
+    if string_to_txo(&candidate).is_ok() {
+        return Some(candidate);
+    }
+
+    let alphabet: Vec<char> = BASE58_ALPHABET.chars().collect();
+    let body: Vec<char> = body.chars().collect();
+    let is_txo = |chars: &[char]| {
+        let candidate = format!("{}{}", TXO_QUALIFIER, chars.iter().collect::<String>());
+        if string_to_txo(&candidate).is_ok() { Some(candidate) } else { None }
+    };
+
+    let mut candidate = body.clone();
+    candidate.push(' ');
+    for &c in &alphabet {
+        candidate[body.len()] = c;
+        if let Some(txo) = is_txo(&candidate) {
+            return Some(txo);
+        }
+    }
+
+    for i in 0..body.len() {
+        let mut candidate = body.clone();
+        candidate.remove(i);
+        if let Some(txo) = is_txo(&candidate) {
+            return Some(txo);
+        }
+    }
+
+    let mut candidate = body.clone();
+    for i in 0..body.len() {
+        for &c in alphabet.iter().filter(|&&c| c != body[i]) {
+            candidate[i] = c;
+            if let Some(txo) = is_txo(&candidate) {
+                return Some(txo);
+            }
+        }
+        candidate[i] = body[i];
+    }
+
+    None
 }
 
 /**
@@ -185,14 +326,15 @@ pub fn string_to_txo(txo_str: &str) -> Result<TXO, serde_json::Error> {
     ```
 */
 pub fn txo_to_string(txo: &TXO) ->  Result<String, ErrorCode> {
-    let temp = txo.to_json()
-        .map_err(|_| ErrorCode::CommonInvalidState)?
-        .as_bytes().into_base58_check();
+    let json = txo.to_json()
+        .map_err(|_| ErrorCode::CommonInvalidState)?;
+    let temp = base58::encode(json.as_bytes(), Checksum::DoubleSha256);
     Ok(TXO_QUALIFIER.to_string() + &temp)
 }
 
 /**
-    takes an "address" and returns "pay:sov" plus address.
+    takes an "address" and returns "pay:sov" plus address, with the network
+    tag when a network is configured.
     there is no validation that the address is valid
 
     ```
@@ -204,15 +346,55 @@ pub fn txo_to_string(txo: &TXO) ->  Result<String, ErrorCode> {
 
 */
 pub fn add_qualifer_to_address(address : &str) -> String {
-    return format!("{}{}", PAYMENT_ADDRESS_QUALIFIER, address);
+    return qualify(address, configured_network());
 }
 
 /**
     Takes a fully qualified address and returns the unqualified address.
-    Unqualified address is <verkey><checksum> without the "pay:sov"
+    Unqualified address is <verkey><checksum> without the "pay:sov" and
+    the network tag.
 */
 pub fn strip_qualifier_from_address(address : &str) -> String {
-    return address[ADDRESS_QUAL_LEN..].to_string();
+    return split_network_tag(&address[ADDRESS_QUAL_LEN..]).1.to_string();
+}
+
+/**
+    The unqualified part of an address, qualified or not.
+
+    Addresses are compared by this, so the same address with and without
+    its qualifier or network tag is one address.
+
+    ```
+    use sovtoken::logic::address::unqualified_part;
+    let address = "WqXg36yxheP7wzUZnhnkUY6Qeaib5uyUZuyaujr7atPHRH3d2";
+    assert_eq!(address, unqualified_part("pay:sov:mainnet:WqXg36yxheP7wzUZnhnkUY6Qeaib5uyUZuyaujr7atPHRH3d2"));
+    assert_eq!(address, unqualified_part("pay:sov:WqXg36yxheP7wzUZnhnkUY6Qeaib5uyUZuyaujr7atPHRH3d2"));
+    assert_eq!(address, unqualified_part(address));
+    ```
+*/
+pub fn unqualified_part(address: &str) -> &str {
+    if address.starts_with(PAYMENT_ADDRESS_QUALIFIER) {
+        split_network_tag(&address[PAYMENT_ADDRESS_QUALIFIER.len()..]).1
+    } else {
+        address
+    }
+}
+
+/**
+    The network tag of a qualified address, `None` when it has none.
+
+    ```
+    use sovtoken::logic::address::network_of_address;
+    assert_eq!(Some("mainnet"), network_of_address("pay:sov:mainnet:WqXg36yxheP7wzUZnhnkUY6Qeaib5uyUZuyaujr7atPHRH3d2"));
+    assert_eq!(None, network_of_address("pay:sov:WqXg36yxheP7wzUZnhnkUY6Qeaib5uyUZuyaujr7atPHRH3d2"));
+    ```
+*/
+pub fn network_of_address(address: &str) -> Option<&str> {
+    if address.starts_with(PAYMENT_ADDRESS_QUALIFIER) {
+        split_network_tag(&address[PAYMENT_ADDRESS_QUALIFIER.len()..]).0
+    } else {
+        None
+    }
 }
 
 /*
@@ -221,10 +403,54 @@ pub fn strip_qualifier_from_address(address : &str) -> String {
     KEEP all public methods above
 */
 
+fn configured_network() -> Option<String> {
+    library_config().network
+}
+
+fn qualify(unqualified_address: &str, network: Option<String>) -> String {
+    match network {
+        Some(network) => format!("{}{}{}{}", PAYMENT_ADDRESS_QUALIFIER, network, NETWORK_TAG_SEPARATOR, unqualified_address),
+        None => format!("{}{}", PAYMENT_ADDRESS_QUALIFIER, unqualified_address),
+    }
+}
+
+fn split_network_tag(address: &str) -> (Option<&str>, &str) {
+    match address.rfind(NETWORK_TAG_SEPARATOR) {
+        Some(index) => (Some(&address[..index]), &address[index + 1..]),
+        None => (None, address),
+    }
+}
+
+fn is_valid_network(network: &str) -> bool {
+    !network.is_empty()
+        && network.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+fn check_network(fully_qualified_address: &str, configured: Option<String>) -> Result<(), ErrorCode> {
+    let network = match network_of_address(fully_qualified_address) {
+        Some(network) => network,
+        None => return Ok(()),
+    };
+
+    if !is_valid_network(network) {
+        error!("Invalid network tag {:?} of the payment address {}", network, fully_qualified_address);
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    match configured {
+        Some(ref configured) if configured != network => {
+            let error = SovtokenError::IncompatiblePaymentMethod(messages::OTHER_NETWORK.with(&[fully_qualified_address.to_string(), network.to_string(), configured.to_string()]));
+            Err(ErrorCode::from(error))
+        },
+        _ => Ok(()),
+    }
+}
+
 
 
 #[cfg(test)]
 pub mod address_tests {
+    use utils::base58::{IntoBase58, FromBase58};
     use utils::random::rand_bytes;
     use utils::constants::general::PAYMENT_ADDRESS_QUALIFIER;
 
@@ -288,6 +514,13 @@ pub mod address_tests {
         assert_eq!(ErrorCode::CommonInvalidStructure, error);
     }
 
+    #[test]
+    fn test_address_of_other_payment_method() {
+        let address = gen_random_base58_address();
+        let error = validate_address(&format!("pay:xyz:{}", address)).unwrap_err();
+        assert_eq!(ErrorCode::PaymentIncompatibleMethodsError, error);
+    }
+
     #[test]
     fn test_verkey_from_qualified_address() {
         let verkey = gen_random_base58_verkey();
@@ -375,4 +608,143 @@ pub mod address_tests {
             assert_eq!(validate_address(&fa).unwrap(), verkeys[i])
         }
     }
+
+    #[test]
+    fn test_tagged_address() {
+        let address = gen_random_base58_address();
+        let tagged = qualify(&address, Some(String::from("mainnet")));
+
+        assert_eq!(format!("pay:sov:mainnet:{}", address), tagged);
+        assert_eq!(Some("mainnet"), network_of_address(&tagged));
+        assert_eq!(address, strip_qualifier_from_address(&tagged));
+        assert_eq!(address, unqualified_part(&tagged));
+        assert!(validate_address(&tagged).is_ok());
+    }
+
+    #[test]
+    fn test_check_network() {
+        let address = gen_random_base58_address();
+        let mainnet = Some(String::from("mainnet"));
+        let untagged = qualify(&address, None);
+        let tagged = qualify(&address, mainnet.clone());
+
+        assert!(check_network(&untagged, mainnet.clone()).is_ok());
+        assert!(check_network(&tagged, mainnet.clone()).is_ok());
+        assert!(check_network(&tagged, None).is_ok());
+        assert_eq!(ErrorCode::PaymentIncompatibleMethodsError, check_network(&tagged, Some(String::from("staging"))).unwrap_err());
+    }
+
+    #[test]
+    fn test_invalid_network_tag() {
+        let address = gen_random_base58_address();
+        for tag in &["", "main net", "main/net"] {
+            let error = validate_address(&format!("pay:sov:{}:{}", tag, address)).unwrap_err();
+            assert_eq!(ErrorCode::CommonInvalidStructure, error, "{:?}", tag);
+        }
+    }
+
+    #[test]
+    fn test_txo_with_tagged_address() {
+        let address = qualify(&gen_random_base58_address(), Some(String::from("mainnet")));
+        let txo = TXO { address: address.clone(), seq_no: 3 };
+        let parsed = string_to_txo(&txo_to_string(&txo).unwrap()).unwrap();
+
+        assert_eq!(txo, parsed);
+        assert_eq!(Some("mainnet"), network_of_address(&parsed.address));
+    }
+
+    fn txo_from_json(json: serde_json::Value) -> String {
+        format!("{}{}", TXO_QUALIFIER, json.to_string().as_bytes().into_base58_check())
+    }
+
+    #[test]
+    fn string_to_txo_bad_prefix() {
+        let error = string_to_txo("utxo:sov:abc").unwrap_err();
+        assert_eq!(TxoParseError::BadPrefix(String::from("utxo:sov")), error);
+    }
+
+    #[test]
+    fn string_to_txo_bad_base58() {
+        let error = string_to_txo("txo:sov:0OIl").unwrap_err();
+        match error {
+            TxoParseError::BadBase58(_) => (),
+            error => panic!("Unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn string_to_txo_bad_checksum() {
+        let txo = txo_from_json(json!({"address": "pay:sov:a", "seqNo": 1}));
+        let txo = replace_char_at(&txo, txo.len() - 1, if txo.ends_with('2') { '3' } else { '2' });
+        let error = string_to_txo(&txo).unwrap_err();
+        match error {
+            TxoParseError::BadChecksum(_) => (),
+            error => panic!("Unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn string_to_txo_truncated() {
+        match string_to_txo("txo:sov:2").unwrap_err() {
+            TxoParseError::Truncated(_) => (),
+            error => panic!("Unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn repair_copied_txo() {
+        let txo = txo_from_json(json!({"address": "pay:sov:a", "seqNo": 1}));
+        let last = txo.chars().last().unwrap();
+        let other = if last == '2' { '3' } else { '2' };
+
+        assert_eq!(Some(txo.clone()), repair_txo(&txo[..txo.len() - 1]));
+        assert_eq!(Some(txo.clone()), repair_txo(&format!(" \"{}\"\n", txo)));
+        assert_eq!(Some(txo.clone()), repair_txo(&txo[TXO_QUALIFIER.len()..]));
+        assert_eq!(Some(txo.clone()), repair_txo(&format!("{}{}", txo, other)));
+        assert_eq!(Some(txo.clone()), repair_txo(&replace_char_at(&txo, txo.len() - 1, other)));
+    }
+
+    #[test]
+    fn repair_unrepairable_txo() {
+        assert_eq!(None, repair_txo("txo:sov:abc"));
+        assert_eq!(None, repair_txo(&"1".repeat(MAX_REPAIRED_TXO_LEN + 1)));
+    }
+
+    #[test]
+    fn string_to_txo_bad_json() {
+        let error = string_to_txo(&txo_from_json(json!({"address": "pay:sov:a"}))).unwrap_err();
+        match error {
+            TxoParseError::BadJson(ref detail) => assert!(detail.contains("missing field `seqNo`")),
+            ref error => panic!("Unexpected error {:?}", error),
+        }
+        assert!(error.to_string().contains("missing field `seqNo`"));
+    }
+
+    #[test]
+    fn string_to_txo_unsupported_version() {
+        let error = string_to_txo(&txo_from_json(json!({"ver": 2, "address": "pay:sov:a", "seqNo": 1}))).unwrap_err();
+        assert_eq!(TxoParseError::UnsupportedVersion(String::from("2")), error);
+    }
+
+    #[test]
+    fn string_to_txo_known_version() {
+        let txo = string_to_txo(&txo_from_json(json!({"ver": 1, "address": "pay:sov:a", "seqNo": 1}))).unwrap();
+        assert_eq!(TXO { address: String::from("pay:sov:a"), seq_no: 1 }, txo);
+    }
+
+    #[test]
+    fn address_of_an_unknown_key_type() {
+        use logic::address_version::{AddressVersion, VersionedKey};
+
+        let key = VersionedKey::new(AddressVersion::Unknown(7), rand_bytes(64)).unwrap();
+        let unqualified = key.encode(address_checksum::configured());
+
+        let address = address_from_unqualified_address(&unqualified).unwrap();
+        assert_eq!(unqualified, unqualified_part(&address));
+        assert_eq!(ErrorCode::PaymentOperationNotSupportedError, validate_address(&address).unwrap_err());
+
+        // a stored TXO of the address still round trips
+        let txo = TXO { address, seq_no: 3 };
+        assert_eq!(txo, string_to_txo(&txo_to_string(&txo).unwrap()).unwrap());
+    }
 }
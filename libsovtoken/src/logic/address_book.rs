@@ -0,0 +1,431 @@
+/*!
+    Labels, creation time and cached balance of the payment addresses of a wallet.
+
+    libindy only keeps the keys of the payment addresses. What libsovtoken
+    knows about an address on top of that is stored as a non secret record in
+    the wallet, keyed by the address:
+
+    * the label set by the application,
+    * when libsovtoken first saw the address,
+    * the balance last fetched by `get_aggregate_balance_handler`.
+
+    Searching lists the addresses of the wallet with their record and keeps
+    the ones matching an [`AddressFilter`]. The results are paged through a
+    search handle, like the wallet searches of libindy.
+
+    [`AddressFilter`]: struct.AddressFilter.html
+*/
+
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+
+use indy::{IndyHandle, ErrorCode};
+use serde_json;
+
+use logic::address;
+use logic::aggregate_balance::AggregateBalance;
+use logic::compression::{self, Compression};
+use logic::config::library_config::library_config;
+use logic::indy_sdk_api::payment_api::PaymentAPI;
+use logic::indy_sdk_api::wallet_api::WalletAPI;
+use logic::state::state;
+use logic::type_aliases::TokenAmount;
+use utils::json_conversion::JsonDeserialize;
+
+/**
+    The wallet record type the address records are stored under.
+*/
+pub const ADDRESS_RECORD_TYPE: &str = "sovtoken_payment_address";
+
+/**
+    What libsovtoken knows about a payment address.
+
+    `created` is when libsovtoken first saw the address, in seconds since the
+    unix epoch. `balance` is `None` until a balance was fetched.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AddressRecord {
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub created: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance: Option<TokenAmount>,
+}
+
+/**
+    Which addresses a search returns, every field is optional.
+
+    ```
+        use sovtoken::utils::json_conversion::JsonDeserialize;
+        use sovtoken::logic::address_book::AddressFilter;
+
+        let filter = AddressFilter::from_json(r#"{"labelPrefix": "savings", "hasBalance": true}"#).unwrap();
+        assert_eq!(None, filter.created_after);
+    ```
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AddressFilter {
+    /// Only addresses with a label starting with the prefix.
+    pub label_prefix: Option<String>,
+    /// Only addresses first seen after the time, in seconds since the unix epoch.
+    pub created_after: Option<u64>,
+    /// Only addresses with, or without, a positive cached balance.
+    pub has_balance: Option<bool>,
+}
+
+impl AddressFilter {
+    pub fn matches(&self, record: &AddressRecord) -> bool {
+        let label_matches = match (&self.label_prefix, &record.label) {
+            (&Some(ref prefix), &Some(ref label)) => label.starts_with(prefix.as_str()),
+            (&Some(_), &None) => false,
+            (&None, _) => true,
+        };
+
+        let created_matches = self.created_after.map_or(true, |after| record.created > after);
+
+        let balance_matches = self.has_balance.map_or(true, |has_balance| {
+            has_balance == (record.balance.unwrap_or(0) > 0)
+        });
+
+        label_matches && created_matches && balance_matches
+    }
+}
+
+/**
+    AddressBook reads and writes the address records of a wallet.
+
+    The records are compressed as set by `compression` in the library config.
+*/
+pub struct AddressBook<W, P> where W: WalletAPI, P: PaymentAPI {
+    wallet_api: W,
+    payment_api: P,
+    compression: Compression,
+}
+
+impl<W: WalletAPI, P: PaymentAPI> AddressBook<W, P> {
+    pub fn new(wallet_api: W, payment_api: P) -> Self {
+        AddressBook { wallet_api, payment_api, compression: library_config().compression }
+    }
+
+    /**
+        Store the records compressed with `compression`, whatever the library config says.
+    */
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /**
+        Set the label of `address`, an empty label removes it.
+
+        Returns `WalletItemNotFound` when the address isn't one of the wallet.
+    */
+    pub fn set_label(&self, wallet_handle: IndyHandle, address: &str, label: &str, now: u64) -> Result<AddressRecord, ErrorCode> {
+        trace!("logic::address_book::set_label >> address: {:?}, label: {:?}", address, label);
+        if !self.payment_api.indy_list_payment_addresses(wallet_handle)?.iter().any(|known| known == address) {
+            error!("{} is not a payment address of the wallet", address);
+            return Err(ErrorCode::WalletItemNotFound);
+        }
+
+        let mut record = self.record(wallet_handle, address, now)?;
+        record.label = if label.is_empty() { None } else { Some(label.to_string()) };
+        self.update(wallet_handle, &record)?;
+        Ok(record)
+    }
+
+    /**
+        Cache the balances fetched by the aggregator.
+
+        Only the addresses of `wallet_handle` are cached.
+    */
+    pub fn cache_balances(&self, wallet_handle: IndyHandle, balance: &AggregateBalance, now: u64) -> Result<(), ErrorCode> {
+        let addresses = balance.wallets.iter()
+            .filter(|wallet| wallet.wallet_handle == wallet_handle)
+            .flat_map(|wallet| wallet.addresses.iter());
+
+        for address in addresses {
+            let mut record = self.record(wallet_handle, &address.address, now)?;
+            record.balance = Some(address.balance);
+            self.update(wallet_handle, &record)?;
+        }
+        Ok(())
+    }
+
+    /**
+        The records of the addresses of the wallet matching `filter`.
+
+        Addresses without a record yet get one, first seen `now`. The records
+        are ordered by creation time, then by address.
+
+        The records are all read before any is added or migrated, the wallet
+        search is closed by then.
+    */
+    pub fn search(&self, wallet_handle: IndyHandle, filter: &AddressFilter, now: u64) -> Result<Vec<AddressRecord>, ErrorCode> {
+        trace!("logic::address_book::search >> filter: {:?}", filter);
+        let values = self.wallet_api.indy_list_records(wallet_handle, ADDRESS_RECORD_TYPE)?;
+        let mut stored = values.iter()
+            .map(|&(ref address, ref value)| read_record_value(value).map(|record| (address.clone(), record)))
+            .collect::<Result<HashMap<String, AddressRecord>, ErrorCode>>()?;
+        self.migrate(wallet_handle, &values, &stored);
+
+        let mut records = Vec::new();
+        for address in self.payment_api.indy_list_payment_addresses(wallet_handle)? {
+            let record = match stored.remove(&address) {
+                Some(record) => record,
+                None => self.record(wallet_handle, &address, now)?,
+            };
+            if filter.matches(&record) {
+                records.push(record);
+            }
+        }
+
+        records.sort_by(|a, b| (a.created, &a.address).cmp(&(b.created, &b.address)));
+        trace!("logic::address_book::search << records: {:?}", records.len());
+        Ok(records)
+    }
+
+    /**
+        The labels of the addresses of the wallet, by unqualified address.
+
+        Only reads the records, addresses without a record have no label.
+    */
+    pub fn labels(&self, wallet_handle: IndyHandle) -> Result<HashMap<String, String>, ErrorCode> {
+        let values = self.wallet_api.indy_list_records(wallet_handle, ADDRESS_RECORD_TYPE)?;
+        let stored = values.iter()
+            .map(|&(ref address, ref value)| read_record_value(value).map(|record| (address.clone(), record)))
+            .collect::<Result<HashMap<String, AddressRecord>, ErrorCode>>()?;
+        self.migrate(wallet_handle, &values, &stored);
+
+        Ok(stored.into_iter()
+            .filter_map(|(address, record)| record.label.map(|label| (address::unqualified_part(&address).to_string(), label)))
+            .collect())
+    }
+
+    // the record of `address`, added first seen `now` when there is none
+    fn record(&self, wallet_handle: IndyHandle, address: &str, now: u64) -> Result<AddressRecord, ErrorCode> {
+        match self.wallet_api.indy_get_record_value(wallet_handle, ADDRESS_RECORD_TYPE, address) {
+            Ok(value) => {
+                let record = read_record_value(&value)?;
+                self.migrate_record(wallet_handle, address, &value, &record);
+                Ok(record)
+            },
+            Err(ErrorCode::WalletItemNotFound) => {
+                let record = AddressRecord { address: address.to_string(), label: None, created: now, balance: None };
+                self.wallet_api.indy_add_record(wallet_handle, ADDRESS_RECORD_TYPE, address, &self.record_value(&record)?)?;
+                Ok(record)
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    fn update(&self, wallet_handle: IndyHandle, record: &AddressRecord) -> Result<(), ErrorCode> {
+        self.wallet_api.indy_update_record_value(wallet_handle, ADDRESS_RECORD_TYPE, &record.address, &self.record_value(record)?)
+    }
+
+    fn record_value(&self, record: &AddressRecord) -> Result<String, ErrorCode> {
+        compression::compress(&serialize(record)?, self.compression)
+    }
+
+    // rewrite the values stored uncompressed when it can, the records read
+    // are used either way
+    fn migrate(&self, wallet_handle: IndyHandle, values: &[(String, String)], records: &HashMap<String, AddressRecord>) {
+        for &(ref address, ref value) in values {
+            if let Some(record) = records.get(address) {
+                self.migrate_record(wallet_handle, address, value, record);
+            }
+        }
+    }
+
+    fn migrate_record(&self, wallet_handle: IndyHandle, address: &str, value: &str, record: &AddressRecord) {
+        if !compression::needs_migration(value, self.compression) {
+            return;
+        }
+        let migrated = self.record_value(record)
+            .and_then(|migrated| self.wallet_api.indy_update_record_value(wallet_handle, ADDRESS_RECORD_TYPE, address, &migrated));
+        if let Err(ec) = migrated {
+            warn!("Address record {} is left as it was stored, its migration failed: {:?}", address, ec);
+        }
+    }
+}
+
+/**
+    Keep `records` for paging, returns the handle of the search.
+*/
+pub fn open_search(records: Vec<AddressRecord>) -> i32 {
+    let search_handle = (state().last_search_handle.fetch_add(1, Ordering::SeqCst) + 1) as i32;
+    state().address_searches.lock().insert(search_handle, records.into_iter().collect());
+    search_handle
+}
+
+/**
+    The next `count` records of a search, none when all were fetched.
+
+    Returns `CommonInvalidStructure` for an unknown or closed search handle.
+*/
+pub fn fetch_search(search_handle: i32, count: usize) -> Result<Vec<AddressRecord>, ErrorCode> {
+    let state = state();
+    let mut searches = state.address_searches.lock();
+    let records = searches.get_mut(&search_handle).ok_or(ErrorCode::CommonInvalidStructure)?;
+
+    let count = ::std::cmp::min(count, records.len());
+    Ok(records.drain(..count).collect())
+}
+
+/**
+    Drop the records of a search.
+
+    Returns `CommonInvalidStructure` for an unknown or closed search handle.
+*/
+pub fn close_search(search_handle: i32) -> Result<(), ErrorCode> {
+    state().address_searches.lock()
+        .remove(&search_handle)
+        .map(|_| ())
+        .ok_or(ErrorCode::CommonInvalidStructure)
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn serialize(record: &AddressRecord) -> Result<String, ErrorCode> {
+    serde_json::to_string(record)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))
+}
+
+fn read_record_value(value: &str) -> Result<AddressRecord, ErrorCode> {
+    deserialize(&compression::decompress(value)?)
+}
+
+fn deserialize(value: &str) -> Result<AddressRecord, ErrorCode> {
+    AddressRecord::from_json(value)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))
+}
+
+
+#[cfg(test)]
+mod address_book_tests {
+    use logic::aggregate_balance::{AddressBalance, WalletBalance};
+    use logic::parsers::parse_get_utxo_response::ParseGetUtxoReply;
+    use utils::test::wallet::WalletMockHandler;
+    use super::*;
+
+    static WALLET_HANDLE: i32 = 1;
+    static FIRST: &'static str = "pay:sov:E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm";
+    static SECOND: &'static str = "pay:sov:2FKYJkgXRZtjhFpTMHhuyfc17BHZWcFPyF2MWy2SZMBaSo64fb";
+
+    struct PaymentMockHandler {}
+
+    impl PaymentAPI for PaymentMockHandler {
+        fn indy_list_payment_addresses(&self, _: IndyHandle) -> Result<Vec<String>, ErrorCode> {
+            Ok(vec![SECOND.to_string(), FIRST.to_string()])
+        }
+
+        fn indy_get_utxos(&self, _: IndyHandle, _: &str) -> Result<ParseGetUtxoReply, ErrorCode> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn address_book() -> AddressBook<WalletMockHandler, PaymentMockHandler> {
+        AddressBook::new(WalletMockHandler::default(), PaymentMockHandler {})
+    }
+
+    fn addresses(records: &Vec<AddressRecord>) -> Vec<&str> {
+        records.iter().map(|record| record.address.as_str()).collect()
+    }
+
+    #[test]
+    fn search_records_new_addresses() {
+        let book = address_book();
+        book.set_label(WALLET_HANDLE, SECOND, "savings", 10).unwrap();
+
+        let records = book.search(WALLET_HANDLE, &AddressFilter::default(), 20).unwrap();
+        assert_eq!(vec![SECOND, FIRST], addresses(&records));
+        assert_eq!(20, records[1].created);
+        assert_eq!(2, book.wallet_api.records.borrow().len());
+    }
+
+    #[test]
+    fn search_by_label_and_creation() {
+        let book = address_book();
+        book.set_label(WALLET_HANDLE, FIRST, "savings", 10).unwrap();
+        book.set_label(WALLET_HANDLE, SECOND, "spending", 20).unwrap();
+
+        let filter = AddressFilter { label_prefix: Some(String::from("sav")), ..AddressFilter::default() };
+        assert_eq!(vec![FIRST], addresses(&book.search(WALLET_HANDLE, &filter, 30).unwrap()));
+
+        let filter = AddressFilter { created_after: Some(10), ..AddressFilter::default() };
+        assert_eq!(vec![SECOND], addresses(&book.search(WALLET_HANDLE, &filter, 30).unwrap()));
+    }
+
+    #[test]
+    fn labels_by_unqualified_address() {
+        let book = address_book();
+        book.set_label(WALLET_HANDLE, FIRST, "savings", 10).unwrap();
+        book.search(WALLET_HANDLE, &AddressFilter::default(), 10).unwrap();
+
+        let labels = book.labels(WALLET_HANDLE).unwrap();
+        assert_eq!(1, labels.len());
+        assert_eq!(Some(&String::from("savings")), labels.get(address::unqualified_part(FIRST)));
+    }
+
+    #[test]
+    fn search_by_cached_balance() {
+        let book = address_book();
+        let balance = AggregateBalance {
+            wallets: vec![WalletBalance {
+                wallet_handle: WALLET_HANDLE,
+                addresses: vec![
+                    AddressBalance { address: FIRST.to_string(), balance: 5, utxo_count: 1 },
+                    AddressBalance { address: SECOND.to_string(), balance: 0, utxo_count: 0 },
+                ],
+                balance: 5,
+            }],
+            total: 5,
+        };
+        book.cache_balances(WALLET_HANDLE, &balance, 10).unwrap();
+
+        let filter = AddressFilter { has_balance: Some(true), ..AddressFilter::default() };
+        let records = book.search(WALLET_HANDLE, &filter, 20).unwrap();
+        assert_eq!(vec![FIRST], addresses(&records));
+        assert_eq!(Some(5), records[0].balance);
+    }
+
+    #[test]
+    fn uncompressed_records_are_migrated_on_search() {
+        let book = address_book().with_compression(Compression::None);
+        book.set_label(WALLET_HANDLE, FIRST, "savings", 10).unwrap();
+
+        let book = AddressBook::new(book.wallet_api, PaymentMockHandler {}).with_compression(Compression::Gzip);
+        let records = book.search(WALLET_HANDLE, &AddressFilter::default(), 20).unwrap();
+        assert_eq!(Some(String::from("savings")), records[0].label);
+
+        let value = book.wallet_api.indy_get_record_value(WALLET_HANDLE, ADDRESS_RECORD_TYPE, FIRST).unwrap();
+        assert!(value.starts_with("gzip:"));
+    }
+
+    #[test]
+    fn label_unknown_address() {
+        let error = address_book().set_label(WALLET_HANDLE, "pay:sov:unknown", "savings", 10).unwrap_err();
+        assert_eq!(ErrorCode::WalletItemNotFound, error);
+    }
+
+    #[test]
+    fn search_pages() {
+        let record = |address: &str| AddressRecord { address: address.to_string(), label: None, created: 0, balance: None };
+        let search_handle = open_search(vec![record(FIRST), record(SECOND)]);
+
+        assert_eq!(vec![record(FIRST)], fetch_search(search_handle, 1).unwrap());
+        assert_eq!(vec![record(SECOND)], fetch_search(search_handle, 5).unwrap());
+        assert!(fetch_search(search_handle, 5).unwrap().is_empty());
+
+        close_search(search_handle).unwrap();
+        assert_eq!(ErrorCode::CommonInvalidStructure, fetch_search(search_handle, 1).unwrap_err());
+    }
+}
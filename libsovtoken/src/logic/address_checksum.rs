@@ -0,0 +1,186 @@
+/*!
+    The checksum appended to the verkey of a payment address.
+
+    An unqualified address is `<verkey><checksum>` in base58, where the
+    checksum is 4 bytes computed from the 32 bytes of the verkey. The
+    Sovrin ledger plugin uses the first 4 bytes of `sha256(sha256(verkey))`.
+
+    Forks of the ledger plugin can use another checksum, which is picked
+    with `address_checksum` in the library config. The helpers at the end of
+    the module tell which checksums an address is valid for, and re-encode
+    an address for another checksum.
+*/
+
+use logic::config::library_config::library_config;
+use utils::base58::{self, Base58Error, Checksum};
+
+/**
+    Computes the checksum of a verkey.
+
+    Only `checksum` has to be implemented, appending and verifying are
+    provided.
+*/
+pub trait AddressChecksum {
+    /**
+        The `base58::CHECKSUM_LEN` bytes appended to `verkey`.
+    */
+    fn checksum(&self, verkey: &[u8]) -> Vec<u8>;
+
+    /**
+        `verkey` followed by its checksum.
+    */
+    fn append(&self, verkey: &[u8]) -> Vec<u8> {
+        let mut bytes = verkey.to_vec();
+        bytes.extend(self.checksum(verkey));
+        bytes
+    }
+
+    /**
+        Verify the checksum at the end of `bytes`, returns the verkey.
+
+        The checksum is compared in constant time.
+    */
+    fn verify(&self, bytes: &[u8]) -> Result<Vec<u8>, Base58Error> {
+        let verkey_len = base58::checked_payload_len(bytes, |verkey| self.checksum(verkey))?;
+        Ok(bytes[..verkey_len].to_vec())
+    }
+}
+
+/**
+    The checksum of the Sovrin ledger plugin, as base58check computes it.
+*/
+pub struct DoubleSha256Checksum;
+
+impl AddressChecksum for DoubleSha256Checksum {
+    fn checksum(&self, verkey: &[u8]) -> Vec<u8> {
+        Checksum::DoubleSha256.of(verkey)
+    }
+}
+
+/**
+    The first 4 bytes of `sha3_256(verkey)`.
+*/
+pub struct Sha3Checksum;
+
+impl AddressChecksum for Sha3Checksum {
+    fn checksum(&self, verkey: &[u8]) -> Vec<u8> {
+        Checksum::Sha3.of(verkey)
+    }
+}
+
+/**
+    The checksums which can be picked in the library config.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChecksumAlgorithm {
+    DoubleSha256,
+    Sha3,
+}
+
+impl Default for ChecksumAlgorithm {
+    fn default() -> Self {
+        ChecksumAlgorithm::DoubleSha256
+    }
+}
+
+impl ChecksumAlgorithm {
+    /**
+        Every algorithm, the default first.
+    */
+    pub fn all() -> Vec<ChecksumAlgorithm> {
+        vec![ChecksumAlgorithm::DoubleSha256, ChecksumAlgorithm::Sha3]
+    }
+
+    pub fn checksum(&self) -> &'static AddressChecksum {
+        match *self {
+            ChecksumAlgorithm::DoubleSha256 => &DoubleSha256Checksum,
+            ChecksumAlgorithm::Sha3 => &Sha3Checksum,
+        }
+    }
+}
+
+/**
+    The checksum of the addresses, set in the library config.
+*/
+pub fn configured() -> &'static AddressChecksum {
+    library_config().address_checksum.checksum()
+}
+
+/**
+    Encode `verkey` as an unqualified address with `checksum`.
+*/
+pub fn encode_address(verkey: &[u8], checksum: &AddressChecksum) -> String {
+    base58::encode(&checksum.append(verkey), Checksum::None)
+}
+
+/**
+    Decode an unqualified address with `checksum`, returns the verkey.
+*/
+pub fn decode_address(unqualified_address: &str, checksum: &AddressChecksum) -> Result<Vec<u8>, Base58Error> {
+    let bytes = base58::decode(unqualified_address, Checksum::None)?;
+    checksum.verify(&bytes)
+}
+
+/**
+    The algorithms `unqualified_address` has a valid checksum for.
+
+    Useful to tell which ledger an address was made for.
+*/
+pub fn matching_algorithms(unqualified_address: &str) -> Vec<ChecksumAlgorithm> {
+    ChecksumAlgorithm::all()
+        .into_iter()
+        .filter(|algorithm| decode_address(unqualified_address, algorithm.checksum()).is_ok())
+        .collect()
+}
+
+/**
+    Re-encode an unqualified address made with `from` for `to`.
+*/
+pub fn convert_address(unqualified_address: &str, from: ChecksumAlgorithm, to: ChecksumAlgorithm) -> Result<String, Base58Error> {
+    let verkey = decode_address(unqualified_address, from.checksum())?;
+    Ok(encode_address(&verkey, to.checksum()))
+}
+
+
+#[cfg(test)]
+mod address_checksum_tests {
+    use super::*;
+    use utils::json_conversion::JsonDeserialize;
+
+    static ADDRESS: &'static str = "2Viu9qrpqM48PSw3vdoQoFKP5AvYTChUZhwWtCydfW9iu7ftRt";
+    static VERKEY: &'static str = "EFfodscoymgdJDuM885uEWmgCcA25P6VR6TjVqsYZLW3";
+
+    fn verkey() -> Vec<u8> {
+        base58::decode(VERKEY, Checksum::None).unwrap()
+    }
+
+    #[test]
+    fn double_sha256_is_base58check() {
+        assert_eq!(ADDRESS, encode_address(&verkey(), &DoubleSha256Checksum));
+        assert_eq!(base58::encode(&verkey(), Checksum::DoubleSha256), ADDRESS);
+    }
+
+    #[test]
+    fn sha3_round_trip() {
+        let address = encode_address(&verkey(), &Sha3Checksum);
+        assert_ne!(ADDRESS, address);
+        assert_eq!(verkey(), decode_address(&address, &Sha3Checksum).unwrap());
+        assert_eq!(Base58Error::BadChecksum, decode_address(&address, &DoubleSha256Checksum).unwrap_err());
+    }
+
+    #[test]
+    fn cross_validation() {
+        assert_eq!(vec![ChecksumAlgorithm::DoubleSha256], matching_algorithms(ADDRESS));
+
+        let converted = convert_address(ADDRESS, ChecksumAlgorithm::DoubleSha256, ChecksumAlgorithm::Sha3).unwrap();
+        assert_eq!(vec![ChecksumAlgorithm::Sha3], matching_algorithms(&converted));
+    }
+
+    #[test]
+    fn algorithm_from_config_name() {
+        let algorithm = ChecksumAlgorithm::from_json(r#""sha3""#).unwrap();
+        assert_eq!(ChecksumAlgorithm::Sha3, algorithm);
+        assert_eq!(ChecksumAlgorithm::DoubleSha256, ChecksumAlgorithm::default());
+    }
+}
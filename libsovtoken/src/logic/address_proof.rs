@@ -0,0 +1,178 @@
+/*!
+    Proofs that a user controls a payment address.
+
+    A service sends a challenge, the wallet signs it with the key of the
+    address and the service verifies the signature against the address.
+
+    The signed message is `sovtoken-address-control:<address>:<challenge>`,
+    so a challenge chosen by the service can never be a transaction the
+    wallet didn't mean to sign.
+*/
+
+use indy::{IndyHandle, ErrorCode};
+use sodiumoxide::crypto::sign::{self, PublicKey, Signature};
+
+use logic::address;
+use logic::indy_sdk_api::crypto_api::CryptoAPI;
+use utils::base58::FromBase58;
+
+/**
+    The prefix of every signed address control message.
+*/
+pub const ADDRESS_CONTROL_PREFIX: &str = "sovtoken-address-control:";
+
+/**
+    The longest challenge accepted, in bytes.
+*/
+pub const MAX_CHALLENGE_LEN: usize = 1024;
+
+/**
+    The message signed to prove control of `address`.
+*/
+pub fn address_control_message(address: &str, challenge: &str) -> String {
+    format!("{}{}:{}", ADDRESS_CONTROL_PREFIX, address, challenge)
+}
+
+/**
+    Sign `challenge` with the key of `address` and call `cb` with the base58 signature.
+*/
+pub fn prove_address_control<A: CryptoAPI>(
+    crypto_api: &'static A,
+    wallet_handle: IndyHandle,
+    address: &str,
+    challenge: &str,
+    cb: Box<Fn(Result<String, ErrorCode>) + Send + Sync>
+) -> Result<(), ErrorCode> {
+    trace!("logic::address_proof::prove_address_control >> address: {:?}", address);
+    let verkey = address::validate_address(address)?;
+    validate_challenge(challenge)?;
+
+    let message = address_control_message(address, challenge);
+    let ec = crypto_api.indy_crypto_sign(wallet_handle, verkey, message.as_bytes(), move |signature| cb(signature));
+
+    match ec {
+        ErrorCode::Success => Ok(()),
+        ec => Err(ec),
+    }
+}
+
+/**
+    Whether `signature` proves control of `address` for `challenge`.
+
+    Fails with `CommonInvalidStructure` when the address, challenge or
+    signature are malformed; a well formed signature by another key is `false`.
+*/
+pub fn verify_address_control(address: &str, challenge: &str, signature: &str) -> Result<bool, ErrorCode> {
+    trace!("logic::address_proof::verify_address_control >> address: {:?}", address);
+    let verkey = address::validate_address(address)?;
+    validate_challenge(challenge)?;
+
+    let public_key = verkey.from_base58().ok()
+        .and_then(|bytes| PublicKey::from_slice(&bytes))
+        .ok_or(ErrorCode::CommonInvalidStructure)?;
+
+    let signature = signature.from_base58().ok()
+        .and_then(|bytes| Signature::from_slice(&bytes))
+        .ok_or(ErrorCode::CommonInvalidStructure)?;
+
+    let message = address_control_message(address, challenge);
+    Ok(sign::verify_detached(&signature, message.as_bytes(), &public_key))
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn validate_challenge(challenge: &str) -> Result<(), ErrorCode> {
+    if challenge.is_empty() || challenge.len() > MAX_CHALLENGE_LEN {
+        error!("A challenge needs 1 to {} bytes, found {}", MAX_CHALLENGE_LEN, challenge.len());
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod address_proof_tests {
+    use std::sync::{Arc, Mutex};
+
+    use sodiumoxide::crypto::sign::{keypair_from_seed, sign_detached, Seed};
+
+    use logic::config::payment_address_config::PaymentAddressConfig;
+    use utils::base58::IntoBase58;
+    use super::*;
+
+    static CHALLENGE: &'static str = "4f1b2c";
+
+    fn keypair() -> (PublicKey, sign::SecretKey) {
+        keypair_from_seed(&Seed::from_slice(b"000000000000000000000000Address1").unwrap())
+    }
+
+    fn address() -> String {
+        address::qualified_address_from_verkey(&keypair().0.into_base58()).unwrap()
+    }
+
+    // signs with the key of `address()`
+    struct CryptoApiHandler {}
+
+    impl CryptoAPI for CryptoApiHandler {
+        fn indy_create_key(&self, _: IndyHandle, _: PaymentAddressConfig) -> Result<String, ErrorCode> {
+            Err(ErrorCode::CommonInvalidState)
+        }
+
+        fn indy_create_key_async<F: 'static>(&self, _: i32, _: PaymentAddressConfig, _: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+            ErrorCode::CommonInvalidState
+        }
+
+        fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _: IndyHandle, verkey: String, message: &[u8], mut cb: F) -> ErrorCode {
+            let (public_key, secret_key) = keypair();
+            assert_eq!(public_key.into_base58(), verkey);
+            cb(Ok(sign_detached(message, &secret_key).into_base58()));
+            ErrorCode::Success
+        }
+    }
+
+    static CRYPTO_API: CryptoApiHandler = CryptoApiHandler {};
+
+    fn prove(address: &str, challenge: &str) -> Result<String, ErrorCode> {
+        let signature = Arc::new(Mutex::new(None));
+        let signature_clone = Arc::clone(&signature);
+
+        prove_address_control(&CRYPTO_API, 1, address, challenge, Box::new(move |result| {
+            *signature_clone.lock().unwrap() = Some(result);
+        }))?;
+
+        let result = signature.lock().unwrap().take().unwrap();
+        result
+    }
+
+    #[test]
+    fn prove_and_verify() {
+        let signature = prove(&address(), CHALLENGE).unwrap();
+        assert!(verify_address_control(&address(), CHALLENGE, &signature).unwrap());
+    }
+
+    #[test]
+    fn verify_other_challenge() {
+        let signature = prove(&address(), CHALLENGE).unwrap();
+        assert!(!verify_address_control(&address(), "other", &signature).unwrap());
+    }
+
+    #[test]
+    fn raw_challenge_signature_is_no_proof() {
+        let signature = sign_detached(CHALLENGE.as_bytes(), &keypair().1).into_base58();
+        assert!(!verify_address_control(&address(), CHALLENGE, &signature).unwrap());
+    }
+
+    #[test]
+    fn malformed_signature() {
+        let error = verify_address_control(&address(), CHALLENGE, "abc").unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error);
+    }
+
+    #[test]
+    fn empty_challenge() {
+        assert_eq!(ErrorCode::CommonInvalidStructure, prove(&address(), "").unwrap_err());
+    }
+}
@@ -0,0 +1,185 @@
+/*!
+    Test vectors of the payment addresses, for the implementations of the
+    addresses in other languages to be checked against the library.
+
+    [`test_vectors`] returns them, `sovtoken_get_address_test_vectors_json`
+    exports them as json. There are two kinds, for each checksum of
+    [`ChecksumAlgorithm`]:
+
+    * encoding vectors, the address of a verkey;
+    * validity vectors, whether a string is a valid Ed25519 payment address.
+
+    The vectors were generated with an implementation independent of the
+    library, from `sha256("sovtoken address test vector <n>")` and a few
+    edge cases, and are kept as they are: a change of the library changing
+    an address fails them. Debug builds check them in `sovtoken_init`, see
+    [`check_test_vectors`].
+
+    [`test_vectors`]: fn.test_vectors.html
+    [`check_test_vectors`]: fn.check_test_vectors.html
+    [`ChecksumAlgorithm`]: ../address_checksum/enum.ChecksumAlgorithm.html
+*/
+
+use indy::ErrorCode;
+
+use logic::address_checksum::{self, ChecksumAlgorithm};
+use logic::address_version::{AddressVersion, VersionedKey};
+use logic::error::SovtokenError;
+use logic::messages;
+use utils::base58::{self, Checksum};
+use utils::constants::general::PAYMENT_ADDRESS_QUALIFIER;
+
+/**
+    The version of the vectors, raised when vectors are changed or removed.
+*/
+pub const TEST_VECTORS_VERSION: u32 = 1;
+
+/**
+    The qualified address of `verkey` with the checksum `algorithm`.
+*/
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingVector {
+    pub algorithm: ChecksumAlgorithm,
+    pub verkey: &'static str,
+    pub address: &'static str,
+}
+
+/**
+    Whether `address` is a valid Ed25519 payment address with the checksum
+    `algorithm`, and what the vector is about.
+*/
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidityVector {
+    pub algorithm: ChecksumAlgorithm,
+    pub address: &'static str,
+    pub valid: bool,
+    pub description: &'static str,
+}
+
+/**
+    Every test vector.
+*/
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TestVectors {
+    pub version: u32,
+    pub encoding: Vec<EncodingVector>,
+    pub validity: Vec<ValidityVector>,
+}
+
+/**
+    The test vectors.
+
+    ```
+    use sovtoken::logic::address_vectors::test_vectors;
+    let vectors = test_vectors();
+    assert!(vectors.validity.iter().any(|vector| !vector.valid));
+    ```
+*/
+pub fn test_vectors() -> TestVectors {
+    TestVectors {
+        version: TEST_VECTORS_VERSION,
+        encoding: ENCODING_VECTORS.to_vec(),
+        validity: VALIDITY_VECTORS.to_vec(),
+    }
+}
+
+/**
+    Whether `address` is a valid Ed25519 payment address with the checksum
+    `algorithm`: `pay:sov:` and the base58 of the 32 bytes of the verkey
+    followed by their checksum.
+
+    The network tag isn't part of the vectors, an address with a tag isn't
+    valid here.
+*/
+pub fn is_valid_address(address: &str, algorithm: ChecksumAlgorithm) -> bool {
+    if !address.starts_with(PAYMENT_ADDRESS_QUALIFIER) {
+        return false;
+    }
+    VersionedKey::decode(&address[PAYMENT_ADDRESS_QUALIFIER.len()..], algorithm.checksum())
+        .map_or(false, |key| key.version == AddressVersion::Ed25519)
+}
+
+/**
+    Check the library against every test vector.
+
+    Fails with `CommonInvalidState` at the first vector the library
+    disagrees with.
+*/
+pub fn check_test_vectors() -> Result<(), ErrorCode> {
+    for vector in ENCODING_VECTORS {
+        let address = base58::decode(vector.verkey, Checksum::None)
+            .map(|verkey| format!("{}{}", PAYMENT_ADDRESS_QUALIFIER, address_checksum::encode_address(&verkey, vector.algorithm.checksum())));
+        if address.as_ref().map(String::as_str) != Ok(vector.address) {
+            return Err(vector_failed(vector.address, &format!("encoded as {:?}", address)));
+        }
+    }
+
+    for vector in VALIDITY_VECTORS {
+        if is_valid_address(vector.address, vector.algorithm) != vector.valid {
+            return Err(vector_failed(vector.address, vector.description));
+        }
+    }
+    Ok(())
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn vector_failed(address: &str, detail: &str) -> ErrorCode {
+    ErrorCode::from(SovtokenError::InvalidState(messages::ADDRESS_VECTOR_FAILED.with(&[address.to_string(), detail.to_string()])))
+}
+
+static ENCODING_VECTORS: &[EncodingVector] = &[
+    EncodingVector { algorithm: ChecksumAlgorithm::DoubleSha256, verkey: "HWDDhpGQu6YXaL187CbG2fjxxRaeyL8snhdTKZsmf1R4", address: "pay:sov:2rzeeryE4PK7YTpb8G761LLNhJUD4xG2W2bXDnKSp68Q3DL7AT" },
+    EncodingVector { algorithm: ChecksumAlgorithm::DoubleSha256, verkey: "CbMBkqeo4o8ueZ9qn9aGtrZModTeHQke9n4CLuH3Ajku", address: "pay:sov:2JrcHofkTqiCTXauvdMqPrEmNENiLkTHXYVGcXC9kbirksDr84" },
+    EncodingVector { algorithm: ChecksumAlgorithm::DoubleSha256, verkey: "6fFVd6LRbx5RbKTDVpKinUw1aezVfyZkha634Ne5BbRm", address: "pay:sov:e2xBzUmW6sV5AsUs1UBcJnFweKJBLcvMbNUf62fuSyzDj4YuJ" },
+    EncodingVector { algorithm: ChecksumAlgorithm::DoubleSha256, verkey: "11111111111111111111111111111111", address: "pay:sov:11111111111111111111111111111111273Yts" },
+    EncodingVector { algorithm: ChecksumAlgorithm::DoubleSha256, verkey: "JEKNVnkbo3jma5nREBBJCDoXFVeKkD56V3xKrvRmWxFG", address: "pay:sov:2wkBET2rRgE8pahuaczxKbmv7ciehqsne57F9gtzf1PVZS9BEY" },
+    EncodingVector { algorithm: ChecksumAlgorithm::DoubleSha256, verkey: "EFfodscoymgdJDuM885uEWmgCcA25P6VR6TjVqsYZLW3", address: "pay:sov:2Viu9qrpqM48PSw3vdoQoFKP5AvYTChUZhwWtCydfW9iu7ftRt" },
+    EncodingVector { algorithm: ChecksumAlgorithm::Sha3, verkey: "HWDDhpGQu6YXaL187CbG2fjxxRaeyL8snhdTKZsmf1R4", address: "pay:sov:2rzeeryE4PK7YTpb8G761LLNhJUD4xG2W2bXDnKSp68Q4GvdLH" },
+    EncodingVector { algorithm: ChecksumAlgorithm::Sha3, verkey: "CbMBkqeo4o8ueZ9qn9aGtrZModTeHQke9n4CLuH3Ajku", address: "pay:sov:2JrcHofkTqiCTXauvdMqPrEmNENiLkTHXYVGcXC9kbirkkFBpU" },
+    EncodingVector { algorithm: ChecksumAlgorithm::Sha3, verkey: "6fFVd6LRbx5RbKTDVpKinUw1aezVfyZkha634Ne5BbRm", address: "pay:sov:e2xBzUmW6sV5AsUs1UBcJnFweKJBLcvMbNUf62fuSyzGFzXs9" },
+    EncodingVector { algorithm: ChecksumAlgorithm::Sha3, verkey: "11111111111111111111111111111111", address: "pay:sov:1111111111111111111111111111111153p9oL" },
+    EncodingVector { algorithm: ChecksumAlgorithm::Sha3, verkey: "JEKNVnkbo3jma5nREBBJCDoXFVeKkD56V3xKrvRmWxFG", address: "pay:sov:2wkBET2rRgE8pahuaczxKbmv7ciehqsne57F9gtzf1PVWaJQZW" },
+];
+
+static VALIDITY_VECTORS: &[ValidityVector] = &[
+    ValidityVector { algorithm: ChecksumAlgorithm::DoubleSha256, address: "pay:sov:2rzeeryE4PK7YTpb8G761LLNhJUD4xG2W2bXDnKSp68Q3DL7AT", valid: true, description: "valid" },
+    ValidityVector { algorithm: ChecksumAlgorithm::DoubleSha256, address: "pay:sov:11111111111111111111111111111111273Yts", valid: true, description: "valid, verkey of zeros" },
+    ValidityVector { algorithm: ChecksumAlgorithm::DoubleSha256, address: "pay:sov:2rzeeryE4PK7YTpb8G761LLNhJUD4xG2W2bXDnKSp68Q3DL7AU", valid: false, description: "bad checksum" },
+    ValidityVector { algorithm: ChecksumAlgorithm::DoubleSha256, address: "pay:sov:2rzeeryE4PK7YTpb8G761LLNhJUD4xG2W2bXDnKSp68Q4GvdLH", valid: false, description: "checksum of the other algorithm" },
+    ValidityVector { algorithm: ChecksumAlgorithm::DoubleSha256, address: "2rzeeryE4PK7YTpb8G761LLNhJUD4xG2W2bXDnKSp68Q3DL7AT", valid: false, description: "no qualifier" },
+    ValidityVector { algorithm: ChecksumAlgorithm::DoubleSha256, address: "pay:xyz:2rzeeryE4PK7YTpb8G761LLNhJUD4xG2W2bXDnKSp68Q3DL7AT", valid: false, description: "other payment method" },
+    ValidityVector { algorithm: ChecksumAlgorithm::DoubleSha256, address: "pay:sov:2rzeeryE4P07YTpb8G761LLNhJUD4xG2W2bXDnKSp68Q3DL7AT", valid: false, description: "not base58" },
+    ValidityVector { algorithm: ChecksumAlgorithm::DoubleSha256, address: "pay:sov:RU7GNk6gHPEGHVubGf5m2fNgVRk4cdUysfBUN9FuovmvPWFL", valid: false, description: "verkey of 31 bytes" },
+    ValidityVector { algorithm: ChecksumAlgorithm::DoubleSha256, address: "pay:sov:", valid: false, description: "empty" },
+    ValidityVector { algorithm: ChecksumAlgorithm::Sha3, address: "pay:sov:2rzeeryE4PK7YTpb8G761LLNhJUD4xG2W2bXDnKSp68Q4GvdLH", valid: true, description: "valid" },
+    ValidityVector { algorithm: ChecksumAlgorithm::Sha3, address: "pay:sov:1111111111111111111111111111111153p9oL", valid: true, description: "valid, verkey of zeros" },
+    ValidityVector { algorithm: ChecksumAlgorithm::Sha3, address: "pay:sov:2rzeeryE4PK7YTpb8G761LLNhJUD4xG2W2bXDnKSp68Q4GvdLJ", valid: false, description: "bad checksum" },
+    ValidityVector { algorithm: ChecksumAlgorithm::Sha3, address: "pay:sov:2rzeeryE4PK7YTpb8G761LLNhJUD4xG2W2bXDnKSp68Q3DL7AT", valid: false, description: "checksum of the other algorithm" },
+    ValidityVector { algorithm: ChecksumAlgorithm::Sha3, address: "2rzeeryE4PK7YTpb8G761LLNhJUD4xG2W2bXDnKSp68Q4GvdLH", valid: false, description: "no qualifier" },
+    ValidityVector { algorithm: ChecksumAlgorithm::Sha3, address: "pay:xyz:2rzeeryE4PK7YTpb8G761LLNhJUD4xG2W2bXDnKSp68Q4GvdLH", valid: false, description: "other payment method" },
+    ValidityVector { algorithm: ChecksumAlgorithm::Sha3, address: "pay:sov:2rzeeryE4P07YTpb8G761LLNhJUD4xG2W2bXDnKSp68Q4GvdLH", valid: false, description: "not base58" },
+    ValidityVector { algorithm: ChecksumAlgorithm::Sha3, address: "pay:sov:RU7GNk6gHPEGHVubGf5m2fNgVRk4cdUysfBUN9FuovmMaMS4", valid: false, description: "verkey of 31 bytes" },
+    ValidityVector { algorithm: ChecksumAlgorithm::Sha3, address: "pay:sov:", valid: false, description: "empty" },
+];
+
+
+#[cfg(test)]
+mod address_vectors_tests {
+    use super::*;
+
+    #[test]
+    fn library_matches_the_vectors() {
+        assert_eq!(Ok(()), check_test_vectors());
+    }
+
+    #[test]
+    fn vectors_as_json() {
+        let vectors = json!(test_vectors());
+        assert_eq!(json!("double_sha256"), vectors["encoding"][0]["algorithm"]);
+        assert_eq!(json!(false), vectors["validity"][2]["valid"]);
+    }
+}
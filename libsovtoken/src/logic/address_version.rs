@@ -0,0 +1,215 @@
+/*!
+    The key type of a payment address, told by a version byte.
+
+    The addresses of today hold an Ed25519 verkey, `<verkey><checksum>`,
+    and stay as they are: 36 bytes decode to an Ed25519 address. The
+    addresses of other key types, e.g. BLS, will put a version byte before
+    the key, `<version><key><checksum>`, and the checksum covers the version.
+    The two forms never have the same length, a versioned address is never
+    36 bytes.
+
+    A version this library doesn't know is kept opaque: the checksum is
+    verified, but the key isn't interpreted. So a TXO or an address of a
+    future key type, stored by a wallet or returned by the ledger, still
+    parses and formats, it can't be signed for.
+*/
+
+use indy::ErrorCode;
+
+use logic::address::VERKEY_LEN;
+use logic::address_checksum::AddressChecksum;
+use logic::error::SovtokenError;
+use logic::messages;
+use utils::base58::{self, Base58Error, Checksum};
+
+/**
+    The version byte of the Ed25519 addresses, which is never written.
+*/
+pub const ED25519_VERSION: u8 = 0;
+
+/**
+    The version byte reserved for the addresses of a BLS key.
+*/
+pub const BLS_VERSION: u8 = 1;
+
+/**
+    The length in bytes of a BLS verkey, a point of G2.
+*/
+pub const BLS_KEY_LEN: usize = 128;
+
+/**
+    The key type of an address.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressVersion {
+    Ed25519,
+    Bls,
+    /// A version of a later library, with its byte.
+    Unknown(u8),
+}
+
+impl AddressVersion {
+    pub fn from_byte(byte: u8) -> AddressVersion {
+        match byte {
+            ED25519_VERSION => AddressVersion::Ed25519,
+            BLS_VERSION => AddressVersion::Bls,
+            byte => AddressVersion::Unknown(byte),
+        }
+    }
+
+    pub fn byte(&self) -> u8 {
+        match *self {
+            AddressVersion::Ed25519 => ED25519_VERSION,
+            AddressVersion::Bls => BLS_VERSION,
+            AddressVersion::Unknown(byte) => byte,
+        }
+    }
+
+    /**
+        The length of the keys of the version, `None` when it is unknown.
+    */
+    pub fn key_len(&self) -> Option<usize> {
+        match *self {
+            AddressVersion::Ed25519 => Some(VERKEY_LEN),
+            AddressVersion::Bls => Some(BLS_KEY_LEN),
+            AddressVersion::Unknown(_) => None,
+        }
+    }
+
+    /**
+        Whether the library signs for the addresses of the version.
+    */
+    pub fn can_sign(&self) -> bool {
+        *self == AddressVersion::Ed25519
+    }
+}
+
+/**
+    The key of an unqualified address, with its version.
+
+    ```
+    use sovtoken::logic::address_checksum::DoubleSha256Checksum;
+    use sovtoken::logic::address_version::{AddressVersion, VersionedKey};
+
+    let address = "WqXg36yxheP7wzUZnhnkUY6Qeaib5uyUZuyaujr7atPHRH3d2";
+    let key = VersionedKey::decode(address, &DoubleSha256Checksum).unwrap();
+    assert_eq!(AddressVersion::Ed25519, key.version);
+    assert_eq!(address, key.encode(&DoubleSha256Checksum));
+    ```
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionedKey {
+    pub version: AddressVersion,
+    pub key: Vec<u8>,
+}
+
+impl VersionedKey {
+    /**
+        Checks the length of `key` for `version`. The key of an unknown
+        version has any length but none, or one a byte short of an Ed25519
+        verkey, which would make it an Ed25519 address.
+    */
+    pub fn new(version: AddressVersion, key: Vec<u8>) -> Result<VersionedKey, Base58Error> {
+        match version.key_len() {
+            Some(expected) if expected != key.len() => Err(Base58Error::BadLength { expected, found: key.len() }),
+            None if key.is_empty() || key.len() + 1 == VERKEY_LEN => Err(Base58Error::BadLength { expected: VERKEY_LEN, found: key.len() }),
+            _ => Ok(VersionedKey { version, key }),
+        }
+    }
+
+    /**
+        Decode an unqualified address, of any version, verifying its checksum.
+
+        An Ed25519 address is unversioned, a versioned one with the
+        Ed25519 byte is rejected so an address has one encoding.
+    */
+    pub fn decode(unqualified_address: &str, checksum: &AddressChecksum) -> Result<VersionedKey, Base58Error> {
+        let bytes = base58::decode(unqualified_address, Checksum::None)?;
+        let payload = checksum.verify(&bytes)?;
+
+        if payload.len() == VERKEY_LEN {
+            return Ok(VersionedKey { version: AddressVersion::Ed25519, key: payload });
+        }
+
+        let (version, key) = match payload.split_first() {
+            Some((&byte, key)) => (AddressVersion::from_byte(byte), key.to_vec()),
+            None => return Err(Base58Error::BadLength { expected: VERKEY_LEN, found: 0 }),
+        };
+        if version == AddressVersion::Ed25519 {
+            return Err(Base58Error::BadLength { expected: VERKEY_LEN, found: payload.len() });
+        }
+        VersionedKey::new(version, key)
+    }
+
+    /**
+        Encode the key as an unqualified address with `checksum`.
+    */
+    pub fn encode(&self, checksum: &AddressChecksum) -> String {
+        let mut payload = Vec::with_capacity(self.key.len() + 1);
+        if self.version != AddressVersion::Ed25519 {
+            payload.push(self.version.byte());
+        }
+        payload.extend_from_slice(&self.key);
+        base58::encode(&checksum.append(&payload), Checksum::None)
+    }
+
+    /**
+        The base58 Ed25519 verkey, to sign with.
+
+        Fails with `PaymentOperationNotSupportedError` for the other
+        versions, which the library can't sign for.
+    */
+    pub fn verkey(&self) -> Result<String, ErrorCode> {
+        if !self.version.can_sign() {
+            let error = SovtokenError::OperationNotSupported(messages::UNSUPPORTED_ADDRESS_VERSION.with(&[format!("{:?}", self.version), self.version.byte().to_string()]));
+            return Err(ErrorCode::from(error));
+        }
+        Ok(base58::encode(&self.key, Checksum::None))
+    }
+}
+
+
+#[cfg(test)]
+mod address_version_tests {
+    use logic::address_checksum::{DoubleSha256Checksum, Sha3Checksum};
+    use utils::random::rand_bytes;
+    use super::*;
+
+    #[test]
+    fn legacy_address_is_ed25519() {
+        let key = VersionedKey::new(AddressVersion::Ed25519, rand_bytes(VERKEY_LEN)).unwrap();
+        let address = key.encode(&DoubleSha256Checksum);
+        assert_eq!(VERKEY_LEN + 4, base58::decode(&address, Checksum::None).unwrap().len());
+        assert_eq!(key, VersionedKey::decode(&address, &DoubleSha256Checksum).unwrap());
+        assert!(key.verkey().is_ok());
+    }
+
+    #[test]
+    fn bls_address() {
+        let key = VersionedKey::new(AddressVersion::Bls, rand_bytes(BLS_KEY_LEN)).unwrap();
+        let address = key.encode(&DoubleSha256Checksum);
+        assert_eq!(key, VersionedKey::decode(&address, &DoubleSha256Checksum).unwrap());
+        assert_eq!(ErrorCode::PaymentOperationNotSupportedError, key.verkey().unwrap_err());
+
+        assert!(VersionedKey::new(AddressVersion::Bls, rand_bytes(VERKEY_LEN)).is_err());
+    }
+
+    #[test]
+    fn unknown_version_is_opaque() {
+        let key = VersionedKey::new(AddressVersion::Unknown(0x42), rand_bytes(48)).unwrap();
+        let address = key.encode(&DoubleSha256Checksum);
+        assert_eq!(key, VersionedKey::decode(&address, &DoubleSha256Checksum).unwrap());
+
+        // still checksum validated
+        assert_eq!(Err(Base58Error::BadChecksum), VersionedKey::decode(&address, &Sha3Checksum));
+    }
+
+    #[test]
+    fn versioned_ed25519_is_rejected() {
+        let mut payload = vec![ED25519_VERSION];
+        payload.extend(rand_bytes(VERKEY_LEN));
+        let address = base58::encode(&DoubleSha256Checksum.append(&payload), Checksum::None);
+        assert!(VersionedKey::decode(&address, &DoubleSha256Checksum).is_err());
+    }
+}
@@ -0,0 +1,381 @@
+/*!
+    Balance summary over the payment addresses of several wallets.
+*/
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use indy::{IndyHandle, ErrorCode};
+
+use logic::indy_sdk_api::payment_api::PaymentAPI;
+use logic::parsers::parse_get_utxo_response::ParseGetUtxoReply;
+use logic::type_aliases::TokenAmount;
+
+/**
+    Balance of a single payment address.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct AddressBalance {
+    pub address: String,
+    pub balance: TokenAmount,
+    #[serde(rename = "utxoCount")]
+    pub utxo_count: usize,
+}
+
+/**
+    Balance of every payment address in a wallet.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct WalletBalance {
+    #[serde(rename = "walletHandle")]
+    pub wallet_handle: IndyHandle,
+    pub addresses: Vec<AddressBalance>,
+    pub balance: TokenAmount,
+}
+
+/**
+    Balance of every wallet and the total over all of them.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct AggregateBalance {
+    pub wallets: Vec<WalletBalance>,
+    pub total: TokenAmount,
+}
+
+/**
+    Aggregator collects the balances of the payment addresses of several wallets.
+*/
+pub struct Aggregator<T> where T: PaymentAPI {
+    injected_api: T
+}
+
+impl<T: PaymentAPI> Aggregator<T> {
+    pub fn new(api_handler: T) -> Self {
+        Aggregator { injected_api: api_handler }
+    }
+
+    /**
+        Fetch the UTXOs of every payment address in `wallet_handles` and sum
+        them, calls `cb` with the balances.
+
+        The addresses of the wallets are listed, then the UTXOs of every
+        address fetched, without waiting for one another. `cb` is called
+        once: from the last reply, or with the first error. It is called
+        with `CommonInvalidState` when a sum overflows a [`TokenAmount`].
+
+        Fails with the error of starting to list the addresses of a wallet,
+        `cb` isn't called after. When `cb` was called with an error already,
+        the error isn't returned again.
+
+        [`TokenAmount`]: ../type_aliases/type.TokenAmount.html
+    */
+    pub fn aggregate_balance<F>(&self, wallet_handles: &[IndyHandle], pool_handle: IndyHandle, cb: F) -> Result<(), ErrorCode>
+        where T: Clone + Send + 'static, F: FnMut(Result<AggregateBalance, ErrorCode>) + 'static + Send
+    {
+        trace!("logic::aggregate_balance::aggregate_balance >> wallet_handles: {:?}, pool_handle: {:?}", wallet_handles, pool_handle);
+
+        let pending = Arc::new(Mutex::new(PendingBalances {
+            wallet_handles: wallet_handles.to_vec(),
+            addresses: vec![None; wallet_handles.len()],
+            balances: Vec::new(),
+            remaining: wallet_handles.len(),
+            cb: Some(cb),
+        }));
+        if wallet_handles.is_empty() {
+            finish(&pending);
+            return Ok(());
+        }
+
+        for (position, wallet_handle) in wallet_handles.iter().enumerate() {
+            let api = self.injected_api.clone();
+            let pending_clone = Arc::clone(&pending);
+            let ec = self.injected_api.indy_list_payment_addresses_async(*wallet_handle, move |addresses| {
+                listed(&api, pool_handle, &pending_clone, position, addresses);
+            });
+            if ec != ErrorCode::Success {
+                // the addresses still being listed are dropped, unless `cb` was called already
+                let cb = lock(&pending).cb.take();
+                return match cb {
+                    Some(_) => Err(ec),
+                    None => Ok(()),
+                };
+            }
+        }
+        Ok(())
+    }
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+/**
+    The balances still coming for `Aggregator::aggregate_balance`.
+
+    The wallets are listed first, `remaining` counts the wallets whose
+    addresses aren't listed yet. Then the UTXOs are fetched, `remaining`
+    counts the addresses whose balance isn't known yet. `cb` is `None` once
+    it was called.
+*/
+struct PendingBalances<F> {
+    wallet_handles: Vec<IndyHandle>,
+    addresses: Vec<Option<Vec<String>>>,
+    balances: Vec<Option<AddressBalance>>,
+    remaining: usize,
+    cb: Option<F>,
+}
+
+fn lock<F>(pending: &Mutex<PendingBalances<F>>) -> MutexGuard<PendingBalances<F>> {
+    pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// The addresses of the wallet at `position` are listed, fetch the UTXOs of
+// every address once the last wallet is.
+fn listed<T, F>(api: &T, pool_handle: IndyHandle, pending: &Arc<Mutex<PendingBalances<F>>>, position: usize, addresses: Result<Vec<String>, ErrorCode>)
+    where T: PaymentAPI, F: FnMut(Result<AggregateBalance, ErrorCode>) + 'static + Send
+{
+    let addresses = {
+        let mut state = lock(pending);
+        if state.cb.is_none() {
+            return;
+        }
+        match addresses {
+            Ok(addresses) => {
+                state.addresses[position] = Some(addresses);
+                state.remaining -= 1;
+                if state.remaining > 0 {
+                    return;
+                }
+                let addresses: Vec<String> = state.addresses.iter()
+                    .flat_map(|addresses| addresses.clone().unwrap_or_default())
+                    .collect();
+                state.balances = vec![None; addresses.len()];
+                state.remaining = addresses.len();
+                Ok(addresses)
+            },
+            Err(ec) => {
+                error!("Failed to list the payment addresses of wallet {}: {:?}", state.wallet_handles[position], ec);
+                Err(ec)
+            },
+        }
+    };
+
+    let addresses = match addresses {
+        Ok(ref addresses) if addresses.is_empty() => return finish(pending),
+        Ok(addresses) => addresses,
+        Err(ec) => return fail(pending, ec),
+    };
+
+    for (index, address) in addresses.into_iter().enumerate() {
+        let pending_clone = Arc::clone(pending);
+        let fetched_address = address.clone();
+        let ec = api.indy_get_utxos_async(pool_handle, &address, move |utxos| {
+            fetched(&pending_clone, index, fetched_address.clone(), utxos);
+        });
+        if ec != ErrorCode::Success {
+            return fail(pending, ec);
+        }
+    }
+}
+
+// The UTXOs of the address at `index` are fetched, call back once the last address's are.
+fn fetched<F>(pending: &Arc<Mutex<PendingBalances<F>>>, index: usize, address: String, utxos: Result<ParseGetUtxoReply, ErrorCode>)
+    where F: FnMut(Result<AggregateBalance, ErrorCode>) + 'static + Send
+{
+    let balance = utxos.and_then(|utxos| {
+        let balance = checked_sum(utxos.iter().map(|utxo| utxo.amount))?;
+        Ok(AddressBalance { address, balance, utxo_count: utxos.len() })
+    });
+
+    let balance = match balance {
+        Ok(balance) => balance,
+        Err(ec) => return fail(pending, ec),
+    };
+
+    {
+        let mut state = lock(pending);
+        if state.cb.is_none() {
+            return;
+        }
+        if state.balances[index].is_none() {
+            state.remaining -= 1;
+        }
+        state.balances[index] = Some(balance);
+        if state.remaining > 0 {
+            return;
+        }
+    }
+    finish(pending)
+}
+
+// Call back with `ec`, unless called back already.
+fn fail<F>(pending: &Mutex<PendingBalances<F>>, ec: ErrorCode) where F: FnMut(Result<AggregateBalance, ErrorCode>) {
+    let cb = lock(pending).cb.take();
+    if let Some(mut cb) = cb {
+        cb(Err(ec));
+    }
+}
+
+// Call back with the balances, every one of them is known.
+fn finish<F>(pending: &Mutex<PendingBalances<F>>) where F: FnMut(Result<AggregateBalance, ErrorCode>) {
+    let (mut cb, result) = {
+        let mut state = lock(pending);
+        let cb = match state.cb.take() {
+            Some(cb) => cb,
+            None => return,
+        };
+        (cb, aggregate(&state))
+    };
+
+    trace!("logic::aggregate_balance::aggregate_balance << result: {:?}", result);
+    cb(result);
+}
+
+// The balances of `state` by wallet, in the order of the wallets and their addresses.
+fn aggregate<F>(state: &PendingBalances<F>) -> Result<AggregateBalance, ErrorCode> {
+    let mut balances = state.balances.iter().cloned();
+    let wallets = state.wallet_handles.iter()
+        .zip(state.addresses.iter())
+        .map(|(wallet_handle, addresses)| {
+            let count = addresses.as_ref().map_or(0, Vec::len);
+            let addresses = balances.by_ref()
+                .take(count)
+                .collect::<Option<Vec<AddressBalance>>>()
+                .ok_or(ErrorCode::CommonInvalidState)?;
+            let balance = checked_sum(addresses.iter().map(|address| address.balance))?;
+            Ok(WalletBalance { wallet_handle: *wallet_handle, addresses, balance })
+        })
+        .collect::<Result<Vec<WalletBalance>, ErrorCode>>()?;
+
+    let total = checked_sum(wallets.iter().map(|wallet| wallet.balance))?;
+    Ok(AggregateBalance { wallets, total })
+}
+
+fn checked_sum<I: Iterator<Item=TokenAmount>>(amounts: I) -> Result<TokenAmount, ErrorCode> {
+    amounts
+        .fold(Some(0), |total: Option<TokenAmount>, amount| total.and_then(|total| total.checked_add(amount)))
+        .ok_or(ErrorCode::CommonInvalidState)
+}
+
+
+#[cfg(test)]
+mod aggregate_balance_tests {
+    use std::collections::HashMap;
+    use std::sync::mpsc::channel;
+
+    use logic::parsers::parse_get_utxo_response::UTXO;
+    use super::*;
+
+    static POOL_HANDLE: i32 = 1;
+
+    // mock SDK payment calls with fixed addresses and amounts, the
+    // async calls call back at once
+    #[derive(Default, Clone)]
+    struct PaymentMockHandler {
+        addresses: HashMap<IndyHandle, Vec<String>>,
+        amounts: HashMap<String, Vec<TokenAmount>>,
+    }
+
+    impl PaymentMockHandler {
+        fn with_address(mut self, wallet_handle: IndyHandle, address: &str, amounts: Vec<TokenAmount>) -> Self {
+            self.addresses.entry(wallet_handle).or_insert_with(Vec::new).push(address.to_string());
+            self.amounts.insert(address.to_string(), amounts);
+            self
+        }
+    }
+
+    impl PaymentAPI for PaymentMockHandler {
+        fn indy_list_payment_addresses(&self, wallet_handle: IndyHandle) -> Result<Vec<String>, ErrorCode> {
+            self.addresses.get(&wallet_handle).cloned().ok_or(ErrorCode::WalletInvalidHandle)
+        }
+
+        fn indy_get_utxos(&self, _: IndyHandle, address: &str) -> Result<ParseGetUtxoReply, ErrorCode> {
+            let amounts = self.amounts.get(address).ok_or(ErrorCode::PoolLedgerTimeout)?;
+            Ok(amounts.iter().enumerate().map(|(seq_no, amount)| UTXO {
+                payment_address: address.to_string(),
+                source: format!("txo:sov:{}", seq_no),
+                amount: *amount,
+                extra: "".to_string(),
+            }).collect())
+        }
+    }
+
+    fn aggregate(aggregator: &Aggregator<PaymentMockHandler>, wallet_handles: &[IndyHandle]) -> Result<AggregateBalance, ErrorCode> {
+        let (sender, receiver) = channel();
+        aggregator.aggregate_balance(wallet_handles, POOL_HANDLE, move |balance| sender.send(balance).unwrap())?;
+        receiver.recv().unwrap()
+    }
+
+    #[test]
+    fn aggregate_over_wallets() {
+        let aggregator = Aggregator::new(PaymentMockHandler::default()
+            .with_address(1, "pay:sov:a", vec![10, 5])
+            .with_address(1, "pay:sov:b", vec![1])
+            .with_address(2, "pay:sov:c", vec![7]));
+
+        let balance = aggregate(&aggregator, &[1, 2]).unwrap();
+
+        assert_eq!(23, balance.total);
+        assert_eq!(16, balance.wallets[0].balance);
+        assert_eq!(AddressBalance { address: String::from("pay:sov:a"), balance: 15, utxo_count: 2 }, balance.wallets[0].addresses[0]);
+        assert_eq!(7, balance.wallets[1].balance);
+    }
+
+    #[test]
+    fn aggregate_unknown_wallet() {
+        let aggregator = Aggregator::new(PaymentMockHandler::default());
+        let error = aggregate(&aggregator, &[3]).unwrap_err();
+        assert_eq!(ErrorCode::WalletInvalidHandle, error);
+    }
+
+    #[test]
+    fn aggregate_overflow() {
+        let aggregator = Aggregator::new(PaymentMockHandler::default()
+            .with_address(1, "pay:sov:a", vec![TokenAmount::max_value(), 1]));
+        let error = aggregate(&aggregator, &[1]).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidState, error);
+    }
+
+    #[test]
+    fn aggregate_no_wallets() {
+        let aggregator = Aggregator::new(PaymentMockHandler::default());
+        assert_eq!(AggregateBalance { wallets: Vec::new(), total: 0 }, aggregate(&aggregator, &[]).unwrap());
+    }
+
+    #[test]
+    fn aggregate_calls_back_once_on_errors() {
+        let mut payment = PaymentMockHandler::default()
+            .with_address(1, "pay:sov:a", vec![1])
+            .with_address(1, "pay:sov:b", vec![2]);
+        payment.amounts.clear();
+        let aggregator = Aggregator::new(payment);
+
+        let (sender, receiver) = channel();
+        aggregator.aggregate_balance(&[1], POOL_HANDLE, move |balance| sender.send(balance).unwrap()).unwrap();
+
+        assert_eq!(ErrorCode::PoolLedgerTimeout, receiver.recv().unwrap().unwrap_err());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn serialize_balance() {
+        let balance = AggregateBalance {
+            wallets: vec![WalletBalance {
+                wallet_handle: 1,
+                addresses: vec![AddressBalance { address: String::from("pay:sov:a"), balance: 3, utxo_count: 1 }],
+                balance: 3,
+            }],
+            total: 3,
+        };
+        let json = ::serde_json::to_value(&balance).unwrap();
+        assert_eq!(json!({
+            "wallets": [{
+                "walletHandle": 1,
+                "addresses": [{"address": "pay:sov:a", "balance": 3, "utxoCount": 1}],
+                "balance": 3
+            }],
+            "total": 3
+        }), json);
+    }
+}
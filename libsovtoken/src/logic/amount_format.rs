@@ -0,0 +1,247 @@
+/*!
+    How token amounts are written in json.
+
+    Json numbers above 2^53 lose precision in JavaScript, where every number
+    is a double, and an amount can be as large as the supply. With
+    [`AmountFormat::String`] the amounts of outputs, UTXOs and fees are
+    emitted as decimal strings, e.g. `"amount": "9007199254740993"`. Both
+    numbers and decimal strings are accepted when deserializing, whatever
+    the format.
+
+    The format is set with `amount_format` in the library config, and can be
+    overridden for a single call with [`with_amount_format`].
+
+    The ledger only knows numbers, so requests and the messages signed for
+    them always use [`AmountFormat::Number`].
+
+    [`AmountFormat::String`]: enum.AmountFormat.html
+    [`AmountFormat::Number`]: enum.AmountFormat.html
+    [`with_amount_format`]: fn.with_amount_format.html
+*/
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde::ser::SerializeMap;
+
+use logic::config::library_config::library_config;
+use logic::type_aliases::TokenAmount;
+use utils::constants::general::SOVATOMS_PER_TOKEN;
+
+/**
+    The json type emitted for token amounts.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AmountFormat {
+    /// A json number, e.g. `10`.
+    Number,
+    /// A decimal string, e.g. `"10"`.
+    String,
+}
+
+impl Default for AmountFormat {
+    fn default() -> Self {
+        AmountFormat::Number
+    }
+}
+
+thread_local! {
+    static OVERRIDE: Cell<Option<AmountFormat>> = Cell::new(None);
+}
+
+/**
+    The amount format to emit on this thread right now.
+*/
+pub fn current() -> AmountFormat {
+    OVERRIDE.with(|format| format.get())
+        .unwrap_or_else(|| library_config().amount_format)
+}
+
+/**
+    Run `f` emitting `format`, whatever the library config says.
+*/
+pub fn with_amount_format<F, R>(format: AmountFormat, f: F) -> R where F: FnOnce() -> R {
+    let previous = OVERRIDE.with(|current| current.replace(Some(format)));
+    let result = f();
+    OVERRIDE.with(|current| current.set(previous));
+    result
+}
+
+/**
+    An amount of sovatoms in tokens, as a decimal string without trailing
+    zeros, to show to users.
+
+    ```
+    use sovtoken::logic::amount_format::tokens;
+    assert_eq!("1.5", tokens(150_000_000));
+    assert_eq!("0.00000001", tokens(1));
+    assert_eq!("10", tokens(1_000_000_000));
+    ```
+*/
+pub fn tokens(sovatoms: TokenAmount) -> String {
+    let whole = sovatoms / SOVATOMS_PER_TOKEN;
+    let fraction = sovatoms % SOVATOMS_PER_TOKEN;
+    if fraction == 0 {
+        return whole.to_string();
+    }
+
+    let digits = SOVATOMS_PER_TOKEN.to_string().len() - 1;
+    let fraction = format!("{:0width$}", fraction, width = digits);
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+/**
+    A token amount, serialized in the [`current`] format and deserialized
+    from a number or a decimal string.
+
+    [`current`]: fn.current.html
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Amount(pub TokenAmount);
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match current() {
+            AmountFormat::Number => serializer.serialize_u64(self.0),
+            AmountFormat::String => serializer.serialize_str(&self.0.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Amount, D::Error> {
+        struct AmountVisitor;
+
+        impl<'de> de::Visitor<'de> for AmountVisitor {
+            type Value = Amount;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an amount as an unsigned integer or a decimal string")
+            }
+
+            fn visit_u64<E: de::Error>(self, amount: u64) -> Result<Amount, E> {
+                Ok(Amount(amount))
+            }
+
+            fn visit_i64<E: de::Error>(self, amount: i64) -> Result<Amount, E> {
+                if amount < 0 {
+                    return Err(E::invalid_value(de::Unexpected::Signed(amount), &self));
+                }
+                Ok(Amount(amount as u64))
+            }
+
+            fn visit_str<E: de::Error>(self, amount: &str) -> Result<Amount, E> {
+                // parse accepts a leading +, an amount is digits only
+                if amount.is_empty() || !amount.bytes().all(|byte| byte.is_ascii_digit()) {
+                    return Err(E::invalid_value(de::Unexpected::Str(amount), &self));
+                }
+                amount.parse::<u64>()
+                    .map(Amount)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Str(amount), &self))
+            }
+        }
+
+        deserializer.deserialize_any(AmountVisitor)
+    }
+}
+
+/**
+    Serialize an amount field in the [`current`] format, for `#[serde(with = "amount_format")]`.
+
+    [`current`]: fn.current.html
+*/
+pub fn serialize<S: Serializer>(amount: &TokenAmount, serializer: S) -> Result<S::Ok, S::Error> {
+    Amount(*amount).serialize(serializer)
+}
+
+/**
+    Deserialize an amount field from a number or a decimal string.
+*/
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<TokenAmount, D::Error> {
+    Amount::deserialize(deserializer).map(|amount| amount.0)
+}
+
+/**
+    Amounts by key, e.g. the fees by transaction type, for
+    `#[serde(with = "amount_format::map")]`.
+*/
+pub mod map {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(amounts: &HashMap<String, TokenAmount>, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(amounts.len()))?;
+        for (key, amount) in amounts {
+            map.serialize_entry(key, &Amount(*amount))?;
+        }
+        map.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, TokenAmount>, D::Error> {
+        let amounts = HashMap::<String, Amount>::deserialize(deserializer)?;
+        Ok(amounts.into_iter().map(|(key, amount)| (key, amount.0)).collect())
+    }
+}
+
+
+#[cfg(test)]
+mod amount_format_tests {
+    use serde_json;
+
+    use super::*;
+
+    const LARGE: TokenAmount = (1 << 53) + 1;
+
+    #[test]
+    fn override_is_restored() {
+        let outer = current();
+        let inner = with_amount_format(AmountFormat::String, || {
+            with_amount_format(AmountFormat::Number, || assert_eq!(AmountFormat::Number, current()));
+            current()
+        });
+
+        assert_eq!(AmountFormat::String, inner);
+        assert_eq!(outer, current());
+    }
+
+    #[test]
+    fn serialize_in_format() {
+        let number = with_amount_format(AmountFormat::Number, || serde_json::to_string(&Amount(LARGE)).unwrap());
+        let string = with_amount_format(AmountFormat::String, || serde_json::to_string(&Amount(LARGE)).unwrap());
+        assert_eq!("9007199254740993", number);
+        assert_eq!("\"9007199254740993\"", string);
+    }
+
+    #[test]
+    fn deserialize_number_or_string() {
+        assert_eq!(Amount(LARGE), serde_json::from_str("9007199254740993").unwrap());
+        assert_eq!(Amount(LARGE), serde_json::from_str("\"9007199254740993\"").unwrap());
+        assert_eq!(Amount(u64::max_value()), serde_json::from_str("\"18446744073709551615\"").unwrap());
+    }
+
+    #[test]
+    fn deserialize_invalid_amounts() {
+        for amount in &["\"\"", "\"+1\"", "\"-1\"", "\"1.5\"", "\"18446744073709551616\"", "-1", "1.5", "null"] {
+            assert!(serde_json::from_str::<Amount>(amount).is_err(), "accepted the amount {}", amount);
+        }
+    }
+
+    #[test]
+    fn map_round_trip() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Fees {
+            #[serde(with = "map")]
+            fees: HashMap<String, TokenAmount>,
+        }
+
+        let mut fees = HashMap::new();
+        fees.insert(String::from("10001"), LARGE);
+        let fees = Fees { fees };
+
+        let json = with_amount_format(AmountFormat::String, || serde_json::to_string(&fees).unwrap());
+        assert_eq!(r#"{"fees":{"10001":"9007199254740993"}}"#, json);
+        assert_eq!(fees, serde_json::from_str(&json).unwrap());
+    }
+}
@@ -1,13 +1,28 @@
 //! TODO ???
 
-use indy::ErrorCode;
+use indy::{ErrorCode, IndyHandle};
 use libc::c_char;
+use logic::amount_format::{tokens, Amount};
+use logic::canonical_order;
 use logic::xfer_payload::{XferPayload, serialize_signature};
-use logic::input::Inputs;
-use logic::output::Outputs;
+use logic::config::library_config::library_config;
+use logic::error::SovtokenError;
+use logic::extra::extra_from_char_ptr;
+use logic::fee_waiver;
+use logic::input::{dedupe_inputs, Inputs};
+use logic::output::{self, Outputs};
+use logic::parsers::common::TXO;
+use logic::request::with_ledger_format;
+use logic::schema::{validate_if_strict, Schema};
+use logic::simulation::BuildOptions;
+use logic::type_aliases::TokenAmount;
+use logic::unknown_fields;
+use logic::utxo_cache;
 use serde_json;
-use utils::ffi_support::{string_from_char_ptr, c_pointer_from_string, c_pointer_from_str};
-use logic::indy_sdk_api::crypto_api::CryptoSdk;
+use serde_json::value::{RawValue, to_raw_value};
+use std::collections::BTreeMap;
+use utils::ffi_support::{string_from_char_ptr, with_c_str};
+use logic::services::services;
 use utils::constants::txn_types::XFER_PUBLIC;
 use utils::constants::txn_fields::FEES;
 use utils::constants::general::JsonCallbackUnwrapped;
@@ -16,7 +31,68 @@ use hex::ToHex;
 
 type SerdeMap = serde_json::Map<String, serde_json::value::Value>;
 type AddRequestFeesCb = extern fn(command_handle_: i32, err: i32, req_with_fees_json: *const c_char) -> i32;
-type DeserializedArguments = (Inputs, Outputs, Option<String>, SerdeMap, AddRequestFeesCb);
+type DeserializedArguments = (Inputs, Outputs, Option<String>, RawRequest, AddRequestFeesCb);
+type RawMap<'a> = BTreeMap<&'a str, &'a RawValue>;
+
+/**
+    The json of a request fees are added to, kept as it was given.
+
+    Requests can be large, e.g. a schema or a revocation registry. Adding
+    the fees reads the keys of the top level object and borrows the values
+    from the json as they are, so the request with fees is written as one
+    new string, without building an owned `Value` of every value on the way.
+    It isn't free of copies: the request is copied into the new string, and
+    the digest the fees are signed over parses the whole request.
+    `fee_injection_on_1mb_request` checks it is faster than the owned values.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawRequest {
+    json: String,
+}
+
+impl RawRequest {
+    /**
+        Check `json` is an object and keep it.
+    */
+    pub fn new(json: String) -> Result<RawRequest, ErrorCode> {
+        parse_raw_map(&json)?;
+        Ok(RawRequest { json })
+    }
+
+    /**
+        The value of `key` at the top level of the request, parsed.
+    */
+    pub fn get(&self, key: &str) -> Option<serde_json::Value> {
+        parse_raw_map(&self.json).ok()
+            .and_then(|map| map.get(key).and_then(|raw| serde_json::from_str(raw.get()).ok()))
+    }
+
+    /**
+        The sha256 of the signature serialization of the request, as hex.
+    */
+    pub fn digest(&self) -> Result<String, ErrorCode> {
+        let value: serde_json::Value = serde_json::from_str(&self.json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure))?;
+        let txn_serialized = serialize_signature(&value)?;
+        let mut hasher = Sha256::default();
+        hasher.input(txn_serialized.as_bytes());
+        Ok(hasher.result().to_hex())
+    }
+
+    /**
+        The json of the request with `fees` at the top level, replacing fees
+        it already had. The other values are copied as they were given.
+    */
+    pub fn with_fees(&self, fees: &serde_json::Value) -> Result<String, ErrorCode> {
+        let fees = to_raw_value(fees).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState))?;
+        let mut map = parse_raw_map(&self.json)?;
+        map.insert(FEES, &*fees);
+        serde_json::to_string(&map).or(Err(ErrorCode::CommonInvalidStructure))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.json
+    }
+}
 
 /**
  * Deserializes arguments of [`add_request_fees_handler`]
@@ -27,6 +103,23 @@ pub fn deserialize_inputs (
     outputs_json: *const c_char,
     extra: *const c_char,
     cb: Option<AddRequestFeesCb>
+) -> Result<DeserializedArguments, ErrorCode> {
+    deserialize_inputs_with_options(req_json, inputs_json, outputs_json, extra, cb, &BuildOptions::default())
+}
+
+/**
+    As [`deserialize_inputs`], outputs of 0 tokens checked and the inputs and
+    outputs put in order with `options`.
+
+    [`deserialize_inputs`]: fn.deserialize_inputs.html
+*/
+pub fn deserialize_inputs_with_options(
+    req_json: *const c_char,
+    inputs_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    cb: Option<AddRequestFeesCb>,
+    options: &BuildOptions
 ) -> Result<DeserializedArguments, ErrorCode> {
     debug!("logic::add_request_fees::deserialize_inputs >> req_json: {:?}, inputs_json: {:?}, outputs_json: {:?}", req_json, inputs_json, outputs_json);
 
@@ -41,38 +134,42 @@ pub fn deserialize_inputs (
     let outputs_json = string_from_char_ptr(outputs_json).ok_or(ErrorCode::CommonInvalidStructure).map_err(map_err_err!())?;
     debug!("Converted outputs_json pointer to string >>> {:?}", outputs_json);
 
-    let extra = string_from_char_ptr(extra);
+    let extra = extra_from_char_ptr(extra)?;
     debug!("Converted extra pointer to string >>> {:?}", extra);
 
-    let inputs: Inputs = serde_json::from_str(&inputs_json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure))?;
+    validate_if_strict(Schema::Inputs, &inputs_json)?;
+    validate_if_strict(Schema::Outputs, &outputs_json)?;
+
+    let inputs: Inputs = unknown_fields::from_json(&inputs_json, "inputs")?;
     debug!("Deserialized input_json >>> {:?}", inputs);
 
-    let outputs: Outputs = serde_json::from_str(&outputs_json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure))?;
+    let mut inputs = dedupe_inputs(inputs, library_config().duplicate_inputs)
+        .map_err(|error| ErrorCode::from(SovtokenError::from(error)))?;
+
+    let mut outputs: Outputs = unknown_fields::from_json(&outputs_json, "outputs")?;
     debug!("Deserialized output_json >>> {:?}", outputs);
 
-    let request_json_object: serde_json::Value = serde_json::from_str(&request_json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure))?;
-    trace!("Converted request_json to serde::json::Value");
+    output::check_amounts(&outputs, options.zero_outputs_allowed()).map_err(ErrorCode::from)?;
+    canonical_order::put_in_order(&mut inputs, &mut outputs, options.preserve_order);
 
-    let request_json_map = request_json_object.as_object().ok_or(ErrorCode::CommonInvalidStructure).map_err(map_err_err!())?;
-    trace!("Converted request_json to hash_map");
+    let request = RawRequest::new(request_json)?;
+    trace!("Checked request_json is an object");
 
-    debug!("Deserialized values: inputs: {:?}, outputs: {:?}, request_json_map: {:?}", inputs, outputs, request_json_map);
+    debug!("Deserialized values: inputs: {:?}, outputs: {:?}", inputs, outputs);
     return Ok((
         inputs,
         outputs,
         extra,
-        request_json_map.to_owned(),
+        request,
         cb,
     ));
 }
 
-pub fn validate_type_not_transfer(request_json_map: &SerdeMap) -> Result<(), ErrorCode> {
-    let key_operation = String::from("operation");
-                                                              
+pub fn validate_type_not_transfer(request: &RawRequest) -> Result<(), ErrorCode> {
     trace!("Getting type from request_json");
-    let transaction_type = request_json_map
-        .get(&key_operation)
-        .and_then(|operation| operation.get("type"))
+    let transaction_type = request
+        .get("operation")
+        .and_then(|operation| operation.get("type").cloned())
         .ok_or(ErrorCode::CommonInvalidStructure)?;
     debug!("Request transaction type was >>> {}", transaction_type);
 
@@ -83,21 +180,144 @@ pub fn validate_type_not_transfer(request_json_map: &SerdeMap) -> Result<(), Err
     };
 }
 
+/**
+    The type of the operation of the request, given as a string or a number.
+*/
+pub fn txn_type(request: &RawRequest) -> Option<String> {
+    match request.get("operation").map(|operation| operation["type"].clone()) {
+        Some(serde_json::Value::String(txn_type)) => Some(txn_type),
+        Some(serde_json::Value::Number(txn_type)) => Some(txn_type.to_string()),
+        _ => None,
+    }
+}
+
+/**
+    Whether a parsed fee schedule set the fee of the type of the request to
+    0 on the network of `pool_handle`, see [`fee_waiver`].
+
+    [`fee_waiver`]: ../../fee_waiver/index.html
+*/
+pub fn fees_waived(request: &RawRequest, pool_handle: Option<IndyHandle>) -> bool {
+    txn_type(request).map_or(false, |txn_type| fee_waiver::is_waived(pool_handle, &txn_type))
+}
+
+/**
+    What the fees added to a request amount to, to show them without
+    parsing the request.
+
+    ```text
+    {
+        "feeTotal": <int>,              // what the inputs give beyond the outputs, null when
+                                        // the amount of an input isn't known
+        "feeTotalTokens": <string>,     // the same in tokens, e.g. "0.5"
+        "changeTotal": <int>,           // what the fee outputs give back
+        "changeTotalTokens": <string>,
+        "inputsUsed": [<string>, ...]   // the txos paying the fees
+    }
+    ```
+*/
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FeesMetadata {
+    pub fee_total: Option<Amount>,
+    pub fee_total_tokens: Option<String>,
+    pub change_total: Amount,
+    pub change_total_tokens: String,
+    pub inputs_used: Vec<String>,
+}
+
+impl FeesMetadata {
+    /**
+        The metadata of the fees `inputs` and `outputs` pay, the amounts of
+        the inputs taken from the GET_UTXO replies cached for the network of
+        `pool_handle`, see [`utxo_cache`].
+
+        Fails as [`of_amounts`] does.
+
+        [`utxo_cache`]: ../../utxo_cache/index.html
+        [`of_amounts`]: struct.FeesMetadata.html#method.of_amounts
+    */
+    pub fn new(inputs: &Inputs, outputs: &Outputs, pool_handle: Option<IndyHandle>) -> Result<FeesMetadata, ErrorCode> {
+        let inputs_used = txos(inputs)?;
+        let input_amounts: Vec<Option<TokenAmount>> = inputs_used.iter()
+            .map(|txo| utxo_cache::known_amount(pool_handle, txo))
+            .collect();
+        FeesMetadata::of(inputs_used, &input_amounts, outputs)
+    }
+
+    /**
+        The metadata of the fees `inputs` and `outputs` pay, `input_amounts`
+        the amounts of the inputs, `None` for an amount not known.
+
+        Fails with `CommonInvalidStructure` when the inputs or the outputs
+        add up to more than a `TokenAmount`, and with
+        `PaymentInsufficientFundsError` when the outputs add up to more than
+        the inputs.
+    */
+    pub fn of_amounts(inputs: &Inputs, input_amounts: &[Option<TokenAmount>], outputs: &Outputs) -> Result<FeesMetadata, ErrorCode> {
+        FeesMetadata::of(txos(inputs)?, input_amounts, outputs)
+    }
+
+    /**
+        The metadata of a request whose fees are waived.
+    */
+    pub fn waived() -> FeesMetadata {
+        FeesMetadata {
+            fee_total: Some(Amount(0)),
+            fee_total_tokens: Some(tokens(0)),
+            change_total: Amount(0),
+            change_total_tokens: tokens(0),
+            inputs_used: Vec::new(),
+        }
+    }
+
+    /* Methods "private" (aka not exported from this module)
+
+        KEEP all public methods above
+    */
+
+    fn of(inputs_used: Vec<String>, input_amounts: &[Option<TokenAmount>], outputs: &Outputs) -> Result<FeesMetadata, ErrorCode> {
+        let mut change_total: TokenAmount = 0;
+        for output in outputs {
+            change_total = change_total.checked_add(output.amount).ok_or(ErrorCode::CommonInvalidStructure)?;
+        }
+
+        let mut inputs_total: Option<TokenAmount> = Some(0);
+        for amount in input_amounts {
+            inputs_total = match (inputs_total, *amount) {
+                (Some(total), Some(amount)) => Some(total.checked_add(amount).ok_or(ErrorCode::CommonInvalidStructure)?),
+                _ => None,
+            };
+        }
+
+        let fee_total = match inputs_total {
+            Some(inputs_total) => Some(inputs_total.checked_sub(change_total).ok_or(ErrorCode::PaymentInsufficientFundsError)?),
+            None => None,
+        };
+
+        Ok(FeesMetadata {
+            fee_total: fee_total.map(Amount),
+            fee_total_tokens: fee_total.map(tokens),
+            change_total: Amount(change_total),
+            change_total_tokens: tokens(change_total),
+            inputs_used,
+        })
+    }
+}
+
 pub fn add_fees_to_request_and_serialize(
     wallet_handle: i32,
     inputs: Inputs,
     outputs: Outputs,
     extra: Option<String>,
-    request_json_map: SerdeMap,
+    request: RawRequest,
     cb: Box<Fn(Result<String, ErrorCode>) + Send + Sync>
 ) -> Result<(), ErrorCode> {
-    trace!("logic::add_request_fees::add_fees_to_request_and_serialize >> wallet_handle: {:?}, inputs: {:?}, outputs: {:?}, request_json_map: {:?}", wallet_handle, inputs, outputs, request_json_map);
-    let res = add_fees(wallet_handle, inputs, outputs, extra, request_json_map, Box::new(move |request_json_map_updated|{
-        let rm_fees = request_json_map_updated.map(|request_json_map_with_fees| serialize_request_with_fees(request_json_map_with_fees));
-        match rm_fees {
-            Ok(some) => cb(some),
-            Err(e) => cb(Err(e))
-        }
+    trace!("logic::add_request_fees::add_fees_to_request_and_serialize >> wallet_handle: {:?}, inputs: {:?}, outputs: {:?}", wallet_handle, inputs, outputs);
+    let txn_digest = Some(request.digest()?);
+    let res = signed_fees(wallet_handle, inputs, outputs, extra, &txn_digest, Box::new(move |fees| {
+        trace!("Added fees to request_json.");
+        cb(fees.and_then(|fees| request.with_fees(&with_ledger_format(|| json!([fees.inputs, fees.outputs, fees.signatures])))));
     }));
     trace!("logic::add_request_fees::add_fees_to_request_and_serialize >> result: {:?}", res);
     res
@@ -110,21 +330,18 @@ pub fn closure_cb_response(command_handle: i32, cb: JsonCallbackUnwrapped) -> im
     move |res| {
         trace!("add_request_fees::closure_cb_response Request with fees >> {:?}", res);
         match res {
-            Ok(res) => cb(command_handle, ErrorCode::Success as i32, c_pointer_from_string(res)),
-            Err(e) => cb(command_handle, e as i32, c_pointer_from_str("")),
+            Ok(res) => with_c_str(&res, |res| cb(command_handle, ErrorCode::Success as i32, res)),
+            Err(e) => with_c_str("", |res| cb(command_handle, e as i32, res)),
         };
     }    
 }
 
-
-/*
-    Methods "private" (aka not exported from this module)
-
-    KEEP all public methods above
+/**
+    Sign `inputs` and `outputs` over the digest of the request and add them as its fees.
 */
-
-fn add_fees(wallet_handle: i32, inputs: Inputs, outputs: Outputs, extra: Option<String>, request_json_map: SerdeMap, cb: Box<Fn(Result<SerdeMap, ErrorCode>) + Send + Sync>) -> Result<(), ErrorCode> {
-    let txn_serialized = serialize_signature(request_json_map.clone().into())?;
+pub fn add_fees(wallet_handle: i32, inputs: Inputs, outputs: Outputs, extra: Option<String>, request_json_map: SerdeMap, cb: Box<Fn(Result<SerdeMap, ErrorCode>) + Send + Sync>) -> Result<(), ErrorCode> {
+    let request_json_map = serde_json::Value::Object(request_json_map);
+    let txn_serialized = serialize_signature(&request_json_map)?;
     let mut hasher = Sha256::default();
     hasher.input(txn_serialized.as_bytes());
     let txn_digest = Some(hasher.result().to_hex());
@@ -132,9 +349,9 @@ fn add_fees(wallet_handle: i32, inputs: Inputs, outputs: Outputs, extra: Option<
         trace!("Added fees to request_json.");
         match fees {
             Ok(fees) => {
-                let mut map = request_json_map.clone();
-                map.insert(FEES.to_string(), json!([fees.inputs, fees.outputs, fees.signatures]));
-                cb(Ok(map.clone()));
+                let mut map = request_json_map.as_object().cloned().unwrap_or_default();
+                map.insert(FEES.to_string(), with_ledger_format(|| json!([fees.inputs, fees.outputs, fees.signatures])));
+                cb(Ok(map));
             }
             Err(err) => {
                 cb(Err(err))
@@ -145,18 +362,26 @@ fn add_fees(wallet_handle: i32, inputs: Inputs, outputs: Outputs, extra: Option<
     Ok(())
 }
 
-fn serialize_request_with_fees(request_json_map_with_fees: SerdeMap) -> Result<String, ErrorCode> {
-    trace!("fee_map: {:?}", request_json_map_with_fees);
-    let serialized_request_with_fees = serde_json::to_string(&json!(request_json_map_with_fees))
-        .or(Err(ErrorCode::CommonInvalidStructure))?;
-    trace!("Serialized request_with_fees");
-    
-    return Ok(serialized_request_with_fees);
-} 
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// the libindy strings of the txos of `inputs`
+fn txos(inputs: &Inputs) -> Result<Vec<String>, ErrorCode> {
+    inputs.iter()
+        .map(|input| TXO::new(&input.address, input.seq_no).to_libindy_string())
+        .collect()
+}
+
+fn parse_raw_map(json: &str) -> Result<RawMap, ErrorCode> {
+    serde_json::from_str(json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure))
+}
 
 fn signed_fees(wallet_handle: i32, inputs: Inputs, outputs: Outputs, extra: Option<String>, txn_digest: &Option<String>, cb: Box<Fn(Result<XferPayload, ErrorCode>) + Send + Sync>) -> Result<(), ErrorCode> {
     let fees = XferPayload::new(inputs, outputs, extra);
-    fees.sign_fees(&CryptoSdk{}, wallet_handle, txn_digest, cb)?;
+    fees.sign_fees(&services().crypto, wallet_handle, txn_digest, cb)?;
     Ok(())
 }
 
@@ -165,12 +390,18 @@ fn signed_fees(wallet_handle: i32, inputs: Inputs, outputs: Outputs, extra: Opti
 mod test_deserialize_inputs {
     use libc::c_char;
     use indy::ErrorCode;
-    use serde_json;
     use std::ptr;
     use utils::constants::txn_types::XFER_PUBLIC;
+    use utils::test::context::in_own_context;
     use utils::test::default;
 
-    use super::{deserialize_inputs, AddRequestFeesCb, DeserializedArguments};
+    use std::collections::HashMap;
+
+    use logic::config::library_config::LibraryConfig;
+    use logic::fee_waiver;
+    use logic::amount_format::Amount;
+    use logic::type_aliases::TokenAmount;
+    use super::{deserialize_inputs, fees_waived, txn_type, AddRequestFeesCb, DeserializedArguments, FeesMetadata, RawRequest};
     use super::validate_type_not_transfer;
 
     pub fn call_deserialize_inputs(
@@ -216,7 +447,7 @@ mod test_deserialize_inputs {
         assert_eq!(error, result.unwrap_err());
     }
 
-    fn deserialize_request_json(json_pointer: *const c_char) -> serde_json::Map<String, serde_json::value::Value> {
+    fn deserialize_request_json(json_pointer: *const c_char) -> RawRequest {
         let (_, _, _, request, _) = call_deserialize_inputs(Some(json_pointer), None, None, None, None).unwrap();
         return request;
     }
@@ -320,6 +551,54 @@ mod test_deserialize_inputs {
         let validated = validate_type_not_transfer(&request);
         assert!(validated.is_ok());
     }
+
+    #[test]
+    fn txn_type_string_or_number() {
+        in_own_context(LibraryConfig::default(), || {
+            let request = deserialize_request_json(json_c_pointer!({"operation": {"type": 94001}}));
+            assert_eq!(Some(String::from("94001")), txn_type(&request));
+            assert!(!fees_waived(&request, None));
+
+            let mut fees = HashMap::new();
+            fees.insert(String::from("94001"), 0);
+            fee_waiver::remember_schedule(None, &fees);
+            assert!(fees_waived(&request, None));
+
+            let request = deserialize_request_json(json_c_pointer!({"operation": {"type": "94001"}}));
+            assert!(fees_waived(&request, None));
+        });
+    }
+
+    #[test]
+    fn fees_metadata() {
+        in_own_context(LibraryConfig::default(), || {
+            let (inputs, outputs) = (default::inputs(), default::outputs());
+
+            let metadata = FeesMetadata::new(&inputs, &outputs, None).unwrap();
+            assert_eq!(None, metadata.fee_total);
+            assert_eq!(inputs.len(), metadata.inputs_used.len());
+            assert!(metadata.inputs_used.iter().all(|txo| txo.starts_with("txo:sov:")));
+            assert_eq!(Amount(32), metadata.change_total);
+
+            let metadata = FeesMetadata::of_amounts(&inputs, &[Some(150_000_000), Some(32)], &outputs).unwrap();
+            assert_eq!(Some(Amount(150_000_000)), metadata.fee_total);
+            assert_eq!(Some(String::from("1.5")), metadata.fee_total_tokens);
+
+            let metadata = FeesMetadata::of_amounts(&inputs, &[Some(150_000_000), None], &outputs).unwrap();
+            assert_eq!(None, metadata.fee_total);
+        });
+    }
+
+    #[test]
+    fn fees_metadata_of_amounts_checked() {
+        let (inputs, outputs) = (default::inputs(), default::outputs());
+
+        let error = FeesMetadata::of_amounts(&inputs, &[Some(10), Some(10)], &outputs).unwrap_err();
+        assert_eq!(ErrorCode::PaymentInsufficientFundsError, error);
+
+        let error = FeesMetadata::of_amounts(&inputs, &[Some(TokenAmount::max_value()), Some(1)], &outputs).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error);
+    }
 }
 
 #[cfg(test)]
@@ -350,3 +629,88 @@ mod closure_cb_response_test {
         assert_eq!(String::from("Heyahh"), result.1);
     }
 }
+
+#[cfg(test)]
+mod raw_request_test {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn request_of_size(bytes: usize) -> String {
+        json!({
+            "identifier": "6ouriXMZkLeHsuXrN1X1fd",
+            "operation": {
+                "type": "101",
+                "data": {
+                    "name": "large",
+                    "version": "1.0",
+                    "attr_names": (0..bytes / 16).map(|i| format!("attribute_{:05}", i)).collect::<Vec<String>>()
+                }
+            },
+            "protocolVersion": 2,
+            "reqId": 1
+        }).to_string()
+    }
+
+    // the way fees were added before, through an owned copy of every value
+    fn with_fees_owned(json: &str, fees: &serde_json::Value) -> String {
+        let mut map: SerdeMap = serde_json::from_str(json).unwrap();
+        map.insert(FEES.to_string(), fees.clone());
+        serde_json::to_string(&json!(map)).unwrap()
+    }
+
+    #[test]
+    fn request_must_be_an_object() {
+        assert_eq!(ErrorCode::CommonInvalidStructure, RawRequest::new(String::from("[]")).unwrap_err());
+        assert_eq!(ErrorCode::CommonInvalidStructure, RawRequest::new(String::from("{")).unwrap_err());
+    }
+
+    #[test]
+    fn values_are_kept_as_given() {
+        let request = RawRequest::new(String::from(r#"{"reqId":1,"operation":{"type":"1", "dest":"x"},"fees":[]}"#)).unwrap();
+        let with_fees = request.with_fees(&json!([[], [], []])).unwrap();
+        assert_eq!(r#"{"fees":[[],[],[]],"operation":{"type":"1", "dest":"x"},"reqId":1}"#, with_fees);
+    }
+
+    #[test]
+    fn same_request_as_owned_values() {
+        let json = request_of_size(1024);
+        let fees = json!([[{"address": "a", "seqNo": 1}], [], ["signature"]]);
+
+        let raw: serde_json::Value = serde_json::from_str(&RawRequest::new(json.clone()).unwrap().with_fees(&fees).unwrap()).unwrap();
+        let owned: serde_json::Value = serde_json::from_str(&with_fees_owned(&json, &fees)).unwrap();
+        assert_eq!(owned, raw);
+    }
+
+    #[test]
+    fn digest_ignores_fees() {
+        let request = RawRequest::new(request_of_size(64)).unwrap();
+        let with_fees = RawRequest::new(request.with_fees(&json!([[], [], []])).unwrap()).unwrap();
+        assert_eq!(request.digest().unwrap(), with_fees.digest().unwrap());
+    }
+
+    // cargo test --release fee_injection_on_1mb_request -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn fee_injection_on_1mb_request() {
+        let json = request_of_size(1024 * 1024);
+        let fees = json!([[{"address": "a", "seqNo": 1}], [{"address": "b", "amount": 1}], ["signature"]]);
+        let runs = 20;
+
+        let started = Instant::now();
+        for _ in 0..runs {
+            with_fees_owned(&json, &fees);
+        }
+        let owned = started.elapsed() / runs;
+
+        let request = RawRequest::new(json.clone()).unwrap();
+        let started = Instant::now();
+        for _ in 0..runs {
+            request.with_fees(&fees).unwrap();
+        }
+        let raw = started.elapsed() / runs;
+
+        println!("{} bytes request, owned values: {:?}, raw values: {:?}", json.len(), owned, raw);
+        assert!(raw < owned);
+    }
+}
@@ -7,9 +7,13 @@ Logic for the [`create_payment_address_handler`].
 use std::os::raw::c_char;
 
 use indy::ErrorCode;
+use serde_json;
 use logic::config::payment_address_config::PaymentAddressConfig;
+use logic::error::SovtokenError;
+use logic::messages::{self, LastError};
+use logic::unknown_fields;
 use utils::constants::general::{JsonCallback, JsonCallbackUnwrapped};
-use utils::ffi_support::{string_from_char_ptr, cstring_from_str, c_pointer_from_str};
+use utils::ffi_support::{string_from_char_ptr, with_c_str};
 use utils::json_conversion::JsonDeserialize;
 
 type DeserializedArguments = (PaymentAddressConfig, JsonCallbackUnwrapped);
@@ -32,9 +36,20 @@ pub fn deserialize_arguments(
     debug!("api::create_payment_address_handler json_config_string >> {:?}", json_config_string);
 
     // TODO: Only continue when seed is missing, not on any error.
-    let config = PaymentAddressConfig::from_json(&json_config_string)
-        .map_err(map_err_trace!())
-        .unwrap_or(PaymentAddressConfig { seed: "".to_string() });
+    // A misspelled key fails, it would otherwise create an address from a random seed.
+    let config = match PaymentAddressConfig::from_json(&json_config_string) {
+        Ok(config) => config,
+        Err(error) => {
+            if let Some(error) = unknown_fields::unknown_field(&error, "payment address config") {
+                return Err(ErrorCode::from(error));
+            }
+            trace!("{:?}", error);
+            PaymentAddressConfig { seed: "".to_string(), strict_seed: false, reuse_existing: false, seed_encoding: None }
+        }
+    };
+    let config = config
+        .validate()
+        .map_err(|error| ErrorCode::from(SovtokenError::from(error)))?;
 
     debug!("api::create_payment_address_handler PaymentAddressConfig >> {:?}", config);
 
@@ -43,23 +58,76 @@ pub fn deserialize_arguments(
 
 /**
 Create a callback for address creation.
+
+The error of a failed creation is passed on as the error code of the
+callback, with the error as json, as `sovtoken_get_last_error_json` gives
+it: the callback runs on a thread of libindy, whose last error the
+application can't read. For a seed used before, its `args` hold the
+existing address.
 */
-pub fn create_address_cb(command_handle: i32, cb: JsonCallbackUnwrapped) -> impl Fn(String, ErrorCode) {
-    move | payment_address: String, error_code: ErrorCode | {
-        if error_code != ErrorCode::Success {
-            error!("create payment address failed ErrorCode={:?}", error_code);
-            cb(command_handle, error_code as i32, c_pointer_from_str(""));
-            return;
-        }
+pub fn create_address_cb(command_handle: i32, cb: JsonCallbackUnwrapped) -> impl Fn(Result<String, SovtokenError>) {
+    move | payment_address: Result<String, SovtokenError> | {
+        let payment_address = match payment_address {
+            Ok(payment_address) => payment_address,
+            Err(error) => {
+                error!("create payment address failed {}", error);
+                let error_code = error.error_code();
+                let error_json = serde_json::to_string(&LastError { code: error_code as i32, message: error.message() }).unwrap_or_default();
+                with_c_str(&error_json, |error_json| cb(command_handle, error_code as i32, error_json));
+                return;
+            }
+        };
 
         debug!("create_payment_address_handler returning payment address of '{}'", &payment_address);
-        let payment_address_cstring = cstring_from_str(payment_address);
-        let payment_address_ptr = payment_address_cstring.as_ptr();
+        with_c_str(&payment_address, |payment_address| cb(command_handle, ErrorCode::Success as i32, payment_address));
+    }
+}
+
+/**
+The config json of the [`create_payment_addresses_handler`].
 
-        cb(command_handle, ErrorCode::Success as i32, payment_address_ptr);   
+[`create_payment_addresses_handler`]: sovtoken::api::create_payment_addresses_handler
+*/
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BatchAddressConfig {
+    pub configs: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub stop_on_failure: bool,
+}
+
+impl BatchAddressConfig {
+    /**
+    The json of each config, to create its address with libindy, or why it
+    is invalid.
+
+    An invalid config fails only its own address, not the batch.
+    */
+    pub fn config_jsons(self) -> Vec<Result<String, SovtokenError>> {
+        self.configs.into_iter()
+            .map(|config| {
+                let json = config.to_string();
+                payment_address_config(config).map(|_| json)
+            })
+            .collect()
     }
 }
 
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn payment_address_config(config: serde_json::Value) -> Result<PaymentAddressConfig, SovtokenError> {
+    serde_json::from_value::<PaymentAddressConfig>(config)
+        .map_err(|error| {
+            unknown_fields::unknown_field(&error, "payment address config")
+                .unwrap_or_else(|| SovtokenError::InvalidStructure(messages::INVALID_CONFIG.with(&[error.to_string()])))
+        })?
+        .validate()
+        .map_err(SovtokenError::from)
+}
+
 #[cfg(test)]
 mod deserialize_arguments_test {
     use super::*;
@@ -91,8 +159,6 @@ mod deserialize_arguments_test {
     }
 
     #[test]
-    #[ignore]
-    // TODO: Validate the seed is 32 characters long.
     fn test_config_with_bad_seed()
     {
         // The seed needs to be 32 characters long.
@@ -112,6 +178,27 @@ mod deserialize_arguments_test {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_config_with_misspelled_seed()
+    {
+        let config_pointer = json_c_pointer!({
+            "sed": "qeWFjZkt9Cr4mhh1VQMrQrKF8a1CeXqN"
+        });
+        let result = call_deserialize_arguments(Some(config_pointer), None);
+        assert_eq!(ErrorCode::CommonInvalidStructure, result.unwrap_err());
+    }
+
+    #[test]
+    fn test_config_with_weak_seed_when_strict()
+    {
+        let config_pointer = json_c_pointer!({
+            "seed": "00000000000000000000000000000000",
+            "strict_seed": true
+        });
+        let result = call_deserialize_arguments(Some(config_pointer), None);
+        assert_eq!(ErrorCode::CommonInvalidStructure, result.unwrap_err());
+    }
+
     #[test]
     fn test_config_with_seed()
     {
@@ -137,36 +224,83 @@ mod create_address_cb_test {
     use utils::test::callbacks;
     use std::sync::mpsc::RecvError;
 
-    fn call_callback(address: String, error_code: ErrorCode)
+    fn call_callback(address: Result<String, SovtokenError>)
         -> Result<(ErrorCode, String), RecvError>
     {
         let (receiver, command_handle, cb) = callbacks::cb_ec_string();
-        create_address_cb(command_handle, cb.unwrap())(address, error_code);
+        create_address_cb(command_handle, cb.unwrap())(address);
         receiver.recv()
     }
 
     #[test]
     fn test_cb_called_on_error()
     {
-        let result = call_callback(
-            String::from("pay:sov:AesjahdahudgaiuNotARealAKeyygigfuigraiudgfasfhja"),
-            ErrorCode::CommonInvalidState
-        ).unwrap();
+        let result = call_callback(Err(SovtokenError::Indy(ErrorCode::CommonInvalidState))).unwrap();
 
         assert_eq!(ErrorCode::CommonInvalidState, result.0);
-        assert_eq!("", result.1);
+        let error: LastError = serde_json::from_str(&result.1).unwrap();
+        assert_eq!("SOV-0025", error.message.id);
+    }
+
+    #[test]
+    fn test_cb_called_with_the_existing_address()
+    {
+        let address = String::from("pay:sov:AesjahdahudgaiuNotARealAKeyygigfuigraiudgfasfhja");
+        let error = SovtokenError::AlreadyExists(messages::ADDRESS_ALREADY_EXISTS.with(&[address.clone()]));
+        let result = call_callback(Err(error)).unwrap();
+
+        assert_eq!(ErrorCode::WalletItemAlreadyExists, result.0);
+        let error: LastError = serde_json::from_str(&result.1).unwrap();
+        assert_eq!(ErrorCode::WalletItemAlreadyExists as i32, error.code);
+        assert_eq!("SOV-0042", error.message.id);
+        assert_eq!(vec![address], error.message.args);
     }
 
     #[test]
     fn test_cb_called_on_success()
     {
         let address = String::from("pay:sov:AesjahdahudgaiuNotARealAKeyygigfuigraiudgfasfhja");
-        let result = call_callback(
-            address.clone(),
-            ErrorCode::Success
-        ).unwrap();
+        let result = call_callback(Ok(address.clone())).unwrap();
 
         assert_eq!(ErrorCode::Success, result.0);
         assert_eq!(address, result.1);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod batch_address_config_test {
+    use super::*;
+
+    #[test]
+    fn each_config_is_validated() {
+        let batch = BatchAddressConfig::from_json(r#"{
+            "configs": [{}, {"seed": "declivity"}, {"seed": "qeWFjZkt9Cr4mhh1VQMrQrKF8a1CeXqN"}, 7]
+        }"#).unwrap();
+        assert!(!batch.stop_on_failure);
+
+        let configs = batch.config_jsons();
+        assert_eq!(4, configs.len());
+        assert_eq!("{}", configs[0].as_ref().unwrap());
+        assert_eq!(ErrorCode::CommonInvalidStructure, configs[1].as_ref().unwrap_err().error_code());
+        assert!(configs[2].is_ok());
+        assert!(configs[3].is_err());
+    }
+
+    #[test]
+    fn config_jsons_of_valid_configs() {
+        let batch = BatchAddressConfig::from_json(r#"{
+            "configs": [{"seed": "qeWFjZkt9Cr4mhh1VQMrQrKF8a1CeXqN", "strict_seed": true}, {"seed": "declivity"}]
+        }"#).unwrap();
+
+        let configs = batch.config_jsons();
+        let config: serde_json::Value = serde_json::from_str(configs[0].as_ref().unwrap()).unwrap();
+        assert_eq!(json!({"seed": "qeWFjZkt9Cr4mhh1VQMrQrKF8a1CeXqN", "strict_seed": true}), config);
+        assert_eq!(ErrorCode::CommonInvalidStructure, configs[1].as_ref().unwrap_err().error_code());
+    }
+
+    #[test]
+    fn stop_on_failure() {
+        let batch = BatchAddressConfig::from_json(r#"{"configs": [], "stopOnFailure": true}"#).unwrap();
+        assert!(batch.stop_on_failure);
+    }
+}
@@ -0,0 +1,316 @@
+/*!
+    Refreshing a request the ledger rejected for a stale `reqId` or
+    transaction author agreement acceptance.
+
+    The request gets a new `reqId` and its acceptance the current time. Every
+    signature over the request is made again with the keys in the wallet: the
+    input signatures of an XFER_PUBLIC operation, the signatures of the fees
+    and the signatures of the submitter DIDs.
+
+    The digest of the accepted agreement is kept as it is, a request
+    rejected for accepting an outdated agreement needs to be built again.
+*/
+
+use std::sync::Arc;
+
+use indy::ErrorCode;
+use serde_json;
+use serde_json::Value as SJsonValue;
+
+use logic::address::add_qualifer_to_address;
+use logic::api_internals::add_request_fees::add_fees;
+use logic::config::payment_config::PaymentRequest;
+use logic::services::services;
+use logic::indy_sdk_api::ledger_api::LedgerAPI;
+use logic::input::{Input, Inputs};
+use logic::output::{Output, Outputs};
+use logic::request::with_ledger_format;
+use logic::xfer_payload::XferPayload;
+use utils::constants::txn_fields::FEES;
+use utils::constants::txn_types::XFER_PUBLIC;
+use utils::random::next_req_id;
+
+type SerdeMap = serde_json::Map<String, SJsonValue>;
+type RequestCallback = Arc<Fn(Result<String, ErrorCode>) + Send + Sync>;
+
+const TAA_ACCEPTANCE: &str = "taaAcceptance";
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/**
+    The DIDs whose signatures a request carries.
+*/
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct DidSigners {
+    /// The submitter of a request with a single `signature`.
+    pub single: Option<String>,
+    /// The DIDs of a multi signed request.
+    pub multi: Vec<String>,
+}
+
+/**
+    Give `request` a new `reqId` and accept the agreement at `now`.
+
+    Removes the DID signatures, which don't match the request anymore, and
+    returns who made them. `now` is in seconds since the unix epoch, the
+    acceptance time is rounded down to the day as libindy does.
+*/
+pub fn refresh_envelope(request: &mut SerdeMap, now: u64) -> Result<DidSigners, ErrorCode> {
+    let mut signers = DidSigners::default();
+
+    if request.remove("signature").is_some() {
+        let identifier = request.get("identifier")
+            .and_then(|identifier| identifier.as_str())
+            .ok_or(ErrorCode::CommonInvalidStructure)?;
+        signers.single = Some(identifier.to_string());
+    }
+
+    if let Some(signatures) = request.remove("signatures") {
+        let signatures = signatures.as_object().ok_or(ErrorCode::CommonInvalidStructure)?;
+        signers.multi = signatures.keys().cloned().collect();
+    }
+
+    request.insert(String::from("reqId"), json!(next_req_id()));
+
+    if let Some(acceptance) = request.get_mut(TAA_ACCEPTANCE) {
+        let acceptance = acceptance.as_object_mut().ok_or(ErrorCode::CommonInvalidStructure)?;
+        acceptance.insert(String::from("time"), json!(now / SECONDS_PER_DAY * SECONDS_PER_DAY));
+    }
+
+    Ok(signers)
+}
+
+/**
+    Refresh `request_json` and sign it again with the keys in `wallet_handle`.
+
+    `cb` is called with the refreshed request json.
+*/
+pub fn refresh_request(wallet_handle: i32, request_json: &str, now: u64, cb: Box<Fn(Result<String, ErrorCode>) + Send + Sync>) -> Result<(), ErrorCode> {
+    trace!("logic::refresh_request::refresh_request >> request_json: {:?}, now: {:?}", request_json, now);
+    let mut request: SerdeMap = serde_json::from_str(request_json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    let signers = refresh_envelope(&mut request, now)?;
+    let cb: RequestCallback = Arc::from(cb);
+    let finish = move |request: Result<SerdeMap, ErrorCode>| {
+        let request_json = request.and_then(|request| {
+            serde_json::to_string(&request)
+                .map_err(map_err_err!())
+                .or(Err(ErrorCode::CommonInvalidState))
+        });
+        match request_json {
+            Ok(request_json) => sign_with_dids(&services().ledger, wallet_handle, request_json, signing_steps(&signers), Arc::clone(&cb)),
+            Err(ec) => cb(Err(ec)),
+        }
+    };
+
+    let txn_type = request.get("operation")
+        .and_then(|operation| operation.get("type"))
+        .and_then(|txn_type| txn_type.as_str())
+        .map(String::from);
+
+    if txn_type == Some(XFER_PUBLIC.to_string()) {
+        let payload = xfer_payload_from_request(&request)?;
+        return payload.sign_transfer(&services().crypto, wallet_handle, Box::new(move |payload| {
+            let mut request = request.clone();
+            let operation = payload.and_then(|payload| {
+                with_ledger_format(|| serde_json::to_value(PaymentRequest::new(payload)))
+                    .map_err(map_err_err!())
+                    .or(Err(ErrorCode::CommonInvalidState))
+            });
+            finish(operation.map(|operation| {
+                request.insert(String::from("operation"), operation);
+                request
+            }));
+        }));
+    }
+
+    if let Some(fees) = request.remove(FEES) {
+        let (inputs, outputs) = fees_from_json(fees)?;
+        return add_fees(wallet_handle, inputs, outputs, None, request, Box::new(finish));
+    }
+
+    finish(Ok(request));
+    Ok(())
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// The addresses in a request are unqualified, signing expects qualified addresses.
+fn qualify(inputs: Inputs, outputs: Outputs) -> (Inputs, Outputs) {
+    let inputs = inputs.into_iter()
+        .map(|input| Input::new(add_qualifer_to_address(&input.address), input.seq_no))
+        .collect();
+    let outputs = outputs.into_iter()
+        .map(|output| Output::new(add_qualifer_to_address(&output.recipient), output.amount))
+        .collect();
+    (inputs, outputs)
+}
+
+// Inputs and outputs are parsed from text, their deserializers borrow the keys.
+fn xfer_payload_from_request(request: &SerdeMap) -> Result<XferPayload, ErrorCode> {
+    let operation = request.get("operation").ok_or(ErrorCode::CommonInvalidStructure)?;
+    let payload: XferPayload = serde_json::from_str(&operation.to_string())
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    let (inputs, outputs) = qualify(payload.inputs, payload.outputs);
+    Ok(XferPayload::new(inputs, outputs, payload.extra))
+}
+
+// Fees are added to a request as [inputs, outputs, signatures].
+fn fees_from_json(fees: SJsonValue) -> Result<(Inputs, Outputs), ErrorCode> {
+    let (inputs, outputs, _): (Inputs, Outputs, SJsonValue) = serde_json::from_str(&fees.to_string())
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+    Ok(qualify(inputs, outputs))
+}
+
+// The DIDs to sign with in order, the submitter first, and whether their signature is a multi signature.
+fn signing_steps(signers: &DidSigners) -> Vec<(String, bool)> {
+    signers.single.iter()
+        .map(|did| (did.clone(), false))
+        .chain(signers.multi.iter().map(|did| (did.clone(), true)))
+        .collect()
+}
+
+// Signs with one DID at a time, each signature is made in the callback of the previous one.
+fn sign_with_dids<L: LedgerAPI + Sync>(ledger: &'static L, wallet_handle: i32, request_json: String, mut steps: Vec<(String, bool)>, cb: RequestCallback) {
+    if steps.is_empty() {
+        return cb(Ok(request_json));
+    }
+
+    let (did, multi) = steps.remove(0);
+    let next = Arc::clone(&cb);
+    let ec = ledger.indy_sign_request(wallet_handle, &did, &request_json, multi, move |signed| {
+        match signed {
+            Ok(signed) => sign_with_dids(ledger, wallet_handle, signed, steps.clone(), Arc::clone(&next)),
+            Err(ec) => next(Err(ec)),
+        }
+    });
+    if ec != ErrorCode::Success {
+        cb(Err(ec));
+    }
+}
+
+
+#[cfg(test)]
+mod refresh_request_tests {
+    use std::sync::Mutex;
+
+    use indy::IndyHandle;
+    use super::*;
+
+    // signs by adding the DID as its signature, on another thread as libindy does
+    struct LedgerMockHandler {}
+
+    impl LedgerAPI for LedgerMockHandler {
+        fn indy_submit_request_async<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _: IndyHandle, _: &str, _: F) -> ErrorCode {
+            ErrorCode::CommonInvalidState
+        }
+
+        fn indy_sign_request<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _: IndyHandle, did: &str, request_json: &str, multi: bool, mut cb: F) -> ErrorCode {
+            if did == "unknown" {
+                return ErrorCode::WalletItemNotFound;
+            }
+            let mut request: SerdeMap = serde_json::from_str(request_json).unwrap();
+            if multi {
+                request.entry(String::from("signatures")).or_insert(json!({}))[did] = json!(format!("sig of {}", did));
+            } else {
+                request.insert(String::from("signature"), json!(format!("sig of {}", did)));
+            }
+            ::std::thread::spawn(move || cb(Ok(SJsonValue::from(request).to_string())));
+            ErrorCode::Success
+        }
+    }
+
+    fn sign(signers: &DidSigners) -> Result<SJsonValue, ErrorCode> {
+        let (sender, receiver) = ::std::sync::mpsc::channel();
+        let sender = Mutex::new(sender);
+        let cb: RequestCallback = Arc::new(move |signed| sender.lock().unwrap().send(signed).unwrap());
+
+        sign_with_dids(&LedgerMockHandler {}, 1, String::from(r#"{"reqId":1,"identifier":"did"}"#), signing_steps(signers), cb);
+        receiver.recv().unwrap().map(|signed| serde_json::from_str(&signed).unwrap())
+    }
+
+    fn request(value: SJsonValue) -> SerdeMap {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn envelope_new_req_id_and_acceptance_time() {
+        let mut request = request(json!({
+            "reqId": 1,
+            "identifier": "did",
+            "operation": {"type": "1"},
+            "taaAcceptance": {"mechanism": "click", "taaDigest": "abc", "time": 1}
+        }));
+
+        refresh_envelope(&mut request, 1_500_000_000).unwrap();
+
+        assert_eq!(json!(1_499_990_400), request["taaAcceptance"]["time"]);
+        assert_eq!(json!("abc"), request["taaAcceptance"]["taaDigest"]);
+        assert!(request["reqId"].is_u64());
+    }
+
+    #[test]
+    fn envelope_removes_did_signatures() {
+        let mut request = request(json!({
+            "reqId": 1,
+            "identifier": "did",
+            "signature": "sig",
+            "signatures": {"trustee1": "sig1", "trustee2": "sig2"},
+            "operation": {"type": "1"}
+        }));
+
+        let signers = refresh_envelope(&mut request, 0).unwrap();
+
+        assert_eq!(Some(String::from("did")), signers.single);
+        assert_eq!(vec![String::from("trustee1"), String::from("trustee2")], signers.multi);
+        assert!(!request.contains_key("signature"));
+        assert!(!request.contains_key("signatures"));
+    }
+
+    #[test]
+    fn envelope_signature_without_identifier() {
+        let mut request = request(json!({"reqId": 1, "signature": "sig"}));
+        assert_eq!(ErrorCode::CommonInvalidStructure, refresh_envelope(&mut request, 0).unwrap_err());
+    }
+
+    #[test]
+    fn fees_are_qualified() {
+        let address = "E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm";
+        let fees = json!([[{"address": address, "seqNo": 1}], [{"address": address, "amount": 5}], ["sig"]]);
+
+        let (inputs, outputs) = fees_from_json(fees).unwrap();
+
+        assert_eq!(vec![Input::new(add_qualifer_to_address(address), 1)], inputs);
+        assert_eq!(vec![Output::new(add_qualifer_to_address(address), 5)], outputs);
+    }
+
+    #[test]
+    fn signed_again_by_every_did() {
+        let signers = DidSigners { single: Some(String::from("did")), multi: vec![String::from("trustee1"), String::from("trustee2")] };
+
+        let request = sign(&signers).unwrap();
+
+        assert_eq!(json!("sig of did"), request["signature"]);
+        assert_eq!(json!({"trustee1": "sig of trustee1", "trustee2": "sig of trustee2"}), request["signatures"]);
+    }
+
+    #[test]
+    fn unsigned_request_left_unsigned() {
+        let request = sign(&DidSigners::default()).unwrap();
+        assert_eq!(json!({"reqId": 1, "identifier": "did"}), request);
+    }
+
+    #[test]
+    fn signing_error_reported() {
+        let signers = DidSigners { single: Some(String::from("did")), multi: vec![String::from("unknown")] };
+        assert_eq!(ErrorCode::WalletItemNotFound, sign(&signers).unwrap_err());
+    }
+}
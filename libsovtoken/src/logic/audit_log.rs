@@ -0,0 +1,363 @@
+/*!
+    Append-only audit log of the payments built and parsed by libsovtoken.
+
+    Disabled unless `audit_log_path` is set in the [`LibraryConfig`]. Each
+    entry holds a timestamp, the canonical hash of the request or response
+    json with the version of the hash, and the outcome. Failing to write an entry is logged, it never fails the
+    payment itself.
+
+    With `compression` set in the library config, each line is compressed
+    on its own, see `logic::compression`, so entries are still appended. A
+    log with plain lines, written before compression was enabled, is
+    rewritten compressed the first time it is read.
+
+    [`LibraryConfig`]: ../config/library_config/struct.LibraryConfig.html
+*/
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use indy::ErrorCode;
+use serde_json;
+
+use logic::compression::{self, Compression};
+use logic::config::library_config::library_config;
+use logic::request_hash::{message_hash, REQUEST_HASH_VERSION};
+use logic::state::state;
+use utils::csv::csv_row;
+
+pub const BUILD_PAYMENT: &'static str = "build_payment";
+pub const PARSE_PAYMENT: &'static str = "parse_payment";
+
+const CSV_HEADER: &'static str = "timestamp,operation,request_hash,outcome,hash_version";
+
+/**
+    A single audit log entry.
+
+    `timestamp` is in seconds since the unix epoch, `request_hash` the
+    canonical hash of the request, see `logic::request_hash`, of the version
+    `hash_version`. The entries written before the hash was versioned are
+    of version 1.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct AuditEntry {
+    pub timestamp: u64,
+    pub operation: String,
+    pub request_hash: String,
+    pub outcome: String,
+    #[serde(default = "unversioned_hash")]
+    pub hash_version: u32,
+}
+
+impl AuditEntry {
+    pub fn new(operation: &str, request_json: &str, outcome: ErrorCode) -> AuditEntry {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        AuditEntry {
+            timestamp,
+            operation: operation.to_string(),
+            request_hash: message_hash(request_json),
+            outcome: format!("{:?}", outcome),
+            hash_version: REQUEST_HASH_VERSION,
+        }
+    }
+}
+
+/**
+    Formats the audit log can be exported to.
+*/
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn from_str(format: &str) -> Result<ExportFormat, ErrorCode> {
+        match format.to_lowercase().as_str() {
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            _ => Err(ErrorCode::CommonInvalidStructure),
+        }
+    }
+}
+
+/**
+    Storage of the audit log entries.
+*/
+pub trait AuditSink {
+    fn append(&self, entry: &AuditEntry) -> Result<(), ErrorCode>;
+    fn entries(&self) -> Result<Vec<AuditEntry>, ErrorCode>;
+}
+
+/**
+    Stores the entries in a file, one json entry per line.
+
+    The lines are compressed as set by `compression` in the library config.
+*/
+pub struct FileSink {
+    path: PathBuf,
+    compression: Compression,
+}
+
+impl FileSink {
+    pub fn new<P: Into<PathBuf>>(path: P) -> FileSink {
+        FileSink { path: path.into(), compression: library_config().compression }
+    }
+
+    /**
+        Write the lines compressed with `compression`, whatever the library config says.
+    */
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    // rewrite the log with `lines` compressed, replacing the file at once
+    fn migrate(&self, lines: &[String]) -> Result<(), ErrorCode> {
+        let mut migrated = String::new();
+        for line in lines {
+            migrated.push_str(&compression::compress(line, self.compression)?);
+            migrated.push('\n');
+        }
+
+        let temp_path = self.path.with_extension("migrating");
+        File::create(&temp_path)
+            .and_then(|mut file| file.write_all(migrated.as_bytes()).and_then(|_| file.sync_all()))
+            .and_then(|_| fs::rename(&temp_path, &self.path))
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonIOError))
+    }
+}
+
+impl AuditSink for FileSink {
+    fn append(&self, entry: &AuditEntry) -> Result<(), ErrorCode> {
+        let line = serde_json::to_string(entry)
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonInvalidState))?;
+        let line = compression::compress(&line, self.compression)?;
+
+        let state = state();
+        let _writing = state.audit_log.lock();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonIOError))?;
+
+        writeln!(file, "{}", line)
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonIOError))
+    }
+
+    fn entries(&self) -> Result<Vec<AuditEntry>, ErrorCode> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let state = state();
+        let _writing = state.audit_log.lock();
+        let file = OpenOptions::new()
+            .read(true)
+            .open(&self.path)
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonIOError))?;
+
+        let blobs = BufReader::new(file).lines()
+            .filter(|line| line.as_ref().map(|l| !l.trim().is_empty()).unwrap_or(true))
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonIOError))?;
+        let lines = blobs.iter()
+            .map(|blob| compression::decompress(blob))
+            .collect::<Result<Vec<String>, ErrorCode>>()?;
+        let entries = lines.iter()
+            .map(|line| serde_json::from_str(line).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState)))
+            .collect::<Result<Vec<AuditEntry>, ErrorCode>>()?;
+
+        if blobs.iter().any(|blob| compression::needs_migration(blob, self.compression)) {
+            if let Err(ec) = self.migrate(&lines) {
+                warn!("The audit log {:?} is left as it was written, its migration failed: {:?}", self.path, ec);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/**
+    Get the sink configured in the library config, if any.
+*/
+pub fn configured_sink() -> Option<FileSink> {
+    library_config().audit_log_path.map(FileSink::new)
+}
+
+/**
+    Append an entry to the configured audit log.
+
+    Does nothing when no audit log is configured.
+*/
+pub fn record(operation: &str, request_json: &str, outcome: ErrorCode) {
+    if let Some(sink) = configured_sink() {
+        let entry = AuditEntry::new(operation, request_json, outcome);
+        if let Err(e) = sink.append(&entry) {
+            error!("Failed to write audit log entry {:?}: {:?}", entry, e);
+        }
+    }
+}
+
+/**
+    Export `entries` in `format`.
+
+    ```
+        # extern crate indy;
+        # extern crate sovtoken;
+        # fn main() {
+            use indy::ErrorCode;
+            use sovtoken::logic::audit_log::{AuditEntry, ExportFormat, export_entries};
+
+            let entry = AuditEntry::new("build_payment", "{}", ErrorCode::Success);
+            let csv = export_entries(&vec![entry], ExportFormat::Csv).unwrap();
+            assert!(csv.starts_with("timestamp,operation,request_hash,outcome,hash_version\n"));
+        # }
+    ```
+*/
+pub fn export_entries(entries: &Vec<AuditEntry>, format: ExportFormat) -> Result<String, ErrorCode> {
+    match format {
+        ExportFormat::Json => serde_json::to_string(entries)
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonInvalidState)),
+        ExportFormat::Csv => {
+            let mut csv = String::from(CSV_HEADER);
+            csv.push('\n');
+            for entry in entries {
+                csv.push_str(&csv_row(&[
+                    &entry.timestamp.to_string(),
+                    &entry.operation,
+                    &entry.request_hash,
+                    &entry.outcome,
+                    &entry.hash_version.to_string(),
+                ]));
+            }
+            Ok(csv)
+        },
+    }
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn unversioned_hash() -> u32 {
+    1
+}
+
+
+#[cfg(test)]
+mod audit_log_tests {
+    use std::env;
+    use std::fs;
+
+    use utils::random::rand_string;
+    use super::*;
+
+    fn temp_sink() -> (FileSink, PathBuf) {
+        let path = env::temp_dir().join(format!("sovtoken_audit_{}.log", rand_string(10)));
+        (FileSink::new(path.clone()), path)
+    }
+
+    #[test]
+    fn entry_hashes_request() {
+        let entry = AuditEntry::new(BUILD_PAYMENT, "", ErrorCode::Success);
+        assert_eq!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855", entry.request_hash);
+        assert_eq!("Success", entry.outcome);
+    }
+
+    #[test]
+    fn file_sink_appends() {
+        let (sink, path) = temp_sink();
+        let first = AuditEntry::new(BUILD_PAYMENT, "{}", ErrorCode::Success);
+        let second = AuditEntry::new(PARSE_PAYMENT, "{}", ErrorCode::CommonInvalidStructure);
+
+        sink.append(&first).unwrap();
+        sink.append(&second).unwrap();
+
+        assert_eq!(vec![first, second], sink.entries().unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_sink_compresses_lines() {
+        let (sink, path) = temp_sink();
+        let sink = sink.with_compression(Compression::Gzip);
+        let entry = AuditEntry::new(BUILD_PAYMENT, "{}", ErrorCode::Success);
+
+        sink.append(&entry).unwrap();
+        sink.append(&entry).unwrap();
+
+        assert!(fs::read_to_string(&path).unwrap().lines().all(|line| line.starts_with("gzip:")));
+        assert_eq!(vec![entry.clone(), entry], sink.entries().unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn plain_log_is_migrated() {
+        let (sink, path) = temp_sink();
+        let entry = AuditEntry::new(BUILD_PAYMENT, "{}", ErrorCode::Success);
+        sink.with_compression(Compression::None).append(&entry).unwrap();
+
+        let sink = FileSink::new(path.clone()).with_compression(Compression::Brotli);
+        sink.append(&entry).unwrap();
+        assert_eq!(vec![entry.clone(), entry.clone()], sink.entries().unwrap());
+
+        assert!(fs::read_to_string(&path).unwrap().lines().all(|line| line.starts_with("br:")));
+        assert_eq!(vec![entry.clone(), entry], sink.entries().unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_sink_without_file() {
+        let (sink, _) = temp_sink();
+        assert!(sink.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn export_json() {
+        let entry = AuditEntry::new(BUILD_PAYMENT, "{}", ErrorCode::Success);
+        let json = export_entries(&vec![entry.clone()], ExportFormat::Json).unwrap();
+        let entries: Vec<AuditEntry> = serde_json::from_str(&json).unwrap();
+        assert_eq!(vec![entry], entries);
+    }
+
+    #[test]
+    fn export_csv() {
+        let entry = AuditEntry {
+            timestamp: 10,
+            operation: String::from("a,b"),
+            request_hash: String::from("abc"),
+            outcome: String::from("Success"),
+            hash_version: 2,
+        };
+        let csv = export_entries(&vec![entry], ExportFormat::Csv).unwrap();
+        assert_eq!("timestamp,operation,request_hash,outcome,hash_version\n10,\"a,b\",abc,Success,2\n", csv);
+    }
+
+    #[test]
+    fn entry_written_before_the_hash_was_versioned() {
+        let entry: AuditEntry = serde_json::from_str(r#"{"timestamp":10,"operation":"build_payment","request_hash":"abc","outcome":"Success"}"#).unwrap();
+        assert_eq!(1, entry.hash_version);
+        assert_eq!(REQUEST_HASH_VERSION, AuditEntry::new(BUILD_PAYMENT, "{}", ErrorCode::Success).hash_version);
+    }
+
+    #[test]
+    fn export_format_from_str() {
+        assert_eq!(ExportFormat::Csv, ExportFormat::from_str("CSV").unwrap());
+        assert_eq!(ErrorCode::CommonInvalidStructure, ExportFormat::from_str("xml").unwrap_err());
+    }
+}
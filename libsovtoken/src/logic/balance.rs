@@ -0,0 +1,311 @@
+/*!
+    Validation of the balance between the inputs, outputs and fees of a transaction.
+
+    When a transfer doesn't balance, the caller needs to know if the shortfall is
+    in the payment itself or in the fees. [`validate_balance`] answers that with a
+    [`BalanceError`] which maps to a payment [`ErrorCode`].
+
+    libindy has one code for insufficient funds, so a shortfall in the payment
+    and one in the fees are both `PaymentInsufficientFundsError`. They are told
+    apart by the id of the last error of the thread, `SOV-0013` for the payment
+    and `SOV-0014` for the fees, see `sovtoken_get_last_error_json`.
+
+    The build handlers check the balance with [`validate_known_balance`], when
+    the fee and the amounts of the inputs are known to the library.
+
+    [`validate_balance`]: ./fn.validate_balance.html
+    [`validate_known_balance`]: ./fn.validate_known_balance.html
+    [`BalanceError`]: ./enum.BalanceError.html
+*/
+
+use std::error::Error;
+use std::fmt;
+
+use indy::{ErrorCode, IndyHandle};
+use logic::error::SovtokenError;
+use logic::fee_waiver;
+use logic::input::Inputs;
+use logic::messages;
+use logic::output::Outputs;
+use logic::parsers::common::TXO;
+use logic::type_aliases::TokenAmount;
+use logic::utxo_cache;
+use utils::constants::general::MAX_SUPPLY;
+
+/**
+    Enum which holds the reasons a transaction can fail to balance.
+
+    ### Includes
+    - `BalanceError::PaymentInsufficientFunds` the inputs don't cover the outputs.
+    - `BalanceError::FeesInsufficientFunds` the inputs cover the outputs, but not the fees.
+    - `BalanceError::ExtraFundsUnallocated` the inputs are more than the outputs plus the fees.
+*/
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BalanceError {
+    PaymentInsufficientFunds { missing: TokenAmount },
+    FeesInsufficientFunds { missing: TokenAmount },
+    ExtraFundsUnallocated { extra: TokenAmount },
+}
+
+impl fmt::Display for BalanceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &BalanceError::PaymentInsufficientFunds { missing } =>
+                write!(f, "{} Missing {} tokens for the outputs.", self.description(), missing),
+            &BalanceError::FeesInsufficientFunds { missing } =>
+                write!(f, "{} Missing {} tokens for the fees.", self.description(), missing),
+            &BalanceError::ExtraFundsUnallocated { extra } =>
+                write!(f, "{} {} tokens are not allocated to an output.", self.description(), extra),
+        }
+    }
+}
+
+impl Error for BalanceError {
+    fn description(&self) -> &str {
+        match self {
+            &BalanceError::PaymentInsufficientFunds { .. } => "Insufficient funds for the payment.",
+            &BalanceError::FeesInsufficientFunds { .. } => "Insufficient funds for the fees.",
+            &BalanceError::ExtraFundsUnallocated { .. } => "Inputs exceed outputs and fees.",
+        }
+    }
+}
+
+impl From<BalanceError> for ErrorCode {
+    fn from(error: BalanceError) -> ErrorCode {
+        ErrorCode::from(SovtokenError::from(error))
+    }
+}
+
+/**
+    Sum the amounts of the outputs.
+
+    Returns `None` when the sum overflows a [`TokenAmount`].
+
+    [`TokenAmount`]: ../type_aliases/type.TokenAmount.html
+*/
+pub fn outputs_total(outputs: &Outputs) -> Option<TokenAmount> {
+    outputs.iter().fold(Some(0), |total, output| {
+        total.and_then(|total| total.checked_add(output.amount))
+    })
+}
+
+/**
+    Sum the amounts of the outputs, which can't hold more than the
+    [`MAX_SUPPLY`] together.
+
+    A mint or transfer above the supply can't be valid, whether its sum
+    overflows a [`TokenAmount`] or not.
+
+    ```
+        use sovtoken::logic::balance::validate_supply;
+        use sovtoken::logic::output::Output;
+        use sovtoken::utils::constants::general::MAX_SUPPLY;
+
+        let address = String::from("pay:sov:a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7");
+        assert_eq!(Ok(MAX_SUPPLY), validate_supply(&vec![Output::new(address.clone(), MAX_SUPPLY)]));
+        assert!(validate_supply(&vec![Output::new(address, MAX_SUPPLY + 1)]).is_err());
+    ```
+
+    [`MAX_SUPPLY`]: ../../utils/constants/general/constant.MAX_SUPPLY.html
+    [`TokenAmount`]: ../type_aliases/type.TokenAmount.html
+*/
+pub fn validate_supply(outputs: &Outputs) -> Result<TokenAmount, SovtokenError> {
+    match outputs_total(outputs) {
+        Some(total) if total <= MAX_SUPPLY => Ok(total),
+        total => Err(SovtokenError::InvalidStructure(messages::SUPPLY_EXCEEDED.with(&[
+            total.map(|total| total.to_string()).unwrap_or(String::from("more than u64::MAX")),
+            MAX_SUPPLY.to_string()
+        ]))),
+    }
+}
+
+/**
+    Validate that `inputs_total` pays for exactly `outputs_total` plus `fee`.
+
+    The payment is checked before the fees, so a transaction which can't
+    even cover its outputs is reported as `PaymentInsufficientFunds`.
+
+    ```
+        use sovtoken::logic::balance::{validate_balance, BalanceError};
+
+        assert!(validate_balance(15, 10, 5).is_ok());
+        assert_eq!(
+            BalanceError::FeesInsufficientFunds { missing: 2 },
+            validate_balance(13, 10, 5).unwrap_err()
+        );
+    ```
+*/
+pub fn validate_balance(
+    inputs_total: TokenAmount,
+    outputs_total: TokenAmount,
+    fee: TokenAmount
+) -> Result<(), BalanceError> {
+    trace!("logic::balance::validate_balance >> inputs_total: {}, outputs_total: {}, fee: {}", inputs_total, outputs_total, fee);
+
+    if inputs_total < outputs_total {
+        return Err(BalanceError::PaymentInsufficientFunds { missing: outputs_total - inputs_total });
+    }
+
+    let remaining = inputs_total - outputs_total;
+
+    if remaining < fee {
+        return Err(BalanceError::FeesInsufficientFunds { missing: fee - remaining });
+    }
+
+    if remaining > fee {
+        return Err(BalanceError::ExtraFundsUnallocated { extra: remaining - fee });
+    }
+
+    Ok(())
+}
+
+/**
+    Validate that `inputs` pay exactly `outputs` plus the fee of `txn_type`,
+    as [`validate_balance`] does.
+
+    The fee is the one known on the network of `pool_handle`, see
+    `fee_waiver::known_fee`, and the amounts of the inputs are the ones of
+    the cached GET_UTXO replies, see `utxo_cache::known_amount`. When the
+    fee or the amount of an input isn't known, there is nothing to validate
+    against and the ledger has the last word.
+*/
+pub fn validate_known_balance(pool_handle: Option<IndyHandle>, txn_type: &str, inputs: &Inputs, outputs: &Outputs) -> Result<(), ErrorCode> {
+    let fee = match fee_waiver::known_fee(pool_handle, txn_type) {
+        Some(fee) => fee,
+        None => return Ok(()),
+    };
+
+    let mut inputs_total: TokenAmount = 0;
+    for input in inputs {
+        let txo = TXO::new(&input.address, input.seq_no).to_libindy_string()?;
+        match utxo_cache::known_amount(pool_handle, &txo) {
+            Some(amount) => inputs_total = inputs_total.checked_add(amount).ok_or(ErrorCode::CommonInvalidStructure)?,
+            None => return Ok(()),
+        }
+    }
+    let outputs_total = outputs_total(outputs).ok_or(ErrorCode::CommonInvalidStructure)?;
+
+    validate_balance(inputs_total, outputs_total, fee)
+        .map_err(map_err_err!())
+        .map_err(ErrorCode::from)
+}
+
+
+#[cfg(test)]
+mod balance_tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use logic::config::library_config::LibraryConfig;
+    use logic::config::set_fees_config::SetFeesMap;
+    use logic::input::Input;
+    use logic::output::Output;
+    use logic::parsers::parse_get_utxo_response::UTXO;
+    use logic::state::state;
+    use logic::utxo_cache::UtxoCache;
+    use utils::test::context::in_own_context;
+
+    static ADDRESS: &'static str = "a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7";
+
+    // a fee of 2 for the txn type 95001 and a UTXO of 10 tokens, the input
+    fn known_fee_and_utxo() -> Inputs {
+        let mut fees: SetFeesMap = HashMap::new();
+        fees.insert(String::from("95001"), 2);
+        fee_waiver::preload_schedule(None, &fees);
+
+        let utxo = UTXO {
+            payment_address: format!("pay:sov:{}", ADDRESS),
+            source: TXO::new(ADDRESS, 1).to_libindy_string().unwrap(),
+            amount: 10,
+            extra: String::new(),
+        };
+        state().utxo_cache.lock().insert(2, ADDRESS, "root", UtxoCache::body_hash("body"), vec![utxo]);
+        vec![Input::new(String::from(ADDRESS), 1)]
+    }
+
+    #[test]
+    fn balanced_without_fee() {
+        assert!(validate_balance(10, 10, 0).is_ok());
+    }
+
+    #[test]
+    fn balanced_with_fee() {
+        assert!(validate_balance(12, 10, 2).is_ok());
+    }
+
+    #[test]
+    fn payment_insufficient_funds() {
+        let error = validate_balance(7, 10, 2).unwrap_err();
+        assert_eq!(BalanceError::PaymentInsufficientFunds { missing: 3 }, error);
+        assert_eq!(ErrorCode::PaymentInsufficientFundsError, ErrorCode::from(error));
+    }
+
+    #[test]
+    fn fees_insufficient_funds() {
+        let error = validate_balance(11, 10, 2).unwrap_err();
+        assert_eq!(BalanceError::FeesInsufficientFunds { missing: 1 }, error);
+        assert_eq!(ErrorCode::PaymentInsufficientFundsError, ErrorCode::from(error));
+    }
+
+    #[test]
+    fn extra_funds_unallocated() {
+        let error = validate_balance(15, 10, 2).unwrap_err();
+        assert_eq!(BalanceError::ExtraFundsUnallocated { extra: 3 }, error);
+        assert_eq!(ErrorCode::PaymentExtraFundsError, ErrorCode::from(error));
+    }
+
+    #[test]
+    fn known_balance_validated() {
+        in_own_context(LibraryConfig::default(), || {
+            let inputs = known_fee_and_utxo();
+            let outputs = |amount| vec![Output::new(String::from(ADDRESS), amount)];
+
+            assert!(validate_known_balance(None, "95001", &inputs, &outputs(8)).is_ok());
+            assert_eq!(ErrorCode::PaymentExtraFundsError, validate_known_balance(None, "95001", &inputs, &outputs(7)).unwrap_err());
+            assert_eq!(ErrorCode::PaymentInsufficientFundsError, validate_known_balance(None, "95001", &inputs, &outputs(9)).unwrap_err());
+            assert_eq!(messages::FEES_INSUFFICIENT_FUNDS.id, messages::last_error().unwrap().message.id);
+        });
+    }
+
+    #[test]
+    fn unknown_balance_not_validated() {
+        in_own_context(LibraryConfig::default(), || {
+            let inputs = known_fee_and_utxo();
+            let outputs = vec![Output::new(String::from(ADDRESS), 20)];
+
+            assert!(validate_known_balance(None, "95002", &inputs, &outputs).is_ok());
+            assert!(validate_known_balance(None, "95001", &vec![Input::new(String::from(ADDRESS), 2)], &outputs).is_ok());
+        });
+    }
+
+    #[test]
+    fn sum_outputs() {
+        let outputs = vec![
+            Output::new(String::from("a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7"), 10),
+            Output::new(String::from("E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm"), 22),
+        ];
+        assert_eq!(Some(32), outputs_total(&outputs));
+    }
+
+    #[test]
+    fn sum_outputs_overflow() {
+        let outputs = vec![
+            Output::new(String::from("a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7"), TokenAmount::max_value()),
+            Output::new(String::from("E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm"), 1),
+        ];
+        assert_eq!(None, outputs_total(&outputs));
+    }
+
+    #[test]
+    fn supply_boundary() {
+        let address = String::from("a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7");
+        let outputs = vec![Output::new(address.clone(), MAX_SUPPLY - 1), Output::new(address.clone(), 1)];
+        assert_eq!(Ok(MAX_SUPPLY), validate_supply(&outputs));
+
+        let outputs = vec![Output::new(address.clone(), MAX_SUPPLY), Output::new(address.clone(), 1)];
+        assert_eq!(ErrorCode::CommonInvalidStructure, validate_supply(&outputs).unwrap_err().error_code());
+
+        let outputs = vec![Output::new(address.clone(), TokenAmount::max_value()), Output::new(address, 1)];
+        assert_eq!(ErrorCode::CommonInvalidStructure, validate_supply(&outputs).unwrap_err().error_code());
+    }
+}
@@ -0,0 +1,276 @@
+/*!
+    Payout batches: one payment to many recipients.
+
+    Duplicate recipients are merged into a single output. When there are more
+    outputs than fit in one XFER, the outputs are split over several requests,
+    each paid with its own UTXOs. The change of a request goes back to the
+    address of the last UTXO it spends.
+*/
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use indy::{IndyHandle, ErrorCode};
+
+use logic::config::payment_config::PaymentRequest;
+use logic::indy_sdk_api::crypto_api::CryptoAPI;
+use logic::output::{Output, Outputs};
+use logic::parsers::parse_get_utxo_response::UTXO;
+use logic::request::Request;
+use logic::request_builder::PaymentRequestBuilder;
+use logic::transfer_limits::TransferLimits;
+use logic::type_aliases::TokenAmount;
+
+/**
+    A request of the batch, as it is described in the manifest.
+
+    `request` is the serialized request json.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchEntry {
+    pub index: usize,
+    pub output_count: usize,
+    pub amount: TokenAmount,
+    pub request: String,
+}
+
+/**
+    Describes the set of requests a batch was split into.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchManifest {
+    pub requests: Vec<BatchEntry>,
+    pub total_amount: TokenAmount,
+    pub merged_duplicates: usize,
+}
+
+/**
+    A payment request of the batch, before signing.
+
+    `change` is the output of the builder which goes back to the payer, if any.
+*/
+#[derive(Debug, Clone)]
+pub struct BatchChunk {
+    pub builder: PaymentRequestBuilder,
+    pub output_count: usize,
+    pub amount: TokenAmount,
+    pub change: Option<Output>,
+}
+
+/**
+    Merge outputs to the same recipient, keeping the order of the first occurrence.
+
+    Returns the merged outputs and how many outputs were merged away.
+*/
+pub fn merge_duplicate_outputs(outputs: Outputs) -> Result<(Outputs, usize), ErrorCode> {
+    let count = outputs.len();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut merged: Outputs = Vec::new();
+
+    for output in outputs {
+        match positions.get(&output.recipient).cloned() {
+            Some(position) => {
+                merged[position].amount = merged[position].amount.checked_add(output.amount)
+                    .ok_or(ErrorCode::CommonInvalidStructure)?;
+            },
+            None => {
+                positions.insert(output.recipient.clone(), merged.len());
+                merged.push(output);
+            },
+        }
+    }
+
+    let merged_away = count - merged.len();
+    Ok((merged, merged_away))
+}
+
+/**
+    Split `outputs` into chunks of at most `limits.max_outputs` outputs and
+    pay each chunk with the next UTXOs.
+
+    Fails with `PaymentInsufficientFundsError` when the UTXOs run out, and
+    with `CommonInvalidStructure` when a chunk needs more than
+    `limits.max_inputs` UTXOs.
+*/
+pub fn plan_batch(utxos: &Vec<UTXO>, outputs: &Outputs, limits: TransferLimits, extra: Option<&str>) -> Result<Vec<BatchChunk>, ErrorCode> {
+    trace!("logic::batch_payment::plan_batch >> outputs: {:?}, limits: {:?}", outputs.len(), limits);
+    let max_outputs = limits.max_outputs;
+    if max_outputs < 2 || limits.max_inputs == 0 || outputs.is_empty() {
+        error!("A batch needs outputs and room for a change output.");
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let mut utxos = utxos.iter();
+    let mut chunks = Vec::new();
+
+    // one output of each request is kept free for the change
+    for chunk_outputs in outputs.chunks(max_outputs - 1) {
+        let amount = chunk_outputs.iter()
+            .fold(Some(0), |total: Option<TokenAmount>, output| total.and_then(|total| total.checked_add(output.amount)))
+            .ok_or(ErrorCode::CommonInvalidStructure)?;
+
+        let mut builder = PaymentRequestBuilder::new();
+        let mut paid: TokenAmount = 0;
+        let mut last_address = None;
+        let mut input_count = 0;
+
+        while paid < amount {
+            let utxo = utxos.next().ok_or(ErrorCode::PaymentInsufficientFundsError)?;
+            input_count += 1;
+            limits.check(input_count, 0)?;
+            paid = paid.checked_add(utxo.amount).ok_or(ErrorCode::CommonInvalidStructure)?;
+            last_address = Some(utxo.payment_address.clone());
+            builder = builder.utxo(utxo)?;
+        }
+
+        for output in chunk_outputs {
+            builder = builder.output(output.clone());
+        }
+
+        let mut output_count = chunk_outputs.len();
+        let mut change = None;
+        if let (true, Some(change_address)) = (paid > amount, last_address) {
+            let change_output = Output::new(change_address, paid - amount);
+            builder = builder.output(change_output.clone());
+            change = Some(change_output);
+            output_count += 1;
+        }
+
+        if let Some(extra) = extra {
+            builder = builder.extra(extra);
+        }
+
+        chunks.push(BatchChunk { builder: builder.fee(0), output_count, amount, change });
+    }
+
+    trace!("logic::batch_payment::plan_batch << chunks: {:?}", chunks.len());
+    Ok(chunks)
+}
+
+/**
+    Sign every chunk and call `cb` once, with the requests in the order of the chunks.
+
+    When signing one of the chunks fails, `cb` is called with the first error.
+*/
+pub fn sign_batch<A: CryptoAPI>(
+    chunks: Vec<BatchChunk>,
+    crypto_api: &'static A,
+    wallet_handle: IndyHandle,
+    cb: Box<Fn(Result<Vec<Request<PaymentRequest>>, ErrorCode>) + Send + Sync>
+) -> Result<(), ErrorCode> {
+    let count = chunks.len();
+    let signed: Arc<Mutex<(Vec<Option<Request<PaymentRequest>>>, bool)>> = Arc::new(Mutex::new(((0..count).map(|_| None).collect(), false)));
+    let cb = Arc::new(cb);
+
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let signed = Arc::clone(&signed);
+        let cb = Arc::clone(&cb);
+
+        chunk.builder.sign(crypto_api, wallet_handle, Box::new(move |request| {
+            let mut signed = signed.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if signed.1 {
+                return;
+            }
+
+            match request {
+                Ok(request) => {
+                    signed.0[index] = Some(request);
+                    if signed.0.iter().all(Option::is_some) {
+                        signed.1 = true;
+                        let requests = signed.0.iter_mut().filter_map(Option::take).collect();
+                        cb(Ok(requests));
+                    }
+                },
+                Err(ec) => {
+                    signed.1 = true;
+                    cb(Err(ec));
+                },
+            }
+        }))?;
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod batch_payment_tests {
+    use logic::parsers::common::TXO;
+    use super::*;
+
+    static INPUT_ADDRESS: &'static str = "pay:sov:E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm";
+
+    fn output(recipient: &str, amount: TokenAmount) -> Output {
+        Output::new(recipient.to_string(), amount)
+    }
+
+    fn limits(max_inputs: usize, max_outputs: usize) -> TransferLimits {
+        TransferLimits { max_inputs, max_outputs }
+    }
+
+    fn utxos(amounts: Vec<TokenAmount>) -> Vec<UTXO> {
+        amounts.into_iter().enumerate().map(|(seq_no, amount)| UTXO {
+            payment_address: INPUT_ADDRESS.to_string(),
+            source: TXO { address: INPUT_ADDRESS.to_string(), seq_no: seq_no as u64 + 1 }.to_libindy_string().unwrap(),
+            amount,
+            extra: "".to_string(),
+        }).collect()
+    }
+
+    #[test]
+    fn merge_duplicates() {
+        let outputs = vec![output("pay:sov:a", 1), output("pay:sov:b", 2), output("pay:sov:a", 3)];
+        let (merged, merged_away) = merge_duplicate_outputs(outputs).unwrap();
+
+        assert_eq!(vec![output("pay:sov:a", 4), output("pay:sov:b", 2)], merged);
+        assert_eq!(1, merged_away);
+    }
+
+    #[test]
+    fn plan_single_request() {
+        let outputs = vec![output("pay:sov:a", 5), output("pay:sov:b", 5)];
+        let chunks = plan_batch(&utxos(vec![10]), &outputs, TransferLimits::default(), None).unwrap();
+
+        assert_eq!(1, chunks.len());
+        assert_eq!(2, chunks[0].output_count);
+        assert_eq!(10, chunks[0].amount);
+    }
+
+    #[test]
+    fn plan_splits_and_adds_change() {
+        let outputs = vec![output("pay:sov:a", 4), output("pay:sov:b", 4), output("pay:sov:c", 4)];
+        let chunks = plan_batch(&utxos(vec![10, 5]), &outputs, limits(10, 3), None).unwrap();
+
+        assert_eq!(2, chunks.len());
+        assert_eq!(3, chunks[0].output_count);
+
+        let first = chunks[0].builder.clone().build().unwrap();
+        assert_eq!(output(INPUT_ADDRESS, 2), first.outputs[2]);
+        assert_eq!(Some(output(INPUT_ADDRESS, 2)), chunks[0].change);
+
+        let second = chunks[1].builder.clone().build().unwrap();
+        assert_eq!(vec![output("pay:sov:c", 4), output(INPUT_ADDRESS, 1)], second.outputs);
+    }
+
+    #[test]
+    fn plan_insufficient_funds() {
+        let outputs = vec![output("pay:sov:a", 4), output("pay:sov:b", 4)];
+        let error = plan_batch(&utxos(vec![5]), &outputs, limits(10, 2), None).unwrap_err();
+        assert_eq!(ErrorCode::PaymentInsufficientFundsError, error);
+    }
+
+    #[test]
+    fn plan_without_room_for_change() {
+        let error = plan_batch(&utxos(vec![5]), &vec![output("pay:sov:a", 4)], limits(10, 1), None).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error);
+    }
+
+    #[test]
+    fn plan_too_many_inputs() {
+        let outputs = vec![output("pay:sov:a", 12)];
+        let error = plan_batch(&utxos(vec![5, 5, 5]), &outputs, limits(2, 10), None).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error);
+    }
+}
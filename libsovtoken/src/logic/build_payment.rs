@@ -2,13 +2,21 @@
 
 use indy::ErrorCode;
 use libc::c_char;
+use logic::audit_log;
+use logic::canonical_order;
+use logic::config::library_config::library_config;
 use logic::config::payment_config::PaymentRequest;
-use logic::input::Inputs;
-use logic::output::Outputs;
+use logic::error::SovtokenError;
+use logic::extra::extra_from_char_ptr;
+use logic::input::{dedupe_inputs, Inputs};
+use logic::output::{self, Outputs};
+use logic::request::Request;
+use logic::schema::{validate_if_strict, Schema};
+use logic::simulation::BuildOptions;
+use logic::unknown_fields;
 use logic::xfer_payload::XferPayload;
-use utils::ffi_support::{string_from_char_ptr, c_pointer_from_str};
+use utils::ffi_support::{string_from_char_ptr, with_c_str};
 use utils::base58::{IntoBase58, FromBase58};
-use serde_json;
 
 type BuildPaymentRequestCb = extern fn(ch: i32, err: i32, request_json: *const c_char) -> i32;
 type DeserializedArguments = (Inputs, Outputs, Option<String>, BuildPaymentRequestCb);
@@ -18,6 +26,22 @@ pub fn deserialize_inputs(
     outputs_json: *const c_char,
     extra: *const c_char,
     cb: Option<BuildPaymentRequestCb>
+) -> Result<DeserializedArguments, ErrorCode> {
+    deserialize_inputs_with_options(inputs_json, outputs_json, extra, cb, &BuildOptions::default())
+}
+
+/**
+    As [`deserialize_inputs`], outputs of 0 tokens checked and the inputs and
+    outputs put in order with `options`.
+
+    [`deserialize_inputs`]: fn.deserialize_inputs.html
+*/
+pub fn deserialize_inputs_with_options(
+    inputs_json: *const c_char,
+    outputs_json: *const c_char,
+    extra: *const c_char,
+    cb: Option<BuildPaymentRequestCb>,
+    options: &BuildOptions
 ) -> Result<DeserializedArguments, ErrorCode> {
     trace!("logic::build_payment::deserialize_inputs >> inputs_json: {:?}, outputs_json: {:?}, extra: {:?}", inputs_json, outputs_json, extra);
     let cb = cb.ok_or(ErrorCode::CommonInvalidStructure)?;
@@ -30,15 +54,22 @@ pub fn deserialize_inputs(
         .ok_or(ErrorCode::CommonInvalidStructure).map_err(map_err_err!())?;
     debug!("Converted outputs_json pointer to string >>> {:?}", outputs_json);
 
-    let inputs: Inputs = serde_json::from_str(&inputs_json).map_err(map_err_err!())
-        .or(Err(ErrorCode::CommonInvalidStructure))?;
+    validate_if_strict(Schema::Inputs, &inputs_json)?;
+    validate_if_strict(Schema::Outputs, &outputs_json)?;
+
+    let inputs: Inputs = unknown_fields::from_json(&inputs_json, "inputs")?;
     debug!("Deserialized input_json >>> {:?}", inputs);
 
-    let outputs: Outputs = serde_json::from_str(&outputs_json).map_err(map_err_err!())
-        .or(Err(ErrorCode::CommonInvalidStructure))?;
+    let mut inputs = dedupe_inputs(inputs, library_config().duplicate_inputs)
+        .map_err(|error| ErrorCode::from(SovtokenError::from(error)))?;
+
+    let mut outputs: Outputs = unknown_fields::from_json(&outputs_json, "outputs")?;
     debug!("Deserialized output_json >>> {:?}", outputs);
 
-    let extra = string_from_char_ptr(extra);
+    output::check_amounts(&outputs, options.zero_outputs_allowed()).map_err(ErrorCode::from)?;
+    canonical_order::put_in_order(&mut inputs, &mut outputs, options.preserve_order);
+
+    let extra = extra_from_char_ptr(extra)?;
     debug!("Deserialized extra >>> {:?}", extra);
 
     trace!("logic::build_payment::deserialize_inputs << inputs: {:?}, outputs: {:?}, extra: {:?}", inputs, outputs, extra);
@@ -50,19 +81,30 @@ pub fn handle_signing(
     signed_payload: Result<XferPayload, ErrorCode>,
     cb: BuildPaymentRequestCb
 ) {
-    let (error_code, pointer) = match build_payment_request_pointer(signed_payload) {
-        Ok(request_pointer) => (ErrorCode::Success, request_pointer),
-        Err(ec) => (ec, c_pointer_from_str("")),
+    let (error_code, request_json) = match build_payment_request_json(signed_payload) {
+        Ok(request_json) => (ErrorCode::Success, request_json),
+        Err(ec) => {
+            audit_log::record(audit_log::BUILD_PAYMENT, "", ec);
+            (ec, String::new())
+        },
     };
     
-    cb(command_handle, error_code as i32, pointer);
+    with_c_str(&request_json, |request_json| cb(command_handle, error_code as i32, request_json));
 }
 
 
-fn build_payment_request_pointer(
-    signed_payload: Result<XferPayload, ErrorCode>
-) -> Result<*const c_char, ErrorCode> {
-    let signed_payload = signed_payload?;
+/**
+    Wraps a signed [`XferPayload`] in a payment request.
+
+    The identifier of the request is derived from the address of the first input.
+    Fails with `CommonInvalidStructure` when the request is larger than the
+    configured `max_request_bytes`.
+
+    [`XferPayload`]: ../xfer_payload/struct.XferPayload.html
+*/
+pub fn build_payment_request(
+    signed_payload: XferPayload
+) -> Result<Request<PaymentRequest>, ErrorCode> {
     debug!("Signed payload >>> {:?}", signed_payload);
 
     if signed_payload.signatures.is_none() {
@@ -78,12 +120,29 @@ fn build_payment_request_pointer(
         .as_request(identifier);
     debug!("payment_request >>> {:?}", payment_request);
 
-    return payment_request
-        .serialize_to_pointer()
+    let max_request_bytes = library_config().max_request_bytes;
+    let size = payment_request.estimated_size().map_err(|_| ErrorCode::CommonInvalidState)?;
+    if size > max_request_bytes {
+        error!("Payment request of {} bytes exceeds the limit of {} bytes.", size, max_request_bytes);
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    return Ok(payment_request);
+}
+
+
+fn build_payment_request_json(
+    signed_payload: Result<XferPayload, ErrorCode>
+) -> Result<String, ErrorCode> {
+    let request_json = build_payment_request(signed_payload?)?
+        .serialize_to_string()
         .map_err(|e| {
             map_err_err!()(e);
             return ErrorCode::CommonInvalidState;
-        });
+        })?;
+
+    audit_log::record(audit_log::BUILD_PAYMENT, &request_json, ErrorCode::Success);
+    return Ok(request_json);
 }
 
 
@@ -171,6 +230,7 @@ mod test_deserialize_inputs {
 #[cfg(test)]
 mod test_handle_signing {
     use super::*;
+    use serde_json;
     use indy::utils::results::ResultHandler;
     use logic::request::Request;
     use utils::test::{default, callbacks};
@@ -0,0 +1,143 @@
+/*!
+    Canonical order of the inputs and outputs of a transfer.
+
+    The signature of an input covers the outputs in their order, and the
+    ledger compares requests with their order, so two applications building
+    the same payment get different requests when they order it differently.
+    The canonical order is:
+
+    * inputs sorted by address, then by seqNo,
+    * outputs sorted by address, then by amount.
+
+    Addresses are compared without their `pay:sov:` qualifier and network tag.
+
+    Only the inputs and outputs are put in order here. The keys of the json
+    objects built with serde_json are sorted, not kept in insertion order:
+    the crate is built without serde_json's `preserve_order` feature, so its
+    `Map` is a `BTreeMap`. The keys of an input or output serialized as a
+    `Value` come out sorted, whatever the order its fields are written in.
+
+    With `canonical_order` in the library config, the builders and the
+    handlers sort the inputs and outputs before signing. A builder keeps the
+    order it was given with [`preserve_order`], a handler with the
+    `preserveOrder` of its options, see [`BuildOptions`].
+
+    [`preserve_order`]: ../request_builder/struct.PaymentRequestBuilder.html#method.preserve_order
+    [`BuildOptions`]: ../simulation/struct.BuildOptions.html
+*/
+
+use std::cmp::Ordering;
+
+use logic::address::unqualified_part;
+use logic::config::library_config::library_config;
+use logic::input::{Input, Inputs};
+use logic::output::{Output, Outputs};
+
+/**
+    Whether payments are put and kept in canonical order, set in the library config.
+*/
+pub fn is_enforced() -> bool {
+    library_config().canonical_order
+}
+
+pub fn compare_inputs(a: &Input, b: &Input) -> Ordering {
+    (unqualified_part(&a.address), a.seq_no).cmp(&(unqualified_part(&b.address), b.seq_no))
+}
+
+pub fn compare_outputs(a: &Output, b: &Output) -> Ordering {
+    (unqualified_part(&a.recipient), a.amount).cmp(&(unqualified_part(&b.recipient), b.amount))
+}
+
+/**
+    Sort `inputs` and `outputs` in canonical order.
+*/
+pub fn sort(inputs: &mut Inputs, outputs: &mut Outputs) {
+    inputs.sort_by(compare_inputs);
+    outputs.sort_by(compare_outputs);
+}
+
+pub fn is_canonical(inputs: &Inputs, outputs: &Outputs) -> bool {
+    inputs.windows(2).all(|pair| compare_inputs(&pair[0], &pair[1]) != Ordering::Greater)
+        && outputs.windows(2).all(|pair| compare_outputs(&pair[0], &pair[1]) != Ordering::Greater)
+}
+
+/**
+    Sort `inputs` and `outputs` in canonical order when it is enforced,
+    unless their order is preserved.
+*/
+pub fn put_in_order(inputs: &mut Inputs, outputs: &mut Outputs, preserve_order: bool) {
+    if is_enforced() && !preserve_order {
+        sort(inputs, outputs);
+    }
+}
+
+
+#[cfg(test)]
+mod canonical_order_tests {
+    use logic::config::library_config::LibraryConfig;
+    use utils::test::context::in_own_context;
+
+    use super::*;
+
+    fn input(address: &str, seq_no: u64) -> Input {
+        Input::new(address.to_string(), seq_no)
+    }
+
+    fn output(recipient: &str, amount: u64) -> Output {
+        Output::new(recipient.to_string(), amount)
+    }
+
+    #[test]
+    fn sort_by_address_then_number() {
+        let mut inputs = vec![input("pay:sov:b", 1), input("pay:sov:a", 2), input("pay:sov:a", 1)];
+        let mut outputs = vec![output("pay:sov:b", 1), output("pay:sov:a", 7), output("pay:sov:a", 3)];
+        sort(&mut inputs, &mut outputs);
+
+        assert_eq!(vec![input("pay:sov:a", 1), input("pay:sov:a", 2), input("pay:sov:b", 1)], inputs);
+        assert_eq!(vec![output("pay:sov:a", 3), output("pay:sov:a", 7), output("pay:sov:b", 1)], outputs);
+        assert!(is_canonical(&inputs, &outputs));
+    }
+
+    #[test]
+    fn qualifier_is_ignored() {
+        let inputs = vec![input("a", 1), input("pay:sov:b", 1)];
+        let outputs = vec![output("pay:sov:a", 1), output("b", 1)];
+        assert!(is_canonical(&inputs, &outputs));
+    }
+
+    #[test]
+    fn json_keys_are_sorted() {
+        let json = json!({"seqNo": 1, "address": "pay:sov:a"}).to_string();
+        assert_eq!(r#"{"address":"pay:sov:a","seqNo":1}"#, json);
+    }
+
+    #[test]
+    fn put_in_order_unless_preserved() {
+        let config = LibraryConfig { canonical_order: true, ..LibraryConfig::default() };
+        in_own_context(config, || {
+            let mut inputs = vec![input("pay:sov:b", 1), input("pay:sov:a", 1)];
+            let mut outputs = vec![output("pay:sov:b", 1), output("pay:sov:a", 1)];
+
+            put_in_order(&mut inputs, &mut outputs, true);
+            assert!(!is_canonical(&inputs, &outputs));
+
+            put_in_order(&mut inputs, &mut outputs, false);
+            assert!(is_canonical(&inputs, &outputs));
+        });
+    }
+
+    #[test]
+    fn order_is_kept_when_not_enforced() {
+        in_own_context(LibraryConfig::default(), || {
+            let mut inputs = vec![input("pay:sov:b", 1), input("pay:sov:a", 1)];
+            put_in_order(&mut inputs, &mut Vec::new(), false);
+            assert_eq!(vec![input("pay:sov:b", 1), input("pay:sov:a", 1)], inputs);
+        });
+    }
+
+    #[test]
+    fn out_of_order() {
+        assert!(!is_canonical(&vec![input("pay:sov:a", 2), input("pay:sov:a", 1)], &Vec::new()));
+        assert!(!is_canonical(&Vec::new(), &vec![output("pay:sov:b", 1), output("pay:sov:a", 1)]));
+    }
+}
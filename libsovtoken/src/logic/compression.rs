@@ -0,0 +1,170 @@
+/*!
+    Compression of the blobs libsovtoken stores in the wallet.
+
+    The address records and the pending transactions are non secret wallet
+    records, and a busy agent accumulates many of them, as it does UTXOs
+    persisted by the cache of GET_UTXO replies and audit log entries. With
+    `compression` set in the library config, their values are compressed
+    and base64 encoded behind a prefix naming the algorithm, e.g.
+    `gzip:H4sIAAAA...`. The audit log is compressed line by line, so it
+    stays appendable.
+
+    Reading is transparent: a value without a prefix is the plain json
+    written before compression was enabled, and the stores, the UTXO cache
+    and the audit log rewrite it compressed the first time they read it.
+*/
+
+use std::io::{Read, Write};
+
+use base64;
+use brotli;
+use flate2;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use indy::ErrorCode;
+
+const GZIP_PREFIX: &str = "gzip:";
+const BROTLI_PREFIX: &str = "br:";
+
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW_BITS: u32 = 22;
+
+/**
+    How stored blobs are compressed, serialized in lowercase.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Stored as plain json.
+    None,
+    Gzip,
+    Brotli,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::None
+    }
+}
+
+/**
+    Compress `value` to store it.
+
+    ```
+        use sovtoken::logic::compression::{compress, decompress, Compression};
+
+        let blob = compress(r#"{"state": "signed"}"#, Compression::Gzip).unwrap();
+        assert!(blob.starts_with("gzip:"));
+        assert_eq!(r#"{"state": "signed"}"#, decompress(&blob).unwrap());
+    ```
+*/
+pub fn compress(value: &str, compression: Compression) -> Result<String, ErrorCode> {
+    match compression {
+        Compression::None => Ok(value.to_string()),
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(value.as_bytes())
+                .map_err(map_err_err!())
+                .or(Err(ErrorCode::CommonInvalidState))?;
+            let bytes = encoder.finish()
+                .map_err(map_err_err!())
+                .or(Err(ErrorCode::CommonInvalidState))?;
+            Ok(format!("{}{}", GZIP_PREFIX, base64::encode(&bytes)))
+        },
+        Compression::Brotli => {
+            let mut bytes = Vec::new();
+            {
+                let mut encoder = brotli::CompressorWriter::new(&mut bytes, BROTLI_BUFFER_SIZE, BROTLI_QUALITY, BROTLI_WINDOW_BITS);
+                encoder.write_all(value.as_bytes())
+                    .map_err(map_err_err!())
+                    .or(Err(ErrorCode::CommonInvalidState))?;
+            }
+            Ok(format!("{}{}", BROTLI_PREFIX, base64::encode(&bytes)))
+        },
+    }
+}
+
+/**
+    The value of a stored blob, compressed or not.
+
+    Fails with `CommonInvalidState` when the blob is corrupted.
+*/
+pub fn decompress(blob: &str) -> Result<String, ErrorCode> {
+    let mut value = String::new();
+
+    let read = if blob.starts_with(GZIP_PREFIX) {
+        let bytes = decode(&blob[GZIP_PREFIX.len()..])?;
+        GzDecoder::new(&bytes[..]).read_to_string(&mut value)
+    } else if blob.starts_with(BROTLI_PREFIX) {
+        let bytes = decode(&blob[BROTLI_PREFIX.len()..])?;
+        brotli::Decompressor::new(&bytes[..], BROTLI_BUFFER_SIZE).read_to_string(&mut value)
+    } else {
+        return Ok(blob.to_string());
+    };
+
+    read.map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState))?;
+    Ok(value)
+}
+
+/**
+    Whether a blob read is plain json which should be rewritten compressed.
+*/
+pub fn needs_migration(blob: &str, compression: Compression) -> bool {
+    compression != Compression::None
+        && !blob.starts_with(GZIP_PREFIX)
+        && !blob.starts_with(BROTLI_PREFIX)
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn decode(encoded: &str) -> Result<Vec<u8>, ErrorCode> {
+    base64::decode(encoded)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))
+}
+
+
+#[cfg(test)]
+mod compression_tests {
+    use super::*;
+
+    static VALUE: &'static str = r#"{"state":"signed","request":"{\"operation\":{\"type\":\"10001\"}}","updated":10}"#;
+
+    #[test]
+    fn round_trip() {
+        for compression in &[Compression::None, Compression::Gzip, Compression::Brotli] {
+            let blob = compress(VALUE, *compression).unwrap();
+            assert_eq!(VALUE, decompress(&blob).unwrap(), "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn plain_json_is_read_as_is() {
+        assert_eq!(VALUE, decompress(VALUE).unwrap());
+    }
+
+    #[test]
+    fn corrupted_blob() {
+        assert_eq!(ErrorCode::CommonInvalidState, decompress("gzip:not base64!").unwrap_err());
+        assert_eq!(ErrorCode::CommonInvalidState, decompress("br:AAAA").unwrap_err());
+    }
+
+    #[test]
+    fn large_values_shrink() {
+        let value = vec![VALUE; 100].join(",");
+        for compression in &[Compression::Gzip, Compression::Brotli] {
+            assert!(compress(&value, *compression).unwrap().len() < value.len() / 4);
+        }
+    }
+
+    #[test]
+    fn migration() {
+        assert!(needs_migration(VALUE, Compression::Gzip));
+        assert!(!needs_migration(VALUE, Compression::None));
+        assert!(!needs_migration(&compress(VALUE, Compression::Brotli).unwrap(), Compression::Gzip));
+    }
+}
@@ -4,6 +4,9 @@
     [`build_get_txn_fees_handler`]: ../../../api/fn.build_get_txn_fees_handler.html
  */
 use logic::did::Did;
+use logic::error::SovtokenError;
+use logic::messages;
+use logic::operation::Operation;
 use logic::request::Request;
 use utils::constants::txn_types::GET_FEES;
 
@@ -29,6 +32,8 @@ use utils::constants::txn_types::GET_FEES;
 pub struct GetFeesRequest {
     #[serde(rename = "type")]
     txn_type: String,
+    #[serde(rename = "txnTypes", default, skip_serializing_if = "Option::is_none")]
+    txn_types: Option<Vec<String>>,
 }
 
 impl GetFeesRequest {
@@ -40,7 +45,23 @@ impl GetFeesRequest {
     */
     pub fn new() -> GetFeesRequest {
         return GetFeesRequest {
-            txn_type: GET_FEES.to_string(),
+            txn_type: Self::TYPE_CODE.to_string(),
+            txn_types: None,
+        };
+    }
+
+    /**
+        Creates a new [`GetFeesRequest`] asking only for the fees of `txn_types`.
+
+        Ledgers whose plugin doesn't know the filter return the full schedule,
+        the GET_FEES parser filters it instead.
+
+        [`GetFeesRequest`]: ./struct.GetFeesRequest.html
+    */
+    pub fn for_types(txn_types: Vec<String>) -> GetFeesRequest {
+        return GetFeesRequest {
+            txn_type: Self::TYPE_CODE.to_string(),
+            txn_types: Some(txn_types),
         };
     }
 
@@ -54,6 +75,23 @@ impl GetFeesRequest {
     }
 }
 
+impl Operation for GetFeesRequest {
+    const TYPE_CODE: &'static str = GET_FEES;
+
+    fn validate(&self) -> Result<(), SovtokenError> {
+        match self.txn_types {
+            Some(ref txn_types) if txn_types.is_empty() => {
+                Err(SovtokenError::InvalidStructure(messages::NO_FEE_TXN_TYPES.message()))
+            },
+            Some(ref txn_types) => match txn_types.iter().find(|txn_type| txn_type.parse::<u32>().is_err()) {
+                Some(txn_type) => Err(SovtokenError::InvalidStructure(messages::TXN_TYPE_NOT_INTEGER.with(&[format!("{:?}", txn_type)]))),
+                None => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod get_fees_config_test {
     use super::*;
@@ -100,4 +138,15 @@ mod get_fees_config_test {
             |_fees_req| {}
         );
     }
+
+    #[test]
+    fn valid_request_for_types() {
+        assert_get_fee_request(
+            json!({
+                "type": GET_FEES,
+                "txnTypes": ["1", "10001"],
+            }),
+            |fees_req| { fees_req.operation = GetFeesRequest::for_types(vec![String::from("1"), String::from("10001")]); }
+        );
+    }
 }
\ No newline at end of file
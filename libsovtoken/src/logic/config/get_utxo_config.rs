@@ -6,6 +6,9 @@
  */
 
 use logic::address::strip_qualifier_from_address;
+use logic::error::SovtokenError;
+use logic::messages;
+use logic::operation::Operation;
 use logic::request::Request;
 use utils::constants::txn_types::GET_UTXO;
 use logic::address::verkey_from_unqualified_address;
@@ -28,12 +31,22 @@ impl GetUtxoOperationRequest {
         let identifier = verkey_from_unqualified_address(&unqualified_address).ok();
         let req = GetUtxoOperationRequest {
             address : unqualified_address,
-            req_type : GET_UTXO.to_string(),
+            req_type : Self::TYPE_CODE.to_string(),
         };
         return Request::new(req, identifier);
     }
 }
 
+impl Operation for GetUtxoOperationRequest {
+    const TYPE_CODE: &'static str = GET_UTXO;
+
+    fn validate(&self) -> Result<(), SovtokenError> {
+        verkey_from_unqualified_address(&self.address)
+            .map(|_| ())
+            .map_err(|_| SovtokenError::InvalidStructure(messages::INVALID_ADDRESS.with(&[format!("{:?}", self.address)])))
+    }
+}
+
 
 #[cfg(test)]
 mod get_utxo_config_tests {
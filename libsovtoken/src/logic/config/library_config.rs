@@ -0,0 +1,581 @@
+/*!
+    Defines the library wide config passed into [`sovtoken_init_with_config`].
+
+    [`sovtoken_init_with_config`]: ../../../api/fn.sovtoken_init_with_config.html
+*/
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+use indy::ErrorCode;
+use serde::de::{Deserialize, Deserializer, Error};
+use serde_json::{self, Map, Value};
+use toml;
+
+use logic::address_checksum::ChecksumAlgorithm;
+use logic::amount_format::AmountFormat;
+use logic::compression::Compression;
+use logic::extra::DEFAULT_MAX_EXTRA_BYTES;
+use logic::field_names::FieldNames;
+use logic::input::DuplicateInputs;
+use logic::state::state;
+use logic::storage::StorageKind;
+use logic::type_aliases::ProtocolVersion;
+use logic::request::check_protocol_version;
+use logic::transfer_limits::{DEFAULT_MAX_INPUTS, DEFAULT_MAX_OUTPUTS};
+use logic::utxo_cache::DEFAULT_UTXO_CACHE_SIZE;
+use utils::constants::general::PROTOCOL_VERSION;
+use utils::dead_letter::DEFAULT_DEAD_LETTER_CAPACITY;
+use utils::dispatcher::DispatchMode;
+use utils::logger::LogFormat;
+use utils::random::{ReqIdStrategy, SEEDED_RANDOM};
+use utils::rate_limit;
+
+/**
+    The message size limit of the ledger nodes.
+*/
+pub const DEFAULT_MAX_REQUEST_BYTES: usize = 128 * 1024;
+
+/**
+    How long libindy gets to sign or create a key, in milliseconds.
+*/
+pub const DEFAULT_INDY_TIMEOUT_MS: u64 = 60 * 1000;
+
+/**
+    Prefix of the environment variables overriding a config key.
+
+    `SOVTOKEN_LOG_LEVEL` overrides `log_level`, `SOVTOKEN_MAX_INPUTS`
+    overrides `max_inputs`, and so on for every key:
+
+    ```text
+    SOVTOKEN_STRICT_VALIDATION          strict_validation       true
+    SOVTOKEN_AUDIT_LOG_PATH             audit_log_path          /var/log/sovtoken.log
+    SOVTOKEN_MAX_REQUEST_BYTES          max_request_bytes       131072
+    SOVTOKEN_MAX_INPUTS                 max_inputs              100
+    SOVTOKEN_MAX_OUTPUTS                max_outputs             100
+    SOVTOKEN_CALLBACK_DISPATCH          callback_dispatch       poll
+    SOVTOKEN_FIELD_NAMES                field_names             recipient
+    SOVTOKEN_AMOUNT_FORMAT              amount_format           string
+    SOVTOKEN_ADDRESS_CHECKSUM           address_checksum        sha3
+    SOVTOKEN_CANONICAL_ORDER            canonical_order         true
+    SOVTOKEN_DUPLICATE_INPUTS           duplicate_inputs        dedupe
+    SOVTOKEN_ALLOW_ZERO_OUTPUTS         allow_zero_outputs      true
+    SOVTOKEN_LOG_LEVEL                  log_level               sovtoken=debug
+    SOVTOKEN_LOG_FORMAT                 log_format              json
+    SOVTOKEN_DEVNET_FAUCET              devnet_faucet           false
+    SOVTOKEN_REQ_ID_STRATEGY            req_id_strategy         sequential
+    SOVTOKEN_UTXO_CACHE_SIZE            utxo_cache_size         0
+    SOVTOKEN_COMPRESSION                compression             gzip
+    SOVTOKEN_STORAGE                    storage                 file
+    SOVTOKEN_STORAGE_PATH               storage_path            /var/lib/sovtoken
+    SOVTOKEN_NETWORK                    network                 mainnet
+    SOVTOKEN_VERBOSE_RECEIPTS           verbose_receipts        true
+    SOVTOKEN_MAX_EXTRA_BYTES            max_extra_bytes         1024
+    SOVTOKEN_HASH_OVERSIZED_EXTRA       hash_oversized_extra    true
+    SOVTOKEN_DEFAULT_IDENTIFIER         default_identifier      LibsovtokenDid11111111
+    SOVTOKEN_JSON_ENVELOPE              json_envelope           true
+    SOVTOKEN_RANDOM_SEED                random_seed             42, with the seeded_random feature
+    SOVTOKEN_CREATE_KEY_TIMEOUT_MS      create_key_timeout_ms   60000
+    SOVTOKEN_SIGN_TIMEOUT_MS            sign_timeout_ms         60000
+    SOVTOKEN_PROTOCOL_VERSION           protocol_version        1
+    SOVTOKEN_STATELESS                  stateless               true
+    SOVTOKEN_FORCE_REREGISTER           force_reregister        true
+    SOVTOKEN_RATE_LIMITS                rate_limits             {"payment": 5}
+    SOVTOKEN_DEAD_LETTER_CAPACITY       dead_letter_capacity    64
+    ```
+*/
+pub const ENV_PREFIX: &str = "SOVTOKEN_";
+
+/**
+    Library wide settings.
+
+    Every field has a default, so `{}` is a valid config.
+
+    ```
+        use sovtoken::utils::json_conversion::JsonDeserialize;
+        use sovtoken::logic::config::library_config::LibraryConfig;
+
+        let config = LibraryConfig::from_json(r#"{"strict_validation": true}"#).unwrap();
+        assert!(config.strict_validation);
+    ```
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct LibraryConfig {
+    /// Validate incoming json against the embedded schemas before deserializing it.
+    /// Without it, the json is only checked by deserializing it.
+    pub strict_validation: bool,
+    /// File the audit log is appended to. No audit log is kept when `None`.
+    pub audit_log_path: Option<String>,
+    /// Largest request, in bytes, the ledger accepts.
+    pub max_request_bytes: usize,
+    /// Most inputs an XFER request can have.
+    pub max_inputs: usize,
+    /// Most outputs an XFER request can have, change included.
+    pub max_outputs: usize,
+    /// Where the callbacks of the handlers called by the application run.
+    pub callback_dispatch: DispatchMode,
+    /// The names emitted for the address of inputs and outputs.
+    pub field_names: FieldNames,
+    /// The json type emitted for amounts, `string` for JavaScript clients.
+    pub amount_format: AmountFormat,
+    /// The checksum of the payment addresses, for forks of the ledger plugin.
+    pub address_checksum: ChecksumAlgorithm,
+    /// Sort the inputs and outputs in canonical order before signing them.
+    pub canonical_order: bool,
+    /// Reject or drop the UTXOs given twice as inputs.
+    pub duplicate_inputs: DuplicateInputs,
+    /// Build the outputs of 0 tokens of mints, payments and fees, instead of rejecting them.
+    pub allow_zero_outputs: bool,
+    /// Log filter, in the format of `RUST_LOG`. `RUST_LOG` is used when `None`.
+    pub log_level: Option<String>,
+    /// How the log lines are written, `json` for the log collectors of tracing systems.
+    pub log_format: LogFormat,
+    /// Build test token requests, with the `devnet` feature.
+    pub devnet_faucet: bool,
+    /// How the req_id of the requests built is chosen.
+    pub req_id_strategy: ReqIdStrategy,
+    /// Most GET_UTXO replies kept parsed, 0 disables the cache.
+    pub utxo_cache_size: usize,
+    /// How the address records, pending transactions, persisted UTXOs and audit log are compressed.
+    pub compression: Compression,
+    /// Where the pending transactions and the persisted caches are stored.
+    pub storage: StorageKind,
+    /// Directory of the file storage backend.
+    pub storage_path: Option<String>,
+    /// The ledger network the addresses are tagged with and must belong to.
+    pub network: Option<String>,
+    /// Keep the audit path of the transaction in the receipts of a payment.
+    pub verbose_receipts: bool,
+    /// Largest extra, in bytes, put on the ledger.
+    pub max_extra_bytes: usize,
+    /// Put the hash of a larger extra on the ledger and store the extra, instead of rejecting it.
+    pub hash_oversized_extra: bool,
+    /// Identifier of the requests built without a submitter DID, `LibsovtokenDid11111111` when `None`.
+    pub default_identifier: Option<String>,
+    /// Wrap the results of the handlers called by the application in `{ver, type, data}`.
+    pub json_envelope: bool,
+    /// Seed of the random data, for tests building the same requests on every run, with the `seeded_random` feature. OS random when `None`.
+    pub random_seed: Option<u64>,
+    /// Milliseconds libindy gets to create a key, 0 waits forever.
+    pub create_key_timeout_ms: u64,
+    /// Milliseconds libindy gets to sign a message, 0 waits forever.
+    pub sign_timeout_ms: u64,
+    /// Protocol version the requests are framed with, 1 for the pools older than indy-node 1.4.
+    #[serde(deserialize_with = "deserialize_protocol_version")]
+    pub protocol_version: ProtocolVersion,
+    /// Run without a wallet, signing with the registered signer, see `logic::stateless`.
+    pub stateless: bool,
+    /// Register with libindy again at init, also what an earlier init registered.
+    pub force_reregister: bool,
+    /// Most calls per second of a wallet, by operation type, see `utils::rate_limit`.
+    #[serde(deserialize_with = "deserialize_rate_limits")]
+    pub rate_limits: BTreeMap<String, u32>,
+    /// Most undeliverable results kept, see `utils::dead_letter`.
+    pub dead_letter_capacity: usize,
+}
+
+impl Default for LibraryConfig {
+    fn default() -> Self {
+        LibraryConfig {
+            strict_validation: false,
+            audit_log_path: None,
+            max_request_bytes: DEFAULT_MAX_REQUEST_BYTES,
+            max_inputs: DEFAULT_MAX_INPUTS,
+            max_outputs: DEFAULT_MAX_OUTPUTS,
+            callback_dispatch: DispatchMode::Inline,
+            field_names: FieldNames::Address,
+            amount_format: AmountFormat::Number,
+            address_checksum: ChecksumAlgorithm::DoubleSha256,
+            canonical_order: false,
+            duplicate_inputs: DuplicateInputs::Reject,
+            allow_zero_outputs: false,
+            log_level: None,
+            log_format: LogFormat::Text,
+            devnet_faucet: true,
+            req_id_strategy: ReqIdStrategy::Random,
+            utxo_cache_size: DEFAULT_UTXO_CACHE_SIZE,
+            compression: Compression::None,
+            storage: StorageKind::Wallet,
+            storage_path: None,
+            network: None,
+            verbose_receipts: false,
+            max_extra_bytes: DEFAULT_MAX_EXTRA_BYTES,
+            hash_oversized_extra: false,
+            default_identifier: None,
+            json_envelope: false,
+            random_seed: None,
+            create_key_timeout_ms: DEFAULT_INDY_TIMEOUT_MS,
+            sign_timeout_ms: DEFAULT_INDY_TIMEOUT_MS,
+            protocol_version: PROTOCOL_VERSION,
+            stateless: false,
+            force_reregister: false,
+            rate_limits: BTreeMap::new(),
+            dead_letter_capacity: DEFAULT_DEAD_LETTER_CAPACITY,
+        }
+    }
+}
+
+impl LibraryConfig {
+    /**
+        Read a config file, TOML when the file name ends with `.toml` and json otherwise.
+
+        The keys are the same in both formats. Fails with `CommonIOError`
+        when the file can't be read, and with `CommonInvalidStructure` when
+        it isn't a valid config.
+    */
+    pub fn from_file(path: &Path) -> Result<LibraryConfig, ErrorCode> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .map_err(|error| {
+                error!("Failed to read the config file {:?}: {}", path, error);
+                ErrorCode::CommonIOError
+            })?;
+
+        let is_toml = path.extension().map_or(false, |extension| extension == "toml");
+        let config = if is_toml {
+            toml::from_str(&contents).map_err(|error| error.to_string())
+        } else {
+            serde_json::from_str(&contents).map_err(|error| error.to_string())
+        };
+
+        config.map_err(|error| {
+            error!("Invalid config file {:?}: {}", path, error);
+            ErrorCode::CommonInvalidStructure
+        })
+    }
+
+    /**
+        The config with the keys of `overrides` replaced.
+
+        Fails with `CommonInvalidStructure` when a value doesn't fit its key.
+        Keys the config doesn't have are ignored.
+    */
+    pub fn with_overrides(&self, overrides: &Map<String, Value>) -> Result<LibraryConfig, ErrorCode> {
+        let mut config = match serde_json::to_value(self) {
+            Ok(Value::Object(config)) => config,
+            _ => return Err(ErrorCode::CommonInvalidState),
+        };
+
+        for (key, value) in overrides {
+            if config.contains_key(key) {
+                config.insert(key.clone(), value.clone());
+            } else {
+                warn!("Ignoring unknown config key {:?}", key);
+            }
+        }
+
+        serde_json::from_value(Value::Object(config)).map_err(|error| {
+            error!("Invalid config override: {}", error);
+            ErrorCode::CommonInvalidStructure
+        })
+    }
+
+    /**
+        Check the keys which only make sense together.
+
+        Fails with `CommonInvalidStructure` when the file storage has no
+        `storage_path`, or a `random_seed` is set in a build without the
+        `seeded_random` feature.
+    */
+    pub fn check(&self) -> Result<(), ErrorCode> {
+        if self.storage == StorageKind::File && self.storage_path.is_none() {
+            error!("The file storage needs a storage_path");
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+        if self.random_seed.is_some() && !SEEDED_RANDOM {
+            error!("A random_seed needs a build with the seeded_random feature");
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+        Ok(())
+    }
+}
+
+/**
+    The config keys overridden by the `SOVTOKEN_*` environment variables.
+
+    See [`overrides_from_vars`].
+
+    [`overrides_from_vars`]: fn.overrides_from_vars.html
+*/
+pub fn env_overrides() -> Map<String, Value> {
+    overrides_from_vars(env::vars())
+}
+
+/**
+    The config keys overridden by the variables `vars`.
+
+    A variable `SOVTOKEN_<KEY>` overrides the key `<key>`. Its value is read
+    as json when it can be, e.g. `true` or `20`, and as a string otherwise,
+    so `SOVTOKEN_LOG_LEVEL=sovtoken=debug` needs no quotes. Variables
+    which don't name a config key are ignored.
+
+    ```
+        use sovtoken::logic::config::library_config::overrides_from_vars;
+
+        let vars = vec![(String::from("SOVTOKEN_MAX_INPUTS"), String::from("20"))];
+        assert!(overrides_from_vars(vars).contains_key("max_inputs"));
+    ```
+*/
+pub fn overrides_from_vars<I>(vars: I) -> Map<String, Value>
+    where I: IntoIterator<Item = (String, String)>
+{
+    let keys = match serde_json::to_value(LibraryConfig::default()) {
+        Ok(Value::Object(config)) => config,
+        _ => Map::new(),
+    };
+
+    let mut overrides = Map::new();
+    for (name, value) in vars {
+        if !name.starts_with(ENV_PREFIX) {
+            continue;
+        }
+
+        let key = name[ENV_PREFIX.len()..].to_lowercase();
+        if keys.contains_key(&key) {
+            let value = serde_json::from_str(&value).unwrap_or(Value::String(value));
+            overrides.insert(key, value);
+        }
+    }
+    overrides
+}
+
+lazy_static! {
+    static ref ENV_OVERRIDES: Map<String, Value> = env_overrides();
+}
+
+/**
+    Get a copy of the current library config.
+*/
+pub fn library_config() -> LibraryConfig {
+    state().config.read().clone()
+}
+
+/**
+    Replace the current library config.
+*/
+pub fn set_library_config(config: LibraryConfig) {
+    debug!("Setting library config >>> {:?}", config);
+    *state().config.write() = config;
+}
+
+/**
+    Set the library config at init.
+
+    The keys of `base`, e.g. the defaults or a config file, are overridden
+    by the `SOVTOKEN_*` environment variables, which are overridden by the
+    keys of `explicit`, given to the api. The environment is read once.
+*/
+pub fn init_library_config(base: LibraryConfig, explicit: &Map<String, Value>) -> Result<(), ErrorCode> {
+    let config = resolve_library_config(base, explicit)?;
+    state().env_applied.store(true, Ordering::SeqCst);
+    set_library_config(config);
+    Ok(())
+}
+
+/**
+    The keys of `base` overridden by the `SOVTOKEN_*` environment variables,
+    overridden by the keys of `explicit`, as [`init_library_config`] sets
+    them, e.g. for a context.
+
+    [`init_library_config`]: fn.init_library_config.html
+*/
+pub fn resolve_library_config(base: LibraryConfig, explicit: &Map<String, Value>) -> Result<LibraryConfig, ErrorCode> {
+    let config = base.with_overrides(&ENV_OVERRIDES)?.with_overrides(explicit)?;
+    config.check()?;
+    Ok(config)
+}
+
+/**
+    Apply the `SOVTOKEN_*` environment variables to the current config,
+    unless [`init_library_config`] already did.
+
+    [`init_library_config`]: fn.init_library_config.html
+*/
+pub fn apply_env_overrides() -> Result<(), ErrorCode> {
+    if state().env_applied.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let config = library_config().with_overrides(&ENV_OVERRIDES)?;
+    config.check()?;
+    set_library_config(config);
+    Ok(())
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn deserialize_protocol_version<'de, D>(deserializer: D) -> Result<ProtocolVersion, D::Error>
+    where D: Deserializer<'de>
+{
+    let protocol_version = ProtocolVersion::deserialize(deserializer)?;
+    check_protocol_version(protocol_version)
+        .map_err(|_| D::Error::custom(format!("unsupported protocol version {}", protocol_version)))?;
+    Ok(protocol_version)
+}
+
+fn deserialize_rate_limits<'de, D>(deserializer: D) -> Result<BTreeMap<String, u32>, D::Error>
+    where D: Deserializer<'de>
+{
+    let rate_limits = BTreeMap::<String, u32>::deserialize(deserializer)?;
+    if let Some(operation) = rate_limits.keys().find(|operation| !rate_limit::OPERATIONS.contains(&operation.as_str())) {
+        return Err(D::Error::custom(format!("unknown operation type {} in the rate limits", operation)));
+    }
+    Ok(rate_limits)
+}
+
+
+#[cfg(test)]
+mod library_config_tests {
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use super::*;
+    use utils::json_conversion::JsonDeserialize;
+    use utils::random::rand_string;
+
+    fn config_file(extension: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("sovtoken_config_{}.{}", rand_string(10), extension));
+        File::create(&path).unwrap().write_all(contents.as_bytes()).unwrap();
+        path
+    }
+
+    // keep the table of ENV_PREFIX and the docs of sovtoken_init_with_config in step
+    #[test]
+    fn every_key_is_documented() {
+        let documented = [
+            "strict_validation", "audit_log_path", "max_request_bytes", "max_inputs", "max_outputs",
+            "callback_dispatch", "field_names", "amount_format", "address_checksum", "canonical_order",
+            "duplicate_inputs", "allow_zero_outputs", "log_level", "log_format", "devnet_faucet",
+            "req_id_strategy", "utxo_cache_size", "compression", "storage", "storage_path", "network",
+            "verbose_receipts", "max_extra_bytes", "hash_oversized_extra", "default_identifier",
+            "json_envelope", "random_seed", "create_key_timeout_ms", "sign_timeout_ms", "protocol_version",
+            "stateless", "force_reregister", "rate_limits", "dead_letter_capacity",
+        ];
+        let keys = match serde_json::to_value(LibraryConfig::default()).unwrap() {
+            Value::Object(config) => config,
+            _ => panic!("the config is an object"),
+        };
+
+        let mut keys: Vec<String> = keys.keys().cloned().collect();
+        let mut documented: Vec<String> = documented.iter().map(|key| key.to_string()).collect();
+        keys.sort();
+        documented.sort();
+        assert_eq!(documented, keys);
+    }
+
+    #[test]
+    fn empty_config_is_lenient() {
+        let config = LibraryConfig::from_json("{}").unwrap();
+        assert_eq!(LibraryConfig::default(), config);
+        assert!(!config.strict_validation);
+    }
+
+    #[test]
+    fn deserialize_strict_config() {
+        let config = LibraryConfig::from_json(r#"{"strict_validation": true}"#).unwrap();
+        assert!(config.strict_validation);
+    }
+
+    #[test]
+    fn config_from_json_file() {
+        let path = config_file("json", r#"{"strict_validation": true, "max_inputs": 20}"#);
+        let config = LibraryConfig::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(config.strict_validation);
+        assert_eq!(20, config.max_inputs);
+    }
+
+    #[test]
+    fn config_from_toml_file() {
+        let path = config_file("toml", "callback_dispatch = \"thread\"\nlog_level = \"sovtoken=debug\"\n");
+        let config = LibraryConfig::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(DispatchMode::Thread, config.callback_dispatch);
+        assert_eq!(Some(String::from("sovtoken=debug")), config.log_level);
+    }
+
+    #[test]
+    fn overrides_from_environment_variables() {
+        let vars = vec![
+            (String::from("SOVTOKEN_LOG_LEVEL"), String::from("sovtoken=debug")),
+            (String::from("SOVTOKEN_DEVNET_FAUCET"), String::from("false")),
+            (String::from("SOVTOKEN_REQ_ID_STRATEGY"), String::from("sequential")),
+            (String::from("SOVTOKEN_RANDOM_SEED"), String::from("42")),
+            (String::from("SOVTOKEN_UNKNOWN"), String::from("1")),
+            (String::from("MAX_INPUTS"), String::from("1")),
+        ];
+        let overrides = overrides_from_vars(vars);
+        assert_eq!(4, overrides.len());
+
+        let config = LibraryConfig::default().with_overrides(&overrides).unwrap();
+        assert_eq!(Some(String::from("sovtoken=debug")), config.log_level);
+        assert!(!config.devnet_faucet);
+        assert_eq!(ReqIdStrategy::Sequential, config.req_id_strategy);
+        assert_eq!(Some(42), config.random_seed);
+        assert_eq!(DEFAULT_MAX_INPUTS, config.max_inputs);
+    }
+
+    #[test]
+    fn overrides_keep_the_other_keys() {
+        let config = LibraryConfig { strict_validation: true, ..LibraryConfig::default() };
+        let overrides = overrides_from_vars(vec![(String::from("SOVTOKEN_MAX_INPUTS"), String::from("20"))]);
+        let config = config.with_overrides(&overrides).unwrap();
+
+        assert!(config.strict_validation);
+        assert_eq!(20, config.max_inputs);
+    }
+
+    #[test]
+    fn file_storage_needs_a_path() {
+        let file_storage = json!({"storage": "file"});
+        let config = LibraryConfig::default().with_overrides(file_storage.as_object().unwrap()).unwrap();
+        assert_eq!(ErrorCode::CommonInvalidStructure, config.check().unwrap_err());
+
+        let with_path = json!({"storage": "file", "storage_path": "/tmp/sovtoken"});
+        let config = LibraryConfig::default().with_overrides(with_path.as_object().unwrap()).unwrap();
+        assert!(config.check().is_ok());
+    }
+
+    #[test]
+    fn invalid_override() {
+        let overrides = overrides_from_vars(vec![(String::from("SOVTOKEN_MAX_INPUTS"), String::from("many"))]);
+        assert_eq!(ErrorCode::CommonInvalidStructure, LibraryConfig::default().with_overrides(&overrides).unwrap_err());
+    }
+
+    #[test]
+    fn protocol_version_override() {
+        let overrides = overrides_from_vars(vec![(String::from("SOVTOKEN_PROTOCOL_VERSION"), String::from("1"))]);
+        assert_eq!(1, LibraryConfig::default().with_overrides(&overrides).unwrap().protocol_version);
+
+        let overrides = overrides_from_vars(vec![(String::from("SOVTOKEN_PROTOCOL_VERSION"), String::from("3"))]);
+        assert_eq!(ErrorCode::CommonInvalidStructure, LibraryConfig::default().with_overrides(&overrides).unwrap_err());
+    }
+
+    #[test]
+    fn rate_limits_by_operation_type() {
+        let config = LibraryConfig::from_json(r#"{"rate_limits": {"payment": 5}}"#).unwrap();
+        assert_eq!(Some(&5), config.rate_limits.get("payment"));
+        assert!(LibraryConfig::from_json(r#"{"rate_limits": {"build_payment_req_handler": 5}}"#).is_err());
+    }
+
+    #[test]
+    fn invalid_config_file() {
+        let path = config_file("toml", "strict_validation = \"yes\"");
+        assert_eq!(ErrorCode::CommonInvalidStructure, LibraryConfig::from_file(&path).unwrap_err());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(ErrorCode::CommonIOError, LibraryConfig::from_file(&path).unwrap_err());
+    }
+}
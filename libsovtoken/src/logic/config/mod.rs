@@ -4,7 +4,8 @@
 
 pub mod get_fees_config;
 pub mod get_utxo_config;
+pub mod library_config;
 pub mod output_mint_config;
 pub mod payment_address_config;
 pub mod payment_config;
-pub mod set_fees_config;
\ No newline at end of file
+pub mod set_fees_config;
@@ -5,7 +5,11 @@
  *  [`build_mint_txn_handler`]: ../../../api/fn.build_mint_txn_handler.html
  */
 
+use logic::balance::validate_supply;
 use logic::did::Did;
+use logic::error::SovtokenError;
+use logic::messages;
+use logic::operation::Operation;
 use logic::request::Request;
 use logic::output::Output;
 use utils::constants::txn_types::MINT_PUBLIC;
@@ -32,7 +36,7 @@ impl MintRequest {
      */
     pub fn new(outputs: Vec<Output>, identifier : Option<Did>, extra: Option<String>) -> Request<MintRequest> {
         let mint = MintRequest {
-            txn_type: MINT_PUBLIC.to_string(),
+            txn_type: Self::TYPE_CODE.to_string(),
             outputs,
             extra,
         };
@@ -49,6 +53,18 @@ impl MintRequest {
     }
 }
 
+impl Operation for MintRequest {
+    const TYPE_CODE: &'static str = MINT_PUBLIC;
+
+    fn validate(&self) -> Result<(), SovtokenError> {
+        if self.outputs.is_empty() {
+            return Err(SovtokenError::InvalidStructure(messages::MINT_WITHOUT_OUTPUTS.message()));
+        }
+        validate_supply(&self.outputs)?;
+        Ok(())
+    }
+}
+
 // this test ensures that the deserialized JSON is serialized correctly
 #[cfg(test)]
 mod output_mint_config_test {
@@ -1,21 +1,87 @@
 //!  defines structure and implementation for PaymentAddressConfig which is used
 //! for generating payment addresses
 
+use base64;
+use hex::FromHex;
 use serde_json;
+use sodiumoxide::crypto::sign::{keypair_from_seed, Seed};
 use std::ffi::CString;
+use std::fmt;
+use utils::base58::{FromBase58, IntoBase58};
 use utils::ffi_support::cstring_from_str;
 use utils::json_conversion::JsonSerialize;
 
+/**
+    The length of a seed in bytes, as libsodium requires.
+*/
+pub const SEED_LEN: usize = 32;
+
+// a seed repeating a pattern this long or shorter is too easy to guess
+const MAX_WEAK_PATTERN_LEN: usize = 4;
+
 /**
      The config structure maps to the config json structure
      used to serialize input via serde and use the data in our logic
 
      The seed should be 32 bytes, thats what libsodium requires. Seed can be optional, in that case libsodium generates a random 32 byte seed
 
+     The seed can be given as 32 characters, or as the hex, base64 or
+     base58 of 32 bytes. A client stack producing another form says which with
+     `seed_encoding`, the seed is then decoded as such only. With
+     `strict_seed` a seed repeating a short pattern, e.g. all zeros, is
+     rejected instead of only logged.
+
+     A seed whose key is already in the wallet fails with
+     `WalletItemAlreadyExists` and the existing address in the last error.
+     With `reuse_existing` the existing address is returned instead.
 */
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct PaymentAddressConfig {
+    #[serde(default)]
     pub seed : String,
+    #[serde(default, skip_serializing)]
+    pub strict_seed: bool,
+    #[serde(default, skip_serializing)]
+    pub reuse_existing: bool,
+    #[serde(default, skip_serializing)]
+    pub seed_encoding: Option<SeedEncoding>,
+}
+
+/**
+    How a seed is encoded, serialized in lowercase.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SeedEncoding {
+    /// The bytes of the string itself.
+    Utf8,
+    Hex,
+    Base64,
+}
+
+impl SeedEncoding {
+    /**
+        The bytes of `seed`, fails with `SeedError::NotEncodedAs` when it
+        isn't encoded so.
+    */
+    pub fn decode(&self, seed: &str) -> Result<Vec<u8>, SeedError> {
+        match *self {
+            SeedEncoding::Utf8 => Ok(seed.as_bytes().to_vec()),
+            SeedEncoding::Hex => Vec::<u8>::from_hex(seed).map_err(|_| SeedError::NotEncodedAs(*self)),
+            SeedEncoding::Base64 => base64::decode(seed).map_err(|_| SeedError::NotEncodedAs(*self)),
+        }
+    }
+}
+
+impl fmt::Display for SeedEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SeedEncoding::Utf8 => write!(f, "utf8"),
+            SeedEncoding::Hex => write!(f, "hex"),
+            SeedEncoding::Base64 => write!(f, "base64"),
+        }
+    }
 }
 
 impl PaymentAddressConfig {
@@ -27,4 +93,195 @@ impl PaymentAddressConfig {
         let serialized = JsonSerialize::to_json(&self)?;
         return Ok(cstring_from_str(serialized));
     }
+
+    /**
+        The 32 bytes of the seed, `None` when no seed is given.
+    */
+    pub fn seed_bytes(&self) -> Result<Option<Vec<u8>>, SeedError> {
+        if self.seed.is_empty() {
+            return Ok(None);
+        }
+
+        let decoded = match self.seed_encoding {
+            Some(encoding) => encoding.decode(&self.seed)?,
+            None if self.seed.len() == SEED_LEN => return Ok(Some(self.seed.as_bytes().to_vec())),
+            None if is_hex_seed(&self.seed) => Vec::<u8>::from_hex(&self.seed).map_err(|_| SeedError::NotEncoded)?,
+            None if self.seed.ends_with('=') => base64::decode(&self.seed).map_err(|_| SeedError::NotEncoded)?,
+            None => self.seed.from_base58().map_err(|_| SeedError::NotEncoded)?,
+        };
+
+        if decoded.len() != SEED_LEN {
+            return Err(SeedError::BadLength(decoded.len()));
+        }
+        Ok(Some(decoded))
+    }
+
+    /**
+        The base58 verkey libindy creates from the seed, `None` when no
+        seed is given.
+    */
+    pub fn verkey(&self) -> Result<Option<String>, SeedError> {
+        let bytes = match self.seed_bytes()? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+
+        let seed = Seed::from_slice(&bytes).ok_or(SeedError::BadLength(bytes.len()))?;
+        let (verkey, _) = keypair_from_seed(&seed);
+        Ok(Some(verkey.0.into_base58()))
+    }
+
+    /**
+        Check the seed before it is passed to libindy.
+
+        A base58 seed, or one with a `seed_encoding`, is converted to base64,
+        which libindy understands. A weak seed is rejected with
+        `strict_seed`, otherwise only logged.
+    */
+    pub fn validate(self) -> Result<PaymentAddressConfig, SeedError> {
+        let bytes = match self.seed_bytes()? {
+            Some(bytes) => bytes,
+            None => return Ok(self),
+        };
+
+        if is_weak_seed(&bytes) {
+            if self.strict_seed {
+                return Err(SeedError::Weak);
+            }
+            warn!("The seed of the payment address repeats a short pattern and is easy to guess");
+        }
+
+        let seed = match self.seed_encoding {
+            None if self.seed.len() == SEED_LEN || self.seed.ends_with('=') => self.seed,
+            _ => base64::encode(&bytes),
+        };
+
+        Ok(PaymentAddressConfig { seed, strict_seed: self.strict_seed, reuse_existing: self.reuse_existing, seed_encoding: None })
+    }
+}
+
+/**
+    Why a seed can't be used.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeedError {
+    /// The seed isn't 32 characters, hex, base64 or base58.
+    NotEncoded,
+    /// The seed isn't encoded with the `seed_encoding` of the config.
+    NotEncodedAs(SeedEncoding),
+    /// The decoded seed isn't 32 bytes, holds the decoded length.
+    BadLength(usize),
+    /// The seed repeats a short pattern, rejected with `strict_seed`.
+    Weak,
+}
+
+impl fmt::Display for SeedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &SeedError::NotEncoded => write!(f, "seed is neither {} characters, hex, base64 nor base58", SEED_LEN),
+            &SeedError::NotEncodedAs(encoding) => write!(f, "seed isn't valid {}", encoding),
+            &SeedError::BadLength(len) => write!(f, "seed decodes to {} bytes instead of {}", len, SEED_LEN),
+            &SeedError::Weak => write!(f, "seed repeats a short pattern"),
+        }
+    }
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// 64 hex characters, which would fail as base58 for the 0 or decode to more than 32 bytes
+fn is_hex_seed(seed: &str) -> bool {
+    seed.len() == 2 * SEED_LEN && seed.chars().all(|c| c.is_digit(16))
+}
+
+fn is_weak_seed(bytes: &[u8]) -> bool {
+    (1..MAX_WEAK_PATTERN_LEN + 1).any(|pattern_len| {
+        bytes.iter().enumerate().all(|(i, byte)| *byte == bytes[i % pattern_len])
+    })
+}
+
+
+#[cfg(test)]
+mod payment_address_config_tests {
+    use super::*;
+
+    static SEED: &'static str = "qeWFjZkt9Cr4mhh1VQMrQrKF8a1CeXqN";
+
+    fn config(seed: &str, strict_seed: bool) -> PaymentAddressConfig {
+        PaymentAddressConfig { seed: seed.to_string(), strict_seed, reuse_existing: false, seed_encoding: None }
+    }
+
+    #[test]
+    fn raw_seed_is_kept() {
+        assert_eq!(SEED, config(SEED, true).validate().unwrap().seed);
+    }
+
+    #[test]
+    fn base64_and_base58_seeds() {
+        let base64_seed = base64::encode(SEED.as_bytes());
+        assert_eq!(base64_seed, config(&base64_seed, true).validate().unwrap().seed);
+
+        let base58_seed = SEED.as_bytes().into_base58();
+        assert_eq!(base64_seed, config(&base58_seed, true).validate().unwrap().seed);
+    }
+
+    #[test]
+    fn hex_seed_guessed() {
+        let base64_seed = base64::encode(SEED.as_bytes());
+        let hex_seed: String = SEED.as_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(base64_seed, config(&hex_seed, true).validate().unwrap().seed);
+        assert_eq!(base64_seed, config(&hex_seed.to_uppercase(), true).validate().unwrap().seed);
+
+        let verkey = config("000000000000000000000000Trustee1", false).verkey().unwrap();
+        let hex_trustee: String = b"000000000000000000000000Trustee1".iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(verkey, config(&hex_trustee, false).verkey().unwrap());
+    }
+
+    #[test]
+    fn seed_encodings() {
+        let encoded = |seed: String, seed_encoding| PaymentAddressConfig { seed, strict_seed: true, reuse_existing: false, seed_encoding: Some(seed_encoding) };
+        let base64_seed = base64::encode(SEED.as_bytes());
+
+        let hex_seed: String = SEED.as_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(base64_seed, encoded(hex_seed, SeedEncoding::Hex).validate().unwrap().seed);
+        assert_eq!(base64_seed, encoded(base64_seed.clone(), SeedEncoding::Base64).validate().unwrap().seed);
+        assert_eq!(base64_seed, encoded(SEED.to_string(), SeedEncoding::Utf8).validate().unwrap().seed);
+
+        // no guessing once the encoding is given
+        assert_eq!(SeedError::NotEncodedAs(SeedEncoding::Hex), encoded(SEED.to_string(), SeedEncoding::Hex).validate().unwrap_err());
+        assert_eq!(SeedError::BadLength(16), encoded("ab".repeat(16), SeedEncoding::Hex).validate().unwrap_err());
+        assert_eq!(SeedError::BadLength(44), encoded(base64_seed, SeedEncoding::Utf8).validate().unwrap_err());
+    }
+
+    #[test]
+    fn short_seed() {
+        assert_eq!(SeedError::NotEncoded, config("declivity", false).validate().unwrap_err());
+        assert_eq!(SeedError::BadLength(6), config(&b"abcdef".into_base58(), false).validate().unwrap_err());
+    }
+
+    #[test]
+    fn weak_seed() {
+        let zeros = base64::encode(&[0; SEED_LEN]);
+        assert_eq!(SeedError::Weak, config(&zeros, true).validate().unwrap_err());
+        assert_eq!(SeedError::Weak, config(&"ab".repeat(16), true).validate().unwrap_err());
+        assert!(config(&zeros, false).validate().is_ok());
+    }
+
+    #[test]
+    fn strict_seed_is_not_sent_to_libindy() {
+        assert_eq!(r#"{"seed":""}"#, config("", true).to_json().unwrap());
+    }
+
+    #[test]
+    fn verkey_of_the_seed() {
+        // the verkey libindy creates from the seed
+        let verkey = config("000000000000000000000000Trustee1", false).verkey().unwrap();
+        assert_eq!(Some(String::from("GJ1SzoWzavQYfNL9XkaJdrQejfztN4XqdsiV4ct3LXKL")), verkey);
+
+        let base64_seed = base64::encode("000000000000000000000000Trustee1".as_bytes());
+        assert_eq!(verkey, config(&base64_seed, false).verkey().unwrap());
+        assert_eq!(None, config("", false).verkey().unwrap());
+    }
 }
@@ -6,6 +6,7 @@
  */
 
 use logic::request::Request;
+use logic::request_builder::PaymentRequestBuilder;
 use utils::constants::txn_types::XFER_PUBLIC;
 use logic::xfer_payload::XferPayload;
 
@@ -78,6 +79,15 @@ impl PaymentRequest {
         };
     }
 
+    /**
+        Start a [`PaymentRequestBuilder`].
+
+        [`PaymentRequestBuilder`]: ../../request_builder/struct.PaymentRequestBuilder.html
+    */
+    pub fn builder() -> PaymentRequestBuilder {
+        return PaymentRequestBuilder::new();
+    }
+
     /**
         Transforms `self` to a [`Request<PaymentRequest>`] struct.
 
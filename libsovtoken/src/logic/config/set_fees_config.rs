@@ -6,6 +6,8 @@
 
 use logic::request::Request;
 use logic::did::Did;
+use logic::error::SovtokenError;
+use logic::operation::Operation;
 use std::collections::HashMap;
 use std::fmt;
 use std::error::Error;
@@ -68,7 +70,7 @@ impl SetFees {
     */
     pub fn new(fees: SetFeesMap) -> SetFees {
         return SetFees {
-            txn_type: SET_FEES,
+            txn_type: Self::TYPE_CODE,
             fees,
         };
     }
@@ -151,25 +153,36 @@ impl SetFees {
         [`SetFeesError::KeyNotInteger`]: ./enum.SetFeesError.html#variant.KeyNotInteger
     */
     pub fn validate(self) -> Result<Self, SetFeesError> {
+        self.check_fees()?;
+        return Ok(self);
+    }
+
+    fn check_fees(&self) -> Result<(), SetFeesError> {
         if self.fees.is_empty() {
             return Err(SetFeesError::Empty);
         }
 
-        {
-            let key_not_integer = self.fees
-                .keys()
-                .find(|&key| key.parse::<u32>().is_err());
-            
-            if let Some(key) = key_not_integer {
-                return Err(SetFeesError::KeyNotInteger(key.to_owned()));
-            }
+        let key_not_integer = self.fees
+            .keys()
+            .find(|&key| key.parse::<u32>().is_err());
+
+        if let Some(key) = key_not_integer {
+            return Err(SetFeesError::KeyNotInteger(key.to_owned()));
         }
-    
-        return Ok(self);
+
+        return Ok(());
     }
 
 }
 
+impl Operation for SetFees {
+    const TYPE_CODE: &'static str = SET_FEES;
+
+    fn validate(&self) -> Result<(), SovtokenError> {
+        self.check_fees().map_err(SovtokenError::from)
+    }
+}
+
 /**
     Enum which holds possible errors for [`SetFees::validate`].
 
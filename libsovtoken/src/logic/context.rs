@@ -0,0 +1,233 @@
+/*!
+    Contexts, isolated instances of the library in one process.
+
+    Everything the handlers share lives in a [`State`]: the config, the
+    caches, the queued callbacks, the metrics. A context owns a state of
+    its own, so e.g. a test harness can run one context per network, with
+    its own config and caches, side by side.
+
+    The handlers keep their signatures, which libindy fixes, so the context
+    of a call is the one its thread entered with `sovtoken_context_enter`,
+    the default context when none. The legacy api, `sovtoken_init` and the
+    handlers called through libindy, runs in the default context. The
+    callbacks of a call enter its context, on whatever thread they run,
+    as they enter its span.
+
+    Not per context: the services, which are stateless, the registration
+    with libindy and the logger, which are process wide.
+
+    A context is created with `sovtoken_context_create` and destroyed with
+    `sovtoken_context_destroy`. A destroyed context can't be entered, its
+    state is freed once the callbacks and threads still in it are done.
+
+    [`State`]: ../state/struct.State.html
+*/
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use indy::ErrorCode;
+
+use logic::config::library_config::LibraryConfig;
+use logic::error::SovtokenError;
+use logic::messages;
+use logic::state::{default_state, State};
+use utils::dispatcher;
+use utils::locks::InstrumentedMutex;
+use utils::span;
+
+/**
+    The handle of the default context, the context of the legacy api.
+*/
+pub const DEFAULT_CONTEXT: i32 = 0;
+
+/**
+    A context, its handle and its state.
+
+    The state is shared by the clones of the context, it's freed with the
+    last one.
+*/
+#[derive(Clone)]
+pub struct Context {
+    handle: i32,
+    state: Arc<State>,
+}
+
+impl Context {
+    pub fn handle(&self) -> i32 {
+        self.handle
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /**
+        The state, kept alive as long as the result is.
+    */
+    pub fn shared_state(&self) -> Arc<State> {
+        Arc::clone(&self.state)
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Context>> = RefCell::new(None);
+}
+
+lazy_static! {
+    static ref CONTEXTS: InstrumentedMutex<HashMap<i32, Context>> = InstrumentedMutex::new("contexts", HashMap::new());
+    static ref LAST_HANDLE: AtomicUsize = AtomicUsize::new(DEFAULT_CONTEXT as usize);
+}
+
+/**
+    The context of this thread right now.
+*/
+pub fn current() -> Context {
+    CURRENT.with(|current| current.borrow().clone())
+        .unwrap_or_else(|| Context { handle: DEFAULT_CONTEXT, state: default_state() })
+}
+
+/**
+    Create a context with `config`, returns its handle.
+*/
+pub fn create(config: LibraryConfig) -> i32 {
+    let handle = (LAST_HANDLE.fetch_add(1, Ordering::SeqCst) + 1) as i32;
+    let state = Arc::new(State::with_config(config));
+    CONTEXTS.lock().insert(handle, Context { handle, state });
+
+    info!("Created context {}", handle);
+    handle
+}
+
+/**
+    The context of `handle`.
+
+    Fails with `CommonInvalidStructure` when it isn't the handle of a
+    context, or of a destroyed one.
+*/
+pub fn find(handle: i32) -> Result<Context, ErrorCode> {
+    if handle == DEFAULT_CONTEXT {
+        return Ok(Context { handle, state: default_state() });
+    }
+    CONTEXTS.lock()
+        .get(&handle)
+        .cloned()
+        .ok_or_else(|| ErrorCode::from(SovtokenError::InvalidStructure(messages::UNKNOWN_CONTEXT.with(&[handle.to_string()]))))
+}
+
+/**
+    Run the calls of this thread in the context of `handle`, the default
+    context for `DEFAULT_CONTEXT`.
+*/
+pub fn enter(handle: i32) -> Result<(), ErrorCode> {
+    let context = find(handle)?;
+    CURRENT.with(|current| *current.borrow_mut() = if handle == DEFAULT_CONTEXT { None } else { Some(context) });
+    Ok(())
+}
+
+/**
+    Run `f` in `context`, the context of the thread is restored afterwards.
+*/
+pub fn in_context<F, R>(context: Context, f: F) -> R where F: FnOnce() -> R {
+    let previous = CURRENT.with(|current| current.replace(Some(context)));
+    let result = f();
+    CURRENT.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/**
+    Run `f` on a thread of its own, in the context and span of this thread.
+
+    For the callbacks of libindy which call into libindy again, e.g. to
+    write the wallet: a blocking call on the thread of libindy waits for
+    that thread.
+*/
+pub fn spawn<F>(f: F) where F: FnOnce() + Send + 'static {
+    let context = current();
+    let span = span::current();
+    thread::spawn(move || in_context(context, || span::in_span(span, f)));
+}
+
+/**
+    Destroy the context of `handle`, it can't be entered anymore.
+
+    Fails with `CommonInvalidState` for the default context and with
+    `CommonInvalidStructure` when it isn't the handle of a context.
+*/
+pub fn destroy(handle: i32) -> Result<(), ErrorCode> {
+    if handle == DEFAULT_CONTEXT {
+        return Err(ErrorCode::from(SovtokenError::InvalidState(messages::DEFAULT_CONTEXT_DESTROYED.message())));
+    }
+    let removed = CONTEXTS.lock().remove(&handle);
+    match removed {
+        Some(context) => {
+            dispatcher::drop_queued(context.state());
+            info!("Destroyed context {}", handle);
+            Ok(())
+        },
+        None => Err(ErrorCode::from(SovtokenError::InvalidStructure(messages::UNKNOWN_CONTEXT.with(&[handle.to_string()])))),
+    }
+}
+
+
+#[cfg(test)]
+mod context_tests {
+    use std::sync::mpsc::channel;
+
+    use logic::config::library_config::library_config;
+    use super::*;
+
+    #[test]
+    fn contexts_have_their_own_config() {
+        let mut config = LibraryConfig::default();
+        config.max_inputs = 3;
+        let handle = create(config);
+        assert_ne!(DEFAULT_CONTEXT, handle);
+
+        enter(handle).unwrap();
+        assert_eq!(3, library_config().max_inputs);
+        assert_eq!(handle, current().handle());
+
+        enter(DEFAULT_CONTEXT).unwrap();
+        assert_eq!(LibraryConfig::default().max_inputs, library_config().max_inputs);
+
+        let context = find(handle).unwrap();
+        assert_eq!(3, in_context(context, || library_config().max_inputs));
+        assert_eq!(DEFAULT_CONTEXT, current().handle());
+
+        destroy(handle).unwrap();
+        assert_eq!(ErrorCode::CommonInvalidStructure, enter(handle).unwrap_err());
+        assert_eq!(ErrorCode::CommonInvalidState, destroy(DEFAULT_CONTEXT).unwrap_err());
+    }
+
+    #[test]
+    fn destroyed_context_frees_its_state() {
+        let handle = create(LibraryConfig::default());
+        let context = find(handle).unwrap();
+        let state = Arc::downgrade(&context.shared_state());
+
+        // a callback still in the context keeps the state
+        destroy(handle).unwrap();
+        assert!(state.upgrade().is_some());
+
+        drop(context);
+        assert!(state.upgrade().is_none());
+    }
+
+    #[test]
+    fn spawned_in_the_context_and_span() {
+        let handle = create(LibraryConfig::default());
+        let context = find(handle).unwrap();
+        let (sender, receiver) = channel();
+
+        in_context(context, || span::with_command_handle(7, || {
+            spawn(move || sender.send((current().handle(), span::current().command_handle)).unwrap());
+        }));
+
+        assert_eq!((handle, Some(7)), receiver.recv().unwrap());
+        destroy(handle).unwrap();
+    }
+}
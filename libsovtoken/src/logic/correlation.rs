@@ -0,0 +1,354 @@
+/*!
+    Matching ledger replies to the requests they answer.
+
+    A caller submitting several requests at once gets the replies back in any
+    order. The [`Correlator`] matches every reply to its request by `reqId`
+    and parses it with the parser registered for the type of the request.
+
+    [`Correlator`]: ./struct.Correlator.html
+*/
+
+use std::collections::HashMap;
+
+use indy::ErrorCode;
+use serde_json;
+use serde_json::Value as SJsonValue;
+
+use logic::parsers::error_code_parser::parse_error_code_from_string;
+use logic::parsers::legacy_reply;
+use logic::parsers::parse_get_txn_fees::parse_fees_from_get_txn_fees_response;
+use logic::parsers::parse_get_utxo_response::{self, ParseGetUtxoResponse};
+use logic::parsers::parse_payment_response::{self, ParsePaymentResponse};
+use logic::request_hash::request_hash;
+use logic::type_aliases::ReqId;
+use utils::constants::txn_types::{GET_FEES, GET_UTXO, XFER_PUBLIC};
+use utils::json_conversion::{JsonDeserialize, JsonSerialize};
+
+/**
+    Parses the json of a reply into the json handed to the caller.
+*/
+pub type ReplyParser = Box<Fn(&str) -> Result<String, ErrorCode>>;
+
+/**
+    What became of a request.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum CorrelationStatus {
+    /// The reply was received and parsed.
+    Parsed,
+    /// The ledger refused the request, or its reply couldn't be parsed.
+    Failed,
+    /// No reply was received for the request.
+    Pending,
+}
+
+/**
+    The outcome of a single request.
+
+    `result` is the parsed reply when `status` is `parsed`, `error` is the
+    error code when `status` is `failed`. A request without a registered
+    parser has the reply itself as `result`. `requestHash` is the canonical
+    hash of the request, see `logic::request_hash`.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Correlation {
+    pub req_id: ReqId,
+    #[serde(rename = "type")]
+    pub txn_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_hash: Option<String>,
+    pub status: CorrelationStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<i32>,
+}
+
+/**
+    The outcome of every request, in the order of the requests, and the
+    replies that didn't match any request.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CorrelationReport {
+    pub correlations: Vec<Correlation>,
+    pub unmatched_replies: Vec<String>,
+}
+
+/**
+    Correlator matches replies to requests and dispatches them to a parser by
+    the transaction type of the request.
+
+    ```
+        use sovtoken::logic::correlation::{Correlator, CorrelationStatus};
+
+        let correlator = Correlator::new()
+            .parser("1", Box::new(|_reply: &str| Ok(String::from("parsed"))));
+
+        let requests = vec![String::from(r#"{"reqId": 7, "operation": {"type": "1"}}"#)];
+        let replies = vec![String::from(r#"{"op": "REPLY", "result": {"reqId": 7}}"#)];
+
+        let report = correlator.correlate(&requests, &replies).unwrap();
+        assert_eq!(CorrelationStatus::Parsed, report.correlations[0].status);
+        assert_eq!(Some(String::from("parsed")), report.correlations[0].result);
+    ```
+*/
+pub struct Correlator {
+    parsers: HashMap<String, ReplyParser>,
+}
+
+impl Correlator {
+    /**
+        A correlator without any parsers.
+    */
+    pub fn new() -> Self {
+        Correlator { parsers: HashMap::new() }
+    }
+
+    /**
+        A correlator with the parsers of the payment transactions: XFER_PUBLIC,
+        GET_UTXO and GET_FEES.
+    */
+    pub fn with_default_parsers() -> Self {
+        Correlator::new()
+            .parser(XFER_PUBLIC, Box::new(parse_payment_reply))
+            .parser(GET_UTXO, Box::new(parse_get_utxo_reply))
+            .parser(GET_FEES, Box::new(parse_get_fees_reply))
+    }
+
+    /**
+        Parse replies to requests of `txn_type` with `parser`.
+    */
+    pub fn parser(mut self, txn_type: &str, parser: ReplyParser) -> Self {
+        self.parsers.insert(txn_type.to_string(), parser);
+        self
+    }
+
+    /**
+        Match `replies` to `requests` by `reqId` and parse them.
+
+        Fails with `CommonInvalidStructure` when a request isn't json or has
+        no `reqId`. Replies without a known `reqId` end up in the unmatched
+        replies. When several replies match the same request, the first one wins.
+    */
+    pub fn correlate(&self, requests: &[String], replies: &[String]) -> Result<CorrelationReport, ErrorCode> {
+        trace!("logic::correlation::correlate >> requests: {:?}, replies: {:?}", requests.len(), replies.len());
+
+        let mut correlations = requests.iter()
+            .map(|request_json| {
+                let request = parse_json(request_json)?;
+                let req_id = request_req_id(&request).ok_or(ErrorCode::CommonInvalidStructure)?;
+                let txn_type = request["operation"]["type"].as_str().map(String::from);
+                let request_hash = request_hash(request_json).ok();
+                Ok(Correlation { req_id, txn_type, request_hash, status: CorrelationStatus::Pending, result: None, error: None })
+            })
+            .collect::<Result<Vec<Correlation>, ErrorCode>>()?;
+
+        let positions: HashMap<ReqId, usize> = correlations.iter().enumerate()
+            .map(|(position, correlation)| (correlation.req_id, position))
+            .collect();
+
+        let mut unmatched_replies = Vec::new();
+        for reply in replies {
+            let position = parse_json(reply).ok()
+                .and_then(|json| reply_req_id(&json))
+                .and_then(|req_id| positions.get(&req_id).cloned())
+                .and_then(|position| if correlations[position].status == CorrelationStatus::Pending { Some(position) } else { None });
+
+            match position {
+                Some(position) => self.dispatch(&mut correlations[position], reply),
+                None => unmatched_replies.push(reply.clone()),
+            }
+        }
+
+        let report = CorrelationReport { correlations, unmatched_replies };
+        trace!("logic::correlation::correlate << report: {:?}", report);
+        Ok(report)
+    }
+
+    fn dispatch(&self, correlation: &mut Correlation, reply: &str) {
+        let parsed = match correlation.txn_type.as_ref().and_then(|txn_type| self.parsers.get(txn_type)) {
+            Some(parser) => parser(reply),
+            None => refusal(reply).map(|_| reply.to_string()),
+        };
+
+        match parsed {
+            Ok(result) => {
+                correlation.status = CorrelationStatus::Parsed;
+                correlation.result = Some(result);
+            },
+            Err(ec) => {
+                correlation.status = CorrelationStatus::Failed;
+                correlation.error = Some(ec as i32);
+            },
+        }
+    }
+}
+
+/**
+    Read a json array of requests or replies.
+
+    The elements can be the json objects themselves or strings holding them.
+*/
+pub fn messages_from_json(json: &str) -> Result<Vec<String>, ErrorCode> {
+    let messages: Vec<SJsonValue> = serde_json::from_str(json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    Ok(messages.into_iter()
+        .map(|message| match message {
+            SJsonValue::String(message) => message,
+            message => message.to_string(),
+        })
+        .collect())
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn parse_json(json: &str) -> Result<SJsonValue, ErrorCode> {
+    serde_json::from_str(json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))
+}
+
+fn request_req_id(request: &SJsonValue) -> Option<ReqId> {
+    request["reqId"].as_u64().map(|req_id| req_id as ReqId)
+}
+
+// REQNACK and REJECT carry the reqId on the top level, a REPLY in its result
+// or, for written transactions, in the metadata of the transaction.
+fn reply_req_id(reply: &SJsonValue) -> Option<ReqId> {
+    reply["reqId"].as_u64()
+        .or(reply["result"]["reqId"].as_u64())
+        .or(reply["result"]["txn"]["metadata"]["reqId"].as_u64())
+        .map(|req_id| req_id as ReqId)
+}
+
+// Fails with the error of the reason when the ledger refused the request.
+fn refusal(reply: &str) -> Result<(), ErrorCode> {
+    let reply = parse_json(reply)?;
+    match reply["op"].as_str() {
+        Some("REQNACK") | Some("REJECT") => Err(parse_error_code_from_string(reply["reason"].as_str().unwrap_or(""))),
+        _ => Ok(()),
+    }
+}
+
+fn parse_payment_reply(reply: &str) -> Result<String, ErrorCode> {
+    let response = ParsePaymentResponse::from_json(&legacy_reply::nest_json(reply)?)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+    parse_payment_response::from_response(response)?
+        .to_json()
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))
+}
+
+fn parse_get_utxo_reply(reply: &str) -> Result<String, ErrorCode> {
+    let response = ParseGetUtxoResponse::from_json(reply)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+    parse_get_utxo_response::from_response(response)?
+        .to_json()
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))
+}
+
+fn parse_get_fees_reply(reply: &str) -> Result<String, ErrorCode> {
+    refusal(reply)?;
+    parse_fees_from_get_txn_fees_response(reply.to_string())
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))
+}
+
+
+#[cfg(test)]
+mod correlation_tests {
+    use super::*;
+
+    fn request(req_id: ReqId, txn_type: &str) -> String {
+        json!({"reqId": req_id, "identifier": "did", "operation": {"type": txn_type}}).to_string()
+    }
+
+    fn echo_correlator() -> Correlator {
+        Correlator::new().parser("1", Box::new(|reply: &str| Ok(reply.to_string())))
+    }
+
+    #[test]
+    fn correlate_out_of_order() {
+        let requests = vec![request(1, "1"), request(2, "1")];
+        let replies = vec![
+            json!({"op": "REPLY", "result": {"reqId": 2, "seqNo": 20}}).to_string(),
+            json!({"op": "REPLY", "result": {"reqId": 1, "seqNo": 10}}).to_string(),
+        ];
+
+        let report = echo_correlator().correlate(&requests, &replies).unwrap();
+
+        assert_eq!(Some(replies[1].clone()), report.correlations[0].result);
+        assert_eq!(Some(replies[0].clone()), report.correlations[1].result);
+        assert_eq!(request_hash(&requests[1]).ok(), report.correlations[1].request_hash);
+        assert!(report.unmatched_replies.is_empty());
+    }
+
+    #[test]
+    fn correlate_pending_and_unmatched() {
+        let requests = vec![request(1, "1")];
+        let replies = vec![json!({"op": "REPLY", "result": {"reqId": 3}}).to_string()];
+
+        let report = echo_correlator().correlate(&requests, &replies).unwrap();
+
+        assert_eq!(CorrelationStatus::Pending, report.correlations[0].status);
+        assert_eq!(replies, report.unmatched_replies);
+    }
+
+    #[test]
+    fn correlate_rejected_without_parser() {
+        let requests = vec![request(1, "100")];
+        let replies = vec![json!({"op": "REJECT", "reqId": 1, "reason": "InsufficientFundsError"}).to_string()];
+
+        let report = Correlator::new().correlate(&requests, &replies).unwrap();
+
+        assert_eq!(CorrelationStatus::Failed, report.correlations[0].status);
+        assert_eq!(Some(ErrorCode::PaymentInsufficientFundsError as i32), report.correlations[0].error);
+    }
+
+    #[test]
+    fn correlate_reply_by_txn_metadata() {
+        let requests = vec![request(5, "100")];
+        let replies = vec![json!({"op": "REPLY", "result": {"txn": {"metadata": {"reqId": 5}}}}).to_string()];
+
+        let report = Correlator::new().correlate(&requests, &replies).unwrap();
+
+        assert_eq!(CorrelationStatus::Parsed, report.correlations[0].status);
+        assert_eq!(Some(replies[0].clone()), report.correlations[0].result);
+    }
+
+    #[test]
+    fn correlate_request_without_req_id() {
+        let requests = vec![json!({"operation": {"type": "1"}}).to_string()];
+        let error = Correlator::new().correlate(&requests, &vec![]).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error);
+    }
+
+    #[test]
+    fn messages_as_objects_or_strings() {
+        let json = json!([{"reqId": 1}, "{\"reqId\":2}"]).to_string();
+        let messages = messages_from_json(&json).unwrap();
+        assert_eq!(vec![r#"{"reqId":1}"#, r#"{"reqId":2}"#], messages);
+    }
+
+    #[test]
+    fn default_parser_failure() {
+        let requests = vec![request(1, GET_UTXO)];
+        let replies = vec![json!({"op": "REPLY", "result": {"reqId": 1}}).to_string()];
+
+        let report = Correlator::with_default_parsers().correlate(&requests, &replies).unwrap();
+
+        assert_eq!(CorrelationStatus::Failed, report.correlations[0].status);
+    }
+}
@@ -0,0 +1,131 @@
+/*!
+    Test token faucet for developer networks.
+
+    Only compiled with the `devnet` feature. Builds a MINT request for a single
+    address and multi signs it with the trustee dids derived from the bundled
+    test trustee seeds.
+
+    The faucet can be turned off at run time with `devnet_faucet` in the
+    library config, e.g. with `SOVTOKEN_DEVNET_FAUCET=false`.
+
+    **Never enable this feature for a build which talks to a production network.**
+*/
+
+use indy::{IndyHandle, ErrorCode};
+use indy::did::Did as IndyDid;
+use sodiumoxide::crypto::sign::{keypair_from_seed, Seed};
+
+use logic::address;
+use logic::config::library_config::library_config;
+use logic::config::output_mint_config::MintRequest;
+use logic::did::Did;
+use logic::output::Output;
+use logic::request::Request;
+use logic::type_aliases::TokenAmount;
+use utils::base58::IntoBase58;
+
+/**
+    Seeds of the trustees registered in the genesis transactions of the developer pool.
+*/
+pub const DEVNET_TRUSTEE_SEEDS: &'static [&'static str] = &[
+    "000000000000000000000000Trustee1",
+];
+
+/**
+    Store the bundled trustee dids in the wallet and return them.
+
+    A trustee which is already in the wallet is not an error.
+*/
+pub fn store_trustee_dids(wallet_handle: IndyHandle) -> Result<Vec<String>, ErrorCode> {
+    DEVNET_TRUSTEE_SEEDS.iter()
+        .map(|seed| store_trustee_did(wallet_handle, seed))
+        .collect()
+}
+
+/**
+    Build a MINT request of `amount` tokens to `payment_address`, signed by the
+    bundled trustees.
+
+    Returns the signed request json. Fails with `PaymentOperationNotSupportedError`
+    when `devnet_faucet` is off in the library config.
+*/
+pub fn build_test_tokens_request(
+    wallet_handle: IndyHandle,
+    payment_address: &str,
+    amount: TokenAmount
+) -> Result<String, ErrorCode> {
+    trace!("logic::devnet::build_test_tokens_request >> payment_address: {:?}, amount: {:?}", payment_address, amount);
+    if !library_config().devnet_faucet {
+        error!("The devnet faucet is turned off in the library config");
+        return Err(ErrorCode::PaymentOperationNotSupportedError);
+    }
+
+    let recipient = address::unqualified_address_from_address(payment_address)?;
+
+    let trustees = store_trustee_dids(wallet_handle)?;
+    let submitter = trustees.get(0).ok_or(ErrorCode::CommonInvalidState)?.clone();
+
+    let mint_request = MintRequest::new(
+        vec![Output::new(recipient, amount)],
+        Some(Did::new(&submitter)),
+        None
+    );
+    info!("Built a test tokens mint request >>> {:?}", mint_request);
+
+    let mint_request = mint_request.serialize_to_string()
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))?;
+
+    let dids = trustees.iter().map(String::as_str).collect();
+    let res = Request::<MintRequest>::multi_sign_request(wallet_handle, &mint_request, dids);
+
+    trace!("logic::devnet::build_test_tokens_request << res: {:?}", res);
+    res
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn store_trustee_did(wallet_handle: IndyHandle, seed: &str) -> Result<String, ErrorCode> {
+    let seed_json = json!({ "seed": seed }).to_string();
+
+    match IndyDid::new(wallet_handle, &seed_json) {
+        Ok((did, _)) => Ok(did),
+        Err(ErrorCode::DidAlreadyExistsError) => did_from_seed(seed),
+        Err(e) => Err(e),
+    }
+}
+
+// The did is the first 16 bytes of the verkey, same as libindy derives it.
+fn did_from_seed(seed: &str) -> Result<String, ErrorCode> {
+    let seed = Seed::from_slice(seed.as_bytes()).ok_or(ErrorCode::CommonInvalidStructure)?;
+    let (verkey, _) = keypair_from_seed(&seed);
+    Ok((&verkey.0[..16]).into_base58())
+}
+
+
+#[cfg(test)]
+mod devnet_tests {
+    use super::*;
+
+    #[test]
+    fn did_from_trustee_seed() {
+        let did = did_from_seed("000000000000000000000000Trustee1").unwrap();
+        assert_eq!("V4SGRU86Z58d6TV7PBUe6f", did);
+    }
+
+    #[test]
+    fn did_from_invalid_seed() {
+        let error = did_from_seed("Trustee1").unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error);
+    }
+
+    #[test]
+    fn test_tokens_invalid_address() {
+        let error = build_test_tokens_request(1, "pay:sov:notanaddress", 10).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error);
+    }
+}
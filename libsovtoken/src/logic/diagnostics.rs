@@ -0,0 +1,184 @@
+/*!
+    A report of how the library was built and what it is doing, to attach
+    to a support request when an integration misbehaves.
+*/
+
+use serde_json::{self, Value};
+
+use logic::config::library_config::library_config;
+use logic::messages::{self, LastError};
+use logic::registration::{self, RegistrationStatus};
+use logic::state::state;
+use logic::utxo_cache::UtxoCacheStats;
+use utils::ffi_support::{buffer_pool_stats, BufferPoolStats};
+
+// Config values not to put in a report sent to support.
+const REDACTED_CONFIG: &[&str] = &["random_seed"];
+
+/**
+    The diagnostics report.
+
+    ```text
+    {
+        "version": <str>,                   // of libsovtoken
+        "libindy": {
+            "crate": <str>,                 // the version of the indy crate built against
+            "linkage": "dylib" | "static"
+        },
+        "features": [<str>, ...],           // the cargo features built with
+        "config": {...},                    // the library config, random_seed redacted
+        "callbacks": {
+            "queued": <int>,                // waiting for sovtoken_poll_events
+            "syncWaiting": <int>            // _sync calls waiting for their callback
+        },
+        "caches": {
+            "utxoReplies": <int>,
+            "utxoCacheStats": {"hits": <int>, "misses": <int>, "evictions": <int>},
+            "feeSchedule": <int>,           // txn types with a known fee
+            "addressSearches": <int>,       // open payment address searches
+            "optimisticUpdates": <int>,
+            "bufferPool": {...}             // as sovtoken_get_buffer_pool_stats_json
+        },
+        "registration": {...},              // as sovtoken_get_registration_status_json, null before init
+        "lastError": {...}                  // the last error of the calling thread, null when none
+    }
+    ```
+*/
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostics {
+    pub version: &'static str,
+    pub libindy: LibindyBuild,
+    pub features: Vec<&'static str>,
+    pub config: Value,
+    pub callbacks: CallbackCounts,
+    pub caches: CacheSizes,
+    pub registration: Option<RegistrationStatus>,
+    pub last_error: Option<LastError>,
+}
+
+/**
+    The libindy the library was built against.
+
+    libindy doesn't report its own version, the version of the wrapper
+    crate tells the api the library expects.
+*/
+#[derive(Serialize, Debug)]
+pub struct LibindyBuild {
+    #[serde(rename = "crate")]
+    pub crate_version: &'static str,
+    pub linkage: &'static str,
+}
+
+/**
+    The callbacks the library owes.
+*/
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CallbackCounts {
+    pub queued: usize,
+    pub sync_waiting: usize,
+}
+
+/**
+    The number of entries of the caches of the state.
+*/
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheSizes {
+    pub utxo_replies: usize,
+    pub utxo_cache_stats: UtxoCacheStats,
+    pub fee_schedule: usize,
+    pub address_searches: usize,
+    pub optimistic_updates: usize,
+    pub buffer_pool: BufferPoolStats,
+}
+
+/**
+    The report now.
+
+    The last error is the one of the calling thread, so call it on the
+    thread of the call which failed.
+*/
+pub fn diagnostics() -> Diagnostics {
+    let state = state();
+
+    let callbacks = CallbackCounts {
+        queued: state.callback_queue.lock().len(),
+        sync_waiting: state.sync_calls.lock().len(),
+    };
+
+    // one lock at a time, the order of the state isn't the order of the report
+    let (utxo_replies, utxo_cache_stats) = {
+        let utxo_cache = state.utxo_cache.lock();
+        (utxo_cache.len(), utxo_cache.stats())
+    };
+    let caches = CacheSizes {
+        utxo_replies,
+        utxo_cache_stats,
+        fee_schedule: state.fee_schedule.lock().len(),
+        address_searches: state.address_searches.lock().len(),
+        optimistic_updates: state.optimistic_updates.lock().len(),
+        buffer_pool: buffer_pool_stats(),
+    };
+
+    Diagnostics {
+        version: env!("CARGO_PKG_VERSION"),
+        libindy: LibindyBuild {
+            crate_version: option_env!("SOVTOKEN_INDY_CRATE_VERSION").unwrap_or("unknown"),
+            linkage: option_env!("SOVTOKEN_LIBINDY_LINKAGE").unwrap_or("unknown"),
+        },
+        features: enabled_features(),
+        config: config_snapshot(),
+        callbacks,
+        caches,
+        registration: registration::status(),
+        last_error: messages::last_error(),
+    }
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn enabled_features() -> Vec<&'static str> {
+    let features = [
+        ("integration", cfg!(feature = "integration")),
+        ("devnet", cfg!(feature = "devnet")),
+        ("fixtures", cfg!(feature = "fixtures")),
+        ("deadlock_detection", cfg!(feature = "deadlock_detection")),
+        ("cli", cfg!(feature = "cli")),
+    ];
+    features.iter().filter(|&&(_, enabled)| enabled).map(|&(name, _)| name).collect()
+}
+
+fn config_snapshot() -> Value {
+    let mut config = serde_json::to_value(library_config()).unwrap_or(Value::Null);
+    if let Some(config) = config.as_object_mut() {
+        for key in REDACTED_CONFIG {
+            if config.get(*key).map_or(false, |value| !value.is_null()) {
+                config.insert(key.to_string(), json!("<redacted>"));
+            }
+        }
+    }
+    config
+}
+
+
+#[cfg(test)]
+mod diagnostics_tests {
+    use indy::ErrorCode;
+    use super::*;
+
+    #[test]
+    fn report_of_the_library() {
+        messages::set_last_error(ErrorCode::CommonInvalidStructure, messages::INVALID_SEED.message());
+
+        let report = serde_json::to_value(diagnostics()).unwrap();
+        assert_eq!(json!(env!("CARGO_PKG_VERSION")), report["version"]);
+        assert!(report["config"].get("utxo_cache_size").is_some());
+        assert!(report["caches"]["bufferPool"].get("pooled").is_some());
+        assert_eq!(json!(messages::INVALID_SEED.message().id), report["lastError"]["id"]);
+    }
+}
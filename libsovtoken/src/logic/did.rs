@@ -6,6 +6,8 @@ use libc::c_char;
 use std::char;
 
 use bs58::decode::DecodeError;
+use indy::ErrorCode;
+use logic::error::SovtokenError;
 use utils::ffi_support::str_from_char_ptr;
 use utils::base58::{FromBase58};
 
@@ -42,6 +44,23 @@ impl<'a> Did<'a> {
         return str_from_char_ptr(pointer).map(Self::new);
     }
 
+    /**
+        The submitter of a read request, validated.
+
+        A null pointer and the placeholder `""` are `None`, the request is
+        then built with the [`default_identifier`].
+
+        [`default_identifier`]: ../request/fn.default_identifier.html
+    */
+    pub fn submitter_from_pointer(pointer: *const c_char) -> Result<Option<Self>, ErrorCode> {
+        match Self::from_pointer(pointer) {
+            None | Some(Did("")) => Ok(None),
+            Some(did) => did.validate()
+                .map(Some)
+                .map_err(|error| ErrorCode::from(SovtokenError::from(error))),
+        }
+    }
+
     /**
          Validate the did
 
@@ -139,6 +158,13 @@ mod test_did_validation {
         assert_eq!(Err(DidError::InvalidChar('!')), Did::from_pointer(pointer).unwrap().validate());
     }
 
+    #[test]
+    fn submitter_placeholder() {
+        assert_eq!(Ok(None), Did::submitter_from_pointer(ptr::null()));
+        assert_eq!(Ok(None), Did::submitter_from_pointer(c_pointer_from_str("")));
+        assert_eq!(Err(ErrorCode::CommonInvalidStructure), Did::submitter_from_pointer(c_pointer_from_str("123456789abcd!efghij")));
+    }
+
     #[test]
     fn did_valid_deserialize() {
         let pointer = c_pointer_from_str("1123456789abcdefghijk");
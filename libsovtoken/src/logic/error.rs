@@ -0,0 +1,311 @@
+/*!
+    The errors of libsovtoken and the [`ErrorCode`]s they are reported as.
+
+    libindy reserves a range of error codes for payment methods. Errors a host
+    application can act on are reported with those codes; only malformed
+    input and internal failures use the common codes.
+
+    | [`SovtokenError`]            | [`ErrorCode`]                        |
+    |------------------------------|--------------------------------------|
+    | `InvalidStructure`           | `CommonInvalidStructure`             |
+    | `InvalidState`               | `CommonInvalidState`                 |
+    | `InsufficientFunds`          | `PaymentInsufficientFundsError`      |
+    | `ExtraFunds`                 | `PaymentExtraFundsError`             |
+    | `SourceDoesNotExist`         | `PaymentSourceDoesNotExistError`     |
+    | `IncompatiblePaymentMethod`  | `PaymentIncompatibleMethodsError`    |
+    | `OperationNotSupported`      | `PaymentOperationNotSupportedError`  |
+    | `TimedOut`                   | `CommonIOError`                      |
+    | `AlreadyExists`              | `WalletItemAlreadyExists`            |
+    | `Busy`                       | `CommonInvalidState`                 |
+    | `Indy(code)`                 | `code`                               |
+
+    The more specific errors of the modules convert into a [`SovtokenError`],
+    and from there into an [`ErrorCode`] at the FFI boundary. The message of
+    an error comes from the [`messages`] catalog, and is kept as the last
+    error of the thread when the error is reported.
+
+    [`ErrorCode`]: ../../../indy/enum.ErrorCode.html
+    [`SovtokenError`]: ./enum.SovtokenError.html
+    [`messages`]: ../messages/index.html
+*/
+
+use std::error::Error;
+use std::fmt;
+
+use indy::ErrorCode;
+
+use logic::balance::BalanceError;
+use logic::config::payment_address_config::SeedError;
+use logic::config::set_fees_config::SetFeesError;
+use logic::did::DidError;
+use logic::input::DuplicateInputError;
+use logic::messages::{self, Message};
+use logic::parsers::common::TxoParseError;
+use utils::base58::Base58Error;
+use utils::secret_sharing::ShareError;
+
+const INSUFFICIENT_FUNDS_ERROR: &str = "InsufficientFundsError";
+const EXTRA_FUNDS_ERROR: &str = "ExtraFundsError";
+const INVALID_FUNDS: &str = "InvalidFundsError";
+
+/**
+    An error of libsovtoken, with the catalog message of what went wrong.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SovtokenError {
+    /// The input is malformed.
+    InvalidStructure(Message),
+    /// The library or wallet is in a state the call can't handle.
+    InvalidState(Message),
+    /// The inputs don't cover the outputs and fees.
+    InsufficientFunds(Message),
+    /// The inputs hold more than the outputs and fees.
+    ExtraFunds(Message),
+    /// A UTXO doesn't exist, or isn't spendable anymore.
+    SourceDoesNotExist(Message),
+    /// An address belongs to another payment method.
+    IncompatiblePaymentMethod(Message),
+    /// The ledger or library doesn't support the operation.
+    OperationNotSupported(Message),
+    /// libindy didn't call back within the timeout of the operation.
+    TimedOut(Message),
+    /// The wallet already holds what was to be created.
+    AlreadyExists(Message),
+    /// The wallet reached the rate limit of the operation, the call can be retried later.
+    Busy(Message),
+    /// An error returned by libindy, passed on as is.
+    Indy(ErrorCode),
+}
+
+impl SovtokenError {
+    /**
+        The error of a REQNACK or REJECT reason of the ledger.
+    */
+    pub fn from_ledger_reason(reason: &str) -> SovtokenError {
+        let message = messages::LEDGER_REFUSED.with(&[reason.to_string()]);
+        if reason.contains(INSUFFICIENT_FUNDS_ERROR) {
+            SovtokenError::InsufficientFunds(message)
+        } else if reason.contains(EXTRA_FUNDS_ERROR) {
+            SovtokenError::ExtraFunds(message)
+        } else if reason.contains(INVALID_FUNDS) {
+            SovtokenError::SourceDoesNotExist(message)
+        } else {
+            SovtokenError::InvalidStructure(message)
+        }
+    }
+
+    /**
+        The [`ErrorCode`] the error is reported as.
+
+        [`ErrorCode`]: ../../../indy/enum.ErrorCode.html
+    */
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            &SovtokenError::InvalidStructure(_) => ErrorCode::CommonInvalidStructure,
+            &SovtokenError::InvalidState(_) => ErrorCode::CommonInvalidState,
+            &SovtokenError::InsufficientFunds(_) => ErrorCode::PaymentInsufficientFundsError,
+            &SovtokenError::ExtraFunds(_) => ErrorCode::PaymentExtraFundsError,
+            &SovtokenError::SourceDoesNotExist(_) => ErrorCode::PaymentSourceDoesNotExistError,
+            &SovtokenError::IncompatiblePaymentMethod(_) => ErrorCode::PaymentIncompatibleMethodsError,
+            &SovtokenError::OperationNotSupported(_) => ErrorCode::PaymentOperationNotSupportedError,
+            &SovtokenError::TimedOut(_) => ErrorCode::CommonIOError,
+            &SovtokenError::AlreadyExists(_) => ErrorCode::WalletItemAlreadyExists,
+            &SovtokenError::Busy(_) => ErrorCode::CommonInvalidState,
+            &SovtokenError::Indy(error_code) => error_code,
+        }
+    }
+
+    /**
+        The catalog message of the error.
+    */
+    pub fn message(&self) -> Message {
+        match self {
+            &SovtokenError::Indy(error_code) => messages::INDY_ERROR.with(&[format!("{:?}", error_code)]),
+            &SovtokenError::InvalidStructure(ref message) |
+            &SovtokenError::InvalidState(ref message) |
+            &SovtokenError::InsufficientFunds(ref message) |
+            &SovtokenError::ExtraFunds(ref message) |
+            &SovtokenError::SourceDoesNotExist(ref message) |
+            &SovtokenError::IncompatiblePaymentMethod(ref message) |
+            &SovtokenError::OperationNotSupported(ref message) |
+            &SovtokenError::TimedOut(ref message) |
+            &SovtokenError::AlreadyExists(ref message) |
+            &SovtokenError::Busy(ref message) => message.clone(),
+        }
+    }
+}
+
+impl fmt::Display for SovtokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &SovtokenError::Indy(error_code) => write!(f, "{} {:?}", self.description(), error_code),
+            &SovtokenError::InvalidStructure(ref message) |
+            &SovtokenError::InvalidState(ref message) |
+            &SovtokenError::InsufficientFunds(ref message) |
+            &SovtokenError::ExtraFunds(ref message) |
+            &SovtokenError::SourceDoesNotExist(ref message) |
+            &SovtokenError::IncompatiblePaymentMethod(ref message) |
+            &SovtokenError::OperationNotSupported(ref message) |
+            &SovtokenError::TimedOut(ref message) |
+            &SovtokenError::AlreadyExists(ref message) |
+            &SovtokenError::Busy(ref message) => write!(f, "{} {} ({})", self.description(), message.text, message.id),
+        }
+    }
+}
+
+impl Error for SovtokenError {
+    fn description(&self) -> &str {
+        match self {
+            &SovtokenError::InvalidStructure(_) => "Invalid structure.",
+            &SovtokenError::InvalidState(_) => "Invalid state.",
+            &SovtokenError::InsufficientFunds(_) => "Insufficient funds.",
+            &SovtokenError::ExtraFunds(_) => "Extra funds.",
+            &SovtokenError::SourceDoesNotExist(_) => "Source does not exist.",
+            &SovtokenError::IncompatiblePaymentMethod(_) => "Incompatible payment method.",
+            &SovtokenError::OperationNotSupported(_) => "Operation not supported.",
+            &SovtokenError::TimedOut(_) => "Timed out.",
+            &SovtokenError::AlreadyExists(_) => "Already exists.",
+            &SovtokenError::Busy(_) => "Busy.",
+            &SovtokenError::Indy(_) => "Indy error.",
+        }
+    }
+}
+
+impl From<SovtokenError> for ErrorCode {
+    fn from(error: SovtokenError) -> ErrorCode {
+        error!("{}", error);
+        let error_code = error.error_code();
+        messages::set_last_error(error_code, error.message());
+        error_code
+    }
+}
+
+impl From<ErrorCode> for SovtokenError {
+    fn from(error_code: ErrorCode) -> SovtokenError {
+        SovtokenError::Indy(error_code)
+    }
+}
+
+impl From<BalanceError> for SovtokenError {
+    fn from(error: BalanceError) -> SovtokenError {
+        match error {
+            BalanceError::PaymentInsufficientFunds { missing } =>
+                SovtokenError::InsufficientFunds(messages::PAYMENT_INSUFFICIENT_FUNDS.with(&[missing.to_string()])),
+            BalanceError::FeesInsufficientFunds { missing } =>
+                SovtokenError::InsufficientFunds(messages::FEES_INSUFFICIENT_FUNDS.with(&[missing.to_string()])),
+            BalanceError::ExtraFundsUnallocated { extra } =>
+                SovtokenError::ExtraFunds(messages::EXTRA_FUNDS_UNALLOCATED.with(&[extra.to_string()])),
+        }
+    }
+}
+
+impl From<TxoParseError> for SovtokenError {
+    fn from(error: TxoParseError) -> SovtokenError {
+        SovtokenError::InvalidStructure(messages::INVALID_TXO.with(&[error.to_string()]))
+    }
+}
+
+impl From<Base58Error> for SovtokenError {
+    fn from(error: Base58Error) -> SovtokenError {
+        SovtokenError::InvalidStructure(messages::BASE58_DECODING_FAILED.with(&[error.to_string()]))
+    }
+}
+
+impl From<SetFeesError> for SovtokenError {
+    fn from(error: SetFeesError) -> SovtokenError {
+        match error {
+            SetFeesError::Empty => SovtokenError::InvalidStructure(messages::SET_FEES_EMPTY.message()),
+            SetFeesError::KeyNotInteger(key) => SovtokenError::InvalidStructure(messages::SET_FEES_KEY_NOT_INTEGER.with(&[key])),
+        }
+    }
+}
+
+impl From<SeedError> for SovtokenError {
+    fn from(error: SeedError) -> SovtokenError {
+        SovtokenError::InvalidStructure(messages::INVALID_SEED.with(&[error.to_string()]))
+    }
+}
+
+impl From<DuplicateInputError> for SovtokenError {
+    fn from(error: DuplicateInputError) -> SovtokenError {
+        SovtokenError::InvalidStructure(messages::DUPLICATE_INPUT.with(&[error.address, error.seq_no.to_string()]))
+    }
+}
+
+impl From<ShareError> for SovtokenError {
+    fn from(error: ShareError) -> SovtokenError {
+        match error {
+            ShareError::InvalidThreshold(threshold, count) => SovtokenError::InvalidStructure(messages::SEED_SHARES_THRESHOLD.with(&[threshold.to_string(), count.to_string()])),
+            ShareError::InvalidShare(position, reason) => SovtokenError::InvalidStructure(messages::SEED_SHARE_INVALID.with(&[position.to_string(), reason])),
+            ShareError::Mismatch => SovtokenError::InvalidStructure(messages::SEED_SHARES_MISMATCH.message()),
+            ShareError::TooFew(given, threshold) => SovtokenError::InvalidStructure(messages::SEED_SHARES_TOO_FEW.with(&[given.to_string(), threshold.to_string()])),
+            ShareError::Corrupted => SovtokenError::InvalidStructure(messages::SEED_SHARES_CORRUPTED.message()),
+            ShareError::NoRandom => SovtokenError::InvalidState(messages::NO_SYSTEM_RANDOM.message()),
+        }
+    }
+}
+
+impl From<DidError> for SovtokenError {
+    fn from(error: DidError) -> SovtokenError {
+        match error {
+            DidError::InvalidLength(len) => SovtokenError::InvalidStructure(messages::DID_INVALID_LENGTH.with(&[len.to_string()])),
+            DidError::InvalidChar(c) => SovtokenError::InvalidStructure(messages::DID_INVALID_CHAR.with(&[c.to_string()])),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+
+    fn message() -> Message {
+        messages::LEDGER_REFUSED.with(&[String::new()])
+    }
+
+    #[test]
+    fn payment_errors_use_the_payment_range() {
+        let errors = vec![
+            (SovtokenError::InsufficientFunds(message()), ErrorCode::PaymentInsufficientFundsError),
+            (SovtokenError::ExtraFunds(message()), ErrorCode::PaymentExtraFundsError),
+            (SovtokenError::SourceDoesNotExist(message()), ErrorCode::PaymentSourceDoesNotExistError),
+            (SovtokenError::IncompatiblePaymentMethod(message()), ErrorCode::PaymentIncompatibleMethodsError),
+            (SovtokenError::OperationNotSupported(message()), ErrorCode::PaymentOperationNotSupportedError),
+        ];
+
+        for (error, error_code) in errors {
+            assert_eq!(error_code, ErrorCode::from(error));
+        }
+    }
+
+    #[test]
+    fn indy_errors_pass_through() {
+        assert_eq!(ErrorCode::WalletItemNotFound, ErrorCode::from(SovtokenError::from(ErrorCode::WalletItemNotFound)));
+    }
+
+    #[test]
+    fn ledger_reasons() {
+        assert_eq!(ErrorCode::PaymentInsufficientFundsError, SovtokenError::from_ledger_reason("client request invalid: InsufficientFundsError()").error_code());
+        assert_eq!(ErrorCode::PaymentExtraFundsError, SovtokenError::from_ledger_reason("client request invalid: ExtraFundsError()").error_code());
+        assert_eq!(ErrorCode::PaymentSourceDoesNotExistError, SovtokenError::from_ledger_reason("client request invalid: InvalidFundsError()").error_code());
+        assert_eq!(ErrorCode::CommonInvalidStructure, SovtokenError::from_ledger_reason("client request invalid").error_code());
+    }
+
+    #[test]
+    fn balance_errors() {
+        let error = SovtokenError::from(BalanceError::FeesInsufficientFunds { missing: 2 });
+        assert_eq!(ErrorCode::PaymentInsufficientFundsError, error.error_code());
+        assert!(error.to_string().contains("Missing 2 tokens for the fees."));
+        assert_eq!(messages::FEES_INSUFFICIENT_FUNDS.id, error.message().id);
+        assert_eq!(vec![String::from("2")], error.message().args);
+    }
+
+    #[test]
+    fn reported_error_is_the_last_error() {
+        let error_code = ErrorCode::from(SovtokenError::from(DidError::InvalidLength(3)));
+
+        let last = messages::last_error().unwrap();
+        assert_eq!(error_code as i32, last.code);
+        assert_eq!(messages::DID_INVALID_LENGTH.id, last.message.id);
+    }
+}
@@ -0,0 +1,269 @@
+/*!
+    Checks of the `extra` of payments, mints and fees.
+
+    The `extra` given to the handlers goes on the ledger as it is. It is
+    checked first:
+
+    * it has to be valid UTF-8,
+    * it can't have control characters, but for tabs and line breaks,
+    * it can't be longer than `max_extra_bytes` of the library config.
+
+    With `hash_oversized_extra` set in the library config, a longer extra
+    isn't rejected: the ledger gets `sha256:<hex of the sha256 of the extra>`
+    instead, and the extra is kept in the storage backend under its hash.
+    [`ExtraStore::full_extra`] gets it back from the extra of a receipt.
+
+    [`ExtraStore::full_extra`]: struct.ExtraStore.html#method.full_extra
+*/
+
+use std::ffi::CStr;
+
+use hex::ToHex;
+use indy::{IndyHandle, ErrorCode};
+use libc::c_char;
+use sha2::{Digest, Sha256};
+
+use logic::config::library_config::library_config;
+use logic::error::SovtokenError;
+use logic::messages;
+use logic::storage::StorageBackend;
+
+/**
+    The default of `max_extra_bytes`.
+*/
+pub const DEFAULT_MAX_EXTRA_BYTES: usize = 1024;
+
+/**
+    The record type the extras replaced by their hash are stored under.
+*/
+pub const EXTRA_RECORD_TYPE: &str = "sovtoken_extra";
+
+/**
+    Prefix of the extra put on the ledger in place of an oversized one.
+*/
+pub const EXTRA_HASH_PREFIX: &str = "sha256:";
+
+/**
+    An extra which passed the checks.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckedExtra {
+    /// Goes on the ledger as it is.
+    Plain(String),
+    /// Too long, `hash` goes on the ledger and `full` is stored.
+    Hashed { hash: String, full: String },
+}
+
+impl CheckedExtra {
+    /**
+        What goes on the ledger.
+    */
+    pub fn on_ledger(&self) -> &str {
+        match self {
+            &CheckedExtra::Plain(ref extra) => extra,
+            &CheckedExtra::Hashed { ref hash, .. } => hash,
+        }
+    }
+}
+
+/**
+    The extra at `extra`, `None` for a null pointer.
+
+    Fails with `CommonInvalidStructure` when the extra isn't UTF-8.
+*/
+pub fn extra_from_char_ptr(extra: *const c_char) -> Result<Option<String>, ErrorCode> {
+    if extra.is_null() {
+        return Ok(None);
+    }
+
+    let extra = unsafe { CStr::from_ptr(extra) };
+    match extra.to_str() {
+        Ok(extra) => Ok(Some(extra.to_string())),
+        Err(error) => {
+            let message = messages::EXTRA_NOT_UTF8.with(&[error.valid_up_to().to_string()]);
+            Err(ErrorCode::from(SovtokenError::InvalidStructure(message)))
+        }
+    }
+}
+
+/**
+    Check `extra` fits on the ledger, or replace it with its hash when
+    `hash_oversized` is set.
+
+    ```
+        use sovtoken::logic::extra::{check, CheckedExtra};
+
+        assert_eq!(Ok(CheckedExtra::Plain(String::from("invoice 12"))), check("invoice 12", 16, false));
+        assert!(check("invoice\u{0}12", 16, false).is_err());
+        assert!(check("invoice 12", 4, true).unwrap().on_ledger().starts_with("sha256:"));
+    ```
+*/
+pub fn check(extra: &str, max_bytes: usize, hash_oversized: bool) -> Result<CheckedExtra, SovtokenError> {
+    if let Some((index, c)) = extra.char_indices().find(|&(_, c)| c.is_control() && !is_line_formatting(c)) {
+        return Err(SovtokenError::InvalidStructure(
+            messages::EXTRA_CONTROL_CHARACTER.with(&[format!("{:?}", c), index.to_string()])
+        ));
+    }
+
+    if extra.len() <= max_bytes {
+        return Ok(CheckedExtra::Plain(extra.to_string()));
+    }
+
+    if !hash_oversized {
+        return Err(SovtokenError::InvalidStructure(
+            messages::EXTRA_TOO_LARGE.with(&[extra.len().to_string(), max_bytes.to_string()])
+        ));
+    }
+
+    let mut hasher = Sha256::default();
+    hasher.input(extra.as_bytes());
+    let hash = format!("{}{}", EXTRA_HASH_PREFIX, hasher.result().to_hex());
+    Ok(CheckedExtra::Hashed { hash, full: extra.to_string() })
+}
+
+/**
+    Keeps the extras replaced by their hash, in the storage backend of the library config.
+*/
+pub struct ExtraStore<T> where T: StorageBackend {
+    storage: T,
+}
+
+impl<T: StorageBackend> ExtraStore<T> {
+    pub fn new(storage: T) -> Self {
+        ExtraStore { storage }
+    }
+
+    /**
+        The extra to put on the ledger for `extra`, checked with the limits
+        of the library config.
+
+        An oversized extra replaced by its hash is stored first.
+    */
+    pub fn prepare(&self, wallet_handle: IndyHandle, extra: Option<String>) -> Result<Option<String>, ErrorCode> {
+        let extra = match extra {
+            Some(extra) => extra,
+            None => return Ok(None),
+        };
+
+        let config = library_config();
+        match check(&extra, config.max_extra_bytes, config.hash_oversized_extra)? {
+            CheckedExtra::Plain(extra) => Ok(Some(extra)),
+            CheckedExtra::Hashed { hash, full } => {
+                debug!("Storing the extra of {} bytes as {}", full.len(), hash);
+                match self.storage.add_record(wallet_handle, EXTRA_RECORD_TYPE, &hash, &full) {
+                    Ok(()) | Err(ErrorCode::WalletItemAlreadyExists) => Ok(Some(hash)),
+                    Err(ec) => Err(ec),
+                }
+            },
+        }
+    }
+
+    /**
+        The extra `prepare` would put on the ledger for `extra`, without
+        storing an oversized extra. For the dry runs of the builders.
+    */
+    pub fn preview(&self, extra: Option<String>) -> Result<Option<String>, ErrorCode> {
+        let config = library_config();
+        match extra {
+            Some(extra) => Ok(Some(check(&extra, config.max_extra_bytes, config.hash_oversized_extra)?.on_ledger().to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /**
+        The extra given for the `extra` of the ledger.
+
+        An extra which isn't a hash is returned as it is. Fails with
+        `WalletItemNotFound` for a hash whose extra isn't stored.
+    */
+    pub fn full_extra(&self, wallet_handle: IndyHandle, extra: &str) -> Result<String, ErrorCode> {
+        if extra.starts_with(EXTRA_HASH_PREFIX) {
+            self.storage.get_record(wallet_handle, EXTRA_RECORD_TYPE, extra)
+        } else {
+            Ok(extra.to_string())
+        }
+    }
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn is_line_formatting(c: char) -> bool {
+    c == '\t' || c == '\n' || c == '\r'
+}
+
+
+#[cfg(test)]
+mod extra_tests {
+    use std::ffi::CString;
+
+    use logic::storage::MemoryStorage;
+    use super::*;
+
+    #[test]
+    fn extra_with_line_breaks() {
+        assert_eq!(Ok(CheckedExtra::Plain(String::from("a\tb\r\nc"))), check("a\tb\r\nc", 16, false));
+    }
+
+    #[test]
+    fn extra_with_control_character() {
+        let error = check("a\u{1b}[31m", 16, true).unwrap_err();
+        assert_eq!(messages::EXTRA_CONTROL_CHARACTER.id, error.message().id);
+        assert_eq!("1", error.message().args[1]);
+    }
+
+    #[test]
+    fn oversized_extra() {
+        let error = check("12345", 4, false).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error.error_code());
+        assert_eq!(messages::EXTRA_TOO_LARGE.id, error.message().id);
+    }
+
+    #[test]
+    fn oversized_extra_hashed() {
+        let checked = check("12345", 4, true).unwrap();
+        assert_eq!(EXTRA_HASH_PREFIX.len() + 64, checked.on_ledger().len());
+        assert_eq!(checked, check("12345", 4, true).unwrap());
+    }
+
+    #[test]
+    fn extra_not_utf8() {
+        let extra = CString::new(vec![b'a', 0xff]).unwrap();
+        assert_eq!(ErrorCode::CommonInvalidStructure, extra_from_char_ptr(extra.as_ptr()).unwrap_err());
+        assert_eq!(Ok(None), extra_from_char_ptr(::std::ptr::null()));
+    }
+
+    #[test]
+    fn full_extra_of_a_hash() {
+        let store = ExtraStore::new(MemoryStorage::new("extra_tests"));
+        let hash = match check("12345", 4, true).unwrap() {
+            CheckedExtra::Hashed { hash, full } => {
+                store.storage.add_record(1, EXTRA_RECORD_TYPE, &hash, &full).unwrap();
+                hash
+            },
+            checked => panic!("{:?} isn't hashed", checked),
+        };
+
+        assert_eq!("12345", store.full_extra(1, &hash).unwrap());
+        assert_eq!("memo", store.full_extra(1, "memo").unwrap());
+        assert_eq!(ErrorCode::WalletItemNotFound, store.full_extra(2, &hash).unwrap_err());
+    }
+
+    #[test]
+    fn preview_stores_nothing() {
+        let store = ExtraStore::new(MemoryStorage::new("extra_tests"));
+        let extra = "x".repeat(library_config().max_extra_bytes + 1);
+
+        match store.preview(Some(extra.clone())) {
+            Ok(Some(ref hash)) if library_config().hash_oversized_extra => {
+                assert!(hash.starts_with(EXTRA_HASH_PREFIX));
+                assert_eq!(ErrorCode::WalletItemNotFound, store.full_extra(1, hash).unwrap_err());
+            },
+            Err(ec) => assert_eq!(ErrorCode::CommonInvalidStructure, ec),
+            previewed => panic!("{:?} for an oversized extra", previewed),
+        }
+        assert_eq!(Ok(Some("memo".to_string())), store.preview(Some("memo".to_string())));
+    }
+}
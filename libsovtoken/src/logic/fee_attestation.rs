@@ -0,0 +1,178 @@
+/*!
+    Attestations of the fee schedule in force, from GET_FEES replies.
+
+    An attestation records a fee schedule with the ledger state it was read
+    from: the root of the state trie, the root of the transactions and the
+    time the nodes signed them, and their BLS multi-signature. Stored next
+    to a payment, it proves which fees were in force when it was made, and
+    two attestations are of the same schedule when their `scheduleHash` is.
+
+    The schedule hash is the sha256, as hex, of the fees as json with the
+    txn types sorted and no whitespace, e.g. `{"1":4,"10001":8}`.
+
+    The root of the state proof has to be the state root the nodes signed,
+    a reply which mixes them up is rejected. Verifying the proof itself is
+    out of scope: it takes the nodes of the state trie hashed up to the
+    signed root and the BLS keys of the pool, which this library has no
+    crypto for. libindy verifies it when the reply is fetched through
+    libindy with the state proof parser of GET_FEES registered, and the
+    attestation keeps the multi-signature to be verified again against the
+    keys of the pool.
+
+    Attesting a schedule doesn't change the fees the library remembers,
+    `parse_get_txn_fees_response_handler` does.
+*/
+
+use std::collections::BTreeMap;
+
+use hex::ToHex;
+use indy::ErrorCode;
+use serde_json::{self, Value};
+use sha2::{Digest, Sha256};
+
+use logic::parsers::parse_get_txn_fees::{filter_fees, ParseGetTxnFeesResponse};
+use logic::type_aliases::{ReqId, TokenAmount};
+use utils::json_conversion::JsonDeserialize;
+
+/**
+    The fee schedule of a GET_FEES reply and the ledger state it was read from.
+
+    The ledger fields are `None` for a reply without a state proof.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeAttestation {
+    pub schedule_hash: String,
+    pub fees: BTreeMap<String, TokenAmount>,
+    pub req_id: ReqId,
+    pub ledger_root: Option<String>,
+    pub txn_root: Option<String>,
+    pub ledger_id: Option<u64>,
+    pub timestamp: Option<u64>,
+    pub multi_signature: Option<Value>,
+}
+
+/**
+    The hash of a fee schedule, see the module doc.
+*/
+pub fn schedule_hash(fees: &BTreeMap<String, TokenAmount>) -> String {
+    let json = serde_json::to_string(fees).unwrap_or_default();
+    let mut hasher = Sha256::default();
+    hasher.input(json.as_bytes());
+    hasher.result().to_hex()
+}
+
+/**
+    The attestation of the GET_FEES reply `response`.
+
+    Fails with `CommonInvalidStructure` when the reply isn't a GET_FEES
+    reply, or when the root of its state proof isn't the signed state root.
+*/
+pub fn attest(response: &str) -> Result<FeeAttestation, ErrorCode> {
+    let reply = ParseGetTxnFeesResponse::from_json(response)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+    let result = reply.result;
+
+    let fees = match result.txn_types {
+        Some(ref txn_types) => filter_fees(&result.fees, txn_types),
+        None => result.fees.clone(),
+    };
+    let fees: BTreeMap<String, TokenAmount> = fees.into_iter().collect();
+
+    let (root_hash, multi_signature) = match result.state_proof {
+        Some(state_proof) => (state_proof.root_hash, state_proof.multi_signature),
+        None => (None, None),
+    };
+    let signed = |field: &str| multi_signature.as_ref().and_then(|multi_signature| multi_signature["value"].get(field).cloned());
+
+    let signed_root = signed("state_root_hash").and_then(|root| root.as_str().map(String::from));
+    if let (Some(root_hash), Some(signed_root)) = (root_hash.as_ref(), signed_root.as_ref()) {
+        if root_hash != signed_root {
+            error!("The state proof of the GET_FEES reply is for the root {}, the nodes signed {}", root_hash, signed_root);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+    }
+
+    let attestation = FeeAttestation {
+        schedule_hash: schedule_hash(&fees),
+        fees,
+        req_id: result.req_id,
+        ledger_root: signed_root,
+        txn_root: signed("txn_root_hash").and_then(|root| root.as_str().map(String::from)),
+        ledger_id: signed("ledger_id").and_then(|id| id.as_u64()),
+        timestamp: signed("timestamp").and_then(|timestamp| timestamp.as_u64()),
+        multi_signature: multi_signature.clone(),
+    };
+    info!("Attested the fee schedule {} at ledger root {:?}", attestation.schedule_hash, attestation.ledger_root);
+    Ok(attestation)
+}
+
+
+#[cfg(test)]
+mod fee_attestation_tests {
+    use super::*;
+
+    fn reply(root_hash: &str) -> String {
+        json!({
+            "op": "REPLY",
+            "result": {
+                "identifier": "6ouriXMZkLeHsuXrN1X1fd",
+                "reqId": 83955,
+                "type": "20001",
+                "fees": {"1": 4, "10001": 8},
+                "state_proof": {
+                    "proof_nodes": "29qFIGZlZXOT0pF7IjEiOjQsIjEwMDAxIjo4fQ==",
+                    "root_hash": root_hash,
+                    "multi_signature": {
+                        "participants": ["Gamma", "Delta", "Beta"],
+                        "signature": "Qk67ePVhxdjHivAf8H4Loy1hN5zfb1dq79VSJKYx485EAXmj44PASpp8gj2faysdN8CNzSoUVvXgd3U4P2CA7VkwD7FHKUuviAFJfRQ68FnpUS8hVuqn6PAuv9RGUobohcJnKJ8CVKxr5i3Zn2JNXbk7AqeYRZQ2egq8fdoP3woPW7",
+                        "value": {
+                            "timestamp": 1530059419,
+                            "state_root_hash": "5BU5Rc3sRtTJB6tVprGiTSqiRaa9o6ei11MjH4Vu16ms",
+                            "ledger_id": 2,
+                            "txn_root_hash": "AKboMiJZJm247Sa7GsKQo5Ba8ukgxTQ3DsLc2pyVuDkU",
+                            "pool_state_root_hash": "J3ATG63R2JKHDCdpKpQf81FTNyQg2Vgz7Pu1ZHZw6zNy"
+                        }
+                    }
+                }
+            }
+        }).to_string()
+    }
+
+    const ROOT_HASH: &str = "5BU5Rc3sRtTJB6tVprGiTSqiRaa9o6ei11MjH4Vu16ms";
+
+    #[test]
+    fn attestation_of_a_signed_schedule() {
+        let attestation = attest(&reply(ROOT_HASH)).unwrap();
+
+        assert_eq!(Some(ROOT_HASH.to_string()), attestation.ledger_root);
+        assert_eq!(Some(1530059419), attestation.timestamp);
+        assert_eq!(Some(2), attestation.ledger_id);
+        assert!(attestation.multi_signature.is_some());
+        assert_eq!(schedule_hash(&attestation.fees), attestation.schedule_hash);
+        assert_eq!(r#"{"1":4,"10001":8}"#, serde_json::to_string(&attestation.fees).unwrap());
+    }
+
+    #[test]
+    fn attestation_of_a_mismatched_root() {
+        let root_hash = "AKboMiJZJm247Sa7GsKQo5Ba8ukgxTQ3DsLc2pyVuDkU";
+        assert_eq!(ErrorCode::CommonInvalidStructure, attest(&reply(root_hash)).unwrap_err());
+    }
+
+    #[test]
+    fn attestation_without_a_state_proof() {
+        let mut response: Value = serde_json::from_str(&reply(ROOT_HASH)).unwrap();
+        response["result"].as_object_mut().unwrap().remove("state_proof");
+        let attestation = attest(&response.to_string()).unwrap();
+
+        assert_eq!(None, attestation.ledger_root);
+        assert_eq!(None, attestation.multi_signature);
+        assert_eq!(r#"{"1":4,"10001":8}"#, serde_json::to_string(&attestation.fees).unwrap());
+    }
+
+    #[test]
+    fn attestation_of_other_reply() {
+        assert_eq!(ErrorCode::CommonInvalidStructure, attest(r#"{"op":"REPLY","result":{"reqId":1}}"#).unwrap_err());
+    }
+}
@@ -0,0 +1,433 @@
+/*!
+    Requests whose fees are waived by the fee schedule.
+
+    A fee schedule can set the fee of a txn type to 0. The request given to
+    `add_request_fees_handler` doesn't say what its fee is, so the schedules
+    of the GET_FEES replies parsed by `parse_get_txn_fees_response_handler`
+    are remembered, and a request of a type they set to 0 is returned
+    without fees.
+
+    A type the schedules don't list isn't waived: the reply to a GET_FEES
+    for some types only says nothing about the others.
+
+    An agent distributing the schedule itself preloads it with
+    `set_fee_schedule_handler` instead of asking the ledger. A preloaded fee
+    is used until a GET_FEES reply lists its type: the ledger fee then
+    replaces it, and when they differ the mismatch is logged and kept, the
+    last [`MAX_FEE_MISMATCHES`] of them.
+
+    Every network has a schedule of its own, see `logic::pools`. The
+    replies libindy hands to `parse_get_txn_fees_response_handler` come
+    without a pool, their fees go to a schedule shared by the networks: the
+    fee of a network is the one of its own schedule, else of the shared one.
+
+    The fees, and which of them are preloaded, are also written to the
+    storage backend of the library config as records of type
+    [`FEE_SCHEDULE_RECORD_TYPE`], one by network, and a schedule is read
+    back the first time it is used after a restart. They are kept with the
+    cache wallet, or as records of no wallet, see `logic::storage`. The
+    mismatches are not persisted.
+
+    [`FEE_SCHEDULE_RECORD_TYPE`]: constant.FEE_SCHEDULE_RECORD_TYPE.html
+    [`MAX_FEE_MISMATCHES`]: constant.MAX_FEE_MISMATCHES.html
+*/
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use indy::{IndyHandle, ErrorCode};
+use serde_json;
+
+use logic::config::set_fees_config::SetFeesMap;
+use logic::state::state;
+use logic::storage::{cache_storage, StorageBackend};
+use logic::type_aliases::TokenAmount;
+
+/**
+    The most mismatches between preloaded and ledger fees kept.
+*/
+pub const MAX_FEE_MISMATCHES: usize = 32;
+
+/**
+    The record type of the persisted fee schedules, one record by network.
+*/
+pub const FEE_SCHEDULE_RECORD_TYPE: &str = "sovtoken_fee_schedule";
+
+/**
+    Where a fee of the schedule comes from.
+*/
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeeSource {
+    /// A parsed GET_FEES reply.
+    Ledger,
+    /// `set_fee_schedule_handler`, not confirmed by the ledger yet.
+    Preloaded,
+}
+
+/**
+    A preloaded fee a GET_FEES reply disagreed with.
+*/
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeMismatch {
+    pub txn_type: String,
+    pub preloaded: TokenAmount,
+    pub ledger: TokenAmount,
+}
+
+/**
+    The fees known, by txn type, and the mismatches seen.
+*/
+#[derive(Debug, Default)]
+pub struct FeeSchedule {
+    fees: HashMap<String, (TokenAmount, FeeSource)>,
+    mismatches: VecDeque<FeeMismatch>,
+    loaded: bool,
+}
+
+impl FeeSchedule {
+    pub fn new() -> Self {
+        FeeSchedule::default()
+    }
+
+    /**
+        The number of txn types with a known fee.
+    */
+    pub fn len(&self) -> usize {
+        self.fees.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fees.is_empty()
+    }
+
+    fn remember(&mut self, fees: &SetFeesMap) {
+        for (txn_type, fee) in fees {
+            let previous = self.fees.insert(txn_type.clone(), (*fee, FeeSource::Ledger));
+            if let Some((preloaded, FeeSource::Preloaded)) = previous {
+                if preloaded != *fee {
+                    warn!("The preloaded fee {} of txn type {} differs from the ledger fee {}", preloaded, txn_type, fee);
+                    if self.mismatches.len() == MAX_FEE_MISMATCHES {
+                        self.mismatches.pop_front();
+                    }
+                    self.mismatches.push_back(FeeMismatch { txn_type: txn_type.clone(), preloaded, ledger: *fee });
+                }
+            }
+        }
+    }
+
+    fn preload(&mut self, fees: &SetFeesMap) {
+        for (txn_type, fee) in fees {
+            self.fees.insert(txn_type.clone(), (*fee, FeeSource::Preloaded));
+        }
+    }
+
+    // the persisted fees don't replace the ones known since the start
+    fn restore(&mut self, persisted: Option<PersistedSchedule>) {
+        if self.loaded {
+            return;
+        }
+        self.loaded = true;
+
+        if let Some(persisted) = persisted {
+            for (txn_type, fee) in persisted.fees {
+                let source = if persisted.preloaded.contains(&txn_type) { FeeSource::Preloaded } else { FeeSource::Ledger };
+                self.fees.entry(txn_type).or_insert((fee, source));
+            }
+        }
+    }
+
+    fn persisted(&self) -> PersistedSchedule {
+        let snapshot = self.snapshot();
+        PersistedSchedule { fees: snapshot.fees, preloaded: snapshot.preloaded }
+    }
+
+    fn fee(&self, txn_type: &str) -> Option<TokenAmount> {
+        self.fees.get(txn_type).map(|&(fee, _)| fee)
+    }
+
+    fn snapshot(&self) -> ScheduleSnapshot {
+        let mut preloaded: Vec<String> = self.fees.iter()
+            .filter(|&(_, &(_, source))| source == FeeSource::Preloaded)
+            .map(|(txn_type, _)| txn_type.clone())
+            .collect();
+        preloaded.sort();
+
+        ScheduleSnapshot {
+            fees: self.fees.iter().map(|(txn_type, &(fee, _))| (txn_type.clone(), fee)).collect(),
+            preloaded,
+            mismatches: self.mismatches.iter().cloned().collect(),
+        }
+    }
+}
+
+/**
+    The schedule as returned by `set_fee_schedule_handler`.
+
+    ```text
+    {
+        "fees": {"<txn type>": <int>, ...},
+        "preloaded": ["<txn type>", ...], // the fees no GET_FEES reply confirmed yet
+        "mismatches": [{"txnType": <str>, "preloaded": <int>, "ledger": <int>}, ...]
+    }
+    ```
+*/
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleSnapshot {
+    pub fees: BTreeMap<String, TokenAmount>,
+    pub preloaded: Vec<String>,
+    pub mismatches: Vec<FeeMismatch>,
+}
+
+/**
+    Remember the fees of a parsed GET_FEES reply, replacing the fees known
+    for the same types, in the schedule of the network of `pool_handle`, or
+    the shared one.
+
+    A preloaded fee the ledger disagrees with is logged and kept as a
+    mismatch.
+*/
+pub fn remember_schedule(pool_handle: Option<IndyHandle>, fees: &SetFeesMap) {
+    let persisted = with_schedule(pool_handle, |schedule| {
+        schedule.remember(fees);
+        schedule.persisted()
+    });
+    persist_schedule(pool_handle, &persisted);
+}
+
+/**
+    Preload the fees of a schedule distributed without the ledger, in the
+    schedule of the network of `pool_handle`, or the shared one.
+
+    The fees replace the fees known for the same types, at once: a request
+    never sees half of the schedule.
+*/
+pub fn preload_schedule(pool_handle: Option<IndyHandle>, fees: &SetFeesMap) {
+    let persisted = with_schedule(pool_handle, |schedule| {
+        schedule.preload(fees);
+        schedule.persisted()
+    });
+    persist_schedule(pool_handle, &persisted);
+    info!("Preloaded the fees of {} txn types", fees.len());
+}
+
+/**
+    The last fee parsed or preloaded for `txn_type` on the network of
+    `pool_handle`, else in the shared schedule. `None` when no schedule
+    listed it.
+*/
+pub fn known_fee(pool_handle: Option<IndyHandle>, txn_type: &str) -> Option<TokenAmount> {
+    pool_handle
+        .and_then(|pool_handle| with_schedule(Some(pool_handle), |schedule| schedule.fee(txn_type)))
+        .or_else(|| with_schedule(None, |schedule| schedule.fee(txn_type)))
+}
+
+/**
+    Whether a parsed schedule set the fee of `txn_type` to 0, see [`known_fee`].
+
+    [`known_fee`]: fn.known_fee.html
+*/
+pub fn is_waived(pool_handle: Option<IndyHandle>, txn_type: &str) -> bool {
+    known_fee(pool_handle, txn_type) == Some(0)
+}
+
+/**
+    The fees known now in the schedule of the network of `pool_handle`, or
+    the shared one, with the preloaded types and the mismatches.
+*/
+pub fn schedule(pool_handle: Option<IndyHandle>) -> ScheduleSnapshot {
+    with_schedule(pool_handle, |schedule| schedule.snapshot())
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// the record id of the schedule shared by the networks
+const SHARED_SCHEDULE: &str = "shared";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct PersistedSchedule {
+    fees: BTreeMap<String, TokenAmount>,
+    preloaded: Vec<String>,
+}
+
+// Run `f` with the schedule of the network of `pool_handle`, the shared one for
+// `None`, read from the storage first when it wasn't yet. The storage is read
+// outside of the lock.
+fn with_schedule<F, R>(pool_handle: Option<IndyHandle>, f: F) -> R where F: FnOnce(&mut FeeSchedule) -> R {
+    if !locked_schedule(pool_handle, |schedule| schedule.loaded) {
+        if let Some((storage, wallet_handle)) = cache_storage() {
+            let persisted = load(&storage, wallet_handle, &record_id(pool_handle));
+            locked_schedule(pool_handle, |schedule| schedule.restore(persisted));
+        }
+    }
+    locked_schedule(pool_handle, f)
+}
+
+fn locked_schedule<F, R>(pool_handle: Option<IndyHandle>, f: F) -> R where F: FnOnce(&mut FeeSchedule) -> R {
+    let state = state();
+    match pool_handle {
+        Some(pool_handle) => f(&mut state.pools.lock().network(pool_handle).fee_schedule),
+        None => f(&mut state.fee_schedule.lock()),
+    }
+}
+
+// the persisted schedules are kept apart by network
+fn record_id(pool_handle: Option<IndyHandle>) -> String {
+    match pool_handle {
+        Some(pool_handle) => state().pools.lock().network_id(pool_handle),
+        None => SHARED_SCHEDULE.to_string(),
+    }
+}
+
+fn persist_schedule(pool_handle: Option<IndyHandle>, persisted: &PersistedSchedule) {
+    if let Some((storage, wallet_handle)) = cache_storage() {
+        persist(&storage, wallet_handle, &record_id(pool_handle), persisted);
+    }
+}
+
+// the schedule persisted as `record_id`, a record which can't be read is logged
+fn load<S: StorageBackend>(storage: &S, wallet_handle: IndyHandle, record_id: &str) -> Option<PersistedSchedule> {
+    let value = match storage.get_record(wallet_handle, FEE_SCHEDULE_RECORD_TYPE, record_id) {
+        Ok(value) => value,
+        Err(ErrorCode::WalletItemNotFound) => return None,
+        Err(error) => {
+            warn!("Failed to read the fee schedule {}: {:?}", record_id, error);
+            return None;
+        },
+    };
+    serde_json::from_str(&value)
+        .map_err(|error| warn!("Failed to read the fee schedule {}: {}", record_id, error))
+        .ok()
+}
+
+// a failure to persist only costs a GET_FEES after a restart, it is logged
+fn persist<S: StorageBackend>(storage: &S, wallet_handle: IndyHandle, record_id: &str, persisted: &PersistedSchedule) {
+    let value = match serde_json::to_string(persisted) {
+        Ok(value) => value,
+        Err(error) => {
+            warn!("Failed to serialize the fee schedule {}: {}", record_id, error);
+            return;
+        },
+    };
+
+    let stored = match storage.add_record(wallet_handle, FEE_SCHEDULE_RECORD_TYPE, record_id, &value) {
+        Err(ErrorCode::WalletItemAlreadyExists) => storage.update_record(wallet_handle, FEE_SCHEDULE_RECORD_TYPE, record_id, &value),
+        stored => stored,
+    };
+    if let Err(error) = stored {
+        warn!("Failed to persist the fee schedule {}: {:?}", record_id, error);
+    }
+}
+
+
+#[cfg(test)]
+mod fee_waiver_tests {
+    use std::collections::HashMap;
+
+    use logic::config::library_config::LibraryConfig;
+    use logic::storage::{FileStorage, NO_WALLET};
+    use utils::test::context::in_own_context;
+
+    use super::*;
+
+    // every test has a schedule of its own
+    #[test]
+    fn zero_fee_is_waived() {
+        in_own_context(LibraryConfig::default(), || {
+            let mut fees: SetFeesMap = HashMap::new();
+            fees.insert(String::from("91001"), 0);
+            fees.insert(String::from("91002"), 5);
+            remember_schedule(None, &fees);
+
+            assert!(is_waived(None, "91001"));
+            assert!(!is_waived(None, "91002"));
+            assert!(!is_waived(None, "91003"));
+        });
+    }
+
+    #[test]
+    fn later_schedule_replaces_the_fee() {
+        in_own_context(LibraryConfig::default(), || {
+            let mut fees: SetFeesMap = HashMap::new();
+            fees.insert(String::from("92001"), 0);
+            remember_schedule(None, &fees);
+            fees.insert(String::from("92001"), 2);
+            remember_schedule(None, &fees);
+
+            assert_eq!(Some(2), known_fee(None, "92001"));
+            assert!(!is_waived(None, "92001"));
+        });
+    }
+
+    #[test]
+    fn ledger_fee_replaces_preloaded_fee() {
+        in_own_context(LibraryConfig::default(), || {
+            let mut fees: SetFeesMap = HashMap::new();
+            fees.insert(String::from("93001"), 0);
+            fees.insert(String::from("93002"), 4);
+            preload_schedule(None, &fees);
+            assert!(is_waived(None, "93001"));
+            assert_eq!(vec![String::from("93002")], schedule(None).preloaded);
+
+            let mut ledger_fees: SetFeesMap = HashMap::new();
+            ledger_fees.insert(String::from("93001"), 3);
+            ledger_fees.insert(String::from("93002"), 4);
+            remember_schedule(None, &ledger_fees);
+
+            assert_eq!(Some(3), known_fee(None, "93001"));
+            let snapshot = schedule(None);
+            assert!(snapshot.preloaded.is_empty());
+            assert_eq!(vec![FeeMismatch { txn_type: String::from("93001"), preloaded: 0, ledger: 3 }], snapshot.mismatches);
+        });
+    }
+
+    #[test]
+    fn schedule_of_a_network() {
+        in_own_context(LibraryConfig::default(), || {
+            let mut shared: SetFeesMap = HashMap::new();
+            shared.insert(String::from("94001"), 2);
+            shared.insert(String::from("94002"), 0);
+            remember_schedule(None, &shared);
+
+            let mut fees: SetFeesMap = HashMap::new();
+            fees.insert(String::from("94001"), 5);
+            remember_schedule(Some(1), &fees);
+
+            assert_eq!(Some(5), known_fee(Some(1), "94001"));
+            assert_eq!(Some(2), known_fee(Some(2), "94001"));
+            assert_eq!(Some(2), known_fee(None, "94001"));
+            assert!(is_waived(Some(1), "94002"));
+            assert_eq!(1, schedule(Some(1)).fees.len());
+        });
+    }
+
+    #[test]
+    fn persisted_schedule_is_restored() {
+        let dir = ::std::env::temp_dir().join(format!("sovtoken_fee_waiver_{}", ::utils::random::rand_string(8)));
+        let storage = FileStorage::new(dir.clone());
+        let mut fees: SetFeesMap = HashMap::new();
+        fees.insert(String::from("95001"), 0);
+        fees.insert(String::from("95002"), 3);
+        let mut schedule = FeeSchedule::new();
+        schedule.remember(&fees);
+        let mut preloaded: SetFeesMap = HashMap::new();
+        preloaded.insert(String::from("95003"), 7);
+        schedule.preload(&preloaded);
+        persist(&storage, NO_WALLET, SHARED_SCHEDULE, &schedule.persisted());
+
+        let mut restored = FeeSchedule::new();
+        let mut known: SetFeesMap = HashMap::new();
+        known.insert(String::from("95002"), 4);
+        restored.remember(&known);
+        restored.restore(load(&storage, NO_WALLET, SHARED_SCHEDULE));
+
+        assert_eq!(Some(0), restored.fee("95001"));
+        assert_eq!(Some(4), restored.fee("95002"));
+        assert_eq!(vec![String::from("95003")], restored.snapshot().preloaded);
+        assert_eq!(None, load(&storage, NO_WALLET, "other network"));
+        let _ = ::std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,102 @@
+/*!
+    The names of the address fields of inputs and outputs.
+
+    Older libindy versions call the address of an output and of an input
+    `address`; newer ones call them `recipient` and `source`. Both are
+    accepted when deserializing. Which names are emitted is set with
+    `field_names` in the library config, and can be overridden for a single
+    call with [`with_field_names`].
+
+    The ledger only knows `address`, so requests, their fees and the
+    messages signed for them always use [`FieldNames::Address`], through
+    [`with_ledger_format`].
+
+    [`with_field_names`]: fn.with_field_names.html
+    [`FieldNames::Address`]: enum.FieldNames.html
+    [`with_ledger_format`]: ../request/fn.with_ledger_format.html
+*/
+
+use std::cell::Cell;
+
+use logic::config::library_config::library_config;
+
+/**
+    The field names emitted for the address of inputs and outputs.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldNames {
+    /// `address` for both inputs and outputs.
+    Address,
+    /// `recipient` for outputs, `source` for inputs.
+    Recipient,
+}
+
+impl Default for FieldNames {
+    fn default() -> Self {
+        FieldNames::Address
+    }
+}
+
+impl FieldNames {
+    pub fn output_address(&self) -> &'static str {
+        match *self {
+            FieldNames::Address => "address",
+            FieldNames::Recipient => "recipient",
+        }
+    }
+
+    pub fn input_address(&self) -> &'static str {
+        match *self {
+            FieldNames::Address => "address",
+            FieldNames::Recipient => "source",
+        }
+    }
+}
+
+thread_local! {
+    static OVERRIDE: Cell<Option<FieldNames>> = Cell::new(None);
+}
+
+/**
+    The field names to emit on this thread right now.
+*/
+pub fn current() -> FieldNames {
+    OVERRIDE.with(|names| names.get())
+        .unwrap_or_else(|| library_config().field_names)
+}
+
+/**
+    Run `f` emitting `names`, whatever the library config says.
+*/
+pub fn with_field_names<F, R>(names: FieldNames, f: F) -> R where F: FnOnce() -> R {
+    let previous = OVERRIDE.with(|current| current.replace(Some(names)));
+    let result = f();
+    OVERRIDE.with(|current| current.set(previous));
+    result
+}
+
+
+#[cfg(test)]
+mod field_names_tests {
+    use super::*;
+
+    #[test]
+    fn override_is_restored() {
+        let outer = current();
+        let inner = with_field_names(FieldNames::Recipient, || {
+            with_field_names(FieldNames::Address, || assert_eq!(FieldNames::Address, current()));
+            current()
+        });
+
+        assert_eq!(FieldNames::Recipient, inner);
+        assert_eq!(outer, current());
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!("recipient", FieldNames::Recipient.output_address());
+        assert_eq!("source", FieldNames::Recipient.input_address());
+        assert_eq!("address", FieldNames::Address.input_address());
+    }
+}
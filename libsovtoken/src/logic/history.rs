@@ -0,0 +1,179 @@
+/*!
+    Export of the transaction history of a payment address.
+
+    libsovtoken doesn't query the history from the ledger itself, the caller
+    passes the parsed history in as [`HistoryEntry`]s. The exporter filters it
+    by address and time range and formats it as accountant-friendly csv.
+
+    [`HistoryEntry`]: ./struct.HistoryEntry.html
+*/
+
+use indy::ErrorCode;
+
+use logic::type_aliases::{TokenAmount, TxnSeqNo};
+use utils::csv::csv_row;
+
+const CSV_HEADER: &'static str = "date,counterparty,amount_in,amount_out,fees,seq_no";
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/**
+    A single transaction in the history of `address`.
+
+    `txn_time` is the ledger time of the transaction in seconds since the unix epoch.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub address: String,
+    pub counterparty: String,
+    #[serde(default)]
+    pub amount_in: TokenAmount,
+    #[serde(default)]
+    pub amount_out: TokenAmount,
+    #[serde(default)]
+    pub fees: TokenAmount,
+    pub seq_no: TxnSeqNo,
+    pub txn_time: u64,
+}
+
+/**
+    Export the entries of `address` with a `txn_time` between `from` and `to`,
+    both inclusive, as csv. A `to` of 0 means no upper bound.
+
+    ```
+        use sovtoken::logic::history::{HistoryEntry, export_history_csv};
+
+        let entry = HistoryEntry {
+            address: String::from("pay:sov:1"),
+            counterparty: String::from("pay:sov:2"),
+            amount_in: 0,
+            amount_out: 10,
+            fees: 1,
+            seq_no: 4,
+            txn_time: 1_500_000_000,
+        };
+
+        let csv = export_history_csv(&vec![entry], "pay:sov:1", 0, 0).unwrap();
+        assert_eq!("date,counterparty,amount_in,amount_out,fees,seq_no\n2017-07-14,pay:sov:2,0,10,1,4\n", csv);
+    ```
+*/
+pub fn export_history_csv(history: &Vec<HistoryEntry>, address: &str, from: u64, to: u64) -> Result<String, ErrorCode> {
+    trace!("logic::history::export_history_csv >> address: {:?}, from: {:?}, to: {:?}", address, from, to);
+
+    if to != 0 && to < from {
+        error!("History range ends before it starts: {} > {}", from, to);
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let mut entries: Vec<&HistoryEntry> = history.iter()
+        .filter(|entry| entry.address == address)
+        .filter(|entry| entry.txn_time >= from && (to == 0 || entry.txn_time <= to))
+        .collect();
+    entries.sort_by_key(|entry| (entry.txn_time, entry.seq_no));
+
+    let mut csv = String::from(CSV_HEADER);
+    csv.push('\n');
+    for entry in entries {
+        csv.push_str(&csv_row(&[
+            &date_from_timestamp(entry.txn_time),
+            &entry.counterparty,
+            &entry.amount_in.to_string(),
+            &entry.amount_out.to_string(),
+            &entry.fees.to_string(),
+            &entry.seq_no.to_string(),
+        ]));
+    }
+
+    trace!("logic::history::export_history_csv << csv: {:?}", csv);
+    Ok(csv)
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// Formats a unix timestamp as a UTC YYYY-MM-DD date.
+// Converts the days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+fn date_from_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / SECONDS_PER_DAY) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+
+#[cfg(test)]
+mod history_tests {
+    use super::*;
+
+    static ADDRESS: &'static str = "pay:sov:iTQzpdRdugkJ2gLD5vW5c159dncSL9jbAtu3WfPcb8qWD9bUd";
+    static COUNTERPARTY: &'static str = "pay:sov:2FKYJkgXRZtjhFpTMHhuyfc17BHZWcFPyF2MWy2SZMBaSo64fb";
+
+    fn entry(address: &str, seq_no: TxnSeqNo, txn_time: u64) -> HistoryEntry {
+        HistoryEntry {
+            address: address.to_string(),
+            counterparty: COUNTERPARTY.to_string(),
+            amount_in: 10,
+            amount_out: 0,
+            fees: 0,
+            seq_no,
+            txn_time,
+        }
+    }
+
+    fn rows(csv: &str) -> Vec<&str> {
+        csv.lines().skip(1).collect()
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!("1970-01-01", date_from_timestamp(0));
+        assert_eq!("2000-02-29", date_from_timestamp(951_782_400));
+        assert_eq!("2018-12-31", date_from_timestamp(1_546_300_799));
+    }
+
+    #[test]
+    fn export_filters_address() {
+        let history = vec![entry(ADDRESS, 1, 100), entry(COUNTERPARTY, 2, 100)];
+        let csv = export_history_csv(&history, ADDRESS, 0, 0).unwrap();
+        assert_eq!(vec![format!("1970-01-01,{},10,0,0,1", COUNTERPARTY)], rows(&csv));
+    }
+
+    #[test]
+    fn export_filters_range() {
+        let history = vec![entry(ADDRESS, 3, 300), entry(ADDRESS, 1, 100), entry(ADDRESS, 2, 200)];
+        let csv = export_history_csv(&history, ADDRESS, 150, 300).unwrap();
+        let seq_nos: Vec<&str> = rows(&csv).iter().map(|row| row.rsplit(',').next().unwrap()).collect();
+        assert_eq!(vec!["2", "3"], seq_nos);
+    }
+
+    #[test]
+    fn export_invalid_range() {
+        let error = export_history_csv(&vec![], ADDRESS, 200, 100).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error);
+    }
+
+    #[test]
+    fn deserialize_entry() {
+        let json = json!({
+            "address": ADDRESS,
+            "counterparty": COUNTERPARTY,
+            "amountOut": 5,
+            "seqNo": 7,
+            "txnTime": 100
+        });
+        let entry: HistoryEntry = ::serde_json::from_value(json).unwrap();
+        assert_eq!(5, entry.amount_out);
+        assert_eq!(0, entry.amount_in);
+    }
+}
@@ -0,0 +1,315 @@
+/*!
+    Proofs that the transaction of a receipt is on the ledger.
+
+    The reply to a write has the audit path of its transaction in the merkle
+    tree of the ledger, and the root of the tree when the transaction was
+    added, the tree of `seqNo` transactions. With `verbose_receipts` set in
+    the library config, `parse_payment_response_handler` keeps them in the
+    `proof` of the receipts, together with the transaction:
+
+    ```text
+    "proof": {
+        "rootHash": <string>,       // base58 root of the tree of ledgerSize transactions
+        "auditPath": [<string>],    // base58 hashes from the leaf of the transaction up to the root
+        "ledgerSize": <int>,        // the seqNo of the transaction
+        "txn": <json>               // the transaction as stored on the ledger
+    }
+    ```
+
+    [`verify_inclusion`] checks a receipt against a root hash known from
+    another source, e.g. a node or an auditor, without trusting the reply.
+    The leaf of a transaction is its MessagePack with the keys sorted, the
+    tree is the RFC 6962 tree of the ledger.
+
+    [`verify_inclusion`]: fn.verify_inclusion.html
+*/
+
+use indy::ErrorCode;
+use rmp_serde;
+use serde_json::{self, Value};
+
+use logic::address;
+use logic::hash::Hash;
+use logic::receipt::Receipt;
+use logic::type_aliases::TxnSeqNo;
+use utils::base58::{self, Checksum};
+
+/**
+    The audit path of a transaction, kept in a receipt.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InclusionProof {
+    pub root_hash: String,
+    pub audit_path: Vec<String>,
+    pub ledger_size: u64,
+    pub txn: Value,
+}
+
+impl InclusionProof {
+    /**
+        The proof of the transaction of the REPLY `resp_json` to a write.
+
+        Fails with `CommonInvalidStructure` when the reply has no audit path.
+    */
+    pub fn from_reply(resp_json: &str) -> Result<InclusionProof, ErrorCode> {
+        let reply: Value = serde_json::from_str(resp_json)
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonInvalidStructure))?;
+        let mut txn = match reply.get("result") {
+            Some(&Value::Object(ref result)) => result.clone(),
+            _ => return Err(ErrorCode::CommonInvalidStructure),
+        };
+
+        let root_hash = txn.remove("rootHash");
+        let audit_path = txn.remove("auditPath");
+        let ledger_size = txn.get("txnMetadata").and_then(|metadata| metadata["seqNo"].as_u64());
+
+        match (root_hash, audit_path, ledger_size) {
+            (Some(Value::String(root_hash)), Some(audit_path), Some(ledger_size)) => {
+                let audit_path = serde_json::from_value(audit_path)
+                    .map_err(map_err_err!())
+                    .or(Err(ErrorCode::CommonInvalidStructure))?;
+                Ok(InclusionProof { root_hash, audit_path, ledger_size, txn: Value::Object(txn) })
+            },
+            _ => {
+                error!("The reply has no rootHash, auditPath or seqNo to prove the transaction with");
+                Err(ErrorCode::CommonInvalidStructure)
+            },
+        }
+    }
+
+    /**
+        The hash of the leaf of the transaction.
+    */
+    pub fn leaf_hash(&self) -> Result<Vec<u8>, ErrorCode> {
+        let leaf = rmp_serde::to_vec(&self.txn)
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonInvalidState))?;
+        Ok(Hash::hash_leaf(&leaf)?.to_vec())
+    }
+}
+
+/**
+    Keep the proof of the transaction of `resp_json` in each of the receipts.
+*/
+pub fn add_to_receipts(receipts: &mut [Receipt], resp_json: &str) -> Result<(), ErrorCode> {
+    if receipts.is_empty() {
+        return Ok(());
+    }
+
+    let proof = InclusionProof::from_reply(resp_json)?;
+    for receipt in receipts.iter_mut() {
+        receipt.proof = Some(proof.clone());
+    }
+    Ok(())
+}
+
+/**
+    Whether the transaction of `receipt` is in the ledger of the base58 root `root_hash`.
+
+    The transaction in the proof has to have the output of the receipt, and
+    its leaf and audit path have to lead to `root_hash`. `root_hash` is the
+    root of the ledger of `ledgerSize` transactions.
+
+    Fails with `CommonInvalidStructure` when the receipt has no proof or the
+    hashes aren't base58.
+*/
+pub fn verify_inclusion(receipt: &Receipt, root_hash: &str) -> Result<bool, ErrorCode> {
+    let proof = match receipt.proof {
+        Some(ref proof) => proof,
+        None => {
+            error!("The receipt {} has no inclusion proof, parse it with verbose_receipts", receipt.receipt);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+    };
+
+    let root_hash = base58::decode(root_hash, Checksum::None)?;
+    let audit_path = proof.audit_path.iter()
+        .map(|hash| base58::decode(hash, Checksum::None))
+        .collect::<Result<Vec<Vec<u8>>, _>>()?;
+
+    if !has_output(&proof.txn, receipt) {
+        return Ok(false);
+    }
+
+    let leaf_index = receipt.seq_no.checked_sub(1).ok_or(ErrorCode::CommonInvalidStructure)?;
+    match root_from_audit_path(leaf_index, proof.ledger_size, proof.leaf_hash()?, &audit_path)? {
+        Some(root) => Ok(base58::constant_time_eq(&root, &root_hash)),
+        None => Ok(false),
+    }
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// Whether the transaction is the one of the receipt and has its output.
+fn has_output(txn: &Value, receipt: &Receipt) -> bool {
+    let seq_no = txn["txnMetadata"]["seqNo"].as_u64();
+    let recipient = address::unqualified_part(&receipt.recipient);
+    let outputs = txn["txn"]["data"]["outputs"].as_array();
+
+    // the outputs of the older pools are [address, amount] pairs
+    seq_no == Some(receipt.seq_no) && outputs.map_or(false, |outputs| outputs.iter().any(|output| {
+        let address = output.get("address").or_else(|| output.get("recipient")).or_else(|| output.get(0)).and_then(Value::as_str);
+        let amount = output.get("amount").or_else(|| output.get(1)).and_then(Value::as_u64);
+        address.map(address::unqualified_part) == Some(recipient) && amount == Some(receipt.amount)
+    }))
+}
+
+// The root of the tree of `tree_size` leaves the audit path leads to from
+// the leaf at `leaf_index`, as RFC 6962. `None` when the path doesn't fit
+// the tree.
+fn root_from_audit_path(leaf_index: TxnSeqNo, tree_size: u64, leaf_hash: Vec<u8>, audit_path: &[Vec<u8>]) -> Result<Option<Vec<u8>>, ErrorCode> {
+    if leaf_index >= tree_size {
+        return Ok(None);
+    }
+
+    let mut index = leaf_index;
+    let mut last = tree_size - 1;
+    let mut hash = leaf_hash;
+
+    for sibling in audit_path {
+        if last == 0 {
+            return Ok(None);
+        }
+
+        if index & 1 == 1 || index == last {
+            hash = Hash::hash_nodes(sibling, &hash)?.to_vec();
+            while index & 1 == 0 && index != 0 {
+                index >>= 1;
+                last >>= 1;
+            }
+        } else {
+            hash = Hash::hash_nodes(&hash, sibling)?.to_vec();
+        }
+
+        index >>= 1;
+        last >>= 1;
+    }
+
+    if last != 0 {
+        return Ok(None);
+    }
+    Ok(Some(hash))
+}
+
+
+#[cfg(test)]
+mod inclusion_proof_tests {
+    use super::*;
+
+    static ADDRESS: &'static str = "2jS4PHWQJKcawRxdW6GVsjnZBa1ecGdCssn7KhWYJZGTXgL7Es";
+
+    // The REPLY of a plenum pool to an XFER_PUBLIC, the second transaction
+    // of its ledger, as in doc/data_structures.md. Its proof was computed by
+    // the pool, not by this module.
+    static PLENUM_REPLY: &'static str = r#"{"op": "REPLY", "protocolVersion": 2, "result": {"txn": {"data": {"extra": null, "inputs": [["dctKSXBbv2My3TGGUgTFjkxu1A9JM3Sscd5FydY4dkxnfwA7q", 1]], "outputs": [["2jS4PHWQJKcawRxdW6GVsjnZBa1ecGdCssn7KhWYJZGTXgL7Es", 13], ["24xHHVDRq97Hss5BxiTciEDsve7nYNx1pxAMi9RAvcWMouviSY", 13], ["mNYFWv9vvoQVCVLrSpbU7ZScthjNJMQxMs3gREQrwcJC1DsG5", 13], ["dctKSXBbv2My3TGGUgTFjkxu1A9JM3Sscd5FydY4dkxnfwA7q", 1]]}, "metadata": {"digest": "228af6a0c773cbbd575bf4e16f9144c2eaa615fa81fdcc3d06b83e20a92e5989", "from": "6baBEYA94sAphWBA5efEsaA6X2wCdyaH7PXuBtv2H5S1", "reqId": 1529682415342024}, "protocolVersion": 2, "type": "10001"}, "reqSignature": {"type": "ED25519", "values": [{"from": "dctKSXBbv2My3TGGUgTFjkxu1A9JM3Sscd5FydY4dkxnfwA7q", "value": "4fFVD1HSVLaVdMpjHU168eviqWDxKrWYx1fRxw4DDLjg4XZXwya7UdcvVty81pYFcng244tS36WbshCeznC8ZN5Z"}]}, "txnMetadata": {"seqNo": 2, "txnTime": 1529682415}, "ver": "1", "auditPath": ["5NtSQUXaZvETP1KEWi8LaxSb9gGa2Qj31xKQoimNxCAT"], "rootHash": "GJFwiQt9r7n25PqM1oXBtRceXCeoqoCBcJmRH1c8fVTs"}}"#;
+    static PLENUM_ROOT: &'static str = "GJFwiQt9r7n25PqM1oXBtRceXCeoqoCBcJmRH1c8fVTs";
+
+    fn leaf(data: &[u8]) -> Vec<u8> {
+        Hash::hash_leaf(&data).unwrap().to_vec()
+    }
+
+    fn node(left: &[u8], right: &[u8]) -> Vec<u8> {
+        Hash::hash_nodes(&left, &right).unwrap().to_vec()
+    }
+
+    fn txn(amount: u64) -> Value {
+        json!({
+            "txn": {
+                "data": {"inputs": [], "outputs": [{"address": ADDRESS, "amount": amount}]},
+                "type": "10001"
+            },
+            "txnMetadata": {"seqNo": 1, "txnTime": 1529682415},
+            "ver": "1"
+        })
+    }
+
+    fn reply(root_hash: &str) -> String {
+        let mut result = txn(13);
+        result["rootHash"] = json!(root_hash);
+        result["auditPath"] = json!([]);
+        json!({"op": "REPLY", "result": result}).to_string()
+    }
+
+    fn receipt(amount: u64) -> Receipt {
+        let mut receipt = Receipt::new(&format!("pay:sov:{}", ADDRESS), amount, 1, None, None).unwrap();
+        receipt.proof = Some(InclusionProof::from_reply(&reply("root")).unwrap());
+        receipt
+    }
+
+    fn root_of_single_txn() -> String {
+        let proof = InclusionProof::from_reply(&reply("root")).unwrap();
+        base58::encode(&proof.leaf_hash().unwrap(), Checksum::None)
+    }
+
+    #[test]
+    fn audit_paths_of_a_tree_of_three() {
+        let leaves: Vec<Vec<u8>> = vec![leaf(b"a"), leaf(b"b"), leaf(b"c")];
+        let left = node(&leaves[0], &leaves[1]);
+        let root = node(&left, &leaves[2]);
+
+        assert_eq!(Some(root.clone()), root_from_audit_path(0, 3, leaves[0].clone(), &[leaves[1].clone(), leaves[2].clone()]).unwrap());
+        assert_eq!(Some(root.clone()), root_from_audit_path(1, 3, leaves[1].clone(), &[leaves[0].clone(), leaves[2].clone()]).unwrap());
+        assert_eq!(Some(root.clone()), root_from_audit_path(2, 3, leaves[2].clone(), &[left.clone()]).unwrap());
+        assert_eq!(None, root_from_audit_path(2, 3, leaves[2].clone(), &[]).unwrap());
+        assert_eq!(None, root_from_audit_path(3, 3, leaves[2].clone(), &[left]).unwrap());
+    }
+
+    #[test]
+    fn proof_from_reply() {
+        let proof = InclusionProof::from_reply(&reply("root")).unwrap();
+
+        assert_eq!("root", proof.root_hash);
+        assert_eq!(1, proof.ledger_size);
+        assert_eq!(txn(13), proof.txn);
+    }
+
+    #[test]
+    fn reply_without_audit_path() {
+        let reply = json!({"op": "REPLY", "result": txn(13)}).to_string();
+        assert_eq!(ErrorCode::CommonInvalidStructure, InclusionProof::from_reply(&reply).unwrap_err());
+    }
+
+    #[test]
+    fn verify_receipt() {
+        assert!(verify_inclusion(&receipt(13), &root_of_single_txn()).unwrap());
+    }
+
+    #[test]
+    fn verify_receipt_of_a_plenum_reply() {
+        let mut receipt = Receipt::new(&format!("pay:sov:{}", ADDRESS), 13, 2, None, None).unwrap();
+        receipt.proof = Some(InclusionProof::from_reply(PLENUM_REPLY).unwrap());
+
+        assert!(verify_inclusion(&receipt, PLENUM_ROOT).unwrap());
+        assert!(!verify_inclusion(&receipt, &base58::encode(&leaf(b"other"), Checksum::None)).unwrap());
+    }
+
+    #[test]
+    fn verify_receipt_of_other_root() {
+        let other_root = base58::encode(&leaf(b"other"), Checksum::None);
+        assert!(!verify_inclusion(&receipt(13), &other_root).unwrap());
+    }
+
+    #[test]
+    fn verify_receipt_of_other_output() {
+        assert!(!verify_inclusion(&receipt(12), &root_of_single_txn()).unwrap());
+    }
+
+    #[test]
+    fn verify_receipt_without_proof() {
+        let mut receipt = receipt(13);
+        receipt.proof = None;
+        assert_eq!(ErrorCode::CommonInvalidStructure, verify_inclusion(&receipt, &root_of_single_txn()).unwrap_err());
+    }
+
+    #[test]
+    fn add_proof_to_receipts() {
+        let mut receipts = vec![Receipt::new(&format!("pay:sov:{}", ADDRESS), 13, 1, None, None).unwrap()];
+        add_to_receipts(&mut receipts, &reply("root")).unwrap();
+        assert_eq!("root", receipts[0].proof.as_ref().unwrap().root_hash);
+    }
+}
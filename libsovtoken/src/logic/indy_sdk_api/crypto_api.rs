@@ -1,9 +1,19 @@
-//! Indy-sdk crypto functions
+/*!
+    Indy-sdk crypto functions
+*/
+
 use indy::{IndyHandle, ErrorCode};
 use indy::crypto::{Crypto, Key};
+
+use logic::config::library_config::library_config;
 use logic::config::payment_address_config::PaymentAddressConfig;
+use logic::context;
+use logic::stateless;
+use logic::wallet_handles;
 use utils::base58::serialize_bytes;
 use utils::json_conversion::JsonSerialize;
+use utils::results::ResultHandler;
+use utils::span;
 
 /**
     This defines the interfaces for INDY SDK crypto apis, which can be replaced with different implementations
@@ -11,10 +21,49 @@ use utils::json_conversion::JsonSerialize;
 
     modeling: master/libindy/src/api/crypto.rs
 */
-pub trait CryptoAPI {
+pub trait CryptoAPI: Sync {
     fn indy_create_key(&self, wallet_id: i32, config: PaymentAddressConfig) -> Result<String, ErrorCode>;
     fn indy_create_key_async<F: 'static>(&self, wallet_id: i32, config: PaymentAddressConfig, closure: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send;
-    fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, wallet_handle: i32, verkey: String, message: String, cb: F) -> ErrorCode;
+    /**
+        Sign `message` with the key of `verkey`, calls `cb` with the base58 signature.
+
+        The message is signed as it is, it may hold any bytes including NULs.
+    */
+    fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, wallet_handle: i32, verkey: String, message: &[u8], cb: F) -> ErrorCode;
+    /**
+        Check the wallet holds the key of `verkey`, calls `cb` with the
+        error of the wallet, e.g. `WalletItemNotFound`, when it doesn't.
+
+        Signs [`KEY_PROBE_MESSAGE`] with the key unless overridden.
+
+        [`KEY_PROBE_MESSAGE`]: constant.KEY_PROBE_MESSAGE.html
+    */
+    fn indy_check_key<F: FnMut(Result<(), ErrorCode>) + 'static + Send>(&self, wallet_handle: i32, verkey: String, mut cb: F) -> ErrorCode {
+        self.indy_crypto_sign(wallet_handle, verkey, KEY_PROBE_MESSAGE, move |signature| cb(signature.map(|_| ())))
+    }
+}
+
+/**
+    Signed to check a key is in the wallet, the signature is thrown away.
+*/
+pub const KEY_PROBE_MESSAGE: &[u8] = b"sovtoken payment address key probe";
+
+impl<'a, T: CryptoAPI> CryptoAPI for &'a T {
+    fn indy_create_key(&self, wallet_id: i32, config: PaymentAddressConfig) -> Result<String, ErrorCode> {
+        (*self).indy_create_key(wallet_id, config)
+    }
+
+    fn indy_create_key_async<F: 'static>(&self, wallet_id: i32, config: PaymentAddressConfig, closure: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+        (*self).indy_create_key_async(wallet_id, config, closure)
+    }
+
+    fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, wallet_handle: i32, verkey: String, message: &[u8], cb: F) -> ErrorCode {
+        (*self).indy_crypto_sign(wallet_handle, verkey, message, cb)
+    }
+
+    fn indy_check_key<F: FnMut(Result<(), ErrorCode>) + 'static + Send>(&self, wallet_handle: i32, verkey: String, cb: F) -> ErrorCode {
+        (*self).indy_check_key(wallet_handle, verkey, cb)
+    }
 }
 
 // ------------------------------------------------------------------
@@ -23,6 +72,9 @@ pub trait CryptoAPI {
 /**
    This is the "production" implementation of CryptoAPI as
    this implementation calls Indy SDK indy_create_key(...)
+
+   In the stateless mode no key is created and the messages are signed by
+   the registered signer, whatever the wallet handle.
 */
 pub struct CryptoSdk{}
 
@@ -37,6 +89,10 @@ impl CryptoAPI for CryptoSdk {
     fn indy_create_key(&self, wallet_id: IndyHandle, config: PaymentAddressConfig) -> Result<String, ErrorCode> {
 
         trace!("create_payment_address calling indy_create_key");
+        if stateless::is_stateless() {
+            return Err(stateless::no_key_creation());
+        }
+        wallet_handles::check(wallet_id)?;
         let mut config_json: String = config.to_json().unwrap();
 
         // indy-sdk expects a valid but empty input to be this below
@@ -45,15 +101,23 @@ impl CryptoAPI for CryptoSdk {
             config_json = r#"{ }"#.to_string();
         }
 
-        return Key::create(wallet_id, Some(&config_json));
+        let handler = ResultHandler::new("key creation", library_config().create_key_timeout_ms);
+        let ec = Key::create_async(wallet_id, Some(&config_json), handler.sender().one());
+        handler.wait(ec)
     }
 
     /**
         for consumers that cannot have blocking calls, this method indy_create_key asynchronously
     */
-    fn indy_create_key_async<F: 'static>(&self, wallet_id: i32, config: PaymentAddressConfig, closure: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+    fn indy_create_key_async<F: 'static>(&self, wallet_id: i32, config: PaymentAddressConfig, mut closure: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
 
         trace!("create_payment_address calling indy_create_key");
+        if stateless::is_stateless() {
+            return stateless::no_key_creation();
+        }
+        if let Err(ec) = wallet_handles::check(wallet_id) {
+            return ec;
+        }
         let mut config_json: String = config.to_json().unwrap();
 
         // indy-sdk expects a valid but empty input to be this below
@@ -62,22 +126,159 @@ impl CryptoAPI for CryptoSdk {
             config_json = r#"{ }"#.to_string();
         }
 
-        return Key::create_async(wallet_id, Some(&config_json), closure);
+        let handler = ResultHandler::new("key creation", library_config().create_key_timeout_ms);
+        let sender = handler.callback(move |result| match result {
+            Ok(verkey) => closure(ErrorCode::Success, verkey),
+            Err(error_code) => closure(error_code, String::new()),
+        });
+
+        // libindy calls back on its own thread, which has to enter the context and span of the call
+        let span = span::current();
+        let context = context::current();
+        let callback = sender.clone();
+        let ec = Key::create_async(wallet_id, Some(&config_json), move |error_code, verkey| {
+            context::in_context(context.clone(), || span::in_span(span, || callback.send(error_code, verkey)))
+        });
+        if ec != ErrorCode::Success {
+            sender.cancel();
+        }
+        return ec;
     }
 
     fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(
         &self,
         wallet_handle: IndyHandle,
         verkey: String,
-        message: String,
+        message: &[u8],
         mut cb: F
     ) -> ErrorCode {
-        return Crypto::sign_async(wallet_handle, &verkey, message.as_bytes(), move |error_code, vec| {
-            if error_code == ErrorCode::Success {
-                cb(Ok(serialize_bytes(&vec)));
-            } else {
-                cb(Err(error_code));
-            }
+        if stateless::is_stateless() {
+            cb(stateless::sign(&verkey, message));
+            return ErrorCode::Success;
+        }
+        if let Err(ec) = wallet_handles::check(wallet_handle) {
+            return ec;
+        }
+
+        let handler = ResultHandler::new("signing", library_config().sign_timeout_ms);
+        let sender = handler.callback(cb);
+
+        // libindy calls back on its own thread, which has to enter the context and span of the call
+        let span = span::current();
+        let context = context::current();
+        let callback = sender.clone();
+        let ec = Crypto::sign_async(wallet_handle, &verkey, message, move |error_code, vec| {
+            context::in_context(context.clone(), || span::in_span(span, || callback.send(error_code, serialize_bytes(&vec))))
         });
+        if ec != ErrorCode::Success {
+            sender.cancel();
+        }
+        return ec;
+    }
+
+    /**
+        looks the metadata of the key up, nothing is signed for a key with
+        metadata. libindy can't look a key itself up: a key without metadata,
+        e.g. the key of a payment address, is checked by signing with it.
+    */
+    fn indy_check_key<F: FnMut(Result<(), ErrorCode>) + 'static + Send>(&self, wallet_handle: IndyHandle, verkey: String, mut cb: F) -> ErrorCode {
+        if stateless::is_stateless() {
+            cb(stateless::sign(&verkey, KEY_PROBE_MESSAGE).map(|_| ()));
+            return ErrorCode::Success;
+        }
+        if let Err(ec) = wallet_handles::check(wallet_handle) {
+            return ec;
+        }
+
+        let handler = ResultHandler::new("key check", library_config().sign_timeout_ms);
+        let sender = handler.callback(cb);
+
+        // libindy calls back on its own thread, which has to enter the context and span of the call
+        let span = span::current();
+        let context = context::current();
+        let callback = sender.clone();
+        let probed_verkey = verkey.clone();
+        let ec = Key::get_metadata_async(wallet_handle, &verkey, move |error_code, _metadata| {
+            context::in_context(context.clone(), || span::in_span(span, || {
+                if error_code != ErrorCode::WalletItemNotFound {
+                    return callback.send(error_code, ());
+                }
+                let probe = callback.clone();
+                let ec = CryptoSdk {}.indy_crypto_sign(wallet_handle, probed_verkey.clone(), KEY_PROBE_MESSAGE, move |signature| {
+                    probe.send(signature.err().unwrap_or(ErrorCode::Success), ());
+                });
+                if ec != ErrorCode::Success {
+                    callback.send(ec, ());
+                }
+            }))
+        });
+        if ec != ErrorCode::Success {
+            sender.cancel();
+        }
+        return ec;
+    }
+}
+
+
+#[cfg(test)]
+mod crypto_api_tests {
+    use std::sync::{Arc, Mutex};
+
+    use sodiumoxide::crypto::sign::{sign_detached, verify_detached, Signature};
+
+    use utils::base58::{FromBase58, IntoBase58};
+    use utils::test::keys::keypair;
+    use super::*;
+
+    // signs with the key of `keypair()`
+    struct CryptoApiHandler {}
+
+    impl CryptoAPI for CryptoApiHandler {
+        fn indy_create_key(&self, _: IndyHandle, _: PaymentAddressConfig) -> Result<String, ErrorCode> {
+            Err(ErrorCode::CommonInvalidState)
+        }
+
+        fn indy_create_key_async<F: 'static>(&self, _: i32, _: PaymentAddressConfig, _: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+            ErrorCode::CommonInvalidState
+        }
+
+        fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _: IndyHandle, verkey: String, message: &[u8], mut cb: F) -> ErrorCode {
+            let (public_key, secret_key) = keypair();
+            assert_eq!(public_key.into_base58(), verkey);
+            cb(Ok(sign_detached(message, &secret_key).into_base58()));
+            ErrorCode::Success
+        }
+    }
+
+    fn sign_through_reference(message: &[u8]) -> Signature {
+        let signature = Arc::new(Mutex::new(String::new()));
+        let signature_clone = Arc::clone(&signature);
+        let crypto_api = CryptoApiHandler {};
+
+        let ec = (&crypto_api).indy_crypto_sign(1, keypair().0.into_base58(), message, move |result| {
+            *signature_clone.lock().unwrap() = result.unwrap();
+        });
+        assert_eq!(ErrorCode::Success, ec);
+
+        let signature = signature.lock().unwrap().from_base58().unwrap();
+        Signature::from_slice(&signature).unwrap()
+    }
+
+    #[test]
+    fn sign_message_with_interior_nul() {
+        let message = b"before\0after";
+        let signature = sign_through_reference(message);
+
+        assert!(verify_detached(&signature, message, &keypair().0));
+        assert!(!verify_detached(&signature, b"before", &keypair().0));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn sign_non_utf8_message() {
+        let message = [0xff, 0xfe, 0x00, 0xc3, 0x28];
+        let signature = sign_through_reference(&message);
+
+        assert!(verify_detached(&signature, &message, &keypair().0));
+        assert!(!verify_detached(&signature, &message[..2], &keypair().0));
+    }
+}
@@ -0,0 +1,104 @@
+/*!
+    Indy-sdk ledger functions
+*/
+use indy::{IndyHandle, ErrorCode};
+use indy::ledger::Ledger;
+
+use logic::context;
+use utils::span;
+
+/**
+    This defines the interfaces for INDY SDK ledger apis, which can be replaced with different
+    implementations (aka production vs test time)
+
+    modeling: master/libindy/src/api/ledger.rs
+*/
+pub trait LedgerAPI {
+    /**
+        Submit `request_json` to the pool, calls `cb` with the reply.
+
+        libindy times the request out by the config of the pool.
+    */
+    fn indy_submit_request_async<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, pool_handle: IndyHandle, request_json: &str, cb: F) -> ErrorCode;
+    /**
+        Sign `request_json` with the key of `did`, calls `cb` with the signed request.
+
+        With `multi` the signature is added to the `signatures` of the request,
+        otherwise it is its `signature`.
+    */
+    fn indy_sign_request<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, wallet_handle: IndyHandle, did: &str, request_json: &str, multi: bool, cb: F) -> ErrorCode;
+}
+
+impl<'a, T: LedgerAPI> LedgerAPI for &'a T {
+    fn indy_submit_request_async<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, pool_handle: IndyHandle, request_json: &str, cb: F) -> ErrorCode {
+        (*self).indy_submit_request_async(pool_handle, request_json, cb)
+    }
+
+    fn indy_sign_request<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, wallet_handle: IndyHandle, did: &str, request_json: &str, multi: bool, cb: F) -> ErrorCode {
+        (*self).indy_sign_request(wallet_handle, did, request_json, multi, cb)
+    }
+}
+
+// ------------------------------------------------------------------
+// LedgerAPI implementation using INDY SDK
+// ------------------------------------------------------------------
+/**
+   This is the "production" implementation of LedgerAPI as
+   this implementation calls Indy SDK ledger functions
+*/
+pub struct LedgerSdk{}
+
+impl LedgerAPI for LedgerSdk {
+
+    fn indy_submit_request_async<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(
+        &self,
+        pool_handle: IndyHandle,
+        request_json: &str,
+        mut cb: F
+    ) -> ErrorCode {
+        trace!("calling Ledger::submit_request_async >> pool_handle: {:?}", pool_handle);
+
+        // libindy calls back on its own thread, which has to enter the context and span of the call
+        let span = span::current();
+        let context = context::current();
+        Ledger::submit_request_async(pool_handle, request_json, move |error_code: ErrorCode, reply: String| {
+            context::in_context(context.clone(), || span::in_span(span, || {
+                if error_code == ErrorCode::Success {
+                    cb(Ok(reply));
+                } else {
+                    cb(Err(error_code));
+                }
+            }))
+        })
+    }
+
+    fn indy_sign_request<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(
+        &self,
+        wallet_handle: IndyHandle,
+        did: &str,
+        request_json: &str,
+        multi: bool,
+        mut cb: F
+    ) -> ErrorCode {
+        trace!("calling Ledger::sign_request_async >> wallet_handle: {:?}, did: {:?}, multi: {:?}", wallet_handle, did, multi);
+
+        // libindy calls back on its own thread, which has to enter the context and span of the call
+        let span = span::current();
+        let context = context::current();
+        let closure = move |error_code: ErrorCode, signed: String| {
+            context::in_context(context.clone(), || span::in_span(span, || {
+                if error_code == ErrorCode::Success {
+                    cb(Ok(signed));
+                } else {
+                    cb(Err(error_code));
+                }
+            }))
+        };
+
+        if multi {
+            Ledger::multi_sign_request_async(wallet_handle, did, request_json, closure)
+        } else {
+            Ledger::sign_request_async(wallet_handle, did, request_json, closure)
+        }
+    }
+}
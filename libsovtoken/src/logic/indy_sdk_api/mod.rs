@@ -1,3 +1,8 @@
-//! for all wrappers/facades/etc around rust-indy-sdk crate or indy-sdk itself
+/*!
+    for all wrappers/facades/etc around rust-indy-sdk crate or indy-sdk itself
+*/
 
-pub mod crypto_api;
\ No newline at end of file
+pub mod crypto_api;
+pub mod ledger_api;
+pub mod payment_api;
+pub mod wallet_api;
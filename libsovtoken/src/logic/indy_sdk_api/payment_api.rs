@@ -0,0 +1,233 @@
+/*!
+    Indy-sdk payment address and ledger functions
+*/
+use indy::{IndyHandle, ErrorCode};
+use indy::ledger::Ledger;
+use indy::payments::Payment;
+use serde_json;
+
+use logic::config::get_utxo_config::GetUtxoOperationRequest;
+use logic::context;
+use logic::parsers::parse_get_utxo_response::ParseGetUtxoReply;
+use logic::stateless;
+use logic::utxo_cache;
+use logic::wallet_handles;
+use utils::constants::general::{PAYMENT_ADDRESS_QUALIFIER, PAYMENT_METHOD_NAME};
+use utils::results::ResultHandler;
+use utils::span;
+
+/**
+    This defines the interfaces for listing the payment addresses of a wallet and
+    fetching their UTXOs, which can be replaced with different implementations
+    (aka production vs test time)
+
+    modeling: master/libindy/src/api/payments.rs
+*/
+pub trait PaymentAPI {
+    fn indy_list_payment_addresses(&self, wallet_handle: IndyHandle) -> Result<Vec<String>, ErrorCode>;
+    fn indy_get_utxos(&self, pool_handle: IndyHandle, address: &str) -> Result<ParseGetUtxoReply, ErrorCode>;
+    /**
+        List the payment addresses of `wallet_handle` without blocking, calls `cb` with them.
+
+        Calls `cb` at once with the result of `indy_list_payment_addresses` unless overridden.
+    */
+    fn indy_list_payment_addresses_async<F: FnMut(Result<Vec<String>, ErrorCode>) + 'static + Send>(&self, wallet_handle: IndyHandle, mut cb: F) -> ErrorCode {
+        cb(self.indy_list_payment_addresses(wallet_handle));
+        ErrorCode::Success
+    }
+
+    /**
+        Fetch the UTXOs of `address` without blocking, calls `cb` with them.
+
+        Calls `cb` at once with the result of `indy_get_utxos` unless overridden.
+    */
+    fn indy_get_utxos_async<F: FnMut(Result<ParseGetUtxoReply, ErrorCode>) + 'static + Send>(&self, pool_handle: IndyHandle, address: &str, mut cb: F) -> ErrorCode {
+        cb(self.indy_get_utxos(pool_handle, address));
+        ErrorCode::Success
+    }
+
+    /**
+        Create the payment address of `config_json` without blocking, calls
+        `cb` with it. The address is then listed by `indy_list_payment_addresses`.
+
+        Fails with `CommonInvalidState` unless overridden.
+    */
+    fn indy_create_payment_address_async<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _wallet_handle: IndyHandle, _config_json: &str, _cb: F) -> ErrorCode {
+        ErrorCode::CommonInvalidState
+    }
+}
+
+impl<'a, T: PaymentAPI> PaymentAPI for &'a T {
+    fn indy_list_payment_addresses(&self, wallet_handle: IndyHandle) -> Result<Vec<String>, ErrorCode> {
+        (*self).indy_list_payment_addresses(wallet_handle)
+    }
+
+    fn indy_get_utxos(&self, pool_handle: IndyHandle, address: &str) -> Result<ParseGetUtxoReply, ErrorCode> {
+        (*self).indy_get_utxos(pool_handle, address)
+    }
+
+    fn indy_list_payment_addresses_async<F: FnMut(Result<Vec<String>, ErrorCode>) + 'static + Send>(&self, wallet_handle: IndyHandle, cb: F) -> ErrorCode {
+        (*self).indy_list_payment_addresses_async(wallet_handle, cb)
+    }
+
+    fn indy_get_utxos_async<F: FnMut(Result<ParseGetUtxoReply, ErrorCode>) + 'static + Send>(&self, pool_handle: IndyHandle, address: &str, cb: F) -> ErrorCode {
+        (*self).indy_get_utxos_async(pool_handle, address, cb)
+    }
+
+    fn indy_create_payment_address_async<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, wallet_handle: IndyHandle, config_json: &str, cb: F) -> ErrorCode {
+        (*self).indy_create_payment_address_async(wallet_handle, config_json, cb)
+    }
+}
+
+// ------------------------------------------------------------------
+// PaymentAPI implementation using INDY SDK
+// ------------------------------------------------------------------
+/**
+   This is the "production" implementation of PaymentAPI as
+   this implementation calls Indy SDK payment and ledger functions
+*/
+pub struct PaymentSdk{}
+
+impl PaymentAPI for PaymentSdk {
+
+    /**
+        lists the payment addresses of the wallet, only sovrin addresses are returned.
+        There are none in the stateless mode, which has no wallet.
+    */
+    fn indy_list_payment_addresses(&self, wallet_handle: IndyHandle) -> Result<Vec<String>, ErrorCode> {
+        trace!("calling Payment::list_payment_addresses >> wallet_handle: {:?}", wallet_handle);
+        if stateless::is_stateless() {
+            debug!("No payment addresses to list in the stateless mode");
+            return Ok(Vec::new());
+        }
+        wallet_handles::check(wallet_handle)?;
+        let addresses = Payment::list_payment_addresses(wallet_handle)?;
+
+        return sovrin_addresses(&addresses);
+    }
+
+    /**
+        lists the payment addresses of the wallet with libindy, calls `cb`
+        with the sovrin addresses, as `indy_list_payment_addresses`.
+    */
+    fn indy_list_payment_addresses_async<F: FnMut(Result<Vec<String>, ErrorCode>) + 'static + Send>(&self, wallet_handle: IndyHandle, mut cb: F) -> ErrorCode {
+        trace!("calling Payment::list_payment_addresses_async >> wallet_handle: {:?}", wallet_handle);
+        if stateless::is_stateless() {
+            debug!("No payment addresses to list in the stateless mode");
+            cb(Ok(Vec::new()));
+            return ErrorCode::Success;
+        }
+        if let Err(ec) = wallet_handles::check(wallet_handle) {
+            return ec;
+        }
+
+        // libindy calls back on its own thread, which has to enter the context and span of the call
+        let span = span::current();
+        let context = context::current();
+        Payment::list_payment_addresses_async(wallet_handle, move |error_code, addresses| {
+            context::in_context(context.clone(), || span::in_span(span, || {
+                if error_code == ErrorCode::Success {
+                    cb(sovrin_addresses(&addresses));
+                } else {
+                    cb(Err(error_code));
+                }
+            }))
+        })
+    }
+
+    /**
+        submits a GET_UTXO request for the address and parses the reply.
+    */
+    fn indy_get_utxos(&self, pool_handle: IndyHandle, address: &str) -> Result<ParseGetUtxoReply, ErrorCode> {
+        trace!("calling Ledger::submit_request_async for GET_UTXO >> address: {:?}", address);
+        let request = get_utxo_request(address)?;
+
+        // libindy times the request out by the config of the pool
+        let handler = ResultHandler::new("GET_UTXO", 0);
+        let ec = Ledger::submit_request_async(pool_handle, &request, handler.sender().one());
+        let response = handler.wait(ec)?;
+
+        return utxo_cache::parse_get_utxo_response_cached(Some(pool_handle), &response);
+    }
+
+    /**
+        submits a GET_UTXO request for the address, calls `cb` with the parsed reply.
+
+        The reply is parsed off the callback thread of libindy, as the UTXO
+        cache can be persisted in the wallet.
+    */
+    fn indy_get_utxos_async<F: FnMut(Result<ParseGetUtxoReply, ErrorCode>) + 'static + Send>(&self, pool_handle: IndyHandle, address: &str, cb: F) -> ErrorCode {
+        trace!("calling Ledger::submit_request_async for GET_UTXO >> address: {:?}", address);
+        let request = match get_utxo_request(address) {
+            Ok(request) => request,
+            Err(ec) => return ec,
+        };
+
+        // libindy calls back on its own thread, which has to enter the context and span of the call
+        let span = span::current();
+        let context = context::current();
+        let mut cb = Some(cb);
+        Ledger::submit_request_async(pool_handle, &request, move |error_code, response| {
+            let mut cb = match cb.take() {
+                Some(cb) => cb,
+                None => return,
+            };
+            context::in_context(context.clone(), || span::in_span(span, || {
+                context::spawn(move || {
+                    if error_code == ErrorCode::Success {
+                        cb(utxo_cache::parse_get_utxo_response_cached(Some(pool_handle), &response));
+                    } else {
+                        cb(Err(error_code));
+                    }
+                });
+            }))
+        })
+    }
+
+    /**
+        creates the payment address with libindy, which calls
+        `create_payment_address_handler` and records the address in the wallet.
+    */
+    fn indy_create_payment_address_async<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, wallet_handle: IndyHandle, config_json: &str, mut cb: F) -> ErrorCode {
+        trace!("calling Payment::create_payment_address_async >> wallet_handle: {:?}", wallet_handle);
+        if let Err(ec) = wallet_handles::check(wallet_handle) {
+            return ec;
+        }
+
+        // libindy calls back on its own thread, which has to enter the context and span of the call
+        let span = span::current();
+        let context = context::current();
+        Payment::create_payment_address_async(wallet_handle, PAYMENT_METHOD_NAME, config_json, move |error_code, address| {
+            context::in_context(context.clone(), || span::in_span(span, || {
+                if error_code == ErrorCode::Success {
+                    cb(Ok(address));
+                } else {
+                    cb(Err(error_code));
+                }
+            }))
+        })
+    }
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn get_utxo_request(address: &str) -> Result<String, ErrorCode> {
+    GetUtxoOperationRequest::new(address.to_string())
+        .serialize_to_string()
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))
+}
+
+// The sovrin addresses of the json array `addresses` listed by libindy.
+fn sovrin_addresses(addresses: &str) -> Result<Vec<String>, ErrorCode> {
+    let addresses: Vec<String> = serde_json::from_str(addresses)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))?;
+
+    Ok(addresses.into_iter()
+        .filter(|address| address.starts_with(PAYMENT_ADDRESS_QUALIFIER))
+        .collect())
+}
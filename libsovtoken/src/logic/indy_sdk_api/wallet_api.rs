@@ -0,0 +1,159 @@
+/*!
+    Indy-sdk wallet record functions
+*/
+use indy::{IndyHandle, ErrorCode};
+use indy::wallet::Wallet;
+use serde_json;
+
+use logic::state::state;
+use logic::stateless::{self, STATELESS_WALLET_HANDLE};
+use logic::storage::StorageBackend;
+use logic::wallet_handles;
+
+const SEARCH_BATCH_SIZE: usize = 100;
+
+/**
+    This defines the interfaces for INDY SDK non secret wallet record apis, which can be replaced
+    with different implementations (aka production vs test time)
+
+    modeling: master/libindy/src/api/non_secrets.rs
+*/
+pub trait WalletAPI {
+    fn indy_add_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode>;
+    fn indy_update_record_value(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode>;
+    fn indy_get_record_value(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<String, ErrorCode>;
+    fn indy_delete_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<(), ErrorCode>;
+    fn indy_list_records(&self, wallet_handle: IndyHandle, xtype: &str) -> Result<Vec<(String, String)>, ErrorCode>;
+}
+
+impl<'a, T: WalletAPI> WalletAPI for &'a T {
+    fn indy_add_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        (*self).indy_add_record(wallet_handle, xtype, id, value)
+    }
+
+    fn indy_update_record_value(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        (*self).indy_update_record_value(wallet_handle, xtype, id, value)
+    }
+
+    fn indy_get_record_value(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<String, ErrorCode> {
+        (*self).indy_get_record_value(wallet_handle, xtype, id)
+    }
+
+    fn indy_delete_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<(), ErrorCode> {
+        (*self).indy_delete_record(wallet_handle, xtype, id)
+    }
+
+    fn indy_list_records(&self, wallet_handle: IndyHandle, xtype: &str) -> Result<Vec<(String, String)>, ErrorCode> {
+        (*self).indy_list_records(wallet_handle, xtype)
+    }
+}
+
+// ------------------------------------------------------------------
+// WalletAPI implementation using INDY SDK
+// ------------------------------------------------------------------
+/**
+   This is the "production" implementation of WalletAPI as
+   this implementation calls Indy SDK non secrets functions
+
+   In the stateless mode the records are kept in the memory storage instead,
+   whatever the wallet handle.
+*/
+pub struct WalletSdk{}
+
+impl WalletAPI for WalletSdk {
+
+    fn indy_add_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        trace!("calling Wallet::add_record >> xtype: {:?}, id: {:?}", xtype, id);
+        if stateless::is_stateless() {
+            return state().memory_storage.add_record(STATELESS_WALLET_HANDLE, xtype, id, value);
+        }
+        wallet_handles::check(wallet_handle)?;
+        return Wallet::add_record(wallet_handle, xtype, id, value, None);
+    }
+
+    fn indy_update_record_value(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        trace!("calling Wallet::update_record_value >> xtype: {:?}, id: {:?}", xtype, id);
+        if stateless::is_stateless() {
+            return state().memory_storage.update_record(STATELESS_WALLET_HANDLE, xtype, id, value);
+        }
+        wallet_handles::check(wallet_handle)?;
+        return Wallet::update_record_value(wallet_handle, xtype, id, value);
+    }
+
+    /**
+        fetches the record and returns only its value.
+    */
+    fn indy_get_record_value(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<String, ErrorCode> {
+        trace!("calling Wallet::get_record >> xtype: {:?}, id: {:?}", xtype, id);
+        if stateless::is_stateless() {
+            return state().memory_storage.get_record(STATELESS_WALLET_HANDLE, xtype, id);
+        }
+        wallet_handles::check(wallet_handle)?;
+        let options = json!({"retrieveType": false, "retrieveValue": true, "retrieveTags": false}).to_string();
+        let record = Wallet::get_record(wallet_handle, xtype, id, &options)?;
+
+        let record: serde_json::Value = serde_json::from_str(&record)
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonInvalidState))?;
+
+        return record.get("value")
+            .and_then(|value| value.as_str())
+            .map(String::from)
+            .ok_or(ErrorCode::CommonInvalidState);
+    }
+
+    fn indy_delete_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<(), ErrorCode> {
+        trace!("calling Wallet::delete_record >> xtype: {:?}, id: {:?}", xtype, id);
+        if stateless::is_stateless() {
+            return state().memory_storage.delete_record(STATELESS_WALLET_HANDLE, xtype, id);
+        }
+        wallet_handles::check(wallet_handle)?;
+        return Wallet::delete_record(wallet_handle, xtype, id);
+    }
+
+    /**
+        lists the ids and values of all records of the type.
+    */
+    fn indy_list_records(&self, wallet_handle: IndyHandle, xtype: &str) -> Result<Vec<(String, String)>, ErrorCode> {
+        trace!("calling Wallet::open_search >> xtype: {:?}", xtype);
+        if stateless::is_stateless() {
+            return state().memory_storage.list_records(STATELESS_WALLET_HANDLE, xtype);
+        }
+        wallet_handles::check(wallet_handle)?;
+        let options = json!({"retrieveRecords": true, "retrieveTotalCount": false, "retrieveType": false, "retrieveValue": true, "retrieveTags": false}).to_string();
+        let search_handle = Wallet::open_search(wallet_handle, xtype, "{}", &options)?;
+
+        let mut records = Vec::new();
+        let fetched = loop {
+            let batch = match Wallet::fetch_search_next_records(wallet_handle, search_handle, SEARCH_BATCH_SIZE) {
+                Ok(batch) => batch,
+                Err(e) => break Err(e),
+            };
+
+            let batch: serde_json::Value = match serde_json::from_str(&batch).map_err(map_err_err!()) {
+                Ok(batch) => batch,
+                Err(_) => break Err(ErrorCode::CommonInvalidState),
+            };
+
+            let batch = match batch["records"].as_array() {
+                Some(batch) if !batch.is_empty() => batch.clone(),
+                _ => break Ok(()),
+            };
+
+            let count = records.len();
+            records.extend(batch.iter().filter_map(|record| {
+                match (record["id"].as_str(), record["value"].as_str()) {
+                    (Some(id), Some(value)) => Some((id.to_string(), value.to_string())),
+                    _ => None,
+                }
+            }));
+
+            if records.len() - count != batch.len() {
+                break Err(ErrorCode::CommonInvalidState);
+            }
+        };
+
+        Wallet::close_search(search_handle)?;
+        fetched.map(|()| records)
+    }
+}
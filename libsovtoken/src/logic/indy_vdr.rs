@@ -0,0 +1,209 @@
+/*!
+    An adapter to indy-vdr, the ledger client which replaces the ledger
+    api of libindy.
+
+    The requests built by the library are indy requests, which indy-vdr
+    takes as they are with `indy_vdr_build_custom_request`, once they are
+    framed with protocol version 2, the only one indy-vdr speaks. Its
+    replies come back in two shapes:
+
+    * a submitted request calls back with the reply, or its `result` alone
+      when the caller unwraps it, e.g. the python wrapper,
+    * a request the ledger refused fails, the error json of
+      `indy_vdr_get_current_error` holds the reason.
+
+    [`request_for_vdr`] frames a request for indy-vdr and
+    [`response_from_vdr`] turns both shapes of reply into the reply libindy
+    would have given, which the `parse_*` functions of the library read.
+
+    [`request_for_vdr`]: fn.request_for_vdr.html
+    [`response_from_vdr`]: fn.response_from_vdr.html
+*/
+
+use indy::ErrorCode;
+use serde_json::{self, Map, Value};
+
+use logic::error::SovtokenError;
+use logic::messages;
+use logic::request::LEGACY_PROTOCOL_VERSION;
+use logic::type_aliases::ProtocolVersion;
+use utils::constants::txn_fields::FEES;
+
+/**
+    The protocol version of the requests indy-vdr sends.
+*/
+pub const VDR_PROTOCOL_VERSION: ProtocolVersion = 2;
+
+/**
+    The code of the indy-vdr error of a request the ledger refused.
+*/
+pub const VDR_POOL_REQUEST_FAILED: i64 = 31;
+
+/**
+    The code of the indy-vdr error of a request the pool didn't answer in time.
+*/
+pub const VDR_POOL_TIMEOUT: i64 = 32;
+
+/**
+    Frame `request_json` for `indy_vdr_build_custom_request`.
+
+    A request without a `protocolVersion`, of protocol version 1, gets
+    version 2. A request already signed, by a DID or its fee inputs, can't
+    be reframed since the signatures cover the version: it fails with
+    `CommonInvalidStructure`, it has to be built with the `protocol_version`
+    2 of the library config. The request keeps its signatures and fees.
+
+    ```
+    use sovtoken::logic::indy_vdr::request_for_vdr;
+    let request = r#"{"operation": {"type": "10002", "address": "a"}, "reqId": 1, "identifier": "Th7MpTaRZVRYnPiabds81Y"}"#;
+    let request: serde_json::Value = serde_json::from_str(&request_for_vdr(request).unwrap()).unwrap();
+    assert_eq!(2, request["protocolVersion"]);
+    ```
+*/
+pub fn request_for_vdr(request_json: &str) -> Result<String, ErrorCode> {
+    let mut request: Map<String, Value> = serde_json::from_str(request_json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    if request.get("reqId").and_then(Value::as_u64).is_none() {
+        error!("The request has no reqId");
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+    if request.get("operation").and_then(|operation| operation.get("type")).and_then(Value::as_str).is_none() {
+        error!("The request has no operation type");
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let version = match request.get("protocolVersion") {
+        None => LEGACY_PROTOCOL_VERSION,
+        Some(version) => version.as_u64().ok_or(ErrorCode::CommonInvalidStructure)? as ProtocolVersion,
+    };
+    if version != VDR_PROTOCOL_VERSION {
+        if is_signed(&request) {
+            let error = SovtokenError::InvalidStructure(messages::SIGNED_FOR_OTHER_PROTOCOL_VERSION.with(&[version.to_string(), VDR_PROTOCOL_VERSION.to_string()]));
+            return Err(ErrorCode::from(error));
+        }
+        request.insert("protocolVersion".to_string(), json!(VDR_PROTOCOL_VERSION));
+    }
+
+    serde_json::to_string(&request).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState))
+}
+
+/**
+    The libindy reply of an indy-vdr `response_json`.
+
+    `response_json` is one of:
+
+    * a reply, `{"op": "REPLY", "result": {...}}`, returned as is,
+    * the `result` of a reply alone, wrapped in a REPLY,
+    * an indy-vdr error json, `{"code": <int>, "message": <string>, ...}`.
+      The error of a refused request is turned into the REJECT libindy
+      gives, with the message as reason. A timeout fails with
+      `PoolLedgerTimeout`, the other errors with `CommonInvalidState`.
+
+    ```
+    use sovtoken::logic::indy_vdr::response_from_vdr;
+    let reply = response_from_vdr(r#"{"type": "10002", "outputs": []}"#).unwrap();
+    let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+    assert_eq!("REPLY", reply["op"]);
+    ```
+*/
+pub fn response_from_vdr(response_json: &str) -> Result<String, ErrorCode> {
+    let response: Map<String, Value> = serde_json::from_str(response_json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    let reply = if response.contains_key("op") {
+        Value::Object(response)
+    } else if let (Some(code), Some(message)) = (response.get("code").and_then(Value::as_i64), response.get("message").and_then(Value::as_str)) {
+        match code {
+            VDR_POOL_REQUEST_FAILED => json!({"op": "REJECT", "reason": message}),
+            VDR_POOL_TIMEOUT => {
+                error!("indy-vdr timed out: {}", message);
+                return Err(ErrorCode::PoolLedgerTimeout);
+            },
+            code => {
+                error!("indy-vdr failed with {}: {}", code, message);
+                return Err(ErrorCode::CommonInvalidState);
+            }
+        }
+    } else {
+        json!({"op": "REPLY", "result": response})
+    };
+
+    serde_json::to_string(&reply).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState))
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// The fees are signed once they have their third element, the signatures.
+fn is_signed(request: &Map<String, Value>) -> bool {
+    let has = |key: &str| request.get(key).map_or(false, |value| !value.is_null());
+    let fees_signed = request.get(FEES).and_then(Value::as_array).map_or(false, |fees| fees.len() > 2);
+    has("signature") || has("signatures") || fees_signed
+}
+
+
+#[cfg(test)]
+mod indy_vdr_tests {
+    use super::*;
+
+    fn get_utxo_request() -> Value {
+        json!({
+            "operation": {"type": "10002", "address": "dctKSXBbv2My3TGGUgTFjkxu1A9JM3Sscd5FydY4dkxnfwA7q"},
+            "reqId": 1,
+            "identifier": "Th7MpTaRZVRYnPiabds81Y"
+        })
+    }
+
+    fn parse(json: &str) -> Value {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn unsigned_request_is_reframed() {
+        let request = parse(&request_for_vdr(&get_utxo_request().to_string()).unwrap());
+        assert_eq!(json!(2), request["protocolVersion"]);
+        assert_eq!(get_utxo_request()["operation"], request["operation"]);
+
+        let mut request = get_utxo_request();
+        request["protocolVersion"] = json!(2);
+        request["signature"] = json!("didsig");
+        assert_eq!(request, parse(&request_for_vdr(&request.to_string()).unwrap()));
+    }
+
+    #[test]
+    fn signed_legacy_request_is_refused() {
+        let mut request = get_utxo_request();
+        request["fees"] = json!([[], [], ["sig"]]);
+        assert_eq!(ErrorCode::CommonInvalidStructure, request_for_vdr(&request.to_string()).unwrap_err());
+
+        let mut request = get_utxo_request();
+        request.as_object_mut().unwrap().remove("reqId");
+        assert_eq!(ErrorCode::CommonInvalidStructure, request_for_vdr(&request.to_string()).unwrap_err());
+    }
+
+    #[test]
+    fn replies_of_vdr() {
+        let reply = json!({"op": "REPLY", "result": {"type": "10002", "outputs": []}});
+        assert_eq!(reply, parse(&response_from_vdr(&reply.to_string()).unwrap()));
+        assert_eq!(reply, parse(&response_from_vdr(&reply["result"].to_string()).unwrap()));
+    }
+
+    #[test]
+    fn errors_of_vdr() {
+        let refused = json!({"code": VDR_POOL_REQUEST_FAILED, "message": "client request invalid: InsufficientFundsError()"});
+        let reply = parse(&response_from_vdr(&refused.to_string()).unwrap());
+        assert_eq!(json!("REJECT"), reply["op"]);
+        assert_eq!(refused["message"], reply["reason"]);
+
+        let timeout = json!({"code": VDR_POOL_TIMEOUT, "message": "Request timed out"});
+        assert_eq!(ErrorCode::PoolLedgerTimeout, response_from_vdr(&timeout.to_string()).unwrap_err());
+
+        let unexpected = json!({"code": 7, "message": "Unexpected error"});
+        assert_eq!(ErrorCode::CommonInvalidState, response_from_vdr(&unexpected.to_string()).unwrap_err());
+    }
+}
@@ -2,10 +2,15 @@
     Payment Input
 */
 
-use serde::{de, Deserialize};
+use serde::{de, Deserialize, Serialize, Serializer};
+use serde::ser::SerializeStruct;
+use std::collections::HashSet;
 use std::fmt;
+use logic::address::unqualified_part;
+use logic::field_names;
 use logic::parsers::common::TXO;
 use logic::type_aliases::TxnSeqNo;
+use utils::constants::general::TXO_QUALIFIER;
 
 pub type Inputs = Vec<Input>;
 
@@ -17,11 +22,84 @@ pub type Inputs = Vec<Input>;
  * [`Inputs`]: Input
  */
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct InputConfig {
     pub ver: u8,
     pub inputs: Inputs,
 }
 
+impl InputConfig {
+    /**
+        Check no UTXO is spent twice, see [`dedupe_inputs`].
+
+        [`dedupe_inputs`]: fn.dedupe_inputs.html
+    */
+    pub fn validate(self, duplicates: DuplicateInputs) -> Result<InputConfig, DuplicateInputError> {
+        let inputs = dedupe_inputs(self.inputs, duplicates)?;
+        Ok(InputConfig { ver: self.ver, inputs })
+    }
+}
+
+/**
+    What to do with a UTXO given twice as an input, set with
+    `duplicate_inputs` in the library config.
+
+    The ledger rejects a transfer spending a UTXO twice, after all inputs
+    were signed.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateInputs {
+    /// Fail with the first duplicate.
+    Reject,
+    /// Keep the first occurrence of every UTXO.
+    Dedupe,
+}
+
+impl Default for DuplicateInputs {
+    fn default() -> Self {
+        DuplicateInputs::Reject
+    }
+}
+
+/**
+    A UTXO given twice as an input.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateInputError {
+    pub address: String,
+    pub seq_no: TxnSeqNo,
+}
+
+impl fmt::Display for DuplicateInputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "input {} with seqNo {} is given twice", self.address, self.seq_no)
+    }
+}
+
+/**
+    Find the UTXOs given twice in `inputs`, and reject or drop them.
+
+    Inputs are the same UTXO when their address, with or without the
+    `pay:sov:` qualifier and network tag, and their seqNo are the same.
+*/
+pub fn dedupe_inputs(inputs: Inputs, duplicates: DuplicateInputs) -> Result<Inputs, DuplicateInputError> {
+    let mut seen = HashSet::new();
+    let mut deduped = Vec::with_capacity(inputs.len());
+
+    for input in inputs {
+        if seen.insert((unqualified_part(&input.address).to_string(), input.seq_no)) {
+            deduped.push(input);
+        } else if duplicates == DuplicateInputs::Reject {
+            return Err(DuplicateInputError { address: input.address, seq_no: input.seq_no });
+        } else {
+            warn!("Dropping input {} with seqNo {} given twice", input.address, input.seq_no);
+        }
+    }
+
+    Ok(deduped)
+}
+
 
 /**
     Struct which holds an address, seq_no, signature, and extra data.
@@ -45,6 +123,8 @@ pub struct InputConfig {
     * address
     * seq_no
 
+    The address can also be given as `source`.
+
     ### Optional Fields
     * signature
     * extra
@@ -61,7 +141,8 @@ pub struct InputConfig {
     ```
 
     # Serialization
-    When Input is serialized, it is always serialized as an array:
+    When Input is serialized, it is always serialized as an object. The
+    address is emitted as `address` or `source`, see [`field_names`].
 
     ```
     use sovtoken::utils::json_conversion::JsonSerialize;
@@ -73,11 +154,11 @@ pub struct InputConfig {
     assert_eq!(json, r#"{"address":"pay:sov:AesjahdahudgaiuNotARealAKeyygigfuigraiudgfasfhja","seqNo":30}"#);
     ```
 
+    [`field_names`]: ../field_names/index.html
 */
-#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Input {
     pub address: String,
-    #[serde(rename = "seqNo")]
     pub seq_no: TxnSeqNo
 }
 
@@ -93,6 +174,15 @@ impl Input {
     }
 }
 
+impl Serialize for Input {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut input = serializer.serialize_struct("Input", 2)?;
+        input.serialize_field(field_names::current().input_address(), &self.address)?;
+        input.serialize_field("seqNo", &self.seq_no)?;
+        return input.end();
+    }
+}
+
 impl<'de> Deserialize<'de> for Input {
     fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Input, D::Error> {
         struct InputVisitor;
@@ -105,9 +195,7 @@ impl<'de> Deserialize<'de> for Input {
             }
 
             fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
-                let txo = TXO::from_libindy_string(v)
-                    .map_err(|ec| de::Error::custom(format!("Error when deserializing txo: error code {:?}", ec)))?;
-
+                let txo = parse_txo(v).map_err(de::Error::custom)?;
                 return Ok(Input::new(txo.address, txo.seq_no ))
             }
 
@@ -118,29 +206,54 @@ impl<'de> Deserialize<'de> for Input {
                 while let Some(key) = map.next_key()? {
                     match key {
                         "address" => { address = map.next_value()?; },
+                        "source" => { address = map.next_value()?; },
                         "seqNo" => { seq_no =  map.next_value()?; },
                         x => { return Err(de::Error::unknown_field(x, FIELDS)) }
                     }
                 }
 
-                let address = address.ok_or(de::Error::missing_field("address"))?;
-                let seq_no = seq_no.ok_or( de::Error::missing_field("seqNo"))?;
+                let address: String = address.ok_or(de::Error::missing_field("address"))?;
 
+                // a txo given as address holds the seqNo, it is decoded checked like any other txo
+                if address.starts_with(TXO_QUALIFIER) {
+                    let txo = parse_txo(&address).map_err(de::Error::custom)?;
+                    if let Some(seq_no) = seq_no {
+                        if seq_no != txo.seq_no {
+                            return Err(de::Error::custom(format!("The seqNo {} doesn't match the seqNo {} of the txo", seq_no, txo.seq_no)));
+                        }
+                    }
+                    return Ok(Input::new(txo.address, txo.seq_no));
+                }
+
+                let seq_no = seq_no.ok_or( de::Error::missing_field("seqNo"))?;
                 return Ok(Input::new(address, seq_no));
             }
         }
 
-        const FIELDS: &'static [&'static str] = &["address", "seqNo"];
+        const FIELDS: &'static [&'static str] = &["address", "source", "seqNo"];
         return deserializer.deserialize_any(InputVisitor);
     }
 }
 
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+/*
+    Decode a txo. A mangled txo isn't repaired here, repairing tries thousands
+    of candidates, the caller can ask for it with `repair_txo_handler`.
+*/
+fn parse_txo(txo_str: &str) -> Result<TXO, String> {
+    TXO::from_libindy_string(txo_str).map_err(|error| format!("Error when deserializing txo: {}", error))
+}
 
 #[cfg(test)]
 mod input_tests {
     use serde_json;
 
-    use logic::input::{Input, InputConfig};
+    use logic::field_names::{self, FieldNames};
+    use logic::input::{dedupe_inputs, DuplicateInputs, DuplicateInputError, Input, InputConfig};
     use logic::parsers::common::TXO;
     use utils::json_conversion::{JsonDeserialize, JsonSerialize};
     use utils::base58::IntoBase58;
@@ -209,6 +322,38 @@ mod input_tests {
         assert_valid_serialize(input, expected);
     }
 
+    #[test]
+    fn serialize_input_with_source() {
+        let input = Input::new(String::from("a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7"), 5);
+        let expected = json!({"source": "a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7", "seqNo":5});
+        field_names::with_field_names(FieldNames::Recipient, || assert_valid_serialize(input, expected));
+    }
+
+    #[test]
+    fn deserialize_input_object_with_source() {
+        let json = json!({"source": "pay:sov:a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7", "seqNo": 30});
+        assert_valid_deserialize(json, valid_input());
+    }
+
+    #[test]
+    fn deserialize_truncated_txo_not_repaired() {
+        let txo = TXO::new("pay:sov:a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7", 30).to_libindy_string().unwrap();
+        let truncated = json!(&txo[..txo.len() - 1]);
+        let invalid = Input::from_json(&json_value_to_string(truncated)).unwrap_err().to_string();
+        assert!(invalid.contains("Error when deserializing txo"));
+        assert!(!invalid.contains(&txo));
+    }
+
+    #[test]
+    fn deserialize_txo_as_address_is_checked() {
+        let txo = TXO::new("pay:sov:a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7", 30).to_libindy_string().unwrap();
+        assert_valid_deserialize(json!({"source": txo}), valid_input());
+        assert_invalid_deserialize(json!({"address": txo, "seqNo": 31}), "doesn't match the seqNo 30");
+
+        let tampered = format!("{}{}", &txo[..txo.len() - 1], if txo.ends_with('2') { '3' } else { '2' });
+        assert_invalid_deserialize(json!({"address": tampered, "seqNo": 30}), "bad checksum");
+    }
+
     // this test ensures that the deserialized JSON is serialized correctly
     #[test]
     fn serializing_payload_struct_output_config() {
@@ -220,4 +365,26 @@ mod input_tests {
         };
         assert_eq!(fee.to_json().unwrap(), r#"{"ver":1,"inputs":[{"address":"a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7","seqNo":30}]}"#);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn dedupe_keeps_first_occurrence() {
+        let other = Input::new(String::from("pay:sov:a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7"), 31);
+        let unqualified = Input::new(String::from("a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7"), 30);
+        let inputs = vec![valid_input(), other.clone(), unqualified, valid_input()];
+
+        assert_eq!(vec![valid_input(), other], dedupe_inputs(inputs, DuplicateInputs::Dedupe).unwrap());
+    }
+
+    #[test]
+    fn reject_duplicate_input() {
+        let config = InputConfig { ver: 1, inputs: vec![valid_input(), valid_input()] };
+        let expected = DuplicateInputError { address: valid_input().address, seq_no: 30 };
+        assert_eq!(expected, config.validate(DuplicateInputs::Reject).unwrap_err());
+    }
+
+    #[test]
+    fn validate_without_duplicates() {
+        let config = InputConfig { ver: 1, inputs: vec![valid_input()] };
+        assert_eq!(vec![valid_input()], config.validate(DuplicateInputs::Reject).unwrap().inputs);
+    }
+}
@@ -0,0 +1,279 @@
+/*!
+    The catalog of the error messages of libsovtoken.
+
+    Every message a [`SovtokenError`] carries comes from a [`MessageTemplate`]
+    of this module, with a stable id, e.g. `SOV-0009`, and an English text
+    with `{}` placeholders for its arguments. Ids are never reused or
+    renumbered, so wrappers can translate a message by its id and arguments,
+    and support can look it up whatever the language of the user.
+
+    The message of the last error reported on a thread is kept with its
+    error code, see [`last_error`]. `sovtoken_get_message_catalog_json`
+    returns the whole catalog to build translations from.
+
+    [`SovtokenError`]: ../error/enum.SovtokenError.html
+    [`MessageTemplate`]: struct.MessageTemplate.html
+    [`last_error`]: fn.last_error.html
+*/
+
+use std::cell::RefCell;
+
+use indy::ErrorCode;
+
+/**
+    A message of the catalog, before its arguments are filled in.
+*/
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageTemplate {
+    pub id: &'static str,
+    pub text: &'static str,
+}
+
+impl MessageTemplate {
+    /**
+        The message with the placeholders of the text replaced by `args`, in order.
+
+        ```
+            use sovtoken::logic::messages::TOO_MANY_INPUTS;
+
+            let message = TOO_MANY_INPUTS.with(&[String::from("100"), String::from("120")]);
+            assert_eq!("SOV-0009", message.id);
+            assert!(message.text.starts_with("A transfer can have 100 inputs, found 120."));
+        ```
+    */
+    pub fn with(&self, args: &[String]) -> Message {
+        let mut parts = self.text.split("{}");
+        let mut text = parts.next().unwrap_or("").to_string();
+        let mut args_iter = args.iter();
+        for part in parts {
+            text.push_str(args_iter.next().map(String::as_str).unwrap_or(""));
+            text.push_str(part);
+        }
+
+        Message { id: self.id.to_string(), text, args: args.to_vec() }
+    }
+
+    /**
+        The message of a text without placeholders.
+    */
+    pub fn message(&self) -> Message {
+        self.with(&[])
+    }
+}
+
+/**
+    A message of the catalog with its arguments.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub id: String,
+    pub text: String,
+    pub args: Vec<String>,
+}
+
+/**
+    The last error reported on a thread, with the code it was reported as.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LastError {
+    pub code: i32,
+    #[serde(flatten)]
+    pub message: Message,
+}
+
+pub const INVALID_CONFIG: MessageTemplate = MessageTemplate { id: "SOV-0001", text: "Invalid config: {}" };
+pub const MINT_WITHOUT_OUTPUTS: MessageTemplate = MessageTemplate { id: "SOV-0002", text: "A mint needs at least one output." };
+pub const INVALID_ADDRESS: MessageTemplate = MessageTemplate { id: "SOV-0003", text: "Invalid address {}." };
+pub const NO_FEE_TXN_TYPES: MessageTemplate = MessageTemplate { id: "SOV-0004", text: "No transaction types to get the fees of." };
+pub const TXN_TYPE_NOT_INTEGER: MessageTemplate = MessageTemplate { id: "SOV-0005", text: "Transaction type {} isn't an integer string." };
+pub const OTHER_PAYMENT_METHOD: MessageTemplate = MessageTemplate { id: "SOV-0006", text: "{} is not a {} address" };
+pub const OTHER_NETWORK: MessageTemplate = MessageTemplate { id: "SOV-0007", text: "{} is of the network {}, not {}" };
+pub const NOT_CANONICAL: MessageTemplate = MessageTemplate { id: "SOV-0008", text: "Inputs have to be sorted by address and seqNo, outputs by address and amount." };
+pub const TOO_MANY_INPUTS: MessageTemplate = MessageTemplate { id: "SOV-0009", text: "A transfer can have {} inputs, found {}. Consolidate the UTXOs or split the payment." };
+pub const TOO_MANY_OUTPUTS: MessageTemplate = MessageTemplate { id: "SOV-0010", text: "A transfer can have {} outputs, found {}. Split the payment over several requests." };
+pub const OPERATION_NOT_SERIALIZED: MessageTemplate = MessageTemplate { id: "SOV-0011", text: "The operation can't be serialized: {}" };
+pub const SUPPLY_EXCEEDED: MessageTemplate = MessageTemplate { id: "SOV-0012", text: "The outputs hold {} sovatoms, more than the supply of {}." };
+pub const PAYMENT_INSUFFICIENT_FUNDS: MessageTemplate = MessageTemplate { id: "SOV-0013", text: "Insufficient funds for the payment. Missing {} tokens for the outputs." };
+pub const FEES_INSUFFICIENT_FUNDS: MessageTemplate = MessageTemplate { id: "SOV-0014", text: "Insufficient funds for the fees. Missing {} tokens for the fees." };
+pub const EXTRA_FUNDS_UNALLOCATED: MessageTemplate = MessageTemplate { id: "SOV-0015", text: "Inputs exceed outputs and fees. {} tokens are not allocated to an output." };
+pub const INVALID_TXO: MessageTemplate = MessageTemplate { id: "SOV-0016", text: "Invalid txo: {}" };
+pub const BASE58_DECODING_FAILED: MessageTemplate = MessageTemplate { id: "SOV-0017", text: "Base58 decoding failed: {}" };
+pub const SET_FEES_EMPTY: MessageTemplate = MessageTemplate { id: "SOV-0018", text: "Set fees was empty." };
+pub const SET_FEES_KEY_NOT_INTEGER: MessageTemplate = MessageTemplate { id: "SOV-0019", text: "A Set fees key wasn't a integer string: {}" };
+pub const INVALID_SEED: MessageTemplate = MessageTemplate { id: "SOV-0020", text: "Invalid seed: {}" };
+pub const DUPLICATE_INPUT: MessageTemplate = MessageTemplate { id: "SOV-0021", text: "Invalid inputs: input {} with seqNo {} is given twice" };
+pub const DID_INVALID_LENGTH: MessageTemplate = MessageTemplate { id: "SOV-0022", text: "Invalid did: {} characters instead of 20 or 21" };
+pub const DID_INVALID_CHAR: MessageTemplate = MessageTemplate { id: "SOV-0023", text: "Invalid did: the character {} isn't alphanumeric" };
+pub const LEDGER_REFUSED: MessageTemplate = MessageTemplate { id: "SOV-0024", text: "The ledger refused the request: {}" };
+pub const INDY_ERROR: MessageTemplate = MessageTemplate { id: "SOV-0025", text: "libindy returned {}" };
+pub const EXTRA_TOO_LARGE: MessageTemplate = MessageTemplate { id: "SOV-0026", text: "The extra has {} bytes, more than the {} allowed." };
+pub const EXTRA_CONTROL_CHARACTER: MessageTemplate = MessageTemplate { id: "SOV-0027", text: "The extra has the control character {} at byte {}" };
+pub const EXTRA_NOT_UTF8: MessageTemplate = MessageTemplate { id: "SOV-0028", text: "The extra isn't valid UTF-8 after byte {}" };
+pub const UNKNOWN_FIELD: MessageTemplate = MessageTemplate { id: "SOV-0029", text: "Unknown field {} in the {}, expected one of {}" };
+pub const UNKNOWN_FIELD_TYPO: MessageTemplate = MessageTemplate { id: "SOV-0030", text: "Unknown field {} in the {}, did you mean {}?" };
+pub const SEED_SHARES_THRESHOLD: MessageTemplate = MessageTemplate { id: "SOV-0031", text: "A seed is split into 2 to 255 shares with a threshold of 2 to the number of shares, not {} of {}." };
+pub const SEED_SHARE_INVALID: MessageTemplate = MessageTemplate { id: "SOV-0032", text: "The seed share at position {} is invalid: {}" };
+pub const SEED_SHARES_MISMATCH: MessageTemplate = MessageTemplate { id: "SOV-0033", text: "The seed shares are from different splits." };
+pub const SEED_SHARES_TOO_FEW: MessageTemplate = MessageTemplate { id: "SOV-0034", text: "{} different seed shares given, {} are needed." };
+pub const SEED_SHARES_CORRUPTED: MessageTemplate = MessageTemplate { id: "SOV-0035", text: "The seed combined from the shares doesn't match its checksum, a share is corrupted." };
+pub const NO_SYSTEM_RANDOM: MessageTemplate = MessageTemplate { id: "SOV-0036", text: "The random number generator of the system isn't available." };
+pub const TIMED_OUT: MessageTemplate = MessageTemplate { id: "SOV-0037", text: "libindy didn't complete {} within {} ms." };
+pub const UNSUPPORTED_PROTOCOL_VERSION: MessageTemplate = MessageTemplate { id: "SOV-0038", text: "Protocol version {} isn't supported, expected one of {}" };
+pub const PROTOCOL_VERSION_MISMATCH: MessageTemplate = MessageTemplate { id: "SOV-0039", text: "The request has protocol version {}, the pool of handle {} speaks {}." };
+pub const UNSUPPORTED_ADDRESS_VERSION: MessageTemplate = MessageTemplate { id: "SOV-0040", text: "The address is of key type {} (version {}), only Ed25519 addresses can be signed for." };
+pub const SIGNED_FOR_OTHER_PROTOCOL_VERSION: MessageTemplate = MessageTemplate { id: "SOV-0041", text: "The request is signed with protocol version {}, build it with protocol version {} to send it with indy-vdr." };
+pub const ADDRESS_ALREADY_EXISTS: MessageTemplate = MessageTemplate { id: "SOV-0042", text: "The wallet already holds the key of the seed, of the payment address {}. Set reuse_existing to get the address instead." };
+pub const ZERO_AMOUNT_OUTPUT: MessageTemplate = MessageTemplate { id: "SOV-0043", text: "The output to {} is of 0 tokens. Set allow_zero_outputs to build outputs of 0 tokens." };
+pub const NO_SIGNER: MessageTemplate = MessageTemplate { id: "SOV-0044", text: "The library runs stateless and no signer is registered, register one with sovtoken_register_signer." };
+pub const SIGNER_FAILED: MessageTemplate = MessageTemplate { id: "SOV-0045", text: "The signer failed to sign with the key {}, it returned {}." };
+pub const STATELESS_KEY_CREATION: MessageTemplate = MessageTemplate { id: "SOV-0046", text: "The library runs stateless and creates no keys, get the address of a key with create_payment_address_from_key_handler." };
+pub const REGISTRATION_FAILED: MessageTemplate = MessageTemplate { id: "SOV-0047", text: "libindy refused to register {} with {}, {}." };
+pub const RATE_LIMITED: MessageTemplate = MessageTemplate { id: "SOV-0048", text: "{} of wallet {} is limited to {} calls per second, the wallet is busy. Try again later." };
+pub const ADDRESS_VECTOR_FAILED: MessageTemplate = MessageTemplate { id: "SOV-0049", text: "The library fails the address test vector {}: {}." };
+pub const UNKNOWN_CONTEXT: MessageTemplate = MessageTemplate { id: "SOV-0050", text: "{} isn't the handle of a context, or the context was destroyed." };
+pub const DEFAULT_CONTEXT_DESTROYED: MessageTemplate = MessageTemplate { id: "SOV-0051", text: "The default context, the context of the legacy api, can't be destroyed." };
+// SOV-0052 and SOV-0053 were of the state proof check of GET_FEES replies, they are retired
+pub const WRITE_TXN_TYPE: MessageTemplate = MessageTemplate { id: "SOV-0054", text: "The request isn't a {} request, of txn type {}, its txn type is {}." };
+pub const FEE_NOT_KNOWN: MessageTemplate = MessageTemplate { id: "SOV-0055", text: "The fee of {} isn't known. Parse a GET_FEES reply or set the fee schedule first." };
+pub const CANCELLED: MessageTemplate = MessageTemplate { id: "SOV-0056", text: "{} was cancelled before libindy called back." };
+pub const FRAMED_FOR_OTHER_PROTOCOL_VERSION: MessageTemplate = MessageTemplate { id: "SOV-0057", text: "The request has protocol version {}, the library frames requests with protocol version {}." };
+
+/**
+    Every message of the catalog, by id.
+*/
+pub static CATALOG: &[MessageTemplate] = &[
+    INVALID_CONFIG,
+    MINT_WITHOUT_OUTPUTS,
+    INVALID_ADDRESS,
+    NO_FEE_TXN_TYPES,
+    TXN_TYPE_NOT_INTEGER,
+    OTHER_PAYMENT_METHOD,
+    OTHER_NETWORK,
+    NOT_CANONICAL,
+    TOO_MANY_INPUTS,
+    TOO_MANY_OUTPUTS,
+    OPERATION_NOT_SERIALIZED,
+    SUPPLY_EXCEEDED,
+    PAYMENT_INSUFFICIENT_FUNDS,
+    FEES_INSUFFICIENT_FUNDS,
+    EXTRA_FUNDS_UNALLOCATED,
+    INVALID_TXO,
+    BASE58_DECODING_FAILED,
+    SET_FEES_EMPTY,
+    SET_FEES_KEY_NOT_INTEGER,
+    INVALID_SEED,
+    DUPLICATE_INPUT,
+    DID_INVALID_LENGTH,
+    DID_INVALID_CHAR,
+    LEDGER_REFUSED,
+    INDY_ERROR,
+    EXTRA_TOO_LARGE,
+    EXTRA_CONTROL_CHARACTER,
+    EXTRA_NOT_UTF8,
+    UNKNOWN_FIELD,
+    UNKNOWN_FIELD_TYPO,
+    SEED_SHARES_THRESHOLD,
+    SEED_SHARE_INVALID,
+    SEED_SHARES_MISMATCH,
+    SEED_SHARES_TOO_FEW,
+    SEED_SHARES_CORRUPTED,
+    NO_SYSTEM_RANDOM,
+    TIMED_OUT,
+    UNSUPPORTED_PROTOCOL_VERSION,
+    PROTOCOL_VERSION_MISMATCH,
+    UNSUPPORTED_ADDRESS_VERSION,
+    SIGNED_FOR_OTHER_PROTOCOL_VERSION,
+    ADDRESS_ALREADY_EXISTS,
+    ZERO_AMOUNT_OUTPUT,
+    NO_SIGNER,
+    SIGNER_FAILED,
+    STATELESS_KEY_CREATION,
+    REGISTRATION_FAILED,
+    RATE_LIMITED,
+    ADDRESS_VECTOR_FAILED,
+    UNKNOWN_CONTEXT,
+    DEFAULT_CONTEXT_DESTROYED,
+    WRITE_TXN_TYPE,
+    FEE_NOT_KNOWN,
+    CANCELLED,
+    FRAMED_FOR_OTHER_PROTOCOL_VERSION,
+];
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<LastError>> = RefCell::new(None);
+}
+
+/**
+    Keep `message` as the last error of the thread, reported as `error_code`.
+*/
+pub fn set_last_error(error_code: ErrorCode, message: Message) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(LastError { code: error_code as i32, message }));
+}
+
+/**
+    Forget the last error of the thread.
+
+    The api calls start with it, but `sovtoken_get_last_error_json` and
+    `sovtoken_free_json`, so a call that succeeds leaves no error of an
+    earlier call behind.
+*/
+pub fn clear_last_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+}
+
+/**
+    The last error with a message reported on this thread, by the last api
+    call.
+
+    Errors without a message, e.g. a malformed json, don't set it, so
+    compare its code with the code of the failed call before showing it.
+*/
+pub fn last_error() -> Option<LastError> {
+    LAST_ERROR.with(|last| last.borrow().clone())
+}
+
+
+#[cfg(test)]
+mod messages_tests {
+    use std::collections::HashSet;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn ids_are_unique() {
+        let ids: HashSet<&str> = CATALOG.iter().map(|template| template.id).collect();
+        assert_eq!(CATALOG.len(), ids.len());
+        assert!(CATALOG.iter().all(|template| template.id.starts_with("SOV-") && template.id.len() == 8));
+    }
+
+    #[test]
+    fn placeholders_filled_in_order() {
+        let message = OTHER_NETWORK.with(&[String::from("pay:sov:a:b"), String::from("a"), String::from("c")]);
+        assert_eq!("pay:sov:a:b is of the network a, not c", message.text);
+        assert_eq!(3, message.args.len());
+    }
+
+    #[test]
+    fn missing_args_are_empty() {
+        assert_eq!("Invalid address .", INVALID_ADDRESS.message().text);
+    }
+
+    #[test]
+    fn last_error_per_thread() {
+        set_last_error(ErrorCode::CommonInvalidStructure, MINT_WITHOUT_OUTPUTS.message());
+
+        let last = last_error().unwrap();
+        assert_eq!(ErrorCode::CommonInvalidStructure as i32, last.code);
+        assert_eq!("SOV-0002", last.message.id);
+        assert!(thread::spawn(last_error).join().unwrap().is_none());
+    }
+
+    #[test]
+    fn last_error_cleared() {
+        set_last_error(ErrorCode::CommonInvalidStructure, MINT_WITHOUT_OUTPUTS.message());
+        clear_last_error();
+
+        assert!(last_error().is_none());
+    }
+}
@@ -3,10 +3,14 @@ use libc::c_char;
 use logic::address;
 use logic::config::output_mint_config::MintRequest;
 use logic::did::Did;
-use serde_json;
+use logic::extra::extra_from_char_ptr;
 use utils::constants::general::{JsonCallback, JsonCallbackUnwrapped};
 use utils::ffi_support::{string_from_char_ptr};
-use logic::output::Outputs;
+use logic::config::library_config::library_config;
+use logic::output::{self, Outputs};
+use logic::operation::Operation;
+use logic::schema::{validate_if_strict, Schema};
+use logic::unknown_fields;
 
 type DeserializedArguments<'a> = (Option<Did<'a>>, Outputs, Option<String>, JsonCallbackUnwrapped);
 
@@ -32,11 +36,14 @@ pub fn deserialize_inputs<'a>(
         .ok_or(ErrorCode::CommonInvalidStructure)?;
     debug!("Converted outputs_json pointer to string >>> {:?}", outputs_json);
 
-    let outputs: Outputs = serde_json::from_str(&outputs_json)
-        .or(Err(ErrorCode::CommonInvalidStructure))?;
+    validate_if_strict(Schema::Outputs, &outputs_json)?;
+
+    let outputs: Outputs = unknown_fields::from_json(&outputs_json, "outputs")?;
     debug!("Deserialized output_json >>> {:?}", outputs);
 
-    let extra = string_from_char_ptr(extra);
+    output::check_amounts(&outputs, library_config().allow_zero_outputs).map_err(ErrorCode::from)?;
+
+    let extra = extra_from_char_ptr(extra)?;
     debug!("Deserialized extra >>> {:?}", extra);
 
     trace!("logic::minting::deserialize_inputs << did: {:?}, outputs: {:?}, extra: {:?}", did, outputs, extra);
@@ -57,6 +64,7 @@ pub fn build_mint_request(
     trace!("Stripped pay:sov: from outputs");
 
     let mint_request = MintRequest::from_config(outputs, did, extra);
+    mint_request.operation.validate()?;
     info!("Built a mint request >>> {:?}", mint_request);
 
     let ptr = mint_request.serialize_to_pointer()
@@ -69,6 +77,7 @@ pub fn build_mint_request(
 #[cfg(test)]
 mod test_build_mint_request {
     use super::*;
+    use serde_json;
     use std::ptr::null;
     use logic::output::Output;
     use utils::base58::IntoBase58;
@@ -5,19 +5,77 @@
 // KEEP THESE IN ALPHABETICAL ORDER
 
 pub mod address;
+pub mod address_book;
+pub mod address_checksum;
+pub mod address_proof;
+pub mod address_vectors;
+pub mod address_version;
+pub mod aggregate_balance;
+pub mod amount_format;
 pub mod api_internals;
+pub mod audit_log;
+pub mod balance;
+pub mod batch_payment;
 pub mod build_payment;
+pub mod canonical_order;
+pub mod compression;
 pub mod config;
+pub mod context;
+pub mod correlation;
+#[cfg(feature = "devnet")]
+pub mod devnet;
+pub mod diagnostics;
 pub mod did;
+pub mod error;
+pub mod extra;
+pub mod fee_attestation;
+pub mod fee_waiver;
+pub mod field_names;
 pub mod hash;
+pub mod history;
 pub mod indy_sdk_api;
+pub mod indy_vdr;
+pub mod inclusion_proof;
 pub mod input;
 pub mod output;
 pub mod minting;
+pub mod operation;
+pub mod optimistic_updates;
+pub mod payment_intent;
+pub mod payment_proof;
+pub mod pending_txn;
+pub mod payment_template;
+pub mod payment_uri;
 pub mod payments;
+pub mod pools;
+pub mod public_export;
+pub mod receipt;
+pub mod reconcile;
+pub mod refresh_utxos;
+pub mod registration;
 pub mod parsers;
 pub mod request;
+pub mod request_builder;
+pub mod request_hash;
+pub mod request_signatures;
+pub mod request_size;
+#[cfg(test)]
+mod roundtrip_tests;
+pub mod schema;
+pub mod seq_no_store;
+pub mod services;
 pub mod set_fees;
+pub mod signing_payload;
+pub mod simulation;
+pub mod state;
+pub mod stateless;
+pub mod storage;
+pub mod transfer_limits;
+pub mod txo_status;
 pub mod type_aliases;
+pub mod unknown_fields;
+pub mod utxo_cache;
 pub mod verify;
+pub mod wallet_handles;
+pub mod write_with_fees;
 pub mod xfer_payload;
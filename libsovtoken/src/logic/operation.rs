@@ -0,0 +1,74 @@
+/*!
+    The operations of the token ledger requests.
+
+    Each operation, e.g. [`MintRequest`] or [`SetFees`], knows its
+    transaction type and how to check itself, so its json is built the
+    same way for all of them.
+
+    [`MintRequest`]: ../config/output_mint_config/struct.MintRequest.html
+    [`SetFees`]: ../config/set_fees_config/struct.SetFees.html
+*/
+
+use serde::Serialize;
+use serde_json;
+
+use logic::error::SovtokenError;
+use logic::messages;
+
+/**
+    The `operation` of a ledger request.
+*/
+pub trait Operation: Serialize + Sized {
+    /**
+        The transaction type, e.g. `"10000"` for MINT_PUBLIC.
+    */
+    const TYPE_CODE: &'static str;
+
+    /**
+        Check the operation can be sent to the ledger.
+    */
+    fn validate(&self) -> Result<(), SovtokenError> {
+        Ok(())
+    }
+
+    /**
+        The json of the operation alone.
+    */
+    fn to_operation_json(&self) -> Result<String, SovtokenError> {
+        self.validate()?;
+        serde_json::to_string(self)
+            .map_err(|error| SovtokenError::InvalidState(messages::OPERATION_NOT_SERIALIZED.with(&[error.to_string()])))
+    }
+}
+
+
+#[cfg(test)]
+mod operation_tests {
+    use std::collections::HashMap;
+
+    use indy::ErrorCode;
+    use logic::config::get_fees_config::GetFeesRequest;
+    use logic::config::set_fees_config::SetFees;
+    use utils::constants::txn_types::{GET_FEES, SET_FEES};
+    use super::*;
+
+    fn type_of<O: Operation>(operation: &O) -> String {
+        let json: serde_json::Value = serde_json::from_str(&operation.to_operation_json().unwrap()).unwrap();
+        json["type"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn type_code_is_serialized() {
+        assert_eq!(GET_FEES, type_of(&GetFeesRequest::new()));
+
+        let mut fees = HashMap::new();
+        fees.insert(String::from("1"), 10);
+        assert_eq!(SET_FEES, type_of(&SetFees::new(fees)));
+    }
+
+    #[test]
+    fn invalid_operation_is_not_serialized() {
+        let error = SetFees::new(HashMap::new()).to_operation_json().unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error.error_code());
+    }
+}
@@ -0,0 +1,303 @@
+/*!
+    Optimistic updates of the UTXOs returned to the application.
+
+    Once a payment is submitted, its inputs are spent as far as the
+    application is concerned, but a GET_UTXO reply of a state root from
+    before the payment still lists them. [`record`] keeps the inputs a
+    submitted request spends, by the canonical hash of the request (see
+    `logic::request_hash`) and those inputs, and
+    `parse_get_utxo_response_handler` leaves the spent inputs out of the
+    UTXOs it returns, see [`apply`]. The hash leaves the fees out: a write
+    paid again with other UTXOs keeps its hash, but is another update. The
+    outputs of the request aren't added: they have no seqNo until the ledger
+    ordered the request, and a GET_UTXO reply lists them from then on.
+
+    When the request is rejected after all, the update is wrong: [`rollback`]
+    drops it, so its inputs are unspent again. `submit_pending_txn_handler`
+    records the requests it submits and rolls back a REQNACK or REJECT by
+    itself; `rollback_transaction_handler` rolls back a rejection seen by the
+    application, e.g. after a reorg.
+
+    The updates of the requests submitted to a pool are kept with the state
+    of its network, see `logic::pools`, so a payment on one network doesn't
+    hide the UTXOs of another, and are dropped with it. A reply parsed
+    without a pool, as libindy parses them, leaves out the inputs spent on
+    any network.
+
+    The updates are kept in memory, the oldest of a network are dropped
+    past [`MAX_OPTIMISTIC_UPDATES`]. The UTXOs used by the library itself,
+    e.g. to reconcile, are always the ones of the ledger.
+
+    [`record`]: fn.record.html
+    [`apply`]: fn.apply.html
+    [`rollback`]: fn.rollback.html
+    [`MAX_OPTIMISTIC_UPDATES`]: constant.MAX_OPTIMISTIC_UPDATES.html
+*/
+
+use std::collections::VecDeque;
+
+use indy::{ErrorCode, IndyHandle};
+
+use logic::address::{address_from_unqualified_address, unqualified_part};
+use logic::parsers::common::TXO;
+use logic::parsers::parse_get_utxo_response::ParseGetUtxoReply;
+use logic::pending_txn::request_inputs;
+use logic::request_hash::request_hash;
+use logic::state::state;
+use logic::type_aliases::TxnSeqNo;
+
+/**
+    The most updates kept for a network, the oldest are dropped first.
+*/
+pub const MAX_OPTIMISTIC_UPDATES: usize = 256;
+
+/**
+    The inputs a submitted request spends, with unqualified addresses.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptimisticUpdate {
+    pub request_hash: String,
+    pub spent: Vec<(String, TxnSeqNo)>,
+}
+
+/**
+    What rolling back a request undid.
+
+    `rolled_back` is false when no update of the request was kept, e.g. it
+    was already rolled back.
+*/
+#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Rollback {
+    pub request_hash: String,
+    pub rolled_back: bool,
+    pub restored: Vec<String>,
+}
+
+/**
+    Keep the update of a request submitted to `pool_handle`, replacing the
+    one of the same request paid with the same inputs, and return its hash.
+*/
+pub fn record(pool_handle: Option<IndyHandle>, request: &str) -> Result<String, ErrorCode> {
+    let request_hash = request_hash(request)?;
+    let update = OptimisticUpdate {
+        request_hash: request_hash.clone(),
+        spent: request_inputs(request)?,
+    };
+    debug!("Recording the update of request {} to pool {:?}: {} inputs spent", request_hash, pool_handle, update.spent.len());
+
+    let state = state();
+    let mut pools = state.pools.lock();
+    let mut unpooled = state.optimistic_updates.lock();
+    let updates = match pool_handle {
+        Some(pool_handle) => &mut pools.network(pool_handle).optimistic_updates,
+        None => &mut *unpooled,
+    };
+    updates.retain(|kept| *kept != update);
+    updates.push_back(update);
+    while updates.len() > MAX_OPTIMISTIC_UPDATES {
+        updates.pop_front();
+    }
+    Ok(request_hash)
+}
+
+/**
+    Drop the update of a rejected request, whatever pool it was submitted to.
+
+    `request` is the request as it was paid: the update of the same request
+    paid with other inputs is kept.
+*/
+pub fn rollback(request: &str) -> Result<Rollback, ErrorCode> {
+    let request_hash = request_hash(request)?;
+    let spent = request_inputs(request)?;
+    let update = {
+        let state = state();
+        let mut pools = state.pools.lock();
+        let mut unpooled = state.optimistic_updates.lock();
+        let mut removed = remove(&mut unpooled, &request_hash, &spent);
+        for updates in pools.optimistic_updates_mut() {
+            removed = removed.or_else(|| remove(updates, &request_hash, &spent));
+        }
+        removed
+    };
+
+    let update = match update {
+        Some(update) => update,
+        None => {
+            debug!("No update of request {} to roll back", request_hash);
+            return Ok(Rollback { request_hash, rolled_back: false, restored: Vec::new() });
+        }
+    };
+
+    let restored = update.spent.into_iter()
+        .map(|(address, seq_no)| TXO { address: address_from_unqualified_address(&address)?, seq_no }.to_libindy_string())
+        .collect::<Result<Vec<String>, ErrorCode>>()?;
+
+    info!("Rolled back request {}: {} inputs restored", request_hash, restored.len());
+    Ok(Rollback { request_hash, rolled_back: true, restored })
+}
+
+/**
+    Leave out of `utxos`, of the network of `pool_handle`, the ones spent by
+    a recorded request. Without a pool, the ones spent on any network are
+    left out.
+*/
+pub fn apply(pool_handle: Option<IndyHandle>, utxos: ParseGetUtxoReply) -> ParseGetUtxoReply {
+    let spent: Vec<(String, TxnSeqNo)> = {
+        let state = state();
+        let mut pools = state.pools.lock();
+        let unpooled = state.optimistic_updates.lock();
+        let spent_by = |updates: &VecDeque<OptimisticUpdate>| updates.iter()
+            .flat_map(|update| update.spent.iter().cloned())
+            .collect::<Vec<(String, TxnSeqNo)>>();
+        match pool_handle {
+            Some(pool_handle) => spent_by(&pools.network(pool_handle).optimistic_updates),
+            None => pools.optimistic_updates_mut()
+                .flat_map(|updates| spent_by(&*updates))
+                .chain(spent_by(&*unpooled))
+                .collect(),
+        }
+    };
+    if spent.is_empty() {
+        return utxos;
+    }
+
+    utxos.into_iter()
+        .filter(|utxo| {
+            let txo = match TXO::from_libindy_string(&utxo.source) {
+                Ok(txo) => (unqualified_part(&txo.address).to_string(), txo.seq_no),
+                Err(_) => return true,
+            };
+            !spent.contains(&txo)
+        })
+        .collect()
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn remove(updates: &mut VecDeque<OptimisticUpdate>, request_hash: &str, spent: &[(String, TxnSeqNo)]) -> Option<OptimisticUpdate> {
+    let position = updates.iter().position(|kept| kept.request_hash == request_hash && kept.spent == spent);
+    position.and_then(|position| updates.remove(position))
+}
+
+
+#[cfg(test)]
+mod optimistic_updates_tests {
+    use logic::config::library_config::LibraryConfig;
+    use logic::parsers::parse_get_utxo_response::UTXO;
+    use utils::test::context::in_own_context;
+    use super::*;
+
+    static PAYER: &'static str = "pay:sov:E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm";
+    static PAYEE: &'static str = "pay:sov:TKe9eXtchV71J2qXX5HwP8rbkTBStnEEkMwQkHie265VtRSbs";
+
+    // the updates are shared by the tests, each uses its own seqNos
+    fn request(seq_no: TxnSeqNo) -> String {
+        json!({
+            "operation": {
+                "type": "10001",
+                "inputs": [{"address": unqualified_part(PAYER), "seqNo": seq_no}],
+                "outputs": [{"address": unqualified_part(PAYEE), "amount": 10}],
+                "signatures": ["signature"]
+            },
+            "reqId": seq_no
+        }).to_string()
+    }
+
+    // a write paid with the UTXO `seq_no`, its hash doesn't depend on it
+    fn paid_request(seq_no: TxnSeqNo) -> String {
+        json!({
+            "operation": {"type": "1", "dest": "Th7MpTaRZVRYnPiabds81Y"},
+            "reqId": 6000,
+            "fees": [[{"address": unqualified_part(PAYER), "seqNo": seq_no}], [], ["signature"]]
+        }).to_string()
+    }
+
+    fn utxo(seq_no: TxnSeqNo) -> UTXO {
+        UTXO {
+            payment_address: PAYER.to_string(),
+            source: TXO { address: PAYER.to_string(), seq_no }.to_libindy_string().unwrap(),
+            amount: 10,
+            extra: String::new(),
+        }
+    }
+
+    #[test]
+    fn spent_inputs_are_left_out() {
+        record(None, &request(1001)).unwrap();
+        assert_eq!(vec![utxo(1002)], apply(None, vec![utxo(1001), utxo(1002)]));
+    }
+
+    #[test]
+    fn rollback_restores_the_inputs() {
+        let hash = record(None, &request(2001)).unwrap();
+
+        let rollback = rollback(&request(2001)).unwrap();
+
+        assert_eq!(hash, rollback.request_hash);
+        assert!(rollback.rolled_back);
+        assert_eq!(vec![utxo(2001).source], rollback.restored);
+        assert_eq!(vec![utxo(2001)], apply(None, vec![utxo(2001)]));
+    }
+
+    #[test]
+    fn updates_are_kept_by_network() {
+        in_own_context(LibraryConfig::default(), || {
+            state().pools.lock().register(1, "testnet");
+            state().pools.lock().register(2, "mainnet");
+            record(Some(1), &request(4001)).unwrap();
+
+            assert!(apply(Some(1), vec![utxo(4001)]).is_empty());
+            assert_eq!(vec![utxo(4001)], apply(Some(2), vec![utxo(4001)]));
+            assert!(apply(None, vec![utxo(4001)]).is_empty());
+
+            assert!(rollback(&request(4001)).unwrap().rolled_back);
+            assert_eq!(vec![utxo(4001)], apply(Some(1), vec![utxo(4001)]));
+        });
+    }
+
+    #[test]
+    fn updates_are_dropped_with_the_network() {
+        in_own_context(LibraryConfig::default(), || {
+            record(Some(3), &request(5001)).unwrap();
+            assert!(state().pools.lock().clear(3));
+            assert_eq!(vec![utxo(5001)], apply(Some(3), vec![utxo(5001)]));
+        });
+    }
+
+    #[test]
+    fn request_paid_again_with_other_inputs() {
+        let first = record(None, &paid_request(6001)).unwrap();
+        let second = record(None, &paid_request(6002)).unwrap();
+
+        assert_eq!(first, second);
+        assert!(apply(None, vec![utxo(6001), utxo(6002)]).is_empty());
+    }
+
+    #[test]
+    fn rollback_of_a_request_paid_again() {
+        record(None, &paid_request(7001)).unwrap();
+        record(None, &paid_request(7002)).unwrap();
+
+        let rollback = rollback(&paid_request(7002)).unwrap();
+
+        assert!(rollback.rolled_back);
+        assert_eq!(vec![utxo(7002).source], rollback.restored);
+        assert_eq!(vec![utxo(7002)], apply(None, vec![utxo(7001), utxo(7002)]));
+    }
+
+    #[test]
+    fn rollback_of_an_unknown_request() {
+        let rollback = rollback(&request(3001)).unwrap();
+        assert!(!rollback.rolled_back);
+        assert!(rollback.restored.is_empty());
+    }
+
+    #[test]
+    fn rollback_of_an_invalid_request() {
+        assert_eq!(ErrorCode::CommonInvalidStructure, rollback("[]").unwrap_err());
+    }
+}
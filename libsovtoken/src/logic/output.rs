@@ -2,8 +2,13 @@
     Payment Output
 */
 
-use serde::{de, Deserialize};
+use serde::{de, Deserialize, Serialize, Serializer};
+use serde::ser::SerializeStruct;
 use std::fmt;
+use logic::amount_format::Amount;
+use logic::error::SovtokenError;
+use logic::field_names;
+use logic::messages;
 use logic::type_aliases::TokenAmount;
 
 pub type Outputs = Vec<Output>;
@@ -16,6 +21,7 @@ pub type Outputs = Vec<Output>;
  * [`Outputs`]: Output
  */
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct OutputConfig {
     pub ver: u8,
     pub outputs: Outputs,
@@ -52,8 +58,13 @@ pub struct OutputConfig {
     let output = Output::from_json(json);
     ```
 
+    The address can also be given as `recipient`, and the amount as a
+    decimal string, see [`amount_format`].
+
     # Serialization
-    When Output is serialized, it is always serialized as an array:
+    When Output is serialized, it is always serialized as an object. The
+    address is emitted as `address` or `recipient`, see [`field_names`],
+    and the amount as a number or a string, see [`amount_format`].
 
     ```
     use sovtoken::utils::json_conversion::JsonSerialize;
@@ -64,10 +75,11 @@ pub struct OutputConfig {
     assert_eq!(json, r#"{"address":"pay:sov:a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7","amount":5}"#);
     ```
 
+    [`field_names`]: ../field_names/index.html
+    [`amount_format`]: ../amount_format/index.html
 */
-#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Output {
-    #[serde(rename = "address")]
     pub recipient: String,
     pub amount: TokenAmount
 }
@@ -78,6 +90,15 @@ impl Output {
     }
 }
 
+impl Serialize for Output {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut output = serializer.serialize_struct("Output", 2)?;
+        output.serialize_field(field_names::current().output_address(), &self.recipient)?;
+        output.serialize_field("amount", &Amount(self.amount))?;
+        return output.end();
+    }
+}
+
 impl<'de> Deserialize<'de> for Output {
     fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Output, D::Error> {
         struct OutputVisitor;
@@ -91,7 +112,7 @@ impl<'de> Deserialize<'de> for Output {
 
             fn visit_map<V: de::MapAccess<'de>>(self, mut map: V) -> Result<Output, V::Error> {
                 let mut address = None;
-                let mut amount = None;
+                let mut amount: Option<Amount> = None;
 
                 while let Some(key) = map.next_key()? {
                     match key {
@@ -105,7 +126,7 @@ impl<'de> Deserialize<'de> for Output {
                 let address = address.ok_or(de::Error::missing_field("recipient or address"))?;
                 let amount = amount.ok_or_else(|| de::Error::missing_field("amount"))?;
 
-                return Ok(Output::new(address, amount));
+                return Ok(Output::new(address, amount.0));
             }
         }
 
@@ -114,11 +135,31 @@ impl<'de> Deserialize<'de> for Output {
     }
 }
 
+/**
+    Reject the outputs of 0 tokens, unless `allow_zero_outputs`.
+
+    An output of 0 tokens is usually a bug of the caller, e.g. an amount
+    lost converting from tokens, though some integrations use one as a
+    marker. The mint, payment and fee builders check their outputs with
+    the `allow_zero_outputs` of the library config.
+*/
+pub fn check_amounts(outputs: &Outputs, allow_zero_outputs: bool) -> Result<(), SovtokenError> {
+    if allow_zero_outputs {
+        return Ok(());
+    }
+    match outputs.iter().find(|output| output.amount == 0) {
+        Some(output) => Err(SovtokenError::InvalidStructure(messages::ZERO_AMOUNT_OUTPUT.with(&[output.recipient.clone()]))),
+        None => Ok(()),
+    }
+}
+
 
 #[cfg(test)]
 mod output_tests {
     use super::*;
     use serde_json;
+    use logic::amount_format::{with_amount_format, AmountFormat};
+    use logic::field_names::FieldNames;
     use utils::json_conversion::{JsonDeserialize, JsonSerialize};
 
 
@@ -179,6 +220,42 @@ mod output_tests {
         assert_valid_serialize(output, json);
     }
 
+    #[test]
+    fn serialize_output_with_recipient() {
+        let json = json!({
+                 "recipient":"a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7",
+                 "amount":10
+            });
+        field_names::with_field_names(FieldNames::Recipient, || assert_valid_serialize(output(), json));
+    }
+
+    #[test]
+    fn serialize_output_with_string_amount() {
+        let json = json!({
+                 "address":"a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7",
+                 "amount":"10"
+            });
+        with_amount_format(AmountFormat::String, || assert_valid_serialize(output(), json));
+    }
+
+    #[test]
+    fn deserialize_output_with_string_amount() {
+        let json = json!({
+                 "address":"a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7",
+                 "amount":"10"
+            });
+        assert_valid_deserialize(json, output());
+    }
+
+    #[test]
+    fn deserialize_output_with_recipient() {
+        let json = json!({
+                 "recipient":"a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7",
+                 "amount":10
+            });
+        assert_valid_deserialize(json, output());
+    }
+
     #[test]
     fn serializing_fee_struct_output_config() {
         let output = output();
@@ -189,4 +266,15 @@ mod output_tests {
         };
         assert_eq!(fee.to_json().unwrap(), r#"{"ver":1,"outputs":[{"address":"a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7","amount":10}]}"#);
     }
+
+    #[test]
+    fn zero_amount_outputs() {
+        let outputs = vec![output(), Output::new(String::from("pay:sov:marker"), 0)];
+        match check_amounts(&outputs, false) {
+            Err(SovtokenError::InvalidStructure(message)) => assert!(message.text.contains("pay:sov:marker")),
+            result => panic!("expected the zero output rejected, got {:?}", result),
+        }
+        assert!(check_amounts(&outputs, true).is_ok());
+        assert!(check_amounts(&vec![output()], false).is_ok());
+    }
 }
@@ -3,11 +3,16 @@
 use indy::ErrorCode;
 use libc::c_char;
 use utils::ffi_support::string_from_char_ptr;
+use utils::constants::general::TXO_QUALIFIER;
 use utils::constants::txn_fields::{RESULT, STATE_PROOF};
-use std::str;
+use std::error::Error;
+use std::fmt;
+use std::str::{self, FromStr};
 use serde_json;
 use serde_json::{Value as SJsonValue};
 use logic::address;
+use logic::amount_format;
+use logic::error::SovtokenError;
 use logic::type_aliases::{TokenAmount, TxnSeqNo};
 
 /**
@@ -24,21 +29,39 @@ pub enum ResponseOperations {
 /**
     UTXO is the structure for the data member utxo_json
 
-    used by [`ParsePaymentReply`], [`ParseResponseWithFeesReply`]
+    The receipt json emitted before receipts were versioned, a subset of
+    the [`Receipt`] the parsers emit now.
+
+    [`Receipt`]: ../../receipt/struct.Receipt.html
 */
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct UTXO {
     pub recipient: String,
     pub receipt: String,
+    #[serde(with = "amount_format")]
     pub amount: TokenAmount,
     pub extra: String,
 }
 
 /**
    TXO is the structure for the data member txo of UTXO structure
+
+   A TXO is passed around as `txo:sov:<base58check of its json>`. Wrapper
+   SDKs should use [`TXO::parse`] and `to_string` instead of encoding it
+   themselves; `new`, `parse`, `to_string`, `address` and `seq_no` are kept
+   stable across minor versions. The TXO is re-exported from the crate root.
+
+   ```
+   use sovtoken::TXO;
+   let txo = TXO::new("pay:sov:iTQzpdRdugkJ2gLD5vW5c159dncSL9jbAtu3WfPcb8qWD9bUd", 1);
+   let parsed = TXO::parse(&txo.to_string()).unwrap();
+   assert_eq!(1, parsed.seq_no());
+   ```
+
+   [`TXO::parse`]: #method.parse
 */
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct TXO {
     pub address: String,
@@ -47,12 +70,120 @@ pub struct TXO {
 
 
 impl TXO {
+    pub fn new(address: &str, seq_no: TxnSeqNo) -> TXO {
+        TXO { address: address.to_string(), seq_no }
+    }
+
+    /**
+        Decode a `txo:sov:` string.
+    */
+    pub fn parse(txo_str: &str) -> Result<TXO, TxoParseError> {
+        address::string_to_txo(txo_str)
+    }
+
+    /**
+        The qualified payment address the TXO belongs to.
+    */
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /**
+        The sequence number of the transaction which created the TXO.
+    */
+    pub fn seq_no(&self) -> TxnSeqNo {
+        self.seq_no
+    }
+
     pub fn to_libindy_string(&self) -> Result<String, ErrorCode> {
         address::txo_to_string(self)
     }
 
-    pub fn from_libindy_string(txo_str: &str) -> Result<Self, serde_json::Error> {
-        address::string_to_txo(txo_str)
+    pub fn from_libindy_string(txo_str: &str) -> Result<Self, TxoParseError> {
+        TXO::parse(txo_str)
+    }
+
+    /**
+        The nearest valid `txo:sov:` string of a mangled one, see [`repair_txo`].
+
+        [`repair_txo`]: ../../address/fn.repair_txo.html
+    */
+    pub fn suggest_repair(txo_str: &str) -> Option<String> {
+        address::repair_txo(txo_str)
+    }
+}
+
+/**
+    The `txo:sov:` string of the TXO.
+*/
+impl fmt::Display for TXO {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let txo_str = self.to_libindy_string().map_err(|_| fmt::Error)?;
+        f.write_str(&txo_str)
+    }
+}
+
+impl FromStr for TXO {
+    type Err = TxoParseError;
+
+    fn from_str(txo_str: &str) -> Result<TXO, TxoParseError> {
+        TXO::parse(txo_str)
+    }
+}
+
+/**
+    Why a string couldn't be decoded as a [`TXO`].
+
+    The `Display` text describes the problem in detail, so it can be passed
+    on in error messages.
+
+    [`TXO`]: ./struct.TXO.html
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxoParseError {
+    /// The string doesn't start with `txo:sov:`, holds the start of the string.
+    BadPrefix(String),
+    /// The string isn't valid base58.
+    BadBase58(String),
+    /// The base58 checksum doesn't match the decoded bytes.
+    BadChecksum(String),
+    /// The string is too short to hold a checksum, e.g. cut off when copied.
+    Truncated(String),
+    /// The decoded bytes aren't the json of a TXO.
+    BadJson(String),
+    /// The TXO json has a version this library doesn't know.
+    UnsupportedVersion(String),
+}
+
+impl fmt::Display for TxoParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &TxoParseError::BadPrefix(ref start) => write!(f, "txo should start with \"{}\", found \"{}\"", TXO_QUALIFIER, start),
+            &TxoParseError::BadBase58(ref detail) => write!(f, "txo is not valid base58: {}", detail),
+            &TxoParseError::BadChecksum(ref detail) => write!(f, "txo has a bad checksum: {}", detail),
+            &TxoParseError::Truncated(ref detail) => write!(f, "txo is truncated: {}", detail),
+            &TxoParseError::BadJson(ref detail) => write!(f, "txo is not valid json: {}", detail),
+            &TxoParseError::UnsupportedVersion(ref version) => write!(f, "txo has unsupported version {}", version),
+        }
+    }
+}
+
+impl Error for TxoParseError {
+    fn description(&self) -> &str {
+        match self {
+            &TxoParseError::BadPrefix(_) => "bad txo prefix",
+            &TxoParseError::BadBase58(_) => "bad txo base58",
+            &TxoParseError::BadChecksum(_) => "bad txo checksum",
+            &TxoParseError::Truncated(_) => "truncated txo",
+            &TxoParseError::BadJson(_) => "bad txo json",
+            &TxoParseError::UnsupportedVersion(_) => "unsupported txo version",
+        }
+    }
+}
+
+impl From<TxoParseError> for ErrorCode {
+    fn from(error: TxoParseError) -> ErrorCode {
+        ErrorCode::from(SovtokenError::from(error))
     }
 }
 
@@ -188,6 +319,27 @@ mod common_tests {
     use super::*;
     use std::ffi::CString;
 
+    static TXO_STR: &'static str = "txo:sov:fkjZEd8eTBnYJsw7m7twMph3UYD7j2SoWcDM45DkmRx8eq2SkQnzxoLxyMT1RBAat9x86MwXNJH88Pxf9u7JsM5m8ApXn3bvgbtS5cegZzNp7WmMSpWL";
+
+    #[test]
+    fn txo_to_string_and_parse() {
+        let txo = TXO::new("pay:sov:iTQzpdRdugkJ2gLD5vW5c159dncSL9jbAtu3WfPcb8qWD9bUd", 1);
+        assert_eq!(TXO_STR, txo.to_string());
+
+        let parsed: TXO = TXO_STR.parse().unwrap();
+        assert_eq!(txo, parsed);
+        assert_eq!("pay:sov:iTQzpdRdugkJ2gLD5vW5c159dncSL9jbAtu3WfPcb8qWD9bUd", parsed.address());
+        assert_eq!(1, parsed.seq_no());
+    }
+
+    #[test]
+    fn txo_parse_bad_prefix() {
+        match TXO::parse("utxo:sov:abc").unwrap_err() {
+            TxoParseError::BadPrefix(_) => (),
+            error => panic!("expected a bad prefix, found {:?}", error),
+        }
+    }
+
     pub fn test_invalid_json(invalid_json: &str) {
         let json_str = CString::new(invalid_json).unwrap();
         let json_str_ptr = json_str.as_ptr();
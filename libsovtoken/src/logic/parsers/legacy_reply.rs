@@ -0,0 +1,255 @@
+/*!
+    The write replies of the pools of protocol version 1.
+
+    Before indy-node 1.4 the `result` of a write reply is flat: the fields
+    of the operation, of the request and of the ledger side by side. From
+    protocol version 2 they are nested:
+
+    ```text
+    protocol version 1                  protocol version 2
+    result.type, result.<op field>      result.txn.type, result.txn.data.<op field>
+    result.identifier, result.reqId     result.txn.metadata.from, result.txn.metadata.reqId
+    result.signature(s)                 result.reqSignature.values
+    result.seqNo, result.txnTime        result.txnMetadata.seqNo, result.txnMetadata.txnTime
+    protocol_version                    protocolVersion
+    ```
+
+    The fees of a request are a transaction of their own, flat the same
+    way. [`nest_json`] rewrites a flat reply as a nested one, so the parsers
+    of write replies know one layout. A nested reply is returned as it is.
+
+    [`nest_json`]: fn.nest_json.html
+*/
+
+use indy::ErrorCode;
+use serde_json::{self, Map, Value};
+
+use logic::request::LEGACY_PROTOCOL_VERSION;
+use utils::constants::txn_fields::{FEES, RESULT};
+
+/**
+    The json of `reply_json` with a nested `result`.
+*/
+pub fn nest_json(reply_json: &str) -> Result<String, ErrorCode> {
+    let reply: Value = serde_json::from_str(reply_json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    if !is_legacy(&reply) {
+        return Ok(reply_json.to_string());
+    }
+    Ok(nest(reply).to_string())
+}
+
+/**
+    `reply` with a nested `result`.
+*/
+pub fn nest(reply: Value) -> Value {
+    let mut reply = match reply {
+        Value::Object(reply) => reply,
+        reply => return reply,
+    };
+
+    if !reply.contains_key("protocolVersion") {
+        if let Some(protocol_version) = reply.remove("protocol_version") {
+            reply.insert("protocolVersion".to_string(), protocol_version);
+        }
+    }
+
+    if let Some(result) = reply.remove(RESULT) {
+        let result = match result {
+            Value::Object(result) if is_flat(&result) => nest_result(result),
+            result => result,
+        };
+        reply.insert(RESULT.to_string(), result);
+    }
+    Value::Object(reply)
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn is_legacy(reply: &Value) -> bool {
+    reply.get("protocol_version").is_some()
+        || reply.get(RESULT).and_then(Value::as_object).map_or(false, is_flat)
+}
+
+// A transaction with its type and seqNo side by side, not in `txn` and `txnMetadata`.
+fn is_flat(txn: &Map<String, Value>) -> bool {
+    !txn.contains_key("txn") && txn.contains_key("type") && txn.contains_key("seqNo")
+}
+
+fn nest_result(mut data: Map<String, Value>) -> Value {
+    // the `fees` of a fee transaction is its amount, not a fee section
+    let fees = if data.get(FEES).map_or(false, |fees| !fees.is_number()) {
+        data.remove(FEES).unwrap_or(Value::Null)
+    } else {
+        Value::Null
+    };
+    let mut take = |key: &str| data.remove(key).unwrap_or(Value::Null);
+
+    let txn_type = take("type");
+    let from = take("identifier");
+    let req_id = take("reqId");
+    let digest = take("digest");
+    let signature = take("signature");
+    let signatures = take("signatures");
+    let seq_no = take("seqNo");
+    let txn_time = take("txnTime");
+    let root_hash = take("rootHash");
+    let audit_path = take("auditPath");
+
+    let values = signature_values(&from, &signature, &signatures, data.get("inputs"));
+    let digest = if digest.is_null() { json!("") } else { digest };
+
+    let mut result = json!({
+        "txn": {
+            "type": txn_type,
+            "protocolVersion": LEGACY_PROTOCOL_VERSION,
+            "metadata": {"digest": digest, "from": from, "reqId": req_id},
+            "data": Value::Object(data),
+        },
+        "txnMetadata": {"seqNo": seq_no, "txnTime": txn_time},
+        "reqSignature": {"type": "ED25519", "values": values},
+        "ver": "1",
+        "rootHash": root_hash,
+        "auditPath": audit_path,
+    });
+
+    match fees {
+        Value::Object(ref fees) if is_flat(fees) => {
+            result[FEES] = nest_result(fees.clone());
+        },
+        Value::Null => (),
+        fees => {
+            result[FEES] = fees;
+        },
+    }
+    result
+}
+
+// The signatures of the request, of its DID or of the addresses of its inputs.
+fn signature_values(from: &Value, signature: &Value, signatures: &Value, inputs: Option<&Value>) -> Vec<Value> {
+    if let Some(signature) = signature.as_str() {
+        return vec![json!({"from": from, "value": signature})];
+    }
+
+    match *signatures {
+        Value::Object(ref signatures) => signatures.iter()
+            .map(|(from, value)| json!({"from": from, "value": value}))
+            .collect(),
+        Value::Array(ref signatures) => {
+            let inputs = inputs.and_then(Value::as_array).cloned().unwrap_or_default();
+            signatures.iter()
+                .enumerate()
+                .map(|(index, value)| {
+                    let input = inputs.get(index).cloned().unwrap_or(Value::Null);
+                    let from = input.get("address").or_else(|| input.get(0)).cloned().unwrap_or(Value::Null);
+                    json!({"from": from, "value": value})
+                })
+                .collect()
+        },
+        _ => Vec::new(),
+    }
+}
+
+
+#[cfg(test)]
+mod legacy_reply_tests {
+    use logic::parsers::parse_payment_response::{from_response, ParsePaymentResponse};
+    use logic::parsers::parse_response_with_fees_handler::{self, ParseResponseWithFees};
+    use utils::json_conversion::JsonDeserialize;
+    use super::*;
+
+    static ADDRESS: &'static str = "2jS4PHWQJKcawRxdW6GVsjnZBa1ecGdCssn7KhWYJZGTXgL7Es";
+    static RECIPIENT: &'static str = "a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7";
+
+    fn legacy_xfer_reply() -> Value {
+        json!({
+            "op": "REPLY",
+            "protocol_version": 1,
+            "result": {
+                "type": "10001",
+                "identifier": ADDRESS,
+                "reqId": 1527714086,
+                "inputs": [{"address": ADDRESS, "seqNo": 1}],
+                "outputs": [{"address": RECIPIENT, "amount": 9}],
+                "signatures": ["4jX5zpR7GSa3q7nW5wPRqXRmmP2fqomAdg3SMaYq2TVGDFyBUeQNpk9WAkfyDRXVn3kRbJqZR5dFE2Ky2yR9Lj6"],
+                "extra": null,
+                "seqNo": 4,
+                "txnTime": 1527714130,
+                "rootHash": "5ecipNPSztrk6X77fYPdepzFRUvLdqBuSqv4M9Mcv2Vn",
+                "auditPath": ["CWQ9keGzhBqyMRLvp7XbMr7da7yUbEU4qGTfJ2KNxMM6"]
+            }
+        })
+    }
+
+    #[test]
+    fn legacy_payment_reply_parsed() {
+        let nested = nest_json(&legacy_xfer_reply().to_string()).unwrap();
+        let receipts = from_response(ParsePaymentResponse::from_json(&nested).unwrap()).unwrap();
+
+        assert_eq!(1, receipts.len());
+        assert_eq!(9, receipts[0].amount);
+        assert_eq!(4, receipts[0].seq_no);
+    }
+
+    #[test]
+    fn signatures_of_the_inputs() {
+        let nested = nest(legacy_xfer_reply());
+
+        assert_eq!(json!(1), nested["protocolVersion"]);
+        assert_eq!(json!(ADDRESS), nested[RESULT]["reqSignature"]["values"][0]["from"]);
+        assert_eq!(json!(ADDRESS), nested[RESULT]["txn"]["metadata"]["from"]);
+        assert_eq!(json!(4), nested[RESULT]["txnMetadata"]["seqNo"]);
+        assert!(nested[RESULT]["txn"]["data"].get("seqNo").is_none());
+    }
+
+    #[test]
+    fn legacy_fees_nested() {
+        let reply = json!({
+            "op": "REPLY",
+            "result": {
+                "type": "1",
+                "identifier": "Th7MpTaRZVRYnPiabds81Y",
+                "reqId": 2,
+                "signature": "sig",
+                "dest": "FYmoFw55GeQH7SRFa37dkx1d2dZ3zUF8ckg7wmL7ofN4",
+                "seqNo": 10,
+                "txnTime": 1527714130,
+                "rootHash": "root",
+                "auditPath": [],
+                "fees": {
+                    "type": "20001",
+                    "reqId": 2,
+                    "inputs": [{"address": ADDRESS, "seqNo": 1}],
+                    "outputs": [{"address": ADDRESS, "amount": 7}],
+                    "fees": 3,
+                    "ref": "1:10",
+                    "signatures": ["sig"],
+                    "seqNo": 5,
+                    "txnTime": 1527714130,
+                    "rootHash": "root",
+                    "auditPath": []
+                }
+            }
+        });
+
+        let nested = nest_json(&reply.to_string()).unwrap();
+        let receipts = parse_response_with_fees_handler::from_response(ParseResponseWithFees::from_json(&nested).unwrap())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(1, receipts.len());
+        assert_eq!(7, receipts[0].amount);
+        assert_eq!(5, receipts[0].seq_no);
+    }
+
+    #[test]
+    fn nested_reply_unchanged() {
+        let reply = json!({"op": "REPLY", "protocolVersion": 2, "result": {"txn": {"type": "10001"}, "txnMetadata": {"seqNo": 4}}}).to_string();
+        assert_eq!(reply, nest_json(&reply).unwrap());
+    }
+}
@@ -3,8 +3,12 @@
 
 pub mod common;
 pub mod parse_get_utxo_response;
+pub mod parse_get_receipt;
 pub mod parse_get_txn_fees;
+pub mod parse_fees_section;
 pub mod parse_payment_response;
 pub mod parse_response_with_fees_handler;
 pub mod error_code_parser;
+pub mod parse_options;
 pub mod parse_verify;
+pub mod legacy_reply;
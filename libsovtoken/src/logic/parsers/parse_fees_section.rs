@@ -0,0 +1,176 @@
+/*!
+    Parses the fees section of any ledger reply into the UTXOs it spent and the change it created.
+
+    The fees section is parsed as [`parse_response_with_fees_handler`]
+    parses it, only the rest of the reply is left unread.
+
+    [`parse_response_with_fees_handler`]: ../parse_response_with_fees_handler/index.html
+*/
+
+use indy::ErrorCode;
+use serde_json;
+
+use logic::address;
+use logic::amount_format;
+use logic::parsers::common::{ResponseOperations, TXO};
+use logic::parsers::error_code_parser;
+use logic::parsers::parse_response_with_fees_handler::{receipts_of_fees, TransactionFees};
+use logic::receipt::Receipt;
+use logic::type_aliases::{TokenAmount, TxnSeqNo};
+
+/**
+    The fees paid by a transaction written with fees, e.g. a NYM.
+
+    ```text
+    {
+        "seqNo": <int>,             // of the fees transaction, which holds the change
+        "txnTime": <int>,
+        "fees": <int>,              // the fees paid
+        "spent": [<string>, ...],   // the txo:sov: ids of the inputs spent on the fees
+        "receipts": [...]           // the receipts of the change
+    }
+    ```
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FeesSection {
+    pub seq_no: TxnSeqNo,
+    pub txn_time: Option<u64>,
+    #[serde(with = "amount_format")]
+    pub fees: TokenAmount,
+    pub spent: Vec<String>,
+    pub receipts: Vec<Receipt>,
+}
+
+/**
+    Parse the `fees` section of a ledger reply, whatever the transaction written.
+
+    Only the `op`, `reason` and `result.fees` of the reply are read, so
+    the replies of every transaction type parse the same way. Returns
+    `None` when the transaction was written without fees. Fails with the
+    error of the reason of a REQNACK or REJECT, and with
+    `CommonInvalidStructure` when the reply or its fees section is invalid.
+*/
+pub fn parse_fees_section(resp: &str) -> Result<Option<FeesSection>, ErrorCode> {
+    let reply: Reply = serde_json::from_str(resp)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    match reply.op {
+        ResponseOperations::REPLY => (),
+        ResponseOperations::REQNACK | ResponseOperations::REJECT => {
+            let reason = reply.reason.ok_or(ErrorCode::CommonInvalidStructure)?;
+            return Err(error_code_parser::parse_error_code_from_string(&reason));
+        }
+    }
+
+    let fees = match reply.result.ok_or(ErrorCode::CommonInvalidStructure)?.fees {
+        Some(fees) => fees,
+        None => return Ok(None),
+    };
+    let fees: TransactionFees = serde_json::from_value(fees)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    let spent = fees.txn.data.inputs.iter()
+        .map(|input| {
+            let address = address::address_from_unqualified_address(&input.address)?;
+            TXO { address, seq_no: input.seq_no }.to_libindy_string()
+        })
+        .collect::<Result<Vec<String>, ErrorCode>>()?;
+
+    Ok(Some(FeesSection {
+        seq_no: fees.tnx_meta_data.seq_no,
+        txn_time: Some(u64::from(fees.tnx_meta_data.txn_time)),
+        fees: fees.txn.data.fees,
+        spent,
+        receipts: receipts_of_fees(&fees)?,
+    }))
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+#[derive(Deserialize, Debug)]
+struct Reply {
+    op: ResponseOperations,
+    result: Option<ReplyResult>,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReplyResult {
+    fees: Option<serde_json::Value>,
+}
+
+
+#[cfg(test)]
+mod parse_fees_section_tests {
+    use super::*;
+
+    static PAYER: &'static str = "dctKSXBbv2My3TGGUgTFjkxu1A9JM3Sscd5FydY4dkxnfwA7q";
+
+    fn nym_reply(fees: serde_json::Value) -> String {
+        json!({
+            "op": "REPLY",
+            "result": {
+                "txn": {
+                    "data": {"dest": "8Wv7NMbsMiNSmNa3iC6fG7", "verkey": "56b9wim9b3dYXzzc8wnm8RZePbyuMoWw5XUXxL4Y9gFZ"},
+                    "metadata": {"reqId": 152969782},
+                    "type": "1"
+                },
+                "txnMetadata": {"seqNo": 13, "txnTime": 1529697829},
+                "fees": fees
+            }
+        }).to_string()
+    }
+
+    #[test]
+    fn fees_of_a_nym() {
+        let resp = nym_reply(json!({
+            "txn": {
+                "data": {
+                    "inputs": [{"address": PAYER, "seqNo": 2}],
+                    "outputs": [{"address": PAYER, "amount": 9}],
+                    "fees": 1,
+                    "ref": "1:13"
+                },
+                "metadata": {"digest": "54289ff3f7853891e2ba9f4edb4925a0028840008395ea717df8b1f757c4fc77", "reqId": 152969782},
+                "protocolVersion": 2,
+                "type": "20000"
+            },
+            "reqSignature": {"type": "ED25519", "values": [{"from": PAYER, "value": "sig"}]},
+            "txnMetadata": {"seqNo": 2, "txnTime": 1529697829},
+            "rootHash": "A8qwQKyKUMd3PnJTKe4bXRzajCUVgSd1J1A7jdahhNW6",
+            "auditPath": []
+        }));
+
+        let section = parse_fees_section(&resp).unwrap().unwrap();
+        assert_eq!(1, section.fees);
+        assert_eq!(Some(1529697829), section.txn_time);
+        let spent = TXO { address: format!("pay:sov:{}", PAYER), seq_no: 2 }.to_libindy_string().unwrap();
+        assert_eq!(vec![spent], section.spent);
+        assert_eq!(1, section.receipts.len());
+        assert_eq!(9, section.receipts[0].amount);
+        assert_eq!(2, section.receipts[0].seq_no);
+    }
+
+    #[test]
+    fn reply_without_fees() {
+        assert_eq!(None, parse_fees_section(&nym_reply(json!(null))).unwrap());
+    }
+
+    #[test]
+    fn rejected_request() {
+        let resp = json!({"op": "REJECT", "reason": "client request invalid: InsufficientFundsError()"}).to_string();
+        assert_eq!(ErrorCode::PaymentInsufficientFundsError, parse_fees_section(&resp).unwrap_err());
+    }
+
+    #[test]
+    fn invalid_fees_section() {
+        let resp = nym_reply(json!({"txn": {"data": {"inputs": []}}}));
+        assert_eq!(ErrorCode::CommonInvalidStructure, parse_fees_section(&resp).unwrap_err());
+    }
+}
@@ -0,0 +1,146 @@
+/*!
+    Parses the GET_TXN reply of a token transaction into its receipts
+*/
+
+use indy::ErrorCode;
+use serde_json;
+
+use logic::address;
+use logic::parsers::common::TXO;
+use logic::parsers::parse_verify::ParseVerifyResponse;
+use logic::receipt::Receipt;
+use logic::type_aliases::TxnSeqNo;
+
+/**
+    A token transaction looked up by its seqNo.
+
+    ```text
+    {
+        "seqNo": <int>,
+        "txnTime": <int>,           // null when the reply doesn't have it
+        "type": <string>,           // the transaction type, e.g. "10001" for XFER_PUBLIC
+        "sources": [<string>, ...], // the txo:sov: ids of the inputs, none for a MINT
+        "receipts": [...],          // the receipts of the outputs
+        "extra": <string>           // null when the transaction has none
+    }
+    ```
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptLookup {
+    pub seq_no: TxnSeqNo,
+    pub txn_time: Option<u64>,
+    #[serde(rename = "type")]
+    pub txn_type: Option<String>,
+    pub sources: Vec<String>,
+    pub receipts: Vec<Receipt>,
+    pub extra: Option<String>,
+}
+
+/**
+    Parse the reply to a GET_TXN request of a token transaction.
+
+    Fails with `PaymentSourceDoesNotExistError` when the ledger has no
+    transaction with the seqNo, and with `CommonInvalidStructure` when the
+    transaction has no outputs, i.e. isn't a token transaction.
+*/
+pub fn parse_get_receipt_response(resp: &str) -> Result<ReceiptLookup, ErrorCode> {
+    let response: ParseVerifyResponse = serde_json::from_str(resp)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    let data = response.result
+        .ok_or(ErrorCode::CommonInvalidStructure)?
+        .data
+        .ok_or(ErrorCode::PaymentSourceDoesNotExistError)?;
+
+    let metadata = data.txn_metadata.ok_or(ErrorCode::CommonInvalidStructure)?;
+    let txn = data.txn.ok_or(ErrorCode::CommonInvalidStructure)?;
+    let txn_data = txn.data.ok_or(ErrorCode::CommonInvalidStructure)?;
+
+    let outputs = match txn_data.outputs {
+        Some(outputs) => outputs,
+        None => {
+            error!("The transaction {} of type {:?} has no outputs", metadata.seq_no, txn.txn_type);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+    };
+
+    let sources = txn_data.inputs.unwrap_or_default().into_iter()
+        .map(|input| {
+            let address = address::address_from_unqualified_address(&input.address)?;
+            TXO { address, seq_no: input.seq_no }.to_libindy_string()
+        })
+        .collect::<Result<Vec<String>, ErrorCode>>()?;
+
+    let extra = txn_data.extra;
+    let receipts = outputs.into_iter()
+        .map(|output| {
+            let address = address::address_from_unqualified_address(&output.recipient)?;
+            Receipt::new(&address, output.amount, metadata.seq_no, metadata.txn_time, extra.as_ref().map(String::as_str))
+        })
+        .collect::<Result<Vec<Receipt>, ErrorCode>>()?;
+
+    Ok(ReceiptLookup {
+        seq_no: metadata.seq_no,
+        txn_time: metadata.txn_time,
+        txn_type: txn.txn_type,
+        sources,
+        receipts,
+        extra,
+    })
+}
+
+
+#[cfg(test)]
+mod parse_get_receipt_tests {
+    use super::*;
+
+    static PAYER: &'static str = "dctKSXBbv2My3TGGUgTFjkxu1A9JM3Sscd5FydY4dkxnfwA7q";
+    static PAYEE: &'static str = "2Viu9qrpqM48PSw3vdoQoFKP5AvYTChUZhwWtCydfW9iu7ftRt";
+
+    fn reply(data: serde_json::Value) -> String {
+        json!({
+            "op": "REPLY",
+            "result": {"data": data, "identifier": "Th7MpTaRZVRYnPiabds81Y", "reqId": 1, "seqNo": 4, "type": "3"}
+        }).to_string()
+    }
+
+    #[test]
+    fn receipts_of_a_transfer() {
+        let resp = reply(json!({
+            "txn": {
+                "data": {
+                    "inputs": [{"address": PAYER, "seqNo": 2}],
+                    "outputs": [{"address": PAYEE, "amount": 11}, {"address": PAYER, "amount": 19}],
+                    "extra": "invoice 12"
+                },
+                "type": "10001"
+            },
+            "txnMetadata": {"seqNo": 4, "txnTime": 1532341475}
+        }));
+
+        let lookup = parse_get_receipt_response(&resp).unwrap();
+        assert_eq!(Some(String::from("10001")), lookup.txn_type);
+        let source = TXO { address: format!("pay:sov:{}", PAYER), seq_no: 2 }.to_libindy_string().unwrap();
+        assert_eq!(vec![source], lookup.sources);
+        assert_eq!(2, lookup.receipts.len());
+        assert!(lookup.receipts.iter().all(|receipt| receipt.seq_no == 4 && receipt.extra == "invoice 12"));
+        assert_eq!(format!("pay:sov:{}", PAYEE), lookup.receipts[0].recipient);
+    }
+
+    #[test]
+    fn receipt_of_an_unknown_seq_no() {
+        let error = parse_get_receipt_response(&reply(json!(null))).unwrap_err();
+        assert_eq!(ErrorCode::PaymentSourceDoesNotExistError, error);
+    }
+
+    #[test]
+    fn receipt_of_a_transaction_without_outputs() {
+        let resp = reply(json!({
+            "txn": {"data": {"dest": "Th7MpTaRZVRYnPiabds81Y"}, "type": "1"},
+            "txnMetadata": {"seqNo": 4}
+        }));
+        assert_eq!(ErrorCode::CommonInvalidStructure, parse_get_receipt_response(&resp).unwrap_err());
+    }
+}
@@ -4,9 +4,11 @@ use std::collections::HashMap;
 use base64;
 use serde_json;
 use serde_json::Error;
-use indy::ErrorCode;
+use indy::{ErrorCode, IndyHandle};
 use libc::c_char;
 
+use logic::amount_format;
+use logic::fee_waiver;
 use logic::parsers::common::{ResponseOperations, StateProof,
                              extract_result_and_state_proof_from_node_reply,
                              KeyValuesInSP, KeyValueSimpleData, ParsedSP};
@@ -51,20 +53,61 @@ pub struct ParseGetTxnFeesResult {
     #[serde(rename = "type")]
     pub txn_type : String,
     pub fees : HashMap<String, TokenAmount>,
+    // The transaction types of a filtered GET_FEES request, echoed by the ledger
+    #[serde(rename = "txnTypes", default, skip_serializing_if = "Option::is_none")]
+    pub txn_types : Option<Vec<String>>,
     // This is being renamed back to the snake case because that is what the JSON object key expects
     #[serde(rename = "state_proof", skip_serializing_if = "Option::is_none")]
     pub state_proof : Option<StateProof>
 }
 
+/**
+    The fees of a GET_FEES reply, as json.
+
+    The fees are remembered, so `add_request_fees_handler` leaves the
+    requests of a type with no fee as they are, see [`fee_waiver`].
+
+    [`fee_waiver`]: ../../fee_waiver/index.html
+*/
 pub fn parse_fees_from_get_txn_fees_response(response : String) -> Result<String, Error> {
+    parse_fees_of_pool(None, response)
+}
+
+/**
+    As [`parse_fees_from_get_txn_fees_response`], the fees remembered for
+    the network of `pool_handle`.
+
+    [`parse_fees_from_get_txn_fees_response`]: fn.parse_fees_from_get_txn_fees_response.html
+*/
+pub fn parse_fees_of_pool(pool_handle: Option<IndyHandle>, response : String) -> Result<String, Error> {
     trace!("logic::parsers::parse_fees_from_get_txn_fees_response >> response: {:?}", response);
     let fees_response : ParseGetTxnFeesResponse =
             ParseGetTxnFeesResponse::from_json(&response).map_err(map_err_err!())?;
-    let res = serde_json::to_string(&fees_response.result.fees).map_err(map_err_err!());
+    let fees = match fees_response.result.txn_types {
+        Some(ref txn_types) => filter_fees(&fees_response.result.fees, txn_types),
+        None => fees_response.result.fees.clone(),
+    };
+    fee_waiver::remember_schedule(pool_handle, &fees);
+    let mut json = Vec::new();
+    let res = amount_format::map::serialize(&fees, &mut serde_json::Serializer::new(&mut json))
+        .map(|_| String::from_utf8(json).unwrap())
+        .map_err(map_err_err!());
     trace!("logic::parsers::parse_fees_from_get_txn_fees_response << result: {:?}", res);
     return res;
 }
 
+/**
+    Keep only the fees of `txn_types`.
+
+    Used when a ledger returned the full schedule for a filtered GET_FEES request.
+*/
+pub fn filter_fees(fees: &HashMap<String, TokenAmount>, txn_types: &[String]) -> HashMap<String, TokenAmount> {
+    fees.iter()
+        .filter(|&(txn_type, _)| txn_types.contains(txn_type))
+        .map(|(txn_type, fee)| (txn_type.clone(), *fee))
+        .collect()
+}
+
 pub fn get_fees_state_proof_extractor(reply_from_node: *const c_char, parsed_sp: *mut *const c_char) -> ErrorCode {
     // TODO: The following errors should have logs
     let (result, state_proof) = match extract_result_and_state_proof_from_node_reply(reply_from_node) {
@@ -117,6 +160,7 @@ mod parse_fees_responses_test {
                 ErrorCode, ParsedSP, KeyValuesInSP, KeyValueSimpleData};
     use serde_json::{Value, Error};
     use serde_json;
+    use logic::amount_format::{with_amount_format, AmountFormat};
     use std::ffi::CString;
     use utils::ffi_support::string_from_char_ptr;
 
@@ -154,6 +198,44 @@ mod parse_fees_responses_test {
         assert_eq!(parsed_fees_json, expected_json, "The json objects don't match");
     }
 
+    #[test]
+    fn parse_fees_filtered_by_echoed_types() {
+        let get_fees_response = json!({
+            "op": "REPLY",
+            "result": {
+                "identifier": "6ouriXMZkLeHsuXrN1X1fd",
+                "reqId": 47660,
+                "type": "20001",
+                "txnTypes": ["1", "10001", "3"],
+                "fees": {"1": 1, "100": 1, "10001": 10}
+            }
+        });
+
+        let fees_json = parse_fees_from_get_txn_fees_response(get_fees_response.to_string()).unwrap();
+        let parsed_fees_json: Value = serde_json::from_str(&fees_json).unwrap();
+
+        assert_eq!(json!({"1": 1, "10001": 10}), parsed_fees_json);
+    }
+
+    #[test]
+    fn parse_fees_with_string_amounts() {
+        let get_fees_response = json!({
+            "op": "REPLY",
+            "result": {
+                "identifier": "6ouriXMZkLeHsuXrN1X1fd",
+                "reqId": 47660,
+                "type": "20001",
+                "fees": {"10001": 9007199254740993u64}
+            }
+        });
+
+        let fees_json = with_amount_format(AmountFormat::String, || {
+            parse_fees_from_get_txn_fees_response(get_fees_response.to_string()).unwrap()
+        });
+
+        assert_eq!(r#"{"10001":"9007199254740993"}"#, fees_json);
+    }
+
     #[test]
     fn failure_parse_fees_from_reply_response() {
         let invalid_json_response =
@@ -8,6 +8,7 @@ use logic::parsers::common::{ResponseOperations, TXO, StateProof, ParsedSP, KeyV
 use logic::parsers::error_code_parser;
 use logic::type_aliases::{TokenAmount, TxnSeqNo, ProtocolVersion, ReqId};
 use logic::address;
+use logic::amount_format;
 use serde_json;
 use utils::constants::txn_fields::OUTPUTS;
 use utils::ffi_support::c_pointer_from_string;
@@ -29,7 +30,8 @@ pub struct UTXOInner {
 #[serde(rename_all = "camelCase")]
 pub struct ParseGetUtxoResponse {
     pub op : ResponseOperations,
-    #[serde(rename = "protocol_version")]
+    // named as in the write replies by some pools
+    #[serde(rename = "protocol_version", alias = "protocolVersion")]
     pub protocol_version: Option<ProtocolVersion>,
     pub result : Option<ParseGetUtxoResponseResult>,
     pub reason: Option<String>,
@@ -59,11 +61,12 @@ pub struct ParseGetUtxoResponseResult {
 
     used by [`ParseGetUtxoReply`]
 */
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct UTXO {
     pub payment_address: String,
     pub source: String,
+    #[serde(with = "amount_format")]
     pub amount: TokenAmount,
     pub extra: String,
 }
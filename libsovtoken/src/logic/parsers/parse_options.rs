@@ -0,0 +1,42 @@
+/*!
+    Options of the parsers taking a wallet, applied to the receipts they emit
+*/
+
+use indy::{IndyHandle, ErrorCode};
+
+use logic::address_book::AddressBook;
+use logic::indy_sdk_api::payment_api::PaymentAPI;
+use logic::indy_sdk_api::wallet_api::WalletAPI;
+use logic::receipt::{self, Receipt};
+
+/**
+    The options json of the `_with_options` parse handlers, every field is optional.
+
+    ```
+        use sovtoken::utils::json_conversion::JsonDeserialize;
+        use sovtoken::logic::parsers::parse_options::ParseOptions;
+
+        let options = ParseOptions::from_json(r#"{"resolveLabels": true}"#).unwrap();
+        assert!(options.resolve_labels);
+        assert!(!ParseOptions::from_json("{}").unwrap().resolve_labels);
+    ```
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ParseOptions {
+    /// Set the `label` of the receipts of the addresses the wallet has a label for.
+    pub resolve_labels: bool,
+}
+
+impl ParseOptions {
+    /**
+        Apply the options to `receipts`, with the records of `wallet_handle`.
+    */
+    pub fn apply<W: WalletAPI, P: PaymentAPI>(&self, address_book: &AddressBook<W, P>, wallet_handle: IndyHandle, receipts: &mut [Receipt]) -> Result<(), ErrorCode> {
+        if self.resolve_labels {
+            let labels = address_book.labels(wallet_handle)?;
+            receipt::add_labels(receipts, &labels);
+        }
+        Ok(())
+    }
+}
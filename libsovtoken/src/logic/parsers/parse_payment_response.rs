@@ -5,12 +5,12 @@ use logic::address::add_qualifer_to_address;
 use logic::input::Inputs;
 use logic::output::Outputs;
 use logic::parsers::common::{ResponseOperations,
-                             UTXO,
-                             TXO,
                              TransactionMetaData,
                              RequireSignature};
 use logic::parsers::error_code_parser;
+use logic::receipt::Receipt;
 use logic::type_aliases::{ProtocolVersion};
+use utils::span;
 
 /**
     for parse_payment_response_handler input resp_json
@@ -77,9 +77,9 @@ pub struct TransactionMetaData2 {
 
 
 /**
-    for parse_payment_response_handler output utxo_json
+    for parse_payment_response_handler output utxo_json, the receipts of the outputs
 */
-pub type ParsePaymentReply = Vec<UTXO>;
+pub type ParsePaymentReply = Vec<Receipt>;
 
 /**
     Converts ParsePaymentReply (which should be input via indy-sdk) to ParsePaymentReply
@@ -90,18 +90,18 @@ pub fn from_response(base: ParsePaymentResponse) -> Result<ParsePaymentReply, Er
     match base.op {
         ResponseOperations::REPLY => {
             let result = base.result.ok_or(ErrorCode::CommonInvalidStructure)?;
-            let mut utxos: Vec<UTXO> = vec![];
+            span::record_req_id(u64::from(result.txn.meta_data.req_id));
+            let mut receipts: Vec<Receipt> = vec![];
+            let seq_no = result.tnx_meta_data.seq_no;
+            let txn_time = u64::from(result.tnx_meta_data.txn_time);
+            let extra = result.txn.data.extra;
             for unspent_output in result.txn.data.outputs {
-                let address = unspent_output.recipient;
-                let amount  = unspent_output.amount;
-                let qualified_address: String = add_qualifer_to_address(&address);
-                let seq_no: u64 = result.tnx_meta_data.seq_no;
-                let txo = (TXO { address: qualified_address.to_string(), seq_no }).to_libindy_string()?;
-                let utxo: UTXO = UTXO { recipient: qualified_address, receipt: txo, amount, extra: "".to_string() };
-
-                utxos.push(utxo);
+                let qualified_address: String = add_qualifer_to_address(&unspent_output.recipient);
+                let receipt = Receipt::new(&qualified_address, unspent_output.amount, seq_no, Some(txn_time), extra.as_ref().map(String::as_str))?;
+
+                receipts.push(receipt);
             }
-            Ok(utxos)
+            Ok(receipts)
         }
         ResponseOperations::REJECT | ResponseOperations::REQNACK => {
             let reason = base.reason.ok_or(ErrorCode::CommonInvalidStructure)?;
@@ -250,5 +250,7 @@ mod parse_payment_response_tests {
         let reply: ParsePaymentReply = from_response(response).unwrap();
 
         assert_eq!(reply.len(), number_of_outputs);
+        assert_eq!(1529682415, reply[0].txn_time.unwrap());
+        assert_eq!(reply[0].seq_no, reply[0].txo().unwrap().seq_no);
     }
 }
\ No newline at end of file
@@ -1,16 +1,18 @@
-//! types used for parse_response_with_fees_handler
+/*!
+    types used for parse_response_with_fees_handler
+*/
 
 use logic::address::add_qualifer_to_address;
 use logic::input::Inputs;
 use logic::output::Outputs;
 use logic::parsers::common::{ResponseOperations,
-                             UTXO,
-                             TXO,
                              TransactionMetaData,
                              RequireSignature};
 use logic::parsers::error_code_parser;
+use logic::receipt::Receipt;
 use indy::ErrorCode;
 use logic::type_aliases::{ProtocolVersion, TokenAmount, TxnSeqNo, TxnVersion};
+use utils::span;
 
 /**
     for parse_response_with_fees_handler input resp_json
@@ -97,9 +99,9 @@ pub struct TransactionMetaData2 {
 }
 
 /**
-    for parse_response_with_fees_handler output utxo_json
+    for parse_response_with_fees_handler output utxo_json, the receipts of the outputs of the fees
 */
-pub type ParseResponseWithFeesReply = Vec<UTXO>;
+pub type ParseResponseWithFeesReply = Vec<Receipt>;
 
 /**
     Converts ParseResponseWithFees (which should be input via indy-sdk) to ParseResponseWithFeesReply
@@ -110,29 +112,14 @@ pub fn from_response(base : ParseResponseWithFees) -> Result<Option<ParseRespons
     match base.op {
         ResponseOperations::REPLY => {
             let result = base.result.ok_or(ErrorCode::CommonInvalidStructure)?;
-            let mut utxos: Vec<UTXO> = vec![];
+            span::record_req_id(result.txn.meta_data.req_id);
 
             // according to the documentation, don't need the inputs.  Only the outputs
             // and seq_no which are part 2 and 3 of the tuple
-            let fees = match &result.fees {
-                Some(fees) => fees,
-                None => {return Ok(None)}
-            };
-            let outputs = &fees.txn.data.outputs;
-            let seq_no: TxnSeqNo = fees.tnx_meta_data.seq_no;
-
-            for output in outputs {
-                let amount: TokenAmount = output.amount;
-                let qualified_address: String = add_qualifer_to_address(&output.recipient);
-
-                let txo = (TXO { address: qualified_address.to_string(), seq_no }).to_libindy_string()?;
-
-                let utxo: UTXO = UTXO { recipient: qualified_address.to_string(), receipt: txo, amount, extra: "".to_string()};
-
-                utxos.push(utxo);
+            match result.fees {
+                Some(ref fees) => receipts_of_fees(fees).map(Some),
+                None => Ok(None),
             }
-
-            Ok(Some(utxos))
         }
         ResponseOperations::REQNACK | ResponseOperations::REJECT => {
             let reason = base.reason.ok_or(ErrorCode::CommonInvalidStructure)?;
@@ -142,6 +129,22 @@ pub fn from_response(base : ParseResponseWithFees) -> Result<Option<ParseRespons
 
 }
 
+/**
+    The receipts of the outputs of the fees transaction `fees`, the change
+    of the fees.
+*/
+pub fn receipts_of_fees(fees: &TransactionFees) -> Result<ParseResponseWithFeesReply, ErrorCode> {
+    let seq_no: TxnSeqNo = fees.tnx_meta_data.seq_no;
+    let txn_time = u64::from(fees.tnx_meta_data.txn_time);
+
+    fees.txn.data.outputs.iter()
+        .map(|output| {
+            let qualified_address: String = add_qualifer_to_address(&output.recipient);
+            Receipt::new(&qualified_address, output.amount, seq_no, Some(txn_time), None)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod parse_response_with_fees_handler_tests {
     use utils::json_conversion::{JsonDeserialize};
@@ -5,8 +5,8 @@ use logic::parsers::common::ResponseOperations;
 use logic::output::Outputs;
 use logic::input::Inputs;
 use indy::ErrorCode;
-use logic::parsers::common::UTXO;
 use logic::parsers::common::TXO;
+use logic::receipt::Receipt;
 use logic::type_aliases::TxnSeqNo;
 use logic::address;
 
@@ -17,7 +17,8 @@ use logic::address;
 #[serde(rename_all = "camelCase")]
 pub struct ParseVerifyResponse {
     pub op : ResponseOperations,
-    #[serde(rename = "protocol_version")]
+    // named as in the write replies by some pools
+    #[serde(rename = "protocol_version", alias = "protocolVersion")]
     pub protocol_version: Option<ProtocolVersion>,
     pub result : Option<ParseVerifyResponseResult>,
 }
@@ -38,13 +39,16 @@ pub struct ParseVerifyResponseResultData {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct TxnMetadata {
-    pub seq_no: TxnSeqNo
+    pub seq_no: TxnSeqNo,
+    pub txn_time: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ParseVerifyResponseResultDataTxn {
-    pub data: Option<ParseVerifyResponseResultDataTxnData>
+    pub data: Option<ParseVerifyResponseResultDataTxnData>,
+    #[serde(rename = "type")]
+    pub txn_type: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -59,7 +63,7 @@ pub struct ParseVerifyResponseResultDataTxnData {
 #[serde(rename_all = "camelCase")]
 pub struct VerifyResult {
     pub sources: Option<Vec<String>>,
-    pub receipts: Option<Vec<UTXO>>,
+    pub receipts: Option<Vec<Receipt>>,
     pub extra: Option<String>
 }
 
@@ -73,8 +77,9 @@ fn parse_verify(resp: &str) -> Result<VerifyResult, ErrorCode> {
         .data
         .ok_or(ErrorCode::PaymentSourceDoesNotExistError)?;
 
-    let seq_no = data.txn_metadata
-        .ok_or(ErrorCode::CommonInvalidStructure)?.seq_no;
+    let txn_metadata = data.txn_metadata
+        .ok_or(ErrorCode::CommonInvalidStructure)?;
+    let seq_no = txn_metadata.seq_no;
 
     let data = data
         .txn
@@ -83,7 +88,7 @@ fn parse_verify(resp: &str) -> Result<VerifyResult, ErrorCode> {
         .ok_or(ErrorCode::CommonInvalidStructure)?;
 
     let mut sources: Vec<String> = vec![];
-    let mut receipts: Vec<UTXO> = vec![];
+    let mut receipts: Vec<Receipt> = vec![];
     let extra = data.extra;
 
     if let Some(inputs) = data.inputs {
@@ -96,12 +101,7 @@ fn parse_verify(resp: &str) -> Result<VerifyResult, ErrorCode> {
     if let Some(outputs) = data.outputs {
         for output in outputs {
             let address = address::address_from_unqualified_address(&output.recipient.to_string())?;
-            receipts.push(UTXO {
-                recipient: address.clone(),
-                receipt: TXO { address, seq_no }.to_libindy_string()?,
-                amount: output.amount,
-                extra: extra.as_ref().unwrap_or(&"".to_string()).to_string(),
-            })
+            receipts.push(Receipt::new(&address, output.amount, seq_no, txn_metadata.txn_time, extra.as_ref().map(String::as_str))?)
         }
     }
 
@@ -168,5 +168,8 @@ mod test_parse_verify {
     pub fn parse_verify_works() {
         let res = parse_verify(VALID_REQUEST).unwrap();
         println!("{:?}", serde_json::to_string(&res));
+
+        let receipts = res.receipts.unwrap();
+        assert!(receipts.iter().all(|receipt| receipt.seq_no == 1 && receipt.txn_time == Some(1532341475)));
     }
 }
\ No newline at end of file
@@ -0,0 +1,259 @@
+/*!
+    Deferred payments registered now and materialized into a signed request later.
+
+    An intent is stored in the wallet with the earliest time it may be
+    submitted. Materializing it reserves the UTXOs it is paid with, so two
+    intents can't spend the same UTXO.
+*/
+
+use indy::{IndyHandle, ErrorCode};
+use serde_json;
+
+use logic::indy_sdk_api::wallet_api::WalletAPI;
+use logic::parsers::parse_get_utxo_response::UTXO;
+use logic::payment_template::{FeePolicy, PaymentTemplate, TemplateRecipient};
+use logic::request_builder::PaymentRequestBuilder;
+use logic::type_aliases::TokenAmount;
+use utils::json_conversion::JsonDeserialize;
+use utils::random::rand_string;
+
+/**
+    The wallet record type the intents are stored under.
+*/
+pub const INTENT_RECORD_TYPE: &str = "sovtoken_payment_intent";
+
+/**
+    The wallet record type of the UTXO reservations, keyed by the UTXO source.
+*/
+pub const RESERVATION_RECORD_TYPE: &str = "sovtoken_utxo_reservation";
+
+const INTENT_ID_LEN: usize = 16;
+
+/**
+    A payment to make once `not_before` has passed.
+
+    `not_before` is in seconds since the unix epoch.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentIntent {
+    pub recipient: String,
+    pub amount: TokenAmount,
+    #[serde(default)]
+    pub not_before: u64,
+    #[serde(default)]
+    pub fee: TokenAmount,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change_address: Option<String>,
+}
+
+impl PaymentIntent {
+    fn as_template(&self) -> PaymentTemplate {
+        PaymentTemplate {
+            recipients: vec![TemplateRecipient { recipient: self.recipient.clone(), share: 1 }],
+            extra: self.extra.clone(),
+            fee_policy: FeePolicy::Fixed { amount: self.fee },
+            change_address: self.change_address.clone(),
+        }
+    }
+}
+
+/**
+    IntentStore registers and materializes payment intents.
+*/
+pub struct IntentStore<T> where T: WalletAPI {
+    injected_api: T
+}
+
+impl<T: WalletAPI> IntentStore<T> {
+    pub fn new(api_handler: T) -> Self {
+        IntentStore { injected_api: api_handler }
+    }
+
+    /**
+        Store `intent` and return its generated id.
+    */
+    pub fn register(&self, wallet_handle: IndyHandle, intent: &PaymentIntent) -> Result<String, ErrorCode> {
+        trace!("logic::payment_intent::register >> intent: {:?}", intent);
+        if intent.amount == 0 {
+            error!("A payment intent needs a positive amount.");
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+
+        let value = serde_json::to_string(intent)
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonInvalidState))?;
+
+        let intent_id = rand_string(INTENT_ID_LEN);
+        self.injected_api.indy_add_record(wallet_handle, INTENT_RECORD_TYPE, &intent_id, &value)?;
+
+        trace!("logic::payment_intent::register << intent_id: {:?}", intent_id);
+        Ok(intent_id)
+    }
+
+    /**
+        Get the intent stored as `intent_id`.
+    */
+    pub fn get(&self, wallet_handle: IndyHandle, intent_id: &str) -> Result<PaymentIntent, ErrorCode> {
+        let value = self.injected_api.indy_get_record_value(wallet_handle, INTENT_RECORD_TYPE, intent_id)?;
+        PaymentIntent::from_json(&value)
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonInvalidState))
+    }
+
+    /**
+        Reserve `utxos` for the intent and start a [`PaymentRequestBuilder`] for it.
+
+        Fails with `CommonInvalidState` when `now` is before the intent's
+        `not_before`, or when a UTXO is reserved by another intent. UTXOs
+        reserved by the same intent can be used again, so an intent can be
+        materialized again after its request failed.
+
+        [`PaymentRequestBuilder`]: ../request_builder/struct.PaymentRequestBuilder.html
+    */
+    pub fn materialize(&self, wallet_handle: IndyHandle, intent_id: &str, utxos: &Vec<UTXO>, now: u64) -> Result<PaymentRequestBuilder, ErrorCode> {
+        trace!("logic::payment_intent::materialize >> intent_id: {:?}, now: {:?}", intent_id, now);
+        let intent = self.get(wallet_handle, intent_id)?;
+
+        if now < intent.not_before {
+            error!("Payment intent {} can't be materialized before {}", intent_id, intent.not_before);
+            return Err(ErrorCode::CommonInvalidState);
+        }
+
+        let builder = intent.as_template().builder(intent.amount, utxos)?;
+
+        for utxo in utxos {
+            match self.injected_api.indy_get_record_value(wallet_handle, RESERVATION_RECORD_TYPE, &utxo.source) {
+                Ok(ref owner) if owner != intent_id => {
+                    error!("{} is already reserved by payment intent {}", utxo.source, owner);
+                    return Err(ErrorCode::CommonInvalidState);
+                },
+                Ok(_) | Err(ErrorCode::WalletItemNotFound) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        for utxo in utxos {
+            match self.injected_api.indy_add_record(wallet_handle, RESERVATION_RECORD_TYPE, &utxo.source, intent_id) {
+                Ok(()) | Err(ErrorCode::WalletItemAlreadyExists) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(builder)
+    }
+
+    /**
+        Get the reserved UTXO sources with the id of the intent reserving them.
+    */
+    pub fn reservations(&self, wallet_handle: IndyHandle) -> Result<Vec<(String, String)>, ErrorCode> {
+        self.injected_api.indy_list_records(wallet_handle, RESERVATION_RECORD_TYPE)
+    }
+
+    /**
+        Release the reservation of the UTXO `source`.
+    */
+    pub fn release(&self, wallet_handle: IndyHandle, source: &str) -> Result<(), ErrorCode> {
+        trace!("logic::payment_intent::release >> source: {:?}", source);
+        self.injected_api.indy_delete_record(wallet_handle, RESERVATION_RECORD_TYPE, source)
+    }
+}
+
+
+#[cfg(test)]
+mod payment_intent_tests {
+    use logic::output::Output;
+    use logic::parsers::common::TXO;
+    use utils::test::wallet::WalletMockHandler;
+    use super::*;
+
+    static WALLET_HANDLE: i32 = 1;
+    static INPUT_ADDRESS: &'static str = "pay:sov:E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm";
+    static RECIPIENT: &'static str = "pay:sov:2FKYJkgXRZtjhFpTMHhuyfc17BHZWcFPyF2MWy2SZMBaSo64fb";
+
+    fn intent(not_before: u64) -> PaymentIntent {
+        PaymentIntent {
+            recipient: RECIPIENT.to_string(),
+            amount: 10,
+            not_before,
+            fee: 0,
+            extra: None,
+            change_address: None,
+        }
+    }
+
+    fn utxos() -> Vec<UTXO> {
+        vec![UTXO {
+            payment_address: INPUT_ADDRESS.to_string(),
+            source: TXO { address: INPUT_ADDRESS.to_string(), seq_no: 1 }.to_libindy_string().unwrap(),
+            amount: 10,
+            extra: "".to_string(),
+        }]
+    }
+
+    #[test]
+    fn register_and_get() {
+        let store = IntentStore::new(WalletMockHandler::default());
+        let intent_id = store.register(WALLET_HANDLE, &intent(100)).unwrap();
+        assert_eq!(intent(100), store.get(WALLET_HANDLE, &intent_id).unwrap());
+    }
+
+    #[test]
+    fn register_zero_amount() {
+        let store = IntentStore::new(WalletMockHandler::default());
+        let mut intent = intent(0);
+        intent.amount = 0;
+        assert_eq!(ErrorCode::CommonInvalidStructure, store.register(WALLET_HANDLE, &intent).unwrap_err());
+    }
+
+    #[test]
+    fn materialize_too_early() {
+        let store = IntentStore::new(WalletMockHandler::default());
+        let intent_id = store.register(WALLET_HANDLE, &intent(100)).unwrap();
+        let error = store.materialize(WALLET_HANDLE, &intent_id, &utxos(), 99).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidState, error);
+    }
+
+    #[test]
+    fn materialize_builds_payment() {
+        let store = IntentStore::new(WalletMockHandler::default());
+        let intent_id = store.register(WALLET_HANDLE, &intent(100)).unwrap();
+
+        let payload = store.materialize(WALLET_HANDLE, &intent_id, &utxos(), 100).unwrap().build().unwrap();
+        assert_eq!(vec![Output::new(RECIPIENT.to_string(), 10)], payload.outputs);
+    }
+
+    #[test]
+    fn materialize_twice() {
+        let store = IntentStore::new(WalletMockHandler::default());
+        let intent_id = store.register(WALLET_HANDLE, &intent(0)).unwrap();
+
+        store.materialize(WALLET_HANDLE, &intent_id, &utxos(), 0).unwrap();
+        assert!(store.materialize(WALLET_HANDLE, &intent_id, &utxos(), 0).is_ok());
+    }
+
+    #[test]
+    fn release_reservation() {
+        let store = IntentStore::new(WalletMockHandler::default());
+        let first = store.register(WALLET_HANDLE, &intent(0)).unwrap();
+        let second = store.register(WALLET_HANDLE, &intent(0)).unwrap();
+        store.materialize(WALLET_HANDLE, &first, &utxos(), 0).unwrap();
+
+        assert_eq!(vec![(utxos()[0].source.clone(), first)], store.reservations(WALLET_HANDLE).unwrap());
+        store.release(WALLET_HANDLE, &utxos()[0].source).unwrap();
+        assert!(store.materialize(WALLET_HANDLE, &second, &utxos(), 0).is_ok());
+    }
+
+    #[test]
+    fn materialize_reserved_utxo() {
+        let store = IntentStore::new(WalletMockHandler::default());
+        let first = store.register(WALLET_HANDLE, &intent(0)).unwrap();
+        let second = store.register(WALLET_HANDLE, &intent(0)).unwrap();
+
+        store.materialize(WALLET_HANDLE, &first, &utxos(), 0).unwrap();
+        let error = store.materialize(WALLET_HANDLE, &second, &utxos(), 0).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidState, error);
+    }
+}
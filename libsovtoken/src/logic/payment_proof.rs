@@ -0,0 +1,482 @@
+/*!
+    Proofs of payment a third party can verify offline.
+
+    When two agents disagree about a payment, the payer hands over a bundle
+    of everything the ledger said about it:
+
+    ```text
+    {
+        "ver": 1,
+        "receipt": <json>,          // the receipt of the output, with its inclusion proof
+        "request": <json>,          // the request as submitted, with the signatures of its inputs
+        "reply": <json>,            // the REPLY of the ledger
+        "verkeys": {<string>: <string>, ...},   // the verkeys of the addresses involved, by address
+        "signer": <string>,         // the payment address which signed the bundle
+        "signature": <string>       // base58 signature of the bundle
+    }
+    ```
+
+    The signed message is `sovtoken-payment-proof:` followed by the json of
+    the bundle without its signature, keys sorted. The bundle is verified as
+    it is given, so it doesn't matter how the verifier formats amounts.
+
+    [`verify_payment_proof`] needs no wallet nor ledger. Without a root hash
+    from another source, the receipt is checked against the root of the
+    reply, which only shows the bundle is consistent.
+
+    [`verify_payment_proof`]: fn.verify_payment_proof.html
+*/
+
+use std::collections::BTreeMap;
+
+use indy::{IndyHandle, ErrorCode};
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+
+use logic::address;
+use logic::inclusion_proof::{self, InclusionProof};
+use logic::indy_sdk_api::crypto_api::CryptoAPI;
+use logic::pending_txn::{request_inputs, request_outputs};
+use logic::receipt::Receipt;
+use logic::signing_payload::{is_signed_by, signing_payload};
+use logic::type_aliases::{TokenAmount, TxnSeqNo};
+use utils::constants::txn_fields::FEES;
+use utils::constants::txn_types::XFER_PUBLIC;
+
+/**
+    The version of the bundle emitted.
+*/
+pub const PAYMENT_PROOF_VERSION: u32 = 1;
+
+/**
+    The prefix of every signed bundle.
+*/
+pub const PAYMENT_PROOF_PREFIX: &str = "sovtoken-payment-proof:";
+
+/**
+    A proof of payment, see the module doc for its json.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PaymentProof {
+    pub ver: u32,
+    pub receipt: Receipt,
+    pub request: Value,
+    pub reply: Value,
+    pub verkeys: BTreeMap<String, String>,
+    pub signer: String,
+    pub signature: String,
+}
+
+/**
+    What verifying a proof of payment found.
+
+    ```text
+    {
+        "valid": <bool>,        // all of the below
+        "signed": <bool>,       // the signer signed the bundle
+        "consistent": <bool>,   // the reply is the one of the request, with the output of the receipt
+        "inputsSigned": <bool>, // the inputs of the request are signed by the keys of their addresses
+        "included": <bool>,     // the audit path of the receipt leads to rootHash
+        "rootHash": <string>    // the root the receipt was checked against
+    }
+    ```
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofVerification {
+    pub valid: bool,
+    pub signed: bool,
+    pub consistent: bool,
+    pub inputs_signed: bool,
+    pub included: bool,
+    pub root_hash: String,
+}
+
+/**
+    Bundle the proof of the payment of `receipt_json` and sign it with the key of `signer`.
+
+    The receipt has to have an inclusion proof, i.e. be parsed with
+    `verbose_receipts`, from `reply_json`, the reply to `request_json`.
+    `cb` is called with the json of the signed bundle.
+
+    Fails with `CommonInvalidStructure` when the jsons are malformed or
+    don't belong together.
+*/
+pub fn export_payment_proof<A: CryptoAPI>(
+    crypto_api: &'static A,
+    wallet_handle: IndyHandle,
+    receipt_json: &str,
+    request_json: &str,
+    reply_json: &str,
+    signer: &str,
+    cb: Box<Fn(Result<String, ErrorCode>) + Send + Sync>
+) -> Result<(), ErrorCode> {
+    trace!("logic::payment_proof::export_payment_proof >> signer: {:?}", signer);
+    let signer_verkey = address::validate_address(signer)?;
+    let receipt: Receipt = parse(receipt_json)?;
+    let request: Value = parse(request_json)?;
+    let reply: Value = parse(reply_json)?;
+
+    if !is_consistent(&receipt, &request, &reply) {
+        error!("The receipt {} isn't an output of the request and its reply", receipt.receipt);
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let verkeys = involved_verkeys(&request, signer)?;
+    let mut bundle = json!({
+        "ver": PAYMENT_PROOF_VERSION,
+        "receipt": serde_json::to_value(&receipt).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidState))?,
+        "request": request,
+        "reply": reply,
+        "verkeys": verkeys,
+        "signer": signer,
+    });
+
+    let message = signed_message(&bundle)?;
+    let ec = crypto_api.indy_crypto_sign(wallet_handle, signer_verkey, message.as_bytes(), move |signature| {
+        cb(signature.map(|signature| {
+            bundle["signature"] = json!(signature);
+            bundle.to_string()
+        }))
+    });
+
+    match ec {
+        ErrorCode::Success => Ok(()),
+        ec => Err(ec),
+    }
+}
+
+/**
+    Verify a proof of payment made by [`export_payment_proof`].
+
+    The receipt is checked against `root_hash` when given, a base58 root
+    known from another source, else against the root in the proof.
+
+    Fails with `CommonInvalidStructure` when the proof is malformed or of
+    another version; a proof which doesn't hold is a verification with
+    `valid` false.
+
+    [`export_payment_proof`]: fn.export_payment_proof.html
+*/
+pub fn verify_payment_proof(proof_json: &str, root_hash: Option<&str>) -> Result<ProofVerification, ErrorCode> {
+    trace!("logic::payment_proof::verify_payment_proof >> root_hash: {:?}", root_hash);
+    let mut bundle: Value = parse(proof_json)?;
+    let proof: PaymentProof = serde_json::from_value(bundle.clone())
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    if proof.ver != PAYMENT_PROOF_VERSION {
+        error!("Proofs of payment of version {} aren't known, only {}", proof.ver, PAYMENT_PROOF_VERSION);
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let root_hash = match (root_hash, proof.receipt.proof.as_ref()) {
+        (Some(root_hash), _) => root_hash.to_string(),
+        (None, Some(inclusion)) => inclusion.root_hash.clone(),
+        (None, None) => {
+            error!("The receipt {} has no inclusion proof", proof.receipt.receipt);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+    };
+
+    if let Some(bundle) = bundle.as_object_mut() {
+        bundle.remove("signature");
+    }
+    let signer_verkey = address::validate_address(&proof.signer)?;
+    let signed = is_signed_by(&signer_verkey, signed_message(&bundle)?.as_bytes(), &proof.signature);
+
+    let consistent = is_consistent(&proof.receipt, &proof.request, &proof.reply)
+        && involved_verkeys(&proof.request, &proof.signer)? == proof.verkeys;
+    let inputs_signed = inputs_signed(&proof.request)?;
+    let included = inclusion_proof::verify_inclusion(&proof.receipt, &root_hash)?;
+
+    let verification = ProofVerification {
+        valid: signed && consistent && inputs_signed && included,
+        signed,
+        consistent,
+        inputs_signed,
+        included,
+        root_hash,
+    };
+    trace!("logic::payment_proof::verify_payment_proof << verification: {:?}", verification);
+    Ok(verification)
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn parse<T: DeserializeOwned>(json: &str) -> Result<T, ErrorCode> {
+    serde_json::from_str(json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))
+}
+
+// The keys are sorted, serde_json keeps objects in a BTreeMap.
+fn signed_message(bundle: &Value) -> Result<String, ErrorCode> {
+    let json = serde_json::to_string(bundle)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))?;
+    Ok(format!("{}{}", PAYMENT_PROOF_PREFIX, json))
+}
+
+// The verkeys of the addresses of the inputs and outputs of the request and of the signer.
+fn involved_verkeys(request: &Value, signer: &str) -> Result<BTreeMap<String, String>, ErrorCode> {
+    let request = request.to_string();
+    let addresses = request_inputs(&request)?.into_iter().map(|(address, _)| address)
+        .chain(request_outputs(&request)?.into_iter().map(|(address, _)| address))
+        .chain(Some(address::unqualified_part(signer).to_string()));
+
+    let mut verkeys = BTreeMap::new();
+    for unqualified in addresses {
+        let verkey = address::verkey_from_unqualified_address(&unqualified)?;
+        verkeys.insert(address::address_from_unqualified_address(&unqualified)?, verkey);
+    }
+    Ok(verkeys)
+}
+
+// Whether the reply is the REPLY to the request, with its reqId and the
+// same inputs and outputs, and the receipt an output of both.
+fn is_consistent(receipt: &Receipt, request: &Value, reply: &Value) -> bool {
+    if reply["op"] != json!("REPLY") || receipt.proof.is_none() {
+        return false;
+    }
+
+    let proof = InclusionProof::from_reply(&reply.to_string()).ok();
+    let req_id = &reply["result"]["txn"]["metadata"]["reqId"];
+    if proof != receipt.proof || req_id.is_null() || *req_id != request["reqId"] {
+        return false;
+    }
+
+    let request_transfers = transfers(request);
+    let reply_transfers = transfers(&reply_as_request(&reply["result"]));
+    let pays_receipt = request_transfers.as_ref()
+        .map(|&(_, ref outputs)| outputs.contains(&(address::unqualified_part(&receipt.recipient).to_string(), receipt.amount)))
+        .unwrap_or(false);
+
+    pays_receipt && request_transfers.is_some() && request_transfers == reply_transfers
+}
+
+// The inputs and outputs of a request, sorted, `None` when it's malformed.
+fn transfers(request: &Value) -> Option<(Vec<(String, TxnSeqNo)>, Vec<(String, TokenAmount)>)> {
+    let request = request.to_string();
+    let mut inputs = request_inputs(&request).ok()?;
+    let mut outputs = request_outputs(&request).ok()?;
+    inputs.sort();
+    outputs.sort();
+    Some((inputs, outputs))
+}
+
+// The transaction of a reply as a request: the inputs and outputs of its
+// data and of its fees, e.g. of a NYM paid with fees.
+fn reply_as_request(result: &Value) -> Value {
+    let fees = &result[FEES];
+    json!({
+        "operation": result["txn"]["data"],
+        "fees": [fees["inputs"], fees["outputs"]],
+    })
+}
+
+// Whether every input of the request has a signature by the key of its address.
+fn inputs_signed(request: &Value) -> Result<bool, ErrorCode> {
+    let signatures = if request["operation"]["type"] == json!(XFER_PUBLIC) {
+        &request["operation"]["signatures"]
+    } else {
+        &request[FEES][2]
+    };
+    let signatures = match signatures.as_array() {
+        Some(signatures) => signatures,
+        None => return Ok(false),
+    };
+
+    let request = request.to_string();
+    let input_count = request_inputs(&request)?.len();
+    if input_count == 0 || signatures.len() != input_count {
+        return Ok(false);
+    }
+
+    for (input_index, signature) in signatures.iter().enumerate() {
+        let payload = signing_payload(&request, input_index)?;
+        if !is_signed_by(&payload.verkey, payload.message.as_bytes(), signature.as_str().unwrap_or("")) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+
+#[cfg(test)]
+mod payment_proof_tests {
+    use std::sync::{Arc, Mutex};
+
+    use sodiumoxide::crypto::sign::sign_detached;
+
+    use logic::config::payment_address_config::PaymentAddressConfig;
+    use logic::hash::Hash;
+    use utils::base58::{self, Checksum, IntoBase58};
+    use utils::test::keys::{keypair, payer};
+    use super::*;
+
+    static RECIPIENT: &'static str = "pay:sov:a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7";
+
+    // signs with the key of `payer()`
+    struct CryptoApiHandler {}
+
+    impl CryptoAPI for CryptoApiHandler {
+        fn indy_create_key(&self, _: IndyHandle, _: PaymentAddressConfig) -> Result<String, ErrorCode> {
+            Err(ErrorCode::CommonInvalidState)
+        }
+
+        fn indy_create_key_async<F: 'static>(&self, _: i32, _: PaymentAddressConfig, _: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+            ErrorCode::CommonInvalidState
+        }
+
+        fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _: IndyHandle, verkey: String, message: &[u8], mut cb: F) -> ErrorCode {
+            let (public_key, secret_key) = keypair();
+            assert_eq!(public_key.into_base58(), verkey);
+            cb(Ok(sign_detached(message, &secret_key).into_base58()));
+            ErrorCode::Success
+        }
+    }
+
+    static CRYPTO_API: CryptoApiHandler = CryptoApiHandler {};
+
+    fn request() -> Value {
+        let mut request = json!({
+            "operation": {
+                "type": XFER_PUBLIC,
+                "inputs": [{"address": address::unqualified_part(&payer()), "seqNo": 1}],
+                "outputs": [{"address": address::unqualified_part(RECIPIENT), "amount": 10}],
+                "signatures": []
+            },
+            "identifier": "LibsovtokenDid11111111",
+            "reqId": 7
+        });
+        let payload = signing_payload(&request.to_string(), 0).unwrap();
+        request["operation"]["signatures"] = json!([sign_detached(payload.message.as_bytes(), &keypair().1).into_base58()]);
+        request
+    }
+
+    // the reply of the second transaction of the ledger
+    fn reply() -> Value {
+        let request = request();
+        reply_of(&request["reqId"], &request["operation"]["outputs"])
+    }
+
+    fn reply_of(req_id: &Value, outputs: &Value) -> Value {
+        let mut result = json!({
+            "txn": {
+                "data": {"inputs": [{"address": address::unqualified_part(&payer()), "seqNo": 1}], "outputs": outputs},
+                "metadata": {"reqId": req_id},
+                "type": XFER_PUBLIC
+            },
+            "txnMetadata": {"seqNo": 2, "txnTime": 1529682415},
+            "ver": "1"
+        });
+        let leaf = InclusionProof { root_hash: String::new(), audit_path: vec![], ledger_size: 2, txn: result.clone() }.leaf_hash().unwrap();
+        let sibling = Hash::hash_leaf(&b"first").unwrap().to_vec();
+        let root = Hash::hash_nodes(&sibling, &leaf).unwrap().to_vec();
+
+        result["rootHash"] = json!(base58::encode(&root, Checksum::None));
+        result["auditPath"] = json!([base58::encode(&sibling, Checksum::None)]);
+        json!({"op": "REPLY", "result": result})
+    }
+
+    fn receipt() -> Receipt {
+        receipt_of(&reply())
+    }
+
+    fn receipt_of(reply: &Value) -> Receipt {
+        let mut receipt = Receipt::new(RECIPIENT, 10, 2, Some(1529682415), None).unwrap();
+        receipt.proof = Some(InclusionProof::from_reply(&reply.to_string()).unwrap());
+        receipt
+    }
+
+    fn export(receipt: &Receipt, request: &Value) -> Result<String, ErrorCode> {
+        export_with_reply(receipt, request, &reply())
+    }
+
+    fn export_with_reply(receipt: &Receipt, request: &Value, reply: &Value) -> Result<String, ErrorCode> {
+        let proof = Arc::new(Mutex::new(None));
+        let proof_clone = Arc::clone(&proof);
+        let receipt_json = serde_json::to_string(receipt).unwrap();
+
+        export_payment_proof(&CRYPTO_API, 1, &receipt_json, &request.to_string(), &reply.to_string(), &payer(), Box::new(move |result| {
+            *proof_clone.lock().unwrap() = Some(result);
+        }))?;
+
+        let result = proof.lock().unwrap().take().unwrap();
+        result
+    }
+
+    #[test]
+    fn export_and_verify() {
+        let proof = export(&receipt(), &request()).unwrap();
+        let verification = verify_payment_proof(&proof, None).unwrap();
+
+        assert!(verification.valid, "{:?}", verification);
+        assert_eq!(receipt().proof.unwrap().root_hash, verification.root_hash);
+
+        let bundle: PaymentProof = serde_json::from_str(&proof).unwrap();
+        assert_eq!(Some(&keypair().0.into_base58()), bundle.verkeys.get(&payer()));
+        assert!(bundle.verkeys.contains_key(RECIPIENT));
+    }
+
+    #[test]
+    fn verify_against_other_root() {
+        let proof = export(&receipt(), &request()).unwrap();
+        let other_root = base58::encode(&Hash::hash_leaf(&b"other").unwrap().to_vec(), Checksum::None);
+
+        let verification = verify_payment_proof(&proof, Some(&other_root)).unwrap();
+        assert!(verification.signed && verification.consistent && verification.inputs_signed);
+        assert!(!verification.included);
+        assert!(!verification.valid);
+    }
+
+    #[test]
+    fn verify_tampered_proof() {
+        let proof = export(&receipt(), &request()).unwrap();
+        let mut bundle: Value = serde_json::from_str(&proof).unwrap();
+        bundle["request"]["operation"]["outputs"][0]["amount"] = json!(11);
+
+        let verification = verify_payment_proof(&bundle.to_string(), None).unwrap();
+        assert!(!verification.signed);
+        assert!(!verification.consistent);
+        assert!(!verification.inputs_signed);
+    }
+
+    #[test]
+    fn export_of_another_output() {
+        let mut receipt = receipt();
+        receipt.amount = 9;
+        assert_eq!(ErrorCode::CommonInvalidStructure, export(&receipt, &request()).unwrap_err());
+    }
+
+    #[test]
+    fn export_with_the_reply_of_another_request() {
+        let outputs = request()["operation"]["outputs"].clone();
+        for req_id in &[json!(8), Value::Null] {
+            let reply = reply_of(req_id, &outputs);
+            assert_eq!(ErrorCode::CommonInvalidStructure, export_with_reply(&receipt_of(&reply), &request(), &reply).unwrap_err());
+        }
+    }
+
+    #[test]
+    fn export_with_a_reply_of_other_outputs() {
+        // the reply pays the receipt too, and one more output
+        let outputs = json!([
+            {"address": address::unqualified_part(RECIPIENT), "amount": 10},
+            {"address": address::unqualified_part(&payer()), "amount": 5}
+        ]);
+        let reply = reply_of(&json!(7), &outputs);
+        assert_eq!(ErrorCode::CommonInvalidStructure, export_with_reply(&receipt_of(&reply), &request(), &reply).unwrap_err());
+    }
+
+    #[test]
+    fn export_without_inclusion_proof() {
+        let mut receipt = receipt();
+        receipt.proof = None;
+        assert_eq!(ErrorCode::CommonInvalidStructure, export(&receipt, &request()).unwrap_err());
+    }
+}
@@ -0,0 +1,289 @@
+/*!
+    Named payment templates stored in the wallet.
+
+    A template holds the recipients of a recurring payment, an optional memo
+    and a fee policy. Building a payment from a template only needs the amount
+    and the UTXOs to pay it with: the amount is split over the recipients by
+    their share, and whatever the UTXOs hold on top of the amount and the fee
+    goes back to the change address.
+*/
+
+use indy::{IndyHandle, ErrorCode};
+use serde_json;
+
+use logic::balance::validate_balance;
+use logic::indy_sdk_api::wallet_api::WalletAPI;
+use logic::output::{Output, Outputs};
+use logic::parsers::parse_get_utxo_response::UTXO;
+use logic::request_builder::PaymentRequestBuilder;
+use logic::type_aliases::TokenAmount;
+use utils::json_conversion::JsonDeserialize;
+
+/**
+    The wallet record type the templates are stored under.
+*/
+pub const TEMPLATE_RECORD_TYPE: &str = "sovtoken_payment_template";
+
+/**
+    A recipient of a template, paid `share` parts of the amount.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct TemplateRecipient {
+    pub recipient: String,
+    pub share: u64,
+}
+
+/**
+    How the fee of a payment built from a template is decided.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum FeePolicy {
+    /// The payment pays no fee.
+    None,
+    /// The payment pays a fixed fee.
+    Fixed { amount: TokenAmount },
+}
+
+impl Default for FeePolicy {
+    fn default() -> Self {
+        FeePolicy::None
+    }
+}
+
+impl FeePolicy {
+    pub fn fee(&self) -> TokenAmount {
+        match self {
+            &FeePolicy::None => 0,
+            &FeePolicy::Fixed { amount } => amount,
+        }
+    }
+}
+
+/**
+    A named payment template.
+
+    ```
+        use sovtoken::utils::json_conversion::JsonDeserialize;
+        use sovtoken::logic::payment_template::{PaymentTemplate, FeePolicy};
+
+        let template = PaymentTemplate::from_json(r#"{
+            "recipients": [{"recipient": "pay:sov:a", "share": 1}],
+            "extra": "monthly subscription",
+            "feePolicy": {"type": "fixed", "amount": 2},
+            "changeAddress": "pay:sov:b"
+        }"#).unwrap();
+
+        assert_eq!(FeePolicy::Fixed { amount: 2 }, template.fee_policy);
+    ```
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentTemplate {
+    pub recipients: Vec<TemplateRecipient>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra: Option<String>,
+    #[serde(default)]
+    pub fee_policy: FeePolicy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change_address: Option<String>,
+}
+
+impl PaymentTemplate {
+    /**
+        Validate the template has recipients and every share is positive.
+    */
+    pub fn validate(self) -> Result<Self, ErrorCode> {
+        if self.recipients.is_empty() || self.recipients.iter().any(|recipient| recipient.share == 0) {
+            error!("A payment template needs recipients with a positive share.");
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+        Ok(self)
+    }
+
+    /**
+        Split `amount` over the recipients by their share.
+
+        The remainder of the division goes to the first recipient.
+    */
+    pub fn outputs(&self, amount: TokenAmount) -> Result<Outputs, ErrorCode> {
+        let total_share = self.recipients.iter()
+            .fold(Some(0), |total: Option<u64>, recipient| total.and_then(|total| total.checked_add(recipient.share)))
+            .ok_or(ErrorCode::CommonInvalidStructure)?;
+
+        let mut outputs = self.recipients.iter()
+            .map(|recipient| {
+                let part = amount.checked_mul(recipient.share).ok_or(ErrorCode::CommonInvalidStructure)? / total_share;
+                Ok(Output::new(recipient.recipient.clone(), part))
+            })
+            .collect::<Result<Outputs, ErrorCode>>()?;
+
+        let paid: TokenAmount = outputs.iter().map(|output| output.amount).sum();
+        outputs[0].amount += amount - paid;
+
+        Ok(outputs)
+    }
+
+    /**
+        Start a [`PaymentRequestBuilder`] paying `amount` with `utxos`.
+
+        Adds a change output when the utxos hold more than the amount and the fee.
+
+        [`PaymentRequestBuilder`]: ../request_builder/struct.PaymentRequestBuilder.html
+    */
+    pub fn builder(&self, amount: TokenAmount, utxos: &Vec<UTXO>) -> Result<PaymentRequestBuilder, ErrorCode> {
+        let fee = self.fee_policy.fee();
+        let inputs_total = utxos.iter()
+            .fold(Some(0), |total: Option<TokenAmount>, utxo| total.and_then(|total| total.checked_add(utxo.amount)))
+            .ok_or(ErrorCode::CommonInvalidStructure)?;
+
+        let mut outputs = self.outputs(amount)?;
+
+        match (inputs_total.checked_sub(amount).and_then(|rest| rest.checked_sub(fee)), &self.change_address) {
+            (Some(change), &Some(ref change_address)) if change > 0 => {
+                outputs.push(Output::new(change_address.clone(), change));
+            },
+            _ => validate_balance(inputs_total, amount, fee).map_err(map_err_err!()).map_err(ErrorCode::from)?,
+        };
+
+        let mut builder = utxos.iter().fold(Ok(PaymentRequestBuilder::new()), |builder, utxo| {
+            builder.and_then(|builder: PaymentRequestBuilder| builder.utxo(utxo))
+        })?;
+
+        for output in outputs {
+            builder = builder.output(output);
+        }
+
+        if let Some(ref extra) = self.extra {
+            builder = builder.extra(extra);
+        }
+
+        Ok(builder.fee(fee))
+    }
+}
+
+/**
+    TemplateStore reads and writes payment templates in the wallet.
+*/
+pub struct TemplateStore<T> where T: WalletAPI {
+    injected_api: T
+}
+
+impl<T: WalletAPI> TemplateStore<T> {
+    pub fn new(api_handler: T) -> Self {
+        TemplateStore { injected_api: api_handler }
+    }
+
+    /**
+        Store `template` as `name`, replacing a template with the same name.
+    */
+    pub fn store(&self, wallet_handle: IndyHandle, name: &str, template: &PaymentTemplate) -> Result<(), ErrorCode> {
+        trace!("logic::payment_template::store >> name: {:?}, template: {:?}", name, template);
+        let value = serde_json::to_string(template)
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonInvalidState))?;
+
+        match self.injected_api.indy_add_record(wallet_handle, TEMPLATE_RECORD_TYPE, name, &value) {
+            Err(ErrorCode::WalletItemAlreadyExists) => self.injected_api.indy_update_record_value(wallet_handle, TEMPLATE_RECORD_TYPE, name, &value),
+            res => res,
+        }
+    }
+
+    /**
+        Get the template stored as `name`.
+
+        Returns `WalletItemNotFound` when there is no such template.
+    */
+    pub fn get(&self, wallet_handle: IndyHandle, name: &str) -> Result<PaymentTemplate, ErrorCode> {
+        let value = self.injected_api.indy_get_record_value(wallet_handle, TEMPLATE_RECORD_TYPE, name)?;
+        PaymentTemplate::from_json(&value)
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonInvalidState))
+    }
+}
+
+
+#[cfg(test)]
+mod payment_template_tests {
+    use logic::parsers::common::TXO;
+    use utils::test::wallet::WalletMockHandler;
+    use super::*;
+
+    static WALLET_HANDLE: i32 = 1;
+    static INPUT_ADDRESS: &'static str = "pay:sov:E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm";
+    static CHANGE_ADDRESS: &'static str = "pay:sov:2FKYJkgXRZtjhFpTMHhuyfc17BHZWcFPyF2MWy2SZMBaSo64fb";
+
+    fn template(fee_policy: FeePolicy, change_address: Option<&str>) -> PaymentTemplate {
+        PaymentTemplate {
+            recipients: vec![
+                TemplateRecipient { recipient: String::from("pay:sov:a"), share: 2 },
+                TemplateRecipient { recipient: String::from("pay:sov:b"), share: 1 },
+            ],
+            extra: Some(String::from("memo")),
+            fee_policy,
+            change_address: change_address.map(String::from),
+        }
+    }
+
+    fn utxo(amount: TokenAmount) -> UTXO {
+        UTXO {
+            payment_address: INPUT_ADDRESS.to_string(),
+            source: TXO { address: INPUT_ADDRESS.to_string(), seq_no: 1 }.to_libindy_string().unwrap(),
+            amount,
+            extra: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn outputs_split_by_share() {
+        let outputs = template(FeePolicy::None, None).outputs(10).unwrap();
+        assert_eq!(vec![
+            Output::new(String::from("pay:sov:a"), 7),
+            Output::new(String::from("pay:sov:b"), 3),
+        ], outputs);
+    }
+
+    #[test]
+    fn validate_zero_share() {
+        let mut template = template(FeePolicy::None, None);
+        template.recipients[1].share = 0;
+        assert_eq!(ErrorCode::CommonInvalidStructure, template.validate().unwrap_err());
+    }
+
+    #[test]
+    fn builder_adds_change() {
+        let payload = template(FeePolicy::Fixed { amount: 2 }, Some(CHANGE_ADDRESS))
+            .builder(9, &vec![utxo(20)]).unwrap()
+            .build().unwrap();
+
+        assert_eq!(Output::new(CHANGE_ADDRESS.to_string(), 9), payload.outputs[2]);
+        assert_eq!(Some(String::from("memo")), payload.extra);
+    }
+
+    #[test]
+    fn builder_without_change_address() {
+        let error = template(FeePolicy::None, None).builder(9, &vec![utxo(20)]).unwrap_err();
+        assert_eq!(ErrorCode::PaymentExtraFundsError, error);
+    }
+
+    #[test]
+    fn builder_insufficient_fee() {
+        let error = template(FeePolicy::Fixed { amount: 2 }, Some(CHANGE_ADDRESS)).builder(9, &vec![utxo(10)]).unwrap_err();
+        assert_eq!(ErrorCode::PaymentInsufficientFundsError, error);
+    }
+
+    #[test]
+    fn store_and_replace() {
+        let store = TemplateStore::new(WalletMockHandler::default());
+        store.store(WALLET_HANDLE, "rent", &template(FeePolicy::None, None)).unwrap();
+        store.store(WALLET_HANDLE, "rent", &template(FeePolicy::Fixed { amount: 1 }, None)).unwrap();
+
+        assert_eq!(FeePolicy::Fixed { amount: 1 }, store.get(WALLET_HANDLE, "rent").unwrap().fee_policy);
+    }
+
+    #[test]
+    fn get_unknown_template() {
+        let store = TemplateStore::new(WalletMockHandler::default());
+        assert_eq!(ErrorCode::WalletItemNotFound, store.get(WALLET_HANDLE, "rent").unwrap_err());
+    }
+}
@@ -0,0 +1,193 @@
+/*!
+    Payment URIs for QR codes.
+
+    A payment URI asks for a payment to an address:
+
+    ```text
+    sov:pay?address=pay:sov:<address>&amount=<int>&memo=<percent encoded text>
+    ```
+
+    `amount` and `memo` are optional. Wallets showing a QR code build the URI
+    with [`PaymentUri::to_uri`], wallets scanning one read it with
+    [`PaymentUri::parse`].
+
+    [`PaymentUri::to_uri`]: struct.PaymentUri.html#method.to_uri
+    [`PaymentUri::parse`]: struct.PaymentUri.html#method.parse
+*/
+
+use indy::ErrorCode;
+
+use logic::address;
+use logic::type_aliases::TokenAmount;
+
+/**
+    The scheme and path every payment URI starts with.
+*/
+pub const PAYMENT_URI_PREFIX: &str = "sov:pay?";
+
+/**
+    The longest memo accepted, in bytes before encoding.
+*/
+pub const MAX_MEMO_LEN: usize = 256;
+
+/**
+    A request for a payment, encoded as a payment URI.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PaymentUri {
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<TokenAmount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+impl PaymentUri {
+    /**
+        Validate the address and memo of a payment request.
+    */
+    pub fn new(address: &str, amount: Option<TokenAmount>, memo: Option<&str>) -> Result<PaymentUri, ErrorCode> {
+        address::validate_address(address)?;
+
+        if let Some(memo) = memo {
+            if memo.len() > MAX_MEMO_LEN {
+                error!("A payment URI memo can have {} bytes, found {}", MAX_MEMO_LEN, memo.len());
+                return Err(ErrorCode::CommonInvalidStructure);
+            }
+        }
+
+        Ok(PaymentUri {
+            address: address.to_string(),
+            amount,
+            memo: memo.map(String::from),
+        })
+    }
+
+    /**
+        The URI, with the parameters in the order address, amount, memo.
+
+        ```
+        use sovtoken::logic::payment_uri::PaymentUri;
+        let uri = PaymentUri::new("pay:sov:2Viu9qrpqM48PSw3vdoQoFKP5AvYTChUZhwWtCydfW9iu7ftRt", Some(10), Some("coffee & cake")).unwrap();
+        assert_eq!("sov:pay?address=pay:sov:2Viu9qrpqM48PSw3vdoQoFKP5AvYTChUZhwWtCydfW9iu7ftRt&amount=10&memo=coffee%20%26%20cake", uri.to_uri());
+        ```
+    */
+    pub fn to_uri(&self) -> String {
+        let mut uri = format!("{}address={}", PAYMENT_URI_PREFIX, self.address);
+        if let Some(amount) = self.amount {
+            uri.push_str(&format!("&amount={}", amount));
+        }
+        if let Some(ref memo) = self.memo {
+            uri.push_str(&format!("&memo={}", percent_encode(memo)));
+        }
+        uri
+    }
+
+    /**
+        Read a payment URI.
+
+        Unknown parameters are ignored, so later versions can add some.
+    */
+    pub fn parse(uri: &str) -> Result<PaymentUri, ErrorCode> {
+        if !uri.starts_with(PAYMENT_URI_PREFIX) {
+            error!("A payment URI starts with {:?}", PAYMENT_URI_PREFIX);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+
+        let mut address = None;
+        let mut amount = None;
+        let mut memo = None;
+
+        for parameter in uri[PAYMENT_URI_PREFIX.len()..].split('&') {
+            let mut parts = parameter.splitn(2, '=');
+            let name = parts.next().unwrap_or("");
+            let value = parts.next().ok_or(ErrorCode::CommonInvalidStructure)?;
+
+            match name {
+                "address" => address = Some(percent_decode(value)?),
+                "amount" => amount = Some(value.parse::<TokenAmount>().or(Err(ErrorCode::CommonInvalidStructure))?),
+                "memo" => memo = Some(percent_decode(value)?),
+                name => debug!("Ignoring payment URI parameter {:?}", name),
+            }
+        }
+
+        let address = address.ok_or(ErrorCode::CommonInvalidStructure)?;
+        PaymentUri::new(&address, amount, memo.as_ref().map(String::as_str))
+    }
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// keeps the unreserved characters of RFC 3986 and ':', encodes the rest
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' | b':' => encoded.push(byte as char),
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn percent_decode(encoded: &str) -> Result<String, ErrorCode> {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = encoded.get(i + 1..i + 3).ok_or(ErrorCode::CommonInvalidStructure)?;
+            decoded.push(u8::from_str_radix(hex, 16).or(Err(ErrorCode::CommonInvalidStructure))?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).or(Err(ErrorCode::CommonInvalidStructure))
+}
+
+
+#[cfg(test)]
+mod payment_uri_tests {
+    use super::*;
+
+    static ADDRESS: &'static str = "pay:sov:2Viu9qrpqM48PSw3vdoQoFKP5AvYTChUZhwWtCydfW9iu7ftRt";
+
+    #[test]
+    fn address_only() {
+        let uri = PaymentUri::new(ADDRESS, None, None).unwrap();
+        assert_eq!(format!("sov:pay?address={}", ADDRESS), uri.to_uri());
+        assert_eq!(uri, PaymentUri::parse(&uri.to_uri()).unwrap());
+    }
+
+    #[test]
+    fn memo_round_trip() {
+        let uri = PaymentUri::new(ADDRESS, Some(25), Some("invoice #12 & tip: 5%, café")).unwrap();
+        assert_eq!(uri, PaymentUri::parse(&uri.to_uri()).unwrap());
+    }
+
+    #[test]
+    fn unknown_parameters_are_ignored() {
+        let uri = format!("sov:pay?label=shop&address={}&amount=3", ADDRESS);
+        assert_eq!(Some(3), PaymentUri::parse(&uri).unwrap().amount);
+    }
+
+    #[test]
+    fn invalid_uris() {
+        let invalid = vec![
+            String::from("bitcoin:pay?address=abc"),
+            String::from("sov:pay?amount=3"),
+            format!("sov:pay?address={}&amount=-3", ADDRESS),
+            format!("sov:pay?address={}&memo=%G1", ADDRESS),
+            String::from("sov:pay?address=pay:sov:abc"),
+        ];
+
+        for uri in invalid {
+            assert_eq!(ErrorCode::CommonInvalidStructure, PaymentUri::parse(&uri).unwrap_err(), "{}", uri);
+        }
+    }
+}
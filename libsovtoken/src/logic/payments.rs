@@ -1,10 +1,52 @@
 //! Payments module contains functions for working with payments.  :D
 
 use indy::ErrorCode;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
 use logic::config::payment_address_config::PaymentAddressConfig;
+use logic::error::SovtokenError;
 use logic::indy_sdk_api::crypto_api::{CryptoAPI};
+use logic::indy_sdk_api::payment_api::PaymentAPI;
 use logic::address;
+use logic::messages;
 
+/**
+    An address created by [`create_payment_addresses_async`], with the index of its config.
+
+    [`create_payment_addresses_async`]: fn.create_payment_addresses_async.html
+*/
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CreatedAddress {
+    pub index: usize,
+    pub address: String,
+}
+
+/**
+    A config of [`create_payment_addresses_async`] no address was created for.
+
+    `error` is the error code a single creation would have returned.
+
+    [`create_payment_addresses_async`]: fn.create_payment_addresses_async.html
+*/
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FailedAddress {
+    pub index: usize,
+    pub error: i32,
+    pub message: String,
+}
+
+/**
+    What [`create_payment_addresses_async`] did with each config, by index.
+
+    [`create_payment_addresses_async`]: fn.create_payment_addresses_async.html
+*/
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchAddressResult {
+    pub created: Vec<CreatedAddress>,
+    pub failed: Vec<FailedAddress>,
+    /// Configs not tried after a failure, with `stop_on_failure`.
+    pub skipped: Vec<usize>,
+}
 
 // ------------------------------------------------------------------
 // CreatePaymentHandler
@@ -30,49 +72,246 @@ impl<T: CryptoAPI> CreatePaymentHandler<T> {
         To get a sovrin address
         the format of the return is:
             pay:sov:{32 byte address}{4 byte checksum}
+
+        A seed whose key is already in the wallet fails with
+        `WalletItemAlreadyExists`, or gives the existing address with
+        `reuse_existing`, see [`PaymentAddressConfig`].
+
+        [`PaymentAddressConfig`]: ../config/payment_address_config/struct.PaymentAddressConfig.html
     */
     pub fn create_payment_address(&self, wallet_id: i32, config: PaymentAddressConfig) -> Result<String, ErrorCode> {
+        let existing = ExistingAddress::of(&config);
+
         trace!("calling self.injected_api.indy_create_key");
-        let verkey = self.injected_api.indy_create_key(wallet_id, config)?;
+        let verkey = match self.injected_api.indy_create_key(wallet_id, config) {
+            Ok(verkey) => verkey,
+            Err(ec) => return existing.or_error(wallet_id, ec).map_err(ErrorCode::from),
+        };
 
         trace!("got verkey from self.injected_api.indy_create_key {}", verkey);
-        return address::qualified_address_from_verkey(&verkey);
+        return address::qualified_address_from_verkey(&verkey)
+            .map_err(|ec| log_failed_address(&verkey, ec));
     }
 
     /**
         To get a sovrin address asynchronously.
         the format of the string sent via the callback is:
             pay:sov:{32 byte address}{4 byte checksum}
+
+        The callback gets the error of the wallet when the key can't be
+        created, e.g. `AlreadyExists` with the existing address in its
+        message for a seed used before, unless the config asks to reuse the
+        existing address. The error isn't kept as the last error, the
+        callback runs on a thread of libindy. When the key creation can't
+        even start, the error is returned and the callback isn't called.
     */
     pub fn create_payment_address_async<F: 'static>(&self,
                                      wallet_id: i32,
                                      config: PaymentAddressConfig,
-                                     mut cb : F) -> ErrorCode where F: FnMut(String, ErrorCode) + Send {
+                                     mut cb : F) -> Result<(), ErrorCode> where F: FnMut(Result<String, SovtokenError>) + Send {
 
+        let existing = ExistingAddress::of(&config);
         let cb_closure = move | err: ErrorCode, verkey : String | {
-            let res = if ErrorCode::Success == err {
+            let address = if ErrorCode::Success == err {
                 trace!("got verkey from self.injected_api.indy_create_key_async {}", verkey);
                 address::qualified_address_from_verkey(&verkey)
+                    .map_err(|ec| SovtokenError::from(log_failed_address(&verkey, ec)))
             } else {
-                Err(err)
+                existing.or_error(wallet_id, err)
             };
 
-            match res {
-                Ok(address) => cb(address, err),
-                Err(e) => {
-                    error!("got error {:?} from self.injected_api.indy_create_key_async", err);
-                    cb("".to_string(), e)
+            cb(address);
+        };
+
+        trace!("calling injected_api.indy_create_key_async");
+        match self.injected_api.indy_create_key_async(wallet_id, config, cb_closure) {
+            ErrorCode::Success => Ok(()),
+            ec => {
+                error!("create payment address failed starting the key creation in wallet {}: {:?}", wallet_id, ec);
+                Err(ec)
+            }
+        }
+    }
+
+    /**
+        The sovrin address of a key already in the wallet, e.g. the verkey of a DID.
+
+        The address is derived from `verkey` as for a created key, no key is
+        created. The key is checked first, so the callback gets the error
+        of the wallet, e.g. `WalletItemNotFound`, when the wallet doesn't
+        hold the key. An abbreviated verkey isn't accepted.
+    */
+    pub fn payment_address_from_key<F: 'static>(&self,
+                                     wallet_id: i32,
+                                     verkey: &str,
+                                     mut cb: F) -> Result<(), ErrorCode> where F: FnMut(Result<String, ErrorCode>) + Send {
+
+        let address = address::qualified_address_from_verkey(verkey)
+            .map_err(|ec| log_failed_address(verkey, ec))?;
+
+        let wallet_verkey = verkey.to_string();
+        let cb_closure = move |key: Result<(), ErrorCode>| {
+            match key {
+                Ok(()) => cb(Ok(address.clone())),
+                Err(ec) => {
+                    error!("payment address from key failed, wallet {} can't sign with {:?}: {:?}", wallet_id, wallet_verkey, ec);
+                    cb(Err(ec))
                 }
             }
         };
 
-        trace!("calling injected_api.indy_create_key_async");
-        let result_code = self.injected_api.indy_create_key_async(wallet_id, config, cb_closure);
+        trace!("calling injected_api.indy_check_key");
+        match self.injected_api.indy_check_key(wallet_id, verkey.to_string(), cb_closure) {
+            ErrorCode::Success => Ok(()),
+            ec => {
+                error!("payment address from key failed starting the signing in wallet {}: {:?}", wallet_id, ec);
+                Err(ec)
+            }
+        }
+    }
+}
+
+/**
+    Create an address for each config with libindy, one after the other,
+    without blocking, and call `cb` with what was done with each. libindy
+    records the addresses, `indy_list_payment_addresses` lists them.
+
+    A config which is invalid or whose address can't be created is listed
+    in the failures with its error, the others are still created. With
+    `stop_on_failure` the configs after the first failure are skipped
+    instead.
+
+    A created key stays in the wallet: libindy can't delete keys, so
+    created addresses aren't rolled back when a later one fails.
+*/
+pub fn create_payment_addresses_async<P, F>(payment_api: P,
+                                            wallet_id: i32,
+                                            configs: Vec<Result<String, SovtokenError>>,
+                                            stop_on_failure: bool,
+                                            cb: F) where P: PaymentAPI + Clone + Send + 'static, F: FnOnce(BatchAddressResult) + Send + 'static {
+    AddressBatch {
+        payment_api,
+        wallet_id,
+        configs: configs.into_iter().enumerate().collect(),
+        stop_on_failure,
+        result: BatchAddressResult::default(),
+        cb,
+    }.next();
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// The configs of a batch left to create, and what was done with the others.
+struct AddressBatch<P, F> {
+    payment_api: P,
+    wallet_id: i32,
+    configs: VecDeque<(usize, Result<String, SovtokenError>)>,
+    stop_on_failure: bool,
+    result: BatchAddressResult,
+    cb: F,
+}
 
-        return result_code;
+impl<P, F> AddressBatch<P, F> where P: PaymentAPI + Clone + Send + 'static, F: FnOnce(BatchAddressResult) + Send + 'static {
+    // Create the address of the next config, and of the ones after it once libindy called back.
+    fn next(mut self) {
+        let (index, config) = match self.configs.pop_front() {
+            Some(next) => next,
+            None => return (self.cb)(self.result),
+        };
+
+        if self.stop_on_failure && !self.result.failed.is_empty() {
+            self.result.skipped.push(index);
+            return self.next();
+        }
+
+        let config = match config {
+            Ok(config) => config,
+            Err(error) => {
+                self.failed(index, error);
+                return self.next();
+            }
+        };
+
+        // the batch goes on in the callback, or here when libindy couldn't start
+        let payment_api = self.payment_api.clone();
+        let wallet_id = self.wallet_id;
+        let batch = Arc::new(Mutex::new(Some(self)));
+        let pending = batch.clone();
+        let ec = payment_api.indy_create_payment_address_async(wallet_id, &config, move |address| {
+            let batch = pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take();
+            if let Some(batch) = batch {
+                batch.created(index, address.map_err(SovtokenError::from));
+            }
+        });
+
+        if ec != ErrorCode::Success {
+            error!("create payment address {} of the batch failed starting in wallet {}: {:?}", index, wallet_id, ec);
+            let batch = batch.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take();
+            if let Some(batch) = batch {
+                batch.created(index, Err(SovtokenError::from(ec)));
+            }
+        }
+    }
+
+    fn created(mut self, index: usize, address: Result<String, SovtokenError>) {
+        match address {
+            Ok(address) => self.result.created.push(CreatedAddress { index, address }),
+            Err(error) => self.failed(index, error),
+        }
+        self.next();
+    }
+
+    fn failed(&mut self, index: usize, error: SovtokenError) {
+        error!("create payment address {} of the batch failed: {}", index, error);
+        self.result.failed.push(FailedAddress { index, error: error.error_code() as i32, message: error.to_string() });
+    }
+}
+
+// The address of the key of a seed, in case the wallet already holds the key.
+struct ExistingAddress {
+    address: Option<String>,
+    reuse: bool,
+}
+
+impl ExistingAddress {
+    fn of(config: &PaymentAddressConfig) -> ExistingAddress {
+        let address = match config.verkey() {
+            Ok(Some(verkey)) => address::qualified_address_from_verkey(&verkey).ok(),
+            _ => None,
+        };
+        ExistingAddress { address, reuse: config.reuse_existing }
+    }
+
+    // The result of a failed key creation: the existing address when the
+    // key of the seed exists and may be reused, else the error, with the
+    // existing address in its message.
+    fn or_error(&self, wallet_id: i32, ec: ErrorCode) -> Result<String, SovtokenError> {
+        match (ec, &self.address) {
+            (ErrorCode::WalletItemAlreadyExists, &Some(ref address)) if self.reuse => {
+                info!("The key of the seed is already in wallet {}, reusing the payment address {}", wallet_id, address);
+                Ok(address.clone())
+            },
+            (ErrorCode::WalletItemAlreadyExists, &Some(ref address)) => {
+                Err(SovtokenError::AlreadyExists(messages::ADDRESS_ALREADY_EXISTS.with(&[address.clone()])))
+            },
+            _ => Err(SovtokenError::from(log_failed_key_creation(wallet_id, ec))),
+        }
     }
 }
 
+fn log_failed_key_creation(wallet_id: i32, ec: ErrorCode) -> ErrorCode {
+    error!("create payment address failed creating the key in wallet {}: {:?}", wallet_id, ec);
+    ec
+}
+
+fn log_failed_address(verkey: &str, ec: ErrorCode) -> ErrorCode {
+    error!("create payment address failed forming the address of verkey {:?}: {:?}", verkey, ec);
+    ec
+}
+
 
 // ------------------------------------------------------------------
 // unit tests
@@ -86,6 +325,8 @@ mod payments_tests {
     use std::time::Duration;
     use logic::address::*;
     use logic::address::address_tests::gen_random_base58_verkey;
+    use logic::messages;
+    use logic::parsers::parse_get_utxo_response::ParseGetUtxoReply;
     use utils::random::rand_string;
     use utils::constants::general::PAYMENT_ADDRESS_QUALIFIER;
     use utils::base58::FromBase58;
@@ -100,7 +341,7 @@ mod payments_tests {
             return Ok(gen_random_base58_verkey());
         }
 
-        fn indy_crypto_sign<F>(&self, _: i32, _: String, _: String, _: F) -> ErrorCode {
+        fn indy_crypto_sign<F>(&self, _: i32, _: String, _: &[u8], _: F) -> ErrorCode {
             return ErrorCode::CommonInvalidState;
         }
 
@@ -110,6 +351,96 @@ mod payments_tests {
         }
     }
 
+    // mock SDK api calls with a wallet which already has the key of the seed
+    struct DuplicateKeyMockHandler {}
+
+    impl CryptoAPI for DuplicateKeyMockHandler {
+        fn indy_create_key(&self, _wallet_id: i32, _config: PaymentAddressConfig) -> Result<String, ErrorCode> {
+            return Err(ErrorCode::WalletItemAlreadyExists);
+        }
+
+        fn indy_crypto_sign<F>(&self, _: i32, _: String, _: &[u8], _: F) -> ErrorCode {
+            return ErrorCode::CommonInvalidState;
+        }
+
+        fn indy_create_key_async<F: 'static>(&self, _wallet_id: i32, _config: PaymentAddressConfig, mut closure: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+            closure(ErrorCode::WalletItemAlreadyExists, String::new());
+            return ErrorCode::Success;
+        }
+    }
+
+
+    // mock SDK api calls with a wallet which holds only the key KNOWN_VERKEY
+    struct KnownKeyMockHandler {}
+
+    static KNOWN_VERKEY: &'static str = "EFfodscoymgdJDuM885uEWmgCcA25P6VR6TjVqsYZLW3";
+
+    impl CryptoAPI for KnownKeyMockHandler {
+        fn indy_create_key(&self, _wallet_id: i32, _config: PaymentAddressConfig) -> Result<String, ErrorCode> {
+            return Err(ErrorCode::CommonInvalidState);
+        }
+
+        fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _: i32, verkey: String, _: &[u8], mut cb: F) -> ErrorCode {
+            if verkey == KNOWN_VERKEY {
+                cb(Ok(String::from("signature")));
+            } else {
+                cb(Err(ErrorCode::WalletItemNotFound));
+            }
+            return ErrorCode::Success;
+        }
+
+        fn indy_create_key_async<F: 'static>(&self, _wallet_id: i32, _config: PaymentAddressConfig, _closure: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+            return ErrorCode::CommonInvalidState;
+        }
+    }
+
+    // mock libindy creating payment addresses, with a wallet which already has the key of
+    // FAILING_SEED and a config of UNSTARTED_SEED which can't even be started
+    #[derive(Clone)]
+    struct FailingSeedMockHandler {}
+
+    static FAILING_SEED: &'static str = "00000000000000000000000000Failed";
+    static UNSTARTED_SEED: &'static str = "0000000000000000000000000Unstarted";
+
+    impl PaymentAPI for FailingSeedMockHandler {
+        fn indy_list_payment_addresses(&self, _: i32) -> Result<Vec<String>, ErrorCode> {
+            return Ok(Vec::new());
+        }
+
+        fn indy_get_utxos(&self, _: i32, _: &str) -> Result<ParseGetUtxoReply, ErrorCode> {
+            return Err(ErrorCode::CommonInvalidState);
+        }
+
+        fn indy_create_payment_address_async<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _: i32, config_json: &str, mut cb: F) -> ErrorCode {
+            if config_json.contains(UNSTARTED_SEED) {
+                return ErrorCode::WalletInvalidHandle;
+            }
+            if config_json.contains(FAILING_SEED) {
+                cb(Err(ErrorCode::WalletItemAlreadyExists));
+            } else {
+                cb(qualified_address_from_verkey(&gen_random_base58_verkey()));
+            }
+            return ErrorCode::Success;
+        }
+    }
+
+    fn batch_configs() -> Vec<Result<String, SovtokenError>> {
+        vec![
+            Ok(json!({"seed": rand_string(VALID_SEED_LEN)}).to_string()),
+            Ok(json!({"seed": FAILING_SEED}).to_string()),
+            Err(SovtokenError::InvalidStructure(messages::INVALID_SEED.message())),
+            Ok(json!({}).to_string()),
+            Ok(json!({"seed": UNSTARTED_SEED}).to_string()),
+        ]
+    }
+
+    fn create_batch(stop_on_failure: bool) -> BatchAddressResult {
+        let (sender, receiver) = channel();
+        create_payment_addresses_async(FailingSeedMockHandler{}, WALLET_ID, batch_configs(), stop_on_failure, move |result| {
+            sender.send(result).unwrap();
+        });
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
 
     static VALID_SEED_LEN: usize = 32;
     static WALLET_ID: i32 = 10;
@@ -133,7 +464,7 @@ mod payments_tests {
     fn success_create_payment_with_seed_returns_address() {
 
         let seed = rand_string(VALID_SEED_LEN);
-        let config: PaymentAddressConfig = PaymentAddressConfig { seed };
+        let config: PaymentAddressConfig = PaymentAddressConfig { seed, strict_seed: false, reuse_existing: false, seed_encoding: None };
         let handler = CreatePaymentHandler::new(CreatePaymentSDKMockHandler{});
 
         let address = match handler.create_payment_address(WALLET_ID, config) {
@@ -160,7 +491,7 @@ mod payments_tests {
     fn success_create_payment_with_no_seed_returns_address() {
 
         let seed = String::new();
-        let config: PaymentAddressConfig = PaymentAddressConfig { seed };
+        let config: PaymentAddressConfig = PaymentAddressConfig { seed, strict_seed: false, reuse_existing: false, seed_encoding: None };
 
         let handler = CreatePaymentHandler::new(CreatePaymentSDKMockHandler{});
         let address = match handler.create_payment_address(WALLET_ID, config){
@@ -185,27 +516,142 @@ mod payments_tests {
     #[test]
     fn success_create_payment_async() {
         let seed = String::new();
-        let config: PaymentAddressConfig = PaymentAddressConfig { seed };
+        let config: PaymentAddressConfig = PaymentAddressConfig { seed, strict_seed: false, reuse_existing: false, seed_encoding: None };
 
         let handler = CreatePaymentHandler::new(CreatePaymentSDKMockHandler{});
 
         let (sender, receiver) = channel();
 
-        let cb_closure = move | address : String, err: ErrorCode | {
-
-            if err != ErrorCode::Success {
-                sender.send(false).unwrap();
-                return;
+        let cb_closure = move | address : Result<String, SovtokenError> | {
+            match address {
+                Ok(address) => {
+                    validate_address(address);
+                    sender.send(true).unwrap();
+                },
+                Err(_) => sender.send(false).unwrap(),
             }
-
-            validate_address(address.to_string());
-            sender.send(true).unwrap();
         };
 
-        let error_code: ErrorCode = handler.create_payment_address_async(WALLET_ID, config, cb_closure);
+        let result = handler.create_payment_address_async(WALLET_ID, config, cb_closure);
 
         let got_good_result = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
         assert_eq!(got_good_result, true);
-        assert_eq!(ErrorCode::Success, error_code);
+        assert_eq!(Ok(()), result);
+    }
+
+    #[test]
+    fn create_payment_async_passes_on_the_wallet_error() {
+        let config: PaymentAddressConfig = PaymentAddressConfig { seed: rand_string(VALID_SEED_LEN), strict_seed: false, reuse_existing: false, seed_encoding: None };
+        let handler = CreatePaymentHandler::new(DuplicateKeyMockHandler{});
+
+        let (sender, receiver) = channel();
+        let result = handler.create_payment_address_async(WALLET_ID, config, move |address| sender.send(address).unwrap());
+
+        assert_eq!(Ok(()), result);
+        let error = receiver.recv_timeout(Duration::from_secs(10)).unwrap().unwrap_err();
+        assert_eq!(ErrorCode::WalletItemAlreadyExists, error.error_code());
+    }
+
+    #[test]
+    fn create_payment_passes_on_the_wallet_error() {
+        let config: PaymentAddressConfig = PaymentAddressConfig { seed: rand_string(VALID_SEED_LEN), strict_seed: false, reuse_existing: false, seed_encoding: None };
+        let handler = CreatePaymentHandler::new(DuplicateKeyMockHandler{});
+
+        assert_eq!(Err(ErrorCode::WalletItemAlreadyExists), handler.create_payment_address(WALLET_ID, config));
+    }
+
+    #[test]
+    fn create_payment_with_a_seed_already_used() {
+        let seed = String::from("000000000000000000000000Trustee1");
+        let existing = qualified_address_from_verkey("GJ1SzoWzavQYfNL9XkaJdrQejfztN4XqdsiV4ct3LXKL").unwrap();
+        let handler = CreatePaymentHandler::new(DuplicateKeyMockHandler{});
+
+        let config = PaymentAddressConfig { seed: seed.clone(), strict_seed: false, reuse_existing: false, seed_encoding: None };
+        assert_eq!(Err(ErrorCode::WalletItemAlreadyExists), handler.create_payment_address(WALLET_ID, config));
+        let last_error = messages::last_error().unwrap();
+        assert_eq!("SOV-0042", last_error.message.id);
+        assert!(last_error.message.text.contains(&existing));
+
+        let config = PaymentAddressConfig { seed, strict_seed: false, reuse_existing: true, seed_encoding: None };
+        let (sender, receiver) = channel();
+        handler.create_payment_address_async(WALLET_ID, config, move |address| sender.send(address).unwrap()).unwrap();
+        assert_eq!(Ok(existing), receiver.recv_timeout(Duration::from_secs(10)).unwrap());
+    }
+
+    #[test]
+    fn create_payment_async_with_a_seed_already_used() {
+        let seed = String::from("000000000000000000000000Trustee1");
+        let existing = qualified_address_from_verkey("GJ1SzoWzavQYfNL9XkaJdrQejfztN4XqdsiV4ct3LXKL").unwrap();
+        let handler = CreatePaymentHandler::new(DuplicateKeyMockHandler{});
+
+        // the callback runs on another thread, the address comes with the error
+        let (sender, receiver) = channel();
+        let config = PaymentAddressConfig { seed, strict_seed: false, reuse_existing: false, seed_encoding: None };
+        handler.create_payment_address_async(WALLET_ID, config, move |address| {
+            let sender = sender.clone();
+            ::std::thread::spawn(move || sender.send(address).unwrap());
+        }).unwrap();
+
+        let error = receiver.recv_timeout(Duration::from_secs(10)).unwrap().unwrap_err();
+        assert_eq!(ErrorCode::WalletItemAlreadyExists, error.error_code());
+        assert_eq!("SOV-0042", error.message().id);
+        assert_eq!(vec![existing], error.message().args);
+    }
+
+    #[test]
+    fn payment_address_from_key_in_wallet() {
+        let handler = CreatePaymentHandler::new(KnownKeyMockHandler{});
+
+        let (sender, receiver) = channel();
+        let result = handler.payment_address_from_key(WALLET_ID, KNOWN_VERKEY, move |address| sender.send(address).unwrap());
+
+        assert_eq!(Ok(()), result);
+        let address = receiver.recv_timeout(Duration::from_secs(10)).unwrap().unwrap();
+        assert_eq!(qualified_address_from_verkey(KNOWN_VERKEY).unwrap(), address);
+        validate_address(address);
+    }
+
+    #[test]
+    fn payment_address_from_key_not_in_wallet() {
+        let handler = CreatePaymentHandler::new(KnownKeyMockHandler{});
+
+        let (sender, receiver) = channel();
+        let result = handler.payment_address_from_key(WALLET_ID, &gen_random_base58_verkey(), move |address| sender.send(address).unwrap());
+
+        assert_eq!(Ok(()), result);
+        assert_eq!(Err(ErrorCode::WalletItemNotFound), receiver.recv_timeout(Duration::from_secs(10)).unwrap());
+    }
+
+    #[test]
+    fn payment_address_from_abbreviated_key() {
+        let handler = CreatePaymentHandler::new(KnownKeyMockHandler{});
+        let result = handler.payment_address_from_key(WALLET_ID, "~CoRER63DVYnWZtK8uAzNbx", |_| panic!("called back"));
+        assert_eq!(Err(ErrorCode::CommonInvalidStructure), result);
+    }
+
+    #[test]
+    fn create_payment_addresses_reports_each_failure() {
+        let result = create_batch(false);
+
+        assert_eq!(vec![0, 3], result.created.iter().map(|created| created.index).collect::<Vec<usize>>());
+        result.created.into_iter().for_each(|created| validate_address(created.address));
+
+        assert_eq!(3, result.failed.len());
+        assert_eq!(1, result.failed[0].index);
+        assert_eq!(ErrorCode::WalletItemAlreadyExists as i32, result.failed[0].error);
+        assert_eq!(2, result.failed[1].index);
+        assert_eq!(ErrorCode::CommonInvalidStructure as i32, result.failed[1].error);
+        assert_eq!(4, result.failed[2].index);
+        assert_eq!(ErrorCode::WalletInvalidHandle as i32, result.failed[2].error);
+        assert!(result.skipped.is_empty());
+    }
+
+    #[test]
+    fn create_payment_addresses_stops_on_failure() {
+        let result = create_batch(true);
+
+        assert_eq!(1, result.created.len());
+        assert_eq!(vec![1], result.failed.iter().map(|failed| failed.index).collect::<Vec<usize>>());
+        assert_eq!(vec![2, 3, 4], result.skipped);
     }
 }
@@ -0,0 +1,510 @@
+/*!
+    Persisted state of payments on their way to the ledger.
+
+    Every tracked payment goes through
+    `built → signed → submitted → confirmed | rejected`, and its state is
+    stored in the wallet at each step. A wallet restarting after a crash can
+    read which of its transfers were in flight and whether they made it to
+    the ledger.
+*/
+
+use indy::{IndyHandle, ErrorCode};
+use serde_json;
+use serde_json::Value as SJsonValue;
+
+use logic::address::unqualified_part;
+use logic::compression::{self, Compression};
+use logic::config::library_config::library_config;
+use logic::request_hash::request_hash;
+use logic::storage::StorageBackend;
+use logic::type_aliases::{TokenAmount, TxnSeqNo};
+use utils::json_conversion::JsonDeserialize;
+use utils::random::rand_string;
+
+/**
+    The wallet record type the pending transactions are stored under.
+*/
+pub const PENDING_TXN_RECORD_TYPE: &str = "sovtoken_pending_txn";
+
+const PENDING_TXN_ID_LEN: usize = 16;
+
+/**
+    The steps of a payment, serialized in lowercase.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum PendingState {
+    Built,
+    Signed,
+    Submitted,
+    Confirmed,
+    Rejected,
+}
+
+impl PendingState {
+    /**
+        The state named `name`, as it is serialized, e.g. `"signed"`.
+    */
+    pub fn from_name(name: &str) -> Result<PendingState, ErrorCode> {
+        serde_json::from_value(SJsonValue::String(name.to_string()))
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonInvalidStructure))
+    }
+
+    /**
+        Whether a transaction in this state can move to `next`.
+
+        A submitted transaction can be submitted again, as a crash can happen
+        before its reply was recorded. A rejected transaction can be signed
+        again once it was refreshed.
+    */
+    pub fn can_advance_to(self, next: PendingState) -> bool {
+        match (self, next) {
+            (PendingState::Built, PendingState::Signed) |
+            (PendingState::Signed, PendingState::Submitted) |
+            (PendingState::Submitted, PendingState::Submitted) |
+            (PendingState::Submitted, PendingState::Confirmed) |
+            (PendingState::Submitted, PendingState::Rejected) |
+            (PendingState::Rejected, PendingState::Signed) => true,
+            _ => false,
+        }
+    }
+}
+
+/**
+    A payment request and the state it reached.
+
+    `updated` is in seconds since the unix epoch. `reason` is the reason the
+    ledger gave for a rejection, `seq_no` the seqNo the ledger gave a
+    confirmed transaction. `request_hash` is the canonical hash of the
+    request, see `logic::request_hash`, none when it isn't a json object.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingTxn {
+    pub state: PendingState,
+    pub request: String,
+    #[serde(default)]
+    pub updated: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq_no: Option<TxnSeqNo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_hash: Option<String>,
+}
+
+impl PendingTxn {
+    /**
+        The inputs of the request, see [`request_inputs`].
+
+        [`request_inputs`]: fn.request_inputs.html
+    */
+    pub fn inputs(&self) -> Result<Vec<(String, TxnSeqNo)>, ErrorCode> {
+        request_inputs(&self.request)
+    }
+
+    /**
+        The outputs of the request, see [`request_outputs`].
+
+        [`request_outputs`]: fn.request_outputs.html
+    */
+    pub fn outputs(&self) -> Result<Vec<(String, TokenAmount)>, ErrorCode> {
+        request_outputs(&self.request)
+    }
+}
+
+/**
+    The inputs of the XFER operation and of the fees of a request, as
+    unqualified addresses and seqNos.
+*/
+pub fn request_inputs(request: &str) -> Result<Vec<(String, TxnSeqNo)>, ErrorCode> {
+    let request: SJsonValue = serde_json::from_str(request)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))?;
+
+    let empty = Vec::new();
+    let operation_inputs = request["operation"]["inputs"].as_array().unwrap_or(&empty);
+    let fees_inputs = request["fees"][0].as_array().unwrap_or(&empty);
+
+    Ok(operation_inputs.iter()
+        .chain(fees_inputs.iter())
+        .filter_map(|input| {
+            match (input["address"].as_str(), input["seqNo"].as_u64()) {
+                (Some(address), Some(seq_no)) => Some((unqualified_part(address).to_string(), seq_no as TxnSeqNo)),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+/**
+    The outputs of the XFER operation and the change of the fees of a
+    request, as unqualified addresses and amounts.
+*/
+pub fn request_outputs(request: &str) -> Result<Vec<(String, TokenAmount)>, ErrorCode> {
+    let request: SJsonValue = serde_json::from_str(request)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))?;
+
+    let empty = Vec::new();
+    let operation_outputs = request["operation"]["outputs"].as_array().unwrap_or(&empty);
+    let fees_outputs = request["fees"][1].as_array().unwrap_or(&empty);
+
+    Ok(operation_outputs.iter()
+        .chain(fees_outputs.iter())
+        .filter_map(|output| {
+            match (output["address"].as_str(), output["amount"].as_u64()) {
+                (Some(address), Some(amount)) => Some((unqualified_part(address).to_string(), amount as TokenAmount)),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+/**
+    PendingTxnStore tracks payments in the storage backend of the library config.
+
+    The records are compressed as set by `compression` in the library config.
+*/
+pub struct PendingTxnStore<T> where T: StorageBackend {
+    injected_api: T,
+    compression: Compression,
+}
+
+impl<T: StorageBackend> PendingTxnStore<T> {
+    pub fn new(api_handler: T) -> Self {
+        PendingTxnStore { injected_api: api_handler, compression: library_config().compression }
+    }
+
+    /**
+        Store the records compressed with `compression`, whatever the library config says.
+    */
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /**
+        Start tracking `request` in the `built` or `signed` state and return its generated id.
+    */
+    pub fn track(&self, wallet_handle: IndyHandle, request: &str, state: PendingState, now: u64) -> Result<String, ErrorCode> {
+        trace!("logic::pending_txn::track >> state: {:?}", state);
+        if state != PendingState::Built && state != PendingState::Signed {
+            error!("A pending transaction starts out built or signed, not {:?}", state);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+
+        let txn = PendingTxn {
+            state,
+            request: request.to_string(),
+            updated: now,
+            reason: None,
+            seq_no: None,
+            request_hash: request_hash(request).ok(),
+        };
+        let txn_id = rand_string(PENDING_TXN_ID_LEN);
+        self.injected_api.add_record(wallet_handle, PENDING_TXN_RECORD_TYPE, &txn_id, &self.record_value(&txn)?)?;
+
+        trace!("logic::pending_txn::track << txn_id: {:?}", txn_id);
+        Ok(txn_id)
+    }
+
+    /**
+        Get the transaction stored as `txn_id`.
+    */
+    pub fn get(&self, wallet_handle: IndyHandle, txn_id: &str) -> Result<PendingTxn, ErrorCode> {
+        let value = self.injected_api.get_record(wallet_handle, PENDING_TXN_RECORD_TYPE, txn_id)?;
+        self.read_record_value(wallet_handle, txn_id, &value)
+    }
+
+    /**
+        Get every tracked transaction with its id.
+    */
+    pub fn list(&self, wallet_handle: IndyHandle) -> Result<Vec<(String, PendingTxn)>, ErrorCode> {
+        self.injected_api.list_records(wallet_handle, PENDING_TXN_RECORD_TYPE)?
+            .into_iter()
+            .map(|(txn_id, value)| {
+                self.read_record_value(wallet_handle, &txn_id, &value).map(|txn| (txn_id, txn))
+            })
+            .collect()
+    }
+
+    /**
+        Move the transaction to `state`.
+
+        `request` replaces the stored request, e.g. with the signed one.
+        Fails with `CommonInvalidState` when the transition isn't allowed.
+    */
+    pub fn advance(&self, wallet_handle: IndyHandle, txn_id: &str, state: PendingState, request: Option<String>, reason: Option<String>, now: u64) -> Result<PendingTxn, ErrorCode> {
+        self.transition(wallet_handle, txn_id, state, now, |txn| {
+            txn.reason = reason;
+            if let Some(request) = request {
+                txn.request_hash = request_hash(&request).ok();
+                txn.request = request;
+            }
+        })
+    }
+
+    /**
+        Mark a signed transaction `submitted`, before its request is sent.
+
+        A crash while waiting for the ledger leaves it visible as in flight.
+        Returns the transaction as recorded.
+    */
+    pub fn submitting(&self, wallet_handle: IndyHandle, txn_id: &str, now: u64) -> Result<PendingTxn, ErrorCode> {
+        self.advance(wallet_handle, txn_id, PendingState::Submitted, None, None, now)
+    }
+
+    /**
+        Record the reply of the ledger to a submitted transaction.
+
+        It is `confirmed` on a REPLY, with the seqNo of the reply, and
+        `rejected` on a REQNACK or REJECT. When the ledger couldn't be
+        reached nothing is recorded, the transaction stays `submitted`.
+
+        Returns the transaction as recorded.
+    */
+    pub fn record_reply(&self, wallet_handle: IndyHandle, txn_id: &str, now: u64, reply: &str) -> Result<PendingTxn, ErrorCode> {
+        let (state, reason, seq_no) = reply_outcome(reply)?;
+        self.transition(wallet_handle, txn_id, state, now, |txn| {
+            txn.reason = reason;
+            if seq_no.is_some() {
+                txn.seq_no = seq_no;
+            }
+        })
+    }
+
+    // Move the transaction to `state`, with the other changes of `update`.
+    fn transition<F>(&self, wallet_handle: IndyHandle, txn_id: &str, state: PendingState, now: u64, update: F) -> Result<PendingTxn, ErrorCode>
+        where F: FnOnce(&mut PendingTxn)
+    {
+        trace!("logic::pending_txn::advance >> txn_id: {:?}, state: {:?}", txn_id, state);
+        let mut txn = self.get(wallet_handle, txn_id)?;
+
+        if !txn.state.can_advance_to(state) {
+            error!("Pending transaction {} can't go from {:?} to {:?}", txn_id, txn.state, state);
+            return Err(ErrorCode::CommonInvalidState);
+        }
+
+        txn.state = state;
+        txn.updated = now;
+        update(&mut txn);
+
+        self.injected_api.update_record(wallet_handle, PENDING_TXN_RECORD_TYPE, txn_id, &self.record_value(&txn)?)?;
+        Ok(txn)
+    }
+
+    fn record_value(&self, txn: &PendingTxn) -> Result<String, ErrorCode> {
+        compression::compress(&serialize(txn)?, self.compression)
+    }
+
+    // the transaction of a record value, rewriting a value stored uncompressed
+    // when it can, the value read is returned either way
+    fn read_record_value(&self, wallet_handle: IndyHandle, txn_id: &str, value: &str) -> Result<PendingTxn, ErrorCode> {
+        let txn = PendingTxn::from_json(&compression::decompress(value)?)
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonInvalidState))?;
+
+        if compression::needs_migration(value, self.compression) {
+            let migrated = self.record_value(&txn)
+                .and_then(|migrated| self.injected_api.update_record(wallet_handle, PENDING_TXN_RECORD_TYPE, txn_id, &migrated));
+            if let Err(ec) = migrated {
+                warn!("Pending transaction {} is left as it was stored, its migration failed: {:?}", txn_id, ec);
+            }
+        }
+        Ok(txn)
+    }
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn serialize(txn: &PendingTxn) -> Result<String, ErrorCode> {
+    serde_json::to_string(txn)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))
+}
+
+// The state a reply moves a submitted transaction to, with the reason of a
+// rejection or the seqNo of a confirmation.
+fn reply_outcome(reply: &str) -> Result<(PendingState, Option<String>, Option<TxnSeqNo>), ErrorCode> {
+    let reply: SJsonValue = serde_json::from_str(reply)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    match reply["op"].as_str() {
+        Some("REPLY") => Ok((PendingState::Confirmed, None, reply["result"]["txnMetadata"]["seqNo"].as_u64())),
+        Some("REQNACK") | Some("REJECT") => Ok((PendingState::Rejected, reply["reason"].as_str().map(String::from), None)),
+        _ => {
+            error!("Unknown reply to a pending transaction: {:?}", reply);
+            Err(ErrorCode::CommonInvalidStructure)
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod pending_txn_tests {
+    use logic::indy_sdk_api::wallet_api::WalletAPI;
+    use logic::storage::MemoryStorage;
+    use utils::test::wallet::WalletMockHandler;
+    use super::*;
+
+    static WALLET_HANDLE: i32 = 1;
+    static REQUEST: &'static str = r#"{"operation": {"type": "10001"}, "reqId": 1}"#;
+
+    fn signed_txn(store: &PendingTxnStore<WalletMockHandler>) -> String {
+        store.track(WALLET_HANDLE, REQUEST, PendingState::Signed, 10).unwrap()
+    }
+
+    #[test]
+    fn state_from_name() {
+        assert_eq!(PendingState::Signed, PendingState::from_name("signed").unwrap());
+        assert_eq!(ErrorCode::CommonInvalidStructure, PendingState::from_name("Signed").unwrap_err());
+    }
+
+    #[test]
+    fn track_and_get() {
+        let store = PendingTxnStore::new(WalletMockHandler::default());
+        let txn_id = store.track(WALLET_HANDLE, REQUEST, PendingState::Built, 10).unwrap();
+
+        let txn = store.get(WALLET_HANDLE, &txn_id).unwrap();
+        assert_eq!(PendingState::Built, txn.state);
+        assert_eq!(REQUEST, txn.request);
+        assert_eq!(10, txn.updated);
+    }
+
+    #[test]
+    fn track_submitted() {
+        let store = PendingTxnStore::new(WalletMockHandler::default());
+        let error = store.track(WALLET_HANDLE, REQUEST, PendingState::Submitted, 10).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error);
+    }
+
+    #[test]
+    fn list_tracked() {
+        let store = PendingTxnStore::new(WalletMockHandler::default());
+        let txn_id = signed_txn(&store);
+
+        let txns = store.list(WALLET_HANDLE).unwrap();
+        assert_eq!(1, txns.len());
+        assert_eq!(txn_id, txns[0].0);
+        assert_eq!(PendingState::Signed, txns[0].1.state);
+    }
+
+    #[test]
+    fn advance_replaces_request() {
+        let store = PendingTxnStore::new(WalletMockHandler::default());
+        let txn_id = store.track(WALLET_HANDLE, REQUEST, PendingState::Built, 10).unwrap();
+
+        assert_eq!(request_hash(REQUEST).ok(), store.get(WALLET_HANDLE, &txn_id).unwrap().request_hash);
+
+        let txn = store.advance(WALLET_HANDLE, &txn_id, PendingState::Signed, Some(String::from("signed")), None, 11).unwrap();
+        assert_eq!("signed", txn.request);
+        assert_eq!(None, txn.request_hash);
+        assert_eq!(txn, store.get(WALLET_HANDLE, &txn_id).unwrap());
+    }
+
+    #[test]
+    fn advance_skipping_a_state() {
+        let store = PendingTxnStore::new(WalletMockHandler::default());
+        let txn_id = store.track(WALLET_HANDLE, REQUEST, PendingState::Built, 10).unwrap();
+
+        let error = store.advance(WALLET_HANDLE, &txn_id, PendingState::Confirmed, None, None, 11).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidState, error);
+    }
+
+    #[test]
+    fn submit_confirmed() {
+        let store = PendingTxnStore::new(WalletMockHandler::default());
+        let txn_id = signed_txn(&store);
+
+        let txn = store.submitting(WALLET_HANDLE, &txn_id, 11).unwrap();
+        assert_eq!(REQUEST, txn.request);
+        let txn = store.record_reply(WALLET_HANDLE, &txn_id, 12, r#"{"op": "REPLY", "result": {"txnMetadata": {"seqNo": 42}}}"#).unwrap();
+
+        assert_eq!(PendingState::Confirmed, txn.state);
+        assert_eq!(Some(42), txn.seq_no);
+        assert_eq!(12, txn.updated);
+        assert_eq!(PendingState::Confirmed, store.get(WALLET_HANDLE, &txn_id).unwrap().state);
+    }
+
+    #[test]
+    fn submit_rejected() {
+        let store = PendingTxnStore::new(WalletMockHandler::default());
+        let txn_id = signed_txn(&store);
+
+        store.submitting(WALLET_HANDLE, &txn_id, 11).unwrap();
+        let txn = store.record_reply(WALLET_HANDLE, &txn_id, 11, r#"{"op": "REJECT", "reason": "InsufficientFundsError"}"#).unwrap();
+
+        assert_eq!(PendingState::Rejected, txn.state);
+        assert_eq!(Some(String::from("InsufficientFundsError")), txn.reason);
+    }
+
+    #[test]
+    fn submitted_until_replied() {
+        let store = PendingTxnStore::new(WalletMockHandler::default());
+        let txn_id = signed_txn(&store);
+
+        store.submitting(WALLET_HANDLE, &txn_id, 11).unwrap();
+
+        assert_eq!(PendingState::Submitted, store.get(WALLET_HANDLE, &txn_id).unwrap().state);
+    }
+
+    #[test]
+    fn submit_confirmed_again() {
+        let store = PendingTxnStore::new(WalletMockHandler::default());
+        let txn_id = signed_txn(&store);
+        store.submitting(WALLET_HANDLE, &txn_id, 11).unwrap();
+        store.record_reply(WALLET_HANDLE, &txn_id, 11, r#"{"op": "REPLY"}"#).unwrap();
+
+        let error = store.submitting(WALLET_HANDLE, &txn_id, 12).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidState, error);
+    }
+
+    #[test]
+    fn memory_storage() {
+        let store = PendingTxnStore::new(MemoryStorage::new("pending_txn_tests::memory"));
+        let txn_id = store.track(WALLET_HANDLE, REQUEST, PendingState::Signed, 10).unwrap();
+        assert_eq!(REQUEST, store.get(WALLET_HANDLE, &txn_id).unwrap().request);
+    }
+
+    #[test]
+    fn compressed_records() {
+        let store = PendingTxnStore::new(WalletMockHandler::default()).with_compression(Compression::Gzip);
+        let txn_id = signed_txn(&store);
+
+        let value = store.injected_api.indy_get_record_value(WALLET_HANDLE, PENDING_TXN_RECORD_TYPE, &txn_id).unwrap();
+        assert!(value.starts_with("gzip:"));
+        assert_eq!(REQUEST, store.get(WALLET_HANDLE, &txn_id).unwrap().request);
+    }
+
+    #[test]
+    fn uncompressed_record_is_migrated_on_read() {
+        let store = PendingTxnStore::new(WalletMockHandler::default()).with_compression(Compression::None);
+        let txn_id = signed_txn(&store);
+
+        let store = PendingTxnStore::new(store.injected_api).with_compression(Compression::Brotli);
+        assert_eq!(1, store.list(WALLET_HANDLE).unwrap().len());
+
+        let value = store.injected_api.indy_get_record_value(WALLET_HANDLE, PENDING_TXN_RECORD_TYPE, &txn_id).unwrap();
+        assert!(value.starts_with("br:"));
+        assert_eq!(PendingState::Signed, store.get(WALLET_HANDLE, &txn_id).unwrap().state);
+    }
+
+    #[test]
+    fn record_is_read_when_its_migration_fails() {
+        let store = PendingTxnStore::new(WalletMockHandler::default()).with_compression(Compression::None);
+        let txn_id = signed_txn(&store);
+
+        let wallet = WalletMockHandler { records: store.injected_api.records, read_only: true };
+        let store = PendingTxnStore::new(wallet).with_compression(Compression::Gzip);
+        assert_eq!(PendingState::Signed, store.get(WALLET_HANDLE, &txn_id).unwrap().state);
+
+        let value = store.injected_api.indy_get_record_value(WALLET_HANDLE, PENDING_TXN_RECORD_TYPE, &txn_id).unwrap();
+        assert!(!value.starts_with("gzip:"));
+    }
+}
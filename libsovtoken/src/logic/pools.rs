@@ -0,0 +1,265 @@
+/*!
+    State kept per ledger network, so one process can serve several.
+
+    A pool handle is mapped to its network by [`sovtoken_register_pool`],
+    with the hash of the genesis transactions of the pool: two pools opened
+    to the same network share their state, pools of different networks
+    never do. An unregistered pool handle is a network of its own.
+
+    The UTXOs the library fetches itself with a pool handle, e.g. for the
+    aggregate balance, are cached in the state of the network. The replies
+    libindy hands to `parse_get_utxo_response_handler` come without a pool
+    and share one cache, which is safe as its entries are keyed by the
+    state root of the ledger.
+
+    The fee schedule is kept per network too: the fees parsed with
+    `parse_get_txn_fees_response_of_pool_handler` or preloaded with
+    `set_fee_schedule_of_pool_handler` are only used for the requests of the
+    network, see `logic::fee_waiver`. The fees parsed or preloaded without a
+    pool are shared by the networks which have no fee of their own.
+
+    [`sovtoken_register_pool`]: ../../api/fn.sovtoken_register_pool.html
+*/
+
+use std::collections::{HashMap, VecDeque};
+
+use hex::ToHex;
+use indy::{IndyHandle, ErrorCode};
+use sha2::{Digest, Sha256};
+
+use logic::fee_waiver::FeeSchedule;
+use logic::messages;
+use logic::optimistic_updates::OptimisticUpdate;
+use logic::request::{check_protocol_version, protocol_version_of};
+use logic::type_aliases::ProtocolVersion;
+use logic::utxo_cache::UtxoCache;
+
+/**
+    What is cached for a network.
+*/
+#[derive(Debug, Default)]
+pub struct NetworkState {
+    pub utxo_cache: UtxoCache,
+    /// The inputs spent by the requests submitted to the network, oldest first, see `logic::optimistic_updates`.
+    pub optimistic_updates: VecDeque<OptimisticUpdate>,
+    /// The fees of the network, see `logic::fee_waiver`.
+    pub fee_schedule: FeeSchedule,
+}
+
+/**
+    The networks of the pool handles and their state.
+*/
+#[derive(Debug, Default)]
+pub struct Pools {
+    networks: HashMap<String, NetworkState>,
+    handles: HashMap<IndyHandle, String>,
+    protocol_versions: HashMap<String, ProtocolVersion>,
+}
+
+impl Pools {
+    pub fn new() -> Self {
+        Pools::default()
+    }
+
+    /**
+        Map `pool_handle` to the network `network_id`.
+
+        The state cached for the pool handle before it was registered is
+        dropped.
+    */
+    pub fn register(&mut self, pool_handle: IndyHandle, network_id: &str) {
+        self.networks.remove(&unregistered_id(pool_handle));
+        self.handles.insert(pool_handle, network_id.to_string());
+    }
+
+    /**
+        The network of `pool_handle`, `pool:<handle>` when it isn't registered.
+    */
+    pub fn network_id(&self, pool_handle: IndyHandle) -> String {
+        self.handles.get(&pool_handle)
+            .cloned()
+            .unwrap_or_else(|| unregistered_id(pool_handle))
+    }
+
+    /**
+        The state of the network of `pool_handle`, empty at first.
+    */
+    pub fn network(&mut self, pool_handle: IndyHandle) -> &mut NetworkState {
+        let network_id = self.network_id(pool_handle);
+        self.networks.entry(network_id).or_insert_with(NetworkState::default)
+    }
+
+    /**
+        The optimistic updates of every network with a state.
+    */
+    pub fn optimistic_updates_mut(&mut self) -> impl Iterator<Item = &mut VecDeque<OptimisticUpdate>> {
+        self.networks.values_mut().map(|network| &mut network.optimistic_updates)
+    }
+
+    /**
+        Drop the state of the network of `pool_handle`, the pool stays registered.
+
+        Returns whether there was any.
+    */
+    pub fn clear(&mut self, pool_handle: IndyHandle) -> bool {
+        let network_id = self.network_id(pool_handle);
+        self.networks.remove(&network_id).is_some()
+    }
+
+    /**
+        Set the protocol version the network of `pool_handle` speaks.
+
+        Fails with `CommonInvalidStructure` when requests can't be framed
+        with it.
+    */
+    pub fn set_protocol_version(&mut self, pool_handle: IndyHandle, protocol_version: ProtocolVersion) -> Result<(), ErrorCode> {
+        check_protocol_version(protocol_version)?;
+        let network_id = self.network_id(pool_handle);
+        self.protocol_versions.insert(network_id, protocol_version);
+        Ok(())
+    }
+
+    /**
+        The protocol version the network of `pool_handle` speaks, `None` when it wasn't set.
+    */
+    pub fn protocol_version(&self, pool_handle: IndyHandle) -> Option<ProtocolVersion> {
+        self.protocol_versions.get(&self.network_id(pool_handle)).cloned()
+    }
+
+    /**
+        Fail with `PoolIncompatibleProtocolVersion` when `request_json` isn't
+        framed with the protocol version of the network of `pool_handle`.
+
+        Any request passes when the protocol version of the network wasn't set.
+    */
+    pub fn check_request(&self, pool_handle: IndyHandle, request_json: &str) -> Result<(), ErrorCode> {
+        let pool_version = match self.protocol_version(pool_handle) {
+            Some(pool_version) => pool_version,
+            None => return Ok(()),
+        };
+
+        let request_version = protocol_version_of(request_json)?;
+        if request_version == pool_version {
+            return Ok(());
+        }
+
+        let message = messages::PROTOCOL_VERSION_MISMATCH.with(&[
+            request_version.to_string(),
+            pool_handle.to_string(),
+            pool_version.to_string(),
+        ]);
+        error!("{}", message.text);
+        messages::set_last_error(ErrorCode::PoolIncompatibleProtocolVersion, message);
+        Err(ErrorCode::PoolIncompatibleProtocolVersion)
+    }
+}
+
+/**
+    The id of the network of genesis transactions, the hex sha256 of their json lines.
+
+    Blank lines and the whitespace around the lines don't change the id.
+*/
+pub fn genesis_hash(genesis_txns: &str) -> String {
+    let mut hasher = Sha256::default();
+    for line in genesis_txns.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        hasher.input(line.as_bytes());
+        hasher.input(b"\n");
+    }
+    hasher.result().to_hex()
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn unregistered_id(pool_handle: IndyHandle) -> String {
+    format!("pool:{}", pool_handle)
+}
+
+
+#[cfg(test)]
+mod pools_tests {
+    use logic::parsers::parse_get_utxo_response::UTXO;
+
+    use super::*;
+
+    fn cache_utxo(pools: &mut Pools, pool_handle: IndyHandle) {
+        let utxos = vec![UTXO {
+            payment_address: String::from("pay:sov:1"),
+            source: String::from("txo:sov:1"),
+            amount: 10,
+            extra: String::new(),
+        }];
+        pools.network(pool_handle).utxo_cache.insert(4, "1", "root", UtxoCache::body_hash("body"), utxos);
+    }
+
+    #[test]
+    fn pools_of_one_network_share_state() {
+        let mut pools = Pools::new();
+        pools.register(1, "mainnet");
+        pools.register(2, "mainnet");
+        cache_utxo(&mut pools, 1);
+
+        assert!(pools.network(2).utxo_cache.get("1", "root").is_some());
+    }
+
+    #[test]
+    fn networks_are_apart() {
+        let mut pools = Pools::new();
+        pools.register(1, "mainnet");
+        pools.register(2, "staging");
+        cache_utxo(&mut pools, 1);
+
+        assert!(pools.network(2).utxo_cache.get("1", "root").is_none());
+        assert!(pools.network(3).utxo_cache.is_empty());
+        assert_eq!("pool:3", pools.network_id(3));
+    }
+
+    #[test]
+    fn clear_one_network() {
+        let mut pools = Pools::new();
+        pools.register(1, "mainnet");
+        pools.register(2, "staging");
+        cache_utxo(&mut pools, 1);
+        cache_utxo(&mut pools, 2);
+
+        assert!(pools.clear(1));
+        assert!(pools.network(1).utxo_cache.is_empty());
+        assert!(!pools.network(2).utxo_cache.is_empty());
+        assert_eq!("mainnet", pools.network_id(1));
+    }
+
+    #[test]
+    fn protocol_version_of_the_network() {
+        let mut pools = Pools::new();
+        pools.register(1, "mainnet");
+        pools.register(2, "mainnet");
+        assert!(pools.check_request(1, "{\"reqId\": 1}").is_ok());
+
+        pools.set_protocol_version(1, 1).unwrap();
+        pools.clear(1);
+        assert_eq!(Some(1), pools.protocol_version(2));
+        assert_eq!(None, pools.protocol_version(3));
+
+        assert!(pools.check_request(2, "{\"reqId\": 1}").is_ok());
+        let request = "{\"reqId\": 1, \"protocolVersion\": 2}";
+        assert_eq!(ErrorCode::PoolIncompatibleProtocolVersion, pools.check_request(2, request).unwrap_err());
+        assert!(pools.check_request(3, request).is_ok());
+    }
+
+    #[test]
+    fn unsupported_protocol_version_of_a_network() {
+        let mut pools = Pools::new();
+        assert_eq!(ErrorCode::CommonInvalidStructure, pools.set_protocol_version(1, 3).unwrap_err());
+        assert_eq!(None, pools.protocol_version(1));
+    }
+
+    #[test]
+    fn genesis_hash_ignores_blank_lines() {
+        let genesis = "{\"txn\": 1}\n{\"txn\": 2}\n";
+        assert_eq!(genesis_hash(genesis), genesis_hash("\n{\"txn\": 1}\r\n\n  {\"txn\": 2}"));
+        assert_ne!(genesis_hash(genesis), genesis_hash("{\"txn\": 1}\n"));
+        assert_eq!(64, genesis_hash(genesis).len());
+    }
+}
@@ -0,0 +1,203 @@
+/*!
+    A view-only export of a wallet, for accounting and reporting systems.
+
+    The export holds what libsovtoken knows about a wallet that isn't
+    secret: the payment addresses with their label and cached balance, and
+    the pending transactions with their inputs and outputs. Keys and seeds
+    stay in the wallet, and so do the signed requests of the pending
+    transactions: anyone holding a signed request could submit it.
+*/
+
+use indy::{IndyHandle, ErrorCode};
+
+use logic::address::address_from_unqualified_address;
+use logic::address_book::{AddressBook, AddressFilter, AddressRecord};
+use logic::indy_sdk_api::payment_api::PaymentAPI;
+use logic::indy_sdk_api::wallet_api::WalletAPI;
+use logic::output::Output;
+use logic::parsers::common::TXO;
+use logic::pending_txn::{PendingState, PendingTxn, PendingTxnStore};
+use logic::storage::Storage;
+use logic::type_aliases::{TokenAmount, TxnSeqNo};
+
+/**
+    The version of the layout of the export.
+*/
+pub const EXPORT_VERSION: u32 = 1;
+
+/**
+    A pending transaction, without its signed request.
+
+    `inputs` are the txo:sov: ids the transaction spends, fees included,
+    `outputs` what it pays, the change of the fees included.
+*/
+#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedTxn {
+    pub txn_id: String,
+    pub state: PendingState,
+    pub updated: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq_no: Option<TxnSeqNo>,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<Output>,
+}
+
+/**
+    The public state of a wallet.
+
+    `exported` is when the export was made, in seconds since the unix epoch.
+    `cached_balance` is the sum of the cached balances of the addresses.
+*/
+#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicState {
+    pub ver: u32,
+    pub exported: u64,
+    pub addresses: Vec<AddressRecord>,
+    pub cached_balance: TokenAmount,
+    pub pending_txns: Vec<ExportedTxn>,
+}
+
+/**
+    PublicExporter reads the public state of a wallet.
+*/
+pub struct PublicExporter<W, P> where W: WalletAPI, P: PaymentAPI {
+    wallet_api: W,
+    payment_api: P,
+}
+
+impl<W: WalletAPI, P: PaymentAPI> PublicExporter<W, P> {
+    pub fn new(wallet_api: W, payment_api: P) -> Self {
+        PublicExporter { wallet_api, payment_api }
+    }
+
+    /**
+        Export the public state of the wallet.
+
+        The addresses are ordered as by `search_payment_addresses_handler`,
+        addresses without a record get one first seen `now`. The pending
+        transactions are ordered by the time they were last updated.
+    */
+    pub fn export(&self, wallet_handle: IndyHandle, now: u64) -> Result<PublicState, ErrorCode> {
+        trace!("logic::public_export::export >> wallet_handle: {:?}", wallet_handle);
+        let addresses = AddressBook::new(&self.wallet_api, &self.payment_api)
+            .search(wallet_handle, &AddressFilter::default(), now)?;
+
+        let cached_balance = addresses.iter()
+            .fold(Some(0), |total: Option<TokenAmount>, record| total.and_then(|total| total.checked_add(record.balance.unwrap_or(0))))
+            .ok_or(ErrorCode::CommonInvalidState)?;
+
+        let mut pending_txns = PendingTxnStore::new(Storage::configured(&self.wallet_api))
+            .list(wallet_handle)?
+            .into_iter()
+            .map(|(txn_id, txn)| exported_txn(txn_id, txn))
+            .collect::<Result<Vec<ExportedTxn>, ErrorCode>>()?;
+        pending_txns.sort_by(|a, b| (a.updated, &a.txn_id).cmp(&(b.updated, &b.txn_id)));
+
+        trace!("logic::public_export::export << addresses: {}, pending_txns: {}", addresses.len(), pending_txns.len());
+        Ok(PublicState { ver: EXPORT_VERSION, exported: now, addresses, cached_balance, pending_txns })
+    }
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn exported_txn(txn_id: String, txn: PendingTxn) -> Result<ExportedTxn, ErrorCode> {
+    let inputs = txn.inputs()?
+        .into_iter()
+        .map(|(address, seq_no)| TXO { address: address_from_unqualified_address(&address)?, seq_no }.to_libindy_string())
+        .collect::<Result<Vec<String>, ErrorCode>>()?;
+
+    let outputs = txn.outputs()?
+        .into_iter()
+        .map(|(address, amount)| address_from_unqualified_address(&address).map(|address| Output::new(address, amount)))
+        .collect::<Result<Vec<Output>, ErrorCode>>()?;
+
+    Ok(ExportedTxn {
+        txn_id,
+        state: txn.state,
+        updated: txn.updated,
+        reason: txn.reason,
+        seq_no: txn.seq_no,
+        inputs,
+        outputs,
+    })
+}
+
+
+#[cfg(test)]
+mod public_export_tests {
+    use logic::address::unqualified_part;
+    use logic::parsers::parse_get_utxo_response::ParseGetUtxoReply;
+    use utils::json_conversion::JsonSerialize;
+    use utils::test::wallet::WalletMockHandler;
+    use super::*;
+
+    static WALLET_HANDLE: i32 = 1;
+    static PAYER: &'static str = "pay:sov:E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm";
+    static PAYEE: &'static str = "pay:sov:TKe9eXtchV71J2qXX5HwP8rbkTBStnEEkMwQkHie265VtRSbs";
+
+    struct PaymentMockHandler {}
+
+    impl PaymentAPI for PaymentMockHandler {
+        fn indy_list_payment_addresses(&self, _: IndyHandle) -> Result<Vec<String>, ErrorCode> {
+            Ok(vec![PAYER.to_string(), PAYEE.to_string()])
+        }
+
+        fn indy_get_utxos(&self, _: IndyHandle, _: &str) -> Result<ParseGetUtxoReply, ErrorCode> {
+            Ok(vec![])
+        }
+    }
+
+    fn request() -> String {
+        json!({
+            "operation": {
+                "type": "10001",
+                "inputs": [{"address": unqualified_part(PAYER), "seqNo": 3}],
+                "outputs": [{"address": unqualified_part(PAYEE), "amount": 10}],
+                "signatures": ["2nMB3sRVZ9vDVo8kkBaXXyoxfjeJ9sWbdEGfgCuP5u5dnnvsfmbHQW3Aqps7G6GD8F3NfdmR6XgRhAUmJYmn7TD1"]
+            },
+            "reqId": 1
+        }).to_string()
+    }
+
+    #[test]
+    fn export_of_a_wallet() {
+        let wallet = WalletMockHandler::default();
+        let payment = PaymentMockHandler {};
+        AddressBook::new(&wallet, &payment).set_label(WALLET_HANDLE, PAYEE, "savings", 5).unwrap();
+        let txn_id = PendingTxnStore::new(&wallet).track(WALLET_HANDLE, &request(), PendingState::Signed, 7).unwrap();
+
+        let state = PublicExporter::new(&wallet, payment).export(WALLET_HANDLE, 9).unwrap();
+
+        assert_eq!(EXPORT_VERSION, state.ver);
+        assert_eq!(vec![PAYEE, PAYER], state.addresses.iter().map(|record| record.address.as_str()).collect::<Vec<_>>());
+        assert_eq!(Some(String::from("savings")), state.addresses[0].label);
+        assert_eq!(0, state.cached_balance);
+        assert_eq!(vec![ExportedTxn {
+            txn_id,
+            state: PendingState::Signed,
+            updated: 7,
+            reason: None,
+            seq_no: None,
+            inputs: vec![TXO { address: PAYER.to_string(), seq_no: 3 }.to_libindy_string().unwrap()],
+            outputs: vec![Output::new(PAYEE.to_string(), 10)],
+        }], state.pending_txns);
+    }
+
+    #[test]
+    fn export_without_signatures() {
+        let wallet = WalletMockHandler::default();
+        PendingTxnStore::new(&wallet).track(WALLET_HANDLE, &request(), PendingState::Signed, 7).unwrap();
+
+        let json = PublicExporter::new(&wallet, PaymentMockHandler {}).export(WALLET_HANDLE, 9).unwrap().to_json().unwrap();
+
+        assert!(!json.contains("signatures"));
+        assert!(!json.contains("2nMB3sRVZ9vDVo8kkBaXXyoxfjeJ9sWbdEGfgCuP5u5dnnvsfmbHQW3Aqps7G6GD8F3NfdmR6XgRhAUmJYmn7TD1"));
+    }
+}
@@ -0,0 +1,196 @@
+/*!
+    The receipts of the outputs of a transaction.
+
+    `parse_payment_response_handler`, `parse_response_with_fees_handler` and
+    `parse_verify_response_handler` emit the outputs of the transaction they
+    parse as a json array of receipts:
+
+    ```text
+    {
+        "ver": 1,               // version of the receipt json
+        "receipt": <string>,    // the txo:sov: id of the output
+        "recipient": <string>,  // qualified address of the output
+        "amount": <int>,        // a string with `amount_format` "string"
+        "seqNo": <int>,         // seqNo of the transaction
+        "txnTime": <int>,       // time the transaction was ordered, null when the reply doesn't have it
+        "extra": <string>       // extra of the transaction, "" when it has none
+    }
+    ```
+
+    With `verbose_receipts` set in the library config, the receipts of a
+    payment also have a `proof`, see [`inclusion_proof`]. Parsed with the
+    `resolveLabels` option, the receipts of the addresses the wallet has a
+    label for have the `label` of the address.
+
+    The receipt json of version 1 is [`Schema::Receipts`]. Fields are only
+    added within a version, so wrappers reading the fields of version 1 can
+    rely on them; a change of a field bumps [`RECEIPT_VERSION`].
+
+    The receipts emitted before the version was introduced have `receipt`,
+    `recipient`, `amount` and `extra` only, the [`UTXO`] of the parsers.
+
+    [`inclusion_proof`]: ../inclusion_proof/index.html
+    [`Schema::Receipts`]: ../schema/enum.Schema.html
+    [`RECEIPT_VERSION`]: constant.RECEIPT_VERSION.html
+    [`UTXO`]: ../parsers/common/struct.UTXO.html
+*/
+
+use std::collections::HashMap;
+
+use indy::ErrorCode;
+
+use logic::address;
+use logic::amount_format;
+use logic::inclusion_proof::InclusionProof;
+use logic::parsers::common::{TXO, UTXO};
+use logic::type_aliases::{TokenAmount, TxnSeqNo};
+
+/**
+    The version of the receipt json emitted.
+*/
+pub const RECEIPT_VERSION: u32 = 1;
+
+/**
+    The receipt of an output of a transaction.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+    pub ver: u32,
+    pub receipt: String,
+    pub recipient: String,
+    #[serde(with = "amount_format")]
+    pub amount: TokenAmount,
+    pub seq_no: TxnSeqNo,
+    pub txn_time: Option<u64>,
+    pub extra: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<InclusionProof>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+impl Receipt {
+    /**
+        The receipt of the output of `amount` to the qualified address
+        `recipient` of the transaction `seq_no`.
+
+        ```
+            use sovtoken::logic::receipt::Receipt;
+
+            let receipt = Receipt::new("pay:sov:iTQzpdRdugkJ2gLD5vW5c159dncSL9jbAtu3WfPcb8qWD9bUd", 10, 1, Some(1529682415), None).unwrap();
+            assert_eq!(1, receipt.txo().unwrap().seq_no);
+            assert_eq!("", receipt.extra);
+        ```
+    */
+    pub fn new(recipient: &str, amount: TokenAmount, seq_no: TxnSeqNo, txn_time: Option<u64>, extra: Option<&str>) -> Result<Receipt, ErrorCode> {
+        let receipt = TXO { address: recipient.to_string(), seq_no }.to_libindy_string()?;
+        Ok(Receipt {
+            ver: RECEIPT_VERSION,
+            receipt,
+            recipient: recipient.to_string(),
+            amount,
+            seq_no,
+            txn_time,
+            extra: extra.unwrap_or("").to_string(),
+            proof: None,
+            label: None,
+        })
+    }
+
+    /**
+        The TXO the receipt is the id of.
+    */
+    pub fn txo(&self) -> Result<TXO, ErrorCode> {
+        TXO::from_libindy_string(&self.receipt)
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonInvalidStructure))
+    }
+}
+
+/**
+    Set the label of the recipient of each receipt, from the labels by unqualified address.
+
+    Receipts of addresses without a label are left as they are.
+*/
+pub fn add_labels(receipts: &mut [Receipt], labels: &HashMap<String, String>) {
+    for receipt in receipts.iter_mut() {
+        if let Some(label) = labels.get(address::unqualified_part(&receipt.recipient)) {
+            receipt.label = Some(label.clone());
+        }
+    }
+}
+
+/**
+    The receipt in the json emitted before receipts were versioned.
+*/
+impl From<Receipt> for UTXO {
+    fn from(receipt: Receipt) -> UTXO {
+        UTXO {
+            recipient: receipt.recipient,
+            receipt: receipt.receipt,
+            amount: receipt.amount,
+            extra: receipt.extra,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod receipt_tests {
+    use serde_json;
+
+    use logic::schema::{validate_json, Schema};
+    use super::*;
+
+    static ADDRESS: &'static str = "pay:sov:iTQzpdRdugkJ2gLD5vW5c159dncSL9jbAtu3WfPcb8qWD9bUd";
+
+    #[test]
+    fn receipt_json() {
+        let receipt = Receipt::new(ADDRESS, 10, 3, Some(1529682415), Some("memo")).unwrap();
+        let json = serde_json::to_value(&receipt).unwrap();
+
+        assert_eq!(json!(RECEIPT_VERSION), json["ver"]);
+        assert_eq!(json!(3), json["seqNo"]);
+        assert_eq!(json!(1529682415), json["txnTime"]);
+        assert_eq!(json!("memo"), json["extra"]);
+        assert_eq!(TXO::new(ADDRESS, 3), receipt.txo().unwrap());
+    }
+
+    #[test]
+    fn receipts_match_their_schema() {
+        let receipts = vec![
+            Receipt::new(ADDRESS, 10, 3, Some(1529682415), None).unwrap(),
+            Receipt::new(ADDRESS, 5, 4, None, Some("memo")).unwrap(),
+        ];
+        let json = serde_json::to_string(&receipts).unwrap();
+
+        assert_eq!(Ok(()), validate_json(Schema::Receipts, &json));
+    }
+
+    #[test]
+    fn labels_of_the_recipients() {
+        let mut receipts = vec![
+            Receipt::new(ADDRESS, 10, 3, None, None).unwrap(),
+            Receipt::new("pay:sov:a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7", 5, 3, None, None).unwrap(),
+        ];
+        let mut labels = HashMap::new();
+        labels.insert(address::unqualified_part(ADDRESS).to_string(), String::from("savings"));
+
+        add_labels(&mut receipts, &labels);
+        assert_eq!(Some(String::from("savings")), receipts[0].label);
+        assert_eq!(None, receipts[1].label);
+        assert_eq!(Ok(()), validate_json(Schema::Receipts, &serde_json::to_string(&receipts).unwrap()));
+    }
+
+    #[test]
+    fn receipt_keeps_the_unversioned_fields() {
+        let receipt = Receipt::new(ADDRESS, 10, 3, None, None).unwrap();
+        let utxo = UTXO::from(receipt.clone());
+        let receipt_json = serde_json::to_value(&receipt).unwrap();
+
+        for (key, value) in serde_json::to_value(&utxo).unwrap().as_object().unwrap() {
+            assert_eq!(value, &receipt_json[key], "{}", key);
+        }
+    }
+}
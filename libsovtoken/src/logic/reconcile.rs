@@ -0,0 +1,290 @@
+/*!
+    Crash recovery for the UTXOs of a payment address.
+
+    After a crash between submitting a payment and recording its reply, the
+    pending transactions and UTXO reservations in the wallet can disagree
+    with the ledger. Reconciling replays them against the UTXOs the ledger
+    holds now:
+
+    * a submitted transaction whose inputs are all spent made it to the
+      ledger and is marked confirmed,
+    * a reservation of a UTXO which doesn't exist anymore is released,
+    * anything else that doesn't match the ledger is reported.
+*/
+
+use std::collections::HashSet;
+
+use indy::{IndyHandle, ErrorCode};
+
+use logic::address::unqualified_part;
+use logic::indy_sdk_api::payment_api::PaymentAPI;
+use logic::indy_sdk_api::wallet_api::WalletAPI;
+use logic::parsers::common::TXO;
+use logic::parsers::parse_get_utxo_response::UTXO;
+use logic::payment_intent::IntentStore;
+use logic::pending_txn::{PendingState, PendingTxn, PendingTxnStore};
+use logic::storage::Storage;
+use logic::type_aliases::TxnSeqNo;
+
+/**
+    How a pending transaction disagrees with the ledger.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum DiscrepancyKind {
+    /// Submitted, but none of its inputs is spent: it never reached the ledger.
+    NotOnLedger,
+    /// Some of its inputs are spent and some aren't.
+    PartiallySpent,
+    /// Confirmed, but none of its inputs is spent.
+    ConfirmedUnspent,
+    /// Not submitted yet, but its inputs were spent by another transaction.
+    SpentElsewhere,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Discrepancy {
+    pub txn_id: String,
+    pub state: PendingState,
+    pub kind: DiscrepancyKind,
+    pub spent_inputs: usize,
+    pub unspent_inputs: usize,
+}
+
+/**
+    What reconciling an address changed and found.
+
+    `confirmed` are the ids of the pending transactions marked confirmed,
+    `released` the UTXO sources whose reservation was released.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconcileReport {
+    pub address: String,
+    pub utxo_count: usize,
+    pub confirmed: Vec<String>,
+    pub released: Vec<String>,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+/**
+    Reconciler compares the wallet state of an address with the ledger.
+*/
+pub struct Reconciler<W, P> where W: WalletAPI, P: PaymentAPI {
+    wallet_api: W,
+    payment_api: P,
+}
+
+impl<W: WalletAPI, P: PaymentAPI> Reconciler<W, P> {
+    pub fn new(wallet_api: W, payment_api: P) -> Self {
+        Reconciler { wallet_api, payment_api }
+    }
+
+    /**
+        Reconcile the pending transactions and reservations of `address`
+        with its UTXOs on the ledger.
+
+        Transactions without inputs from `address` and rejected transactions
+        are left alone. Fails with the errors of requesting the UTXOs, `cb` is
+        called with the errors of fetching them and of updating the wallet.
+    */
+    pub fn reconcile<F>(&self, wallet_handle: IndyHandle, pool_handle: IndyHandle, address: &str, now: u64, cb: F) -> Result<(), ErrorCode>
+        where W: Clone + Send + 'static, F: FnMut(Result<ReconcileReport, ErrorCode>) + 'static + Send
+    {
+        trace!("logic::reconcile::reconcile >> address: {:?}", address);
+        let mut pending = Some((self.wallet_api.clone(), address.to_string(), cb));
+
+        let ec = self.payment_api.indy_get_utxos_async(pool_handle, address, move |utxos| {
+            let (wallet_api, address, mut cb) = match pending.take() {
+                Some(pending) => pending,
+                None => return,
+            };
+
+            let report = utxos.and_then(|utxos| replay(&wallet_api, wallet_handle, &address, now, &utxos));
+            trace!("logic::reconcile::reconcile << report: {:?}", report);
+            cb(report);
+        });
+        match ec {
+            ErrorCode::Success => Ok(()),
+            ec => Err(ec),
+        }
+    }
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// Replay the pending transactions and reservations of `address` against `utxos`.
+fn replay<W: WalletAPI>(wallet_api: &W, wallet_handle: IndyHandle, address: &str, now: u64, utxos: &[UTXO]) -> Result<ReconcileReport, ErrorCode> {
+    let address_key = unqualified_part(address).to_string();
+    let unspent: HashSet<(String, TxnSeqNo)> = utxos.iter()
+        .map(|utxo| TXO::from_libindy_string(&utxo.source).map(|txo| (unqualified_part(&txo.address).to_string(), txo.seq_no)))
+        .collect::<Result<_, _>>()
+        .map_err(ErrorCode::from)?;
+
+    let txn_store = PendingTxnStore::new(Storage::configured(wallet_api));
+    let mut confirmed = Vec::new();
+    let mut discrepancies = Vec::new();
+
+    for (txn_id, txn) in txn_store.list(wallet_handle)? {
+        let inputs: Vec<(String, TxnSeqNo)> = txn.inputs()?
+            .into_iter()
+            .filter(|&(ref input_address, _)| *input_address == address_key)
+            .collect();
+
+        if inputs.is_empty() || txn.state == PendingState::Rejected {
+            continue;
+        }
+
+        let unspent_inputs = inputs.iter().filter(|input| unspent.contains(*input)).count();
+        let spent_inputs = inputs.len() - unspent_inputs;
+
+        let kind = match (txn.state, spent_inputs, unspent_inputs) {
+            (_, spent, unspent) if spent > 0 && unspent > 0 => Some(DiscrepancyKind::PartiallySpent),
+            (PendingState::Submitted, 0, _) => Some(DiscrepancyKind::NotOnLedger),
+            (PendingState::Submitted, _, 0) => {
+                txn_store.advance(wallet_handle, &txn_id, PendingState::Confirmed, None, None, now)?;
+                confirmed.push(txn_id.clone());
+                None
+            },
+            (PendingState::Confirmed, 0, _) => Some(DiscrepancyKind::ConfirmedUnspent),
+            (PendingState::Built, _, 0) | (PendingState::Signed, _, 0) => Some(DiscrepancyKind::SpentElsewhere),
+            _ => None,
+        };
+
+        if let Some(kind) = kind {
+            discrepancies.push(Discrepancy { txn_id, state: txn.state, kind, spent_inputs, unspent_inputs });
+        }
+    }
+
+    let intent_store = IntentStore::new(wallet_api);
+    let mut released = Vec::new();
+
+    for (source, _) in intent_store.reservations(wallet_handle)? {
+        let txo = match TXO::from_libindy_string(&source) {
+            Ok(txo) => (unqualified_part(&txo.address).to_string(), txo.seq_no),
+            Err(e) => {
+                warn!("Skipping the reservation of {}: {}", source, e);
+                continue;
+            }
+        };
+
+        if txo.0 == address_key && !unspent.contains(&txo) {
+            intent_store.release(wallet_handle, &source)?;
+            released.push(source);
+        }
+    }
+
+    Ok(ReconcileReport {
+        address: address.to_string(),
+        utxo_count: utxos.len(),
+        confirmed,
+        released,
+        discrepancies,
+    })
+}
+
+#[cfg(test)]
+mod reconcile_tests {
+    use serde_json::Value as SJsonValue;
+
+    use utils::test::wallet::WalletMockHandler;
+    use super::*;
+
+    static WALLET_HANDLE: i32 = 1;
+    static ADDRESS: &'static str = "pay:sov:E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm";
+
+    fn source(seq_no: TxnSeqNo) -> String {
+        TXO { address: ADDRESS.to_string(), seq_no }.to_libindy_string().unwrap()
+    }
+
+    fn request(seq_nos: &[TxnSeqNo]) -> String {
+        let inputs: Vec<SJsonValue> = seq_nos.iter()
+            .map(|seq_no| json!({"address": unqualified_part(ADDRESS), "seqNo": seq_no}))
+            .collect();
+        json!({"operation": {"type": "10001", "inputs": inputs}}).to_string()
+    }
+
+    fn track(wallet: &WalletMockHandler, seq_nos: &[TxnSeqNo], state: PendingState) -> String {
+        let store = PendingTxnStore::new(wallet);
+        let txn_id = store.track(WALLET_HANDLE, &request(seq_nos), PendingState::Signed, 0).unwrap();
+        if state != PendingState::Signed {
+            store.advance(WALLET_HANDLE, &txn_id, PendingState::Submitted, None, None, 0).unwrap();
+        }
+        if state == PendingState::Confirmed {
+            store.advance(WALLET_HANDLE, &txn_id, PendingState::Confirmed, None, None, 0).unwrap();
+        }
+        txn_id
+    }
+
+    // the ledger holds the UTXOs with the seqNos `unspent`
+    fn reconcile(wallet: &WalletMockHandler, unspent: Vec<TxnSeqNo>) -> ReconcileReport {
+        let utxos: Vec<UTXO> = unspent.into_iter().map(|seq_no| UTXO {
+            payment_address: ADDRESS.to_string(),
+            source: source(seq_no),
+            amount: 10,
+            extra: "".to_string(),
+        }).collect();
+        replay(wallet, WALLET_HANDLE, ADDRESS, 10, &utxos).unwrap()
+    }
+
+    #[test]
+    fn submitted_and_spent_is_confirmed() {
+        let wallet = WalletMockHandler::default();
+        let txn_id = track(&wallet, &[1, 2], PendingState::Submitted);
+
+        let report = reconcile(&wallet, vec![3]);
+
+        assert_eq!(vec![txn_id.clone()], report.confirmed);
+        assert!(report.discrepancies.is_empty());
+        let txn = PendingTxnStore::new(&wallet).get(WALLET_HANDLE, &txn_id).unwrap();
+        assert_eq!(PendingState::Confirmed, txn.state);
+    }
+
+    #[test]
+    fn submitted_and_unspent_is_reported() {
+        let wallet = WalletMockHandler::default();
+        let txn_id = track(&wallet, &[1], PendingState::Submitted);
+
+        let report = reconcile(&wallet, vec![1]);
+
+        assert!(report.confirmed.is_empty());
+        assert_eq!(vec![Discrepancy {
+            txn_id,
+            state: PendingState::Submitted,
+            kind: DiscrepancyKind::NotOnLedger,
+            spent_inputs: 0,
+            unspent_inputs: 1,
+        }], report.discrepancies);
+    }
+
+    #[test]
+    fn discrepancies_of_other_states() {
+        let wallet = WalletMockHandler::default();
+        track(&wallet, &[1, 2], PendingState::Confirmed);
+        track(&wallet, &[3], PendingState::Signed);
+        track(&wallet, &[4], PendingState::Confirmed);
+
+        let report = reconcile(&wallet, vec![1, 4]);
+
+        let mut kinds: Vec<String> = report.discrepancies.iter().map(|d| format!("{:?}", d.kind)).collect();
+        kinds.sort();
+        assert_eq!(vec!["ConfirmedUnspent", "PartiallySpent", "SpentElsewhere"], kinds);
+    }
+
+    #[test]
+    fn stale_reservations_are_released() {
+        let wallet = WalletMockHandler::default();
+        wallet.indy_add_record(WALLET_HANDLE, ::logic::payment_intent::RESERVATION_RECORD_TYPE, &source(1), "intent").unwrap();
+        wallet.indy_add_record(WALLET_HANDLE, ::logic::payment_intent::RESERVATION_RECORD_TYPE, &source(2), "intent").unwrap();
+
+        let report = reconcile(&wallet, vec![2]);
+
+        assert_eq!(vec![source(1)], report.released);
+        assert_eq!(1, IntentStore::new(&wallet).reservations(WALLET_HANDLE).unwrap().len());
+    }
+}
@@ -0,0 +1,180 @@
+/*!
+    Refreshing the UTXOs of a payment address from the ledger.
+
+    The highest seqNo seen for the address, see [`seq_no_store`], decides
+    whether only the UTXOs after it are requested or all of them. Until the
+    ledger accepts a starting seqNo every refresh is a full refresh, and a
+    delta is cut from the full reply.
+
+    The refresh mode is read before the GET_UTXO is submitted. The seqNos of
+    the reply are recorded when it is parsed, off the callback thread of
+    libindy.
+
+    [`seq_no_store`]: ../seq_no_store/index.html
+*/
+
+use indy::{IndyHandle, ErrorCode};
+
+use logic::indy_sdk_api::payment_api::PaymentAPI;
+use logic::indy_sdk_api::wallet_api::WalletAPI;
+use logic::optimistic_updates;
+use logic::parsers::common::TXO;
+use logic::parsers::parse_get_utxo_response::ParseGetUtxoReply;
+use logic::seq_no_store::{RefreshMode, SeqNoStore};
+use logic::type_aliases::TxnSeqNo;
+
+/**
+    The UTXOs of a refreshed address.
+
+    ```text
+    {
+        "address": <string>,
+        "fromSeqNo": <int>,     // null for a full refresh
+        "lastSeqNo": <int>,     // null when the address never had a UTXO
+        "utxos": [<utxo>, ...]  // as parse_get_utxo_response_handler
+    }
+    ```
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshedUtxos {
+    pub address: String,
+    pub from_seq_no: Option<TxnSeqNo>,
+    pub last_seq_no: Option<TxnSeqNo>,
+    pub utxos: ParseGetUtxoReply,
+}
+
+/**
+    UtxoRefresher fetches the UTXOs of addresses and tracks their seqNos.
+*/
+pub struct UtxoRefresher<W, P> where W: WalletAPI, P: PaymentAPI {
+    wallet_api: W,
+    payment_api: P,
+}
+
+impl<W: WalletAPI, P: PaymentAPI> UtxoRefresher<W, P> {
+    pub fn new(wallet_api: W, payment_api: P) -> Self {
+        UtxoRefresher { wallet_api, payment_api }
+    }
+
+    /**
+        Refresh the UTXOs of `address` and record the highest seqNo of the reply.
+
+        Fails with the errors of reading the wallet and of requesting the
+        UTXOs, `cb` is called with the errors of fetching them and of
+        recording their seqNo.
+    */
+    pub fn refresh<F>(&self, wallet_handle: IndyHandle, pool_handle: IndyHandle, address: &str, cb: F) -> Result<(), ErrorCode>
+        where W: Clone + Send + 'static, F: FnMut(Result<RefreshedUtxos, ErrorCode>) + 'static + Send
+    {
+        trace!("logic::refresh_utxos::refresh >> address: {:?}", address);
+        let mode = SeqNoStore::new(&self.wallet_api).refresh_mode(wallet_handle, address)?;
+        debug!("Refreshing the UTXOs of {}: {:?}", address, mode);
+
+        let mut pending = Some((self.wallet_api.clone(), address.to_string(), cb));
+        let ec = self.payment_api.indy_get_utxos_async(pool_handle, address, move |utxos| {
+            let (wallet_api, address, mut cb) = match pending.take() {
+                Some(pending) => pending,
+                None => return,
+            };
+
+            let refreshed = utxos
+                .map(|utxos| optimistic_updates::apply(Some(pool_handle), utxos))
+                .and_then(|utxos| refreshed(&wallet_api, wallet_handle, address, mode, utxos));
+            trace!("logic::refresh_utxos::refresh << refreshed: {:?}", refreshed);
+            cb(refreshed);
+        });
+        match ec {
+            ErrorCode::Success => Ok(()),
+            ec => Err(ec),
+        }
+    }
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// Record the seqNos of `utxos` and keep the ones `mode` asked for.
+fn refreshed<W: WalletAPI>(wallet_api: &W, wallet_handle: IndyHandle, address: String, mode: RefreshMode, utxos: ParseGetUtxoReply) -> Result<RefreshedUtxos, ErrorCode> {
+    let store = SeqNoStore::new(wallet_api);
+    store.record_utxo_reply(wallet_handle, &utxos)?;
+    let last_seq_no = store.last_seq_no(wallet_handle, &address)?;
+
+    let (from_seq_no, utxos) = match mode {
+        RefreshMode::Full => (None, utxos),
+        RefreshMode::Delta { from } => {
+            let delta = utxos.into_iter()
+                .filter(|utxo| TXO::from_libindy_string(&utxo.source).map(|txo| txo.seq_no > from).unwrap_or(true))
+                .collect();
+            (Some(from), delta)
+        },
+    };
+
+    Ok(RefreshedUtxos { address, from_seq_no, last_seq_no, utxos })
+}
+
+
+#[cfg(test)]
+mod refresh_utxos_tests {
+    use logic::parsers::parse_get_utxo_response::UTXO;
+    use utils::test::wallet::WalletMockHandler;
+    use super::*;
+
+    static WALLET_HANDLE: i32 = 1;
+    static ADDRESS: &'static str = "pay:sov:iTQzpdRdugkJ2gLD5vW5c159dncSL9jbAtu3WfPcb8qWD9bUd";
+
+    fn utxos(seq_nos: &[TxnSeqNo]) -> ParseGetUtxoReply {
+        seq_nos.iter().map(|seq_no| UTXO {
+            payment_address: ADDRESS.to_string(),
+            source: TXO { address: ADDRESS.to_string(), seq_no: *seq_no }.to_libindy_string().unwrap(),
+            amount: 10,
+            extra: "".to_string(),
+        }).collect()
+    }
+
+    #[test]
+    fn full_refresh_records_seq_no() {
+        let wallet = WalletMockHandler::default();
+
+        let refreshed = refreshed(&wallet, WALLET_HANDLE, ADDRESS.to_string(), RefreshMode::Full, utxos(&[3, 8, 5])).unwrap();
+
+        assert_eq!(None, refreshed.from_seq_no);
+        assert_eq!(Some(8), refreshed.last_seq_no);
+        assert_eq!(3, refreshed.utxos.len());
+        assert_eq!(Some(8), SeqNoStore::new(&wallet).last_seq_no(WALLET_HANDLE, ADDRESS).unwrap());
+    }
+
+    #[test]
+    fn refresh_keeps_highest_seq_no() {
+        let wallet = WalletMockHandler::default();
+        SeqNoStore::new(&wallet).record_seq_no(WALLET_HANDLE, ADDRESS, 12).unwrap();
+
+        let refreshed = refreshed(&wallet, WALLET_HANDLE, ADDRESS.to_string(), RefreshMode::Full, utxos(&[3])).unwrap();
+
+        assert_eq!(Some(12), refreshed.last_seq_no);
+    }
+
+    #[test]
+    fn refresh_without_utxos() {
+        let wallet = WalletMockHandler::default();
+
+        let refreshed = refreshed(&wallet, WALLET_HANDLE, ADDRESS.to_string(), RefreshMode::Full, Vec::new()).unwrap();
+
+        assert_eq!(None, refreshed.last_seq_no);
+        assert!(refreshed.utxos.is_empty());
+    }
+
+    #[test]
+    fn delta_refresh_keeps_later_utxos() {
+        let wallet = WalletMockHandler::default();
+
+        let refreshed = refreshed(&wallet, WALLET_HANDLE, ADDRESS.to_string(), RefreshMode::Delta { from: 5 }, utxos(&[3, 8, 5])).unwrap();
+
+        assert_eq!(Some(5), refreshed.from_seq_no);
+        assert_eq!(utxos(&[8]), refreshed.utxos);
+        assert_eq!(Some(8), refreshed.last_seq_no);
+    }
+}
@@ -0,0 +1,308 @@
+/*!
+    The registration of the library with libindy, and what came of it.
+
+    `sovtoken_init` registers three things with libindy: the payment method
+    with its callbacks, and the state proof parsers of GET_UTXO and GET_FEES.
+    Each is a [`RegistrationStep`]. A step failing doesn't stop the others,
+    so the payment method is usable even when a state proof parser isn't,
+    and the [`RegistrationStatus`] tells which steps succeeded and why the
+    others failed.
+
+    libindy has no way to unregister, a step registered once stays
+    registered for the life of the process. Calling `sovtoken_init` again
+    skips the registered steps, unless `force_reregister` is set in the
+    library config, e.g. after libindy was reloaded. libindy refuses to
+    register a state proof parser twice, with `CommonInvalidState`: the
+    step is then already registered, e.g. by the `sovtoken_init` of another
+    context, see `logic::context`.
+
+    [`RegistrationStep`]: struct.RegistrationStep.html
+    [`RegistrationStatus`]: struct.RegistrationStatus.html
+*/
+
+use indy::ErrorCode;
+
+use logic::messages;
+use logic::state::state;
+
+/**
+    Registration of the payment method and its callbacks.
+*/
+pub const PAYMENT_METHOD: &str = "payment_method";
+
+/**
+    The callbacks of the payment method, in the order libindy takes them.
+*/
+pub const PAYMENT_METHOD_CALLBACKS: &[&str] = &[
+    "create_payment_address_handler",
+    "add_request_fees_handler",
+    "parse_response_with_fees_handler",
+    "build_get_utxo_request_handler",
+    "parse_get_utxo_response_handler",
+    "build_payment_req_handler",
+    "parse_payment_response_handler",
+    "build_mint_txn_handler",
+    "build_set_txn_fees_handler",
+    "build_get_txn_fees_handler",
+    "parse_get_txn_fees_response_handler",
+    "build_verify_req_handler",
+    "parse_verify_response_handler",
+];
+
+/**
+    Registration of the state proof parser of GET_UTXO.
+*/
+pub const GET_UTXO_STATE_PROOF_PARSER: &str = "get_utxo_state_proof_parser";
+
+/**
+    Registration of the state proof parser of GET_FEES.
+*/
+pub const GET_FEES_STATE_PROOF_PARSER: &str = "get_fees_state_proof_parser";
+
+/**
+    What became of a step.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StepOutcome {
+    /// Registered by this call.
+    Registered,
+    /// Registered by an earlier call, and not registered again.
+    AlreadyRegistered,
+    /// libindy refused it.
+    Failed,
+}
+
+/**
+    Why libindy refused a step, from the error it returned.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureClass {
+    /// libindy rejected an argument, e.g. it is of another version than the library was built against.
+    IncompatibleLibindy,
+    /// libindy isn't in a state to register, e.g. another library registered the same name.
+    Conflict,
+    /// Any other error.
+    Other,
+}
+
+impl FailureClass {
+    /**
+        The class of the error libindy returned.
+    */
+    pub fn of(error: ErrorCode) -> FailureClass {
+        match error {
+            ErrorCode::CommonInvalidParam1 | ErrorCode::CommonInvalidParam2 |
+            ErrorCode::CommonInvalidParam3 | ErrorCode::CommonInvalidParam4 |
+            ErrorCode::CommonInvalidParam5 | ErrorCode::CommonInvalidParam6 |
+            ErrorCode::CommonInvalidParam7 | ErrorCode::CommonInvalidParam8 |
+            ErrorCode::CommonInvalidParam9 | ErrorCode::CommonInvalidParam10 |
+            ErrorCode::CommonInvalidParam11 | ErrorCode::CommonInvalidParam12 |
+            ErrorCode::CommonInvalidStructure => FailureClass::IncompatibleLibindy,
+            ErrorCode::CommonInvalidState => FailureClass::Conflict,
+            _ => FailureClass::Other,
+        }
+    }
+
+    // What to do about it, for the message of the failure.
+    fn hint(self) -> &'static str {
+        match self {
+            FailureClass::IncompatibleLibindy => "check that libindy is of the version the library was built against",
+            FailureClass::Conflict => "check that no other library registered the same name",
+            FailureClass::Other => "see the libindy log",
+        }
+    }
+}
+
+/**
+    A step of the registration.
+
+    `callbacks` are the callbacks registered by the step. `error` and
+    `failure` are the error libindy returned and its class, when the step
+    failed.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationStep {
+    pub name: String,
+    pub callbacks: Vec<String>,
+    pub outcome: StepOutcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<FailureClass>,
+}
+
+/**
+    What came of the last `sovtoken_init`.
+
+    `registered` is true when every step is registered, `forced` when the
+    steps registered by an earlier call were registered again.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationStatus {
+    pub registered: bool,
+    pub forced: bool,
+    pub steps: Vec<RegistrationStep>,
+}
+
+impl RegistrationStatus {
+    /**
+        The error of the first failed step, `Success` when none failed.
+    */
+    pub fn error_code(&self) -> ErrorCode {
+        self.steps.iter()
+            .filter_map(|step| step.error)
+            .next()
+            .map_or(ErrorCode::Success, ErrorCode::from)
+    }
+}
+
+/**
+    A step to run: its name, the names of its callbacks and the call
+    registering them.
+
+    `already_registered` is the error libindy returns when the step was
+    registered before, `None` when it registers a step again without error.
+*/
+pub struct Step<'a> {
+    pub name: &'static str,
+    pub callbacks: &'static [&'static str],
+    pub register: Box<Fn() -> Result<(), ErrorCode> + 'a>,
+    pub already_registered: Option<ErrorCode>,
+}
+
+/**
+    Run the `steps` which aren't registered yet, every one when `force` is
+    set, and keep the status as the status of the library.
+
+    A failed step sets the last error and doesn't stop the next steps.
+*/
+pub fn register(steps: Vec<Step>, force: bool) -> RegistrationStatus {
+    let previous = status();
+
+    let steps: Vec<RegistrationStep> = steps.into_iter()
+        .map(|step| {
+            let callbacks = step.callbacks.iter().map(|callback| callback.to_string()).collect();
+            if !force && was_registered(&previous, step.name) {
+                debug!("{} is already registered", step.name);
+                return RegistrationStep { name: step.name.to_string(), callbacks, outcome: StepOutcome::AlreadyRegistered, error: None, failure: None };
+            }
+
+            match (step.register)() {
+                Ok(()) => RegistrationStep { name: step.name.to_string(), callbacks, outcome: StepOutcome::Registered, error: None, failure: None },
+                Err(error) if Some(error) == step.already_registered => {
+                    debug!("{} was already registered with libindy", step.name);
+                    RegistrationStep { name: step.name.to_string(), callbacks, outcome: StepOutcome::AlreadyRegistered, error: None, failure: None }
+                },
+                Err(error) => {
+                    let failure = FailureClass::of(error);
+                    error!("Registering {} failed with {:?}", step.name, error);
+                    messages::set_last_error(error, messages::REGISTRATION_FAILED.with(&[step.name.to_string(), format!("{:?}", error), failure.hint().to_string()]));
+                    RegistrationStep { name: step.name.to_string(), callbacks, outcome: StepOutcome::Failed, error: Some(error as i32), failure: Some(failure) }
+                }
+            }
+        })
+        .collect();
+
+    let status = RegistrationStatus {
+        registered: steps.iter().all(|step| step.outcome != StepOutcome::Failed),
+        forced: force,
+        steps,
+    };
+    *state().registration.lock() = Some(status.clone());
+    status
+}
+
+/**
+    The status of the last `sovtoken_init`, `None` before the first one.
+*/
+pub fn status() -> Option<RegistrationStatus> {
+    state().registration.lock().clone()
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// Whether the step `name` was registered by an earlier call.
+fn was_registered(previous: &Option<RegistrationStatus>, name: &str) -> bool {
+    previous.as_ref().map_or(false, |previous| {
+        previous.steps.iter().any(|step| step.name == name && step.outcome != StepOutcome::Failed)
+    })
+}
+
+
+#[cfg(test)]
+mod registration_tests {
+    use std::cell::Cell;
+
+    use logic::config::library_config::LibraryConfig;
+    use utils::test::context::in_own_context;
+    use super::*;
+
+    fn step<'a>(name: &'static str, calls: &'a Cell<u32>, result: Result<(), ErrorCode>) -> Step<'a> {
+        Step {
+            name,
+            callbacks: &["callback"],
+            register: Box::new(move || {
+                calls.set(calls.get() + 1);
+                result
+            }),
+            already_registered: Some(ErrorCode::CommonInvalidState),
+        }
+    }
+
+    #[test]
+    fn failed_steps_are_retried_registered_steps_skipped() {
+        in_own_context(LibraryConfig::default(), failed_steps_are_retried_registered_steps_skipped_in_context);
+    }
+
+    fn failed_steps_are_retried_registered_steps_skipped_in_context() {
+        let (payment, parser) = (Cell::new(0), Cell::new(0));
+
+        let status = register(vec![
+            step("test_payment", &payment, Ok(())),
+            step("test_parser", &parser, Err(ErrorCode::CommonInvalidParam3)),
+        ], false);
+        assert!(!status.registered);
+        assert_eq!(ErrorCode::CommonInvalidParam3, status.error_code());
+        assert_eq!(Some(FailureClass::IncompatibleLibindy), status.steps[1].failure);
+        assert_eq!(json!("incompatible_libindy"), json!(status.steps[1].failure));
+
+        let status = register(vec![
+            step("test_payment", &payment, Ok(())),
+            step("test_parser", &parser, Ok(())),
+        ], false);
+        assert!(status.registered);
+        assert_eq!(StepOutcome::AlreadyRegistered, status.steps[0].outcome);
+        assert_eq!(StepOutcome::Registered, status.steps[1].outcome);
+        assert_eq!((1, 2), (payment.get(), parser.get()));
+
+        let status = register(vec![step("test_payment", &payment, Ok(()))], true);
+        assert_eq!(StepOutcome::Registered, status.steps[0].outcome);
+        assert_eq!(2, payment.get());
+        assert_eq!(Some(status), super::status());
+    }
+
+    #[test]
+    fn step_registered_with_libindy_before() {
+        in_own_context(LibraryConfig::default(), || {
+            let parser = Cell::new(0);
+
+            // e.g. by the sovtoken_init of another context
+            let status = register(vec![step("test_parser", &parser, Err(ErrorCode::CommonInvalidState))], false);
+            assert!(status.registered);
+            assert_eq!(StepOutcome::AlreadyRegistered, status.steps[0].outcome);
+            assert_eq!(ErrorCode::Success, status.error_code());
+
+            let mut payment = step("test_payment", &parser, Err(ErrorCode::CommonInvalidState));
+            payment.already_registered = None;
+            let status = register(vec![payment], false);
+            assert_eq!(Some(FailureClass::Conflict), status.steps[0].failure);
+        });
+    }
+}
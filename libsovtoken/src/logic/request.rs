@@ -6,14 +6,115 @@ use std::ffi::CString;
 use libc::c_char;
 use indy::{IndyHandle, ErrorCode, ledger::Ledger};
 
-use utils::ffi_support::{cstring_from_str, c_pointer_from_string};
-use utils::random::rand_req_id;
-use utils::json_conversion::JsonSerialize;
-use utils::constants::general::PROTOCOL_VERSION;
+use utils::ffi_support::c_pointer_from_string;
+use utils::random::next_req_id;
+use utils::span;
+use utils::serde_buffers;
+use utils::constants::general::SUPPORTED_PROTOCOL_VERSIONS;
+use logic::amount_format::{with_amount_format, AmountFormat};
+use logic::config::library_config::library_config;
+use logic::error::SovtokenError;
+use logic::messages;
+use logic::field_names::{with_field_names, FieldNames};
 use logic::type_aliases::{ProtocolVersion, ReqId};
 
 pub const DEFAULT_LIBSOVTOKEN_DID: &'static str = "LibsovtokenDid11111111";
 
+/**
+    The protocol version of the requests without a `protocolVersion`.
+*/
+pub const LEGACY_PROTOCOL_VERSION: ProtocolVersion = 1;
+
+/**
+    The identifier of the requests built without a submitter DID, the
+    `default_identifier` of the library config or [`DEFAULT_LIBSOVTOKEN_DID`].
+
+    [`DEFAULT_LIBSOVTOKEN_DID`]: constant.DEFAULT_LIBSOVTOKEN_DID.html
+*/
+pub fn default_identifier() -> String {
+    library_config().default_identifier.unwrap_or_else(|| DEFAULT_LIBSOVTOKEN_DID.to_string())
+}
+
+/**
+    Run `f` emitting the field names and amounts the ledger knows, whatever
+    the library config says.
+
+    Everything serialized for the ledger or signed for it goes through here.
+*/
+pub fn with_ledger_format<F, R>(f: F) -> R where F: FnOnce() -> R {
+    with_field_names(FieldNames::Address, || with_amount_format(AmountFormat::Number, f))
+}
+
+/**
+    Fail with `CommonInvalidStructure` when requests can't be framed with
+    `protocol_version`.
+*/
+pub fn check_protocol_version(protocol_version: ProtocolVersion) -> Result<(), ErrorCode> {
+    if SUPPORTED_PROTOCOL_VERSIONS.contains(&protocol_version) {
+        return Ok(());
+    }
+
+    let supported = SUPPORTED_PROTOCOL_VERSIONS.iter().map(ProtocolVersion::to_string).collect::<Vec<String>>().join(", ");
+    Err(ErrorCode::from(SovtokenError::InvalidStructure(
+        messages::UNSUPPORTED_PROTOCOL_VERSION.with(&[protocol_version.to_string(), supported])
+    )))
+}
+
+/**
+    The protocol version a request json is framed with, 1 when it has no
+    `protocolVersion`.
+*/
+pub fn protocol_version_of(request_json: &str) -> Result<ProtocolVersion, ErrorCode> {
+    let request: serde_json::Value = serde_json::from_str(request_json).map_err(|error| {
+        error!("Invalid request json: {}", error);
+        ErrorCode::CommonInvalidStructure
+    })?;
+
+    match request.get("protocolVersion") {
+        None => Ok(LEGACY_PROTOCOL_VERSION),
+        Some(version) => version.as_u64()
+            .map(|version| version as ProtocolVersion)
+            .ok_or(ErrorCode::CommonInvalidStructure),
+    }
+}
+
+/**
+    Fail with `PoolIncompatibleProtocolVersion` when `request_json` states
+    another `protocolVersion` than the `protocol_version` of the library
+    config, e.g. libindy was set to another version than the library.
+
+    A request without `protocolVersion` passes, it was framed by hand.
+*/
+pub fn check_framing(request_json: &str) -> Result<(), ErrorCode> {
+    let request: serde_json::Value = serde_json::from_str(request_json).map_err(|error| {
+        error!("Invalid request json: {}", error);
+        ErrorCode::CommonInvalidStructure
+    })?;
+    if request.get("protocolVersion").is_none() {
+        return Ok(());
+    }
+
+    let request_version = protocol_version_of(request_json)?;
+    let library_version = library_config().protocol_version;
+    if request_version == library_version {
+        return Ok(());
+    }
+
+    let message = messages::FRAMED_FOR_OTHER_PROTOCOL_VERSION.with(&[request_version.to_string(), library_version.to_string()]);
+    error!("{}", message.text);
+    messages::set_last_error(ErrorCode::PoolIncompatibleProtocolVersion, message);
+    Err(ErrorCode::PoolIncompatibleProtocolVersion)
+}
+
+/**
+    A request to the ledger.
+
+    With protocol version 2 the request carries its `protocolVersion`. The
+    pools older than indy-node 1.4 don't know the field, a request of
+    protocol version 1 is framed without it. The operation is nested the
+    same way in both versions, the replies aren't: a pool of protocol
+    version 1 replies with a flat `result`, see `logic::parsers::legacy_reply`.
+*/
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Request<T>
@@ -21,6 +122,7 @@ pub struct Request<T>
 {
     pub operation: T,
     pub req_id: ReqId,
+    #[serde(default = "legacy_protocol_version", skip_serializing_if = "is_legacy_protocol_version")]
     pub protocol_version: ProtocolVersion,
     pub identifier : String,
 }
@@ -29,22 +131,25 @@ impl<T> Request<T>
     where T: Serialize
 {
     pub fn new(operation: T, identifier : Option<String>) -> Self {
-        let req_id = rand_req_id();
+        let req_id = next_req_id();
+        span::record_req_id(u64::from(req_id));
         return Request {
             operation,
-            protocol_version: PROTOCOL_VERSION,
+            protocol_version: library_config().protocol_version,
             req_id,
-            identifier: identifier.unwrap_or(DEFAULT_LIBSOVTOKEN_DID.to_string())
+            identifier: identifier.unwrap_or_else(default_identifier)
         }
     }
 
     pub fn serialize_to_cstring(&self) -> Result<CString, serde_json::Error> {
-        return self.serialize_to_string().map_err(map_err_err!())
-            .map(|string| cstring_from_str(string));
+        return with_ledger_format(|| serde_buffers::to_json_cstring(&self)).map_err(map_err_err!());
     }
 
+    /**
+        The json of the request, with the field names and amounts of the ledger.
+    */
     pub fn serialize_to_string(&self) -> Result<String, serde_json::Error> {
-        return JsonSerialize::to_json(&self).map_err(map_err_err!());
+        return with_ledger_format(|| serde_buffers::to_json_string(&self)).map_err(map_err_err!());
     }
 
     pub fn serialize_to_pointer(&self) -> Result<*const c_char, serde_json::Error> {
@@ -52,6 +157,15 @@ impl<T> Request<T>
             .map(|string| c_pointer_from_string(string));
     }
 
+    /**
+        The size in bytes of the serialized request.
+
+        Signatures added to the request afterwards, e.g. by multi signing, are not included.
+    */
+    pub fn estimated_size(&self) -> Result<usize, serde_json::Error> {
+        return with_ledger_format(|| serde_buffers::json_len(&self)).map_err(map_err_err!());
+    }
+
     pub fn multi_sign_request(wallet_handle: IndyHandle, req: &str, dids: Vec<&str>) -> Result<String, ErrorCode> {
         let mut signed_req: String = req.to_string();
         for did in dids {
@@ -59,4 +173,81 @@ impl<T> Request<T>
         }
         Ok(signed_req)
     }
-}
\ No newline at end of file
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn legacy_protocol_version() -> ProtocolVersion {
+    LEGACY_PROTOCOL_VERSION
+}
+
+fn is_legacy_protocol_version(protocol_version: &ProtocolVersion) -> bool {
+    *protocol_version == LEGACY_PROTOCOL_VERSION
+}
+
+
+#[cfg(test)]
+mod request_tests {
+    use logic::config::library_config::LibraryConfig;
+    use logic::output::Output;
+    use utils::test::context::in_own_context;
+    use super::*;
+
+    fn request(protocol_version: ProtocolVersion) -> Request<String> {
+        Request { operation: String::from("op"), req_id: 1, protocol_version, identifier: String::from("id") }
+    }
+
+    #[test]
+    fn protocol_version_2_framing() {
+        let json = request(2).serialize_to_string().unwrap();
+        assert_eq!(2, protocol_version_of(&json).unwrap());
+        assert!(json.contains("\"protocolVersion\":2"));
+    }
+
+    #[test]
+    fn protocol_version_1_framing() {
+        let json = request(1).serialize_to_string().unwrap();
+        assert!(!json.contains("protocolVersion"));
+        assert_eq!(1, protocol_version_of(&json).unwrap());
+
+        let parsed: Request<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(1, parsed.protocol_version);
+    }
+
+    #[test]
+    fn unsupported_protocol_version() {
+        check_protocol_version(1).unwrap();
+        check_protocol_version(2).unwrap();
+        assert_eq!(ErrorCode::CommonInvalidStructure, check_protocol_version(3).unwrap_err());
+        assert_eq!(ErrorCode::CommonInvalidStructure, protocol_version_of("{\"protocolVersion\": \"2\"}").unwrap_err());
+    }
+
+    #[test]
+    fn framing_of_the_library_config() {
+        in_own_context(LibraryConfig { protocol_version: 1, ..LibraryConfig::default() }, || {
+            check_framing(&request(1).serialize_to_string().unwrap()).unwrap();
+            check_framing("{\"operation\": {\"type\": \"1\"}}").unwrap();
+            assert_eq!(ErrorCode::PoolIncompatibleProtocolVersion, check_framing(&request(2).serialize_to_string().unwrap()).unwrap_err());
+        });
+    }
+
+    #[test]
+    fn ledger_field_names_whatever_the_config() {
+        let config = LibraryConfig { field_names: FieldNames::Recipient, ..LibraryConfig::default() };
+        in_own_context(config, || {
+            let outputs = vec![Output::new(String::from("pay:sov:1"), 10)];
+            let request = Request { operation: outputs.clone(), req_id: 1, protocol_version: 2, identifier: String::from("id") };
+
+            let json = request.serialize_to_string().unwrap();
+            assert!(json.contains("\"address\":\"pay:sov:1\""));
+            assert!(!json.contains("recipient"));
+
+            let fees = with_ledger_format(|| json!([outputs]));
+            assert_eq!(json!("pay:sov:1"), fees[0][0]["address"]);
+            assert!(json!(outputs)[0].get("recipient").is_some());
+        });
+    }
+}
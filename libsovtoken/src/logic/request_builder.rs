@@ -0,0 +1,312 @@
+/*!
+    Fluent builders for Rust consumers of libsovtoken.
+
+    Composes the existing config structs, so a payment request can be built
+    without assembling input and output json by hand.
+
+    ```
+        use sovtoken::logic::config::payment_config::PaymentRequest;
+        use sovtoken::logic::input::Input;
+        use sovtoken::logic::output::Output;
+
+        let payload = PaymentRequest::builder()
+            .input(Input::new(String::from("pay:sov:E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm"), 1))
+            .output(Output::new(String::from("pay:sov:TKe9eXtchV71J2qXX5HwP8rbkTBStnEEkMwQkHie265VtRSbs"), 10))
+            .build()
+            .unwrap();
+
+        assert_eq!(1, payload.inputs.len());
+    ```
+*/
+
+use indy::{IndyHandle, ErrorCode};
+
+use logic::balance::{outputs_total, validate_balance};
+use logic::build_payment::build_payment_request;
+use logic::canonical_order;
+use logic::config::payment_config::PaymentRequest;
+use logic::config::set_fees_config::SetFeesMap;
+use logic::indy_sdk_api::crypto_api::CryptoAPI;
+use logic::input::{Input, Inputs};
+use logic::output::{Output, Outputs};
+use logic::parsers::common::TXO;
+use logic::parsers::parse_get_utxo_response::UTXO;
+use logic::request::Request;
+use logic::simulation::{self, RequestPlan};
+use logic::transfer_limits::TransferLimits;
+use logic::type_aliases::TokenAmount;
+use logic::xfer_payload::XferPayload;
+use utils::constants::txn_types::XFER_PUBLIC;
+
+/**
+    Builds a [`Request<PaymentRequest>`].
+
+    The balance of the payment is only validated when the fee is set with
+    [`fee_from_schedule`] and the amount of every input is known, i.e. all
+    inputs were added with [`utxo`].
+
+    [`Request<PaymentRequest>`]: ../request/struct.Request.html
+    [`fee_from_schedule`]: #method.fee_from_schedule
+    [`utxo`]: #method.utxo
+*/
+#[derive(Debug, Default, Clone)]
+pub struct PaymentRequestBuilder {
+    inputs: Inputs,
+    input_amounts: Vec<Option<TokenAmount>>,
+    outputs: Outputs,
+    extra: Option<String>,
+    fee: Option<TokenAmount>,
+    preserve_order: bool,
+}
+
+impl PaymentRequestBuilder {
+    pub fn new() -> Self {
+        PaymentRequestBuilder::default()
+    }
+
+    /**
+        Add an input of unknown amount.
+    */
+    pub fn input(mut self, input: Input) -> Self {
+        self.inputs.push(input);
+        self.input_amounts.push(None);
+        self
+    }
+
+    /**
+        Add a utxo returned by the GET_UTXO parser as an input.
+    */
+    pub fn utxo(mut self, utxo: &UTXO) -> Result<Self, ErrorCode> {
+        let txo = TXO::from_libindy_string(&utxo.source)
+            .map_err(ErrorCode::from)?;
+
+        self.inputs.push(Input::new(txo.address, txo.seq_no));
+        self.input_amounts.push(Some(utxo.amount));
+        Ok(self)
+    }
+
+    pub fn output(mut self, output: Output) -> Self {
+        self.outputs.push(output);
+        self
+    }
+
+    pub fn extra(mut self, extra: &str) -> Self {
+        self.extra = Some(extra.to_string());
+        self
+    }
+
+    /**
+        Set the fee the inputs have to pay on top of the outputs.
+    */
+    pub fn fee(mut self, fee: TokenAmount) -> Self {
+        self.fee = Some(fee);
+        self
+    }
+
+    /**
+        Keep the inputs and outputs in the order they were added.
+
+        Without it, they are sorted in canonical order when the library
+        config asks for it, see [`canonical_order`].
+
+        [`canonical_order`]: ../canonical_order/index.html
+    */
+    pub fn preserve_order(mut self) -> Self {
+        self.preserve_order = true;
+        self
+    }
+
+    /**
+        Take the fee of a payment from a fee schedule.
+
+        A schedule without a fee for payments means the payment is free.
+    */
+    pub fn fee_from_schedule(self, schedule: &SetFeesMap) -> Self {
+        self.fee(schedule.get(XFER_PUBLIC).cloned().unwrap_or(0))
+    }
+
+    /**
+        Validate and build the unsigned [`XferPayload`].
+
+        Fails when the payment has more inputs or outputs than the
+        [`TransferLimits`] of the library config allow.
+
+        [`XferPayload`]: ../xfer_payload/struct.XferPayload.html
+        [`TransferLimits`]: ../transfer_limits/struct.TransferLimits.html
+    */
+    pub fn build(self) -> Result<XferPayload, ErrorCode> {
+        if self.inputs.is_empty() || self.outputs.is_empty() {
+            error!("A payment needs at least one input and one output.");
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+        TransferLimits::configured().check(self.inputs.len(), self.outputs.len())?;
+
+        let inputs_total = self.input_amounts.iter()
+            .fold(Some(0), |total: Option<TokenAmount>, amount| {
+                total.and_then(|total| amount.and_then(|amount| total.checked_add(amount)))
+            });
+
+        if let (Some(fee), Some(inputs_total)) = (self.fee, inputs_total) {
+            let outputs_total = outputs_total(&self.outputs).ok_or(ErrorCode::CommonInvalidStructure)?;
+            validate_balance(inputs_total, outputs_total, fee)
+                .map_err(map_err_err!())
+                .map_err(ErrorCode::from)?;
+        }
+
+        let mut inputs = self.inputs;
+        let mut outputs = self.outputs;
+        canonical_order::put_in_order(&mut inputs, &mut outputs, self.preserve_order);
+
+        Ok(XferPayload::new(inputs, outputs, self.extra))
+    }
+
+    /**
+        Build the payload as [`build`] does and return the plan of the
+        request, without signing it.
+
+        [`build`]: #method.build
+    */
+    pub fn simulate(self) -> Result<RequestPlan, ErrorCode> {
+        let input_amounts: Vec<(Input, TokenAmount)> = self.inputs.iter()
+            .zip(self.input_amounts.iter())
+            .filter_map(|(input, amount)| amount.map(|amount| (input.clone(), amount)))
+            .collect();
+
+        simulation::plan_request(&self.build()?, &input_amounts)
+    }
+
+    /**
+        Build the payload, sign it with the keys in the wallet and call `cb`
+        with the payment request.
+    */
+    pub fn sign<A: CryptoAPI>(
+        self,
+        crypto_api: &'static A,
+        wallet_handle: IndyHandle,
+        cb: Box<Fn(Result<Request<PaymentRequest>, ErrorCode>) + Send + Sync>
+    ) -> Result<(), ErrorCode> {
+        self.build()?.sign_transfer(
+            crypto_api,
+            wallet_handle,
+            Box::new(move |signed_payload| cb(signed_payload.and_then(build_payment_request)))
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod request_builder_tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use logic::config::payment_address_config::PaymentAddressConfig;
+    use utils::base58::{IntoBase58, FromBase58};
+    use super::*;
+
+    static INPUT_ADDRESS: &'static str = "pay:sov:E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm";
+    static OUTPUT_ADDRESS: &'static str = "pay:sov:TKe9eXtchV71J2qXX5HwP8rbkTBStnEEkMwQkHie265VtRSbs";
+
+    struct CryptoApiHandler {}
+
+    impl CryptoAPI for CryptoApiHandler {
+        fn indy_create_key(&self, _: IndyHandle, _: PaymentAddressConfig) -> Result<String, ErrorCode> {
+            return Err(ErrorCode::CommonInvalidState);
+        }
+
+        fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _wallet_handle: IndyHandle, verkey: String, _message: &[u8], mut cb: F) -> ErrorCode {
+            cb(Ok(verkey + "signed"));
+            return ErrorCode::Success;
+        }
+
+        fn indy_create_key_async<F: 'static>(&self, _wallet_id: i32, _config: PaymentAddressConfig, _closure: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+            return ErrorCode::CommonInvalidState;
+        }
+    }
+
+    fn utxo(amount: TokenAmount) -> UTXO {
+        UTXO {
+            payment_address: INPUT_ADDRESS.to_string(),
+            source: TXO { address: INPUT_ADDRESS.to_string(), seq_no: 1 }.to_libindy_string().unwrap(),
+            amount,
+            extra: "".to_string(),
+        }
+    }
+
+    fn schedule(fee: TokenAmount) -> SetFeesMap {
+        let mut schedule = HashMap::new();
+        schedule.insert(XFER_PUBLIC.to_string(), fee);
+        schedule
+    }
+
+    #[test]
+    fn build_without_outputs() {
+        let error = PaymentRequest::builder()
+            .input(Input::new(INPUT_ADDRESS.to_string(), 1))
+            .build()
+            .unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error);
+    }
+
+    #[test]
+    fn build_too_many_outputs() {
+        let limits = TransferLimits::configured();
+        let builder = (0..limits.max_outputs + 1).fold(
+            PaymentRequest::builder().input(Input::new(INPUT_ADDRESS.to_string(), 1)),
+            |builder, _| builder.output(Output::new(OUTPUT_ADDRESS.to_string(), 1))
+        );
+        assert_eq!(ErrorCode::CommonInvalidStructure, builder.build().unwrap_err());
+    }
+
+    #[test]
+    fn build_from_utxo() {
+        let payload = PaymentRequest::builder()
+            .utxo(&utxo(12)).unwrap()
+            .output(Output::new(OUTPUT_ADDRESS.to_string(), 10))
+            .extra("extra")
+            .fee_from_schedule(&schedule(2))
+            .build()
+            .unwrap();
+
+        assert_eq!(vec![Input::new(INPUT_ADDRESS.to_string(), 1)], payload.inputs);
+        assert_eq!(Some(String::from("extra")), payload.extra);
+    }
+
+    #[test]
+    fn build_fee_not_covered() {
+        let error = PaymentRequest::builder()
+            .utxo(&utxo(10)).unwrap()
+            .output(Output::new(OUTPUT_ADDRESS.to_string(), 10))
+            .fee_from_schedule(&schedule(2))
+            .build()
+            .unwrap_err();
+        assert_eq!(ErrorCode::PaymentInsufficientFundsError, error);
+    }
+
+    #[test]
+    fn build_skips_balance_with_unknown_amounts() {
+        let payload = PaymentRequest::builder()
+            .input(Input::new(INPUT_ADDRESS.to_string(), 1))
+            .output(Output::new(OUTPUT_ADDRESS.to_string(), 10))
+            .fee_from_schedule(&schedule(2))
+            .build();
+        assert!(payload.is_ok());
+    }
+
+    #[test]
+    fn sign_builds_request() {
+        let result = Arc::new(Mutex::new(None));
+        let result_clone = Arc::clone(&result);
+
+        PaymentRequest::builder()
+            .utxo(&utxo(10)).unwrap()
+            .output(Output::new(OUTPUT_ADDRESS.to_string(), 10))
+            .sign(&CryptoApiHandler {}, 1, Box::new(move |request| {
+                *result_clone.lock().unwrap() = Some(request);
+            }))
+            .unwrap();
+
+        let request = result.lock().unwrap().take().unwrap().unwrap();
+        let identifier = "E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm".from_base58_check().unwrap().into_base58();
+        assert_eq!(identifier, request.identifier);
+    }
+}
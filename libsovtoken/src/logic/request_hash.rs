@@ -0,0 +1,154 @@
+/*!
+    The canonical hash of a request, the id it has across the library.
+
+    The hash is the sha256, as hex, of the canonical json of the request:
+    the keys of every object sorted, no whitespace, and the top level
+    `signature`, `signatures` and `fees` left out. So a request keeps its
+    hash when it is reformatted, signed by its DID or given fees, two
+    requests which differ in their json differ in their hash, and any json
+    library sorting the keys computes the same hash.
+
+    It is the id of a request in the pending transactions, the audit log
+    and the correlations. The optimistic updates key a request by its hash
+    and the inputs paying it, as a write paid again with other UTXOs keeps
+    its hash. `hash_request_handler` exports it. Ids kept by an earlier
+    version of the hash, e.g. in an audit log, are told apart by
+    [`REQUEST_HASH_VERSION`].
+
+    [`REQUEST_HASH_VERSION`]: constant.REQUEST_HASH_VERSION.html
+*/
+
+use indy::ErrorCode;
+use serde_json::{self, Value};
+use sha2::{Digest, Sha256};
+use hex::ToHex;
+
+/**
+    The version of the canonical hash, 1 was the sha256 of the signature
+    serialization of the request.
+*/
+pub const REQUEST_HASH_VERSION: u32 = 2;
+
+// The top level values a request is signed or paid with, which aren't part of its id.
+const UNHASHED: [&'static str; 3] = ["signature", "signatures", "fees"];
+
+/**
+    The canonical hash of `request_json`.
+
+    Fails with `CommonInvalidStructure` when it isn't a json object.
+
+    ```
+    use sovtoken::logic::request_hash::request_hash;
+    let request = r#"{"reqId": 1, "operation": {"type": "10002"}}"#;
+    let reformatted = r#"{ "operation": {"type": "10002"}, "reqId": 1, "signature": "sig" }"#;
+    assert_eq!(request_hash(request).unwrap(), request_hash(reformatted).unwrap());
+    ```
+*/
+pub fn request_hash(request_json: &str) -> Result<String, ErrorCode> {
+    let mut request: Value = serde_json::from_str(request_json).map_err(map_err_err!()).or(Err(ErrorCode::CommonInvalidStructure))?;
+    {
+        let map = request.as_object_mut().ok_or(ErrorCode::CommonInvalidStructure)?;
+        for key in UNHASHED.iter() {
+            map.remove(*key);
+        }
+    }
+
+    let mut canonical = String::new();
+    write_canonical(&request, &mut canonical);
+    Ok(sha256_hex(&canonical))
+}
+
+/**
+    The hash of a message logged by id, e.g. in the audit log: the canonical
+    hash of a request, and the sha256 of the message as it is otherwise,
+    e.g. for an empty message.
+*/
+pub fn message_hash(message: &str) -> String {
+    request_hash(message).unwrap_or_else(|_| sha256_hex(message))
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn sha256_hex(message: &str) -> String {
+    let mut hasher = Sha256::default();
+    hasher.input(message.as_bytes());
+    hasher.result().to_hex()
+}
+
+// The keys are sorted here, whatever the order the map of serde_json keeps them in.
+fn write_canonical(value: &Value, out: &mut String) {
+    match *value {
+        Value::Object(ref map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(&map[key], out);
+            }
+            out.push('}');
+        },
+        Value::Array(ref values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(value, out);
+            }
+            out.push(']');
+        },
+        ref scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+
+#[cfg(test)]
+mod request_hash_tests {
+    use super::*;
+
+    #[test]
+    fn hash_of_the_signed_request() {
+        let request = json!({"reqId": 1, "identifier": "Th7MpTaRZVRYnPiabds81Y", "operation": {"type": "1", "dest": "a"}});
+        let mut signed = request.clone();
+        signed["signature"] = json!("didsig");
+        signed["fees"] = json!([[], [], ["sig"]]);
+        assert_eq!(request_hash(&request.to_string()).unwrap(), request_hash(&signed.to_string()).unwrap());
+
+        let mut other = request.clone();
+        other["reqId"] = json!(2);
+        assert_ne!(request_hash(&request.to_string()).unwrap(), request_hash(&other.to_string()).unwrap());
+
+        assert_eq!(ErrorCode::CommonInvalidStructure, request_hash("[1]").unwrap_err());
+    }
+
+    #[test]
+    fn hash_of_the_canonical_json() {
+        let request = r#"{ "reqId": 1, "operation": { "type": "1", "dest": "a" }, "signature": "sig" }"#;
+        assert_eq!(sha256_hex(r#"{"operation":{"dest":"a","type":"1"},"reqId":1}"#), request_hash(request).unwrap());
+    }
+
+    #[test]
+    fn requests_the_signature_serialization_confuses_differ() {
+        // joined without json syntax, both serialize to "operation:a:b|c"
+        let nested = json!({"operation": {"a": "b|c"}});
+        let joined = json!({"operation": "a:b|c"});
+        assert_ne!(request_hash(&nested.to_string()).unwrap(), request_hash(&joined.to_string()).unwrap());
+
+        let array = json!({"operation": ["a", "b"]});
+        let string = json!({"operation": "a,b"});
+        assert_ne!(request_hash(&array.to_string()).unwrap(), request_hash(&string.to_string()).unwrap());
+    }
+
+    #[test]
+    fn hash_of_a_message_which_is_no_request() {
+        assert_eq!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855", message_hash(""));
+    }
+}
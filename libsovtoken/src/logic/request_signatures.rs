@@ -0,0 +1,290 @@
+/*!
+    The two kinds of signatures of a request written with fees.
+
+    The DIDs which authorize the operation and the keys of the UTXOs which
+    pay its fees both sign the request, and the two are never mixed:
+
+    * the DIDs sign in `signature`, for the single submitter, or in
+      `signatures`, an object of the signature by DID of a multi signed
+      request,
+    * the fee inputs sign in the `fees` block, `[inputs, outputs,
+      signatures]`, one signature per input in the order of the inputs.
+      Each signs the fee inputs and outputs and the digest of the host
+      request, see [`signing_payload`].
+
+    The digest leaves out both kinds of signatures and the fees, so the
+    DIDs can sign before or after the fees are added, and a fee signature
+    can't be moved to another request.
+
+    [`signing_payload`]: ../signing_payload/index.html
+*/
+
+use std::collections::BTreeMap;
+
+use indy::ErrorCode;
+use serde_json::{self, Value};
+
+use logic::api_internals::add_request_fees::RawRequest;
+use logic::input::Inputs;
+use logic::signing_payload::{is_signed_by, signing_payload};
+use utils::constants::txn_fields::FEES;
+
+/**
+    The signatures of the fee inputs of a request.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeSignatures {
+    pub txn_digest: String,
+    pub inputs: Inputs,
+    pub signatures: Vec<String>,
+}
+
+/**
+    The signatures of a request, by kind.
+
+    ```text
+    {
+        "didSignatures": {<string: did>: <string>, ...},
+        "fees": {                       // null when the request has no fees
+            "txnDigest": <string>,      // hex digest of the request the fee inputs sign
+            "inputs": [...],
+            "signatures": [<string>, ...]   // none before the fees are signed
+        }
+    }
+    ```
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestSignatures {
+    pub did_signatures: BTreeMap<String, String>,
+    pub fees: Option<FeeSignatures>,
+}
+
+/**
+    Which fee inputs of a request are signed over its digest.
+
+    The DID signatures aren't verified, the verkeys of the DIDs are on the
+    ledger.
+
+    ```text
+    {
+        "valid": <bool>,            // every fee input is signed
+        "txnDigest": <string>,
+        "signed": [<bool>, ...]     // by fee input
+    }
+    ```
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeSignaturesCheck {
+    pub valid: bool,
+    pub txn_digest: String,
+    pub signed: Vec<bool>,
+}
+
+/**
+    Split the signatures of `request_json` by kind.
+
+    Fails with `CommonInvalidStructure` when the two kinds are mixed: a
+    `signatures` which isn't an object by DID, a fee block without one
+    signature per input, or a signature given as both kinds.
+*/
+pub fn split_signatures(request_json: &str) -> Result<RequestSignatures, ErrorCode> {
+    let request: Value = serde_json::from_str(request_json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    let mut did_signatures = BTreeMap::new();
+    match request.get("signature") {
+        None | Some(&Value::Null) => (),
+        Some(&Value::String(ref signature)) => {
+            let identifier = request["identifier"].as_str().ok_or_else(|| {
+                error!("The request has a signature but no identifier");
+                ErrorCode::CommonInvalidStructure
+            })?;
+            did_signatures.insert(identifier.to_string(), signature.clone());
+        },
+        Some(_) => {
+            error!("The signature of the request isn't a string");
+            return Err(ErrorCode::CommonInvalidStructure);
+        },
+    }
+
+    match request.get("signatures") {
+        None | Some(&Value::Null) => (),
+        Some(&Value::Object(ref signatures)) => {
+            for (did, signature) in signatures {
+                let signature = signature.as_str().ok_or(ErrorCode::CommonInvalidStructure)?;
+                did_signatures.insert(did.clone(), signature.to_string());
+            }
+        },
+        Some(_) => {
+            error!("The signatures of the request aren't an object by DID, the signatures of the fee inputs belong in the fees");
+            return Err(ErrorCode::CommonInvalidStructure);
+        },
+    }
+
+    let fees = match request.get(FEES) {
+        None | Some(&Value::Null) => None,
+        Some(fees) => Some(fee_signatures(request_json, fees)?),
+    };
+
+    if let Some(ref fees) = fees {
+        if fees.signatures.iter().any(|signature| did_signatures.values().any(|did_signature| did_signature == signature)) {
+            error!("A signature of the request is both a DID signature and a fee input signature");
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+    }
+
+    Ok(RequestSignatures { did_signatures, fees })
+}
+
+/**
+    Check the fee inputs of `request_json` are signed by their keys over the
+    digest of the request, the host request included.
+
+    Fails with `CommonInvalidStructure` when the request has no fees or its
+    signatures are mixed, see [`split_signatures`].
+
+    [`split_signatures`]: fn.split_signatures.html
+*/
+pub fn verify_fee_signatures(request_json: &str) -> Result<FeeSignaturesCheck, ErrorCode> {
+    let fees = split_signatures(request_json)?.fees.ok_or_else(|| {
+        error!("The request has no fees");
+        ErrorCode::CommonInvalidStructure
+    })?;
+
+    let mut signed = Vec::with_capacity(fees.inputs.len());
+    for input_index in 0..fees.inputs.len() {
+        let payload = signing_payload(request_json, input_index)?;
+        let is_signed = fees.signatures.get(input_index)
+            .map_or(false, |signature| is_signed_by(&payload.verkey, payload.message.as_bytes(), signature));
+        signed.push(is_signed);
+    }
+
+    Ok(FeeSignaturesCheck {
+        valid: !signed.is_empty() && signed.iter().all(|&is_signed| is_signed),
+        txn_digest: fees.txn_digest,
+        signed,
+    })
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn fee_signatures(request_json: &str, fees: &Value) -> Result<FeeSignatures, ErrorCode> {
+    let fees = match fees.as_array() {
+        Some(fees) if fees.len() == 2 || fees.len() == 3 => fees,
+        _ => {
+            error!("The fees of the request aren't [inputs, outputs, signatures]");
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+    };
+
+    // parsed from the text, the deserializer of inputs borrows its keys
+    let inputs: Inputs = serde_json::from_str(&fees[0].to_string())
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+    let signatures: Vec<String> = match fees.get(2) {
+        Some(signatures) => serde_json::from_value(signatures.clone())
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonInvalidStructure))?,
+        None => Vec::new(),
+    };
+
+    if !signatures.is_empty() && signatures.len() != inputs.len() {
+        error!("The fees have {} inputs and {} signatures", inputs.len(), signatures.len());
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let txn_digest = RawRequest::new(request_json.to_string())?.digest()?;
+    Ok(FeeSignatures { txn_digest, inputs, signatures })
+}
+
+
+#[cfg(test)]
+mod request_signatures_tests {
+    use sodiumoxide::crypto::sign::sign_detached;
+
+    use logic::address;
+    use utils::base58::IntoBase58;
+    use utils::test::keys::{self, keypair};
+    use super::*;
+
+    static RECIPIENT: &'static str = "a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7";
+
+    fn payer() -> String {
+        address::unqualified_part(&keys::payer()).to_string()
+    }
+
+    // a NYM with fees signed by the key of its input
+    fn nym_with_fees() -> Value {
+        let mut request = json!({
+            "operation": {"type": "1", "dest": "Th7MpTaRZVRYnPiabds81Y"},
+            "identifier": "Th7MpTaRZVRYnPiabds81Y",
+            "reqId": 1,
+            "signature": "didsig",
+            "fees": [[{"address": payer(), "seqNo": 2}], [{"address": RECIPIENT, "amount": 9}]]
+        });
+        let payload = signing_payload(&request.to_string(), 0).unwrap();
+        let signature = sign_detached(payload.message.as_bytes(), &keypair().1);
+        request["fees"].as_array_mut().unwrap().push(json!([signature.0.into_base58()]));
+        request
+    }
+
+    #[test]
+    fn split_did_and_fee_signatures() {
+        let signatures = split_signatures(&nym_with_fees().to_string()).unwrap();
+        assert_eq!(Some(&String::from("didsig")), signatures.did_signatures.get("Th7MpTaRZVRYnPiabds81Y"));
+
+        let fees = signatures.fees.unwrap();
+        assert_eq!(1, fees.inputs.len());
+        assert_eq!(1, fees.signatures.len());
+    }
+
+    #[test]
+    fn fee_signatures_over_the_host_request() {
+        let request = nym_with_fees();
+        let check = verify_fee_signatures(&request.to_string()).unwrap();
+        assert!(check.valid);
+
+        // the DIDs may sign after the fees
+        let mut multi_signed = request.clone();
+        multi_signed["signatures"] = json!({"Th7MpTaRZVRYnPiabds81Y": "didsig2"});
+        assert!(verify_fee_signatures(&multi_signed.to_string()).unwrap().valid);
+
+        // the fee signature doesn't sign another host request
+        let mut moved = request.clone();
+        moved["operation"]["dest"] = json!("LibsovtokenDid11111111");
+        let check = verify_fee_signatures(&moved.to_string()).unwrap();
+        assert!(!check.valid);
+        assert_eq!(vec![false], check.signed);
+    }
+
+    #[test]
+    fn mixed_signatures() {
+        let mut request = nym_with_fees();
+        request["signatures"] = request["fees"][2].clone();
+        assert_eq!(ErrorCode::CommonInvalidStructure, split_signatures(&request.to_string()).unwrap_err());
+
+        let mut request = nym_with_fees();
+        request["signature"] = request["fees"][2][0].clone();
+        assert_eq!(ErrorCode::CommonInvalidStructure, split_signatures(&request.to_string()).unwrap_err());
+
+        let mut request = nym_with_fees();
+        request["fees"][2] = json!(["sig1", "sig2"]);
+        assert_eq!(ErrorCode::CommonInvalidStructure, split_signatures(&request.to_string()).unwrap_err());
+    }
+
+    #[test]
+    fn request_without_fees() {
+        let mut request = nym_with_fees();
+        request.as_object_mut().unwrap().remove(FEES);
+
+        assert_eq!(None, split_signatures(&request.to_string()).unwrap().fees);
+        assert_eq!(ErrorCode::CommonInvalidStructure, verify_fee_signatures(&request.to_string()).unwrap_err());
+    }
+}
@@ -0,0 +1,281 @@
+/*!
+    Size estimation and splitting of XFER requests.
+
+    The ledger nodes reject requests larger than their message size limit,
+    the limit used here is `max_request_bytes` of the [`LibraryConfig`]. A
+    transfer spending too many UTXOs to fit is split into several requests,
+    each spending a group of the UTXOs and paying the outputs in order. An
+    output can be paid partly by one request and partly by the next one.
+
+    The requests don't spend each others outputs, but submitting them in the
+    order of their `index` keeps the payments to the recipients in order.
+
+    [`LibraryConfig`]: ../config/library_config/struct.LibraryConfig.html
+*/
+
+use indy::ErrorCode;
+
+use logic::batch_payment::BatchChunk;
+use logic::config::payment_config::PaymentRequest;
+use logic::output::{Output, Outputs};
+use logic::parsers::parse_get_utxo_response::UTXO;
+use logic::request_builder::PaymentRequestBuilder;
+use logic::transfer_limits::TransferLimits;
+use logic::type_aliases::TokenAmount;
+use logic::xfer_payload::XferPayload;
+
+// a base58 ed25519 signature is at most 88 characters
+const SIGNATURE_PLACEHOLDER: &str = "1111111111111111111111111111111111111111111111111111111111111111111111111111111111111111";
+// a base58 verkey is at most 44 characters
+const IDENTIFIER_PLACEHOLDER: &str = "11111111111111111111111111111111111111111111";
+
+/**
+    Estimate the size in bytes of the signed request `payload` becomes.
+
+    The signatures and the identifier are not known before signing, the
+    estimate uses the largest size they can have.
+*/
+pub fn estimate_xfer_size(payload: &XferPayload) -> Result<usize, ErrorCode> {
+    let mut payload = payload.clone();
+    payload.signatures = Some(payload.inputs.iter().map(|_| String::from(SIGNATURE_PLACEHOLDER)).collect());
+
+    PaymentRequest::new(payload)
+        .as_request(String::from(IDENTIFIER_PLACEHOLDER))
+        .estimated_size()
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))
+}
+
+/**
+    Pay `outputs` with `utxos` in as few requests as fit in `max_bytes` each.
+
+    A request spends at most `limits.max_inputs` UTXOs. Only as many UTXOs as
+    needed are spent, whatever the last group of every request holds on top
+    of the outputs goes back to the address of its last UTXO.
+
+    Fails with `CommonInvalidStructure` when a single UTXO with all outputs
+    doesn't fit, or the outputs and a change output are more than
+    `limits.max_outputs`, and with `PaymentInsufficientFundsError` when the
+    UTXOs run out.
+*/
+pub fn split_transfer(utxos: &Vec<UTXO>, outputs: &Outputs, extra: Option<&str>, max_bytes: usize, limits: TransferLimits) -> Result<Vec<BatchChunk>, ErrorCode> {
+    trace!("logic::request_size::split_transfer >> utxos: {:?}, outputs: {:?}, max_bytes: {:?}, limits: {:?}", utxos.len(), outputs.len(), max_bytes, limits);
+    if utxos.is_empty() || outputs.is_empty() {
+        error!("A transfer needs UTXOs and outputs.");
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+    limits.check(0, outputs.len() + 1)?;
+
+    let max_inputs = max_inputs_per_request(utxos, outputs, extra, max_bytes, limits.max_inputs)?;
+    if max_inputs == 0 {
+        error!("The outputs don't fit in a request of {} bytes.", max_bytes);
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let mut unpaid = outputs.clone();
+    let mut next_output = 0;
+    let mut chunks = Vec::new();
+
+    for group in utxos.chunks(max_inputs) {
+        if next_output == unpaid.len() {
+            break;
+        }
+
+        let mut available = checked_total(group.iter().map(|utxo| utxo.amount))?;
+        let mut builder = group.iter().fold(Ok(PaymentRequestBuilder::new()), |builder, utxo| {
+            builder.and_then(|builder: PaymentRequestBuilder| builder.utxo(utxo))
+        })?;
+
+        let mut amount: TokenAmount = 0;
+        let mut output_count = 0;
+        while available > 0 && next_output < unpaid.len() {
+            let output = &mut unpaid[next_output];
+            let paid = ::std::cmp::min(available, output.amount);
+
+            builder = builder.output(Output::new(output.recipient.clone(), paid));
+            output.amount -= paid;
+            available -= paid;
+            amount += paid;
+            output_count += 1;
+
+            if output.amount == 0 {
+                next_output += 1;
+            }
+        }
+
+        let mut change = None;
+        if available > 0 {
+            let change_output = Output::new(group[group.len() - 1].payment_address.clone(), available);
+            builder = builder.output(change_output.clone());
+            change = Some(change_output);
+            output_count += 1;
+        }
+
+        if let Some(extra) = extra {
+            builder = builder.extra(extra);
+        }
+
+        chunks.push(BatchChunk { builder: builder.fee(0), output_count, amount, change });
+    }
+
+    if next_output < unpaid.len() {
+        error!("The UTXOs don't cover the outputs.");
+        return Err(ErrorCode::PaymentInsufficientFundsError);
+    }
+
+    trace!("logic::request_size::split_transfer << chunks: {:?}", chunks.len());
+    Ok(chunks)
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// The most UTXOs a request can spend, up to `max_inputs`, assuming it pays
+// every output and a change output. The size grows with every UTXO, so the
+// count is searched by halves instead of building a request for each count.
+fn max_inputs_per_request(utxos: &Vec<UTXO>, outputs: &Outputs, extra: Option<&str>, max_bytes: usize, max_inputs: usize) -> Result<usize, ErrorCode> {
+    let mut outputs = outputs.clone();
+    outputs.push(Output::new(utxos[0].payment_address.clone(), TokenAmount::max_value()));
+
+    let fits = |count: usize| -> Result<bool, ErrorCode> {
+        let mut builder = utxos[..count].iter().fold(Ok(PaymentRequestBuilder::new()), |builder, utxo| {
+            builder.and_then(|builder: PaymentRequestBuilder| builder.utxo(utxo))
+        })?;
+        for output in &outputs {
+            builder = builder.output(output.clone());
+        }
+        if let Some(extra) = extra {
+            builder = builder.extra(extra);
+        }
+        Ok(estimate_xfer_size(&builder.build()?)? <= max_bytes)
+    };
+
+    // `low` UTXOs fit, more than `high` don't
+    let mut low = 0;
+    let mut high = ::std::cmp::min(utxos.len(), max_inputs);
+    while low < high {
+        let count = low + (high - low + 1) / 2;
+        if fits(count)? {
+            low = count;
+        } else {
+            high = count - 1;
+        }
+    }
+
+    Ok(low)
+}
+
+fn checked_total<I: Iterator<Item=TokenAmount>>(amounts: I) -> Result<TokenAmount, ErrorCode> {
+    amounts
+        .fold(Some(0), |total: Option<TokenAmount>, amount| total.and_then(|total| total.checked_add(amount)))
+        .ok_or(ErrorCode::CommonInvalidStructure)
+}
+
+
+#[cfg(test)]
+mod request_size_tests {
+    use logic::parsers::common::TXO;
+    use super::*;
+
+    static INPUT_ADDRESS: &'static str = "pay:sov:E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm";
+
+    fn output(recipient: &str, amount: TokenAmount) -> Output {
+        Output::new(recipient.to_string(), amount)
+    }
+
+    fn utxos(amounts: Vec<TokenAmount>) -> Vec<UTXO> {
+        amounts.into_iter().enumerate().map(|(seq_no, amount)| UTXO {
+            payment_address: INPUT_ADDRESS.to_string(),
+            source: TXO { address: INPUT_ADDRESS.to_string(), seq_no: seq_no as u64 + 1 }.to_libindy_string().unwrap(),
+            amount,
+            extra: "".to_string(),
+        }).collect()
+    }
+
+    fn size_with_inputs(count: usize, outputs: &Outputs) -> usize {
+        let mut outputs = outputs.clone();
+        outputs.push(output(INPUT_ADDRESS, TokenAmount::max_value()));
+        let builder = utxos(vec![1; count]).iter()
+            .fold(PaymentRequestBuilder::new(), |builder, utxo| builder.utxo(utxo).unwrap());
+        let payload = outputs.into_iter().fold(builder, |builder, output| builder.output(output)).build().unwrap();
+        estimate_xfer_size(&payload).unwrap()
+    }
+
+    #[test]
+    fn estimate_grows_with_inputs() {
+        let outputs = vec![output("pay:sov:a", 1)];
+        assert!(size_with_inputs(2, &outputs) > size_with_inputs(1, &outputs));
+    }
+
+    #[test]
+    fn split_fits_in_one_request() {
+        let outputs = vec![output("pay:sov:a", 15)];
+        let chunks = split_transfer(&utxos(vec![10, 10]), &outputs, None, size_with_inputs(2, &outputs), TransferLimits::default()).unwrap();
+
+        assert_eq!(1, chunks.len());
+        let payload = chunks[0].builder.clone().build().unwrap();
+        assert_eq!(vec![output("pay:sov:a", 15), output(INPUT_ADDRESS, 5)], payload.outputs);
+    }
+
+    #[test]
+    fn split_output_over_requests() {
+        let outputs = vec![output("pay:sov:a", 15), output("pay:sov:b", 10)];
+        let chunks = split_transfer(&utxos(vec![10, 10, 10]), &outputs, None, size_with_inputs(1, &outputs), TransferLimits::default()).unwrap();
+
+        assert_eq!(3, chunks.len());
+        assert_eq!(vec![output("pay:sov:a", 10)], chunks[0].builder.clone().build().unwrap().outputs);
+        assert_eq!(vec![output("pay:sov:a", 5), output("pay:sov:b", 5)], chunks[1].builder.clone().build().unwrap().outputs);
+        assert_eq!(vec![output("pay:sov:b", 5), output(INPUT_ADDRESS, 5)], chunks[2].builder.clone().build().unwrap().outputs);
+        assert_eq!(25, chunks.iter().map(|chunk| chunk.amount).sum::<TokenAmount>());
+    }
+
+    #[test]
+    fn split_spends_only_needed_utxos() {
+        let outputs = vec![output("pay:sov:a", 10)];
+        let chunks = split_transfer(&utxos(vec![10, 10, 10]), &outputs, None, size_with_inputs(1, &outputs), TransferLimits::default()).unwrap();
+        assert_eq!(1, chunks.len());
+    }
+
+    #[test]
+    fn split_insufficient_funds() {
+        let outputs = vec![output("pay:sov:a", 25)];
+        let error = split_transfer(&utxos(vec![10, 10]), &outputs, None, size_with_inputs(1, &outputs), TransferLimits::default()).unwrap_err();
+        assert_eq!(ErrorCode::PaymentInsufficientFundsError, error);
+    }
+
+    #[test]
+    fn split_limit_too_small() {
+        let outputs = vec![output("pay:sov:a", 10)];
+        let error = split_transfer(&utxos(vec![10]), &outputs, None, 10, TransferLimits::default()).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error);
+    }
+
+    #[test]
+    fn split_respects_max_inputs() {
+        let outputs = vec![output("pay:sov:a", 30)];
+        let limits = TransferLimits { max_inputs: 2, max_outputs: 10 };
+        let chunks = split_transfer(&utxos(vec![10, 10, 10]), &outputs, None, size_with_inputs(3, &outputs), limits).unwrap();
+        assert_eq!(2, chunks.len());
+    }
+
+    #[test]
+    fn split_too_many_outputs() {
+        let outputs = vec![output("pay:sov:a", 5), output("pay:sov:b", 5)];
+        let limits = TransferLimits { max_inputs: 10, max_outputs: 2 };
+        let error = split_transfer(&utxos(vec![10]), &outputs, None, size_with_inputs(1, &outputs), limits).unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error);
+    }
+
+    #[test]
+    fn max_inputs_is_the_most_that_fit() {
+        let outputs = vec![output("pay:sov:a", 10)];
+        let utxos = utxos(vec![1; 9]);
+
+        assert_eq!(4, max_inputs_per_request(&utxos, &outputs, None, size_with_inputs(4, &outputs), 10).unwrap());
+        assert_eq!(9, max_inputs_per_request(&utxos, &outputs, None, size_with_inputs(9, &outputs), 10).unwrap());
+        assert_eq!(3, max_inputs_per_request(&utxos, &outputs, None, size_with_inputs(9, &outputs), 3).unwrap());
+    }
+}
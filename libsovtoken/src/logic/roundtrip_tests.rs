@@ -0,0 +1,172 @@
+/*!
+    Property tests for the wire format.
+
+    Every serializable type is generated at random and has to survive a
+    serialize → deserialize round trip unchanged. The signing serialization
+    of a payload has to be the same before and after the round trip, or
+    signatures made over the original wouldn't verify over the copy.
+
+    Amounts are also drawn around the boundaries where json numbers lose
+    precision: 2^53 for the clients parsing them as doubles, the token
+    supply and `u64::MAX`.
+*/
+
+use proptest::prelude::*;
+use serde_json;
+
+use logic::balance::validate_supply;
+use logic::config::payment_config::PaymentRequest;
+use logic::config::set_fees_config::SetFeesMap;
+use logic::input::{Input, Inputs};
+use logic::output::{Output, Outputs};
+use logic::parsers::common::TXO;
+use logic::request::Request;
+use logic::xfer_payload::{XferPayload, serialize_signature};
+use utils::constants::general::MAX_SUPPLY;
+use utils::json_conversion::{JsonDeserialize, JsonSerialize};
+
+const ADDRESS: &str = "pay:sov:[1-9A-HJ-NP-Za-km-z]{45,50}";
+
+fn input() -> BoxedStrategy<Input> {
+    (ADDRESS, any::<u64>())
+        .prop_map(|(address, seq_no)| Input::new(address, seq_no))
+        .boxed()
+}
+
+fn output() -> BoxedStrategy<Output> {
+    (ADDRESS, any::<u64>())
+        .prop_map(|(address, amount)| Output::new(address, amount))
+        .boxed()
+}
+
+// the largest integer a double holds exactly
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+fn boundary_amount() -> BoxedStrategy<u64> {
+    prop_oneof![
+        (0u64..3),
+        (MAX_SAFE_INTEGER - 2..MAX_SAFE_INTEGER + 3),
+        (MAX_SUPPLY - 2..MAX_SUPPLY + 3),
+        (u64::max_value() - 2..u64::max_value()).prop_map(|amount| amount + 1),
+        any::<u64>(),
+    ].boxed()
+}
+
+fn xfer_payload() -> BoxedStrategy<XferPayload> {
+    (
+        prop::collection::vec(input(), 1..5),
+        prop::collection::vec(output(), 1..5),
+        prop::option::of(".{0,20}"),
+        prop::option::of(prop::collection::vec("[1-9A-HJ-NP-Za-km-z]{80,88}", 1..5)),
+    )
+        .prop_map(|(inputs, outputs, extra, signatures)| {
+            let mut payload = XferPayload::new(inputs, outputs, extra);
+            payload.signatures = signatures;
+            payload
+        })
+        .boxed()
+}
+
+fn fees() -> BoxedStrategy<SetFeesMap> {
+    prop::collection::hash_map("[0-9]{1,5}", any::<u64>(), 0..10).boxed()
+}
+
+proptest! {
+    #[test]
+    fn input_round_trip(input in input()) {
+        let json = input.to_json().unwrap();
+        prop_assert_eq!(input, Input::from_json(&json).unwrap());
+    }
+
+    #[test]
+    fn inputs_round_trip(inputs in prop::collection::vec(input(), 0..10)) {
+        let json = serde_json::to_string(&inputs).unwrap();
+        prop_assert_eq!(inputs, serde_json::from_str::<Inputs>(&json).unwrap());
+    }
+
+    #[test]
+    fn outputs_round_trip(outputs in prop::collection::vec(output(), 0..10)) {
+        let json = serde_json::to_string(&outputs).unwrap();
+        prop_assert_eq!(outputs, serde_json::from_str::<Outputs>(&json).unwrap());
+    }
+
+    #[test]
+    fn fees_round_trip(fees in fees()) {
+        let json = serde_json::to_string(&fees).unwrap();
+        prop_assert_eq!(fees, serde_json::from_str::<SetFeesMap>(&json).unwrap());
+    }
+
+    #[test]
+    fn txo_round_trip(address in ADDRESS, seq_no in any::<u64>()) {
+        let txo = TXO { address, seq_no };
+        let txo_str = txo.to_libindy_string().unwrap();
+        prop_assert_eq!(txo, TXO::from_libindy_string(&txo_str).unwrap());
+    }
+
+    #[test]
+    fn xfer_payload_round_trip(payload in xfer_payload()) {
+        let json = payload.to_json().unwrap();
+        prop_assert_eq!(payload, XferPayload::from_json(&json).unwrap());
+    }
+
+    #[test]
+    fn payment_request_round_trip(payload in xfer_payload(), identifier in "[1-9A-HJ-NP-Za-km-z]{40,44}") {
+        let request = PaymentRequest::new(payload).as_request(identifier);
+        let json = request.serialize_to_string().unwrap();
+
+        let copy: Request<PaymentRequest> = serde_json::from_str(&json).unwrap();
+        prop_assert_eq!(json, copy.serialize_to_string().unwrap());
+    }
+
+    #[test]
+    fn signing_serialization_stable(payload in xfer_payload()) {
+        let value = serde_json::to_value(&payload).unwrap();
+        let copy: XferPayload = serde_json::from_str(&value.to_string()).unwrap();
+
+        prop_assert_eq!(
+            serialize_signature(&value).unwrap(),
+            serialize_signature(&serde_json::to_value(&copy).unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn boundary_amount_round_trip(address in ADDRESS, amount in boundary_amount()) {
+        let output = Output::new(address, amount);
+        let json = output.to_json().unwrap();
+        prop_assert!(json.contains(&format!(":{}", amount)));
+        prop_assert_eq!(output.clone(), Output::from_json(&json).unwrap());
+
+        let value = serde_json::to_value(&output).unwrap();
+        prop_assert_eq!(Some(amount), value["amount"].as_u64());
+        prop_assert_eq!(amount.to_string(), serialize_signature(&value["amount"]).unwrap());
+    }
+
+    #[test]
+    fn supply_is_never_exceeded(amounts in prop::collection::vec(boundary_amount(), 1..4)) {
+        let outputs: Outputs = amounts.iter()
+            .map(|&amount| Output::new(String::from("pay:sov:a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7"), amount))
+            .collect();
+        let total = amounts.iter().fold(0u128, |total, &amount| total + amount as u128);
+
+        match validate_supply(&outputs) {
+            Ok(checked) => prop_assert!(checked as u128 == total && total <= MAX_SUPPLY as u128),
+            Err(_) => prop_assert!(total > MAX_SUPPLY as u128),
+        }
+    }
+}
+
+/*
+    A json number which isn't an exact u64 has to be rejected, not rounded
+    into a different amount.
+*/
+#[test]
+fn inexact_amounts_are_rejected() {
+    let address = "pay:sov:a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7";
+    for amount in &["18446744073709551616", "1.8446744073709552e19", "10.5", "-1", "1e3"] {
+        let json = format!(r#"{{"address":"{}","amount":{}}}"#, address, amount);
+        assert!(Output::from_json(&json).is_err(), "accepted the amount {}", amount);
+    }
+
+    let json = format!(r#"{{"address":"{}","amount":18446744073709551615}}"#, address);
+    assert_eq!(u64::max_value(), Output::from_json(&json).unwrap().amount);
+}
@@ -0,0 +1,340 @@
+/*!
+    Embedded JSON schemas for the json passed into the api, and of the
+    receipts it emits.
+
+    The json passed in is only validated when `strict_validation` is set in
+    the [`LibraryConfig`]. Without it, the handlers don't validate against the
+    schemas: the json fails when it can't be deserialized into the structs of
+    the call, e.g. a missing field or a field of the wrong type, and nothing
+    else is checked before the call's own checks. The
+    validator understands the subset of JSON schema the embedded schemas need:
+    `type`, `required`, `properties`, `additionalProperties`, `items`,
+    `minimum` and `anyOf`.
+
+    [`LibraryConfig`]: ../config/library_config/struct.LibraryConfig.html
+*/
+
+use indy::ErrorCode;
+use serde_json;
+use serde_json::Value;
+
+use logic::config::library_config::library_config;
+
+const INPUTS_SCHEMA: &str = r#"{
+    "type": "array",
+    "items": {"type": "string"}
+}"#;
+
+const OUTPUTS_SCHEMA: &str = r#"{
+    "type": "array",
+    "items": {
+        "type": "object",
+        "required": ["amount"],
+        "anyOf": [
+            {"required": ["recipient"]},
+            {"required": ["address"]}
+        ],
+        "properties": {
+            "recipient": {"type": "string"},
+            "address": {"type": "string"},
+            "amount": {"anyOf": [
+                {"type": "integer", "minimum": 0},
+                {"type": "string"}
+            ]},
+            "extra": {}
+        },
+        "additionalProperties": false
+    }
+}"#;
+
+const FEES_SCHEMA: &str = r#"{
+    "type": "object",
+    "additionalProperties": {"anyOf": [
+        {"type": "integer", "minimum": 0},
+        {"type": "string"}
+    ]}
+}"#;
+
+const RESPONSE_SCHEMA: &str = r#"{
+    "type": "object",
+    "required": ["op"],
+    "properties": {
+        "op": {"type": "string"},
+        "reason": {"type": "string"},
+        "result": {"type": "object"}
+    }
+}"#;
+
+// Version 1 of the receipts, see logic::receipt.
+const RECEIPTS_SCHEMA: &str = r#"{
+    "type": "array",
+    "items": {
+        "type": "object",
+        "required": ["ver", "receipt", "recipient", "amount", "seqNo", "txnTime", "extra"],
+        "properties": {
+            "ver": {"type": "integer", "minimum": 1},
+            "receipt": {"type": "string"},
+            "recipient": {"type": "string"},
+            "amount": {"anyOf": [
+                {"type": "integer", "minimum": 0},
+                {"type": "string"}
+            ]},
+            "seqNo": {"type": "integer", "minimum": 1},
+            "txnTime": {"anyOf": [
+                {"type": "integer", "minimum": 0},
+                {"type": "null"}
+            ]},
+            "extra": {"type": "string"},
+            "proof": {
+                "type": "object",
+                "required": ["rootHash", "auditPath", "ledgerSize", "txn"],
+                "properties": {
+                    "rootHash": {"type": "string"},
+                    "auditPath": {"type": "array", "items": {"type": "string"}},
+                    "ledgerSize": {"type": "integer", "minimum": 1},
+                    "txn": {"type": "object"}
+                }
+            },
+            "label": {"type": "string"}
+        }
+    }
+}"#;
+
+/**
+    The embedded schemas.
+*/
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Schema {
+    Inputs,
+    Outputs,
+    Fees,
+    Response,
+    /// The receipts emitted by the parsers, version 1.
+    Receipts,
+}
+
+impl Schema {
+    fn json(&self) -> Value {
+        let schema = match self {
+            &Schema::Inputs => INPUTS_SCHEMA,
+            &Schema::Outputs => OUTPUTS_SCHEMA,
+            &Schema::Fees => FEES_SCHEMA,
+            &Schema::Response => RESPONSE_SCHEMA,
+            &Schema::Receipts => RECEIPTS_SCHEMA,
+        };
+        serde_json::from_str(schema).unwrap()
+    }
+}
+
+/**
+    A single schema violation.
+
+    `path` points to the offending value, e.g. `$[0].amount`.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct SchemaViolation {
+    pub path: String,
+    pub message: String,
+}
+
+/**
+    Validate `json` against `schema`.
+
+    ```
+        use sovtoken::logic::schema::{validate_json, Schema};
+
+        let violations = validate_json(Schema::Outputs, r#"[{"recipient": "pay:sov:1", "amount": -10}]"#).unwrap_err();
+        assert_eq!("$[0].amount", violations[0].path);
+    ```
+*/
+pub fn validate_json(schema: Schema, json: &str) -> Result<(), Vec<SchemaViolation>> {
+    let instance: Value = serde_json::from_str(json).map_err(|e| vec![SchemaViolation {
+        path: String::from("$"),
+        message: format!("Invalid json: {}", e),
+    }])?;
+
+    let mut violations = Vec::new();
+    check(&schema.json(), &instance, "$", &mut violations);
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+/**
+    Validate `json` against `schema` when strict validation is enabled.
+
+    Logs each violation and returns `ErrorCode::CommonInvalidStructure`.
+    When strict validation isn't enabled, it passes whatever the json, so a
+    handler can't rely on it to validate its json.
+*/
+pub fn validate_if_strict(schema: Schema, json: &str) -> Result<(), ErrorCode> {
+    if !library_config().strict_validation {
+        return Ok(());
+    }
+
+    validate_json(schema, json).map_err(|violations| {
+        for violation in violations {
+            error!("{:?} schema violation at {}: {}", schema, violation.path, violation.message);
+        }
+        ErrorCode::CommonInvalidStructure
+    })
+}
+
+/*
+    Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn check(schema: &Value, instance: &Value, path: &str, violations: &mut Vec<SchemaViolation>) {
+    let mut violation = |message: String| violations.push(SchemaViolation { path: path.to_string(), message });
+
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        if !is_type(instance, expected) {
+            violation(format!("Expected type {}, found {}", expected, instance));
+            return;
+        }
+    }
+
+    if let (Some(minimum), Some(value)) = (schema.get("minimum").and_then(Value::as_i64), instance.as_i64()) {
+        if value < minimum {
+            violation(format!("{} is less than the minimum of {}", value, minimum));
+        }
+    }
+
+    if let (Some(required), Some(object)) = (schema.get("required").and_then(Value::as_array), instance.as_object()) {
+        for field in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(field) {
+                violation(format!("Missing required field `{}`", field));
+            }
+        }
+    }
+
+    if let Some(any_of) = schema.get("anyOf").and_then(Value::as_array) {
+        let matches_any = any_of.iter().any(|sub_schema| {
+            let mut sub_violations = Vec::new();
+            check(sub_schema, instance, path, &mut sub_violations);
+            sub_violations.is_empty()
+        });
+        if !matches_any {
+            violation(String::from("Doesn't match any of the allowed schemas"));
+        }
+    }
+
+    if let (Some(items), Some(array)) = (schema.get("items"), instance.as_array()) {
+        for (index, item) in array.iter().enumerate() {
+            check(items, item, &format!("{}[{}]", path, index), violations);
+        }
+    }
+
+    if let Some(object) = instance.as_object() {
+        let properties = schema.get("properties").and_then(Value::as_object);
+
+        for (key, value) in object {
+            let field_path = format!("{}.{}", path, key);
+            match (properties.and_then(|p| p.get(key)), schema.get("additionalProperties")) {
+                (Some(property_schema), _) => check(property_schema, value, &field_path, violations),
+                (None, Some(&Value::Bool(false))) => violations.push(SchemaViolation {
+                    path: field_path,
+                    message: String::from("Unknown field"),
+                }),
+                (None, Some(additional @ &Value::Object(_))) => check(additional, value, &field_path, violations),
+                (None, _) => (),
+            }
+        }
+    }
+}
+
+fn is_type(instance: &Value, expected: &str) -> bool {
+    match expected {
+        "string" => instance.is_string(),
+        "integer" => instance.is_u64() || instance.is_i64(),
+        "number" => instance.is_number(),
+        "boolean" => instance.is_boolean(),
+        "array" => instance.is_array(),
+        "object" => instance.is_object(),
+        "null" => instance.is_null(),
+        _ => false,
+    }
+}
+
+
+#[cfg(test)]
+mod schema_tests {
+    use super::*;
+
+    fn assert_violation(schema: Schema, json: serde_json::Value, path: &str) {
+        let violations = validate_json(schema, &json.to_string()).unwrap_err();
+        assert!(violations.iter().any(|v| v.path == path), "No violation at {} in {:?}", path, violations);
+    }
+
+    #[test]
+    fn valid_inputs() {
+        assert!(validate_json(Schema::Inputs, r#"["txo:sov:1", "txo:sov:2"]"#).is_ok());
+    }
+
+    #[test]
+    fn inputs_not_strings() {
+        assert_violation(Schema::Inputs, json!([{"address": "pay:sov:1", "seqNo": 1}]), "$[0]");
+    }
+
+    #[test]
+    fn valid_outputs() {
+        let outputs = json!([
+            {"recipient": "pay:sov:1", "amount": 10},
+            {"address": "pay:sov:2", "amount": 0}
+        ]);
+        assert!(validate_json(Schema::Outputs, &outputs.to_string()).is_ok());
+    }
+
+    #[test]
+    fn outputs_missing_recipient() {
+        assert_violation(Schema::Outputs, json!([{"amount": 10}]), "$[0]");
+    }
+
+    #[test]
+    fn outputs_unknown_field() {
+        assert_violation(Schema::Outputs, json!([{"recipient": "pay:sov:1", "amount": 10, "seqNo": 2}]), "$[0].seqNo");
+    }
+
+    #[test]
+    fn receipts_missing_seq_no() {
+        let receipts = json!([{"ver": 1, "receipt": "txo:sov:1", "recipient": "pay:sov:1", "amount": 10, "txnTime": null, "extra": ""}]);
+        assert_violation(Schema::Receipts, receipts, "$[0]");
+    }
+
+    #[test]
+    fn outputs_negative_amount() {
+        assert_violation(Schema::Outputs, json!([{"recipient": "pay:sov:1", "amount": -1}]), "$[0].amount");
+    }
+
+    #[test]
+    fn fees_not_amount() {
+        assert_violation(Schema::Fees, json!({"1": 4, "10001": true}), "$.10001");
+    }
+
+    #[test]
+    fn amounts_as_strings() {
+        assert!(validate_json(Schema::Outputs, &json!([{"recipient": "pay:sov:1", "amount": "10"}]).to_string()).is_ok());
+        assert!(validate_json(Schema::Fees, &json!({"1": "4", "10001": 8}).to_string()).is_ok());
+    }
+
+    #[test]
+    fn response_without_op() {
+        assert_violation(Schema::Response, json!({"result": {}}), "$");
+    }
+
+    #[test]
+    fn invalid_json() {
+        let violations = validate_json(Schema::Response, "{op}").unwrap_err();
+        assert_eq!("$", violations[0].path);
+    }
+
+    #[test]
+    fn lenient_by_default() {
+        assert!(validate_if_strict(Schema::Inputs, "{}").is_ok());
+    }
+}
@@ -0,0 +1,175 @@
+/*!
+    Persists the highest ledger seqNo observed for each payment address.
+
+    The seqNo is stored as a non secret record in the wallet, keyed by the
+    payment address. When the UTXOs of an address are refreshed, see
+    [`refresh_utxos`], the stored seqNo decides if only the delta has to be
+    requested from the ledger or if a full refresh is needed.
+
+    [`refresh_utxos`]: ../refresh_utxos/index.html
+*/
+
+use indy::{IndyHandle, ErrorCode};
+use logic::indy_sdk_api::wallet_api::WalletAPI;
+use logic::parsers::common::TXO;
+use logic::parsers::parse_get_utxo_response::ParseGetUtxoReply;
+use logic::type_aliases::TxnSeqNo;
+
+/**
+    The wallet record type the seqNos are stored under.
+*/
+pub const SEQ_NO_RECORD_TYPE: &str = "sovtoken_last_seq_no";
+
+/**
+    Whether the ledger accepts a starting seqNo in a GET_UTXO request.
+
+    Until it does, every refresh is a full refresh.
+*/
+pub const LEDGER_SUPPORTS_UTXO_FROM: bool = false;
+
+/**
+    How the UTXOs of an address should be refreshed.
+*/
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RefreshMode {
+    /// Request every UTXO of the address.
+    Full,
+    /// Request only the UTXOs after seqNo `from`.
+    Delta { from: TxnSeqNo },
+}
+
+/**
+    SeqNoStore reads and writes the last known seqNo of payment addresses.
+*/
+pub struct SeqNoStore<T> where T: WalletAPI {
+    injected_api: T
+}
+
+impl<T: WalletAPI> SeqNoStore<T> {
+    pub fn new(api_handler: T) -> Self {
+        SeqNoStore { injected_api: api_handler }
+    }
+
+    /**
+        Get the last known seqNo of `address`.
+
+        Returns `None` when nothing was recorded for the address.
+    */
+    pub fn last_seq_no(&self, wallet_handle: IndyHandle, address: &str) -> Result<Option<TxnSeqNo>, ErrorCode> {
+        match self.injected_api.indy_get_record_value(wallet_handle, SEQ_NO_RECORD_TYPE, address) {
+            Ok(value) => {
+                let seq_no = value.parse::<TxnSeqNo>()
+                    .map_err(map_err_err!())
+                    .or(Err(ErrorCode::CommonInvalidState))?;
+                Ok(Some(seq_no))
+            },
+            Err(ErrorCode::WalletItemNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /**
+        Record `seq_no` for `address` when it is higher than the stored one.
+
+        Returns the seqNo which is stored after the call.
+    */
+    pub fn record_seq_no(&self, wallet_handle: IndyHandle, address: &str, seq_no: TxnSeqNo) -> Result<TxnSeqNo, ErrorCode> {
+        trace!("logic::seq_no_store::record_seq_no >> address: {:?}, seq_no: {:?}", address, seq_no);
+        let value = seq_no.to_string();
+
+        let res = match self.last_seq_no(wallet_handle, address)? {
+            None => {
+                self.injected_api.indy_add_record(wallet_handle, SEQ_NO_RECORD_TYPE, address, &value)?;
+                seq_no
+            },
+            Some(last) if last < seq_no => {
+                self.injected_api.indy_update_record_value(wallet_handle, SEQ_NO_RECORD_TYPE, address, &value)?;
+                seq_no
+            },
+            Some(last) => last,
+        };
+
+        trace!("logic::seq_no_store::record_seq_no << result: {:?}", res);
+        Ok(res)
+    }
+
+    /**
+        Record the highest seqNo of each address found in a parsed GET_UTXO reply.
+    */
+    pub fn record_utxo_reply(&self, wallet_handle: IndyHandle, reply: &ParseGetUtxoReply) -> Result<(), ErrorCode> {
+        for utxo in reply {
+            let txo = TXO::from_libindy_string(&utxo.source)
+                .map_err(ErrorCode::from)?;
+            self.record_seq_no(wallet_handle, &txo.address, txo.seq_no)?;
+        }
+        Ok(())
+    }
+
+    /**
+        Decide how the UTXOs of `address` should be refreshed.
+
+        Falls back to `RefreshMode::Full` when the ledger can't handle a
+        starting seqNo or when nothing was recorded for the address.
+    */
+    pub fn refresh_mode(&self, wallet_handle: IndyHandle, address: &str) -> Result<RefreshMode, ErrorCode> {
+        if !LEDGER_SUPPORTS_UTXO_FROM {
+            return Ok(RefreshMode::Full);
+        }
+
+        let mode = match self.last_seq_no(wallet_handle, address)? {
+            Some(from) => RefreshMode::Delta { from },
+            None => RefreshMode::Full,
+        };
+        Ok(mode)
+    }
+}
+
+
+#[cfg(test)]
+mod seq_no_store_tests {
+    use logic::parsers::parse_get_utxo_response::UTXO;
+    use utils::test::wallet::WalletMockHandler;
+    use super::*;
+
+    static WALLET_HANDLE: i32 = 1;
+    static ADDRESS: &'static str = "pay:sov:iTQzpdRdugkJ2gLD5vW5c159dncSL9jbAtu3WfPcb8qWD9bUd";
+
+    fn store() -> SeqNoStore<WalletMockHandler> {
+        SeqNoStore::new(WalletMockHandler::default())
+    }
+
+    #[test]
+    fn last_seq_no_unknown_address() {
+        assert_eq!(None, store().last_seq_no(WALLET_HANDLE, ADDRESS).unwrap());
+    }
+
+    #[test]
+    fn record_keeps_highest_seq_no() {
+        let store = store();
+        assert_eq!(5, store.record_seq_no(WALLET_HANDLE, ADDRESS, 5).unwrap());
+        assert_eq!(9, store.record_seq_no(WALLET_HANDLE, ADDRESS, 9).unwrap());
+        assert_eq!(9, store.record_seq_no(WALLET_HANDLE, ADDRESS, 3).unwrap());
+        assert_eq!(Some(9), store.last_seq_no(WALLET_HANDLE, ADDRESS).unwrap());
+    }
+
+    #[test]
+    fn record_utxo_reply() {
+        let store = store();
+        let reply: ParseGetUtxoReply = vec![2, 7, 4].into_iter().map(|seq_no| UTXO {
+            payment_address: ADDRESS.to_string(),
+            source: TXO { address: ADDRESS.to_string(), seq_no }.to_libindy_string().unwrap(),
+            amount: 10,
+            extra: "".to_string(),
+        }).collect();
+
+        store.record_utxo_reply(WALLET_HANDLE, &reply).unwrap();
+        assert_eq!(Some(7), store.last_seq_no(WALLET_HANDLE, ADDRESS).unwrap());
+    }
+
+    #[test]
+    fn refresh_mode_is_full_without_ledger_support() {
+        let store = store();
+        store.record_seq_no(WALLET_HANDLE, ADDRESS, 5).unwrap();
+        assert_eq!(RefreshMode::Full, store.refresh_mode(WALLET_HANDLE, ADDRESS).unwrap());
+    }
+}
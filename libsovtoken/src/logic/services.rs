@@ -0,0 +1,257 @@
+/*!
+    The services the handlers work with, set up once.
+
+    Instead of creating SDK objects where they are needed, the handlers borrow
+    them from [`services`], which is initialized by `sovtoken_init`. Logic
+    taking a [`Services`] can be handed mocks instead of the SDK.
+
+    [`services`]: fn.services.html
+    [`Services`]: struct.Services.html
+*/
+
+use lazy_static;
+
+use logic::address_book::AddressBook;
+use logic::aggregate_balance::Aggregator;
+use logic::config::library_config::{library_config, LibraryConfig};
+use logic::extra::ExtraStore;
+use logic::indy_sdk_api::crypto_api::{CryptoAPI, CryptoSdk};
+use logic::indy_sdk_api::ledger_api::{LedgerAPI, LedgerSdk};
+use logic::indy_sdk_api::payment_api::{PaymentAPI, PaymentSdk};
+use logic::indy_sdk_api::wallet_api::{WalletAPI, WalletSdk};
+use logic::payment_intent::IntentStore;
+use logic::payment_template::TemplateStore;
+use logic::payments::CreatePaymentHandler;
+use logic::pending_txn::PendingTxnStore;
+use logic::public_export::PublicExporter;
+use logic::reconcile::Reconciler;
+use logic::refresh_utxos::UtxoRefresher;
+use logic::storage::Storage;
+use logic::txo_status::TxoStatusChecker;
+use logic::write_with_fees::WriteFunder;
+
+/**
+    The services backed by the Indy SDK.
+*/
+pub type SdkServices = Services<CryptoSdk, WalletSdk, PaymentSdk, LedgerSdk>;
+
+/**
+    Holds one implementation of each SDK api.
+
+    The stores and helpers returned by the methods borrow them, so they all
+    work with the same implementations. Each of them is generic over the
+    apis it uses: in production they are the SDK implementations of
+    [`SdkServices`], or the storage backend of the library config for the
+    stores of `Storage`, and in tests anything implementing the same traits,
+    e.g. a mock of `WalletAPI`.
+
+    [`SdkServices`]: type.SdkServices.html
+*/
+pub struct Services<C, W, P, L> where C: CryptoAPI, W: WalletAPI, P: PaymentAPI, L: LedgerAPI {
+    pub crypto: C,
+    pub wallet: W,
+    pub payment: P,
+    pub ledger: L,
+}
+
+impl<C: CryptoAPI, W: WalletAPI, P: PaymentAPI, L: LedgerAPI> Services<C, W, P, L> {
+    pub fn new(crypto: C, wallet: W, payment: P, ledger: L) -> Self {
+        Services { crypto, wallet, payment, ledger }
+    }
+
+    /**
+        The current library config.
+    */
+    pub fn config(&self) -> LibraryConfig {
+        library_config()
+    }
+
+    pub fn payment_addresses(&self) -> CreatePaymentHandler<&C> {
+        CreatePaymentHandler::new(&self.crypto)
+    }
+
+    pub fn aggregator(&self) -> Aggregator<&P> {
+        Aggregator::new(&self.payment)
+    }
+
+    /**
+        The labels, creation time and cached balance of the payment addresses.
+    */
+    pub fn address_book(&self) -> AddressBook<&W, &P> {
+        AddressBook::new(&self.wallet, &self.payment)
+    }
+
+    pub fn templates(&self) -> TemplateStore<&W> {
+        TemplateStore::new(&self.wallet)
+    }
+
+    pub fn intents(&self) -> IntentStore<&W> {
+        IntentStore::new(&self.wallet)
+    }
+
+    /**
+        The payments on their way to the ledger, in the storage backend of the library config.
+    */
+    pub fn pending_txns(&self) -> PendingTxnStore<Storage<&W>> {
+        PendingTxnStore::new(Storage::configured(&self.wallet))
+    }
+
+    /**
+        The extras replaced by their hash, in the storage backend of the library config.
+    */
+    pub fn extras(&self) -> ExtraStore<Storage<&W>> {
+        ExtraStore::new(Storage::configured(&self.wallet))
+    }
+
+    /**
+        Refreshes UTXOs, tracking the last seqNo seen for each payment address.
+    */
+    pub fn utxo_refresher(&self) -> UtxoRefresher<&W, &P> {
+        UtxoRefresher::new(&self.wallet, &self.payment)
+    }
+
+    pub fn reconciler(&self) -> Reconciler<&W, &P> {
+        Reconciler::new(&self.wallet, &self.payment)
+    }
+
+    /**
+        Whether txos are spent, by the ledger and the pending transactions.
+    */
+    pub fn txo_status(&self) -> TxoStatusChecker<&W, &P> {
+        TxoStatusChecker::new(&self.wallet, &self.payment)
+    }
+
+    /**
+        The addresses and pending transactions of a wallet, without its secrets.
+    */
+    pub fn public_export(&self) -> PublicExporter<&W, &P> {
+        PublicExporter::new(&self.wallet, &self.payment)
+    }
+
+    /**
+        The inputs and change paying the fees of NYM, ATTRIB and SCHEMA requests.
+    */
+    pub fn write_funder(&self) -> WriteFunder<&P> {
+        WriteFunder::new(&self.payment)
+    }
+}
+
+lazy_static! {
+    static ref SERVICES: SdkServices = Services::new(CryptoSdk {}, WalletSdk {}, PaymentSdk {}, LedgerSdk {});
+}
+
+/**
+    The services of the handlers.
+*/
+pub fn services() -> &'static SdkServices {
+    &SERVICES
+}
+
+/**
+    Set up the services of the handlers, called by `sovtoken_init`.
+*/
+pub fn init_services() {
+    lazy_static::initialize(&SERVICES);
+}
+
+
+#[cfg(test)]
+mod services_tests {
+    use std::sync::mpsc::channel;
+
+    use indy::{IndyHandle, ErrorCode};
+    use logic::config::payment_address_config::PaymentAddressConfig;
+    use logic::parsers::parse_get_utxo_response::ParseGetUtxoReply;
+    use logic::payment_intent::PaymentIntent;
+    use logic::pending_txn::PendingState;
+    use utils::test::wallet::WalletMockHandler;
+    use super::*;
+
+    static WALLET_HANDLE: i32 = 1;
+
+    struct CryptoMockHandler {}
+
+    impl CryptoAPI for CryptoMockHandler {
+        fn indy_create_key(&self, _: IndyHandle, _: PaymentAddressConfig) -> Result<String, ErrorCode> {
+            Ok(String::from("EFfodscoQ3hpBB6ZLcHsfV5oKoMxD7XWpwCnwMeprVJL"))
+        }
+
+        fn indy_create_key_async<F: 'static>(&self, _: i32, _: PaymentAddressConfig, _: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+            ErrorCode::CommonInvalidState
+        }
+
+        fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _: IndyHandle, _: String, _: &[u8], _: F) -> ErrorCode {
+            ErrorCode::CommonInvalidState
+        }
+    }
+
+    struct PaymentMockHandler {}
+
+    impl PaymentAPI for PaymentMockHandler {
+        fn indy_list_payment_addresses(&self, _: IndyHandle) -> Result<Vec<String>, ErrorCode> {
+            Ok(Vec::new())
+        }
+
+        fn indy_get_utxos(&self, _: IndyHandle, _: &str) -> Result<ParseGetUtxoReply, ErrorCode> {
+            Ok(Vec::new())
+        }
+    }
+
+    struct LedgerMockHandler {}
+
+    impl LedgerAPI for LedgerMockHandler {
+        fn indy_submit_request_async<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _: IndyHandle, _: &str, mut cb: F) -> ErrorCode {
+            cb(Ok(String::from(r#"{"op": "REPLY"}"#)));
+            ErrorCode::Success
+        }
+
+        fn indy_sign_request<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _: IndyHandle, _: &str, _: &str, _: bool, _: F) -> ErrorCode {
+            ErrorCode::CommonInvalidState
+        }
+    }
+
+    fn mock_services() -> Services<CryptoMockHandler, WalletMockHandler, PaymentMockHandler, LedgerMockHandler> {
+        Services::new(CryptoMockHandler {}, WalletMockHandler::default(), PaymentMockHandler {}, LedgerMockHandler {})
+    }
+
+    #[test]
+    fn stores_share_the_wallet() {
+        let services = mock_services();
+
+        let intent = PaymentIntent {
+            recipient: String::from("pay:sov:2FKYJkgXRZtjhFpTMHhuyfc17BHZWcFPyF2MWy2SZMBaSo64fb"),
+            amount: 10,
+            not_before: 0,
+            fee: 0,
+            extra: None,
+            change_address: None,
+        };
+        services.intents().register(WALLET_HANDLE, &intent).unwrap();
+        services.pending_txns().track(WALLET_HANDLE, "{}", PendingState::Built, 0).unwrap();
+
+        assert_eq!(2, services.wallet.records.borrow().len());
+    }
+
+    #[test]
+    fn pending_txn_submitted_to_the_ledger() {
+        let services = mock_services();
+        let txn_id = services.pending_txns().track(WALLET_HANDLE, "{}", PendingState::Signed, 0).unwrap();
+
+        let txn = services.pending_txns().submitting(WALLET_HANDLE, &txn_id, 0).unwrap();
+        let (sender, receiver) = channel();
+        let ec = services.ledger.indy_submit_request_async(2, &txn.request, move |reply| sender.send(reply).unwrap());
+        assert_eq!(ErrorCode::Success, ec);
+
+        let reply = receiver.recv().unwrap().unwrap();
+        let txn = services.pending_txns().record_reply(WALLET_HANDLE, &txn_id, 0, &reply).unwrap();
+        assert_eq!(PendingState::Confirmed, txn.state);
+    }
+
+    #[test]
+    fn payment_address_from_crypto() {
+        let address = mock_services().payment_addresses()
+            .create_payment_address(WALLET_HANDLE, PaymentAddressConfig { seed: String::new(), strict_seed: false, reuse_existing: false, seed_encoding: None })
+            .unwrap();
+        assert!(address.starts_with("pay:sov:"));
+    }
+}
@@ -1,9 +1,13 @@
 //! This module is for ???
 
+use std::collections::HashMap;
+
 use indy::ErrorCode;
 use libc::c_char;
+use logic::amount_format::Amount;
 use logic::config::set_fees_config::{SetFees, SetFeesMap};
 use logic::did::Did;
+use logic::schema::{validate_if_strict, Schema};
 use serde_json;
 use utils::constants::general::{JsonCallback, JsonCallbackUnwrapped};
 use utils::ffi_support::string_from_char_ptr;
@@ -48,19 +52,34 @@ pub fn deserialize_inputs<'a>(
     let set_fees_json = string_from_char_ptr(fees_json)
         .ok_or(ErrorCode::CommonInvalidStructure).map_err(map_err_err!())?;
 
-    let set_fees_map: SetFeesMap = serde_json::from_str(&set_fees_json).map_err(map_err_err!())
+    let set_fees = SetFees::new(fees_from_json(&set_fees_json)?);
+
+    let res = Ok((did, set_fees, cb));
+    trace!("logic::set_fees::deserialize_inputs << res: {:?}", res);
+    return res;
+}
+
+/**
+    The fees of a fees json, `{"<txn type>": <fee>, ...}`.
+
+    The txn types are codes, or the names of the indy txn types, e.g.
+    `"NYM"`. Fails with `CommonInvalidStructure` when the fees are empty or
+    a txn type isn't an integer.
+*/
+pub fn fees_from_json(fees_json: &str) -> Result<SetFeesMap, ErrorCode> {
+    validate_if_strict(Schema::Fees, fees_json)?;
+
+    let set_fees_map: HashMap<String, Amount> = serde_json::from_str(fees_json).map_err(map_err_err!())
         .or(Err(ErrorCode::CommonInvalidStructure))?;
 
     let set_fees_map: SetFeesMap = set_fees_map.iter()
-        .map(|(key, val)| (txn_name_to_code(key), val.clone())).collect();
+        .map(|(key, val)| (txn_name_to_code(key), val.0)).collect();
 
     let set_fees = SetFees::new(set_fees_map)
         .validate().map_err(map_err_err!())
         .or(Err(ErrorCode::CommonInvalidStructure))?;
 
-    let res = Ok((did, set_fees, cb));
-    trace!("logic::set_fees::deserialize_inputs << res: {:?}", res);
-    return res;
+    Ok(set_fees.fees)
 }
 
 #[cfg(test)]
@@ -123,12 +142,24 @@ mod test_deserialize_inputs {
     }
 
     #[test]
-    fn deserialize_invalid_fees_string_values() {
-        let invalid_fees = json_c_pointer!({
+    fn deserialize_fees_string_values() {
+        let fees = json_c_pointer!({
             "4": "2",
             "20000": "5",
         });
 
+        let (_, fees, _) = call_deserialize_inputs(None, Some(fees), None).unwrap();
+
+        assert_eq!(fees.fees.get("4"), Some(&2));
+        assert_eq!(fees.fees.get("20000"), Some(&5));
+    }
+
+    #[test]
+    fn deserialize_invalid_fees_decimal_string_values() {
+        let invalid_fees = json_c_pointer!({
+            "4": "2.5",
+        });
+
         let result = call_deserialize_inputs(None, Some(invalid_fees), None);
 
         assert_eq!(ErrorCode::CommonInvalidStructure, result.unwrap_err());
@@ -0,0 +1,250 @@
+/*!
+    The exact bytes signed for an input of a request.
+
+    Security reviews and hardware signers check what a key signs without
+    reading the signing code. [`signing_payload`] rebuilds the message of an
+    input from a built request, the way [`XferPayload`] signs it:
+
+    * an XFER_PUBLIC request signs the `inputs` and `outputs` of its operation,
+    * a request with `fees` signs the inputs and outputs of the fees and the
+      sha256 of the [`serialize_signature`] of the request, the fees left out.
+
+    The signatures of the request are ignored, so a request can be checked
+    before or after it is signed.
+
+    [`signing_payload`]: fn.signing_payload.html
+    [`XferPayload`]: ../xfer_payload/struct.XferPayload.html
+    [`serialize_signature`]: ../xfer_payload/fn.serialize_signature.html
+*/
+
+use base64;
+use hex::ToHex;
+use indy::ErrorCode;
+use serde_json::{self, Value};
+use sodiumoxide::crypto::sign::{self, PublicKey, Signature};
+
+use logic::address;
+use logic::api_internals::add_request_fees::RawRequest;
+use logic::input::{Input, Inputs};
+use logic::output::Outputs;
+use logic::xfer_payload::{signing_message, XferPayload};
+use utils::base58::FromBase58;
+use utils::constants::txn_fields::FEES;
+use utils::constants::txn_types::XFER_PUBLIC;
+
+/**
+    The message signed for an input, with the key it is signed with.
+
+    ```text
+    {
+        "input": {"address": <string>, "seqNo": <int>},
+        "verkey": <string>,         // the key of the address of the input
+        "txnDigest": <string>,      // hex digest of the request, null for a transfer
+        "message": <string>,        // the signed bytes as utf-8 text
+        "hex": <string>,            // the signed bytes as hex
+        "base64": <string>          // the signed bytes as base64
+    }
+    ```
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SigningPayload {
+    pub input: Input,
+    pub verkey: String,
+    pub txn_digest: Option<String>,
+    pub message: String,
+    pub hex: String,
+    pub base64: String,
+}
+
+/**
+    The payload signed for the input at `input_index` of `request_json`.
+
+    Fails with `CommonInvalidStructure` when the request is neither a
+    transfer nor has fees, or has no input at `input_index`.
+*/
+pub fn signing_payload(request_json: &str, input_index: usize) -> Result<SigningPayload, ErrorCode> {
+    let request: Value = serde_json::from_str(request_json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    let (inputs, outputs, txn_digest) = if request["operation"]["type"] == json!(XFER_PUBLIC) {
+        let operation = &request["operation"];
+        (inputs_of(&operation["inputs"])?, outputs_of(&operation["outputs"])?, None)
+    } else if let Some(fees) = request.get(FEES) {
+        let digest = RawRequest::new(request_json.to_string())?.digest()?;
+        (inputs_of(&fees[0])?, outputs_of(&fees[1])?, Some(digest))
+    } else {
+        error!("The request is neither a transfer nor has fees, none of its inputs are signed");
+        return Err(ErrorCode::CommonInvalidStructure);
+    };
+
+    payload_of(&inputs, &outputs, input_index, txn_digest)
+}
+
+/**
+    The payloads signed for every input of an unsigned transfer, in the order of its inputs.
+*/
+pub fn transfer_signing_payloads(payload: &XferPayload) -> Result<Vec<SigningPayload>, ErrorCode> {
+    (0..payload.inputs.len())
+        .map(|input_index| payload_of(&payload.inputs, &payload.outputs, input_index, None))
+        .collect()
+}
+
+/**
+    The payloads signed for every input of the fees of a request with the
+    hex digest `txn_digest`, in the order of the inputs.
+*/
+pub fn fees_signing_payloads(inputs: &Inputs, outputs: &Outputs, txn_digest: &str) -> Result<Vec<SigningPayload>, ErrorCode> {
+    (0..inputs.len())
+        .map(|input_index| payload_of(inputs, outputs, input_index, Some(txn_digest.to_string())))
+        .collect()
+}
+
+/**
+    Whether `signature`, in base58, is the signature of `message` by the key
+    of the base58 `verkey`. A malformed key or signature signs nothing.
+*/
+pub fn is_signed_by(verkey: &str, message: &[u8], signature: &str) -> bool {
+    let public_key = verkey.from_base58().ok().and_then(|bytes| PublicKey::from_slice(&bytes));
+    let signature = signature.from_base58().ok().and_then(|bytes| Signature::from_slice(&bytes));
+
+    match (public_key, signature) {
+        (Some(public_key), Some(signature)) => sign::verify_detached(&signature, message, &public_key),
+        _ => false,
+    }
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn payload_of(inputs: &Inputs, outputs: &Outputs, input_index: usize, txn_digest: Option<String>) -> Result<SigningPayload, ErrorCode> {
+    let input = match inputs.get(input_index) {
+        Some(input) => Input::new(address::unqualified_part(&input.address).to_string(), input.seq_no),
+        None => {
+            error!("The request has {} inputs, none at {}", inputs.len(), input_index);
+            return Err(ErrorCode::CommonInvalidStructure);
+        }
+    };
+    let outputs = outputs.iter().cloned()
+        .map(|mut output| {
+            output.recipient = address::unqualified_part(&output.recipient).to_string();
+            output
+        })
+        .collect();
+
+    let verkey = address::verkey_from_unqualified_address(&input.address)?;
+    let message = signing_message(&input, &outputs, &txn_digest)?;
+
+    Ok(SigningPayload {
+        input,
+        verkey,
+        txn_digest,
+        hex: message.as_bytes().to_hex(),
+        base64: base64::encode(message.as_bytes()),
+        message,
+    })
+}
+
+// Parsed from the text, the deserializers of inputs and outputs borrow their keys.
+fn inputs_of(inputs: &Value) -> Result<Inputs, ErrorCode> {
+    serde_json::from_str(&inputs.to_string())
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))
+}
+
+fn outputs_of(outputs: &Value) -> Result<Outputs, ErrorCode> {
+    serde_json::from_str(&outputs.to_string())
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))
+}
+
+
+#[cfg(test)]
+mod signing_payload_tests {
+    use logic::output::Output;
+    use super::*;
+
+    static ADDRESS: &'static str = "2jS4PHWQJKcawRxdW6GVsjnZBa1ecGdCssn7KhWYJZGTXgL7Es";
+    static RECIPIENT: &'static str = "a8QAXMjRwEGoGLmMFEc5sTcntZxEF1BpqAs8GoKFa9Ck81fo7";
+
+    fn transfer() -> Value {
+        json!({
+            "operation": {
+                "type": XFER_PUBLIC,
+                "inputs": [{"address": ADDRESS, "seqNo": 2}, {"address": RECIPIENT, "seqNo": 3}],
+                "outputs": [{"address": RECIPIENT, "amount": 10}],
+                "signatures": ["sig1", "sig2"]
+            },
+            "identifier": "LibsovtokenDid11111111",
+            "reqId": 1
+        })
+    }
+
+    #[test]
+    fn payload_of_a_transfer_input() {
+        let payload = signing_payload(&transfer().to_string(), 0).unwrap();
+        let outputs = vec![Output::new(RECIPIENT.to_string(), 10)];
+
+        assert_eq!(Input::new(ADDRESS.to_string(), 2), payload.input);
+        assert_eq!(None, payload.txn_digest);
+        assert_eq!(signing_message(&payload.input, &outputs, &None).unwrap(), payload.message);
+        assert_eq!(payload.message.as_bytes().to_hex(), payload.hex);
+        assert_eq!(address::verkey_from_unqualified_address(ADDRESS).unwrap(), payload.verkey);
+    }
+
+    #[test]
+    fn payload_of_qualified_addresses() {
+        let mut request = transfer();
+        request["operation"]["inputs"][0]["address"] = json!(format!("pay:sov:{}", ADDRESS));
+        request["operation"]["outputs"][0]["address"] = json!(format!("pay:sov:{}", RECIPIENT));
+
+        assert_eq!(signing_payload(&transfer().to_string(), 0), signing_payload(&request.to_string(), 0));
+    }
+
+    #[test]
+    fn payload_of_a_fees_input() {
+        let request = json!({
+            "operation": {"type": "1", "dest": "Th7MpTaRZVRYnPiabds81Y"},
+            "identifier": "LibsovtokenDid11111111",
+            "reqId": 1,
+            "fees": [[{"address": ADDRESS, "seqNo": 2}], [{"address": RECIPIENT, "amount": 9}], ["sig"]]
+        });
+        let mut unsigned = request.clone();
+        unsigned.as_object_mut().unwrap().remove(FEES);
+
+        let payload = signing_payload(&request.to_string(), 0).unwrap();
+        let digest = RawRequest::new(unsigned.to_string()).unwrap().digest().unwrap();
+        assert_eq!(Some(digest.clone()), payload.txn_digest);
+        assert!(payload.message.ends_with(&digest));
+    }
+
+    #[test]
+    fn payloads_of_fees_not_added_yet() {
+        let request = json!({
+            "operation": {"type": "1", "dest": "Th7MpTaRZVRYnPiabds81Y"},
+            "identifier": "LibsovtokenDid11111111",
+            "reqId": 1
+        });
+        let inputs = vec![Input::new(ADDRESS.to_string(), 2)];
+        let outputs = vec![Output::new(RECIPIENT.to_string(), 9)];
+        let digest = RawRequest::new(request.to_string()).unwrap().digest().unwrap();
+
+        let mut with_fees = request.clone();
+        with_fees[FEES] = json!([[{"address": ADDRESS, "seqNo": 2}], [{"address": RECIPIENT, "amount": 9}], ["sig"]]);
+        assert_eq!(vec![signing_payload(&with_fees.to_string(), 0).unwrap()], fees_signing_payloads(&inputs, &outputs, &digest).unwrap());
+    }
+
+    #[test]
+    fn payload_of_a_missing_input() {
+        assert_eq!(ErrorCode::CommonInvalidStructure, signing_payload(&transfer().to_string(), 2).unwrap_err());
+    }
+
+    #[test]
+    fn payload_of_a_request_without_inputs() {
+        let request = json!({"operation": {"type": "1"}, "reqId": 1});
+        assert_eq!(ErrorCode::CommonInvalidStructure, signing_payload(&request.to_string(), 0).unwrap_err());
+    }
+}
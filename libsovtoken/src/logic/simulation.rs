@@ -0,0 +1,334 @@
+/*!
+    Dry runs of the payment builders.
+
+    A simulation runs the validation, coin selection and size estimation of a
+    build and rebuilds the messages the inputs would sign, but signs nothing,
+    stores nothing in the wallet and submits nothing. The [`PaymentPlan`] of a
+    payment and the [`FeesPlan`] of the fees of a request are meant to be
+    shown to the user before they confirm.
+
+    The fees are the ones of the parsed fee schedules, see [`fee_waiver`].
+
+    [`PaymentPlan`]: struct.PaymentPlan.html
+    [`FeesPlan`]: struct.FeesPlan.html
+    [`fee_waiver`]: ../fee_waiver/index.html
+*/
+
+use indy::{ErrorCode, IndyHandle};
+
+use logic::api_internals::add_request_fees::{self, FeesMetadata, RawRequest};
+use logic::batch_payment::BatchChunk;
+use logic::config::library_config::library_config;
+use logic::fee_waiver;
+use logic::input::{Input, Inputs};
+use logic::output::{Output, Outputs};
+use logic::request_size::estimate_xfer_size;
+use logic::signing_payload::{fees_signing_payloads, transfer_signing_payloads, SigningPayload};
+use logic::type_aliases::{TokenAmount, TxnSeqNo};
+use logic::xfer_payload::XferPayload;
+use utils::constants::txn_types::XFER_PUBLIC;
+
+/**
+    The options json of the `_with_options` build handlers, every field is optional.
+
+    ```
+        use sovtoken::utils::json_conversion::JsonDeserialize;
+        use sovtoken::logic::simulation::BuildOptions;
+
+        assert!(BuildOptions::from_json(r#"{"simulate": true}"#).unwrap().simulate);
+        assert!(!BuildOptions::from_json("{}").unwrap().simulate);
+    ```
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BuildOptions {
+    /// Return the plan of the requests instead of signing them.
+    pub simulate: bool,
+    /// Build the outputs of 0 tokens, as the `allow_zero_outputs` of the library config when `None`.
+    pub allow_zero_outputs: Option<bool>,
+    /// The pool the request is for, fees are only added to a request of the protocol version of the pool.
+    pub pool_handle: Option<IndyHandle>,
+    /// Keep the inputs and outputs given in their order, see `logic::canonical_order`.
+    pub preserve_order: bool,
+}
+
+impl BuildOptions {
+    /**
+        Whether the build accepts outputs of 0 tokens.
+    */
+    pub fn zero_outputs_allowed(&self) -> bool {
+        self.allow_zero_outputs.unwrap_or_else(|| library_config().allow_zero_outputs)
+    }
+}
+
+/**
+    An input chosen for a request, `amount` is `None` when the builder was
+    given the input without its amount.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedInput {
+    pub address: String,
+    pub seq_no: TxnSeqNo,
+    pub amount: Option<TokenAmount>,
+}
+
+/**
+    What a request would be, had it been signed.
+
+    `amount` is what the outputs pay, the change excluded. `fee` is what
+    the inputs hold on top of the outputs, the change included. It is `None`
+    when the amount of an input isn't known. `required_fee` is the fee of
+    XFER_PUBLIC in the fee schedule, `None` when no schedule listed it.
+*/
+#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestPlan {
+    pub index: usize,
+    pub amount: TokenAmount,
+    pub inputs: Vec<PlannedInput>,
+    pub outputs: Vec<Output>,
+    pub change: Option<Output>,
+    pub fee: Option<TokenAmount>,
+    pub required_fee: Option<TokenAmount>,
+    pub estimated_bytes: usize,
+    pub signing_payloads: Vec<SigningPayload>,
+}
+
+/**
+    The plan of a build, one [`RequestPlan`] per request it would sign.
+
+    [`RequestPlan`]: struct.RequestPlan.html
+*/
+#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentPlan {
+    pub requests: Vec<RequestPlan>,
+    pub total_amount: TokenAmount,
+    pub total_change: TokenAmount,
+    pub total_fees: Option<TokenAmount>,
+    pub total_required_fees: Option<TokenAmount>,
+    pub estimated_bytes: usize,
+    pub merged_duplicates: usize,
+}
+
+/**
+    The plan of the fees `add_request_fees_handler` would add to a request.
+
+    `fees_applied` is false when the fee schedule waives the fees of the
+    request, `metadata` is then the one of waived fees and nothing would be
+    signed.
+*/
+#[derive(Serialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FeesPlan {
+    pub fees_applied: bool,
+    pub metadata: FeesMetadata,
+    pub txn_digest: String,
+    pub signing_payloads: Vec<SigningPayload>,
+}
+
+/**
+    The plan of the unsigned `payload`.
+
+    `input_amounts` are the amounts known for the inputs, the inputs of
+    `payload` missing from it have no amount in the plan.
+*/
+pub fn plan_request(payload: &XferPayload, input_amounts: &[(Input, TokenAmount)]) -> Result<RequestPlan, ErrorCode> {
+    let inputs: Vec<PlannedInput> = payload.inputs.iter()
+        .map(|input| PlannedInput {
+            address: input.address.clone(),
+            seq_no: input.seq_no,
+            amount: input_amounts.iter()
+                .find(|&&(ref known, _)| known == input)
+                .map(|&(_, amount)| amount),
+        })
+        .collect();
+
+    let inputs_total = inputs.iter()
+        .fold(Some(0), |total: Option<TokenAmount>, input| total.and_then(|total| input.amount.and_then(|amount| total.checked_add(amount))));
+    let outputs_total = payload.outputs.iter()
+        .fold(Some(0), |total: Option<TokenAmount>, output| total.and_then(|total| total.checked_add(output.amount)))
+        .ok_or(ErrorCode::CommonInvalidStructure)?;
+
+    Ok(RequestPlan {
+        index: 0,
+        amount: outputs_total,
+        inputs,
+        outputs: payload.outputs.clone(),
+        change: None,
+        fee: inputs_total.and_then(|inputs_total| inputs_total.checked_sub(outputs_total)),
+        required_fee: fee_waiver::known_fee(None, XFER_PUBLIC),
+        estimated_bytes: estimate_xfer_size(payload)?,
+        signing_payloads: transfer_signing_payloads(payload)?,
+    })
+}
+
+/**
+    The plan of the single request of `build_payment_req_handler`, whose
+    inputs are given without their amounts.
+*/
+pub fn plan_payment(payload: &XferPayload) -> Result<PaymentPlan, ErrorCode> {
+    plan_of(vec![plan_request(payload, &[])?], 0)
+}
+
+/**
+    The plan of the requests a batch or a split transfer was divided into.
+*/
+pub fn plan_chunks(chunks: Vec<BatchChunk>, merged_duplicates: usize) -> Result<PaymentPlan, ErrorCode> {
+    let mut requests = Vec::new();
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let mut request = chunk.builder.simulate()?;
+        request.index = index;
+        request.amount = chunk.amount;
+        request.change = chunk.change;
+        requests.push(request);
+    }
+    plan_of(requests, merged_duplicates)
+}
+
+/**
+    The plan of the fees `inputs` and `outputs` would pay for `request`, with
+    the fee schedule of the network of `pool_handle`.
+*/
+pub fn plan_fees(request: &RawRequest, inputs: &Inputs, outputs: &Outputs, pool_handle: Option<IndyHandle>) -> Result<FeesPlan, ErrorCode> {
+    let txn_digest = request.digest()?;
+    if add_request_fees::fees_waived(request, pool_handle) {
+        return Ok(FeesPlan { fees_applied: false, metadata: FeesMetadata::waived(), txn_digest, signing_payloads: Vec::new() });
+    }
+
+    Ok(FeesPlan {
+        fees_applied: true,
+        metadata: FeesMetadata::new(inputs, outputs, pool_handle)?,
+        signing_payloads: fees_signing_payloads(inputs, outputs, &txn_digest)?,
+        txn_digest,
+    })
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn plan_of(requests: Vec<RequestPlan>, merged_duplicates: usize) -> Result<PaymentPlan, ErrorCode> {
+    let total_amount = checked_total(requests.iter().map(|request| request.amount))?;
+    let total_change = checked_total(requests.iter().map(|request| request.change.as_ref().map(|change| change.amount).unwrap_or(0)))?;
+    let total_fees = requests.iter()
+        .fold(Some(0), |total: Option<TokenAmount>, request| total.and_then(|total| request.fee.and_then(|fee| total.checked_add(fee))));
+    let total_required_fees = requests.iter()
+        .fold(Some(0), |total: Option<TokenAmount>, request| total.and_then(|total| request.required_fee.and_then(|fee| total.checked_add(fee))));
+    let estimated_bytes = requests.iter().map(|request| request.estimated_bytes).sum();
+
+    Ok(PaymentPlan { requests, total_amount, total_change, total_fees, total_required_fees, estimated_bytes, merged_duplicates })
+}
+
+fn checked_total<I: Iterator<Item=TokenAmount>>(amounts: I) -> Result<TokenAmount, ErrorCode> {
+    amounts
+        .fold(Some(0), |total: Option<TokenAmount>, amount| total.and_then(|total| total.checked_add(amount)))
+        .ok_or(ErrorCode::CommonInvalidStructure)
+}
+
+
+#[cfg(test)]
+mod simulation_tests {
+    use std::collections::HashMap;
+
+    use logic::batch_payment::plan_batch;
+    use logic::config::library_config::LibraryConfig;
+    use logic::parsers::common::TXO;
+    use logic::parsers::parse_get_utxo_response::UTXO;
+    use logic::state::state;
+    use logic::transfer_limits::TransferLimits;
+    use logic::utxo_cache::UtxoCache;
+    use utils::test::context::in_own_context;
+    use super::*;
+
+    static INPUT_ADDRESS: &'static str = "pay:sov:E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm";
+    static OUTPUT_ADDRESS: &'static str = "pay:sov:TKe9eXtchV71J2qXX5HwP8rbkTBStnEEkMwQkHie265VtRSbs";
+
+    fn utxos(amounts: Vec<TokenAmount>) -> Vec<UTXO> {
+        amounts.into_iter().enumerate().map(|(seq_no, amount)| UTXO {
+            payment_address: INPUT_ADDRESS.to_string(),
+            source: TXO { address: INPUT_ADDRESS.to_string(), seq_no: seq_no as u64 + 1 }.to_libindy_string().unwrap(),
+            amount,
+            extra: "".to_string(),
+        }).collect()
+    }
+
+    #[test]
+    fn plan_of_a_batch() {
+        let outputs = vec![Output::new(OUTPUT_ADDRESS.to_string(), 7)];
+        let chunks = plan_batch(&utxos(vec![5, 5]), &outputs, TransferLimits::default(), None).unwrap();
+        let plan = plan_chunks(chunks, 0).unwrap();
+
+        assert_eq!(1, plan.requests.len());
+        assert_eq!(7, plan.total_amount);
+        assert_eq!(3, plan.total_change);
+        assert_eq!(Some(0), plan.total_fees);
+        assert_eq!(7, plan.requests[0].amount);
+        assert_eq!(Some(Output::new(INPUT_ADDRESS.to_string(), 3)), plan.requests[0].change);
+        assert_eq!(vec![Some(5), Some(5)], plan.requests[0].inputs.iter().map(|input| input.amount).collect::<Vec<_>>());
+        assert_eq!(2, plan.requests[0].signing_payloads.len());
+        assert!(plan.estimated_bytes > 0);
+    }
+
+    #[test]
+    fn plan_of_inputs_without_amount() {
+        let payload = XferPayload::new(
+            vec![Input::new(INPUT_ADDRESS.to_string(), 1)],
+            vec![Output::new(OUTPUT_ADDRESS.to_string(), 10)],
+            None
+        );
+        let plan = plan_request(&payload, &[]).unwrap();
+
+        assert_eq!(None, plan.inputs[0].amount);
+        assert_eq!(None, plan.fee);
+        assert_eq!(None, plan.signing_payloads[0].txn_digest);
+    }
+
+    #[test]
+    fn plan_of_a_payment_with_the_scheduled_fee() {
+        in_own_context(LibraryConfig::default(), || {
+            let mut fees = HashMap::new();
+            fees.insert(XFER_PUBLIC.to_string(), 3);
+            fee_waiver::remember_schedule(None, &fees);
+
+            let payload = XferPayload::new(
+                vec![Input::new(INPUT_ADDRESS.to_string(), 1)],
+                vec![Output::new(OUTPUT_ADDRESS.to_string(), 10)],
+                None
+            );
+            let plan = plan_payment(&payload).unwrap();
+
+            assert_eq!(Some(3), plan.requests[0].required_fee);
+            assert_eq!(Some(3), plan.total_required_fees);
+            assert_eq!(None, plan.total_fees);
+        });
+    }
+
+    #[test]
+    fn plan_of_fees() {
+        in_own_context(LibraryConfig::default(), || {
+            let mut fees = HashMap::new();
+            fees.insert("1".to_string(), 4);
+            fees.insert("100".to_string(), 0);
+            fee_waiver::remember_schedule(None, &fees);
+
+            let inputs = vec![Input::new(INPUT_ADDRESS.to_string(), 1)];
+            let outputs = vec![Output::new(INPUT_ADDRESS.to_string(), 6)];
+            state().utxo_cache.lock().insert(4, INPUT_ADDRESS, "root", UtxoCache::body_hash("body"), utxos(vec![10]));
+
+            let nym = RawRequest::new(json!({"operation": {"type": "1"}, "reqId": 1}).to_string()).unwrap();
+            let plan = plan_fees(&nym, &inputs, &outputs, None).unwrap();
+            assert!(plan.fees_applied);
+            assert_eq!(Some(4), plan.metadata.fee_total.map(|amount| amount.0));
+            assert_eq!(1, plan.signing_payloads.len());
+            assert_eq!(Some(plan.txn_digest.clone()), plan.signing_payloads[0].txn_digest);
+
+            let attrib = RawRequest::new(json!({"operation": {"type": "100"}, "reqId": 1}).to_string()).unwrap();
+            let plan = plan_fees(&attrib, &inputs, &outputs, None).unwrap();
+            assert!(!plan.fees_applied);
+            assert!(plan.signing_payloads.is_empty());
+        });
+    }
+}
@@ -0,0 +1,240 @@
+/*!
+    The global mutable state of the library, in one place.
+
+    The handlers are called from any thread of the application and of
+    libindy, so everything they share lives in the [`State`], behind a named,
+    instrumented lock or in an atomic. `State` is checked to be `Send` and
+    `Sync` at compile time, so adding a field which isn't thread safe fails
+    to build instead of racing.
+
+    Locks are held only to read or update the state, never while calling
+    back the application or libindy. When two locks have to be held at once,
+    take them in the order of the fields below. Build with the
+    `deadlock_detection` feature to check it.
+
+    Not in the state: the services are immutable, and the field names
+    override of [`with_field_names`] is per thread.
+
+    Every context has a state of its own, [`state`] is the one of the
+    context of the calling thread, see `logic::context`.
+
+    [`State`]: struct.State.html
+    [`state`]: fn.state.html
+    [`with_field_names`]: ../field_names/fn.with_field_names.html
+*/
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+
+use logic::address_book::AddressRecord;
+use logic::config::library_config::LibraryConfig;
+use logic::context;
+use logic::fee_waiver::FeeSchedule;
+use logic::optimistic_updates::OptimisticUpdate;
+use logic::pools::Pools;
+use logic::registration::RegistrationStatus;
+use logic::stateless::SignerCallback;
+use logic::storage::MemoryStorage;
+use logic::utxo_cache::UtxoCache;
+use logic::wallet_handles::WalletUse;
+use utils::dead_letter::DeadLetterQueue;
+use utils::dispatcher::Event;
+use utils::ffi_support::BufferPool;
+use utils::locks::{InstrumentedMutex, InstrumentedRwLock, LockStats};
+use utils::metrics::Metric;
+use utils::random::{self, RngProvider};
+use utils::rate_limit::TokenBucket;
+use utils::results::ResultSender;
+use utils::serde_buffers::SerdeBufferCounters;
+
+/**
+    Everything the handlers share.
+*/
+pub struct State {
+    /// The library config.
+    pub config: InstrumentedRwLock<LibraryConfig>,
+    /// Whether the `SOVTOKEN_*` environment variables were applied to the config.
+    pub env_applied: AtomicBool,
+    /// The records of the open payment address searches, by search handle.
+    pub address_searches: InstrumentedMutex<HashMap<i32, VecDeque<AddressRecord>>>,
+    /// The last search handle given out.
+    pub last_search_handle: AtomicUsize,
+    /// The buffers callback strings are passed in.
+    pub buffer_pool: InstrumentedMutex<BufferPool>,
+    /// The counters of the buffers requests are serialized in, see `utils::serde_buffers`.
+    pub serde_buffers: SerdeBufferCounters,
+    /// The callbacks waiting for `sovtoken_poll_events`.
+    pub callback_queue: InstrumentedMutex<VecDeque<Event>>,
+    /// The sender to the callback thread, once it runs.
+    pub callback_thread: InstrumentedMutex<Option<Sender<Event>>>,
+    /// The metrics of the handlers, by name.
+    pub metrics: InstrumentedMutex<BTreeMap<String, Metric>>,
+    /// The next req_id of the sequential strategy.
+    pub next_req_id: AtomicUsize,
+    /// Held while `sovtoken_self_test` runs, so two runs don't mix their counts.
+    pub self_test: InstrumentedMutex<()>,
+    /// Callbacks of the running self test.
+    pub self_test_callbacks: AtomicUsize,
+    /// Callbacks of the running self test with an error.
+    pub self_test_failures: AtomicUsize,
+    /// The parsed GET_UTXO replies.
+    pub utxo_cache: InstrumentedMutex<UtxoCache>,
+    /// The records of the memory storage backend.
+    pub memory_storage: MemoryStorage,
+    /// The networks of the pool handles and their cached state.
+    pub pools: InstrumentedMutex<Pools>,
+    /// Where the callback of a `_sync` call sends its result, by command handle.
+    pub sync_calls: InstrumentedMutex<HashMap<i32, ResultSender<String>>>,
+    /// The last command handle of a `_sync` call.
+    pub last_sync_handle: AtomicUsize,
+    /// The fees of the GET_FEES replies parsed and of the schedules preloaded, by txn type.
+    pub fee_schedule: InstrumentedMutex<FeeSchedule>,
+    /// The inputs spent by the requests submitted without a pool, oldest first.
+    pub optimistic_updates: InstrumentedMutex<VecDeque<OptimisticUpdate>>,
+    /// Where the random data comes from, see `utils::random`.
+    pub rng: InstrumentedMutex<RngProvider>,
+    /// The wallet handles used, by handle.
+    pub wallet_handles: InstrumentedMutex<HashMap<i32, WalletUse>>,
+    /// The signer of the stateless mode, see `logic::stateless`.
+    pub signer: InstrumentedRwLock<Option<SignerCallback>>,
+    /// What came of the last registration with libindy, see `logic::registration`.
+    pub registration: InstrumentedMutex<Option<RegistrationStatus>>,
+    /// The calls left to the wallets, by operation type and wallet handle, see `utils::rate_limit`.
+    pub rate_limits: InstrumentedMutex<HashMap<(String, i32), TokenBucket>>,
+    /// The results which couldn't be delivered, see `utils::dead_letter`.
+    pub dead_letters: InstrumentedMutex<DeadLetterQueue>,
+    /// Held while the audit log file is written, see `logic::audit_log`.
+    pub audit_log: InstrumentedMutex<()>,
+    /// The wallet the caches are persisted in, see `logic::storage`.
+    pub cache_wallet: InstrumentedMutex<Option<i32>>,
+}
+
+impl State {
+    /**
+        A state with `config`, e.g. for a context, see `logic::context`.
+
+        The `SOVTOKEN_*` environment variables are taken as applied to the config.
+    */
+    pub fn with_config(config: LibraryConfig) -> Self {
+        let state = State::new();
+        *state.config.write() = config;
+        state.env_applied.store(true, Ordering::SeqCst);
+        state
+    }
+
+    fn new() -> Self {
+        State {
+            config: InstrumentedRwLock::new("config", LibraryConfig::default()),
+            env_applied: AtomicBool::new(false),
+            address_searches: InstrumentedMutex::new("address_searches", HashMap::new()),
+            last_search_handle: AtomicUsize::new(0),
+            buffer_pool: InstrumentedMutex::new("buffer_pool", BufferPool::new()),
+            serde_buffers: SerdeBufferCounters::new(),
+            callback_queue: InstrumentedMutex::new("callback_queue", VecDeque::new()),
+            callback_thread: InstrumentedMutex::new("callback_thread", None),
+            metrics: InstrumentedMutex::new("metrics", BTreeMap::new()),
+            next_req_id: AtomicUsize::new(random::first_sequential_req_id() as usize),
+            self_test: InstrumentedMutex::new("self_test", ()),
+            self_test_callbacks: AtomicUsize::new(0),
+            self_test_failures: AtomicUsize::new(0),
+            utxo_cache: InstrumentedMutex::new("utxo_cache", UtxoCache::new()),
+            memory_storage: MemoryStorage::new("memory_storage"),
+            pools: InstrumentedMutex::new("pools", Pools::new()),
+            sync_calls: InstrumentedMutex::new("sync_calls", HashMap::new()),
+            last_sync_handle: AtomicUsize::new(0),
+            fee_schedule: InstrumentedMutex::new("fee_schedule", FeeSchedule::new()),
+            optimistic_updates: InstrumentedMutex::new("optimistic_updates", VecDeque::new()),
+            rng: InstrumentedMutex::new("rng", RngProvider::Os),
+            wallet_handles: InstrumentedMutex::new("wallet_handles", HashMap::new()),
+            signer: InstrumentedRwLock::new("signer", None),
+            registration: InstrumentedMutex::new("registration", None),
+            rate_limits: InstrumentedMutex::new("rate_limits", HashMap::new()),
+            dead_letters: InstrumentedMutex::new("dead_letters", DeadLetterQueue::default()),
+            audit_log: InstrumentedMutex::new("audit_log", ()),
+            cache_wallet: InstrumentedMutex::new("cache_wallet", None),
+        }
+    }
+
+    /**
+        The counters of every lock, by name.
+    */
+    pub fn lock_stats(&self) -> BTreeMap<&'static str, LockStats> {
+        let mut stats = BTreeMap::new();
+        stats.insert(self.config.name(), self.config.stats());
+        stats.insert(self.address_searches.name(), self.address_searches.stats());
+        stats.insert(self.buffer_pool.name(), self.buffer_pool.stats());
+        stats.insert(self.callback_queue.name(), self.callback_queue.stats());
+        stats.insert(self.callback_thread.name(), self.callback_thread.stats());
+        stats.insert(self.metrics.name(), self.metrics.stats());
+        stats.insert(self.self_test.name(), self.self_test.stats());
+        stats.insert(self.utxo_cache.name(), self.utxo_cache.stats());
+        stats.insert(self.memory_storage.name(), self.memory_storage.stats());
+        stats.insert(self.pools.name(), self.pools.stats());
+        stats.insert(self.sync_calls.name(), self.sync_calls.stats());
+        stats.insert(self.fee_schedule.name(), self.fee_schedule.stats());
+        stats.insert(self.optimistic_updates.name(), self.optimistic_updates.stats());
+        stats.insert(self.rng.name(), self.rng.stats());
+        stats.insert(self.wallet_handles.name(), self.wallet_handles.stats());
+        stats.insert(self.signer.name(), self.signer.stats());
+        stats.insert(self.registration.name(), self.registration.stats());
+        stats.insert(self.rate_limits.name(), self.rate_limits.stats());
+        stats.insert(self.dead_letters.name(), self.dead_letters.stats());
+        stats.insert(self.audit_log.name(), self.audit_log.stats());
+        stats.insert(self.cache_wallet.name(), self.cache_wallet.stats());
+        stats
+    }
+}
+
+lazy_static! {
+    static ref STATE: Arc<State> = Arc::new(State::new());
+}
+
+/**
+    The state of the context of this thread, see `logic::context`.
+
+    Keep it in a variable to hold a guard of one of its locks.
+*/
+pub fn state() -> Arc<State> {
+    context::current().shared_state()
+}
+
+/**
+    The state of the default context, created on first use.
+*/
+pub fn default_state() -> Arc<State> {
+    Arc::clone(&STATE)
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// fails to build when a field of the state can't be shared between threads
+#[allow(dead_code)]
+fn state_is_thread_safe() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<State>();
+}
+
+
+#[cfg(test)]
+mod state_tests {
+    use super::*;
+
+    #[test]
+    fn every_lock_has_stats() {
+        let stats = state().lock_stats();
+        assert_eq!(21, stats.len());
+        assert!(stats.contains_key("config"));
+    }
+
+    #[test]
+    fn config_lock_is_counted() {
+        let before = state().config.stats().acquired;
+        let _ = state().config.read().max_inputs;
+        assert!(state().config.stats().acquired > before);
+    }
+}
@@ -0,0 +1,130 @@
+/*!
+    The stateless mode, for integrations keeping their keys outside of libindy.
+
+    With `stateless` set in the library config, the library is a request
+    codec: it builds, signs and parses requests without touching a wallet.
+
+    * The `wallet_handle` of the handlers is ignored, no wallet needs to be open.
+    * The inputs and proofs are signed by the [`SignerCallback`] the
+      application registered with `sovtoken_register_signer`.
+    * Keys aren't created, the address of a key kept outside is derived with
+      `create_payment_address_from_key_handler`, which signs with the signer.
+    * The records the library keeps in the wallet, e.g. the pending
+      transactions or the labels of the addresses, are kept in memory
+      instead, for the life of the context, at most `storage::MAX_MEMORY_RECORDS` of
+      them. The callers of a context share them, callers keeping their
+      records apart run in contexts of their own. No payment address is
+      listed.
+
+    [`SignerCallback`]: type.SignerCallback.html
+*/
+
+use indy::ErrorCode;
+use libc::c_char;
+
+use logic::config::library_config::library_config;
+use logic::error::SovtokenError;
+use logic::messages;
+use logic::state::state;
+use utils::base58::serialize_bytes;
+use utils::ffi_support::with_c_str;
+
+/**
+    The length in bytes of an Ed25519 signature.
+*/
+pub const SIGNATURE_LEN: usize = 64;
+
+/**
+    The wallet handle the records of the stateless mode are kept by, the
+    handles given to the handlers are ignored.
+*/
+pub const STATELESS_WALLET_HANDLE: i32 = 0;
+
+/**
+    Signs `message_len` bytes at `message_raw` with the key of the base58
+    `verkey`, writing the `SIGNATURE_LEN` bytes of the signature to
+    `signature_raw`. Returns 0 when it signed, any other value otherwise.
+
+    It is called on the thread of the handler, and may block.
+*/
+pub type SignerCallback = extern fn(verkey: *const c_char, message_raw: *const u8, message_len: u32, signature_raw: *mut u8) -> i32;
+
+/**
+    Whether the library runs stateless.
+*/
+pub fn is_stateless() -> bool {
+    library_config().stateless
+}
+
+/**
+    Register the signer of the stateless mode, `None` to remove it.
+*/
+pub fn register_signer(signer: Option<SignerCallback>) {
+    debug!("Registering the signer >>> {}", signer.is_some());
+    *state().signer.write() = signer;
+}
+
+/**
+    Sign `message` with the registered signer, returns the base58 signature.
+
+    Fails with `PaymentOperationNotSupportedError` when no signer is
+    registered and with `CommonInvalidState` when the signer fails.
+*/
+pub fn sign(verkey: &str, message: &[u8]) -> Result<String, ErrorCode> {
+    let signer = match *state().signer.read() {
+        Some(signer) => signer,
+        None => return Err(ErrorCode::from(SovtokenError::OperationNotSupported(messages::NO_SIGNER.message()))),
+    };
+
+    let mut signature = [0u8; SIGNATURE_LEN];
+    let result = with_c_str(verkey, |verkey| signer(verkey, message.as_ptr(), message.len() as u32, signature.as_mut_ptr()));
+    if result != 0 {
+        return Err(ErrorCode::from(SovtokenError::InvalidState(messages::SIGNER_FAILED.with(&[verkey.to_string(), result.to_string()]))));
+    }
+    Ok(serialize_bytes(&signature))
+}
+
+/**
+    The error of creating a key in the stateless mode, which has no wallet
+    to create it in.
+*/
+pub fn no_key_creation() -> ErrorCode {
+    ErrorCode::from(SovtokenError::OperationNotSupported(messages::STATELESS_KEY_CREATION.message()))
+}
+
+
+#[cfg(test)]
+mod stateless_tests {
+    use std::ffi::CStr;
+    use std::slice;
+
+    use utils::base58::{decode, Checksum};
+    use super::*;
+
+    // signs with the first byte of the message, fails for the verkey "unknown"
+    extern fn test_signer(verkey: *const c_char, message_raw: *const u8, message_len: u32, signature_raw: *mut u8) -> i32 {
+        let verkey = unsafe { CStr::from_ptr(verkey) }.to_str().unwrap();
+        if verkey == "unknown" {
+            return 7;
+        }
+        let message = unsafe { slice::from_raw_parts(message_raw, message_len as usize) };
+        let signature = unsafe { slice::from_raw_parts_mut(signature_raw, SIGNATURE_LEN) };
+        for byte in signature.iter_mut() {
+            *byte = message[0];
+        }
+        0
+    }
+
+    #[test]
+    fn sign_with_the_signer() {
+        register_signer(None);
+        assert_eq!(ErrorCode::PaymentOperationNotSupportedError, sign("verkey", b"message").unwrap_err());
+
+        register_signer(Some(test_signer));
+        let signature = decode(&sign("verkey", b"message").unwrap(), Checksum::None).unwrap();
+        assert_eq!(vec![b'm'; SIGNATURE_LEN], signature);
+        assert_eq!(ErrorCode::CommonInvalidState, sign("unknown", b"message").unwrap_err());
+
+        register_signer(None);
+    }
+}
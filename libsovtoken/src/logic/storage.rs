@@ -0,0 +1,612 @@
+/*!
+    Where libsovtoken keeps its caches and stores.
+
+    The pending transactions, the extras and the persisted caches of
+    GET_UTXO replies and fee schedules are written through a
+    [`StorageBackend`], chosen with `storage` in the library config:
+
+    * `wallet`, the default: non secret records of the indy wallet.
+    * `memory`: a map of the process, lost when it exits.
+    * `file`: one file per record under `storage_path`, which it requires.
+
+    The records of the memory backend are kept by wallet handle, like the
+    wallet. The file backend keeps the records of each wallet in a directory
+    of its own, named by a random partition id stored in the wallet, so the
+    records of a wallet are found again after a restart, when its handle
+    changed. A library config with the file backend and no `storage_path` is
+    rejected, and a store of such a config fails every call with
+    `CommonInvalidState` rather than keeping the records elsewhere.
+
+    The caches are filled by replies parsed without a wallet handle. They are
+    persisted in the wallet set with `set_cache_wallet_handler`, see
+    [`cache_storage`], and without one as records of no wallet: apart from
+    the wallets by the memory backend, in the shared partition by the file
+    backend. The wallet backend has no wallet to keep them in, they are then
+    kept in memory only.
+
+    [`StorageBackend`]: trait.StorageBackend.html
+    [`cache_storage`]: fn.cache_storage.html
+*/
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use hex::{FromHex, ToHex};
+use indy::{IndyHandle, ErrorCode};
+
+use logic::config::library_config::library_config;
+use logic::indy_sdk_api::wallet_api::{WalletAPI, WalletSdk};
+use logic::services::services;
+use logic::state::{state, State};
+use utils::locks::{InstrumentedMutex, LockStats};
+use utils::random::rand_bytes;
+
+/**
+    The backend selected in the library config, serialized in lowercase.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    Wallet,
+    Memory,
+    File,
+}
+
+impl Default for StorageKind {
+    fn default() -> Self {
+        StorageKind::Wallet
+    }
+}
+
+/**
+    The record type of the partition id of a wallet in the file backend.
+*/
+pub const PARTITION_RECORD_TYPE: &str = "sovtoken_storage_partition";
+
+/**
+    The partition of the records of the file backend which belong to no
+    wallet, e.g. the persisted UTXO cache.
+*/
+pub const SHARED_PARTITION: &str = "shared";
+
+/**
+    The wallet handle of the records which belong to no wallet.
+*/
+pub const NO_WALLET: IndyHandle = 0;
+
+// the id of the partition record, a wallet has one partition
+const PARTITION_RECORD_ID: &str = "partition";
+
+/**
+    Records of a type, by id.
+
+    Fails like the non secret records of the wallet: adding an existing
+    record with `WalletItemAlreadyExists`, and reading, updating or deleting
+    a missing one with `WalletItemNotFound`.
+*/
+pub trait StorageBackend {
+    fn add_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode>;
+    fn update_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode>;
+    fn get_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<String, ErrorCode>;
+    fn delete_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<(), ErrorCode>;
+    fn list_records(&self, wallet_handle: IndyHandle, xtype: &str) -> Result<Vec<(String, String)>, ErrorCode>;
+}
+
+/**
+    The wallet backend, any implementation of the wallet api stores records.
+*/
+impl<W: WalletAPI> StorageBackend for W {
+    fn add_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        self.indy_add_record(wallet_handle, xtype, id, value)
+    }
+
+    fn update_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        self.indy_update_record_value(wallet_handle, xtype, id, value)
+    }
+
+    fn get_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<String, ErrorCode> {
+        self.indy_get_record_value(wallet_handle, xtype, id)
+    }
+
+    fn delete_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<(), ErrorCode> {
+        self.indy_delete_record(wallet_handle, xtype, id)
+    }
+
+    fn list_records(&self, wallet_handle: IndyHandle, xtype: &str) -> Result<Vec<(String, String)>, ErrorCode> {
+        self.indy_list_records(wallet_handle, xtype)
+    }
+}
+
+/**
+    The most records the memory backend keeps for a wallet handle. The
+    stateless mode keeps the records of every caller under one handle.
+*/
+pub const MAX_MEMORY_RECORDS: usize = 10_000;
+
+/**
+    Records in a map of the process, at most `MAX_MEMORY_RECORDS` by wallet
+    handle. Adding a record to a full wallet fails with `CommonInvalidState`,
+    nothing is evicted.
+*/
+pub struct MemoryStorage {
+    records: InstrumentedMutex<HashMap<IndyHandle, HashMap<(String, String), String>>>,
+}
+
+impl MemoryStorage {
+    pub fn new(name: &'static str) -> Self {
+        MemoryStorage { records: InstrumentedMutex::new(name, HashMap::new()) }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.records.name()
+    }
+
+    pub fn stats(&self) -> LockStats {
+        self.records.stats()
+    }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn add_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        let mut records = self.records.lock();
+        let wallet = records.entry(wallet_handle).or_insert_with(HashMap::new);
+        let key = (xtype.to_string(), id.to_string());
+        if wallet.contains_key(&key) {
+            return Err(ErrorCode::WalletItemAlreadyExists);
+        }
+        if wallet.len() >= MAX_MEMORY_RECORDS {
+            error!("The memory storage of wallet {} is full, {} records", wallet_handle, MAX_MEMORY_RECORDS);
+            return Err(ErrorCode::CommonInvalidState);
+        }
+        wallet.insert(key, value.to_string());
+        Ok(())
+    }
+
+    fn update_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        let mut records = self.records.lock();
+        let record = records.get_mut(&wallet_handle)
+            .and_then(|wallet| wallet.get_mut(&(xtype.to_string(), id.to_string())))
+            .ok_or(ErrorCode::WalletItemNotFound)?;
+        *record = value.to_string();
+        Ok(())
+    }
+
+    fn get_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<String, ErrorCode> {
+        self.records.lock()
+            .get(&wallet_handle)
+            .and_then(|wallet| wallet.get(&(xtype.to_string(), id.to_string())))
+            .cloned()
+            .ok_or(ErrorCode::WalletItemNotFound)
+    }
+
+    fn delete_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<(), ErrorCode> {
+        let mut records = self.records.lock();
+        let deleted = records.get_mut(&wallet_handle)
+            .and_then(|wallet| wallet.remove(&(xtype.to_string(), id.to_string())))
+            .map(|_| ())
+            .ok_or(ErrorCode::WalletItemNotFound);
+
+        if records.get(&wallet_handle).map_or(false, HashMap::is_empty) {
+            records.remove(&wallet_handle);
+        }
+        deleted
+    }
+
+    fn list_records(&self, wallet_handle: IndyHandle, xtype: &str) -> Result<Vec<(String, String)>, ErrorCode> {
+        Ok(self.records.lock()
+            .get(&wallet_handle)
+            .map(|wallet| wallet.iter()
+                .filter(|&(&(ref record_type, _), _)| record_type == xtype)
+                .map(|(&(_, ref id), value)| (id.clone(), value.clone()))
+                .collect())
+            .unwrap_or_default())
+    }
+}
+
+/**
+    Records in files, `<dir>/<type>/<hex of the id>`, whatever the wallet.
+
+    A record is written to a temporary file first and renamed, so a crash
+    never leaves half a record.
+*/
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> FileStorage {
+        FileStorage { dir: dir.into() }
+    }
+
+    /**
+        The records of `partition`, in the directory of that name.
+    */
+    pub fn partition(&self, partition: &str) -> FileStorage {
+        FileStorage { dir: self.dir.join(partition) }
+    }
+
+    /* Methods "private" (aka not exported from this module)
+
+        KEEP all public methods above
+    */
+
+    fn path(&self, xtype: &str, id: &str) -> PathBuf {
+        self.dir.join(xtype).join(id.as_bytes().to_hex())
+    }
+
+    fn write(&self, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        let path = self.path(xtype, id);
+        let temp = path.with_extension("tmp");
+
+        fs::create_dir_all(self.dir.join(xtype))
+            .and_then(|_| File::create(&temp))
+            .and_then(|mut file| file.write_all(value.as_bytes()).and_then(|_| file.sync_all()))
+            .and_then(|_| fs::rename(&temp, &path))
+            .map_err(map_err_err!())
+            .or(Err(ErrorCode::CommonIOError))
+    }
+}
+
+impl StorageBackend for FileStorage {
+    fn add_record(&self, _: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        if self.path(xtype, id).exists() {
+            return Err(ErrorCode::WalletItemAlreadyExists);
+        }
+        self.write(xtype, id, value)
+    }
+
+    fn update_record(&self, _: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        if !self.path(xtype, id).exists() {
+            return Err(ErrorCode::WalletItemNotFound);
+        }
+        self.write(xtype, id, value)
+    }
+
+    fn get_record(&self, _: IndyHandle, xtype: &str, id: &str) -> Result<String, ErrorCode> {
+        let mut value = String::new();
+        match OpenOptions::new().read(true).open(self.path(xtype, id)) {
+            Ok(mut file) => file.read_to_string(&mut value)
+                .map_err(map_err_err!())
+                .or(Err(ErrorCode::CommonIOError))?,
+            Err(ref error) if error.kind() == ErrorKind::NotFound => return Err(ErrorCode::WalletItemNotFound),
+            Err(error) => {
+                error!("Failed to read the {} record {}: {}", xtype, id, error);
+                return Err(ErrorCode::CommonIOError);
+            },
+        };
+        Ok(value)
+    }
+
+    fn delete_record(&self, _: IndyHandle, xtype: &str, id: &str) -> Result<(), ErrorCode> {
+        match fs::remove_file(self.path(xtype, id)) {
+            Ok(()) => Ok(()),
+            Err(ref error) if error.kind() == ErrorKind::NotFound => Err(ErrorCode::WalletItemNotFound),
+            Err(error) => {
+                error!("Failed to delete the {} record {}: {}", xtype, id, error);
+                Err(ErrorCode::CommonIOError)
+            },
+        }
+    }
+
+    fn list_records(&self, wallet_handle: IndyHandle, xtype: &str) -> Result<Vec<(String, String)>, ErrorCode> {
+        let entries = match fs::read_dir(self.dir.join(xtype)) {
+            Ok(entries) => entries,
+            Err(ref error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => {
+                error!("Failed to list the {} records: {}", xtype, error);
+                return Err(ErrorCode::CommonIOError);
+            },
+        };
+
+        let mut records = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(map_err_err!()).or(Err(ErrorCode::CommonIOError))?;
+            let id = entry.file_name().to_str()
+                .and_then(|name| Vec::<u8>::from_hex(name).ok())
+                .and_then(|id| String::from_utf8(id).ok());
+
+            // skips the temporary files of interrupted writes
+            if let Some(id) = id {
+                let value = self.get_record(wallet_handle, xtype, &id)?;
+                records.push((id, value));
+            }
+        }
+        Ok(records)
+    }
+}
+
+/**
+    One of the backends, as selected in the library config.
+*/
+pub enum Storage<W> where W: WalletAPI {
+    Wallet(W),
+    /// The records of the memory backend are in the state of the context.
+    Memory(Arc<State>),
+    /// The records of a wallet are in its partition, whose id is read from the wallet.
+    File(FileStorage, W),
+    /// The file backend without a `storage_path`, every call fails.
+    Misconfigured,
+}
+
+impl<W: WalletAPI> Storage<W> {
+    /**
+        The backend of the library config, `wallet_api` for the wallet
+        backend and for the partition ids of the file backend.
+
+        The file backend without a `storage_path` is logged, and every call
+        of the store fails with `CommonInvalidState`.
+    */
+    pub fn configured(wallet_api: W) -> Storage<W> {
+        let config = library_config();
+        match (config.storage, config.storage_path) {
+            (StorageKind::Wallet, _) => Storage::Wallet(wallet_api),
+            (StorageKind::Memory, _) => Storage::Memory(state()),
+            (StorageKind::File, Some(path)) => Storage::File(FileStorage::new(path), wallet_api),
+            (StorageKind::File, None) => {
+                error!("The file storage needs a storage_path, no record can be stored");
+                Storage::Misconfigured
+            },
+        }
+    }
+
+    /* Methods "private" (aka not exported from this module)
+
+        KEEP all public methods above
+    */
+
+    fn with_backend<F, T>(&self, wallet_handle: IndyHandle, f: F) -> Result<T, ErrorCode>
+        where F: FnOnce(&StorageBackend) -> Result<T, ErrorCode>
+    {
+        match *self {
+            Storage::Wallet(_) if wallet_handle == NO_WALLET => {
+                error!("The wallet storage can't keep records of no wallet");
+                Err(ErrorCode::WalletInvalidHandle)
+            },
+            Storage::Wallet(ref wallet_api) => f(wallet_api),
+            Storage::Memory(ref state) => f(&state.memory_storage),
+            Storage::File(ref file, _) if wallet_handle == NO_WALLET => f(&file.partition(SHARED_PARTITION)),
+            Storage::File(ref file, ref wallet_api) => f(&file.partition(&partition_of(wallet_api, wallet_handle)?)),
+            Storage::Misconfigured => {
+                error!("The file storage needs a storage_path");
+                Err(ErrorCode::CommonInvalidState)
+            },
+        }
+    }
+}
+
+impl<W: WalletAPI> StorageBackend for Storage<W> {
+    fn add_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        self.with_backend(wallet_handle, |backend| backend.add_record(wallet_handle, xtype, id, value))
+    }
+
+    fn update_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        self.with_backend(wallet_handle, |backend| backend.update_record(wallet_handle, xtype, id, value))
+    }
+
+    fn get_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<String, ErrorCode> {
+        self.with_backend(wallet_handle, |backend| backend.get_record(wallet_handle, xtype, id))
+    }
+
+    fn delete_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<(), ErrorCode> {
+        self.with_backend(wallet_handle, |backend| backend.delete_record(wallet_handle, xtype, id))
+    }
+
+    fn list_records(&self, wallet_handle: IndyHandle, xtype: &str) -> Result<Vec<(String, String)>, ErrorCode> {
+        self.with_backend(wallet_handle, |backend| backend.list_records(wallet_handle, xtype))
+    }
+}
+
+/**
+    The backend of the library config the caches are persisted in, with the
+    wallet handle they are kept under: the cache wallet, else [`NO_WALLET`].
+
+    `None` for the wallet backend without a cache wallet and for a
+    misconfigured backend, the caches are then kept in memory only.
+
+    [`NO_WALLET`]: constant.NO_WALLET.html
+*/
+pub fn cache_storage() -> Option<(Storage<&'static WalletSdk>, IndyHandle)> {
+    let wallet_handle = {
+        let state = state();
+        let cache_wallet = *state.cache_wallet.lock();
+        cache_wallet.unwrap_or(NO_WALLET)
+    };
+
+    match Storage::configured(&services().wallet) {
+        Storage::Wallet(_) if wallet_handle == NO_WALLET => None,
+        Storage::Misconfigured => None,
+        storage => Some((storage, wallet_handle)),
+    }
+}
+
+/**
+    Persist the caches in the wallet of `wallet_handle`, or as records of
+    no wallet for `None`.
+*/
+pub fn set_cache_wallet(wallet_handle: Option<IndyHandle>) {
+    let state = state();
+    *state.cache_wallet.lock() = wallet_handle;
+}
+
+/**
+    Stop persisting the caches in the wallet of `wallet_handle` once it is closed.
+*/
+pub fn forget_cache_wallet(wallet_handle: IndyHandle) {
+    let state = state();
+    let mut cache_wallet = state.cache_wallet.lock();
+    if *cache_wallet == Some(wallet_handle) {
+        *cache_wallet = None;
+    }
+}
+
+// The partition id of the wallet of `wallet_handle`, stored in the wallet on first use.
+fn partition_of<W: WalletAPI>(wallet_api: &W, wallet_handle: IndyHandle) -> Result<String, ErrorCode> {
+    match wallet_api.indy_get_record_value(wallet_handle, PARTITION_RECORD_TYPE, PARTITION_RECORD_ID) {
+        Err(ErrorCode::WalletItemNotFound) => (),
+        partition => return partition,
+    }
+
+    let partition = rand_bytes(16).to_hex();
+    match wallet_api.indy_add_record(wallet_handle, PARTITION_RECORD_TYPE, PARTITION_RECORD_ID, &partition) {
+        Ok(()) => {
+            info!("The file storage keeps the records of wallet {} in the partition {}", wallet_handle, partition);
+            Ok(partition)
+        },
+        // another call stored one first
+        Err(ErrorCode::WalletItemAlreadyExists) => wallet_api.indy_get_record_value(wallet_handle, PARTITION_RECORD_TYPE, PARTITION_RECORD_ID),
+        Err(ec) => Err(ec),
+    }
+}
+
+#[cfg(test)]
+mod storage_tests {
+    use std::env;
+
+    use logic::config::library_config::LibraryConfig;
+    use utils::random::rand_string;
+    use utils::test::context::in_own_context;
+
+    use super::*;
+
+    static WALLET_HANDLE: i32 = 1;
+    static XTYPE: &'static str = "sovtoken_test";
+
+    // a wallet whose records are kept by wallet handle
+    struct MemoryWallet(MemoryStorage);
+
+    impl WalletAPI for MemoryWallet {
+        fn indy_add_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+            self.0.add_record(wallet_handle, xtype, id, value)
+        }
+
+        fn indy_update_record_value(&self, wallet_handle: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+            self.0.update_record(wallet_handle, xtype, id, value)
+        }
+
+        fn indy_get_record_value(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<String, ErrorCode> {
+            self.0.get_record(wallet_handle, xtype, id)
+        }
+
+        fn indy_delete_record(&self, wallet_handle: IndyHandle, xtype: &str, id: &str) -> Result<(), ErrorCode> {
+            self.0.delete_record(wallet_handle, xtype, id)
+        }
+
+        fn indy_list_records(&self, wallet_handle: IndyHandle, xtype: &str) -> Result<Vec<(String, String)>, ErrorCode> {
+            self.0.list_records(wallet_handle, xtype)
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        env::temp_dir().join(format!("sovtoken_storage_{}", rand_string(8)))
+    }
+
+    fn check_backend<S: StorageBackend>(storage: &S) {
+        storage.add_record(WALLET_HANDLE, XTYPE, "txo:sov:1", "one").unwrap();
+        assert_eq!(ErrorCode::WalletItemAlreadyExists, storage.add_record(WALLET_HANDLE, XTYPE, "txo:sov:1", "one").unwrap_err());
+        assert_eq!("one", storage.get_record(WALLET_HANDLE, XTYPE, "txo:sov:1").unwrap());
+
+        storage.update_record(WALLET_HANDLE, XTYPE, "txo:sov:1", "two").unwrap();
+        assert_eq!("two", storage.get_record(WALLET_HANDLE, XTYPE, "txo:sov:1").unwrap());
+        assert_eq!(ErrorCode::WalletItemNotFound, storage.update_record(WALLET_HANDLE, XTYPE, "missing", "two").unwrap_err());
+
+        assert_eq!(vec![(String::from("txo:sov:1"), String::from("two"))], storage.list_records(WALLET_HANDLE, XTYPE).unwrap());
+        assert!(storage.list_records(WALLET_HANDLE, "sovtoken_other").unwrap().is_empty());
+
+        storage.delete_record(WALLET_HANDLE, XTYPE, "txo:sov:1").unwrap();
+        assert_eq!(ErrorCode::WalletItemNotFound, storage.get_record(WALLET_HANDLE, XTYPE, "txo:sov:1").unwrap_err());
+        assert_eq!(ErrorCode::WalletItemNotFound, storage.delete_record(WALLET_HANDLE, XTYPE, "txo:sov:1").unwrap_err());
+    }
+
+    #[test]
+    fn memory_backend() {
+        check_backend(&MemoryStorage::new("storage_tests::memory"));
+    }
+
+    #[test]
+    fn memory_backend_keeps_wallets_apart() {
+        let storage = MemoryStorage::new("storage_tests::wallets");
+        storage.add_record(WALLET_HANDLE, XTYPE, "1", "one").unwrap();
+        assert!(storage.list_records(WALLET_HANDLE + 1, XTYPE).unwrap().is_empty());
+    }
+
+    #[test]
+    fn memory_backend_is_bounded() {
+        let storage = MemoryStorage::new("storage_tests::bounded");
+        for i in 0..MAX_MEMORY_RECORDS {
+            storage.add_record(WALLET_HANDLE, XTYPE, &i.to_string(), "").unwrap();
+        }
+
+        assert_eq!(ErrorCode::CommonInvalidState, storage.add_record(WALLET_HANDLE, XTYPE, "full", "").unwrap_err());
+        storage.add_record(WALLET_HANDLE + 1, XTYPE, "full", "").unwrap();
+
+        storage.delete_record(WALLET_HANDLE, XTYPE, "0").unwrap();
+        storage.add_record(WALLET_HANDLE, XTYPE, "full", "").unwrap();
+    }
+
+    #[test]
+    fn file_backend() {
+        let dir = temp_dir();
+        check_backend(&FileStorage::new(dir.clone()));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn file_backend_keeps_wallets_apart() {
+        let dir = temp_dir();
+        let wallet_api = MemoryWallet(MemoryStorage::new("storage_tests::partitions"));
+        let storage = Storage::File(FileStorage::new(dir.clone()), &wallet_api);
+        storage.add_record(WALLET_HANDLE, XTYPE, "1", "one").unwrap();
+        assert!(storage.list_records(WALLET_HANDLE + 1, XTYPE).unwrap().is_empty());
+
+        // the partition is found again by the next store of the wallet
+        let reopened = Storage::File(FileStorage::new(dir.clone()), &wallet_api);
+        assert_eq!("one", reopened.get_record(WALLET_HANDLE, XTYPE, "1").unwrap());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn misconfigured_backend_fails() {
+        let storage: Storage<MemoryWallet> = Storage::Misconfigured;
+
+        assert_eq!(ErrorCode::CommonInvalidState, storage.add_record(WALLET_HANDLE, XTYPE, "1", "one").unwrap_err());
+        assert_eq!(ErrorCode::CommonInvalidState, storage.list_records(WALLET_HANDLE, XTYPE).unwrap_err());
+    }
+
+    #[test]
+    fn records_of_no_wallet() {
+        let dir = temp_dir();
+        let wallet_api = MemoryWallet(MemoryStorage::new("storage_tests::no_wallet"));
+        let storage = Storage::File(FileStorage::new(dir.clone()), &wallet_api);
+        storage.add_record(NO_WALLET, XTYPE, "1", "one").unwrap();
+        assert_eq!("one", FileStorage::new(dir.join(SHARED_PARTITION)).get_record(NO_WALLET, XTYPE, "1").unwrap());
+
+        let storage = Storage::Wallet(&wallet_api);
+        assert_eq!(ErrorCode::WalletInvalidHandle, storage.add_record(NO_WALLET, XTYPE, "1", "one").unwrap_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn cache_wallet_is_forgotten_when_closed() {
+        in_own_context(LibraryConfig::default(), || {
+            assert!(cache_storage().is_none());
+
+            set_cache_wallet(Some(WALLET_HANDLE));
+            forget_cache_wallet(WALLET_HANDLE + 1);
+            assert_eq!(Some(WALLET_HANDLE), cache_storage().map(|(_, wallet_handle)| wallet_handle));
+
+            forget_cache_wallet(WALLET_HANDLE);
+            assert!(cache_storage().is_none());
+        });
+    }
+
+    #[test]
+    fn file_backend_survives_reopening() {
+        let dir = temp_dir();
+        FileStorage::new(dir.clone()).add_record(WALLET_HANDLE, XTYPE, "1", "one").unwrap();
+        assert_eq!("one", FileStorage::new(dir.clone()).get_record(WALLET_HANDLE, XTYPE, "1").unwrap());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,95 @@
+/*!
+    The most inputs and outputs one XFER request can have.
+
+    The ledger plugin rejects transfers with more inputs or outputs than its
+    limits, the limits used here are `max_inputs` and `max_outputs` of the
+    [`LibraryConfig`]. They should match the ledger the library talks to.
+
+    Payments are checked before they are signed, and the batch and split
+    helpers plan their requests within the same limits.
+
+    [`LibraryConfig`]: ../config/library_config/struct.LibraryConfig.html
+*/
+
+use logic::config::library_config::library_config;
+use logic::error::SovtokenError;
+use logic::messages;
+
+/**
+    The default maximum number of inputs of an XFER request.
+*/
+pub const DEFAULT_MAX_INPUTS: usize = 100;
+
+/**
+    The default maximum number of outputs of an XFER request, change included.
+*/
+pub const DEFAULT_MAX_OUTPUTS: usize = 100;
+
+/**
+    Limits on the size of a transfer.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferLimits {
+    pub max_inputs: usize,
+    pub max_outputs: usize,
+}
+
+impl Default for TransferLimits {
+    fn default() -> Self {
+        TransferLimits { max_inputs: DEFAULT_MAX_INPUTS, max_outputs: DEFAULT_MAX_OUTPUTS }
+    }
+}
+
+impl TransferLimits {
+    /**
+        The limits set in the library config.
+    */
+    pub fn configured() -> TransferLimits {
+        let config = library_config();
+        TransferLimits { max_inputs: config.max_inputs, max_outputs: config.max_outputs }
+    }
+
+    /**
+        Check a transfer with `inputs` inputs and `outputs` outputs is within the limits.
+    */
+    pub fn check(&self, inputs: usize, outputs: usize) -> Result<(), SovtokenError> {
+        if inputs > self.max_inputs {
+            return Err(SovtokenError::InvalidStructure(messages::TOO_MANY_INPUTS.with(&[
+                self.max_inputs.to_string(), inputs.to_string()
+            ])));
+        }
+
+        if outputs > self.max_outputs {
+            return Err(SovtokenError::InvalidStructure(messages::TOO_MANY_OUTPUTS.with(&[
+                self.max_outputs.to_string(), outputs.to_string()
+            ])));
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod transfer_limits_tests {
+    use indy::ErrorCode;
+    use super::*;
+
+    #[test]
+    fn within_limits() {
+        let limits = TransferLimits { max_inputs: 2, max_outputs: 3 };
+        assert!(limits.check(2, 3).is_ok());
+    }
+
+    #[test]
+    fn too_many_inputs_or_outputs() {
+        let limits = TransferLimits { max_inputs: 2, max_outputs: 3 };
+        assert_eq!(ErrorCode::CommonInvalidStructure, limits.check(3, 1).unwrap_err().error_code());
+        assert_eq!(ErrorCode::CommonInvalidStructure, limits.check(1, 4).unwrap_err().error_code());
+    }
+
+    #[test]
+    fn default_limits() {
+        assert_eq!(TransferLimits { max_inputs: DEFAULT_MAX_INPUTS, max_outputs: DEFAULT_MAX_OUTPUTS }, TransferLimits::default());
+    }
+}
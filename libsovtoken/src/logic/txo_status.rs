@@ -0,0 +1,255 @@
+/*!
+    Whether a txo is still unspent.
+
+    The ledger has no query for a single txo. The UTXOs of the address of the
+    txo are fetched with a GET_UTXO and the txo is unspent when it is one of
+    them. The ledger doesn't say which transaction spent a txo either: it is
+    looked up in the pending transactions of the wallet, along with the seqNo
+    the ledger gave it when it was confirmed.
+
+    The wallet is read before the GET_UTXO is submitted, so nothing calls
+    into libindy from the callback of the reply.
+*/
+
+use indy::{IndyHandle, ErrorCode};
+
+use logic::address::unqualified_part;
+use logic::indy_sdk_api::payment_api::PaymentAPI;
+use logic::indy_sdk_api::wallet_api::WalletAPI;
+use logic::parsers::common::TXO;
+use logic::pending_txn::{PendingState, PendingTxnStore};
+use logic::storage::Storage;
+use logic::type_aliases::{TokenAmount, TxnSeqNo};
+
+/**
+    The pending transaction of the wallet which has a txo as input.
+
+    `seq_no` is `None` until the transaction is confirmed, and for
+    transactions confirmed by reconciling instead of by their reply.
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SpendingTxn {
+    pub txn_id: String,
+    pub state: PendingState,
+    pub seq_no: Option<TxnSeqNo>,
+}
+
+/**
+    Whether a txo is spent.
+
+    ```text
+    {
+        "txo": <string>,
+        "address": <string>,
+        "seqNo": <int>,
+        "spent": <bool>,
+        "amount": <int>,    // null when spent
+        "spentBy": {        // null when unspent or not spent by this wallet
+            "txnId": <string>,
+            "state": <string>,
+            "seqNo": <int>  // null when not known
+        }
+    }
+    ```
+*/
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TxoStatus {
+    pub txo: String,
+    pub address: String,
+    pub seq_no: TxnSeqNo,
+    pub spent: bool,
+    pub amount: Option<TokenAmount>,
+    pub spent_by: Option<SpendingTxn>,
+}
+
+/**
+    TxoStatusChecker checks txos against the ledger and the wallet.
+*/
+pub struct TxoStatusChecker<W, P> where W: WalletAPI, P: PaymentAPI {
+    wallet_api: W,
+    payment_api: P,
+}
+
+impl<W: WalletAPI, P: PaymentAPI> TxoStatusChecker<W, P> {
+    pub fn new(wallet_api: W, payment_api: P) -> Self {
+        TxoStatusChecker { wallet_api, payment_api }
+    }
+
+    /**
+        Check whether `txo` is still unspent, calls `cb` with its status.
+
+        A spent txo is matched with the pending transaction spending it,
+        rejected transactions left out. A confirmed transaction is preferred
+        over one still in flight.
+
+        Fails with `CommonInvalidStructure` when `txo` is invalid and with
+        the errors of reading the wallet, `cb` is called with the errors of
+        fetching the UTXOs.
+    */
+    pub fn check<F>(&self, wallet_handle: IndyHandle, pool_handle: IndyHandle, txo: &str, mut cb: F) -> Result<(), ErrorCode>
+        where F: FnMut(Result<TxoStatus, ErrorCode>) + 'static + Send
+    {
+        trace!("logic::txo_status::check >> txo: {:?}", txo);
+        let parsed = TXO::from_libindy_string(txo).map_err(ErrorCode::from)?;
+        let key = (unqualified_part(&parsed.address).to_string(), parsed.seq_no);
+        let spending = self.spending_txn(wallet_handle, &key)?;
+
+        let txo = txo.to_string();
+        let address = parsed.address.clone();
+        let ec = self.payment_api.indy_get_utxos_async(pool_handle, &parsed.address, move |utxos| {
+            let status = utxos.map(|utxos| {
+                let amount = utxos.iter()
+                    .find(|utxo| {
+                        TXO::from_libindy_string(&utxo.source)
+                            .map(|source| unqualified_part(&source.address) == key.0 && source.seq_no == key.1)
+                            .unwrap_or(false)
+                    })
+                    .map(|utxo| utxo.amount);
+
+                TxoStatus {
+                    txo: txo.clone(),
+                    address: address.clone(),
+                    seq_no: key.1,
+                    spent: amount.is_none(),
+                    amount,
+                    spent_by: amount.map_or_else(|| spending.clone(), |_| None),
+                }
+            });
+            trace!("logic::txo_status::check << status: {:?}", status);
+            cb(status);
+        });
+        match ec {
+            ErrorCode::Success => Ok(()),
+            ec => Err(ec),
+        }
+    }
+
+    // The pending transaction with the input `key`.
+    fn spending_txn(&self, wallet_handle: IndyHandle, key: &(String, TxnSeqNo)) -> Result<Option<SpendingTxn>, ErrorCode> {
+        let txn_store = PendingTxnStore::new(Storage::configured(&self.wallet_api));
+        let mut spending: Option<SpendingTxn> = None;
+
+        for (txn_id, txn) in txn_store.list(wallet_handle)? {
+            if txn.state == PendingState::Rejected || !txn.inputs()?.contains(key) {
+                continue;
+            }
+
+            let better = match spending {
+                Some(ref found) => found.state != PendingState::Confirmed && txn.state == PendingState::Confirmed,
+                None => true,
+            };
+            if better {
+                spending = Some(SpendingTxn { txn_id, state: txn.state, seq_no: txn.seq_no });
+            }
+        }
+
+        Ok(spending)
+    }
+}
+
+
+#[cfg(test)]
+mod txo_status_tests {
+    use std::sync::{Arc, Mutex};
+
+    use logic::parsers::parse_get_utxo_response::{ParseGetUtxoReply, UTXO};
+    use utils::test::wallet::WalletMockHandler;
+    use super::*;
+
+    static WALLET_HANDLE: i32 = 1;
+    static POOL_HANDLE: i32 = 2;
+    static ADDRESS: &'static str = "pay:sov:E9LNHk8shQ6xe2RfydzXDSsyhWC6vJaUeKE2mmc6mWraDfmKm";
+
+    // the ledger holds the UTXOs with these seqNos
+    struct PaymentMockHandler {
+        unspent: Vec<TxnSeqNo>,
+    }
+
+    impl PaymentAPI for PaymentMockHandler {
+        fn indy_list_payment_addresses(&self, _: IndyHandle) -> Result<Vec<String>, ErrorCode> {
+            Ok(vec![ADDRESS.to_string()])
+        }
+
+        fn indy_get_utxos(&self, _: IndyHandle, address: &str) -> Result<ParseGetUtxoReply, ErrorCode> {
+            Ok(self.unspent.iter().map(|seq_no| UTXO {
+                payment_address: address.to_string(),
+                source: source(*seq_no),
+                amount: 10,
+                extra: "".to_string(),
+            }).collect())
+        }
+    }
+
+    fn source(seq_no: TxnSeqNo) -> String {
+        TXO { address: ADDRESS.to_string(), seq_no }.to_libindy_string().unwrap()
+    }
+
+    fn spend(wallet: &WalletMockHandler, seq_no: TxnSeqNo, reply: &str) -> String {
+        let request = json!({
+            "operation": {"type": "10001", "inputs": [{"address": unqualified_part(ADDRESS), "seqNo": seq_no}]}
+        }).to_string();
+        let store = PendingTxnStore::new(wallet);
+        let txn_id = store.track(WALLET_HANDLE, &request, PendingState::Signed, 0).unwrap();
+        store.submitting(WALLET_HANDLE, &txn_id, 0).unwrap();
+        store.record_reply(WALLET_HANDLE, &txn_id, 0, reply).unwrap();
+        txn_id
+    }
+
+    // the mock calls back at once
+    fn check_txo(wallet: &WalletMockHandler, unspent: Vec<TxnSeqNo>, txo: &str) -> Result<TxoStatus, ErrorCode> {
+        let checked = Arc::new(Mutex::new(None));
+        let checked_clone = Arc::clone(&checked);
+        TxoStatusChecker::new(wallet, PaymentMockHandler { unspent })
+            .check(WALLET_HANDLE, POOL_HANDLE, txo, move |status| *checked_clone.lock().unwrap() = Some(status))?;
+        let status = checked.lock().unwrap().take().unwrap();
+        status
+    }
+
+    fn check(wallet: &WalletMockHandler, unspent: Vec<TxnSeqNo>, seq_no: TxnSeqNo) -> TxoStatus {
+        check_txo(wallet, unspent, &source(seq_no)).unwrap()
+    }
+
+    #[test]
+    fn unspent_txo() {
+        let wallet = WalletMockHandler::default();
+
+        let status = check(&wallet, vec![1, 2], 2);
+
+        assert!(!status.spent);
+        assert_eq!(Some(10), status.amount);
+        assert_eq!(ADDRESS, status.address);
+        assert_eq!(None, status.spent_by);
+    }
+
+    #[test]
+    fn txo_spent_by_a_confirmed_txn() {
+        let wallet = WalletMockHandler::default();
+        spend(&wallet, 1, r#"{"op": "REJECT", "reason": "double spend"}"#);
+        let txn_id = spend(&wallet, 1, r#"{"op": "REPLY", "result": {"txnMetadata": {"seqNo": 7}}}"#);
+
+        let status = check(&wallet, vec![2], 1);
+
+        assert!(status.spent);
+        assert_eq!(None, status.amount);
+        assert_eq!(Some(SpendingTxn { txn_id, state: PendingState::Confirmed, seq_no: Some(7) }), status.spent_by);
+    }
+
+    #[test]
+    fn txo_spent_elsewhere() {
+        let wallet = WalletMockHandler::default();
+        spend(&wallet, 2, r#"{"op": "REPLY", "result": {"txnMetadata": {"seqNo": 7}}}"#);
+
+        let status = check(&wallet, vec![], 1);
+
+        assert!(status.spent);
+        assert_eq!(None, status.spent_by);
+    }
+
+    #[test]
+    fn invalid_txo() {
+        let wallet = WalletMockHandler::default();
+        assert_eq!(ErrorCode::CommonInvalidStructure, check_txo(&wallet, vec![], "txo:sov:1").unwrap_err());
+    }
+}
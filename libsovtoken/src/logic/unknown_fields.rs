@@ -0,0 +1,147 @@
+/*!
+    Targeted errors for the unknown fields of the json given to the handlers.
+
+    Inputs, outputs, their configs and the payment address configs reject the
+    fields they don't know, so a typo such as `ammount` fails the call instead
+    of being ignored. The error names the field, and the known field it is
+    likely a typo of, in the last error of the thread.
+*/
+
+use indy::ErrorCode;
+use serde::Deserialize;
+use serde_json;
+
+use logic::error::SovtokenError;
+use logic::messages;
+
+// a known field this close to an unknown one is suggested as its spelling
+const MAX_TYPO_DISTANCE: usize = 2;
+
+/**
+    Deserialize `json`, the `what` of a handler, e.g. `"outputs"`.
+
+    Fails with `CommonInvalidStructure`, and with a message naming the
+    field when the json has an unknown field.
+*/
+pub fn from_json<'a, T: Deserialize<'a>>(json: &'a str, what: &str) -> Result<T, ErrorCode> {
+    serde_json::from_str(json).map_err(|error| {
+        error!("Invalid {}: {}", what, error);
+        match unknown_field(&error, what) {
+            Some(error) => ErrorCode::from(error),
+            None => ErrorCode::CommonInvalidStructure,
+        }
+    })
+}
+
+/**
+    The error naming the unknown field `error` is about, `None` when
+    `error` isn't about an unknown field.
+
+    ```
+        use sovtoken::logic::output::Output;
+        use sovtoken::logic::unknown_fields::unknown_field;
+        use sovtoken::utils::json_conversion::JsonDeserialize;
+
+        let error = Output::from_json(r#"{"address": "pay:sov:a", "ammount": 5}"#).unwrap_err();
+        let message = unknown_field(&error, "output").unwrap().message();
+        assert_eq!("Unknown field `ammount` in the output, did you mean `amount`?", message.text);
+    ```
+*/
+pub fn unknown_field(error: &serde_json::Error, what: &str) -> Option<SovtokenError> {
+    let text = error.to_string();
+    if !text.starts_with("unknown field") {
+        return None;
+    }
+
+    // "unknown field `ammount`, expected one of `recipient`, `amount`, `address` at line 1 column 10"
+    let mut names = text.split('`').skip(1).step_by(2);
+    let field = names.next()?;
+    let expected: Vec<&str> = names.collect();
+
+    let message = match closest(field, &expected) {
+        Some(known) => messages::UNKNOWN_FIELD_TYPO.with(&[quoted(field), what.to_string(), quoted(known)]),
+        None => {
+            let expected = expected.iter().map(|name| quoted(name)).collect::<Vec<String>>().join(", ");
+            messages::UNKNOWN_FIELD.with(&[quoted(field), what.to_string(), expected])
+        },
+    };
+    Some(SovtokenError::InvalidStructure(message))
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn quoted(name: &str) -> String {
+    format!("`{}`", name)
+}
+
+fn closest<'a>(field: &str, known: &[&'a str]) -> Option<&'a str> {
+    known.iter()
+        .map(|name| (edit_distance(&field.to_lowercase(), &name.to_lowercase()), *name))
+        .filter(|&(distance, _)| distance <= MAX_TYPO_DISTANCE)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, name)| name)
+}
+
+// Levenshtein distance, the fields are short
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+
+#[cfg(test)]
+mod unknown_fields_tests {
+    use logic::input::{Input, InputConfig};
+    use logic::messages::last_error;
+    use super::*;
+
+    #[test]
+    fn typo_of_a_known_field() {
+        let error = serde_json::from_str::<Input>(r#"{"address": "pay:sov:a", "seqNO": 1}"#).unwrap_err();
+        let message = unknown_field(&error, "input").unwrap().message();
+        assert_eq!(messages::UNKNOWN_FIELD_TYPO.id, message.id);
+        assert_eq!("`seqNo`", message.args[2]);
+    }
+
+    #[test]
+    fn field_far_from_the_known_ones() {
+        let error = serde_json::from_str::<InputConfig>(r#"{"ver": 1, "inputs": [], "memo": "a"}"#).unwrap_err();
+        let message = unknown_field(&error, "inputs").unwrap().message();
+        assert_eq!(messages::UNKNOWN_FIELD.id, message.id);
+        assert_eq!("`ver`, `inputs`", message.args[2]);
+    }
+
+    #[test]
+    fn other_errors_are_not_about_fields() {
+        let error = serde_json::from_str::<InputConfig>(r#"{"ver": 1}"#).unwrap_err();
+        assert!(unknown_field(&error, "inputs").is_none());
+    }
+
+    #[test]
+    fn from_json_keeps_the_message() {
+        let error = from_json::<Vec<Input>>(r#"[{"adress": "pay:sov:a", "seqNo": 1}]"#, "inputs").unwrap_err();
+        assert_eq!(ErrorCode::CommonInvalidStructure, error);
+        assert_eq!(messages::UNKNOWN_FIELD_TYPO.id, last_error().unwrap().message.id);
+    }
+
+    #[test]
+    fn distance_of_typos() {
+        assert_eq!(0, edit_distance("amount", "amount"));
+        assert_eq!(1, edit_distance("ammount", "amount"));
+        assert_eq!(3, edit_distance("memo", "ver"));
+    }
+}
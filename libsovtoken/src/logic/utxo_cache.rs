@@ -0,0 +1,434 @@
+/*!
+    Parsed GET_UTXO replies, by payment address and ledger state root.
+
+    The UTXOs of an address can't change while the state root stays the
+    same, so a reply for an address and a root already parsed gives the same
+    UTXOs. A reply body seen before isn't even deserialized: it is looked up
+    by its sha256.
+
+    Replies without a state proof carry no root and aren't cached. The
+    least recently used entry is evicted once `utxo_cache_size` entries are
+    cached, and a size of 0 disables the cache.
+
+    The last UTXOs parsed for each address are also written to the storage
+    backend of the library config, and survive a restart of the process. The
+    wallet of a reply isn't known when it is parsed: they are kept with the
+    cache wallet, or as records of no wallet, see `logic::storage`. The
+    persisted UTXOs are compressed as set by `compression` in the library
+    config, and the ones persisted plain are rewritten compressed when they
+    are read.
+*/
+
+use std::collections::VecDeque;
+
+use indy::{IndyHandle, ErrorCode};
+use serde_json;
+use sha2::{Digest, Sha256};
+
+use logic::amount_format::{with_amount_format, AmountFormat};
+use logic::compression::{self, Compression};
+use logic::config::library_config::library_config;
+use logic::parsers::parse_get_utxo_response::{self, ParseGetUtxoReply, ParseGetUtxoResponse};
+use logic::state::state;
+use logic::storage::{cache_storage, StorageBackend};
+use logic::type_aliases::TokenAmount;
+use utils::json_conversion::JsonDeserialize;
+
+/**
+    The number of replies cached by default.
+*/
+pub const DEFAULT_UTXO_CACHE_SIZE: usize = 64;
+
+/**
+    The record type the UTXOs of an address are persisted under.
+*/
+pub const UTXO_CACHE_RECORD_TYPE: &str = "sovtoken_utxo_cache";
+
+/**
+    Counters of the cache.
+*/
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UtxoCacheStats {
+    /// Replies answered from the cache.
+    pub hits: usize,
+    /// Replies parsed.
+    pub misses: usize,
+    /// Entries dropped to make room.
+    pub evictions: usize,
+}
+
+/**
+    The cache of parsed GET_UTXO replies, most recently used last.
+*/
+#[derive(Debug, Default)]
+pub struct UtxoCache {
+    entries: VecDeque<CacheEntry>,
+    stats: UtxoCacheStats,
+}
+
+impl UtxoCache {
+    pub fn new() -> Self {
+        UtxoCache::default()
+    }
+
+    /**
+        The sha256 of a reply body, to look it up with [`get_by_body`].
+
+        [`get_by_body`]: struct.UtxoCache.html#method.get_by_body
+    */
+    pub fn body_hash(body: &str) -> Vec<u8> {
+        let mut hasher = Sha256::default();
+        hasher.input(body.as_bytes());
+        hasher.result().to_vec()
+    }
+
+    /**
+        The UTXOs of a reply body parsed before.
+    */
+    pub fn get_by_body(&mut self, body_hash: &[u8]) -> Option<ParseGetUtxoReply> {
+        let position = self.entries.iter().position(|entry| entry.body_hash == body_hash);
+        self.hit(position)
+    }
+
+    /**
+        The UTXOs of `address` at the state `root_hash`.
+    */
+    pub fn get(&mut self, address: &str, root_hash: &str) -> Option<ParseGetUtxoReply> {
+        let position = self.entries.iter()
+            .position(|entry| entry.address == address && entry.root_hash == root_hash);
+        self.hit(position)
+    }
+
+    /**
+        The amount of the UTXO `txo`, in the most recent entry listing it.
+    */
+    pub fn amount_of(&self, txo: &str) -> Option<TokenAmount> {
+        self.entries.iter()
+            .rev()
+            .filter_map(|entry| entry.utxos.iter().find(|utxo| utxo.source == txo))
+            .map(|utxo| utxo.amount)
+            .next()
+    }
+
+    /**
+        Count a reply which had to be parsed.
+    */
+    pub fn miss(&mut self) {
+        self.stats.misses += 1;
+    }
+
+    /**
+        Cache the UTXOs of `address` at the state `root_hash`, as parsed
+        from the body with `body_hash`.
+
+        Replaces the entry of the same address and root, and evicts the
+        least recently used entries above `capacity`.
+    */
+    pub fn insert(&mut self, capacity: usize, address: &str, root_hash: &str, body_hash: Vec<u8>, utxos: ParseGetUtxoReply) {
+        self.entries.retain(|entry| entry.address != address || entry.root_hash != root_hash);
+        self.entries.push_back(CacheEntry {
+            address: address.to_string(),
+            root_hash: root_hash.to_string(),
+            body_hash,
+            utxos,
+        });
+
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+            self.stats.evictions += 1;
+        }
+    }
+
+    /**
+        Drop every entry, the counters are kept.
+    */
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> UtxoCacheStats {
+        self.stats
+    }
+
+    /* Methods "private" (aka not exported from this module)
+
+        KEEP all public methods above
+    */
+
+    fn hit(&mut self, position: Option<usize>) -> Option<ParseGetUtxoReply> {
+        let entry = self.entries.remove(position?)?;
+        let utxos = entry.utxos.clone();
+        self.entries.push_back(entry);
+        self.stats.hits += 1;
+        Some(utxos)
+    }
+}
+
+/**
+    Parse a GET_UTXO reply, or take its UTXOs from the cache.
+
+    The replies fetched with a pool handle are cached in the state of its
+    network, see [`pools`]. Errors are never cached: a REQNACK or REJECT is
+    parsed each time.
+
+    [`pools`]: ../pools/index.html
+*/
+pub fn parse_get_utxo_response_cached(pool_handle: Option<IndyHandle>, resp_json: &str) -> Result<ParseGetUtxoReply, ErrorCode> {
+    let capacity = library_config().utxo_cache_size;
+    if capacity == 0 {
+        return parse(resp_json);
+    }
+
+    let body_hash = UtxoCache::body_hash(resp_json);
+    if let Some(utxos) = with_cache(pool_handle, |cache| cache.get_by_body(&body_hash)) {
+        debug!("GET_UTXO reply taken from the cache");
+        return Ok(utxos);
+    }
+
+    let response = ParseGetUtxoResponse::from_json(resp_json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+
+    let key = response.result.as_ref().and_then(|result| {
+        let root_hash = result.state_proof.as_ref().and_then(|state_proof| state_proof.root_hash.clone());
+        root_hash.map(|root_hash| (result.address.clone(), root_hash))
+    });
+
+    let storage = cache_storage();
+    let compression = library_config().compression;
+
+    if let Some((ref address, ref root_hash)) = key {
+        if let Some(utxos) = with_cache(pool_handle, |cache| cache.get(address, root_hash)) {
+            debug!("UTXOs of {} at state root {} taken from the cache", address, root_hash);
+            return Ok(utxos);
+        }
+
+        let record_id = record_id(pool_handle, address);
+        if let Some(utxos) = storage.as_ref().and_then(|&(ref storage, wallet_handle)| load(storage, wallet_handle, &record_id, root_hash, compression)) {
+            debug!("UTXOs of {} at state root {} taken from the storage", address, root_hash);
+            with_cache(pool_handle, |cache| cache.insert(capacity, address, root_hash, body_hash, utxos.clone()));
+            return Ok(utxos);
+        }
+    }
+
+    with_cache(pool_handle, |cache| cache.miss());
+    let utxos = parse_get_utxo_response::from_response(response)?;
+
+    if let Some((address, root_hash)) = key {
+        if let Some((ref storage, wallet_handle)) = storage {
+            persist(storage, wallet_handle, &record_id(pool_handle, &address), &root_hash, &utxos, compression);
+        }
+        with_cache(pool_handle, |cache| cache.insert(capacity, &address, &root_hash, body_hash, utxos.clone()));
+    }
+
+    Ok(utxos)
+}
+
+/**
+    The amount of the UTXO `txo` in the GET_UTXO replies cached for the
+    network of `pool_handle`, or parsed without a pool. `None` when no
+    cached reply lists it.
+*/
+pub fn known_amount(pool_handle: Option<IndyHandle>, txo: &str) -> Option<TokenAmount> {
+    with_cache(pool_handle, |cache| cache.amount_of(txo))
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PersistedUtxos {
+    root_hash: String,
+    utxos: ParseGetUtxoReply,
+}
+
+// the cache of the network of the pool, or the one of the replies parsed without a pool
+fn with_cache<F, R>(pool_handle: Option<IndyHandle>, f: F) -> R where F: FnOnce(&mut UtxoCache) -> R {
+    match pool_handle {
+        Some(pool_handle) => f(&mut state().pools.lock().network(pool_handle).utxo_cache),
+        None => f(&mut state().utxo_cache.lock()),
+    }
+}
+
+// the persisted UTXOs of an address are kept apart by network
+fn record_id(pool_handle: Option<IndyHandle>, address: &str) -> String {
+    match pool_handle {
+        Some(pool_handle) => format!("{}/{}", state().pools.lock().network_id(pool_handle), address),
+        None => address.to_string(),
+    }
+}
+
+// the UTXOs persisted as `record_id`, when they are at `root_hash`, rewriting
+// a record persisted uncompressed
+fn load<S: StorageBackend>(storage: &S, wallet_handle: IndyHandle, record_id: &str, root_hash: &str, compression: Compression) -> Option<ParseGetUtxoReply> {
+    let value = storage.get_record(wallet_handle, UTXO_CACHE_RECORD_TYPE, record_id).ok()?;
+    let json = compression::decompress(&value).ok()?;
+    let persisted: PersistedUtxos = serde_json::from_str(&json).map_err(map_err_err!()).ok()?;
+
+    if compression::needs_migration(&value, compression) {
+        persist(storage, wallet_handle, record_id, &persisted.root_hash, &persisted.utxos, compression);
+    }
+
+    if persisted.root_hash == root_hash {
+        Some(persisted.utxos)
+    } else {
+        None
+    }
+}
+
+// a failure to persist only costs a parse after a restart, it is logged
+fn persist<S: StorageBackend>(storage: &S, wallet_handle: IndyHandle, record_id: &str, root_hash: &str, utxos: &ParseGetUtxoReply, compression: Compression) {
+    let persisted = PersistedUtxos { root_hash: root_hash.to_string(), utxos: utxos.clone() };
+    let value = match with_amount_format(AmountFormat::Number, || serde_json::to_string(&persisted)) {
+        Ok(value) => value,
+        Err(error) => {
+            error!("Failed to serialize the UTXOs of {}: {}", record_id, error);
+            return;
+        },
+    };
+    let value = match compression::compress(&value, compression) {
+        Ok(value) => value,
+        Err(ec) => {
+            error!("Failed to compress the UTXOs of {}: {:?}", record_id, ec);
+            return;
+        },
+    };
+
+    let stored = match storage.add_record(wallet_handle, UTXO_CACHE_RECORD_TYPE, record_id, &value) {
+        Err(ErrorCode::WalletItemAlreadyExists) => storage.update_record(wallet_handle, UTXO_CACHE_RECORD_TYPE, record_id, &value),
+        stored => stored,
+    };
+    if let Err(error) = stored {
+        error!("Failed to persist the UTXOs of {}: {:?}", record_id, error);
+    }
+}
+
+fn parse(resp_json: &str) -> Result<ParseGetUtxoReply, ErrorCode> {
+    let response = ParseGetUtxoResponse::from_json(resp_json)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+    parse_get_utxo_response::from_response(response)
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    address: String,
+    root_hash: String,
+    body_hash: Vec<u8>,
+    utxos: ParseGetUtxoReply,
+}
+
+
+#[cfg(test)]
+mod utxo_cache_tests {
+    use logic::parsers::parse_get_utxo_response::UTXO;
+    use logic::storage::{FileStorage, NO_WALLET};
+
+    use super::*;
+
+    fn utxos(amount: u64) -> ParseGetUtxoReply {
+        vec![UTXO {
+            payment_address: String::from("pay:sov:1"),
+            source: String::from("txo:sov:1"),
+            amount,
+            extra: String::new(),
+        }]
+    }
+
+    #[test]
+    fn get_by_address_and_root() {
+        let mut cache = UtxoCache::new();
+        cache.insert(2, "1", "root", UtxoCache::body_hash("body"), utxos(10));
+
+        assert_eq!(Some(utxos(10)), cache.get("1", "root"));
+        assert_eq!(None, cache.get("1", "other root"));
+        assert_eq!(None, cache.get("2", "root"));
+        assert_eq!(1, cache.stats().hits);
+    }
+
+    #[test]
+    fn get_by_body() {
+        let mut cache = UtxoCache::new();
+        cache.insert(2, "1", "root", UtxoCache::body_hash("body"), utxos(10));
+
+        assert_eq!(Some(utxos(10)), cache.get_by_body(&UtxoCache::body_hash("body")));
+        assert_eq!(None, cache.get_by_body(&UtxoCache::body_hash("other body")));
+    }
+
+    #[test]
+    fn amount_of_a_cached_utxo() {
+        let mut cache = UtxoCache::new();
+        cache.insert(2, "1", "root", UtxoCache::body_hash("body"), utxos(10));
+
+        assert_eq!(Some(10), cache.amount_of("txo:sov:1"));
+        assert_eq!(None, cache.amount_of("txo:sov:2"));
+    }
+
+    #[test]
+    fn insert_replaces_same_address_and_root() {
+        let mut cache = UtxoCache::new();
+        cache.insert(2, "1", "root", UtxoCache::body_hash("body"), utxos(10));
+        cache.insert(2, "1", "root", UtxoCache::body_hash("new body"), utxos(10));
+
+        assert_eq!(1, cache.len());
+        assert_eq!(None, cache.get_by_body(&UtxoCache::body_hash("body")));
+        assert!(cache.get_by_body(&UtxoCache::body_hash("new body")).is_some());
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let mut cache = UtxoCache::new();
+        cache.insert(2, "1", "root", UtxoCache::body_hash("1"), utxos(1));
+        cache.insert(2, "2", "root", UtxoCache::body_hash("2"), utxos(2));
+        cache.get("1", "root");
+        cache.insert(2, "3", "root", UtxoCache::body_hash("3"), utxos(3));
+
+        assert_eq!(2, cache.len());
+        assert_eq!(None, cache.get("2", "root"));
+        assert!(cache.get("1", "root").is_some());
+        assert_eq!(1, cache.stats().evictions);
+    }
+
+    #[test]
+    fn persisted_utxos_of_the_same_root() {
+        let dir = ::std::env::temp_dir().join(format!("sovtoken_utxo_cache_{}", ::utils::random::rand_string(8)));
+        let storage = FileStorage::new(dir.clone());
+        persist(&storage, NO_WALLET, "1", "root", &utxos(10), Compression::Gzip);
+
+        assert!(storage.get_record(NO_WALLET, UTXO_CACHE_RECORD_TYPE, "1").unwrap().starts_with("gzip:"));
+        assert_eq!(Some(utxos(10)), load(&storage, NO_WALLET, "1", "root", Compression::Gzip));
+        assert_eq!(None, load(&storage, NO_WALLET, "1", "new root", Compression::Gzip));
+        assert_eq!(None, load(&storage, NO_WALLET, "2", "root", Compression::Gzip));
+        let _ = ::std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn persisted_plain_utxos_are_migrated() {
+        let dir = ::std::env::temp_dir().join(format!("sovtoken_utxo_cache_{}", ::utils::random::rand_string(8)));
+        let storage = FileStorage::new(dir.clone());
+        persist(&storage, NO_WALLET, "1", "root", &utxos(10), Compression::None);
+
+        assert_eq!(Some(utxos(10)), load(&storage, NO_WALLET, "1", "root", Compression::Brotli));
+        assert!(storage.get_record(NO_WALLET, UTXO_CACHE_RECORD_TYPE, "1").unwrap().starts_with("br:"));
+        assert_eq!(Some(utxos(10)), load(&storage, NO_WALLET, "1", "root", Compression::None));
+        let _ = ::std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn capacity_zero_caches_nothing() {
+        let mut cache = UtxoCache::new();
+        cache.insert(0, "1", "root", UtxoCache::body_hash("body"), utxos(10));
+        assert!(cache.is_empty());
+    }
+}
@@ -17,14 +17,7 @@ pub fn deserialize<'a>(
     let cb = cb.ok_or(ErrorCode::CommonInvalidStructure)?;
     trace!("Unwrapped callback.");
 
-    let did = opt_res_to_res_opt!(
-        Did::from_pointer(did)
-            .map(|did| {
-                did.validate()
-                    .map_err(map_err_err!())
-                    .or(Err(ErrorCode::CommonInvalidStructure))
-            })
-    )?;
+    let did = Did::submitter_from_pointer(did)?;
 
     debug!("Converted did pointer to string >>> {:?}", did);
 
@@ -33,8 +26,7 @@ pub fn deserialize<'a>(
     debug!("Converted txo pointer to string >>> {:?}", txo);
 
     let txo = TXO::from_libindy_string(&txo)
-        .map_err(map_err_err!())
-        .map_err(|_| ErrorCode::CommonInvalidStructure)?;
+        .map_err(ErrorCode::from)?;
     debug!("Deserialized txo: {:?}", txo);
 
     trace!("logic::verify::deserialize << did: {:?}, txo: {:?}", did, txo);
@@ -70,6 +62,18 @@ mod test_deserialize {
         let txo_c = c_pointer_from_str(&txo);
         let cb = default::empty_callback_string;
 
+        let (did, _, _) = super::deserialize(did, txo_c, Some(cb)).unwrap();
+        assert_eq!(None, did);
+    }
+
+    #[test]
+    pub fn deserialize_fails_for_invalid_did() {
+        let did = c_pointer_from_str("123456789abcd!efghij");
+        let payment_address = "pay:sov:d0kitWxupHvZ4i0NHJhoj79RcUeyt3YlwAc8Hbcy87iRLSZC".to_string();
+        let txo = TXO { address: payment_address.clone(), seq_no: 1 }.to_libindy_string().unwrap();
+        let txo_c = c_pointer_from_str(&txo);
+        let cb = default::empty_callback_string;
+
         let ec = super::deserialize(did, txo_c, Some(cb)).unwrap_err();
         assert_eq!(ec, ErrorCode::CommonInvalidStructure);
     }
@@ -0,0 +1,123 @@
+/*!
+    The wallet handles the library is used with.
+
+    libindy doesn't tell a payment plugin when a wallet is closed, and a
+    call on a closed wallet fails deep in an async pipeline, sometimes after
+    the callbacks of the handler are set up. The calls of the SDK
+    implementations of [`WalletAPI`], [`CryptoAPI`] and [`PaymentAPI`] check
+    the handle first: a handle the application reported closed with
+    `wallet_closed_handler` fails at once with `WalletInvalidHandle`.
+
+    libindy never gives out a handle twice, so a closed handle stays closed.
+
+    [`WalletAPI`]: ../indy_sdk_api/wallet_api/trait.WalletAPI.html
+    [`CryptoAPI`]: ../indy_sdk_api/crypto_api/trait.CryptoAPI.html
+    [`PaymentAPI`]: ../indy_sdk_api/payment_api/trait.PaymentAPI.html
+*/
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use indy::{IndyHandle, ErrorCode};
+
+use logic::state::state;
+
+/**
+    How a wallet handle was used.
+
+    `first_used` is when the library first saw it, in seconds since the
+    unix epoch.
+*/
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct WalletUse {
+    pub first_used: u64,
+    pub closed: bool,
+}
+
+/**
+    Fail with `WalletInvalidHandle` when `wallet_handle` can't be a handle
+    of libindy or was closed, remember it otherwise.
+*/
+pub fn check(wallet_handle: IndyHandle) -> Result<(), ErrorCode> {
+    if wallet_handle <= 0 {
+        error!("{} isn't a wallet handle", wallet_handle);
+        return Err(ErrorCode::WalletInvalidHandle);
+    }
+
+    let state = state();
+    let mut handles = state.wallet_handles.lock();
+    let wallet = handles.entry(wallet_handle).or_insert_with(|| {
+        debug!("First use of wallet handle {}", wallet_handle);
+        WalletUse { first_used: now(), closed: false }
+    });
+
+    if wallet.closed {
+        error!("The wallet of handle {} is closed", wallet_handle);
+        return Err(ErrorCode::WalletInvalidHandle);
+    }
+    Ok(())
+}
+
+/**
+    Mark `wallet_handle` closed, return how it was used before, `None` when
+    the library never used it.
+*/
+pub fn mark_closed(wallet_handle: IndyHandle) -> Option<WalletUse> {
+    let state = state();
+    let mut handles = state.wallet_handles.lock();
+    let used = handles.get(&wallet_handle).cloned();
+    let first_used = used.map_or_else(now, |wallet| wallet.first_used);
+
+    info!("Wallet handle {} closed", wallet_handle);
+    handles.insert(wallet_handle, WalletUse { first_used, closed: true });
+    used
+}
+
+/**
+    The wallet handles used, by handle.
+*/
+pub fn wallet_handles() -> HashMap<IndyHandle, WalletUse> {
+    state().wallet_handles.lock().clone()
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+
+#[cfg(test)]
+mod wallet_handles_tests {
+    use super::*;
+
+    // the handles are shared by the tests, each uses its own
+    #[test]
+    fn closed_handle_fails() {
+        check(9101).unwrap();
+        let used = mark_closed(9101).unwrap();
+
+        assert!(!used.closed);
+        assert_eq!(ErrorCode::WalletInvalidHandle, check(9101).unwrap_err());
+        assert!(wallet_handles()[&9101].closed);
+    }
+
+    #[test]
+    fn handle_closed_before_use() {
+        assert_eq!(None, mark_closed(9201));
+        assert_eq!(ErrorCode::WalletInvalidHandle, check(9201).unwrap_err());
+    }
+
+    #[test]
+    fn invalid_handle() {
+        assert_eq!(ErrorCode::WalletInvalidHandle, check(0).unwrap_err());
+        assert_eq!(ErrorCode::WalletInvalidHandle, check(-1).unwrap_err());
+    }
+}
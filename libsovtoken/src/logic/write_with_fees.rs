@@ -0,0 +1,313 @@
+/*!
+    Fees of the indy writes which cost tokens: NYM, ATTRIB and SCHEMA.
+
+    Paying for a write takes four calls: fetch the UTXOs of the funding
+    address, look up the fee of the txn type, pick the inputs and the change
+    and add the fees to the request. [`WriteFunder`] does the first three,
+    so `build_nym_with_fees_handler` and its siblings take the prepared
+    request and a funding address, and return the request with its fees.
+    `parse_write_with_fees_response_handler` parses the reply to it.
+
+    The fee is the one of the parsed GET_FEES replies and the preloaded fee
+    schedule, see [`fee_waiver`], so one of them has to be known first. The
+    UTXOs are fetched without blocking, and the ones spent by the requests
+    submitted to the pool already are left out, see [`optimistic_updates`].
+    The largest UTXOs are spent first, so the request has as few inputs as
+    possible, and the change goes back to the funding address.
+
+    [`WriteFunder`]: struct.WriteFunder.html
+    [`fee_waiver`]: ../fee_waiver/index.html
+    [`optimistic_updates`]: ../optimistic_updates/index.html
+*/
+
+use indy::{IndyHandle, ErrorCode};
+
+use logic::api_internals::add_request_fees::{txn_type, RawRequest};
+use logic::error::SovtokenError;
+use logic::fee_waiver;
+use logic::indy_sdk_api::payment_api::PaymentAPI;
+use logic::input::{Input, Inputs};
+use logic::messages;
+use logic::optimistic_updates;
+use logic::output::{Output, Outputs};
+use logic::parsers::common::TXO;
+use logic::parsers::parse_get_utxo_response::UTXO;
+use logic::state::state;
+use logic::type_aliases::TokenAmount;
+use utils::constants::txn_types::{ATTRIB, NYM, SCHEMA};
+
+/**
+    An indy write paid for with fees.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteTxn {
+    Nym,
+    Attrib,
+    Schema,
+}
+
+impl WriteTxn {
+    /**
+        The txn type of the operation of the write.
+    */
+    pub fn txn_type(self) -> &'static str {
+        match self {
+            WriteTxn::Nym => NYM,
+            WriteTxn::Attrib => ATTRIB,
+            WriteTxn::Schema => SCHEMA,
+        }
+    }
+
+    /**
+        The name of the write, as in the messages.
+    */
+    pub fn name(self) -> &'static str {
+        match self {
+            WriteTxn::Nym => "NYM",
+            WriteTxn::Attrib => "ATTRIB",
+            WriteTxn::Schema => "SCHEMA",
+        }
+    }
+
+    /**
+        Check that `request` is a request of this write.
+
+        Fails with `CommonInvalidStructure` when it is of another type.
+    */
+    pub fn check(self, request: &RawRequest) -> Result<(), ErrorCode> {
+        match txn_type(request) {
+            Some(ref txn_type) if txn_type == self.txn_type() => Ok(()),
+            other => {
+                let other = other.unwrap_or_else(|| "missing".to_string());
+                Err(ErrorCode::from(SovtokenError::InvalidStructure(messages::WRITE_TXN_TYPE.with(&[self.name().to_string(), self.txn_type().to_string(), other]))))
+            }
+        }
+    }
+}
+
+/**
+    The inputs and outputs paying the fee of a write.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Funding {
+    pub fee: TokenAmount,
+    pub inputs: Inputs,
+    pub input_amounts: Vec<TokenAmount>,
+    pub outputs: Outputs,
+}
+
+/**
+    Pay `fee` from `utxos`, the largest first, with the change to `funding_address`.
+
+    There is no change output when the inputs add up to the fee.
+
+    Fails with `PaymentInsufficientFundsError` when the UTXOs don't add up
+    to the fee, and with `CommonInvalidStructure` for a UTXO with an
+    invalid source or when the UTXOs add up to more than a `TokenAmount`.
+*/
+pub fn select_funding(utxos: &[UTXO], funding_address: &str, fee: TokenAmount) -> Result<Funding, ErrorCode> {
+    let mut utxos: Vec<&UTXO> = utxos.iter().collect();
+    utxos.sort_by(|a, b| b.amount.cmp(&a.amount));
+
+    let mut inputs = Vec::new();
+    let mut input_amounts = Vec::new();
+    let mut total: TokenAmount = 0;
+    for utxo in utxos {
+        if total >= fee && !inputs.is_empty() {
+            break;
+        }
+        let txo = TXO::from_libindy_string(&utxo.source).map_err(ErrorCode::from)?;
+        inputs.push(Input::new(txo.address, txo.seq_no));
+        input_amounts.push(utxo.amount);
+        total = total.checked_add(utxo.amount).ok_or(ErrorCode::CommonInvalidStructure)?;
+    }
+
+    if total < fee {
+        return Err(ErrorCode::from(SovtokenError::InsufficientFunds(messages::FEES_INSUFFICIENT_FUNDS.with(&[(fee - total).to_string()]))));
+    }
+
+    let outputs = match total - fee {
+        0 => Vec::new(),
+        change => vec![Output::new(funding_address.to_string(), change)],
+    };
+    Ok(Funding { fee, inputs, input_amounts, outputs })
+}
+
+/**
+    Picks the inputs and outputs paying for writes.
+*/
+pub struct WriteFunder<P> where P: PaymentAPI {
+    payment_api: P,
+}
+
+impl<P: PaymentAPI> WriteFunder<P> {
+    pub fn new(payment_api: P) -> Self {
+        WriteFunder { payment_api }
+    }
+
+    /**
+        Call `cb` with the funding of `request`, a request of `write`, by
+        the UTXOs of `funding_address` on the ledger of `pool_handle`.
+        `None` when the fee schedule waives the fee of the write.
+
+        Fails with `CommonInvalidStructure` when the request is of another
+        write, with `PoolIncompatibleProtocolVersion` when it isn't framed
+        with the protocol version of the pool, and with `CommonInvalidState`
+        when the fee of the write isn't known. `cb` is called with
+        `PaymentInsufficientFundsError` when the funding address can't pay
+        it, and with the errors of fetching the UTXOs.
+    */
+    pub fn fund<F>(&self, pool_handle: IndyHandle, write: WriteTxn, request: &RawRequest, funding_address: &str, mut cb: F) -> Result<(), ErrorCode>
+        where F: FnMut(Result<Option<Funding>, ErrorCode>) + 'static + Send
+    {
+        trace!("logic::write_with_fees::fund >> write: {:?}, funding_address: {:?}", write, funding_address);
+        write.check(request)?;
+        state().pools.lock().check_request(pool_handle, request.as_str())?;
+
+        let fee = fee_waiver::known_fee(Some(pool_handle), write.txn_type())
+            .ok_or_else(|| ErrorCode::from(SovtokenError::InvalidState(messages::FEE_NOT_KNOWN.with(&[write.name().to_string()]))))?;
+        if fee == 0 {
+            debug!("The fee schedule waives the fee of {}", write.name());
+            cb(Ok(None));
+            return Ok(());
+        }
+
+        let address = funding_address.to_string();
+        let ec = self.payment_api.indy_get_utxos_async(pool_handle, funding_address, move |utxos| {
+            let funding = utxos.and_then(|utxos| {
+                let unspent = optimistic_updates::apply(Some(pool_handle), utxos);
+                select_funding(&unspent, &address, fee)
+            });
+            trace!("logic::write_with_fees::fund << funding: {:?}", funding);
+            cb(funding.map(Some));
+        });
+        match ec {
+            ErrorCode::Success => Ok(()),
+            ec => Err(ec),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod write_with_fees_tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use serde_json;
+
+    use logic::address::unqualified_part;
+    use logic::config::library_config::LibraryConfig;
+    use logic::indy_sdk_api::payment_api::PaymentAPI;
+    use logic::parsers::parse_get_utxo_response::ParseGetUtxoReply;
+    use utils::test::context::in_own_context;
+    use super::*;
+
+    const ADDRESS: &str = "pay:sov:iTQzpdRdugkJ2gLD5vW5c159dncSL9jbAtu3WfPcb8qWD9bUd";
+
+    fn utxo(seq_no: u64, amount: TokenAmount) -> UTXO {
+        UTXO {
+            payment_address: ADDRESS.to_string(),
+            source: TXO::new(ADDRESS, seq_no).to_libindy_string().unwrap(),
+            amount,
+            extra: String::new(),
+        }
+    }
+
+    struct Ledger(Vec<UTXO>);
+
+    impl PaymentAPI for Ledger {
+        fn indy_list_payment_addresses(&self, _: IndyHandle) -> Result<Vec<String>, ErrorCode> {
+            Ok(vec![ADDRESS.to_string()])
+        }
+
+        fn indy_get_utxos(&self, _: IndyHandle, _: &str) -> Result<ParseGetUtxoReply, ErrorCode> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn attrib(request: serde_json::Value) -> RawRequest {
+        RawRequest::new(request.to_string()).unwrap()
+    }
+
+    fn schedule(fee: TokenAmount) {
+        let mut fees = HashMap::new();
+        fees.insert(ATTRIB.to_string(), fee);
+        fee_waiver::remember_schedule(None, &fees);
+    }
+
+    // the mock calls back at once
+    fn fund(funder: &WriteFunder<Ledger>, write: WriteTxn, request: &RawRequest) -> Result<Option<Funding>, ErrorCode> {
+        let funded = Arc::new(Mutex::new(None));
+        let funded_clone = Arc::clone(&funded);
+        funder.fund(1, write, request, ADDRESS, move |funding| *funded_clone.lock().unwrap() = Some(funding))?;
+        let funding = funded.lock().unwrap().take().unwrap();
+        funding
+    }
+
+    #[test]
+    fn largest_utxos_pay_first() {
+        let funding = select_funding(&[utxo(1, 3), utxo(2, 10), utxo(3, 5)], ADDRESS, 12).unwrap();
+        assert_eq!(vec![Input::new(ADDRESS.to_string(), 2), Input::new(ADDRESS.to_string(), 3)], funding.inputs);
+        assert_eq!(vec![Output::new(ADDRESS.to_string(), 3)], funding.outputs);
+
+        let exact = select_funding(&[utxo(1, 12)], ADDRESS, 12).unwrap();
+        assert!(exact.outputs.is_empty());
+
+        assert_eq!(ErrorCode::PaymentInsufficientFundsError, select_funding(&[utxo(1, 3)], ADDRESS, 12).unwrap_err());
+    }
+
+    #[test]
+    fn fund_a_write_of_the_right_type() {
+        in_own_context(LibraryConfig::default(), || {
+            let funder = WriteFunder::new(Ledger(vec![utxo(1, 10)]));
+            let request = attrib(json!({"reqId": 1, "operation": {"type": "100", "dest": "Th7MpTaRZVRYnPiabds81Y"}}));
+            assert_eq!(ErrorCode::CommonInvalidState, fund(&funder, WriteTxn::Attrib, &request).unwrap_err());
+
+            schedule(4);
+            let funding = fund(&funder, WriteTxn::Attrib, &request).unwrap().unwrap();
+            assert_eq!(4, funding.fee);
+            assert_eq!(vec![10], funding.input_amounts);
+            assert_eq!(vec![Output::new(ADDRESS.to_string(), 6)], funding.outputs);
+
+            assert_eq!(ErrorCode::CommonInvalidStructure, fund(&funder, WriteTxn::Nym, &request).unwrap_err());
+
+            schedule(0);
+            assert_eq!(None, fund(&funder, WriteTxn::Attrib, &request).unwrap());
+        });
+    }
+
+    #[test]
+    fn utxos_spent_already_pay_nothing() {
+        in_own_context(LibraryConfig::default(), || {
+            schedule(4);
+            let payment = json!({
+                "operation": {
+                    "type": "10001",
+                    "inputs": [{"address": unqualified_part(ADDRESS), "seqNo": 2}],
+                    "outputs": [{"address": unqualified_part(ADDRESS), "amount": 10}],
+                    "signatures": ["signature"]
+                },
+                "reqId": 2
+            });
+            optimistic_updates::record(Some(1), &payment.to_string()).unwrap();
+
+            let funder = WriteFunder::new(Ledger(vec![utxo(1, 5), utxo(2, 10)]));
+            let request = attrib(json!({"reqId": 1, "operation": {"type": "100", "dest": "Th7MpTaRZVRYnPiabds81Y"}}));
+            let funding = fund(&funder, WriteTxn::Attrib, &request).unwrap().unwrap();
+            assert_eq!(vec![Input::new(ADDRESS.to_string(), 1)], funding.inputs);
+        });
+    }
+
+    #[test]
+    fn request_of_another_protocol_version() {
+        in_own_context(LibraryConfig::default(), || {
+            schedule(4);
+            state().pools.lock().set_protocol_version(1, 1).unwrap();
+
+            let funder = WriteFunder::new(Ledger(vec![utxo(1, 10)]));
+            let request = attrib(json!({"reqId": 1, "protocolVersion": 2, "operation": {"type": "100", "dest": "Th7MpTaRZVRYnPiabds81Y"}}));
+            assert_eq!(ErrorCode::PoolIncompatibleProtocolVersion, fund(&funder, WriteTxn::Attrib, &request).unwrap_err());
+        });
+    }
+}
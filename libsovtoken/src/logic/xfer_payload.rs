@@ -12,16 +12,20 @@ use hex::ToHex;
 use serde_json;
 use std::sync::Arc;
 use std::sync::Mutex;
-use std::collections::HashMap;
+use std::time::Instant;
 
 use indy::IndyHandle;
 use indy::ErrorCode;
 
 use logic::address;
+use logic::balance::validate_supply;
 use logic::indy_sdk_api::crypto_api::CryptoAPI;
 use logic::input::{Input, Inputs};
 use logic::output::{Outputs};
 use logic::hash::Hash;
+use logic::request::with_ledger_format;
+use logic::transfer_limits::TransferLimits;
+use utils::metrics::{self, SIGNING_METRIC};
 
 /**
  * Holds `inputs` and `outputs`
@@ -77,6 +81,8 @@ impl XferPayload {
         if self.inputs.len() < 1 {
             return Err(ErrorCode::CommonInvalidStructure);
         }
+        TransferLimits::configured().check(self.inputs.len(), self.outputs.len())?;
+        validate_supply(&self.outputs)?;
         self.sign(crypto_api, wallet_handle, txn_digest, cb)
     }
 
@@ -84,17 +90,22 @@ impl XferPayload {
     /** 
      * Signs [`Inputs`]
      * 
-     * Validates that inputs and outputs both have a valid `address`.
+     * Validates that inputs and outputs both have a valid `address`, that
+     * there aren't more of them than the [`TransferLimits`] allow, and that
+     * the outputs don't hold more than the supply of tokens.
      * Signs each [`Input`] with [`sign_input`]
      * 
      * [`Input`]: Input
      * [`Inputs`]: Inputs
+     * [`TransferLimits`]: ../transfer_limits/struct.TransferLimits.html
      */
     pub fn sign_transfer<A: CryptoAPI>(self, crypto_api: &'static A, wallet_handle: IndyHandle, cb: Box<Fn(Result<XferPayload, ErrorCode>) + Send + Sync>) -> Result<(), ErrorCode> {
         trace!("logic::xfer_payload::xfer_payload::sign >> wallet_handle: {:?}", wallet_handle);
         if self.outputs.len() < 1 || self.inputs.len() < 1 {
             return Err(ErrorCode::CommonInvalidStructure);
         }
+        TransferLimits::configured().check(self.inputs.len(), self.outputs.len())?;
+        validate_supply(&self.outputs)?;
         self.sign(crypto_api, wallet_handle, &None, cb)
     }
 
@@ -110,7 +121,10 @@ impl XferPayload {
 
         debug!("Indicator stripped from inputs");
 
+        let signing_started = Instant::now();
+
         XferPayload::sign_inputs(crypto_api, wallet_handle, &self.inputs.clone(), &self.outputs.clone(), txn_digest, &self.extra.clone(),Box::new(move |signatures| {
+            metrics::record(SIGNING_METRIC, signing_started.elapsed(), signatures.is_ok());
             match signatures {
                 Ok(signatures) => {
                     let payload = Self::clone_payload_add_signatures(&self, signatures);
@@ -129,14 +143,7 @@ impl XferPayload {
         res
     }
 
-    fn clone_payload_add_signatures(prev: &Self, signatures: HashMap<String, String>) -> Self {
-        let signatures = prev.inputs
-            .iter()
-            .map(|input_address| signatures.get(&input_address.to_string()))
-            .filter(|signature| signature.is_some())
-            .map(|signature| signature.unwrap().to_owned())
-            .collect();
-        
+    fn clone_payload_add_signatures(prev: &Self, signatures: Vec<String>) -> Self {
         XferPayload {
             inputs: prev.inputs.clone(),
             outputs: prev.outputs.clone(),
@@ -146,83 +153,80 @@ impl XferPayload {
     }
 }
 
+/*
+    The signatures of a payload being signed, by input position. The callback
+    is called once: with every signature, or with the first error of the
+    wallet. The keys of the input addresses were checked before, see
+    `check_keys`.
+*/
+struct PendingSignatures {
+    signatures: Vec<Option<String>>,
+    remaining: usize,
+    done: bool,
+}
+
 trait InputSigner<A: CryptoAPI> {
-    fn sign_inputs(crypto_api: &'static A, wallet_handle: IndyHandle, inputs: &Inputs, outputs: &Outputs, txn_digest: &Option<String>, extra: &Option<String>, cb: Box<Fn(Result<HashMap<String, String>, ErrorCode>) + Send + Sync>)
-                   -> Result<(), ErrorCode>
+    fn sign_inputs(crypto_api: &'static A, wallet_handle: IndyHandle, inputs: &Inputs, outputs: &Outputs, txn_digest: &Option<String>, extra: &Option<String>, cb: Box<Fn(Result<Vec<String>, ErrorCode>) + Send + Sync>)
+                   -> Result<(), ErrorCode> where A: 'static
     {
-        let inputs_result: Arc<Mutex<HashMap<String, String>>> = Default::default();
-
-        let res_cnt = inputs.len();
-        let cb = Arc::new(move |signature: Result<String, ErrorCode>, input| {
-            match signature {
-                Ok(signature) => {
-                    let mut results = inputs_result.lock().unwrap();
-                    results.insert(input, signature);
-                    if results.len() == res_cnt {
-                        cb(Ok(results.clone()))
-                    }
-                }
-                Err(err) => cb(Err(err))
-            }
-        });
-
+        // every input address has to resolve to a verkey before anything is sent to the wallet
+        let mut keys: Vec<(String, String)> = Vec::new();
         for input in inputs {
-            let cb = cb.clone();
-            match Self::sign_input(crypto_api, wallet_handle, input, outputs, txn_digest, extra, Box::new(cb)) {
-                err @ Err(_) => { return err; }
-                _ => ()
+            let verkey = address::verkey_from_unqualified_address(&input.address)?;
+            if !keys.iter().any(|&(ref known, _)| *known == verkey) {
+                keys.push((verkey, input.address.clone()));
             }
         }
 
-        Ok(())
+        // and the wallet has to hold every key before anything is signed
+        let cb: Arc<Fn(Result<Vec<String>, ErrorCode>) + Send + Sync> = Arc::from(cb);
+        let (inputs, outputs, txn_digest, extra) = (inputs.clone(), outputs.clone(), txn_digest.clone(), extra.clone());
+        check_keys(crypto_api, wallet_handle, keys, Box::new(move |checked| {
+            let signing = checked.and_then(|()| sign_each(crypto_api, wallet_handle, &inputs, &outputs, &txn_digest, &extra, cb.clone()));
+            if let Err(err) = signing {
+                cb(Err(err));
+            }
+        }))
     }
 
     /**
-     * Signs an [`Input`] with indy_crypto_sign
+     * Signs an [`Input`] with indy_crypto_sign, with the key of its own address
      *
-     * Validates the `input`'s `address`, but not the `outputs`.
-     * The message that will be signed is
-     * `[[<address>, <seq_no>], [<Output>, <Output>, ...]]`
+     * Validates the `input`'s `address`, but not the `outputs`. The signature
+     * is passed to `cb` with the `position` of the input.
+     * The message that will be signed is the [`signing_message`] of the input.
      *
      * [`Input`]: Input
+     * [`signing_message`]: fn.signing_message.html
      */
     fn sign_input(
         crypto_api: &'static A,
         wallet_handle: IndyHandle,
+        position: usize,
         input: &Input,
         outputs: &Outputs,
         txn_digest: &Option<String>,
         _extra: &Option<String>,
-        cb: Box<Arc<Fn(Result<String, ErrorCode>, String) + Send + Sync>>,
+        cb: Box<Arc<Fn(Result<String, ErrorCode>, usize) + Send + Sync>>,
     ) -> Result<(), ErrorCode>
     {
         trace!("logic::xfer_payload::input_signer::sign_input >> input: {:?}, outputs: {:?}, wallet_handle {:?}", input, outputs, wallet_handle);
         let verkey = address::verkey_from_unqualified_address(&input.address.clone())?;
         debug!("Received verkey for payment address >>> {:?}", verkey);
 
-        let vals: Vec<serde_json::Value> = vec![
-            Some(json!([input])),
-            Some(json!(outputs)),
-            txn_digest.clone().map(|e| json!(e)),
-//            _extra.map(|e| json!(e))
-        ].into_iter().filter_map(|e| e).collect();
-
-        let message = serialize_signature(json!(vals))?;
+        let message = signing_message(input, outputs, txn_digest)?;
 
         debug!("Message to sign >>> {:?}", &message);
 
-        let input_key = input.to_string();
-
         let ca = move |signature: Result<String, ErrorCode>| {
-            let key = input_key.clone();
-            debug!("Received encoded signature >>> {:?} for input {:?}", signature, key);
-            cb(signature, key);
+            debug!("Received encoded signature >>> {:?} for input {:?}", signature, position);
+            cb(signature, position);
         };
 
         let ec = crypto_api.indy_crypto_sign(
             wallet_handle,
             verkey.clone(),
-            message.clone(),
+            message.as_bytes(),
             ca,
         );
 
@@ -235,27 +239,54 @@ trait InputSigner<A: CryptoAPI> {
     }
 }
 
-pub fn serialize_signature(v: serde_json::Value) -> Result<String, ErrorCode> {
+/**
+    The message signed with the key of `input` to spend it.
+
+    It is the [`serialize_signature`] of
+    `[[<input>], [<output>, ...], <txn_digest>]`, with the addresses as
+    given, unqualified in a request, and the field names and amounts of the
+    ledger. `txn_digest` is the digest of the request the input pays the
+    fees of, `None` for a transfer.
+
+    [`serialize_signature`]: fn.serialize_signature.html
+*/
+pub fn signing_message(input: &Input, outputs: &Outputs, txn_digest: &Option<String>) -> Result<String, ErrorCode> {
+    // the ledger verifies the signature over its own field names and amounts
+    let vals: Vec<serde_json::Value> = with_ledger_format(|| vec![
+        Some(json!([input])),
+        Some(json!(outputs)),
+        txn_digest.clone().map(|e| json!(e)),
+    ]).into_iter().filter_map(|e| e).collect();
+
+    serialize_signature(&json!(vals))
+}
+
+/**
+    The message the ledger signs or hashes for `v`.
+
+    Walks `v` by reference, so a large request isn't copied.
+*/
+pub fn serialize_signature(v: &serde_json::Value) -> Result<String, ErrorCode> {
     do_serialize_signature(v, true)
 }
 
-fn do_serialize_signature(v: serde_json::Value, is_top_level: bool) -> Result<String, ErrorCode> {
-    match v {
+fn do_serialize_signature(v: &serde_json::Value, is_top_level: bool) -> Result<String, ErrorCode> {
+    match *v {
         serde_json::Value::Bool(value) => Ok(if value { "True".to_string() } else { "False".to_string() }),
-        serde_json::Value::Number(value) => Ok(value.to_string()),
-        serde_json::Value::String(value) => Ok(value),
-        serde_json::Value::Array(array) => {
+        serde_json::Value::Number(ref value) => Ok(value.to_string()),
+        serde_json::Value::String(ref value) => Ok(value.clone()),
+        serde_json::Value::Array(ref array) => {
             let mut result = "".to_string();
             let length = array.len();
             for (index, element) in array.iter().enumerate() {
-                result += &do_serialize_signature(element.clone(), false)?;
+                result += &do_serialize_signature(element, false)?;
                 if index < length - 1 {
                     result += ",";
                 }
             }
             Ok(result)
         }
-        serde_json::Value::Object(map) => {
+        serde_json::Value::Object(ref map) => {
             let mut result = "".to_string();
             let mut in_middle = false;
             for key in map.keys() {
@@ -266,13 +297,15 @@ fn do_serialize_signature(v: serde_json::Value, is_top_level: bool) -> Result<St
                     result += "|";
                 }
 
-                let mut value = map[key].clone();
-                if key == "raw" || key == "hash" || key == "enc" {
+                let value = &map[key];
+                let serialized = if key == "raw" || key == "hash" || key == "enc" {
                     let mut ctx = Hash::new_context()?;
                     ctx.update(&value.as_str().ok_or(ErrorCode::CommonInvalidState)?.as_bytes()).map_err(|_| ErrorCode::CommonInvalidState)?;
-                    value = serde_json::Value::String(ctx.finish2().map_err(|_| ErrorCode::CommonInvalidState)?.as_ref().to_hex());
-                }
-                result = result + key + ":" + &do_serialize_signature(value, false)?;
+                    ctx.finish2().map_err(|_| ErrorCode::CommonInvalidState)?.as_ref().to_hex()
+                } else {
+                    do_serialize_signature(value, false)?
+                };
+                result = result + key + ":" + &serialized;
                 in_middle = true;
             }
             Ok(result)
@@ -281,12 +314,113 @@ fn do_serialize_signature(v: serde_json::Value, is_top_level: bool) -> Result<St
     }
 }
 
+// Check the wallet holds each of `keys`, the verkeys of the input addresses with an address
+// of each. `cb` is called once: when every key was found, or with the first error.
+fn check_keys<A: CryptoAPI>(crypto_api: &A, wallet_handle: IndyHandle, keys: Vec<(String, String)>, cb: Box<Fn(Result<(), ErrorCode>) + Send + Sync>) -> Result<(), ErrorCode> {
+    if keys.is_empty() {
+        cb(Ok(()));
+        return Ok(());
+    }
+
+    // the keys still to be found, None once `cb` was called
+    let pending = Arc::new(Mutex::new(Some(keys.len())));
+    let cb = Arc::new(cb);
+    for (verkey, address) in keys {
+        let pending_clone = Arc::clone(&pending);
+        let cb = cb.clone();
+        let ec = crypto_api.indy_check_key(wallet_handle, verkey, move |key| {
+            let result = {
+                let mut pending = pending_clone.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                let remaining = match *pending {
+                    Some(remaining) => remaining,
+                    None => return,
+                };
+                match key {
+                    Ok(()) if remaining > 1 => {
+                        *pending = Some(remaining - 1);
+                        return;
+                    }
+                    Ok(()) => {
+                        *pending = None;
+                        Ok(())
+                    }
+                    Err(err) => {
+                        *pending = None;
+                        error!("The wallet holds no key for the input address {}: {:?}", address, err);
+                        Err(err)
+                    }
+                }
+            };
+            cb(result);
+        });
+        if ec != ErrorCode::Success {
+            // the keys still being checked are dropped
+            *pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+            return Err(ec);
+        }
+    }
+    Ok(())
+}
+
+// Sign every input, `cb` is called once: with every signature, or with the first error.
+fn sign_each<A: CryptoAPI>(crypto_api: &'static A, wallet_handle: IndyHandle, inputs: &Inputs, outputs: &Outputs, txn_digest: &Option<String>, extra: &Option<String>, cb: Arc<Fn(Result<Vec<String>, ErrorCode>) + Send + Sync>)
+                           -> Result<(), ErrorCode>
+{
+    let pending = Arc::new(Mutex::new(PendingSignatures {
+        signatures: vec![None; inputs.len()],
+        remaining: inputs.len(),
+        done: false,
+    }));
+
+    let addresses: Vec<String> = inputs.iter().map(|input| input.address.clone()).collect();
+    let pending_clone = Arc::clone(&pending);
+    let cb = Arc::new(move |signature: Result<String, ErrorCode>, position: usize| {
+        let result = {
+            let mut pending = pending_clone.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if pending.done {
+                return;
+            }
+            match signature {
+                Ok(signature) => {
+                    if pending.signatures[position].is_none() {
+                        pending.remaining -= 1;
+                    }
+                    pending.signatures[position] = Some(signature);
+                    if pending.remaining > 0 {
+                        return;
+                    }
+                    pending.done = true;
+                    Ok(pending.signatures.iter().filter_map(|signature| signature.clone()).collect())
+                }
+                Err(err) => {
+                    error!("The wallet can't sign input {} with the key of {}: {:?}", position, addresses[position], err);
+                    pending.done = true;
+                    Err(err)
+                }
+            }
+        };
+        cb(result);
+    });
+
+    for (position, input) in inputs.iter().enumerate() {
+        let cb = cb.clone();
+        if let Err(err) = <XferPayload as InputSigner<A>>::sign_input(crypto_api, wallet_handle, position, input, outputs, txn_digest, extra, Box::new(cb)) {
+            // the signatures still coming for the earlier inputs are dropped
+            pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).done = true;
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod test_xfer_payload {
     use super::*;
     use logic::config::payment_address_config::PaymentAddressConfig;
     use logic::output::Output;
     use std::sync::mpsc::channel;
+    use utils::constants::general::MAX_SUPPLY;
 
     struct CryptoApiHandler {}
 
@@ -295,7 +429,7 @@ mod test_xfer_payload {
             return Err(ErrorCode::CommonInvalidState);
         }
 
-        fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _wallet_handle: IndyHandle, verkey: String, _message: String, mut cb: F) -> ErrorCode {
+        fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _wallet_handle: IndyHandle, verkey: String, _message: &[u8], mut cb: F) -> ErrorCode {
             cb(Ok(verkey + "signed"));
             return ErrorCode::Success;
         }
@@ -305,6 +439,54 @@ mod test_xfer_payload {
         }
     }
  
+    // fails to sign with the key of the first input address
+    struct FailingCryptoApiHandler {}
+
+    impl CryptoAPI for FailingCryptoApiHandler {
+        fn indy_create_key(&self, _: IndyHandle, _: PaymentAddressConfig) -> Result<String, ErrorCode> {
+            Err(ErrorCode::CommonInvalidState)
+        }
+
+        fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _: IndyHandle, verkey: String, _: &[u8], mut cb: F) -> ErrorCode {
+            if verkey == "31VzUm5vZRfWPk38W3YJaNjrkUeD6tELmjxv42cp7Vnk" {
+                cb(Err(ErrorCode::WalletItemNotFound));
+            } else {
+                cb(Ok(verkey + "signed"));
+            }
+            ErrorCode::Success
+        }
+
+        fn indy_create_key_async<F: 'static>(&self, _: i32, _: PaymentAddressConfig, _: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+            ErrorCode::CommonInvalidState
+        }
+    }
+
+    // finds no key for one verkey, and signs nothing
+    struct MissingKeyCryptoApiHandler {}
+
+    impl CryptoAPI for MissingKeyCryptoApiHandler {
+        fn indy_create_key(&self, _: IndyHandle, _: PaymentAddressConfig) -> Result<String, ErrorCode> {
+            Err(ErrorCode::CommonInvalidState)
+        }
+
+        fn indy_crypto_sign<F: FnMut(Result<String, ErrorCode>) + 'static + Send>(&self, _: IndyHandle, _: String, _: &[u8], _: F) -> ErrorCode {
+            panic!("signed with a key missing from the wallet");
+        }
+
+        fn indy_create_key_async<F: 'static>(&self, _: i32, _: PaymentAddressConfig, _: F) -> ErrorCode where F: FnMut(ErrorCode, String) + Send {
+            ErrorCode::CommonInvalidState
+        }
+
+        fn indy_check_key<F: FnMut(Result<(), ErrorCode>) + 'static + Send>(&self, _: IndyHandle, verkey: String, mut cb: F) -> ErrorCode {
+            if verkey == "31VzUm5vZRfWPk38W3YJaNjrkUeD6tELmjxv42cp7Vnk" {
+                cb(Err(ErrorCode::WalletItemNotFound));
+            } else {
+                cb(Ok(()));
+            }
+            ErrorCode::Success
+        }
+    }
+
     fn inputs_outputs_valid() -> (Inputs, Outputs) {
         let outputs = vec![
             Output::new(String::from("TKe9eXtchV71J2qXX5HwP8rbkTBStnEEkMwQkHie265VtRSbs"), 10),
@@ -337,6 +519,7 @@ mod test_xfer_payload {
         XferPayload::sign_input(
             &CryptoApiHandler{},
             wallet_handle,
+            0,
             input,
             outputs,
             &None,
@@ -354,7 +537,7 @@ mod test_xfer_payload {
         let cb = move |result| { sender.lock().unwrap().send(result).unwrap(); };
         XferPayload::sign_inputs(&CryptoApiHandler{}, wallet_handle, inputs, outputs, &None, &None,
                                  Box::new(cb))?;
-        receiver.recv().unwrap().map(|map| map.values().cloned().collect())
+        receiver.recv().unwrap()
     }
 
     #[test]
@@ -554,4 +737,52 @@ mod test_xfer_payload {
             assert_eq!(expected_signatures, signed_payload.signatures.unwrap());
         }
     }
+
+    #[test]
+    fn sign_inputs_of_one_address_keeps_a_signature_per_input() {
+        let (inputs, outputs) = inputs_outputs_valid();
+        let inputs = vec![inputs[1].clone(), inputs[0].clone(), Input::new(inputs[1].address.clone(), 2)];
+
+        let signatures = sign_inputs_sync(&inputs, &outputs).unwrap();
+        assert_eq!(vec![
+            String::from("GyPZzuu8S1KMs5p6iE1wBzjQsFtaB7eigssW4YbdXdtesigned"),
+            String::from("31VzUm5vZRfWPk38W3YJaNjrkUeD6tELmjxv42cp7Vnksigned"),
+            String::from("GyPZzuu8S1KMs5p6iE1wBzjQsFtaB7eigssW4YbdXdtesigned"),
+        ], signatures);
+    }
+
+    #[test]
+    fn sign_inputs_reports_one_error() {
+        let (inputs, outputs) = inputs_outputs_valid();
+        let (sender, receiver) = channel();
+        let sender = Mutex::new(sender);
+        let cb = move |result| { sender.lock().unwrap().send(result).unwrap(); };
+        XferPayload::sign_inputs(&FailingCryptoApiHandler{}, 1, &inputs, &outputs, &None, &None, Box::new(cb)).unwrap();
+
+        assert_eq!(ErrorCode::WalletItemNotFound, receiver.recv().unwrap().unwrap_err());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn sign_inputs_checks_the_keys_first() {
+        let (inputs, outputs) = inputs_outputs_valid();
+        let (sender, receiver) = channel();
+        let sender = Mutex::new(sender);
+        let cb = move |result| { sender.lock().unwrap().send(result).unwrap(); };
+        XferPayload::sign_inputs(&MissingKeyCryptoApiHandler{}, 1, &inputs, &outputs, &None, &None, Box::new(cb)).unwrap();
+
+        assert_eq!(ErrorCode::WalletItemNotFound, receiver.recv().unwrap().unwrap_err());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn sign_payload_above_supply() {
+        let (inputs, mut outputs) = inputs_outputs_valid_qualified();
+        outputs[0].amount = MAX_SUPPLY;
+
+        let cb = move |_| panic!("signed above the supply");
+        let signed_payload = XferPayload::new(inputs, outputs, None).sign_transfer(&CryptoApiHandler{}, 1, Box::new(cb)).unwrap_err();
+
+        assert_eq!(ErrorCode::CommonInvalidStructure, signed_payload);
+    }
 }
@@ -1,9 +1,15 @@
 //!  Base58 helper functions
 
 
+use std::fmt;
+
 use indy::ErrorCode;
+use logic::error::SovtokenError;
 use bs58;
 use bs58::decode::DecodeError;
+use sha2::{Sha256, Digest};
+use sha3::Sha3_256;
+use utils::serde_buffers::with_buffer;
 /**
     Deserializes a base58 String object with checksum.
 
@@ -60,6 +66,185 @@ impl<I: AsRef<[u8]>> IntoBase58 for I {
 }
 
 
+/**
+    Length of the checksum appended by every [`Checksum`] but `None`.
+
+    [`Checksum`]: ./enum.Checksum.html
+*/
+pub const CHECKSUM_LEN: usize = 4;
+
+/**
+    Multibase prefix of base58 with the bitcoin alphabet.
+*/
+pub const MULTIBASE_BASE58_BTC: char = 'z';
+
+/**
+    The characters of base58 with the bitcoin alphabet, by value.
+*/
+pub const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/**
+    The checksum appended to the bytes before they are encoded.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// The bytes are encoded as they are.
+    None,
+    /// The first 4 bytes of sha256(sha256(bytes)), as base58check does.
+    DoubleSha256,
+    /// The first 4 bytes of sha3_256(bytes).
+    Sha3,
+}
+
+impl Checksum {
+    /**
+        The checksum of `bytes`, empty for `None`.
+    */
+    pub fn of(&self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            &Checksum::None => Vec::new(),
+            &Checksum::DoubleSha256 => {
+                let first = Sha256::digest(bytes);
+                Sha256::digest(&first)[..CHECKSUM_LEN].to_vec()
+            },
+            &Checksum::Sha3 => Sha3_256::digest(bytes)[..CHECKSUM_LEN].to_vec(),
+        }
+    }
+}
+
+/**
+    Why a string couldn't be decoded.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Base58Error {
+    /// The string isn't base58, holds the decoder error.
+    InvalidBase58(String),
+    /// The decoded bytes are shorter than the checksum.
+    MissingChecksum,
+    /// The checksum doesn't match the decoded bytes.
+    BadChecksum,
+    /// The decoded bytes don't have the expected length.
+    BadLength { expected: usize, found: usize },
+    /// The string doesn't start with a supported multibase prefix.
+    UnsupportedMultibase(Option<char>),
+}
+
+impl fmt::Display for Base58Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Base58Error::InvalidBase58(ref detail) => write!(f, "invalid base58: {}", detail),
+            &Base58Error::MissingChecksum => write!(f, "too short to hold a checksum"),
+            &Base58Error::BadChecksum => write!(f, "checksum doesn't match"),
+            &Base58Error::BadLength { expected, found } => write!(f, "expected {} bytes, found {}", expected, found),
+            &Base58Error::UnsupportedMultibase(Some(prefix)) => write!(f, "unsupported multibase prefix '{}'", prefix),
+            &Base58Error::UnsupportedMultibase(None) => write!(f, "missing multibase prefix"),
+        }
+    }
+}
+
+impl From<Base58Error> for ErrorCode {
+    fn from(error: Base58Error) -> ErrorCode {
+        ErrorCode::from(SovtokenError::from(error))
+    }
+}
+
+/**
+    Encode `bytes` followed by their `checksum`.
+
+    ```
+    use sovtoken::utils::base58::{encode, Checksum};
+    assert_eq!("3NbSEAfMyPeDeKn6mTppHLkVwi537H9YFCvV", encode(b"My base58 test string.", Checksum::DoubleSha256));
+    ```
+*/
+pub fn encode(bytes: &[u8], checksum: Checksum) -> String {
+    with_buffer(|data| {
+        data.extend_from_slice(bytes);
+        data.extend(checksum.of(bytes));
+        bs58::encode(&data[..]).into_string()
+    })
+}
+
+/**
+    Decode `encoded` and verify its `checksum`, returns the bytes without the checksum.
+
+    The checksum is compared in constant time.
+*/
+pub fn decode(encoded: &str, checksum: Checksum) -> Result<Vec<u8>, Base58Error> {
+    let mut data = bs58::decode(encoded).into_vec()
+        .map_err(|error| Base58Error::InvalidBase58(format!("{:?}", error)))?;
+
+    if checksum == Checksum::None {
+        return Ok(data);
+    }
+
+    let payload_len = checked_payload_len(&data, |payload| checksum.of(payload))?;
+    data.truncate(payload_len);
+    Ok(data)
+}
+
+/**
+    The length of `data` without the checksum at its end, after verifying
+    it is `checksum_of` the bytes before it.
+
+    The checksum is compared in constant time.
+*/
+pub fn checked_payload_len<F: Fn(&[u8]) -> Vec<u8>>(data: &[u8], checksum_of: F) -> Result<usize, Base58Error> {
+    if data.len() < CHECKSUM_LEN {
+        return Err(Base58Error::MissingChecksum);
+    }
+
+    let payload_len = data.len() - CHECKSUM_LEN;
+    if !constant_time_eq(&data[payload_len..], &checksum_of(&data[..payload_len])) {
+        return Err(Base58Error::BadChecksum);
+    }
+    Ok(payload_len)
+}
+
+/**
+    [`decode`] bytes which have to be `len` bytes long, e.g. a key.
+
+    [`decode`]: ./fn.decode.html
+*/
+pub fn decode_exact(encoded: &str, checksum: Checksum, len: usize) -> Result<Vec<u8>, Base58Error> {
+    let data = decode(encoded, checksum)?;
+    if data.len() != len {
+        return Err(Base58Error::BadLength { expected: len, found: data.len() });
+    }
+    Ok(data)
+}
+
+/**
+    [`encode`] with the base58btc multibase prefix.
+
+    [`encode`]: ./fn.encode.html
+*/
+pub fn encode_multibase(bytes: &[u8], checksum: Checksum) -> String {
+    format!("{}{}", MULTIBASE_BASE58_BTC, encode(bytes, checksum))
+}
+
+/**
+    [`decode`] a string with the base58btc multibase prefix.
+
+    [`decode`]: ./fn.decode.html
+*/
+pub fn decode_multibase(encoded: &str, checksum: Checksum) -> Result<Vec<u8>, Base58Error> {
+    match encoded.chars().next() {
+        Some(MULTIBASE_BASE58_BTC) => decode(&encoded[MULTIBASE_BASE58_BTC.len_utf8()..], checksum),
+        prefix => Err(Base58Error::UnsupportedMultibase(prefix)),
+    }
+}
+
+/**
+    Compare `a` and `b` in time independent of where they differ.
+*/
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+
 /*
          UNIT TESTS BELOW
          (and only unit tests---do not add more functions below this mod)
@@ -94,4 +279,53 @@ mod base58_tests {
         deserialize_base58_string("3NbSEAfMyPeDeKn6mTppHLkVwi537H9YFCvV", Ok("My base58 test string."));
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn decode_matches_base58check() {
+        let decoded = decode("3NbSEAfMyPeDeKn6mTppHLkVwi537H9YFCvV", Checksum::DoubleSha256).unwrap();
+        assert_eq!(b"My base58 test string.".to_vec(), decoded);
+    }
+
+    #[test]
+    fn decode_bad_checksum() {
+        let error = decode("3NbSEAfMyPeDeKn6mTppHLkVwi537H9YFdeV", Checksum::DoubleSha256).unwrap_err();
+        assert_eq!(Base58Error::BadChecksum, error);
+    }
+
+    #[test]
+    fn decode_missing_checksum() {
+        let error = decode(&encode(b"ab", Checksum::None), Checksum::DoubleSha256).unwrap_err();
+        assert_eq!(Base58Error::MissingChecksum, error);
+    }
+
+    #[test]
+    fn decode_exact_bad_length() {
+        let error = decode_exact(&encode(&[1, 2, 3], Checksum::DoubleSha256), Checksum::DoubleSha256, 32).unwrap_err();
+        assert_eq!(Base58Error::BadLength { expected: 32, found: 3 }, error);
+    }
+
+    #[test]
+    fn decode_keeps_leading_zeros() {
+        let bytes = vec![0, 0, 1, 2];
+        assert_eq!(bytes, decode(&encode(&bytes, Checksum::None), Checksum::None).unwrap());
+    }
+
+    #[test]
+    fn multibase_round_trip() {
+        let encoded = encode_multibase(b"multibase", Checksum::DoubleSha256);
+        assert!(encoded.starts_with('z'));
+        assert_eq!(b"multibase".to_vec(), decode_multibase(&encoded, Checksum::DoubleSha256).unwrap());
+    }
+
+    #[test]
+    fn multibase_unsupported_prefix() {
+        let error = decode_multibase("mABC", Checksum::None).unwrap_err();
+        assert_eq!(Base58Error::UnsupportedMultibase(Some('m')), error);
+    }
+
+    #[test]
+    fn constant_time_comparison() {
+        assert!(constant_time_eq(&[1, 2, 3], &[1, 2, 3]));
+        assert!(!constant_time_eq(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!constant_time_eq(&[1, 2], &[1, 2, 3]));
+    }
+}
@@ -7,6 +7,25 @@ pub static PAYMENT_ADDRESS_QUALIFIER: &'static str = "pay:sov:";
 pub static TXO_QUALIFIER: &str = "txo:sov:";
 
 pub const PROTOCOL_VERSION: u32 = 2;
+
+/**
+The protocol versions the requests can be framed with, 1 for the pools
+older than indy-node 1.4.
+*/
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[u32] = &[1, 2];
+
+/**
+Sovatoms per token, amounts are counted in sovatoms.
+*/
+pub const SOVATOMS_PER_TOKEN: u64 = 100_000_000;
+
+/**
+The tokens which can ever exist, 10 billion, in sovatoms.
+
+Below `u64::MAX`, so a sum of amounts which stays within the supply can't
+overflow.
+*/
+pub const MAX_SUPPLY: u64 = 10_000_000_000 * SOVATOMS_PER_TOKEN;
 pub const LEDGER_ID: &str = "1001";
 
 /**
@@ -40,3 +40,5 @@ pub const GET_FEES: &'static str = "20001";
 pub const NYM: &'static str = "1";
 
 pub const ATTRIB: &'static str = "100";
+
+pub const SCHEMA: &'static str = "101";
@@ -0,0 +1,33 @@
+/*!
+    Helpers for writing csv exports.
+*/
+
+/**
+    Quote `field` when it contains a separator, a quote or a newline.
+
+    ```
+        use sovtoken::utils::csv::csv_field;
+
+        assert_eq!("abc", csv_field("abc"));
+        assert_eq!("\"a,\"\"b\"\"\"", csv_field("a,\"b\""));
+    ```
+*/
+pub fn csv_field(field: &str) -> String {
+    if field.contains(',') || field.contains('"') || field.contains('\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/**
+    Join `fields` into a newline terminated csv row.
+*/
+pub fn csv_row(fields: &[&str]) -> String {
+    let mut row = fields.iter()
+        .map(|field| csv_field(field))
+        .collect::<Vec<String>>()
+        .join(",");
+    row.push('\n');
+    row
+}
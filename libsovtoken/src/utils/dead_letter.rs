@@ -0,0 +1,181 @@
+/*!
+    Dead letters, the results which couldn't be delivered to a callback.
+
+    A result is lost when no call waits for it anymore, e.g. a `_sync` call
+    which already returned, when it comes after the timeout of its libindy
+    call, see `utils::results`, or when its callback was queued for
+    `sovtoken_poll_events` in a context destroyed before it was polled.
+    Instead of only logging it, the library keeps a [`DeadLetter`], so an
+    integrator can find the responses lost and why, with
+    `sovtoken_drain_dead_letters_json`.
+
+    A dead letter holds what identifies the result, never the result itself:
+    its command handle or libindy operation, its error code, and the length
+    and sha256 of its json. The results are requests, replies and addresses,
+    and anyone calling the drain would read them.
+
+    The queue keeps the last `dead_letter_capacity` letters of the library
+    config, 64 by default, and counts the older ones dropped. A capacity of 0
+    keeps none.
+
+    A callback which panics isn't a dead letter: the callbacks are
+    `extern "C"` functions, a panic can't unwind out of them.
+
+    [`DeadLetter`]: struct.DeadLetter.html
+*/
+
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hex::ToHex;
+use sha2::{Digest, Sha256};
+
+use logic::config::library_config::library_config;
+use logic::state::{state, State};
+
+/**
+    The dead letters kept by default.
+*/
+pub const DEFAULT_DEAD_LETTER_CAPACITY: usize = 64;
+
+/**
+    Why a result wasn't delivered.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadLetterReason {
+    /// No call waits for the result of the command handle.
+    NoWaiter,
+    /// libindy called back after the timeout of the call.
+    AfterTimeout,
+    /// The callback was queued in a context destroyed before it was polled.
+    ContextDestroyed,
+}
+
+/**
+    A result which couldn't be delivered.
+
+    `err` is the error code of the result, when known. `result_len` and
+    `result_hash`, the sha256 as hex, are of its json, or of its debug form
+    for the results of libindy, the result itself isn't kept.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetter {
+    pub command_handle: Option<i32>,
+    pub operation: Option<String>,
+    pub err: Option<i32>,
+    pub result_len: usize,
+    pub result_hash: String,
+    pub reason: DeadLetterReason,
+    pub timestamp: u64,
+}
+
+/**
+    The dead letters kept, and how many older ones were dropped.
+*/
+#[derive(Debug, Default)]
+pub struct DeadLetterQueue {
+    letters: VecDeque<DeadLetter>,
+    dropped: u64,
+}
+
+/**
+    The dead letters drained, as `sovtoken_drain_dead_letters_json` returns them.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DrainedLetters {
+    pub dead_letters: Vec<DeadLetter>,
+    pub dropped: u64,
+}
+
+/**
+    Keep the dead letter of a result which couldn't be delivered, in the state of the current context.
+*/
+pub fn capture(command_handle: Option<i32>, operation: Option<&str>, err: Option<i32>, result: &str, reason: DeadLetterReason) {
+    let capacity = library_config().dead_letter_capacity;
+    capture_in(&state(), capacity, command_handle, operation, err, result, reason);
+}
+
+/**
+    Keep the dead letter of a result in `state`, e.g. of another context
+    than the current one, keeping at most `capacity` letters.
+*/
+pub fn capture_in(state: &State, capacity: usize, command_handle: Option<i32>, operation: Option<&str>, err: Option<i32>, result: &str, reason: DeadLetterReason) {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    warn!("Keeping a result as a dead letter, {:?}, command handle: {:?}, operation: {:?}", reason, command_handle, operation);
+
+    let mut hasher = Sha256::default();
+    hasher.input(result.as_bytes());
+
+    let mut queue = state.dead_letters.lock();
+    queue.letters.push_back(DeadLetter {
+        command_handle,
+        operation: operation.map(String::from),
+        err,
+        result_len: result.len(),
+        result_hash: hasher.result().to_hex(),
+        reason,
+        timestamp,
+    });
+    while queue.letters.len() > capacity {
+        queue.letters.pop_front();
+        queue.dropped += 1;
+    }
+}
+
+/**
+    Take up to `max` dead letters, the oldest first, with the count of the
+    letters dropped since the last drain.
+*/
+pub fn drain(max: usize) -> DrainedLetters {
+    let state = state();
+    let mut queue = state.dead_letters.lock();
+    let count = ::std::cmp::min(max, queue.letters.len());
+    let dead_letters = queue.letters.drain(..count).collect();
+    let dropped = queue.dropped;
+    queue.dropped = 0;
+    DrainedLetters { dead_letters, dropped }
+}
+
+/**
+    How many dead letters are kept.
+*/
+pub fn len() -> usize {
+    state().dead_letters.lock().letters.len()
+}
+
+
+#[cfg(test)]
+mod dead_letter_tests {
+    use logic::config::library_config::LibraryConfig;
+    use logic::context;
+    use super::*;
+
+    #[test]
+    fn letters_over_the_capacity_are_dropped() {
+        // a context of its own, the other tests may leave dead letters
+        let mut config = LibraryConfig::default();
+        config.dead_letter_capacity = 2;
+        let own = context::find(context::create(config)).unwrap();
+
+        context::in_context(own, || {
+            for command_handle in 0..3 {
+                capture(Some(command_handle), None, Some(0), "{}", DeadLetterReason::NoWaiter);
+            }
+            assert_eq!(2, len());
+
+            let drained = drain(1);
+            assert_eq!(1, drained.dropped);
+            assert_eq!(Some(1), drained.dead_letters[0].command_handle);
+            assert_eq!(2, drained.dead_letters[0].result_len);
+            assert_eq!("44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a", drained.dead_letters[0].result_hash);
+
+            let drained = drain(usize::max_value());
+            assert_eq!(0, drained.dropped);
+            assert_eq!(Some(2), drained.dead_letters[0].command_handle);
+            assert_eq!(0, len());
+        });
+    }
+}
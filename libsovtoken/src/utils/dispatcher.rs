@@ -0,0 +1,250 @@
+/*!
+    Dispatcher module decides on which thread the callbacks of the handlers
+    run, so applications embedding libsovtoken don't have to hop threads in
+    every callback themselves.
+
+    Only the handlers called by the application directly go through the
+    dispatcher. The handlers registered with libindy call back libindy, which
+    does its own dispatching.
+*/
+
+use std::sync::mpsc::channel;
+use std::thread;
+
+use indy::ErrorCode;
+
+use logic::config::library_config::library_config;
+use logic::context::{self, Context};
+use logic::state::{default_state, state, State};
+use utils::constants::general::JsonCallbackUnwrapped;
+use utils::dead_letter::{self, DeadLetterReason};
+use utils::envelope;
+use utils::ffi_support::{with_c_str, with_secret_c_str};
+use utils::secret_sharing::zeroize_string;
+use utils::span::{self, Span};
+use utils::sync_call::is_sync_callback;
+
+/**
+    Where the callbacks run, set with `callback_dispatch` in the library config.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DispatchMode {
+    /// On the thread finishing the call, the caller's or a libindy thread.
+    Inline,
+    /// On one dedicated callback thread, in the order the calls finished.
+    Thread,
+    /// Queued until the application calls `sovtoken_poll_events`.
+    Poll,
+}
+
+impl Default for DispatchMode {
+    fn default() -> Self {
+        DispatchMode::Inline
+    }
+}
+
+/**
+    A callback waiting to be called.
+*/
+pub struct Event {
+    cb: JsonCallbackUnwrapped,
+    command_handle: i32,
+    err: i32,
+    json: String,
+    span: Span,
+    context: Context,
+}
+
+impl Event {
+    fn call(self) {
+        let Event { cb, command_handle, err, json, span, context } = self;
+        context::in_context(context, || span::in_span(span, || with_c_str(&json, |json| cb(command_handle, err, json))));
+    }
+}
+
+/**
+    Call `cb` with the result of a command, in the configured [`DispatchMode`].
+
+    The callback of a `_sync` call runs inline, its caller is blocked waiting for it.
+
+    [`DispatchMode`]: enum.DispatchMode.html
+*/
+pub fn dispatch(cb: JsonCallbackUnwrapped, command_handle: i32, err: i32, json: String) {
+    let mode = if is_sync_callback(cb) { DispatchMode::Inline } else { library_config().callback_dispatch };
+    let span = Span { command_handle: Some(command_handle), ..span::current() };
+    dispatch_with(mode, Event { cb, command_handle, err, json, span, context: context::current() });
+}
+
+/**
+    Call `cb` with a secret result, a seed or its shares, on this thread.
+
+    The result isn't queued or handed to the callback thread, nor kept as a
+    dead letter. It is passed in a buffer of its own, wrapped as `data_type`
+    when the config asks for envelopes, and every copy of it is zeroed once
+    `cb` returned. `data` is json with `is_json`, text otherwise.
+*/
+pub fn dispatch_secret(cb: JsonCallbackUnwrapped, command_handle: i32, data_type: &str, mut data: String, is_json: bool) {
+    let mut wrapped = envelope::wrap_secret(data_type, &data, is_json);
+    {
+        let json = wrapped.as_ref().unwrap_or(&data);
+        span::in_span(Span { command_handle: Some(command_handle), ..span::current() }, || {
+            with_secret_c_str(json, |json| cb(command_handle, ErrorCode::Success as i32, json))
+        });
+    }
+
+    if let Some(ref mut wrapped) = wrapped {
+        zeroize_string(wrapped);
+    }
+    zeroize_string(&mut data);
+}
+
+/**
+    Call up to `max_events` queued callbacks on the calling thread.
+
+    Returns how many callbacks were called.
+*/
+pub fn poll_events(max_events: usize) -> usize {
+    let mut called = 0;
+    while called < max_events {
+        // the lock is released before the callback runs, so it can start new commands
+        let event = state().callback_queue.lock().pop_front();
+        match event {
+            Some(event) => event.call(),
+            None => break,
+        }
+        called += 1;
+    }
+    called
+}
+
+/**
+    Keep the callbacks still queued in the destroyed context of `state` as
+    dead letters of the default context, they can't be polled anymore.
+*/
+pub fn drop_queued(state: &State) {
+    let events: Vec<Event> = state.callback_queue.lock().drain(..).collect();
+    if events.is_empty() {
+        return;
+    }
+
+    let default = default_state();
+    let capacity = default.config.read().dead_letter_capacity;
+    for event in events {
+        dead_letter::capture_in(&default, capacity, Some(event.command_handle), None, Some(event.err), &event.json, DeadLetterReason::ContextDestroyed);
+    }
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn dispatch_with(mode: DispatchMode, event: Event) {
+    match mode {
+        DispatchMode::Inline => event.call(),
+        DispatchMode::Poll => state().callback_queue.lock().push_back(event),
+        DispatchMode::Thread => {
+            let state = state();
+            let mut sender = state.callback_thread.lock();
+            if sender.is_none() {
+                let (tx, rx) = channel::<Event>();
+                thread::spawn(move || {
+                    for event in rx {
+                        event.call();
+                    }
+                });
+                *sender = Some(tx);
+            }
+
+            if let Err(e) = sender.as_ref().unwrap().send(event) {
+                error!("The callback thread is gone, calling back inline");
+                e.0.call();
+            }
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod dispatcher_tests {
+    use libc::c_char;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use logic::config::library_config::LibraryConfig;
+    use utils::ffi_support::str_from_char_ptr;
+    use super::*;
+
+    lazy_static! {
+        static ref CALLS: Mutex<Vec<(i32, i32, String, thread::ThreadId)>> = Mutex::new(Vec::new());
+    }
+
+    extern fn record(command_handle: i32, err: i32, json: *const c_char) -> i32 {
+        let json = str_from_char_ptr(json).unwrap().to_string();
+        CALLS.lock().unwrap().push((command_handle, err, json, thread::current().id()));
+        0
+    }
+
+    fn event(command_handle: i32) -> Event {
+        Event { cb: record, command_handle, err: 0, json: String::from("{}"), span: Span::default(), context: context::current() }
+    }
+
+    fn calls_of(command_handle: i32) -> Vec<(i32, i32, String, thread::ThreadId)> {
+        CALLS.lock().unwrap().iter().filter(|call| call.0 == command_handle).cloned().collect()
+    }
+
+    #[test]
+    fn inline_calls_back_on_the_calling_thread() {
+        dispatch_with(DispatchMode::Inline, event(101));
+
+        let calls = calls_of(101);
+        assert_eq!(1, calls.len());
+        assert_eq!(String::from("{}"), calls[0].2);
+        assert_eq!(thread::current().id(), calls[0].3);
+    }
+
+    #[test]
+    fn poll_waits_for_the_pump() {
+        dispatch_with(DispatchMode::Poll, event(102));
+        dispatch_with(DispatchMode::Poll, event(102));
+        assert!(calls_of(102).is_empty());
+
+        poll_events(usize::max_value());
+        assert_eq!(2, calls_of(102).len());
+    }
+
+    #[test]
+    fn thread_calls_back_on_the_callback_thread() {
+        dispatch_with(DispatchMode::Thread, event(103));
+
+        for _ in 0..500 {
+            if !calls_of(103).is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let calls = calls_of(103);
+        assert_eq!(1, calls.len());
+        assert_ne!(thread::current().id(), calls[0].3);
+    }
+
+    #[test]
+    fn queued_in_a_destroyed_context_is_a_dead_letter() {
+        let handle = context::create(LibraryConfig::default());
+        let own = context::find(handle).unwrap();
+        context::in_context(own, || dispatch_with(DispatchMode::Poll, Event { context: context::current(), ..event(104) }));
+
+        context::destroy(handle).unwrap();
+
+        let letters = dead_letter::drain(usize::max_value()).dead_letters;
+        assert!(letters.iter().any(|letter| letter.command_handle == Some(104) && letter.reason == DeadLetterReason::ContextDestroyed));
+        assert!(calls_of(104).is_empty());
+    }
+
+    #[test]
+    fn default_mode_is_inline() {
+        assert_eq!(DispatchMode::Inline, DispatchMode::default());
+    }
+}
@@ -0,0 +1,212 @@
+/*!
+    Envelope module wraps the json the handlers call back with in
+    `{"ver": 1, "type": <string>, "data": <json>}`, when `json_envelope` is
+    set in the library config.
+
+    Wrappers can dispatch on the type instead of guessing it from the shape
+    of the json, and the data can change shape in a new version without
+    breaking the parsers of the old one. A result which isn't json, e.g. a
+    payment address, is wrapped as a json string.
+
+    The json data is embedded as it is, byte for byte: a signed request
+    keeps the order of its keys and the text of its numbers under the
+    envelope, and [`unwrap`] gives it back the same way.
+
+    Only the handlers called by the application directly wrap their results.
+    The handlers registered with libindy call back libindy, which expects
+    the results unwrapped.
+
+    [`unwrap`]: fn.unwrap.html
+*/
+
+use indy::ErrorCode;
+use serde_json::{self, Value};
+use serde_json::value::{RawValue, to_raw_value};
+
+use logic::config::library_config::library_config;
+use utils::secret_sharing::zeroize_string;
+
+/**
+    The version of the envelope, not of its data.
+*/
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/**
+    A result wrapped with its type.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Envelope {
+    pub ver: u32,
+    #[serde(rename = "type")]
+    pub data_type: String,
+    pub data: Value,
+}
+
+/**
+    `json` wrapped as `data_type` when the library config asks for envelopes,
+    `json` as it is otherwise.
+*/
+pub fn wrap(data_type: &str, json: String) -> String {
+    if library_config().json_envelope {
+        envelope_of(data_type, json)
+    } else {
+        json
+    }
+}
+
+/**
+    The secret `data` wrapped as `data_type` when the library config asks for
+    envelopes, `None` otherwise. `data` is json with `is_json`, text wrapped
+    as a json string otherwise.
+
+    Unlike [`wrap`], the data isn't parsed, so no copy of it is left in
+    memory but the one returned, to zero once it was called back with.
+
+    [`wrap`]: fn.wrap.html
+*/
+pub fn wrap_secret(data_type: &str, data: &str, is_json: bool) -> Option<String> {
+    if !library_config().json_envelope {
+        return None;
+    }
+
+    let mut quoted = if is_json { None } else { serde_json::to_string(data).ok() };
+    let data_json = quoted.as_ref().map(String::as_str).unwrap_or(data);
+    let head = format!(r#"{{"ver":{},"type":{},"data":"#, ENVELOPE_VERSION, Value::from(data_type));
+
+    // allocated once, a growing string would leave copies of the data behind
+    let mut wrapped = String::with_capacity(head.len() + data_json.len() + 1);
+    wrapped.push_str(&head);
+    wrapped.push_str(data_json);
+    wrapped.push('}');
+
+    if let Some(ref mut quoted) = quoted {
+        zeroize_string(quoted);
+    }
+    Some(wrapped)
+}
+
+/**
+    The data of the envelope `json`, as the handler would have called back
+    without envelopes. Anything else than an envelope is returned as it is.
+
+    ```
+        use sovtoken::utils::envelope::unwrap;
+
+        let wrapped = r#"{"ver": 1, "type": "payment_address", "data": "pay:sov:a"}"#;
+        assert_eq!("pay:sov:a", unwrap(wrapped).unwrap());
+        assert_eq!("[]", unwrap("[]").unwrap());
+    ```
+
+    Fails with `CommonInvalidStructure` for an envelope of a newer version.
+*/
+pub fn unwrap(json: &str) -> Result<String, ErrorCode> {
+    let envelope: RawEnvelope = match serde_json::from_str(json) {
+        Ok(envelope) => envelope,
+        Err(_) => return Ok(json.to_string()),
+    };
+
+    if envelope.ver > ENVELOPE_VERSION {
+        error!("The envelope of version {} is newer than {}", envelope.ver, ENVELOPE_VERSION);
+        return Err(ErrorCode::CommonInvalidStructure);
+    }
+
+    let data = envelope.data.get();
+    match serde_json::from_str::<String>(data) {
+        Ok(text) => Ok(text),
+        Err(_) => Ok(data.to_string()),
+    }
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// An envelope whose data is left as the text it was given in.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawEnvelope<'a> {
+    ver: u32,
+    #[serde(rename = "type")]
+    data_type: String,
+    #[serde(borrow)]
+    data: &'a RawValue,
+}
+
+fn envelope_of(data_type: &str, json: String) -> String {
+    let text: Box<RawValue>;
+    let data = match serde_json::from_str::<&RawValue>(&json) {
+        Ok(data) => data,
+        Err(_) => match to_raw_value(&json) {
+            Ok(quoted) => {
+                text = quoted;
+                &*text
+            },
+            Err(_) => return json.clone(),
+        },
+    };
+
+    let envelope = RawEnvelope { ver: ENVELOPE_VERSION, data_type: data_type.to_string(), data };
+    serde_json::to_string(&envelope).unwrap_or_else(|_| json.clone())
+}
+
+#[cfg(test)]
+mod envelope_tests {
+    use logic::config::library_config::LibraryConfig;
+    use logic::context;
+    use super::*;
+
+    #[test]
+    fn json_data_is_kept_as_json() {
+        let wrapped = envelope_of("receipts", String::from(r#"[{"seqNo": 4}]"#));
+        let envelope: Envelope = serde_json::from_str(&wrapped).unwrap();
+        assert_eq!("receipts", envelope.data_type);
+        assert_eq!(json!([{"seqNo": 4}]), envelope.data);
+        assert_eq!(json!([{"seqNo": 4}]).to_string(), unwrap(&wrapped).unwrap());
+    }
+
+    #[test]
+    fn json_data_is_kept_byte_for_byte() {
+        let request = r#"{"reqId":1,"operation":{"type":"10001","amount":1.0e2},"identifier":"Th7MpTaRZVRYnPiabds81Y"}"#;
+        let wrapped = envelope_of("request", String::from(request));
+
+        assert!(wrapped.contains(request));
+        assert_eq!(request, unwrap(&wrapped).unwrap());
+    }
+
+    #[test]
+    fn text_data_round_trips() {
+        let address = String::from("pay:sov:dctKSXBbv2My3TGGUgTFjkxu1A9JM3Sscd5FydY4dkxnfwA7q");
+        let wrapped = envelope_of("payment_address", address.clone());
+        assert_eq!(address, unwrap(&wrapped).unwrap());
+    }
+
+    #[test]
+    fn secret_data_wrapped_as_text_or_json() {
+        let mut config = LibraryConfig::default();
+        config.json_envelope = true;
+        let handle = context::create(config);
+
+        context::in_context(context::find(handle).unwrap(), || {
+            let wrapped = wrap_secret("seed", "000000000000000000000000Trustee1", false).unwrap();
+            assert_eq!("000000000000000000000000Trustee1", unwrap(&wrapped).unwrap());
+
+            let wrapped = wrap_secret("seed_shares", r#"["a","b"]"#, true).unwrap();
+            assert_eq!(json!({"ver": 1, "type": "seed_shares", "data": ["a", "b"]}), serde_json::from_str::<Value>(&wrapped).unwrap());
+        });
+        context::destroy(handle).unwrap();
+    }
+
+    #[test]
+    fn newer_envelope() {
+        let wrapped = json!({"ver": ENVELOPE_VERSION + 1, "type": "receipts", "data": []}).to_string();
+        assert_eq!(ErrorCode::CommonInvalidStructure, unwrap(&wrapped).unwrap_err());
+    }
+
+    #[test]
+    fn object_with_more_fields_is_not_an_envelope() {
+        let json = json!({"ver": 1, "type": "10001", "data": {}, "reqId": 3}).to_string();
+        assert_eq!(json, unwrap(&json).unwrap());
+    }
+}
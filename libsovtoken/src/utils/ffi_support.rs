@@ -5,7 +5,57 @@
 use libc::c_char;
 use std::ffi::{CString, CStr};
 use indy::ErrorCode;
+use logic::state::state;
 use utils::json_conversion::JsonDeserialize;
+use utils::secret_sharing::zeroize;
+
+/**
+    Most buffers kept in the pool of [`with_c_str`].
+
+    [`with_c_str`]: fn.with_c_str.html
+*/
+pub const MAX_POOLED_BUFFERS: usize = 64;
+
+/**
+    Largest buffer, in bytes, given back to the pool of [`with_c_str`].
+    Larger buffers are freed, so one huge reply doesn't stay allocated.
+
+    [`with_c_str`]: fn.with_c_str.html
+*/
+pub const MAX_POOLED_BUFFER_BYTES: usize = 64 * 1024;
+
+/**
+    Counters of the buffer pool of [`with_c_str`].
+
+    [`with_c_str`]: fn.with_c_str.html
+*/
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferPoolStats {
+    /// Buffers taken from the pool or allocated.
+    pub lent: u64,
+    /// Lent buffers which came from the pool.
+    pub reused: u64,
+    /// Buffers freed instead of given back, the pool being full or the buffer too large.
+    pub freed: u64,
+    /// Buffers in the pool now.
+    pub pooled: usize,
+}
+
+/**
+    The buffers of [`with_c_str`], kept in the library state.
+
+    [`with_c_str`]: fn.with_c_str.html
+*/
+pub struct BufferPool {
+    buffers: Vec<Vec<u8>>,
+    stats: BufferPoolStats,
+}
+
+impl BufferPool {
+    pub fn new() -> Self {
+        BufferPool { buffers: Vec::new(), stats: BufferPoolStats::default() }
+    }
+}
 
 /**
     utility method for converting *const c_char a &str.  Returns None
@@ -51,6 +101,87 @@ pub fn c_pointer_from_string(string: String) -> *const c_char {
 }
 
 
+/**
+    Call `f` with `string` as a C string, in a buffer of a pool.
+
+    The pointer is only valid during `f`, which is what a callback gets from
+    libindy and the handlers: the caller copies the string before it
+    returns. Unlike [`c_pointer_from_string`], the buffer isn't leaked but
+    reused by the next call, so building many requests doesn't allocate a
+    string for each callback. A string with a NUL ends there for C.
+
+    ```
+        use sovtoken::utils::ffi_support::{with_c_str, str_from_char_ptr};
+
+        let copy = with_c_str("{}", |pointer| str_from_char_ptr(pointer).unwrap().to_string());
+        assert_eq!("{}", copy);
+    ```
+
+    [`c_pointer_from_string`]: fn.c_pointer_from_string.html
+*/
+pub fn with_c_str<F, R>(string: &str, f: F) -> R
+    where F: FnOnce(*const c_char) -> R
+{
+    let state = state();
+    let mut buffer = {
+        let mut pool = state.buffer_pool.lock();
+        pool.stats.lent += 1;
+        match pool.buffers.pop() {
+            Some(buffer) => {
+                pool.stats.reused += 1;
+                buffer
+            }
+            None => Vec::new(),
+        }
+    };
+
+    buffer.clear();
+    buffer.extend_from_slice(string.as_bytes());
+    buffer.push(0);
+
+    // the pool isn't locked during `f`, which may call back into the library
+    let res = f(buffer.as_ptr() as *const c_char);
+
+    let mut pool = state.buffer_pool.lock();
+    if buffer.capacity() <= MAX_POOLED_BUFFER_BYTES && pool.buffers.len() < MAX_POOLED_BUFFERS {
+        pool.buffers.push(buffer);
+    } else {
+        pool.stats.freed += 1;
+    }
+    res
+}
+
+/**
+    Call `f` with the secret `string` as a C string, in a buffer of its own.
+
+    Unlike [`with_c_str`], the buffer isn't pooled, and is zeroed once `f`
+    returned, so a seed or its shares don't stay in memory.
+
+    [`with_c_str`]: fn.with_c_str.html
+*/
+pub fn with_secret_c_str<F, R>(string: &str, f: F) -> R
+    where F: FnOnce(*const c_char) -> R
+{
+    let mut buffer = Vec::with_capacity(string.len() + 1);
+    buffer.extend_from_slice(string.as_bytes());
+    buffer.push(0);
+
+    let res = f(buffer.as_ptr() as *const c_char);
+    zeroize(&mut buffer);
+    res
+}
+
+/**
+    The counters of the buffer pool of [`with_c_str`].
+
+    [`with_c_str`]: fn.with_c_str.html
+*/
+pub fn buffer_pool_stats() -> BufferPoolStats {
+    let state = state();
+    let pool = state.buffer_pool.lock();
+    BufferPoolStats { pooled: pool.buffers.len(), ..pool.stats }
+}
+
 /**
     Deserialize a char ptr to a struct.
 */
@@ -111,7 +242,8 @@ mod ffi_support_tests {
     use std::ffi::CString;
     use serde_json::Value;
     use utils::general::ResultExtension;
-    use utils::ffi_support::{str_from_char_ptr, cstring_from_str, deserialize_from_char_ptr, c_pointer_from_string, string_from_char_ptr};
+    use utils::ffi_support::{str_from_char_ptr, cstring_from_str, deserialize_from_char_ptr, c_pointer_from_string, string_from_char_ptr,
+                             with_c_str, buffer_pool_stats, MAX_POOLED_BUFFER_BYTES};
     use indy::ErrorCode;
 
     static VALID_DUMMY_JSON: &'static str = r#"{"field1":"data"}"#;
@@ -144,6 +276,37 @@ mod ffi_support_tests {
         assert_eq!(string2, string);
     }
 
+    #[test]
+    fn pooled_buffer_is_reused() {
+        let first = with_c_str("first", |pointer| {
+            assert_eq!("first", str_from_char_ptr(pointer).unwrap());
+            pointer as usize
+        });
+        let before = buffer_pool_stats();
+        with_c_str("second", |pointer| assert_eq!("second", str_from_char_ptr(pointer).unwrap()));
+        let after = buffer_pool_stats();
+
+        assert_ne!(0, first);
+        assert!(after.lent > before.lent);
+        assert!(after.reused > before.reused);
+    }
+
+    #[test]
+    fn nested_pooled_buffers() {
+        with_c_str("outer", |outer| {
+            with_c_str("inner", |inner| assert_eq!("inner", str_from_char_ptr(inner).unwrap()));
+            assert_eq!("outer", str_from_char_ptr(outer).unwrap());
+        });
+    }
+
+    #[test]
+    fn large_buffer_is_freed() {
+        let large = "a".repeat(MAX_POOLED_BUFFER_BYTES + 1);
+        let before = buffer_pool_stats();
+        with_c_str(&large, |pointer| assert_eq!(large.len(), str_from_char_ptr(pointer).unwrap().len()));
+        assert!(buffer_pool_stats().freed > before.freed);
+    }
+
     #[test]
     fn deserialize_error_with_null_pointer() {
         let pointer = ptr::null();
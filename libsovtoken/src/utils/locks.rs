@@ -0,0 +1,275 @@
+/*!
+    Locks module wraps `Mutex` and `RwLock` with a name and counters of how
+    often they were taken and had to wait, for the global state of the
+    library.
+
+    A lock poisoned by a panicking thread is taken anyway: a panic in one
+    callback shouldn't make every later call of the application fail.
+
+    With the `deadlock_detection` feature, every thread keeps the names of
+    the locks it holds, and taking a lock the thread already holds, or two
+    locks in the reverse order of an earlier thread, panics with their names
+    instead of hanging some day in production.
+*/
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/**
+    Counters of a lock.
+*/
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LockStats {
+    /// Times the lock was taken.
+    pub acquired: usize,
+    /// Times the lock was held by another thread and had to be waited for.
+    pub contended: usize,
+}
+
+/**
+    A `Mutex` with a name and counters.
+*/
+pub struct InstrumentedMutex<T> {
+    name: &'static str,
+    inner: Mutex<T>,
+    counters: Counters,
+}
+
+impl<T> InstrumentedMutex<T> {
+    pub fn new(name: &'static str, value: T) -> Self {
+        InstrumentedMutex { name, inner: Mutex::new(value), counters: Counters::new() }
+    }
+
+    pub fn lock(&self) -> LockGuard<MutexGuard<T>> {
+        deadlock_detection::acquiring(self.name);
+        let guard = match self.inner.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => {
+                self.counters.contended.fetch_add(1, Ordering::Relaxed);
+                self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+            }
+        };
+        self.counters.acquired.fetch_add(1, Ordering::Relaxed);
+        LockGuard { name: self.name, guard }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn stats(&self) -> LockStats {
+        self.counters.stats()
+    }
+}
+
+/**
+    A `RwLock` with a name and counters.
+
+    Readers count as acquisitions too. With `deadlock_detection`, a thread
+    reading a lock it already reads is reported: a writer waiting between
+    the two reads blocks the second one.
+*/
+pub struct InstrumentedRwLock<T> {
+    name: &'static str,
+    inner: RwLock<T>,
+    counters: Counters,
+}
+
+impl<T> InstrumentedRwLock<T> {
+    pub fn new(name: &'static str, value: T) -> Self {
+        InstrumentedRwLock { name, inner: RwLock::new(value), counters: Counters::new() }
+    }
+
+    pub fn read(&self) -> LockGuard<RwLockReadGuard<T>> {
+        deadlock_detection::acquiring(self.name);
+        let guard = match self.inner.try_read() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => {
+                self.counters.contended.fetch_add(1, Ordering::Relaxed);
+                self.inner.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+            }
+        };
+        self.counters.acquired.fetch_add(1, Ordering::Relaxed);
+        LockGuard { name: self.name, guard }
+    }
+
+    pub fn write(&self) -> LockGuard<RwLockWriteGuard<T>> {
+        deadlock_detection::acquiring(self.name);
+        let guard = match self.inner.try_write() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => {
+                self.counters.contended.fetch_add(1, Ordering::Relaxed);
+                self.inner.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+            }
+        };
+        self.counters.acquired.fetch_add(1, Ordering::Relaxed);
+        LockGuard { name: self.name, guard }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn stats(&self) -> LockStats {
+        self.counters.stats()
+    }
+}
+
+/**
+    The guard of an instrumented lock, releases the lock when dropped.
+*/
+pub struct LockGuard<G> {
+    name: &'static str,
+    guard: G,
+}
+
+impl<G: Deref> Deref for LockGuard<G> {
+    type Target = G::Target;
+
+    fn deref(&self) -> &G::Target {
+        &self.guard
+    }
+}
+
+impl<G: DerefMut> DerefMut for LockGuard<G> {
+    fn deref_mut(&mut self) -> &mut G::Target {
+        &mut self.guard
+    }
+}
+
+impl<G> Drop for LockGuard<G> {
+    fn drop(&mut self) {
+        deadlock_detection::released(self.name);
+    }
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+struct Counters {
+    acquired: AtomicUsize,
+    contended: AtomicUsize,
+}
+
+impl Counters {
+    fn new() -> Self {
+        Counters { acquired: AtomicUsize::new(0), contended: AtomicUsize::new(0) }
+    }
+
+    fn stats(&self) -> LockStats {
+        LockStats {
+            acquired: self.acquired.load(Ordering::Relaxed),
+            contended: self.contended.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(feature = "deadlock_detection")]
+mod deadlock_detection {
+    use std::cell::RefCell;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    thread_local! {
+        static HELD: RefCell<Vec<&'static str>> = RefCell::new(Vec::new());
+    }
+
+    lazy_static! {
+        // (first, then) for every pair of locks a thread held at once
+        static ref ORDER: Mutex<HashSet<(&'static str, &'static str)>> = Mutex::new(HashSet::new());
+    }
+
+    pub fn acquiring(name: &'static str) {
+        HELD.with(|held| {
+            let mut held = held.borrow_mut();
+            if held.contains(&name) {
+                panic!("Deadlock: the lock {:?} is taken twice by the same thread", name);
+            }
+
+            let mut order = ORDER.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            for &first in held.iter() {
+                if order.contains(&(name, first)) {
+                    panic!("Potential deadlock: the locks {:?} and {:?} are taken in both orders", first, name);
+                }
+                order.insert((first, name));
+            }
+            held.push(name);
+        });
+    }
+
+    pub fn released(name: &'static str) {
+        HELD.with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(position) = held.iter().rposition(|held_name| *held_name == name) {
+                held.remove(position);
+            }
+        });
+    }
+}
+
+#[cfg(not(feature = "deadlock_detection"))]
+mod deadlock_detection {
+    #[inline]
+    pub fn acquiring(_name: &'static str) {}
+
+    #[inline]
+    pub fn released(_name: &'static str) {}
+}
+
+
+#[cfg(test)]
+mod locks_tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn mutex_counts_acquisitions() {
+        let mutex = InstrumentedMutex::new("locks_tests::mutex", 1);
+        *mutex.lock() += 1;
+        assert_eq!(2, *mutex.lock());
+        assert_eq!(2, mutex.stats().acquired);
+        assert_eq!(0, mutex.stats().contended);
+    }
+
+    #[test]
+    fn rw_lock_counts_readers_and_writers() {
+        let lock = InstrumentedRwLock::new("locks_tests::rw_lock", String::from("a"));
+        lock.write().push('b');
+        assert_eq!("ab", *lock.read());
+        assert_eq!(2, lock.stats().acquired);
+    }
+
+    #[test]
+    fn poisoned_mutex_is_taken() {
+        let mutex = Arc::new(InstrumentedMutex::new("locks_tests::poisoned", 1));
+        let mutex_clone = Arc::clone(&mutex);
+        let _ = thread::spawn(move || {
+            let _guard = mutex_clone.lock();
+            panic!("poisoning the lock");
+        }).join();
+
+        assert_eq!(1, *mutex.lock());
+    }
+
+    #[cfg(feature = "deadlock_detection")]
+    #[test]
+    #[should_panic(expected = "taken in both orders")]
+    fn reverse_order_is_detected() {
+        let first = InstrumentedMutex::new("locks_tests::first", ());
+        let second = InstrumentedMutex::new("locks_tests::second", ());
+        {
+            let _first = first.lock();
+            let _second = second.lock();
+        }
+        let _second = second.lock();
+        let _first = first.lock();
+    }
+}
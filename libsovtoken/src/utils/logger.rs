@@ -7,12 +7,74 @@ use std::io::Write;
 
 use env_logger::{Builder, fmt};
 use log::{Record, Level, Metadata, Log, LevelFilter};
+use logic::config::library_config::library_config;
+use utils::span;
 #[cfg(target_os = "android")]
 use android_logger;
 #[cfg(target_os = "android")]
 use android_logger::Filter;
 
 
+/**
+    How the log lines are written, set with `log_format` in the library config.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `level|target|file:line| [command_handle=1 reqId=2] message`
+    Text,
+    /// One json object per line, for the log collectors of tracing systems.
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        LogFormat::Text
+    }
+}
+
+/**
+    The line logged for `record`, tagged with the span of the thread.
+
+    ```
+        extern crate log;
+        extern crate sovtoken;
+
+        use sovtoken::utils::logger::{format_record, LogFormat};
+        use sovtoken::utils::span;
+
+        let line = span::with_command_handle(4, || {
+            format_record(LogFormat::Json, &log::Record::builder().args(format_args!("signed")).build())
+        });
+        assert!(line.contains(r#""commandHandle":4"#));
+    ```
+*/
+pub fn format_record(format: LogFormat, record: &Record) -> String {
+    let span = span::current();
+    match format {
+        LogFormat::Text => {
+            let span = if span.is_empty() { String::new() } else { format!("[{}] ", span) };
+            format!("{:>5}|{:<30}|{:>35}:{:<4}| {}{}",
+                record.level(),
+                record.target(),
+                record.file().unwrap_or(""),
+                record.line().unwrap_or(0),
+                span,
+                record.args()
+            )
+        },
+        LogFormat::Json => json!({
+            "level": record.level().to_string(),
+            "target": record.target(),
+            "file": record.file(),
+            "line": record.line(),
+            "commandHandle": span.command_handle,
+            "reqId": span.req_id,
+            "message": record.args().to_string(),
+        }).to_string(),
+    }
+}
+
 /**
     Routes logging to console all of the time regardless of RUST_LOG setting.  helpful for unit tests
 */
@@ -25,13 +87,7 @@ impl Log for ConsoleLogger {
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
-            println!("\r\n{:>5}|{:<30}|{:>35}:{:<4}| {}",
-                record.level(),
-                record.target(),
-                record.file().unwrap(),
-                record.line().unwrap(),
-                record.args()
-            );
+            println!("\r\n{}", format_record(library_config().log_format, record));
         }
     }
 
@@ -42,21 +98,18 @@ impl Log for ConsoleLogger {
 
 /**
     Required call to get logging in libsovtoken to appear, depending on call (debug! vs error! etc)
-    and RUST_LOG env setting.
+    and the `log_level` of the library config, or the RUST_LOG env setting without one.
 */
 pub fn init_log() {
     if cfg!(target_os = "android") {
         #[cfg(target_os = "android")]
-        let log_filter = match env::var("RUST_LOG") {
-            Ok(val) => match val.to_lowercase().as_ref(){
-                "error" => Filter::default().with_min_level(Level::Error),
-                "warn" => Filter::default().with_min_level(Level::Warn),
-                "info" => Filter::default().with_min_level(Level::Info),
-                "debug" => Filter::default().with_min_level(Level::Debug),
-                "trace" => Filter::default().with_min_level(Level::Trace),
-                _ => Filter::default().with_min_level(Level::Error),
-            },
-            Err(..) => Filter::default().with_min_level(Level::Error)
+        let log_filter = match log_filter().to_lowercase().as_ref() {
+            "error" => Filter::default().with_min_level(Level::Error),
+            "warn" => Filter::default().with_min_level(Level::Warn),
+            "info" => Filter::default().with_min_level(Level::Info),
+            "debug" => Filter::default().with_min_level(Level::Debug),
+            "trace" => Filter::default().with_min_level(Level::Trace),
+            _ => Filter::default().with_min_level(Level::Error),
         };
 
         //Set logging to off when deploying production android app.
@@ -65,26 +118,26 @@ pub fn init_log() {
 
         info!("Logging for Android");
     } else{
+        let format = library_config().log_format;
         Builder::new()
-            .format(|buf: &mut fmt::Formatter, record: &Record| {
-                writeln!(
-                    buf,
-                    "{:>5}|{:<30}|{:>35}:{:<4}| {}",
-                    record.level(),
-                    record.target(),
-                    record.file().unwrap(),
-                    record.line().unwrap(),
-                    record.args()
-                )
+            .format(move |buf: &mut fmt::Formatter, record: &Record| {
+                writeln!(buf, "{}", format_record(format, record))
             })
             .filter(None, LevelFilter::Off)
-            .parse(env::var("RUST_LOG").as_ref().map(String::as_str).unwrap_or(""))
+            .parse(&log_filter())
             .try_init()
             .ok();
     }
 
 }
 
+// the log_level of the library config, RUST_LOG when it has none
+fn log_filter() -> String {
+    library_config().log_level
+        .or_else(|| env::var("RUST_LOG").ok())
+        .unwrap_or_default()
+}
+
 macro_rules! _map_err {
     ($lvl:expr, $expr:expr) => (
         |err| {
@@ -0,0 +1,224 @@
+/*!
+    Metrics module keeps per handler counters and latency histograms, so
+    applications embedding libsovtoken can scrape health data through
+    `sovtoken_get_metrics_json`.
+*/
+
+use std::time::{Duration, Instant};
+
+use indy::ErrorCode;
+use serde_json;
+
+use logic::messages::Message;
+use logic::state::state;
+use utils::rate_limit;
+
+/**
+    Upper bounds, in milliseconds, of the latency histogram buckets.
+
+    Anything slower ends up in the last (unbounded) bucket.
+*/
+pub const LATENCY_BUCKETS_MS: &'static [u64] = &[1, 5, 10, 25, 50, 100, 250, 500, 1000];
+
+/**
+    Name of the metric recorded for signing the inputs of a payload.
+*/
+pub const SIGNING_METRIC: &'static str = "sign_inputs";
+
+/**
+    A latency bucket. `le_ms` of `None` is the unbounded bucket.
+*/
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Bucket {
+    pub le_ms: Option<u64>,
+    pub count: u64,
+}
+
+/**
+    Counters and latency histogram of a single handler or operation.
+
+    `throttled` counts the calls refused by the rate limit, which aren't
+    counted as calls, and `last_throttled` is the busy message of the last
+    one.
+*/
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub calls: u64,
+    pub failures: u64,
+    pub throttled: u64,
+    pub last_throttled: Option<Message>,
+    pub latency_sum_ms: f64,
+    pub latency_ms: Vec<Bucket>,
+}
+
+impl Metric {
+    fn new() -> Self {
+        let mut buckets: Vec<Bucket> = LATENCY_BUCKETS_MS.iter()
+            .map(|le| Bucket { le_ms: Some(*le), count: 0 })
+            .collect();
+        buckets.push(Bucket { le_ms: None, count: 0 });
+
+        Metric {
+            calls: 0,
+            failures: 0,
+            throttled: 0,
+            last_throttled: None,
+            latency_sum_ms: 0.0,
+            latency_ms: buckets,
+        }
+    }
+
+    fn observe(&mut self, elapsed: Duration, success: bool) {
+        let elapsed_ms = duration_as_ms(elapsed);
+
+        self.calls += 1;
+        if !success {
+            self.failures += 1;
+        }
+        self.latency_sum_ms += elapsed_ms;
+
+        if let Some(bucket) = self.latency_ms.iter_mut()
+            .find(|bucket| bucket.le_ms.map_or(true, |le| elapsed_ms <= le as f64)) {
+            bucket.count += 1;
+        }
+    }
+}
+
+/**
+    Record one call of `name` which took `elapsed`.
+*/
+pub fn record(name: &str, elapsed: Duration, success: bool) {
+    let state = state();
+    let mut registry = state.metrics.lock();
+    registry.entry(name.to_string())
+        .or_insert_with(Metric::new)
+        .observe(elapsed, success);
+}
+
+/**
+    Run an api handler and record its latency and outcome.
+
+    A return value other than `ErrorCode::Success` counts as a failure.
+*/
+pub fn measure_handler<F: FnOnce() -> i32>(name: &str, handler: F) -> i32 {
+    let started = Instant::now();
+    let res = handler();
+    record(name, started.elapsed(), res == ErrorCode::Success as i32);
+    res
+}
+
+/**
+    Run an api handler of the operation type `operation` using the wallet
+    of `wallet_handle`, when the rate limit of the operation allows, see
+    `utils::rate_limit`.
+
+    The latency and outcome are recorded as by `measure_handler`. A call
+    refused by the rate limit fails with the busy error and is counted as
+    throttled. Its message is kept in the metric, not as the last error of
+    the thread, which is libindy's.
+*/
+pub fn measure_wallet_handler<F: FnOnce() -> i32>(name: &str, operation: &str, wallet_handle: i32, handler: F) -> i32 {
+    if let Err(error) = rate_limit::acquire(operation, wallet_handle) {
+        warn!("{} refused: {}", name, error);
+        let ec = error.error_code();
+        record_throttled(name, error.message());
+        return ec as i32;
+    }
+    measure_handler(name, handler)
+}
+
+/**
+    Record one call of `name` refused by the rate limit with `message`.
+*/
+pub fn record_throttled(name: &str, message: Message) {
+    let state = state();
+    let mut registry = state.metrics.lock();
+    let metric = registry.entry(name.to_string()).or_insert_with(Metric::new);
+    metric.throttled += 1;
+    metric.last_throttled = Some(message);
+}
+
+/**
+    Get a copy of the metric recorded for `name`.
+
+    ```
+        use sovtoken::utils::metrics;
+        use std::time::Duration;
+
+        metrics::record("doc_example", Duration::from_millis(3), true);
+        assert_eq!(1, metrics::metric("doc_example").unwrap().calls);
+    ```
+*/
+pub fn metric(name: &str) -> Option<Metric> {
+    state().metrics.lock().get(name).cloned()
+}
+
+/**
+    Serialize every recorded metric to json, keyed by name.
+*/
+pub fn metrics_json() -> Result<String, ErrorCode> {
+    let state = state();
+    let registry = state.metrics.lock();
+    serde_json::to_string(&*registry)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidState))
+}
+
+fn duration_as_ms(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+
+#[cfg(test)]
+mod metrics_tests {
+    use super::*;
+
+    #[test]
+    fn record_counts_calls_and_failures() {
+        record("record_counts_calls_and_failures", Duration::from_millis(2), true);
+        record("record_counts_calls_and_failures", Duration::from_millis(2), false);
+
+        let metric = metric("record_counts_calls_and_failures").unwrap();
+        assert_eq!(2, metric.calls);
+        assert_eq!(1, metric.failures);
+        assert_eq!(4.0, metric.latency_sum_ms);
+    }
+
+    #[test]
+    fn record_fills_latency_buckets() {
+        record("record_fills_latency_buckets", Duration::from_millis(1), true);
+        record("record_fills_latency_buckets", Duration::from_millis(30), true);
+        record("record_fills_latency_buckets", Duration::from_secs(2), true);
+
+        let buckets = metric("record_fills_latency_buckets").unwrap().latency_ms;
+        assert_eq!(Bucket { le_ms: Some(1), count: 1 }, buckets[0]);
+        assert_eq!(Bucket { le_ms: Some(50), count: 1 }, buckets[4]);
+        assert_eq!(Bucket { le_ms: None, count: 1 }, buckets[LATENCY_BUCKETS_MS.len()]);
+    }
+
+    #[test]
+    fn measure_handler_failure() {
+        let res = measure_handler("measure_handler_failure", || ErrorCode::CommonInvalidStructure as i32);
+
+        assert_eq!(ErrorCode::CommonInvalidStructure as i32, res);
+        assert_eq!(1, metric("measure_handler_failure").unwrap().failures);
+    }
+
+    #[test]
+    fn throttled_calls_are_not_calls() {
+        record_throttled("throttled_calls_are_not_calls");
+
+        let metric = metric("throttled_calls_are_not_calls").unwrap();
+        assert_eq!(1, metric.throttled);
+        assert_eq!(0, metric.calls);
+    }
+
+    #[test]
+    fn metrics_json_contains_metric() {
+        measure_handler("metrics_json_contains_metric", || ErrorCode::Success as i32);
+
+        let json: serde_json::Value = serde_json::from_str(&metrics_json().unwrap()).unwrap();
+        assert_eq!(1, json["metrics_json_contains_metric"]["calls"]);
+        assert_eq!(0, json["metrics_json_contains_metric"]["failures"]);
+    }
+}
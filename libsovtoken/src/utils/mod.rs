@@ -4,12 +4,26 @@
 
 pub mod base58;
 pub mod constants;
+pub mod csv;
+pub mod dead_letter;
+pub mod dispatcher;
+pub mod envelope;
 #[macro_use] pub mod ffi_support;
 pub mod general;
+pub mod locks;
 #[macro_use] pub mod json_conversion;
 #[macro_use] pub mod logger;
 #[macro_use] pub mod conversions;
+pub mod metrics;
 pub mod random;
+pub mod rate_limit;
+pub mod results;
+pub mod secret_sharing;
+pub mod serde_buffers;
+pub mod span;
+pub mod sync_call;
+pub mod timer;
+pub mod wire_format;
 
 #[cfg(any(test, feature = "integration"))]
 pub mod test;
@@ -1,33 +1,187 @@
 //! Contains functions for random data generation
+//!
+//! The random data of the library, e.g. req_ids and the ids of pending
+//! transactions, comes from the [`RngProvider`] of the state. It is OS
+//! random, unless `random_seed` is set in the library config: then it is a
+//! ChaCha generator seeded with it, so test runs build byte identical
+//! requests across wrappers. Keys and the shares of secrets are always OS
+//! random.
+//!
+//! A seed makes the req_ids and nonces predictable, so it is only honored
+//! by the builds of the tests and with the `seeded_random` feature. Other
+//! builds reject a library config with a `random_seed`.
+//!
+//! [`RngProvider`]: enum.RngProvider.html
 
 extern crate rand;
 
 
-use self::rand::Rng;
-use self::rand::random;
+use self::rand::{ChaChaRng, Rng, SeedableRng};
+use std::sync::atomic::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use logic::config::library_config::library_config;
+use logic::state::state;
 use logic::type_aliases::ReqId;
+
+/**
+    Whether this build honors the `random_seed` of the library config.
+*/
+pub const SEEDED_RANDOM: bool = cfg!(any(test, feature = "seeded_random"));
+
+/**
+    How the req_id of the requests built is chosen, set in the library config.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReqIdStrategy {
+    /// A random number for every request.
+    Random,
+    /// Consecutive numbers, starting at the time the state was created in
+    /// nanoseconds, so the requests of a run can be told apart in the logs,
+    /// also from a run started in the same second. For the same req_ids on
+    /// every run, keep `random` and set the `random_seed`.
+    Sequential,
+}
+
+/**
+    Where the random data comes from.
+*/
+pub enum RngProvider {
+    /// The thread generator of rand, seeded by the OS.
+    Os,
+    /// A ChaCha generator and the seed it started from.
+    Seeded(u64, ChaChaRng),
+}
+
+impl RngProvider {
+    /**
+        The generator of `seed`, the same data for the same seed.
+    */
+    pub fn seeded(seed: u64) -> RngProvider {
+        RngProvider::Seeded(seed, ChaChaRng::from_seed(&[seed as u32, (seed >> 32) as u32]))
+    }
+
+    /**
+        The seed of the generator, `None` when it is OS random.
+    */
+    pub fn seed(&self) -> Option<u64> {
+        match *self {
+            RngProvider::Os => None,
+            RngProvider::Seeded(seed, _) => Some(seed),
+        }
+    }
+}
+
+impl Rng for RngProvider {
+    fn next_u32(&mut self) -> u32 {
+        match *self {
+            RngProvider::Os => rand::thread_rng().next_u32(),
+            RngProvider::Seeded(_, ref mut rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match *self {
+            RngProvider::Os => rand::thread_rng().next_u64(),
+            RngProvider::Seeded(_, ref mut rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match *self {
+            RngProvider::Os => rand::thread_rng().fill_bytes(dest),
+            RngProvider::Seeded(_, ref mut rng) => rng.fill_bytes(dest),
+        }
+    }
+}
+
+/**
+    Call `f` with the generator of the state.
+
+    The generator follows the `random_seed` of the library config, it is
+    seeded again when the seed changes. It is OS random in the builds
+    without `SEEDED_RANDOM`.
+*/
+pub fn with_rng<T, F: FnOnce(&mut RngProvider) -> T>(f: F) -> T {
+    let seed = if SEEDED_RANDOM { library_config().random_seed } else { None };
+    let state = state();
+    let mut rng = state.rng.lock();
+    if rng.seed() != seed {
+        *rng = seed.map_or(RngProvider::Os, RngProvider::seeded);
+    }
+    f(&mut rng)
+}
+
 /**
    Builds a string of random numbers of the inputted length
 */
 pub fn rand_string(length : usize) -> String {
-    let s = rand::thread_rng()
-        .gen_ascii_chars()
-        .take(length)
-        .collect::<String>();
-
-    return s;
+    with_rng(|rng| rng.gen_ascii_chars().take(length).collect::<String>())
 }
 
 pub fn rand_bytes(length : usize) -> Vec<u8> {
-    rand::thread_rng()
-        .gen_iter::<u8>().take(length).collect::<Vec<u8>>()
+    with_rng(|rng| rng.gen_iter::<u8>().take(length).collect::<Vec<u8>>())
 }
 
 /**
     `request` requires a req_id which is random number that can not be duplicate
-    to any current request . This function simply calls and returns rand's
-    `random` function using a u32
+    to any current request. This function returns a random u32 of the
+    generator of the state, see [`with_rng`].
+
+    [`with_rng`]: fn.with_rng.html
 */
 pub fn rand_req_id() -> ReqId {
-    random::<ReqId>()
-}
\ No newline at end of file
+    with_rng(|rng| rng.gen::<ReqId>())
+}
+
+/**
+    The first req_id of the sequential strategy, the time in nanoseconds
+    cut to a `ReqId`.
+*/
+pub fn first_sequential_req_id() -> ReqId {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs().wrapping_mul(1_000_000_000).wrapping_add(d.subsec_nanos() as u64) as ReqId)
+        .unwrap_or(1)
+}
+
+/**
+    The req_id of a new request, following the `req_id_strategy` of the library config.
+*/
+pub fn next_req_id() -> ReqId {
+    match library_config().req_id_strategy {
+        ReqIdStrategy::Random => rand_req_id(),
+        ReqIdStrategy::Sequential => state().next_req_id.fetch_add(1, Ordering::SeqCst) as ReqId,
+    }
+}
+
+
+#[cfg(test)]
+mod random_tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_data() {
+        let mut first = RngProvider::seeded(42);
+        let mut second = RngProvider::seeded(42);
+
+        let first_data: Vec<u64> = first.gen_iter().take(4).collect();
+        let second_data: Vec<u64> = second.gen_iter().take(4).collect();
+        assert_eq!(first_data, second_data);
+        assert_eq!(Some(42), first.seed());
+    }
+
+    #[test]
+    fn sequences_started_in_the_same_second_differ() {
+        let first = first_sequential_req_id();
+        ::std::thread::sleep(::std::time::Duration::from_millis(1));
+        assert_ne!(first, first_sequential_req_id());
+    }
+
+    #[test]
+    fn other_seed_other_data() {
+        let first: Vec<u64> = RngProvider::seeded(42).gen_iter().take(4).collect();
+        let second: Vec<u64> = RngProvider::seeded(43).gen_iter().take(4).collect();
+        assert_ne!(first, second);
+    }
+}
@@ -0,0 +1,216 @@
+/*!
+    Rate limiting of the handlers building requests with a wallet, so a
+    deployment can cap the token operations of a wallet per second.
+
+    The limits are set by operation type in the `rate_limits` of the library
+    config, e.g. `{"payment": 5}`, the types are in [`OPERATIONS`]. An
+    operation without a limit isn't limited. Every wallet has a bucket per
+    operation holding as many calls as the limit, refilled at the limit per
+    second, so a wallet can burst up to the limit at once. A bucket unused
+    for a second is full again and is evicted, it is created full on the
+    next call.
+
+    A call finding its bucket empty fails at once with the busy error. The
+    handlers run on the thread of libindy, whose last error the application
+    can't read, so the refusal is reported in the metrics instead: counted
+    as `throttled`, with the busy message of the last refusal, see
+    [`measure_wallet_handler`].
+
+    [`OPERATIONS`]: constant.OPERATIONS.html
+    [`measure_wallet_handler`]: ../metrics/fn.measure_wallet_handler.html
+*/
+
+use std::time::{Duration, Instant};
+
+use logic::config::library_config::library_config;
+use logic::error::SovtokenError;
+use logic::messages;
+use logic::state::state;
+
+pub const CREATE_ADDRESS: &'static str = "create_address";
+pub const PAYMENT: &'static str = "payment";
+pub const FEES: &'static str = "fees";
+pub const GET_UTXO: &'static str = "get_utxo";
+pub const SET_FEES: &'static str = "set_fees";
+pub const GET_FEES: &'static str = "get_fees";
+pub const MINT: &'static str = "mint";
+pub const VERIFY: &'static str = "verify";
+pub const TEST_TOKENS: &'static str = "test_tokens";
+
+/**
+    The operation types a limit can be set for.
+*/
+pub const OPERATIONS: &'static [&'static str] = &[CREATE_ADDRESS, PAYMENT, FEES, GET_UTXO, SET_FEES, GET_FEES, MINT, VERIFY, TEST_TOKENS];
+
+// Buckets kept before the ones unused for a second are evicted.
+const EVICTION_THRESHOLD: usize = 256;
+
+/**
+    The calls left to a wallet for an operation.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct TokenBucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    fn full(limit: u32, now: Instant) -> Self {
+        TokenBucket { tokens: limit as f64, refilled: now }
+    }
+
+    // Take a call from the bucket, refilled at `limit` calls per second since the last call.
+    fn take(&mut self, limit: u32, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.refilled);
+        let elapsed_secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
+        self.tokens = (self.tokens + elapsed_secs * limit as f64).min(limit as f64);
+        self.refilled = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Whatever the limit, a bucket unused for a second refilled to the limit.
+    fn is_full(&self, now: Instant) -> bool {
+        now.duration_since(self.refilled) >= Duration::from_secs(1)
+    }
+}
+
+/**
+    Take a call of the operation type `operation` for the wallet of `wallet_handle`.
+
+    Fails with the busy error, `CommonInvalidState` with the message
+    SOV-0048, when the wallet made as many calls in the last second as the
+    limit of the operation allows.
+*/
+pub fn acquire(operation: &str, wallet_handle: i32) -> Result<(), SovtokenError> {
+    acquire_at(operation, wallet_handle, Instant::now())
+}
+
+/**
+    Forget the buckets of the wallet of `wallet_handle`, e.g. once it is closed.
+*/
+pub fn forget_wallet(wallet_handle: i32) {
+    state().rate_limits.lock().retain(|&(_, handle), _| handle != wallet_handle);
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+fn acquire_at(operation: &str, wallet_handle: i32, now: Instant) -> Result<(), SovtokenError> {
+    let limit = match library_config().rate_limits.get(operation) {
+        Some(&limit) => limit,
+        None => return Ok(()),
+    };
+
+    let state = state();
+    let mut buckets = state.rate_limits.lock();
+    if buckets.len() >= EVICTION_THRESHOLD {
+        buckets.retain(|_, bucket| !bucket.is_full(now));
+    }
+    let taken = buckets
+        .entry((operation.to_string(), wallet_handle))
+        .or_insert_with(|| TokenBucket::full(limit, now))
+        .take(limit, now);
+
+    if taken {
+        Ok(())
+    } else {
+        Err(SovtokenError::Busy(messages::RATE_LIMITED.with(&[operation.to_string(), wallet_handle.to_string(), limit.to_string()])))
+    }
+}
+
+
+#[cfg(test)]
+mod rate_limit_tests {
+    use indy::ErrorCode;
+
+    use logic::config::library_config::LibraryConfig;
+    use utils::metrics;
+    use utils::test::context::in_own_context;
+    use super::*;
+
+    fn limited(operation: &str, limit: u32) -> LibraryConfig {
+        let mut config = LibraryConfig::default();
+        config.rate_limits.insert(operation.to_string(), limit);
+        config
+    }
+
+    #[test]
+    fn bucket_refills_at_the_limit() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::full(2, start);
+
+        assert!(bucket.take(2, start));
+        assert!(bucket.take(2, start));
+        assert!(!bucket.take(2, start));
+        assert!(bucket.take(2, start + Duration::from_millis(500)));
+        assert!(!bucket.take(2, start + Duration::from_millis(600)));
+
+        // the bucket holds no more than the limit
+        let later = start + Duration::from_secs(10);
+        assert!(bucket.take(2, later));
+        assert!(bucket.take(2, later));
+        assert!(!bucket.take(2, later));
+    }
+
+    #[test]
+    fn operation_without_limit() {
+        in_own_context(LibraryConfig::default(), || {
+            for _ in 0..100 {
+                assert!(acquire(PAYMENT, 1).is_ok());
+            }
+        });
+    }
+
+    #[test]
+    fn limit_per_wallet_and_operation() {
+        in_own_context(limited(PAYMENT, 2), || {
+            let now = Instant::now();
+            assert!(acquire_at(PAYMENT, 1, now).is_ok());
+            assert!(acquire_at(PAYMENT, 1, now).is_ok());
+
+            let busy = acquire_at(PAYMENT, 1, now).unwrap_err();
+            assert_eq!(ErrorCode::CommonInvalidState, busy.error_code());
+            assert_eq!(messages::RATE_LIMITED.id, busy.message().id);
+
+            assert!(acquire_at(PAYMENT, 2, now).is_ok());
+            assert!(acquire_at(MINT, 1, now).is_ok());
+            assert!(acquire_at(PAYMENT, 1, now + Duration::from_millis(500)).is_ok());
+        });
+    }
+
+    #[test]
+    fn unused_buckets_are_evicted() {
+        in_own_context(limited(PAYMENT, 1), || {
+            let start = Instant::now();
+            for wallet_handle in 0..EVICTION_THRESHOLD as i32 {
+                acquire_at(PAYMENT, wallet_handle, start).unwrap();
+            }
+            assert_eq!(EVICTION_THRESHOLD, state().rate_limits.lock().len());
+
+            acquire_at(PAYMENT, -1, start + Duration::from_secs(1)).unwrap();
+            assert_eq!(1, state().rate_limits.lock().len());
+        });
+    }
+
+    #[test]
+    fn refused_call_counted_as_throttled() {
+        in_own_context(limited(PAYMENT, 1), || {
+            let name = "rate_limit_tests_handler";
+            assert_eq!(0, metrics::measure_wallet_handler(name, PAYMENT, 1, || ErrorCode::Success as i32));
+            assert_eq!(ErrorCode::CommonInvalidState as i32, metrics::measure_wallet_handler(name, PAYMENT, 1, || ErrorCode::Success as i32));
+
+            let metric = metrics::metric(name).unwrap();
+            assert_eq!(1, metric.calls);
+            assert_eq!(1, metric.throttled);
+            assert_eq!(messages::RATE_LIMITED.id, metric.last_throttled.unwrap().id);
+        });
+    }
+}
@@ -0,0 +1,385 @@
+/*!
+    Results of the libindy calls.
+
+    libindy calls back with an error code and up to three values. A call
+    hands libindy a callback of a [`ResultHandler`], made with `one`, `two`
+    or `three` of its [`ResultSender`] by the number of values. A blocking
+    call waits for the result with [`ResultHandler::wait`], a call which
+    can't block has it passed on to a callback of its own with
+    [`ResultHandler::callback`].
+
+    Both give up after the timeout of the handler, with the error of
+    [`timed_out`]: the wait returns it, the callback is called with it and a
+    later result of libindy is kept as a dead letter, see
+    `utils::dead_letter`. The timeouts of the callbacks are watched by the
+    single thread of `utils::timer`. A wait also ends when the call is
+    cancelled with a [`Canceller`] of the handler, e.g. by another thread
+    shutting down, and when libindy dropped its callback without calling it.
+
+    ```
+    # extern crate indy;
+    # extern crate sovtoken;
+    # fn main() {
+    use indy::ErrorCode;
+    use sovtoken::utils::results::ResultHandler;
+
+    let handler = ResultHandler::new("test", 100);
+    let mut cb = handler.sender().two();
+    cb(ErrorCode::Success, 1, String::from("one"));
+    assert_eq!(Ok((1, String::from("one"))), handler.wait(ErrorCode::Success));
+    # }
+    ```
+
+    [`ResultHandler`]: struct.ResultHandler.html
+    [`ResultSender`]: struct.ResultSender.html
+    [`ResultHandler::wait`]: struct.ResultHandler.html#method.wait
+    [`ResultHandler::callback`]: struct.ResultHandler.html#method.callback
+    [`Canceller`]: struct.Canceller.html
+    [`timed_out`]: fn.timed_out.html
+*/
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use indy::ErrorCode;
+
+use logic::context;
+use logic::error::SovtokenError;
+use logic::messages;
+use utils::dead_letter::{self, DeadLetterReason};
+use utils::timer;
+
+type Callback<T> = Box<FnMut(Result<T, ErrorCode>) + Send>;
+
+// What a waiting handler receives: the result of libindy, the cancellation of the call or the drop of its callback.
+enum Outcome<T> {
+    Done(ErrorCode, T),
+    Cancelled,
+    Dropped,
+}
+
+// Where the senders of a handler send the result to, shared by them.
+enum Sink<T> {
+    Wait(Sender<Outcome<T>>),
+    Callback(&'static str, Mutex<Option<Callback<T>>>),
+}
+
+/**
+    The result of a libindy call, of `T`, a tuple for several values.
+*/
+pub struct ResultHandler<T> {
+    operation: &'static str,
+    timeout_ms: u64,
+    sink: Arc<Sink<T>>,
+    sender: Sender<Outcome<T>>,
+    receiver: Receiver<Outcome<T>>,
+}
+
+/**
+    Sends the result of libindy to its [`ResultHandler`].
+
+    [`ResultHandler`]: struct.ResultHandler.html
+*/
+pub struct ResultSender<T> {
+    sink: Arc<Sink<T>>,
+}
+
+/**
+    Cancels the wait of a [`ResultHandler`], from any thread.
+
+    [`ResultHandler`]: struct.ResultHandler.html
+*/
+pub struct Canceller<T> {
+    sender: Sender<Outcome<T>>,
+}
+
+impl<T> Clone for ResultSender<T> {
+    fn clone(&self) -> Self {
+        ResultSender { sink: Arc::clone(&self.sink) }
+    }
+}
+
+impl<T> Clone for Canceller<T> {
+    fn clone(&self) -> Self {
+        Canceller { sender: self.sender.clone() }
+    }
+}
+
+/**
+    The error of `operation` timing out after `timeout_ms`.
+*/
+pub fn timed_out(operation: &str, timeout_ms: u64) -> ErrorCode {
+    ErrorCode::from(SovtokenError::TimedOut(messages::TIMED_OUT.with(&[operation.to_string(), timeout_ms.to_string()])))
+}
+
+impl<T> ResultHandler<T> {
+    /**
+        The handler of `operation`, giving up after `timeout_ms`. A timeout
+        of 0 waits forever.
+    */
+    pub fn new(operation: &'static str, timeout_ms: u64) -> Self {
+        let (sender, receiver) = channel();
+        let sink = Arc::new(Sink::Wait(sender.clone()));
+        ResultHandler { operation, timeout_ms, sink, sender, receiver }
+    }
+
+    pub fn sender(&self) -> ResultSender<T> {
+        ResultSender { sink: Arc::clone(&self.sink) }
+    }
+
+    pub fn canceller(&self) -> Canceller<T> {
+        Canceller { sender: self.sender.clone() }
+    }
+
+    /**
+        Wait for the result, `err` is what the libindy call returned.
+
+        Fails at once with `err` when it isn't `Success`, as libindy won't
+        call back. Otherwise fails with the error libindy called back with,
+        with `CommonIOError` on a timeout, and with `CommonInvalidState`
+        when the call was cancelled or its callback dropped.
+    */
+    pub fn wait(self, err: ErrorCode) -> Result<T, ErrorCode> {
+        let ResultHandler { operation, timeout_ms, sink, sender, receiver } = self;
+        if err != ErrorCode::Success {
+            return Err(err);
+        }
+        // once the senders handed out are gone too, the sink tells the callback was dropped
+        drop(sink);
+        drop(sender);
+
+        let received = if timeout_ms == 0 {
+            receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            receiver.recv_timeout(Duration::from_millis(timeout_ms))
+        };
+
+        match received {
+            Ok(Outcome::Done(ErrorCode::Success, value)) => Ok(value),
+            Ok(Outcome::Done(ec, _)) => Err(ec),
+            Ok(Outcome::Cancelled) => Err(ErrorCode::from(SovtokenError::InvalidState(messages::CANCELLED.with(&[operation.to_string()])))),
+            Ok(Outcome::Dropped) | Err(RecvTimeoutError::Disconnected) => {
+                error!("libindy dropped the callback of {}", operation);
+                Err(ErrorCode::CommonInvalidState)
+            },
+            Err(RecvTimeoutError::Timeout) => Err(timed_out(operation, timeout_ms)),
+        }
+    }
+}
+
+impl<T: Debug + Send + 'static> ResultHandler<T> {
+    /**
+        Pass the result on to `cb` instead of waiting for it, for the calls
+        which can't block.
+
+        `cb` is called once, with the result or with the error of
+        [`timed_out`] after the timeout of the handler. When the libindy
+        call fails, [`ResultSender::cancel`] drops `cb` without calling it.
+
+        [`timed_out`]: fn.timed_out.html
+        [`ResultSender::cancel`]: struct.ResultSender.html#method.cancel
+    */
+    pub fn callback<F>(self, cb: F) -> ResultSender<T>
+        where F: FnMut(Result<T, ErrorCode>) + Send + 'static
+    {
+        let ResultHandler { operation, timeout_ms, .. } = self;
+        let sink = Arc::new(Sink::Callback(operation, Mutex::new(Some(Box::new(cb) as Callback<T>))));
+
+        // the callback is gone once it was called, only a callback still waiting times out
+        if timeout_ms != 0 {
+            let watched = Arc::clone(&sink);
+            let context = context::current();
+            timer::after(Duration::from_millis(timeout_ms), move || {
+                if let Some(mut cb) = watched.take_callback() {
+                    warn!("libindy didn't call back within {} ms for {}", timeout_ms, operation);
+                    context::in_context(context, || cb(Err(timed_out(operation, timeout_ms))));
+                }
+            });
+        }
+
+        ResultSender { sink }
+    }
+}
+
+impl<T: Debug + Send> ResultSender<T> {
+    /**
+        Send the result. A result after the wait ended is dropped, a result
+        after the callback timed out is kept as a dead letter.
+    */
+    pub fn send(&self, err: ErrorCode, value: T) {
+        match *self.sink {
+            Sink::Wait(ref sender) => {
+                let _ = sender.send(Outcome::Done(err, value));
+            },
+            Sink::Callback(operation, _) => match self.sink.take_callback() {
+                Some(mut cb) => cb(if err == ErrorCode::Success { Ok(value) } else { Err(err) }),
+                None => {
+                    warn!("Dropping the result of {}, it came after its timeout", operation);
+                    dead_letter::capture(None, Some(operation), Some(err as i32), &format!("{:?}", value), DeadLetterReason::AfterTimeout);
+                },
+            },
+        }
+    }
+
+    /**
+        Drop the callback of [`ResultHandler::callback`] without calling
+        it, when libindy won't call back because it refused the call.
+
+        [`ResultHandler::callback`]: struct.ResultHandler.html#method.callback
+    */
+    pub fn cancel(&self) {
+        self.sink.take_callback();
+    }
+
+    /**
+        The callback of a call with one value.
+    */
+    pub fn one(self) -> impl FnMut(ErrorCode, T) + Send {
+        move |err, value| self.send(err, value)
+    }
+}
+
+impl ResultSender<()> {
+    /**
+        The callback of a call without a value.
+    */
+    pub fn empty(self) -> impl FnMut(ErrorCode) + Send {
+        move |err| self.send(err, ())
+    }
+}
+
+impl<A: Debug + Send, B: Debug + Send> ResultSender<(A, B)> {
+    /**
+        The callback of a call with two values.
+    */
+    pub fn two(self) -> impl FnMut(ErrorCode, A, B) + Send {
+        move |err, a, b| self.send(err, (a, b))
+    }
+}
+
+impl<A: Debug + Send, B: Debug + Send, C: Debug + Send> ResultSender<(A, B, C)> {
+    /**
+        The callback of a call with three values.
+    */
+    pub fn three(self) -> impl FnMut(ErrorCode, A, B, C) + Send {
+        move |err, a, b, c| self.send(err, (a, b, c))
+    }
+}
+
+impl<T> Canceller<T> {
+    /**
+        End the wait with the cancelled error, unless it already ended.
+    */
+    pub fn cancel(&self) {
+        let _ = self.sender.send(Outcome::Cancelled);
+    }
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+impl<T> Sink<T> {
+    fn take_callback(&self) -> Option<Callback<T>> {
+        match *self {
+            Sink::Callback(_, ref cb) => cb.lock().ok().and_then(|mut cb| cb.take()),
+            Sink::Wait(_) => None,
+        }
+    }
+}
+
+impl<T> Drop for Sink<T> {
+    // the last sender is gone: a wait still waiting won't get a result
+    fn drop(&mut self) {
+        if let Sink::Wait(ref sender) = *self {
+            let _ = sender.send(Outcome::Dropped);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod results_tests {
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn results_of_three_values() {
+        let handler = ResultHandler::new("test", 1000);
+        let mut cb = handler.sender().three();
+        thread::spawn(move || cb(ErrorCode::Success, 1, 2, String::from("three")));
+        assert_eq!(Ok((1, 2, String::from("three"))), handler.wait(ErrorCode::Success));
+    }
+
+    #[test]
+    fn wait_for_an_error() {
+        let handler = ResultHandler::<u8>::new("test", 10);
+        handler.sender().send(ErrorCode::WalletItemNotFound, 0);
+        assert_eq!(ErrorCode::WalletItemNotFound, handler.wait(ErrorCode::Success).unwrap_err());
+
+        let handler = ResultHandler::<()>::new("test", 10);
+        assert_eq!(ErrorCode::CommonInvalidParam3, handler.wait(ErrorCode::CommonInvalidParam3).unwrap_err());
+    }
+
+    #[test]
+    fn wait_times_out() {
+        let handler = ResultHandler::<u8>::new("test", 10);
+        let _cb = handler.sender().one();
+        assert_eq!(ErrorCode::CommonIOError, handler.wait(ErrorCode::Success).unwrap_err());
+    }
+
+    #[test]
+    fn cancelled_or_dropped_call() {
+        let handler = ResultHandler::<u8>::new("test", 0);
+        let canceller = handler.canceller();
+        thread::spawn(move || canceller.cancel());
+        assert_eq!(ErrorCode::CommonInvalidState, handler.wait(ErrorCode::Success).unwrap_err());
+
+        let handler = ResultHandler::<u8>::new("test", 0);
+        drop(handler.sender());
+        assert_eq!(ErrorCode::CommonInvalidState, handler.wait(ErrorCode::Success).unwrap_err());
+    }
+
+    #[test]
+    fn dropped_callback_ends_a_wait_with_a_live_canceller() {
+        let handler = ResultHandler::<u8>::new("test", 0);
+        let _canceller = handler.canceller();
+        let sender = handler.sender();
+        thread::spawn(move || drop(sender));
+        assert_eq!(ErrorCode::CommonInvalidState, handler.wait(ErrorCode::Success).unwrap_err());
+    }
+
+    #[test]
+    fn callback_with_the_result() {
+        let (sender, receiver) = channel();
+        let cb = ResultHandler::<u8>::new("test", 1000).callback(move |result| sender.send(result).unwrap());
+
+        cb.send(ErrorCode::Success, 1);
+        assert_eq!(Ok(1), receiver.recv_timeout(Duration::from_secs(1)).unwrap());
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn callback_times_out() {
+        let (sender, receiver) = channel();
+        let cb = ResultHandler::<u8>::new("test", 10).callback(move |result| sender.send(result).unwrap());
+
+        assert_eq!(Err(ErrorCode::CommonIOError), receiver.recv_timeout(Duration::from_secs(1)).unwrap());
+        cb.send(ErrorCode::Success, 1);
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn cancelled_callback() {
+        let (sender, receiver) = channel();
+        let cb = ResultHandler::<u8>::new("test", 10).callback(move |result| sender.send(result).unwrap());
+
+        cb.cancel();
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
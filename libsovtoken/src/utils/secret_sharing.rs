@@ -0,0 +1,339 @@
+/*!
+    Shamir secret sharing of seeds.
+
+    [`split`] turns a secret into `count` shares, any `threshold` of which
+    give it back with [`combine`]; fewer shares tell nothing about it. Each
+    byte of the secret is the constant of a random polynomial of degree
+    `threshold - 1` over GF(256), and a share holds the values of the
+    polynomials at its index.
+
+    A share is the base58check of:
+
+    ```text
+    | version | threshold | index | split id (4 bytes) | values |
+    ```
+
+    The split id is random, so shares of different splits of the same secret
+    aren't combined together. The first 4 bytes of the sha256 of the secret
+    are shared along with it, and checked once the secret is combined.
+
+    [`split`]: fn.split.html
+    [`combine`]: fn.combine.html
+*/
+
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::{self, Ordering};
+
+use rand::{OsRng, Rng};
+use sha2::{Digest, Sha256};
+
+use utils::base58::{FromBase58, IntoBase58};
+
+/**
+    The version of the layout of the shares.
+*/
+pub const SHARE_VERSION: u8 = 1;
+
+// version, threshold, index and split id
+const HEADER_LEN: usize = 7;
+const CHECKSUM_LEN: usize = 4;
+
+/**
+    Why a secret can't be split or combined.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShareError {
+    /// The threshold isn't between 2 and the number of shares, holds both.
+    InvalidThreshold(u32, u32),
+    /// A share can't be decoded, holds its position in the shares and why.
+    InvalidShare(usize, String),
+    /// The shares are from different splits, or two differ at the same index.
+    Mismatch,
+    /// Less shares than the threshold, holds the number given and the threshold.
+    TooFew(usize, u8),
+    /// The combined secret doesn't match its checksum.
+    Corrupted,
+    /// The system random number generator isn't available.
+    NoRandom,
+}
+
+impl fmt::Display for ShareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ShareError::InvalidThreshold(threshold, count) => write!(f, "threshold {} of {} shares", threshold, count),
+            &ShareError::InvalidShare(position, ref reason) => write!(f, "share {}: {}", position, reason),
+            &ShareError::Mismatch => write!(f, "the shares are from different splits"),
+            &ShareError::TooFew(given, threshold) => write!(f, "{} shares given, {} needed", given, threshold),
+            &ShareError::Corrupted => write!(f, "the combined secret doesn't match its checksum"),
+            &ShareError::NoRandom => write!(f, "no system random number generator"),
+        }
+    }
+}
+
+/**
+    Split `secret` into `count` shares, `threshold` of which are needed to combine it.
+
+    ```
+        use sovtoken::utils::secret_sharing::{combine, split};
+
+        let shares = split(b"000000000000000000000000Trustee1", 2, 3).unwrap();
+        assert_eq!(3, shares.len());
+        assert_eq!(b"000000000000000000000000Trustee1".to_vec(), combine(&shares[1..]).unwrap());
+    ```
+*/
+pub fn split(secret: &[u8], threshold: u32, count: u32) -> Result<Vec<String>, ShareError> {
+    if threshold < 2 || threshold > count || count > 255 {
+        return Err(ShareError::InvalidThreshold(threshold, count));
+    }
+
+    let mut rng = OsRng::new().map_err(|_| ShareError::NoRandom)?;
+    let mut split_id = [0u8; 4];
+    rng.fill_bytes(&mut split_id);
+
+    let mut payload = secret.to_vec();
+    payload.extend_from_slice(&checksum(secret));
+
+    let mut shares: Vec<Vec<u8>> = (1..count + 1)
+        .map(|index| {
+            let mut share = vec![SHARE_VERSION, threshold as u8, index as u8];
+            share.extend_from_slice(&split_id);
+            share
+        })
+        .collect();
+
+    let mut coefficients = vec![0u8; threshold as usize];
+    for &byte in payload.iter() {
+        coefficients[0] = byte;
+        rng.fill_bytes(&mut coefficients[1..]);
+        for share in shares.iter_mut() {
+            let value = evaluate(&coefficients, share[2]);
+            share.push(value);
+        }
+    }
+    zeroize(&mut payload);
+    zeroize(&mut coefficients);
+
+    Ok(shares.into_iter()
+        .map(|mut share| {
+            let encoded = share.into_base58_check();
+            zeroize(&mut share);
+            encoded
+        })
+        .collect())
+}
+
+/**
+    Combine the secret split into `shares`.
+
+    Shares given twice are counted once. Fails with `TooFew` when less than
+    the threshold of the split are given.
+*/
+pub fn combine(shares: &[String]) -> Result<Vec<u8>, ShareError> {
+    let mut decoded: Vec<Vec<u8>> = Vec::new();
+    for (position, share) in shares.iter().enumerate() {
+        let bytes = decode(position, share)?;
+        if !decoded.contains(&bytes) {
+            decoded.push(bytes);
+        }
+    }
+
+    let first = match decoded.first() {
+        Some(first) => first.clone(),
+        None => return Err(ShareError::TooFew(0, 2)),
+    };
+    let threshold = first[1];
+
+    for (i, share) in decoded.iter().enumerate() {
+        if share.len() != first.len() || share[..2] != first[..2] || share[3..HEADER_LEN] != first[3..HEADER_LEN] {
+            return Err(ShareError::Mismatch);
+        }
+        if decoded[..i].iter().any(|other| other[2] == share[2]) {
+            return Err(ShareError::Mismatch);
+        }
+    }
+
+    if decoded.len() < threshold as usize {
+        return Err(ShareError::TooFew(decoded.len(), threshold));
+    }
+
+    let shares = &decoded[..threshold as usize];
+    let weights: Vec<u8> = shares.iter()
+        .map(|share| lagrange_weight(share[2], shares.iter().map(|other| other[2])))
+        .collect();
+
+    let payload: Vec<u8> = (HEADER_LEN..first.len())
+        .map(|position| {
+            shares.iter().zip(weights.iter())
+                .fold(0, |secret, (share, weight)| secret ^ gf_mul(share[position], *weight))
+        })
+        .collect();
+
+    for share in decoded.iter_mut() {
+        zeroize(share);
+    }
+    let mut first = first;
+    zeroize(&mut first);
+
+    let mut payload = payload;
+    let secret_len = payload.len() - CHECKSUM_LEN;
+    let valid = checksum(&payload[..secret_len]) == &payload[secret_len..];
+    zeroize(&mut payload[secret_len..]);
+    payload.truncate(secret_len);
+    if !valid {
+        zeroize(&mut payload);
+        return Err(ShareError::Corrupted);
+    }
+    Ok(payload)
+}
+
+/**
+    Overwrite `bytes` with zeros, so a secret doesn't stay in memory once it is freed.
+*/
+pub fn zeroize(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        // volatile, so the writes to a buffer about to be freed aren't optimized away
+        unsafe { ptr::write_volatile(byte, 0) };
+    }
+    atomic::compiler_fence(Ordering::SeqCst);
+}
+
+/**
+    Overwrite `string` with zeros, see [`zeroize`].
+
+    [`zeroize`]: fn.zeroize.html
+*/
+pub fn zeroize_string(string: &mut String) {
+    // zeros are valid UTF-8
+    unsafe { zeroize(string.as_bytes_mut()) };
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// A share isn't logged, its position is used instead.
+fn decode(position: usize, share: &str) -> Result<Vec<u8>, ShareError> {
+    let invalid = |reason: &str| ShareError::InvalidShare(position, reason.to_string());
+
+    let bytes = share.from_base58_check().map_err(|error| invalid(&error.to_string()))?;
+    if bytes.len() <= HEADER_LEN + CHECKSUM_LEN {
+        return Err(invalid("too short"));
+    }
+    if bytes[0] != SHARE_VERSION {
+        return Err(invalid(&format!("unknown version {}", bytes[0])));
+    }
+    if bytes[1] < 2 || bytes[2] == 0 {
+        return Err(invalid("invalid threshold or index"));
+    }
+    Ok(bytes)
+}
+
+fn checksum(secret: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::default();
+    hasher.input(secret);
+    hasher.result()[..CHECKSUM_LEN].to_vec()
+}
+
+// The value at `x` of the polynomial with `coefficients`, lowest degree first.
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients.iter().rev().fold(0, |value, coefficient| gf_mul(value, x) ^ coefficient)
+}
+
+// The weight of the value at `x` in the value at 0 of the polynomial through `xs`.
+fn lagrange_weight<I: Iterator<Item=u8>>(x: u8, xs: I) -> u8 {
+    xs.filter(|other| *other != x)
+        .fold(1, |weight, other| gf_mul(weight, gf_mul(other, gf_inv(other ^ x))))
+}
+
+// Multiplication in GF(256) with the AES polynomial x^8 + x^4 + x^3 + x + 1.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+// a^254 is the inverse of a in GF(256).
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut power = a;
+    let mut exponent = 254;
+    while exponent != 0 {
+        if exponent & 1 != 0 {
+            result = gf_mul(result, power);
+        }
+        power = gf_mul(power, power);
+        exponent >>= 1;
+    }
+    result
+}
+
+
+#[cfg(test)]
+mod secret_sharing_tests {
+    use super::*;
+
+    static SECRET: &'static [u8] = b"000000000000000000000000Trustee1";
+
+    #[test]
+    fn inverses() {
+        assert!((1..256).all(|a| gf_mul(a as u8, gf_inv(a as u8)) == 1));
+    }
+
+    #[test]
+    fn any_threshold_shares_combine() {
+        let shares = split(SECRET, 3, 5).unwrap();
+
+        for skipped in 0..5 {
+            let mut some: Vec<String> = shares.clone();
+            some.remove(skipped);
+            some.remove(skipped % 4);
+            assert_eq!(SECRET.to_vec(), combine(&some).unwrap());
+        }
+    }
+
+    #[test]
+    fn too_few_shares() {
+        let shares = split(SECRET, 3, 5).unwrap();
+        let some = vec![shares[0].clone(), shares[3].clone(), shares[0].clone()];
+        assert_eq!(ShareError::TooFew(2, 3), combine(&some).unwrap_err());
+    }
+
+    #[test]
+    fn shares_of_different_splits() {
+        let first = split(SECRET, 2, 2).unwrap();
+        let second = split(SECRET, 2, 2).unwrap();
+        assert_eq!(ShareError::Mismatch, combine(&[first[0].clone(), second[1].clone()]).unwrap_err());
+    }
+
+    #[test]
+    fn tampered_share() {
+        let shares = split(SECRET, 2, 2).unwrap();
+        let mut bytes = shares[0].from_base58_check().unwrap();
+        bytes[HEADER_LEN] ^= 1;
+
+        assert_eq!(ShareError::Corrupted, combine(&[bytes.into_base58_check(), shares[1].clone()]).unwrap_err());
+        match combine(&[String::from("x"), shares[1].clone()]).unwrap_err() {
+            ShareError::InvalidShare(position, _) => assert_eq!(0, position),
+            error => panic!("{:?} isn't an invalid share", error),
+        }
+    }
+
+    #[test]
+    fn invalid_threshold() {
+        assert_eq!(ShareError::InvalidThreshold(1, 3), split(SECRET, 1, 3).unwrap_err());
+        assert_eq!(ShareError::InvalidThreshold(4, 3), split(SECRET, 4, 3).unwrap_err());
+        assert_eq!(ShareError::InvalidThreshold(2, 256), split(SECRET, 2, 256).unwrap_err());
+    }
+}
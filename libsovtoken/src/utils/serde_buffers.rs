@@ -0,0 +1,213 @@
+/*!
+    Buffers reused by the serialization of requests and by base58 encoding.
+
+    An agent building thousands of requests a minute serialized each into a
+    fresh `Vec`, grown a few times on the way, and copied the bytes of every
+    base58 string it encoded. Each thread keeps a few buffers here instead:
+    [`with_buffer`] lends one, the serialization writes into it and only the
+    result is copied out, at its exact size. The pool is per thread, so
+    lending a buffer takes no lock; the counters are shared by the threads.
+
+    [`with_buffer`]: fn.with_buffer.html
+*/
+
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::Serialize;
+use serde_json;
+
+use logic::state::state;
+
+/**
+    Most buffers a thread keeps.
+*/
+pub const MAX_THREAD_BUFFERS: usize = 4;
+
+/**
+    Largest buffer, in bytes, given back to the pool of a thread. Larger
+    buffers are freed, so one huge request doesn't stay allocated.
+*/
+pub const MAX_SERDE_BUFFER_BYTES: usize = 64 * 1024;
+
+/**
+    Counters of the buffers lent by [`with_buffer`], over every thread.
+
+    [`with_buffer`]: fn.with_buffer.html
+*/
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SerdeBufferStats {
+    /// Buffers taken from the pool of a thread or allocated.
+    pub lent: usize,
+    /// Lent buffers which came from the pool of a thread.
+    pub reused: usize,
+    /// Buffers freed instead of given back, the pool being full or the buffer too large.
+    pub freed: usize,
+}
+
+/**
+    The counters of [`SerdeBufferStats`], kept in the library state.
+
+    [`SerdeBufferStats`]: struct.SerdeBufferStats.html
+*/
+pub struct SerdeBufferCounters {
+    lent: AtomicUsize,
+    reused: AtomicUsize,
+    freed: AtomicUsize,
+}
+
+impl SerdeBufferCounters {
+    pub fn new() -> Self {
+        SerdeBufferCounters { lent: AtomicUsize::new(0), reused: AtomicUsize::new(0), freed: AtomicUsize::new(0) }
+    }
+
+    pub fn stats(&self) -> SerdeBufferStats {
+        SerdeBufferStats {
+            lent: self.lent.load(Ordering::Relaxed),
+            reused: self.reused.load(Ordering::Relaxed),
+            freed: self.freed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+thread_local! {
+    static BUFFERS: RefCell<Vec<Vec<u8>>> = RefCell::new(Vec::new());
+}
+
+/**
+    Call `f` with an empty buffer of the pool of the thread.
+
+    The buffer goes back to the pool when `f` returns, `f` copies out what
+    it keeps. `f` may lend another buffer, e.g. to encode base58 while
+    serializing.
+
+    ```
+        use sovtoken::utils::serde_buffers::with_buffer;
+
+        let len = with_buffer(|buffer| {
+            buffer.extend_from_slice(b"{}");
+            buffer.len()
+        });
+        assert_eq!(2, len);
+    ```
+*/
+pub fn with_buffer<F, R>(f: F) -> R
+    where F: FnOnce(&mut Vec<u8>) -> R
+{
+    let state = state();
+    let counters = &state.serde_buffers;
+    counters.lent.fetch_add(1, Ordering::Relaxed);
+
+    // the pool isn't borrowed during `f`, which may lend a buffer too
+    let mut buffer = match BUFFERS.with(|buffers| buffers.borrow_mut().pop()) {
+        Some(buffer) => {
+            counters.reused.fetch_add(1, Ordering::Relaxed);
+            buffer
+        },
+        None => Vec::new(),
+    };
+    buffer.clear();
+
+    let res = f(&mut buffer);
+
+    let kept = buffer.capacity() <= MAX_SERDE_BUFFER_BYTES && BUFFERS.with(|buffers| {
+        let mut buffers = buffers.borrow_mut();
+        if buffers.len() < MAX_THREAD_BUFFERS {
+            buffers.push(buffer);
+            true
+        } else {
+            false
+        }
+    });
+    if !kept {
+        counters.freed.fetch_add(1, Ordering::Relaxed);
+    }
+    res
+}
+
+/**
+    The json of `value`, serialized in a buffer of the pool.
+*/
+pub fn to_json_string<T: Serialize>(value: &T) -> Result<String, serde_json::Error> {
+    with_buffer(|buffer| {
+        serde_json::to_writer(&mut *buffer, value)?;
+        // serde_json only writes utf-8
+        Ok(String::from_utf8_lossy(buffer).into_owned())
+    })
+}
+
+/**
+    The json of `value` as a C string, serialized in a buffer of the pool.
+
+    json escapes NUL, so the json never holds one.
+*/
+pub fn to_json_cstring<T: Serialize>(value: &T) -> Result<CString, serde_json::Error> {
+    with_buffer(|buffer| {
+        serde_json::to_writer(&mut *buffer, value)?;
+        Ok(CString::new(buffer.as_slice()).unwrap())
+    })
+}
+
+/**
+    The size in bytes of the json of `value`, serialized in a buffer of the pool.
+*/
+pub fn json_len<T: Serialize>(value: &T) -> Result<usize, serde_json::Error> {
+    with_buffer(|buffer| {
+        serde_json::to_writer(&mut *buffer, value)?;
+        Ok(buffer.len())
+    })
+}
+
+/**
+    The counters of the buffers lent, over every thread.
+*/
+pub fn serde_buffer_stats() -> SerdeBufferStats {
+    state().serde_buffers.stats()
+}
+
+
+#[cfg(test)]
+mod serde_buffers_tests {
+    use super::*;
+
+    #[test]
+    fn buffers_are_reused() {
+        // the pool is per thread, the counters aren't
+        let stats = ::std::thread::spawn(|| {
+            let before = serde_buffer_stats();
+            let first = to_json_string(&json!({"a": 1})).unwrap();
+            let second = to_json_string(&json!([1, 2])).unwrap();
+            assert_eq!("{\"a\":1}", first);
+            assert_eq!("[1,2]", second);
+            (before, serde_buffer_stats())
+        }).join().unwrap();
+
+        assert!(stats.1.lent >= stats.0.lent + 2);
+        assert!(stats.1.reused >= stats.0.reused + 1);
+    }
+
+    #[test]
+    fn nested_buffers() {
+        let (outer, inner) = with_buffer(|outer| {
+            outer.push(1);
+            let inner = with_buffer(|inner| inner.len());
+            (outer.len(), inner)
+        });
+        assert_eq!((1, 0), (outer, inner));
+    }
+
+    #[test]
+    fn large_buffers_are_freed() {
+        let before = serde_buffer_stats();
+        with_buffer(|buffer| buffer.resize(MAX_SERDE_BUFFER_BYTES + 1, 0));
+        assert!(serde_buffer_stats().freed > before.freed);
+    }
+
+    #[test]
+    fn json_of_a_value() {
+        let value = json!({"operation": {"type": "10001"}, "reqId": 1});
+        assert_eq!(value.to_string().len(), json_len(&value).unwrap());
+        assert_eq!(value.to_string(), to_json_cstring(&value).unwrap().into_string().unwrap());
+    }
+}
@@ -0,0 +1,171 @@
+/*!
+    The command handle and reqId the log messages of a call are tagged with.
+
+    A handler runs in the span of its command handle, and the reqId is
+    recorded in it once the request is built or the reply parsed. The span
+    is kept per thread, so a callback called by libindy on one of its threads
+    enters the span of the call it finishes with [`in_span`], and every
+    message of a payment, from the api call through the signing to the parse
+    of the reply, carries the same ids.
+
+    The reqId belongs to the span it was recorded in: it is gone once the
+    span is left, and a reqId recorded outside of any span, e.g. by a parser
+    called on a thread of the host, is dropped, so it never tags the later
+    log messages of that thread.
+
+    [`in_span`]: fn.in_span.html
+*/
+
+use std::cell::Cell;
+use std::fmt;
+
+/**
+    The ids a log message is tagged with, both `None` outside of a call.
+*/
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Span {
+    pub command_handle: Option<i32>,
+    pub req_id: Option<u64>,
+}
+
+impl Span {
+    pub fn is_empty(&self) -> bool {
+        self.command_handle.is_none() && self.req_id.is_none()
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut separator = "";
+        if let Some(command_handle) = self.command_handle {
+            write!(f, "command_handle={}", command_handle)?;
+            separator = " ";
+        }
+        if let Some(req_id) = self.req_id {
+            write!(f, "{}reqId={}", separator, req_id)?;
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    static CURRENT: Cell<Span> = Cell::new(Span::default());
+    static DEPTH: Cell<usize> = Cell::new(0);
+}
+
+/**
+    The span of this thread right now.
+*/
+pub fn current() -> Span {
+    CURRENT.with(|span| span.get())
+}
+
+/**
+    Run `f` in `span`, the span of the thread is restored afterwards, also
+    when `f` panics.
+*/
+pub fn in_span<F, R>(span: Span, f: F) -> R where F: FnOnce() -> R {
+    let _entered = Entered::enter(span);
+    f()
+}
+
+/**
+    Run the handler of `command_handle` in its span.
+*/
+pub fn with_command_handle<F, R>(command_handle: i32, f: F) -> R where F: FnOnce() -> R {
+    in_span(Span { command_handle: Some(command_handle), req_id: None }, f)
+}
+
+/**
+    Tag the rest of the span of this thread with `req_id`, until the span is
+    left. Outside of a span `req_id` is dropped.
+*/
+pub fn record_req_id(req_id: u64) {
+    if DEPTH.with(|depth| depth.get()) == 0 {
+        trace!("reqId {} recorded outside of a span, dropped", req_id);
+        return;
+    }
+
+    CURRENT.with(|span| {
+        let mut current = span.get();
+        current.req_id = Some(req_id);
+        span.set(current);
+    });
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+// A span entered, the previous one is restored when it is dropped.
+struct Entered {
+    previous: Span,
+}
+
+impl Entered {
+    fn enter(span: Span) -> Entered {
+        DEPTH.with(|depth| depth.set(depth.get() + 1));
+        Entered { previous: CURRENT.with(|current| current.replace(span)) }
+    }
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.previous));
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+
+#[cfg(test)]
+mod span_tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn span_is_restored() {
+        with_command_handle(7, || {
+            record_req_id(12);
+            assert_eq!("command_handle=7 reqId=12", current().to_string());
+        });
+        assert!(current().is_empty());
+    }
+
+    #[test]
+    fn span_is_per_thread() {
+        with_command_handle(8, || {
+            let span = current();
+            assert!(thread::spawn(|| current()).join().unwrap().is_empty());
+            assert_eq!(Some(8), thread::spawn(move || in_span(span, current)).join().unwrap().command_handle);
+        });
+    }
+
+    #[test]
+    fn req_id_is_scoped_to_the_span() {
+        record_req_id(5);
+        assert!(current().is_empty());
+
+        with_command_handle(9, || {
+            in_span(current(), || record_req_id(13));
+            assert_eq!(None, current().req_id);
+        });
+        assert!(current().is_empty());
+    }
+
+    #[test]
+    fn span_is_restored_on_panic() {
+        let _ = ::std::panic::catch_unwind(|| with_command_handle(10, || {
+            record_req_id(14);
+            panic!("handler failed");
+        }));
+        assert!(current().is_empty());
+    }
+
+    #[test]
+    fn display_of_req_id_only() {
+        assert_eq!("reqId=3", Span { command_handle: None, req_id: Some(3) }.to_string());
+    }
+}
@@ -0,0 +1,155 @@
+/*!
+    Blocking calls of the handlers, for the `_sync` variants of the api.
+
+    Some host languages can't pass a callback, or only with much wrapper
+    code. [`call_sync`] calls a handler with a callback of its own and
+    waits until the handler called back, so the `_sync` variants return
+    what the handler would have called back with, in a [`SyncResult`].
+
+    The callback of a `_sync` call always runs inline, whatever the
+    `callback_dispatch` of the library config says, see [`is_sync_callback`].
+
+    [`call_sync`]: fn.call_sync.html
+    [`SyncResult`]: struct.SyncResult.html
+    [`is_sync_callback`]: fn.is_sync_callback.html
+*/
+
+use std::ffi::CString;
+use std::ptr;
+use std::sync::atomic::Ordering;
+
+use indy::ErrorCode;
+use libc::c_char;
+
+use logic::state::state;
+use utils::constants::general::{JsonCallback, JsonCallbackUnwrapped};
+use utils::dead_letter::{self, DeadLetterReason};
+use utils::ffi_support::str_from_char_ptr;
+use utils::results::ResultHandler;
+
+/**
+    What a `_sync` variant returns.
+
+    `json` is what the handler called back with, null when `error_code`
+    isn't `Success`. It is owned by the caller, who frees it with
+    `sovtoken_free_json`.
+*/
+#[repr(C)]
+#[derive(Debug)]
+pub struct SyncResult {
+    pub error_code: i32,
+    pub json: *mut c_char,
+}
+
+impl From<Result<String, ErrorCode>> for SyncResult {
+    fn from(result: Result<String, ErrorCode>) -> SyncResult {
+        match result {
+            Ok(json) => match CString::new(json) {
+                Ok(json) => SyncResult { error_code: ErrorCode::Success as i32, json: json.into_raw() },
+                Err(_) => SyncResult { error_code: ErrorCode::CommonInvalidState as i32, json: ptr::null_mut() },
+            },
+            Err(ec) => SyncResult { error_code: ec as i32, json: ptr::null_mut() },
+        }
+    }
+}
+
+/**
+    Call `handler` with a command handle and a callback, and wait for the callback.
+
+    Returns the error `handler` returns, or else the result of the callback.
+*/
+pub fn call_sync<F>(handler: F) -> Result<String, ErrorCode>
+    where F: FnOnce(i32, JsonCallback) -> i32
+{
+    let command_handle = (state().last_sync_handle.fetch_add(1, Ordering::SeqCst) % i32::max_value() as usize) as i32;
+    let result = ResultHandler::new("sync call", 0);
+    state().sync_calls.lock().insert(command_handle, result.sender());
+
+    let ec = handler(command_handle, Some(complete_sync_call));
+    if ec != ErrorCode::Success as i32 {
+        state().sync_calls.lock().remove(&command_handle);
+    }
+    result.wait(ErrorCode::from(ec))
+}
+
+/**
+    Whether `cb` is the callback of the `_sync` calls, whose caller is blocked waiting for it.
+*/
+pub fn is_sync_callback(cb: JsonCallbackUnwrapped) -> bool {
+    cb as usize == complete_sync_call as JsonCallbackUnwrapped as usize
+}
+
+/* Methods "private" (aka not exported from this module)
+
+    KEEP all public methods above
+*/
+
+extern fn complete_sync_call(command_handle: i32, err: i32, json: *const c_char) -> i32 {
+    let json = str_from_char_ptr(json).unwrap_or("").to_string();
+    let waiter = state().sync_calls.lock().remove(&command_handle);
+    match waiter {
+        Some(sender) => sender.send(ErrorCode::from(err), json),
+        None => {
+            error!("No call is waiting for the command handle {}", command_handle);
+            dead_letter::capture(Some(command_handle), None, Some(err), &json, DeadLetterReason::NoWaiter);
+        },
+    }
+    ErrorCode::Success as i32
+}
+
+
+#[cfg(test)]
+mod sync_call_tests {
+    use std::thread;
+
+    use logic::config::library_config::LibraryConfig;
+    use logic::context;
+    use utils::ffi_support::with_c_str;
+    use super::*;
+
+    #[test]
+    fn result_of_an_inline_callback() {
+        let result = call_sync(|command_handle, cb| {
+            with_c_str("{}", |json| cb.unwrap()(command_handle, ErrorCode::Success as i32, json));
+            ErrorCode::Success as i32
+        });
+        assert_eq!(Ok(String::from("{}")), result);
+    }
+
+    #[test]
+    fn result_of_a_callback_on_another_thread() {
+        let result = call_sync(|command_handle, cb| {
+            thread::spawn(move || with_c_str("", |json| cb.unwrap()(command_handle, ErrorCode::CommonInvalidState as i32, json)));
+            ErrorCode::Success as i32
+        });
+        assert_eq!(Err(ErrorCode::CommonInvalidState), result);
+    }
+
+    #[test]
+    fn error_of_the_handler() {
+        let result = call_sync(|_, _| ErrorCode::CommonInvalidStructure as i32);
+        assert_eq!(Err(ErrorCode::CommonInvalidStructure), result);
+    }
+
+    #[test]
+    fn sync_result_owns_the_json() {
+        let result = SyncResult::from(Ok(String::from("[]")));
+        assert_eq!(ErrorCode::Success as i32, result.error_code);
+        let json = unsafe { CString::from_raw(result.json) };
+        assert_eq!("[]", json.as_c_str().to_str().unwrap());
+        assert!(SyncResult::from(Err(ErrorCode::CommonInvalidState)).json.is_null());
+    }
+
+    #[test]
+    fn result_without_a_waiter_is_a_dead_letter() {
+        let own = context::find(context::create(LibraryConfig::default())).unwrap();
+        context::in_context(own, || {
+            with_c_str("{}", |json| complete_sync_call(-7, ErrorCode::Success as i32, json));
+
+            let drained = dead_letter::drain(usize::max_value());
+            assert_eq!(1, drained.dead_letters.len());
+            assert_eq!(Some(-7), drained.dead_letters[0].command_handle);
+            assert_eq!(DeadLetterReason::NoWaiter, drained.dead_letters[0].reason);
+        });
+    }
+}
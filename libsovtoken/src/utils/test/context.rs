@@ -0,0 +1,20 @@
+/*!
+Contexts of their own for tests sharing no state with the others.
+*/
+
+use logic::config::library_config::LibraryConfig;
+use logic::context;
+
+/**
+    Run `f` in a new context with `config`, destroyed once `f` returned.
+
+    The fee schedule, the caches and the dead letters of the context are
+    its own, so a test doesn't see what the tests running beside it did.
+*/
+pub fn in_own_context<F, R>(config: LibraryConfig, f: F) -> R where F: FnOnce() -> R {
+    let handle = context::create(config);
+    let own = context::find(handle).unwrap();
+    let result = context::in_context(own, f);
+    context::destroy(handle).unwrap();
+    result
+}
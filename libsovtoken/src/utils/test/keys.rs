@@ -0,0 +1,35 @@
+/*!
+    A key pair and its payment address, for tests signing with a real key.
+
+    **These should only be used for testing**
+*/
+
+use sodiumoxide::crypto::sign::{keypair_from_seed, PublicKey, SecretKey, Seed};
+
+use logic::address;
+use utils::base58::IntoBase58;
+
+/**
+    The seed of [`keypair`].
+
+    [`keypair`]: fn.keypair.html
+*/
+pub const PAYER_SEED: &[u8; 32] = b"000000000000000000000000Payment1";
+
+/**
+    The key pair of [`payer`].
+
+    [`payer`]: fn.payer.html
+*/
+pub fn keypair() -> (PublicKey, SecretKey) {
+    keypair_from_seed(&Seed::from_slice(PAYER_SEED).unwrap())
+}
+
+/**
+    The fully qualified payment address of the key of [`keypair`].
+
+    [`keypair`]: fn.keypair.html
+*/
+pub fn payer() -> String {
+    address::qualified_address_from_verkey(&keypair().0.into_base58()).unwrap()
+}
@@ -3,4 +3,7 @@ A set of utils that should only be unsed in testing functions.
 */
 
 pub mod callbacks;
-pub mod default;
\ No newline at end of file
+pub mod context;
+pub mod default;
+pub mod keys;
+pub mod wallet;
\ No newline at end of file
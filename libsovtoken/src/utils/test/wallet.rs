@@ -0,0 +1,70 @@
+/*!
+    A wallet of non secret records in memory, in place of the Indy SDK.
+
+    **These should only be used for testing**
+*/
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use indy::{IndyHandle, ErrorCode};
+
+use logic::indy_sdk_api::wallet_api::WalletAPI;
+
+/**
+    Mocks the SDK wallet calls with a map of the records by type and id,
+    whatever the wallet handle. Like the wallet, adding a record twice and
+    updating a missing one fail, and with `read_only` every update fails.
+*/
+#[derive(Default)]
+pub struct WalletMockHandler {
+    pub records: RefCell<HashMap<(String, String), String>>,
+    pub read_only: bool,
+}
+
+impl WalletAPI for WalletMockHandler {
+    fn indy_add_record(&self, _: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        let key = (xtype.to_string(), id.to_string());
+        let mut records = self.records.borrow_mut();
+        if records.contains_key(&key) {
+            return Err(ErrorCode::WalletItemAlreadyExists);
+        }
+        records.insert(key, value.to_string());
+        Ok(())
+    }
+
+    fn indy_update_record_value(&self, _: IndyHandle, xtype: &str, id: &str, value: &str) -> Result<(), ErrorCode> {
+        if self.read_only {
+            return Err(ErrorCode::WalletAccessFailed);
+        }
+        let key = (xtype.to_string(), id.to_string());
+        let mut records = self.records.borrow_mut();
+        if !records.contains_key(&key) {
+            return Err(ErrorCode::WalletItemNotFound);
+        }
+        records.insert(key, value.to_string());
+        Ok(())
+    }
+
+    fn indy_get_record_value(&self, _: IndyHandle, xtype: &str, id: &str) -> Result<String, ErrorCode> {
+        self.records.borrow()
+            .get(&(xtype.to_string(), id.to_string()))
+            .cloned()
+            .ok_or(ErrorCode::WalletItemNotFound)
+    }
+
+    fn indy_delete_record(&self, _: IndyHandle, xtype: &str, id: &str) -> Result<(), ErrorCode> {
+        self.records.borrow_mut()
+            .remove(&(xtype.to_string(), id.to_string()))
+            .map(|_| ())
+            .ok_or(ErrorCode::WalletItemNotFound)
+    }
+
+    fn indy_list_records(&self, _: IndyHandle, xtype: &str) -> Result<Vec<(String, String)>, ErrorCode> {
+        Ok(self.records.borrow()
+            .iter()
+            .filter(|&(&(ref record_type, _), _)| record_type == xtype)
+            .map(|(&(_, ref id), value)| (id.clone(), value.clone()))
+            .collect())
+    }
+}
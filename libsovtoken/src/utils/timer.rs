@@ -0,0 +1,101 @@
+/*!
+    One thread for every timeout of the library.
+
+    [`after`] runs a task once its timeout expired, on the timer thread,
+    started on first use. The tasks are kept by when they expire, so a
+    timeout costs an entry in a heap instead of a thread of its own. A task
+    is run even when what it watches is done already: it has to check it
+    still has something to do, and return quickly, the next tasks wait for
+    it.
+
+    [`after`]: fn.after.html
+*/
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use utils::locks::InstrumentedMutex;
+
+type Task = Box<FnOnce() + Send>;
+
+lazy_static! {
+    static ref TIMER: InstrumentedMutex<Option<Sender<(Instant, Task)>>> = InstrumentedMutex::new("timer", None);
+}
+
+/**
+    Run `task` on the timer thread in `timeout`.
+*/
+pub fn after<F: FnOnce() + Send + 'static>(timeout: Duration, task: F) {
+    let at = Instant::now() + timeout;
+    let mut timer = TIMER.lock();
+    let task = Box::new(task) as Task;
+    let task = match *timer {
+        Some(ref sender) => match sender.send((at, task)) {
+            Ok(()) => return,
+            Err(unsent) => (unsent.0).1,
+        },
+        None => task,
+    };
+
+    // first use, or the timer thread is gone
+    let (sender, receiver) = channel::<(Instant, Task)>();
+    let _ = sender.send((at, task));
+    thread::spawn(move || {
+        let mut expiries = BinaryHeap::new();
+        let mut tasks: HashMap<u64, Task> = HashMap::new();
+        let mut next_id = 0u64;
+        loop {
+            let received = match expiries.peek() {
+                Some(&Reverse((at, _))) => {
+                    let now = Instant::now();
+                    if at > now { receiver.recv_timeout(at - now) } else { Err(RecvTimeoutError::Timeout) }
+                },
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            match received {
+                Ok((at, task)) => {
+                    next_id += 1;
+                    expiries.push(Reverse((at, next_id)));
+                    tasks.insert(next_id, task);
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    let now = Instant::now();
+                    while let Some(&Reverse((at, id))) = expiries.peek() {
+                        if at > now {
+                            break;
+                        }
+                        expiries.pop();
+                        if let Some(task) = tasks.remove(&id) {
+                            task();
+                        }
+                    }
+                },
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+    *timer = Some(sender);
+}
+
+
+#[cfg(test)]
+mod timer_tests {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+
+    #[test]
+    fn tasks_run_in_the_order_they_expire() {
+        let (sender, receiver) = channel();
+        let later = sender.clone();
+        after(Duration::from_millis(200), move || later.send(2).unwrap());
+        after(Duration::from_millis(10), move || sender.send(1).unwrap());
+
+        assert_eq!(1, receiver.recv_timeout(Duration::from_secs(5)).unwrap());
+        assert_eq!(2, receiver.recv_timeout(Duration::from_secs(5)).unwrap());
+    }
+}
@@ -0,0 +1,115 @@
+/*!
+    Wire format module converts requests and responses between json and
+    base64 encoded MessagePack.
+
+    MessagePack is a lot smaller than json for the same request, which helps
+    agents relaying requests over slow or metered links. The ledger only
+    speaks json, so a MessagePack request has to be converted back before it
+    is submitted.
+*/
+
+use base64;
+use indy::ErrorCode;
+use rmp_serde;
+use serde_json::{self, Value};
+
+/**
+    The formats a request can be returned in.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WireFormat {
+    Json,
+    /// MessagePack, base64 encoded so it fits in a c string.
+    Msgpack,
+}
+
+impl WireFormat {
+    /**
+        The format named `name`, json when no name is given.
+    */
+    pub fn from_name(name: Option<&str>) -> Result<WireFormat, ErrorCode> {
+        match name {
+            None | Some("") | Some("json") => Ok(WireFormat::Json),
+            Some("msgpack") => Ok(WireFormat::Msgpack),
+            Some(name) => {
+                error!("Unknown wire format {:?}, expected json or msgpack", name);
+                Err(ErrorCode::CommonInvalidStructure)
+            }
+        }
+    }
+
+    /**
+        Convert `json` to the format.
+    */
+    pub fn encode(&self, json: String) -> Result<String, ErrorCode> {
+        match *self {
+            WireFormat::Json => Ok(json),
+            WireFormat::Msgpack => {
+                let value: Value = serde_json::from_str(&json)
+                    .map_err(map_err_err!())
+                    .or(Err(ErrorCode::CommonInvalidStructure))?;
+                let bytes = rmp_serde::to_vec(&value)
+                    .map_err(map_err_err!())
+                    .or(Err(ErrorCode::CommonInvalidState))?;
+                Ok(base64::encode(&bytes))
+            }
+        }
+    }
+}
+
+/**
+    Convert json or base64 encoded MessagePack to json.
+
+    Json is returned as is, so the parse handlers can take either format.
+*/
+pub fn to_json(input: &str) -> Result<String, ErrorCode> {
+    let trimmed = input.trim();
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        return Ok(input.to_string());
+    }
+
+    let bytes = base64::decode(trimmed)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+    let value: Value = rmp_serde::from_slice(&bytes)
+        .map_err(map_err_err!())
+        .or(Err(ErrorCode::CommonInvalidStructure))?;
+    Ok(value.to_string())
+}
+
+
+#[cfg(test)]
+mod wire_format_tests {
+    use super::*;
+
+    static REQUEST: &'static str = r#"{"identifier":"LibsovtokenDid11111111","operation":{"type":"10001","outputs":[{"address":"2FKYJkgXRZtjhFpTMHhuyfc17BHZWcFPyF2MWy2SZMBaSo64fb","amount":10}]},"protocolVersion":2,"reqId":1538667213391614}"#;
+
+    #[test]
+    fn from_name_defaults_to_json() {
+        assert_eq!(WireFormat::Json, WireFormat::from_name(None).unwrap());
+        assert_eq!(WireFormat::Msgpack, WireFormat::from_name(Some("msgpack")).unwrap());
+        assert_eq!(ErrorCode::CommonInvalidStructure, WireFormat::from_name(Some("cbor")).unwrap_err());
+    }
+
+    #[test]
+    fn msgpack_roundtrip() {
+        let encoded = WireFormat::Msgpack.encode(REQUEST.to_string()).unwrap();
+        assert!(encoded.len() < REQUEST.len());
+
+        let decoded: Value = serde_json::from_str(&to_json(&encoded).unwrap()).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(REQUEST).unwrap(), decoded);
+    }
+
+    #[test]
+    fn json_passes_through() {
+        assert_eq!(REQUEST, WireFormat::Json.encode(REQUEST.to_string()).unwrap());
+        assert_eq!(REQUEST, to_json(REQUEST).unwrap());
+    }
+
+    #[test]
+    fn invalid_msgpack() {
+        assert_eq!(ErrorCode::CommonInvalidStructure, to_json("not base64!").unwrap_err());
+        assert_eq!(ErrorCode::CommonInvalidStructure, to_json(&base64::encode(&[0xc1])).unwrap_err());
+    }
+}
@@ -3,9 +3,24 @@
 
 extern crate sovtoken;
 extern crate indy;                      // lib-sdk project
+extern crate serde_json;
 
 use indy::ErrorCode;
-use sovtoken::api::sovtoken_init;
+use sovtoken::api::{hash_request_handler, sovtoken_context_create, sovtoken_context_destroy, sovtoken_context_enter, sovtoken_drain_dead_letters_json};
+use sovtoken::api::{sovtoken_free_json, sovtoken_get_address_test_vectors_json, sovtoken_get_registration_status_json, sovtoken_init, sovtoken_self_test};
+use sovtoken::logic::address::qualified_address_from_verkey;
+use sovtoken::utils::sync_call::SyncResult;
+use sovtoken::utils::test::callbacks;
+use std::ffi::{CStr, CString};
+
+// the json of a successful `SyncResult`, freed
+fn json_of(result: SyncResult) -> serde_json::Value {
+   assert_eq!(result.error_code, ErrorCode::Success as i32, "the call did not return ErrorCode::Success");
+
+   let json = unsafe { CStr::from_ptr(result.json) }.to_str().unwrap().to_string();
+   sovtoken_free_json(result.json);
+   serde_json::from_str(&json).unwrap()
+}
 
 
 #[test]
@@ -15,4 +30,75 @@ fn sovtoken_init_executes_successfully() {
 
    assert_eq!(err, ErrorCode::Success as i32, "sovtoken_init did not return ErrorCode::Success");
 
-}
\ No newline at end of file
+}
+
+#[test]
+fn sovtoken_self_test_executes_successfully() {
+
+   let err : i32 = sovtoken_self_test();
+
+   assert_eq!(err, ErrorCode::Success as i32, "sovtoken_self_test did not return ErrorCode::Success");
+
+}
+
+#[test]
+fn sovtoken_registration_status_after_init() {
+
+   assert_eq!(sovtoken_init(), ErrorCode::Success as i32, "sovtoken_init did not return ErrorCode::Success");
+
+   let status = json_of(sovtoken_get_registration_status_json());
+
+   assert_eq!(status["registered"], serde_json::Value::Bool(true));
+   let steps = status["steps"].as_array().unwrap();
+   assert!(steps.iter().any(|step| step["name"] == "payment_method"));
+   assert!(steps.iter().all(|step| step["outcome"] != "failed"));
+
+}
+
+#[test]
+fn sovtoken_address_test_vectors_match_the_addresses() {
+
+   let vectors = json_of(sovtoken_get_address_test_vectors_json());
+
+   let encoding = vectors["encoding"].as_array().unwrap();
+   let double_sha256: Vec<&serde_json::Value> = encoding.iter().filter(|vector| vector["algorithm"] == "double_sha256").collect();
+   assert!(!double_sha256.is_empty());
+   for vector in double_sha256 {
+      let address = qualified_address_from_verkey(vector["verkey"].as_str().unwrap()).unwrap();
+      assert_eq!(vector["address"].as_str().unwrap(), address);
+   }
+
+   let validity = vectors["validity"].as_array().unwrap();
+   assert!(validity.iter().any(|vector| vector["valid"] == true));
+   assert!(validity.iter().any(|vector| vector["valid"] == false));
+
+}
+
+#[test]
+fn sovtoken_dead_letters_of_a_destroyed_context_drained() {
+
+   let config = CString::new(r#"{"callback_dispatch": "poll"}"#).unwrap();
+   let mut context_handle = 0;
+   assert_eq!(sovtoken_context_create(config.as_ptr(), &mut context_handle), ErrorCode::Success as i32);
+   assert_eq!(sovtoken_context_enter(context_handle), ErrorCode::Success as i32);
+
+   // the callback is queued in the context, never polled
+   let request = CString::new(r#"{"identifier": "Th7MpTaRZVRYnPiabds81Y", "reqId": 1, "operation": {"type": "1"}}"#).unwrap();
+   let (_receiver, command_handle, cb) = callbacks::cb_ec_string();
+   assert_eq!(hash_request_handler(command_handle, request.as_ptr(), cb), ErrorCode::Success as i32);
+
+   assert_eq!(sovtoken_context_enter(0), ErrorCode::Success as i32);
+   assert_eq!(sovtoken_context_destroy(context_handle), ErrorCode::Success as i32);
+
+   let drained = json_of(sovtoken_drain_dead_letters_json(0));
+   assert!(drained["dropped"].is_u64());
+   let letters = drained["deadLetters"].as_array().unwrap();
+   let letter = letters.iter().find(|letter| letter["commandHandle"] == command_handle).unwrap();
+   assert_eq!(letter["reason"], "context_destroyed");
+   assert_eq!(letter["err"], ErrorCode::Success as i32);
+   assert_eq!(letter["resultHash"].as_str().unwrap().len(), 64);
+
+   let drained = json_of(sovtoken_drain_dead_letters_json(0));
+   assert!(drained["deadLetters"].as_array().unwrap().iter().all(|letter| letter["commandHandle"] != command_handle));
+
+}
@@ -0,0 +1,111 @@
+#[macro_use] extern crate serde_json;
+extern crate indy;
+extern crate sovtoken;
+
+use indy::ErrorCode;
+use sovtoken::utils::test::callbacks;
+use std::ffi::{CStr, CString};
+use std::time::Duration;
+
+// ***** HELPER METHODS *****
+
+fn attest(resp_json: &str) -> Result<serde_json::Value, ErrorCode> {
+    let resp_json = CString::new(resp_json).unwrap();
+    let (receiver, command_handle, cb) = callbacks::cb_ec_string();
+
+    let ec = sovtoken::api::attest_get_txn_fees_response_handler(command_handle, resp_json.as_ptr(), cb);
+    if ec != ErrorCode::Success as i32 {
+        return Err(ErrorCode::from(ec));
+    }
+
+    match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+        (ErrorCode::Success, attestation) => Ok(serde_json::from_str(&attestation).unwrap()),
+        (ec, _) => Err(ec),
+    }
+}
+
+fn attest_sync(resp_json: &str) -> Result<serde_json::Value, ErrorCode> {
+    let resp_json = CString::new(resp_json).unwrap();
+    let result = sovtoken::api::attest_get_txn_fees_response_sync(resp_json.as_ptr());
+    if result.error_code != ErrorCode::Success as i32 {
+        return Err(ErrorCode::from(result.error_code));
+    }
+
+    let attestation = unsafe { CStr::from_ptr(result.json) }.to_str().unwrap().to_string();
+    sovtoken::api::sovtoken_free_json(result.json);
+    Ok(serde_json::from_str(&attestation).unwrap())
+}
+
+// ***** HELPER TEST DATA  *****
+
+fn get_fees_reply() -> serde_json::Value {
+    json!({
+        "op": "REPLY",
+        "result": {
+            "identifier": "6ouriXMZkLeHsuXrN1X1fd",
+            "reqId": 83955,
+            "type": "20001",
+            "fees": {"10001": 8, "1": 4},
+            "state_proof": {
+                "proof_nodes": "29qFIGZlZXOT0pF7IjEiOjQsIjEwMDAxIjo4fQ==",
+                "root_hash": "5BU5Rc3sRtTJB6tVprGiTSqiRaa9o6ei11MjH4Vu16ms",
+                "multi_signature": {
+                    "participants": ["Gamma", "Delta", "Beta"],
+                    "signature": "Qk67ePVhxdjHivAf8H4Loy1hN5zfb1dq79VSJKYx485EAXmj44PASpp8gj2faysdN8CNzSoUVvXgd3U4P2CA7VkwD7FHKUuviAFJfRQ68FnpUS8hVuqn6PAuv9RGUobohcJnKJ8CVKxr5i3Zn2JNXbk7AqeYRZQ2egq8fdoP3woPW7",
+                    "value": {
+                        "timestamp": 1530059419,
+                        "state_root_hash": "5BU5Rc3sRtTJB6tVprGiTSqiRaa9o6ei11MjH4Vu16ms",
+                        "ledger_id": 2,
+                        "txn_root_hash": "AKboMiJZJm247Sa7GsKQo5Ba8ukgxTQ3DsLc2pyVuDkU",
+                        "pool_state_root_hash": "J3ATG63R2JKHDCdpKpQf81FTNyQg2Vgz7Pu1ZHZw6zNy"
+                    }
+                }
+            }
+        }
+    })
+}
+
+// ***** UNIT TESTS *****
+
+#[test]
+fn attestation_of_a_signed_schedule() {
+    let attestation = attest(&get_fees_reply().to_string()).unwrap();
+
+    assert_eq!(json!({"1": 4, "10001": 8}), attestation["fees"]);
+    assert_eq!(json!(83955), attestation["reqId"]);
+    assert_eq!(json!("5BU5Rc3sRtTJB6tVprGiTSqiRaa9o6ei11MjH4Vu16ms"), attestation["ledgerRoot"]);
+    assert_eq!(json!(2), attestation["ledgerId"]);
+    assert_eq!(json!(1530059419), attestation["timestamp"]);
+
+    let schedule_hash = attestation["scheduleHash"].as_str().unwrap();
+    assert_eq!(64, schedule_hash.len());
+    assert!(schedule_hash.chars().all(|c| c.is_digit(16)));
+
+    assert_eq!(attestation, attest_sync(&get_fees_reply().to_string()).unwrap());
+}
+
+#[test]
+fn attestation_without_a_state_proof() {
+    let mut reply = get_fees_reply();
+    reply["result"].as_object_mut().unwrap().remove("state_proof");
+    let attestation = attest(&reply.to_string()).unwrap();
+
+    assert_eq!(json!({"1": 4, "10001": 8}), attestation["fees"]);
+    assert!(attestation["ledgerRoot"].is_null());
+    assert!(attestation["multiSignature"].is_null());
+    assert_eq!(attest(&get_fees_reply().to_string()).unwrap()["scheduleHash"], attestation["scheduleHash"]);
+}
+
+#[test]
+fn attestation_of_other_reply_fails() {
+    let reply = json!({"op": "REPLY", "result": {"reqId": 1}}).to_string();
+    assert_eq!(ErrorCode::CommonInvalidStructure, attest(&reply).unwrap_err());
+    assert_eq!(ErrorCode::CommonInvalidStructure, attest_sync(&reply).unwrap_err());
+}
+
+#[test]
+fn attestation_of_a_mismatched_root_fails() {
+    let mut reply = get_fees_reply();
+    reply["result"]["state_proof"]["root_hash"] = json!("AKboMiJZJm247Sa7GsKQo5Ba8ukgxTQ3DsLc2pyVuDkU");
+    assert_eq!(ErrorCode::CommonInvalidStructure, attest(&reply.to_string()).unwrap_err());
+}
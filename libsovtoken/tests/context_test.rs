@@ -0,0 +1,58 @@
+#[macro_use] extern crate serde_json;
+extern crate indy;
+extern crate sovtoken;
+
+use indy::ErrorCode;
+use std::ffi::CString;
+use std::ptr;
+
+// ***** HELPER METHODS *****
+
+fn context_create(config: serde_json::Value) -> Result<i32, ErrorCode> {
+    let config = CString::new(config.to_string()).unwrap();
+    let mut context_handle = 0;
+
+    let ec = sovtoken::api::sovtoken_context_create(config.as_ptr(), &mut context_handle);
+    if ec != ErrorCode::Success as i32 {
+        return Err(ErrorCode::from(ec));
+    }
+    Ok(context_handle)
+}
+
+// ***** UNIT TESTS *****
+
+#[test]
+fn context_entered_until_destroyed() {
+    let context_handle = context_create(json!({})).unwrap();
+    assert_ne!(0, context_handle);
+
+    assert_eq!(ErrorCode::Success as i32, sovtoken::api::sovtoken_context_enter(context_handle));
+    assert_eq!(ErrorCode::Success as i32, sovtoken::api::sovtoken_context_enter(0));
+    assert_eq!(ErrorCode::Success as i32, sovtoken::api::sovtoken_context_destroy(context_handle));
+
+    assert_eq!(ErrorCode::CommonInvalidStructure as i32, sovtoken::api::sovtoken_context_enter(context_handle));
+    assert_eq!(ErrorCode::CommonInvalidStructure as i32, sovtoken::api::sovtoken_context_destroy(context_handle));
+}
+
+#[test]
+fn contexts_have_handles_of_their_own() {
+    let first = context_create(json!({})).unwrap();
+    let second = context_create(json!({"stateless": true})).unwrap();
+    assert_ne!(first, second);
+
+    assert_eq!(ErrorCode::Success as i32, sovtoken::api::sovtoken_context_destroy(first));
+    assert_eq!(ErrorCode::Success as i32, sovtoken::api::sovtoken_context_destroy(second));
+}
+
+#[test]
+fn default_context_not_destroyed() {
+    assert_eq!(ErrorCode::CommonInvalidState as i32, sovtoken::api::sovtoken_context_destroy(0));
+}
+
+#[test]
+fn context_not_created_with_invalid_config() {
+    assert_eq!(ErrorCode::CommonInvalidStructure, context_create(json!([])).unwrap_err());
+
+    let config = CString::new("{}").unwrap();
+    assert_eq!(ErrorCode::CommonInvalidStructure as i32, sovtoken::api::sovtoken_context_create(config.as_ptr(), ptr::null_mut()));
+}
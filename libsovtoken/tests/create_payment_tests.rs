@@ -126,7 +126,7 @@ fn success_callback_is_called() {
     let (receiver, command_handle, cb) = callbacks::cb_ec_string();
 
     let seed = rand_string(VALID_SEED_LEN);
-    let config: PaymentAddressConfig = PaymentAddressConfig { seed, };
+    let config: PaymentAddressConfig = PaymentAddressConfig { seed, strict_seed: false, reuse_existing: false, seed_encoding: None };
 
     let config_str =  config.serialize_to_cstring().unwrap();
     let config_str_ptr = config_str.as_ptr();
@@ -156,4 +156,23 @@ pub fn create_address_two_times_with_the_same_seed() {
     let err = indy::payments::Payment::create_payment_address(wallet.handle, "sov", &seed).unwrap_err();
 
     assert_eq!(err, indy::ErrorCode::WalletItemAlreadyExists);
-}
\ No newline at end of file
+}
+#[test]
+pub fn create_address_two_times_with_the_same_seed_calls_back_the_existing_address() {
+    sovtoken::api::sovtoken_init();
+    let wallet = utils::wallet::Wallet::new();
+
+    let seed = json!({"seed": rand_string(VALID_SEED_LEN)}).to_string();
+    let existing = indy::payments::Payment::create_payment_address(wallet.handle, "sov", &seed).unwrap();
+
+    let (receiver, command_handle, cb) = callbacks::cb_ec_string();
+    let config_str = CString::new(seed).unwrap();
+    let return_error = sovtoken::api::create_payment_address_handler(command_handle, wallet.handle, config_str.as_ptr(), cb);
+    assert_eq!(ErrorCode::Success, ErrorCode::from(return_error));
+
+    let (err, error_json) = receiver.recv_timeout(Duration::from_secs(TIMEOUT_SECONDS)).unwrap();
+    assert_eq!(ErrorCode::WalletItemAlreadyExists, err);
+    let error: serde_json::Value = serde_json::from_str(&error_json).unwrap();
+    assert_eq!("SOV-0042", error["id"]);
+    assert_eq!(json!([existing]), error["args"]);
+}
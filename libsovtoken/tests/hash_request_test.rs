@@ -0,0 +1,78 @@
+#[macro_use] extern crate serde_json;
+extern crate indy;
+extern crate sovtoken;
+
+use indy::ErrorCode;
+use sovtoken::utils::test::callbacks;
+use std::ffi::{CStr, CString};
+use std::time::Duration;
+
+// ***** HELPER METHODS *****
+
+fn hash_request(request_json: &str) -> Result<String, ErrorCode> {
+    let request_json = CString::new(request_json).unwrap();
+    let (receiver, command_handle, cb) = callbacks::cb_ec_string();
+
+    let ec = sovtoken::api::hash_request_handler(command_handle, request_json.as_ptr(), cb);
+    if ec != ErrorCode::Success as i32 {
+        return Err(ErrorCode::from(ec));
+    }
+
+    match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+        (ErrorCode::Success, hash) => Ok(hash),
+        (ec, _) => Err(ec),
+    }
+}
+
+fn hash_request_sync(request_json: &str) -> Result<String, ErrorCode> {
+    let request_json = CString::new(request_json).unwrap();
+    let result = sovtoken::api::hash_request_sync(request_json.as_ptr());
+    if result.error_code != ErrorCode::Success as i32 {
+        return Err(ErrorCode::from(result.error_code));
+    }
+
+    let hash = unsafe { CStr::from_ptr(result.json) }.to_str().unwrap().to_string();
+    sovtoken::api::sovtoken_free_json(result.json);
+    Ok(hash)
+}
+
+// ***** HELPER TEST DATA  *****
+
+fn request() -> serde_json::Value {
+    json!({
+        "identifier": "Th7MpTaRZVRYnPiabds81Y",
+        "reqId": 1527714086,
+        "protocolVersion": 2,
+        "operation": {"type": "1", "dest": "FYmoFw55GeQH7SRFa37dkx1d2dZ3zUF8ckg7wmL7ofN4"}
+    })
+}
+
+// ***** UNIT TESTS *****
+
+#[test]
+fn hash_is_hex_sha256() {
+    let hash = hash_request(&request().to_string()).unwrap();
+
+    assert_eq!(64, hash.len());
+    assert!(hash.chars().all(|c| c.is_digit(16)));
+    assert_eq!(hash, hash_request_sync(&request().to_string()).unwrap());
+}
+
+#[test]
+fn hash_ignores_format_and_signature() {
+    let hash = hash_request(&request().to_string()).unwrap();
+
+    let mut signed = request();
+    signed["signature"] = json!("4jX5zpR7GSa3q7nW5wPRqXRmmP2fqomAdg3SMaYq2TVG");
+    assert_eq!(hash, hash_request(&serde_json::to_string_pretty(&signed).unwrap()).unwrap());
+
+    let mut other = request();
+    other["reqId"] = json!(1527714087);
+    assert_ne!(hash, hash_request(&other.to_string()).unwrap());
+}
+
+#[test]
+fn hash_of_non_object_fails() {
+    assert_eq!(ErrorCode::CommonInvalidStructure, hash_request("[1, 2]").unwrap_err());
+    assert_eq!(ErrorCode::CommonInvalidStructure, hash_request_sync("[1, 2]").unwrap_err());
+}
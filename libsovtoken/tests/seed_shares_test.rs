@@ -0,0 +1,64 @@
+extern crate indy;
+extern crate serde_json;
+extern crate sovtoken;
+
+use indy::ErrorCode;
+use sovtoken::utils::test::callbacks;
+use std::ffi::CString;
+use std::time::Duration;
+
+static SEED: &'static str = "000000000000000000000000Escrowed";
+
+// ***** HELPER METHODS *****
+
+fn split_seed(seed: &str, threshold: u32, share_count: u32) -> Result<Vec<String>, ErrorCode> {
+    let seed = CString::new(seed).unwrap();
+    let (receiver, command_handle, cb) = callbacks::cb_ec_string();
+
+    let ec = sovtoken::api::split_seed_handler(command_handle, seed.as_ptr(), threshold, share_count, cb);
+    if ec != ErrorCode::Success as i32 {
+        return Err(ErrorCode::from(ec));
+    }
+
+    match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+        (ErrorCode::Success, shares) => Ok(serde_json::from_str(&shares).unwrap()),
+        (ec, _) => Err(ec),
+    }
+}
+
+fn recover_seed(shares: &[String]) -> Result<String, ErrorCode> {
+    let shares = CString::new(serde_json::to_string(shares).unwrap()).unwrap();
+    let (receiver, command_handle, cb) = callbacks::cb_ec_string();
+
+    let ec = sovtoken::api::recover_seed_handler(command_handle, shares.as_ptr(), cb);
+    if ec != ErrorCode::Success as i32 {
+        return Err(ErrorCode::from(ec));
+    }
+
+    match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+        (ErrorCode::Success, seed) => Ok(seed),
+        (ec, _) => Err(ec),
+    }
+}
+
+// ***** UNIT TESTS *****
+
+#[test]
+fn seed_recovered_from_threshold_shares() {
+    let shares = split_seed(SEED, 2, 3).unwrap();
+    assert_eq!(3, shares.len());
+
+    assert_eq!(SEED, recover_seed(&shares[1..]).unwrap());
+    assert_eq!(SEED, recover_seed(&[shares[2].clone(), shares[0].clone()]).unwrap());
+}
+
+#[test]
+fn seed_not_recovered_from_too_few_shares() {
+    let shares = split_seed(SEED, 3, 5).unwrap();
+    assert_eq!(ErrorCode::CommonInvalidStructure, recover_seed(&shares[..2]).unwrap_err());
+}
+
+#[test]
+fn seed_not_split_below_threshold_of_two() {
+    assert_eq!(ErrorCode::CommonInvalidStructure, split_seed(SEED, 1, 3).unwrap_err());
+}
@@ -0,0 +1,44 @@
+#[macro_use] extern crate serde_json;
+extern crate indy;
+extern crate sovtoken;
+
+use indy::ErrorCode;
+use sovtoken::utils::test::callbacks;
+use std::ffi::CString;
+use std::time::Duration;
+
+// ***** HELPER METHODS *****
+
+fn set_fee_schedule(fees: serde_json::Value) -> Result<serde_json::Value, ErrorCode> {
+    let fees = CString::new(fees.to_string()).unwrap();
+    let (receiver, command_handle, cb) = callbacks::cb_ec_string();
+
+    let ec = sovtoken::api::set_fee_schedule_handler(command_handle, fees.as_ptr(), cb);
+    if ec != ErrorCode::Success as i32 {
+        return Err(ErrorCode::from(ec));
+    }
+
+    match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+        (ErrorCode::Success, schedule) => Ok(serde_json::from_str(&schedule).unwrap()),
+        (ec, _) => Err(ec),
+    }
+}
+
+// ***** UNIT TESTS *****
+
+#[test]
+fn preloaded_fees_are_cached() {
+    let schedule = set_fee_schedule(json!({"1": 4, "10001": 12})).unwrap();
+
+    assert_eq!(json!(4), schedule["fees"]["1"]);
+    assert_eq!(json!(12), schedule["fees"]["10001"]);
+
+    let preloaded = schedule["preloaded"].as_array().unwrap();
+    assert!(preloaded.contains(&json!("1")));
+    assert!(preloaded.contains(&json!("10001")));
+}
+
+#[test]
+fn empty_fees_are_not_preloaded() {
+    assert_eq!(ErrorCode::CommonInvalidStructure, set_fee_schedule(json!({})).unwrap_err());
+}
@@ -0,0 +1,103 @@
+extern crate bs58;
+extern crate indy;
+extern crate libc;
+extern crate sovtoken;
+#[macro_use] extern crate serde_json;
+
+use indy::ErrorCode;
+use libc::c_char;
+use sovtoken::logic::address;
+use sovtoken::logic::parsers::common::TXO;
+use sovtoken::logic::stateless::SIGNATURE_LEN;
+use sovtoken::utils::test::callbacks;
+use std::ffi::{CStr, CString};
+use std::ptr;
+use std::slice;
+use std::time::Duration;
+
+// ***** HELPER METHODS *****
+
+// signs with the first byte of the message, fails for any other key than VERKEY's
+extern fn test_signer(verkey: *const c_char, message_raw: *const u8, message_len: u32, signature_raw: *mut u8) -> i32 {
+    let verkey = unsafe { CStr::from_ptr(verkey) }.to_str().unwrap();
+    if verkey != verkey_of_test() {
+        return 1;
+    }
+    let message = unsafe { slice::from_raw_parts(message_raw, message_len as usize) };
+    let signature = unsafe { slice::from_raw_parts_mut(signature_raw, SIGNATURE_LEN) };
+    for byte in signature.iter_mut() {
+        *byte = message[0];
+    }
+    0
+}
+
+fn verkey_of_test() -> String {
+    bs58::encode([7u8; 32]).into_string()
+}
+
+// this thread runs in a stateless context of its own
+fn enter_stateless_context() {
+    let config = CString::new(json!({"stateless": true}).to_string()).unwrap();
+    let mut context_handle = 0;
+    assert_eq!(ErrorCode::Success as i32, sovtoken::api::sovtoken_context_create(config.as_ptr(), &mut context_handle));
+    assert_eq!(ErrorCode::Success as i32, sovtoken::api::sovtoken_context_enter(context_handle));
+}
+
+fn build_payment(wallet_handle: i32, address: &str) -> Result<String, ErrorCode> {
+    let inputs = json!([TXO { address: address.to_string(), seq_no: 1 }.to_libindy_string().unwrap()]);
+    let outputs = json!([{"recipient": address, "amount": 5}]);
+    let inputs = CString::new(inputs.to_string()).unwrap();
+    let outputs = CString::new(outputs.to_string()).unwrap();
+
+    let (receiver, command_handle, cb) = callbacks::cb_ec_string();
+    let ec = sovtoken::api::build_payment_req_handler(command_handle, wallet_handle, ptr::null(), inputs.as_ptr(), outputs.as_ptr(), ptr::null(), cb);
+    if ec != ErrorCode::Success as i32 {
+        return Err(ErrorCode::from(ec));
+    }
+
+    match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+        (ErrorCode::Success, request) => Ok(request),
+        (ec, _) => Err(ec),
+    }
+}
+
+// ***** UNIT TESTS *****
+
+#[test]
+fn address_from_key_signed_by_the_signer() {
+    enter_stateless_context();
+    assert_eq!(ErrorCode::Success as i32, sovtoken::api::sovtoken_register_signer(Some(test_signer)));
+
+    let verkey = CString::new(verkey_of_test()).unwrap();
+    let (receiver, command_handle, cb) = callbacks::cb_ec_string();
+    let ec = sovtoken::api::create_payment_address_from_key_handler(command_handle, 1, verkey.as_ptr(), cb);
+    assert_eq!(ErrorCode::Success as i32, ec);
+
+    let (ec, payment_address) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(ErrorCode::Success, ec);
+    assert_eq!(address::qualified_address_from_verkey(&verkey_of_test()).unwrap(), payment_address);
+}
+
+#[test]
+fn payment_built_and_signed_without_a_wallet() {
+    enter_stateless_context();
+    assert_eq!(ErrorCode::Success as i32, sovtoken::api::sovtoken_register_signer(Some(test_signer)));
+
+    // the wallet handle is ignored, no wallet is open
+    let address = address::qualified_address_from_verkey(&verkey_of_test()).unwrap();
+    let request = build_payment(12345, &address).unwrap();
+    let request: serde_json::Value = serde_json::from_str(&request).unwrap();
+
+    let signatures = request["operation"]["signatures"].as_array().unwrap();
+    assert_eq!(1, signatures.len());
+    assert_eq!(SIGNATURE_LEN, bs58::decode(signatures[0].as_str().unwrap()).into_vec().unwrap().len());
+}
+
+#[test]
+fn payment_without_a_signer() {
+    enter_stateless_context();
+    assert_eq!(ErrorCode::Success as i32, sovtoken::api::sovtoken_register_signer(None));
+
+    let address = address::qualified_address_from_verkey(&verkey_of_test()).unwrap();
+    assert_eq!(ErrorCode::PaymentOperationNotSupportedError, build_payment(1, &address).unwrap_err());
+}
@@ -0,0 +1,191 @@
+#[macro_use] extern crate serde_json;
+extern crate indy;
+extern crate libc;
+extern crate sovtoken;
+
+use indy::ErrorCode;
+use libc::c_char;
+use sovtoken::logic::address::qualified_address_from_verkey;
+use sovtoken::utils::constants::general::JsonCallback;
+use sovtoken::utils::test::callbacks;
+use std::ffi::{CStr, CString};
+use std::time::Duration;
+
+static DID: &'static str = "Th7MpTaRZVRYnPiabds81Y";
+static VERKEY: &'static str = "FYmoFw55GeQH7SRFa37dkx1d2dZ3zUF8ckg7wmL7ofN4";
+
+type BuildWithFees = extern "C" fn(i32, i32, i32, *const c_char, *const c_char, JsonCallback) -> i32;
+
+// ***** HELPER METHODS *****
+
+// this thread runs in a context of its own, with the fees of the writes waived
+fn enter_waived_context() {
+    let config = CString::new("{}").unwrap();
+    let mut context_handle = 0;
+    assert_eq!(ErrorCode::Success as i32, sovtoken::api::sovtoken_context_create(config.as_ptr(), &mut context_handle));
+    assert_eq!(ErrorCode::Success as i32, sovtoken::api::sovtoken_context_enter(context_handle));
+
+    let fees = CString::new(json!({"1": 0, "100": 0, "101": 0}).to_string()).unwrap();
+    let (receiver, command_handle, cb) = callbacks::cb_ec_string();
+    assert_eq!(ErrorCode::Success as i32, sovtoken::api::set_fee_schedule_handler(command_handle, fees.as_ptr(), cb));
+    assert_eq!(ErrorCode::Success, receiver.recv_timeout(Duration::from_secs(5)).unwrap().0);
+}
+
+fn build_with_fees(handler: BuildWithFees, request: &serde_json::Value, funding_address: &str) -> Result<serde_json::Value, ErrorCode> {
+    let request = CString::new(request.to_string()).unwrap();
+    let funding_address = CString::new(funding_address).unwrap();
+    let (receiver, command_handle, cb) = callbacks::cb_ec_string();
+
+    let ec = handler(command_handle, 1, 1, request.as_ptr(), funding_address.as_ptr(), cb);
+    if ec != ErrorCode::Success as i32 {
+        return Err(ErrorCode::from(ec));
+    }
+
+    match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+        (ErrorCode::Success, status) => Ok(serde_json::from_str(&status).unwrap()),
+        (ec, _) => Err(ec),
+    }
+}
+
+fn parse_write_with_fees_response(resp_json: &str) -> Result<serde_json::Value, ErrorCode> {
+    let resp_json = CString::new(resp_json).unwrap();
+    let (receiver, command_handle, cb) = callbacks::cb_ec_string();
+
+    let ec = sovtoken::api::parse_write_with_fees_response_handler(command_handle, resp_json.as_ptr(), cb);
+    if ec != ErrorCode::Success as i32 {
+        return Err(ErrorCode::from(ec));
+    }
+
+    match receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+        (ErrorCode::Success, parsed) => Ok(serde_json::from_str(&parsed).unwrap()),
+        (ec, _) => Err(ec),
+    }
+}
+
+fn parse_write_with_fees_response_sync(resp_json: &str) -> Result<serde_json::Value, ErrorCode> {
+    let resp_json = CString::new(resp_json).unwrap();
+    let result = sovtoken::api::parse_write_with_fees_response_sync(resp_json.as_ptr());
+    if result.error_code != ErrorCode::Success as i32 {
+        return Err(ErrorCode::from(result.error_code));
+    }
+
+    let parsed = unsafe { CStr::from_ptr(result.json) }.to_str().unwrap().to_string();
+    sovtoken::api::sovtoken_free_json(result.json);
+    Ok(serde_json::from_str(&parsed).unwrap())
+}
+
+fn funding_address() -> String {
+    qualified_address_from_verkey(VERKEY).unwrap()
+}
+
+// ***** HELPER TEST DATA  *****
+
+fn request(operation: serde_json::Value) -> serde_json::Value {
+    json!({
+        "identifier": DID,
+        "reqId": 1527714086,
+        "protocolVersion": 2,
+        "operation": operation
+    })
+}
+
+fn nym_request() -> serde_json::Value {
+    request(json!({"type": "1", "dest": "FYmoFw55GeQH7SRFa37dkx1d2dZ3zUF8ckg7wmL7ofN4", "verkey": VERKEY}))
+}
+
+fn attrib_request() -> serde_json::Value {
+    request(json!({"type": "100", "dest": DID, "raw": "{\"endpoint\":{\"ha\":\"127.0.0.1:5555\"}}"}))
+}
+
+fn schema_request() -> serde_json::Value {
+    request(json!({"type": "101", "data": {"name": "gvt", "version": "1.0", "attr_names": ["name", "age"]}}))
+}
+
+// a NYM reply of a pool of protocol version 1, with fees
+fn nym_with_fees_reply() -> serde_json::Value {
+    json!({
+        "op": "REPLY",
+        "result": {
+            "type": "1",
+            "identifier": DID,
+            "reqId": 2,
+            "signature": "sig",
+            "dest": "FYmoFw55GeQH7SRFa37dkx1d2dZ3zUF8ckg7wmL7ofN4",
+            "seqNo": 10,
+            "txnTime": 1527714130,
+            "rootHash": "root",
+            "auditPath": [],
+            "fees": {
+                "type": "20001",
+                "reqId": 2,
+                "inputs": [{"address": "2jS4PHWQJKcawRxdW6GVsjnZBa1ecGdCssn7KhWYJZGTXgL7Es", "seqNo": 1}],
+                "outputs": [{"address": "2jS4PHWQJKcawRxdW6GVsjnZBa1ecGdCssn7KhWYJZGTXgL7Es", "amount": 7}],
+                "fees": 3,
+                "ref": "1:10",
+                "signatures": ["sig"],
+                "seqNo": 5,
+                "txnTime": 1527714130,
+                "rootHash": "root",
+                "auditPath": []
+            }
+        }
+    })
+}
+
+// ***** UNIT TESTS *****
+
+#[test]
+fn writes_returned_without_fees_when_waived() {
+    enter_waived_context();
+
+    let writes: Vec<(BuildWithFees, serde_json::Value)> = vec![
+        (sovtoken::api::build_nym_with_fees_handler, nym_request()),
+        (sovtoken::api::build_attrib_with_fees_handler, attrib_request()),
+        (sovtoken::api::build_schema_with_fees_handler, schema_request()),
+    ];
+    for (handler, request) in writes {
+        let status = build_with_fees(handler, &request, &funding_address()).unwrap();
+
+        assert_eq!(json!(false), status["feesApplied"]);
+        let returned: serde_json::Value = serde_json::from_str(status["request"].as_str().unwrap()).unwrap();
+        assert_eq!(request, returned);
+    }
+}
+
+#[test]
+fn write_of_other_type_fails() {
+    enter_waived_context();
+
+    let err = build_with_fees(sovtoken::api::build_nym_with_fees_handler, &attrib_request(), &funding_address()).unwrap_err();
+    assert_eq!(ErrorCode::CommonInvalidStructure, err);
+    let err = build_with_fees(sovtoken::api::build_schema_with_fees_handler, &nym_request(), &funding_address()).unwrap_err();
+    assert_eq!(ErrorCode::CommonInvalidStructure, err);
+}
+
+#[test]
+fn write_with_invalid_funding_address_fails() {
+    enter_waived_context();
+
+    let err = build_with_fees(sovtoken::api::build_nym_with_fees_handler, &nym_request(), "sov:invalid").unwrap_err();
+    assert_eq!(ErrorCode::CommonInvalidStructure, err);
+}
+
+#[test]
+fn reply_with_fees_parsed() {
+    let parsed = parse_write_with_fees_response(&nym_with_fees_reply().to_string()).unwrap();
+
+    assert_eq!(json!("1"), parsed["txnType"]);
+    assert_eq!(json!(10), parsed["seqNo"]);
+    assert_eq!(json!(1527714130), parsed["txnTime"]);
+    assert_eq!(1, parsed["receipts"].as_array().unwrap().len());
+    assert_eq!(json!(7), parsed["receipts"][0]["amount"]);
+
+    assert_eq!(parsed, parse_write_with_fees_response_sync(&nym_with_fees_reply().to_string()).unwrap());
+}
+
+#[test]
+fn reply_without_result_fails() {
+    let reply = json!({"op": "REPLY"}).to_string();
+    assert_eq!(ErrorCode::CommonInvalidStructure, parse_write_with_fees_response(&reply).unwrap_err());
+    assert_eq!(ErrorCode::CommonInvalidStructure, parse_write_with_fees_response_sync(&reply).unwrap_err());
+}